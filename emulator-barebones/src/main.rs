@@ -0,0 +1,193 @@
+use std::fs::File;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use emulator_core::{
+    bios_function_name, calculate_lcd_checksum, BootMode, Cartridge, Cpu, Key, KeysState,
+    StepEvent, SwiStats, CYCLES_PER_FRAME, CYCLES_PER_SECOND,
+};
+
+// How long a scripted key press is held if no duration is given, long enough for games that
+// only poll input every few frames.
+const DEFAULT_PRESS_FRAMES: u64 = 5;
+
+// Runs a ROM without any video or audio output, then reports the final LCD checksum. Meant for
+// driving ROM compatibility tests from outside the emulator.
+#[derive(Debug, Parser)]
+struct Args {
+    rom: String,
+
+    /// Number of frames to run for.
+    #[clap(
+        short,
+        long,
+        conflicts_with = "seconds",
+        required_unless_present = "seconds"
+    )]
+    frames: Option<u64>,
+
+    /// Number of seconds of emulated time to run for, rounded down to whole frames.
+    #[clap(short, long)]
+    seconds: Option<f64>,
+
+    /// Hold a key from a given frame, as KEY@FRAME or KEY@FRAME+DURATION (in frames, 5 by
+    /// default). Keys are a, b, select, start, right, left, up, down, r and l. May be repeated.
+    #[clap(short, long)]
+    press: Vec<KeyPress>,
+
+    /// Checksum the final frame is expected to have, in hex. Exits with a failure status if it
+    /// doesn't match.
+    #[clap(short, long, value_parser = parse_checksum)]
+    expect: Option<u64>,
+
+    /// Fail as soon as the screen stays unchanged for this many seconds, which usually means the
+    /// game has frozen.
+    #[clap(long)]
+    fail_if_frozen: Option<f64>,
+
+    /// Skip the BIOS boot animation and start directly at the cartridge entry point.
+    #[clap(long)]
+    skip_bios: bool,
+
+    /// Print how often each BIOS function was called to stderr once the run is over, most
+    /// called first.
+    #[clap(long)]
+    swi_stats: bool,
+
+    /// Write a Chrome trace of where emulation time goes to the given file, for viewing in
+    /// Perfetto or chrome://tracing. Traces grow quickly, so keep runs short.
+    #[cfg(feature = "profile")]
+    #[clap(long)]
+    profile_trace: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct KeyPress {
+    key: Key,
+    start_frame: u64,
+    frames: u64,
+}
+
+impl KeyPress {
+    fn held_on(&self, frame: u64) -> bool {
+        (self.start_frame..self.start_frame + self.frames).contains(&frame)
+    }
+}
+
+impl FromStr for KeyPress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (key, timing) = s
+            .split_once('@')
+            .ok_or_else(|| anyhow!("expected KEY@FRAME or KEY@FRAME+DURATION"))?;
+        let (start_frame, frames) = match timing.split_once('+') {
+            Some((start_frame, frames)) => (start_frame.parse()?, frames.parse()?),
+            None => (timing.parse()?, DEFAULT_PRESS_FRAMES),
+        };
+
+        let key = key.parse()?;
+
+        Ok(Self {
+            key,
+            start_frame,
+            frames,
+        })
+    }
+}
+
+fn seconds_to_frames(seconds: f64) -> u64 {
+    (seconds * CYCLES_PER_SECOND as f64) as u64 / CYCLES_PER_FRAME
+}
+
+// Records every span entered into a Chrome trace, which is written out once the returned guard is
+// dropped.
+#[cfg(feature = "profile")]
+fn start_profile_trace(file_name: &str) -> tracing_chrome::FlushGuard {
+    use tracing_subscriber::prelude::*;
+
+    let (chrome_layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+        .file(file_name)
+        .build();
+    tracing_subscriber::registry().with(chrome_layer).init();
+
+    guard
+}
+
+fn print_swi_stats(swi_stats: &SwiStats) {
+    eprintln!("{} BIOS calls", swi_stats.total_calls());
+    for (number, count) in swi_stats.by_calls() {
+        let name = bios_function_name(number).unwrap_or("unknown");
+        eprint!("{number:02X} {name:<22}{:>10}", count.calls);
+        if count.handled_by_hook > 0 {
+            eprint!(" ({} by hook)", count.handled_by_hook);
+        }
+        eprintln!();
+    }
+}
+
+fn parse_checksum(s: &str) -> Result<u64> {
+    Ok(u64::from_str_radix(s.trim_start_matches("0x"), 16)?)
+}
+
+fn main() -> Result<ExitCode> {
+    env_logger::init();
+
+    let args = Args::parse();
+    #[cfg(feature = "profile")]
+    let _profile_guard = args.profile_trace.as_deref().map(start_profile_trace);
+
+    let frames = match (args.frames, args.seconds) {
+        (Some(frames), _) => frames,
+        (None, Some(seconds)) => seconds_to_frames(seconds),
+        (None, None) => unreachable!("clap requires one of them"),
+    };
+    let frozen_frames = args.fail_if_frozen.map(seconds_to_frames);
+
+    let rom_file =
+        File::open(&args.rom).map_err(|_| anyhow!("failed to open ROM file \"{}\"", args.rom))?;
+    let cartridge = Cartridge::new(rom_file, None)?;
+
+    let boot_mode = if args.skip_bios {
+        BootMode::SkipBios
+    } else {
+        BootMode::Bios
+    };
+    let mut cpu = Cpu::with_boot_mode(cartridge, boot_mode);
+
+    for frame in 0..frames {
+        let mut keys_state = KeysState::default();
+        for press in args.press.iter().filter(|press| press.held_on(frame)) {
+            keys_state.set_pressed(press.key, true);
+        }
+        cpu.bus.keypad.set_state(keys_state);
+
+        while !matches!(cpu.run_until_event(), StepEvent::FrameComplete) {}
+
+        if frozen_frames.is_some_and(|frozen_frames| cpu.is_screen_frozen(frozen_frames)) {
+            println!("{:016X}", calculate_lcd_checksum(&cpu));
+            eprintln!(
+                "screen frozen since frame {}",
+                frame - cpu.bus.lcd.unchanged_frames()
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    let checksum = calculate_lcd_checksum(&cpu);
+    println!("{checksum:016X}");
+
+    if args.swi_stats {
+        print_swi_stats(cpu.swi_stats());
+    }
+
+    match args.expect {
+        Some(expected) if expected != checksum => {
+            eprintln!("checksum mismatch, expected {expected:016X}");
+            Ok(ExitCode::FAILURE)
+        }
+        _ => Ok(ExitCode::SUCCESS),
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{anyhow, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Break(Option<String>), // lists breakpoints without a location
+    Delete(String),
+    Watch(Option<String>), // lists watchpoints without a location
+    Unwatch(String),
+    IoTrace(Option<String>), // lists traced registers without a register
+    IoUntrace(String),
+    Step(u64),
+    Continue,
+    Registers,
+    Examine {
+        count: usize,
+        size: Size,
+        format: Format,
+        location: String,
+    },
+    Disassemble {
+        location: Option<String>,
+        count: usize,
+    },
+    Trace(bool),
+    Help,
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    Byte,
+    Halfword,
+    Word,
+}
+
+impl Size {
+    pub fn bytes(self) -> u32 {
+        match self {
+            Self::Byte => 1,
+            Self::Halfword => 2,
+            Self::Word => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Hex,
+    Decimal,
+}
+
+pub const HELP: &str = "\
+break [LOCATION]       stop before executing LOCATION, or list breakpoints (b)
+delete LOCATION        remove a breakpoint
+watch [LOCATION]       stop once the word at LOCATION changes, or list watchpoints
+unwatch LOCATION       remove a watchpoint
+iotrace [REGISTER]     print every access to an IO register, or list traced registers
+iountrace REGISTER     stop tracing an IO register
+step [COUNT]           execute COUNT instructions, 1 by default (s)
+continue               run until something stops execution, or Ctrl-C (c)
+regs                   show registers and flags
+x/NSF LOCATION         examine N units of size S (b, h, w) in format F (x, d)
+disas [LOCATION] [N]   disassemble N instructions, 10 by default, from the PC by default
+trace on|off           print every instruction as it's executed
+quit                   exit (q)
+
+LOCATION is a 0x prefixed hex address, a decimal address, a symbol, or pc. REGISTER is an IO
+register name like DISPCNT, or a LOCATION. An empty line repeats the previous command.";
+
+const DEFAULT_DISASSEMBLY_COUNT: usize = 10;
+
+// Returns `None` for an empty line.
+pub fn parse(line: &str) -> Result<Option<Command>> {
+    let mut words = line.split_whitespace();
+    let Some(name) = words.next() else {
+        return Ok(None);
+    };
+    let arguments = words.collect::<Vec<_>>();
+    let location = |index: usize| arguments.get(index).map(|argument| argument.to_string());
+    let required_location =
+        |index: usize| location(index).ok_or_else(|| anyhow!("{name} needs a location"));
+
+    let command = match name {
+        "break" | "b" => Command::Break(location(0)),
+        "delete" => Command::Delete(required_location(0)?),
+        "watch" => Command::Watch(location(0)),
+        "unwatch" => Command::Unwatch(required_location(0)?),
+        "iotrace" => Command::IoTrace(location(0)),
+        "iountrace" => Command::IoUntrace(required_location(0)?),
+        "step" | "s" => Command::Step(match arguments.first() {
+            Some(count) => count.parse()?,
+            None => 1,
+        }),
+        "continue" | "c" => Command::Continue,
+        "regs" => Command::Registers,
+        "disas" => Command::Disassemble {
+            location: location(0),
+            count: match arguments.get(1) {
+                Some(count) => count.parse()?,
+                None => DEFAULT_DISASSEMBLY_COUNT,
+            },
+        },
+        "trace" => match arguments.first().copied() {
+            Some("on") => Command::Trace(true),
+            Some("off") => Command::Trace(false),
+            _ => return Err(anyhow!("expected trace on or trace off")),
+        },
+        "help" | "h" => Command::Help,
+        "quit" | "q" => Command::Quit,
+        _ if name == "x" || name.starts_with("x/") => {
+            let (count, size, format) = parse_examine_spec(name.trim_start_matches('x'))?;
+            Command::Examine {
+                count,
+                size,
+                format,
+                location: required_location(0)?,
+            }
+        }
+        _ => return Err(anyhow!("unknown command \"{name}\", try help")),
+    };
+
+    Ok(Some(command))
+}
+
+// Parses the "/16wx" part of an examine command. Everything is optional, defaulting to a single
+// word in hex.
+fn parse_examine_spec(spec: &str) -> Result<(usize, Size, Format)> {
+    let spec = spec.trim_start_matches('/');
+    let digits = spec.len() - spec.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (count, letters) = spec.split_at(digits);
+
+    let count = if count.is_empty() { 1 } else { count.parse()? };
+    let mut size = Size::Word;
+    let mut format = Format::Hex;
+    for letter in letters.chars() {
+        match letter {
+            'b' => size = Size::Byte,
+            'h' => size = Size::Halfword,
+            'w' => size = Size::Word,
+            'x' => format = Format::Hex,
+            'd' => format = Format::Decimal,
+            _ => return Err(anyhow!("unknown examine format '{letter}'")),
+        }
+    }
+
+    Ok((count, size, format))
+}
@@ -0,0 +1,393 @@
+mod command;
+
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use command::{Command, Format, Size};
+use emulator_core::{
+    BootMode, Cartridge, Cpu, CpuConfig, ErrorPolicy, InstructionSet, IoTrace, Register, StepEvent,
+    SymbolTable,
+};
+
+// An interactive debugger for the terminal, for when there's no display to run a GUI on.
+#[derive(Debug, Parser)]
+struct Args {
+    rom: String,
+
+    /// Skip the BIOS boot animation and start directly at the cartridge entry point.
+    #[clap(long)]
+    skip_bios: bool,
+}
+
+// Stops execution once the value at `address` differs from `value`. Checked after every
+// instruction, so it catches writes from DMA too, just not which instruction made them.
+struct Watchpoint {
+    address: u32,
+    value: u32,
+}
+
+struct Debugger {
+    cpu: Cpu,
+    symbols: Option<SymbolTable>,
+    watchpoints: Vec<Watchpoint>,
+    trace: bool,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Debugger {
+    // Returns false once the debugger should exit.
+    fn run_command(&mut self, command: Command) -> Result<bool> {
+        match command {
+            Command::Break(Some(location)) => {
+                let address = self.resolve(&location)?;
+                self.cpu.add_breakpoint(address);
+                println!("breakpoint at {}", self.describe(address));
+            }
+            Command::Break(None) => {
+                for &address in self.cpu.get_breakpoints() {
+                    println!("{}", self.describe(address));
+                }
+            }
+            Command::Delete(location) => {
+                let address = self.resolve(&location)?;
+                self.cpu.remove_breakpoint(address);
+            }
+            Command::Watch(Some(location)) => {
+                let address = self.resolve(&location)?;
+                let value = self.cpu.bus.read_word_address_debug(address);
+                self.watchpoints.push(Watchpoint { address, value });
+                println!("watching {} = {value:08X}", self.describe(address));
+            }
+            Command::Watch(None) => {
+                for watchpoint in &self.watchpoints {
+                    println!(
+                        "{} = {:08X}",
+                        self.describe(watchpoint.address),
+                        watchpoint.value
+                    );
+                }
+            }
+            Command::Unwatch(location) => {
+                let address = self.resolve(&location)?;
+                self.watchpoints
+                    .retain(|watchpoint| watchpoint.address != address);
+            }
+            Command::IoTrace(Some(register)) => {
+                let address = self.resolve_io_register(&register)?;
+                let io_trace = self.cpu.bus.io_trace_mut().unwrap();
+                let name = io_trace
+                    .trace(address)
+                    .ok_or_else(|| anyhow!("no IO register at {address:08X}"))?;
+                println!("tracing {name}");
+            }
+            Command::IoTrace(None) => {
+                for name in self.cpu.bus.io_trace().unwrap().traced() {
+                    println!("{name}");
+                }
+            }
+            Command::IoUntrace(register) => {
+                let address = self.resolve_io_register(&register)?;
+                self.cpu.bus.io_trace_mut().unwrap().untrace(address);
+            }
+            Command::Step(count) => self.execute(Some(count)),
+            Command::Continue => self.execute(None),
+            Command::Registers => self.print_registers(),
+            Command::Examine {
+                count,
+                size,
+                format,
+                location,
+            } => {
+                let address = self.resolve(&location)?;
+                self.examine(address, count, size, format);
+            }
+            Command::Disassemble { location, count } => {
+                let address = match location {
+                    Some(location) => self.resolve(&location)?,
+                    None => self.cpu.get_executing_pc(),
+                };
+
+                for index in 0..count as u32 {
+                    let address = address + index * self.cpu.get_instruction_width();
+                    println!("{}", self.format_instruction(address));
+                }
+            }
+            Command::Trace(trace) => self.trace = trace,
+            Command::Help => println!("{}", command::HELP),
+            Command::Quit => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    // Runs `count` instructions, or until stopped if `None`. Explicit steps run through
+    // breakpoints, like the other frontends.
+    fn execute(&mut self, count: Option<u64>) {
+        self.interrupted.store(false, Ordering::SeqCst);
+
+        let mut executed = 0;
+        loop {
+            if count.is_some_and(|count| executed >= count) {
+                break;
+            }
+
+            if self.interrupted.load(Ordering::SeqCst) {
+                println!("interrupted");
+                break;
+            }
+
+            let executing_pc = self.cpu.get_executing_pc();
+            let traced = self.trace.then(|| self.format_instruction(executing_pc));
+
+            match self.cpu.fetch_decode_execute() {
+                Some(StepEvent::BreakpointHit { .. }) if count.is_some() => continue,
+                Some(StepEvent::BreakpointHit { address }) => {
+                    println!("breakpoint hit at {}", self.describe(address));
+                    break;
+                }
+                Some(StepEvent::BkptHit { address, comment }) => {
+                    println!("bkpt #{comment} hit at {}", self.describe(address));
+                    break;
+                }
+                Some(StepEvent::InvalidOpcode { address, opcode }) => {
+                    println!("invalid opcode {opcode:08X} at {}", self.describe(address));
+                    break;
+                }
+                Some(StepEvent::Error(error)) => {
+                    println!("stopped: {error}");
+                    break;
+                }
+                _ => {}
+            }
+
+            if let Some(traced) = traced {
+                println!("{traced}");
+            }
+            for entry in self.cpu.bus.io_trace_mut().unwrap().take_entries() {
+                println!("{}", entry.description);
+            }
+            executed += 1;
+
+            if let Some((address, old_value, new_value)) = self.poll_watchpoints() {
+                println!(
+                    "watchpoint hit, {} changed from {old_value:08X} to {new_value:08X}",
+                    self.describe(address)
+                );
+                break;
+            }
+        }
+
+        println!("{}", self.format_instruction(self.cpu.get_executing_pc()));
+    }
+
+    fn poll_watchpoints(&mut self) -> Option<(u32, u32, u32)> {
+        for watchpoint in &mut self.watchpoints {
+            let value = self.cpu.bus.read_word_address_debug(watchpoint.address);
+            if value != watchpoint.value {
+                let old_value = std::mem::replace(&mut watchpoint.value, value);
+                return Some((watchpoint.address, old_value, value));
+            }
+        }
+
+        None
+    }
+
+    fn print_registers(&self) {
+        for row in (0..16).collect::<Vec<_>>().chunks(4) {
+            let line = row
+                .iter()
+                .map(|&index| {
+                    let register = Register::from_index(index);
+                    let value = self.cpu.read_register(register, |pc| pc);
+                    format!("{:>4} = {value:08X}", register.to_string())
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            println!("{line}");
+        }
+
+        let flag = |set: bool, name: char| if set { name } else { '-' };
+        println!(
+            "cpsr = {:08X} [{}{}{}{}{}{}] {:?} {}",
+            self.cpu.read_register(Register::Cpsr, |pc| pc),
+            flag(self.cpu.get_sign_flag(), 'N'),
+            flag(self.cpu.get_zero_flag(), 'Z'),
+            flag(self.cpu.get_carry_flag(), 'C'),
+            flag(self.cpu.get_overflow_flag(), 'V'),
+            flag(self.cpu.get_irq_disable(), 'I'),
+            flag(self.cpu.get_fiq_disable(), 'F'),
+            self.cpu.get_cpu_mode(),
+            match self.cpu.get_instruction_mode() {
+                InstructionSet::Arm => "ARM",
+                InstructionSet::Thumb => "Thumb",
+            },
+        );
+    }
+
+    fn examine(&self, address: u32, count: usize, size: Size, format: Format) {
+        const BYTES_PER_LINE: u32 = 16;
+
+        let values_per_line = (BYTES_PER_LINE / size.bytes()) as usize;
+        for line in 0..count.div_ceil(values_per_line) {
+            let line_address = address + (line * values_per_line) as u32 * size.bytes();
+            let values = (0..values_per_line.min(count - line * values_per_line))
+                .map(|index| {
+                    let value_address = line_address + index as u32 * size.bytes();
+                    let value = match size {
+                        Size::Byte => {
+                            u32::from(self.cpu.bus.read_byte_address_debug(value_address))
+                        }
+                        Size::Halfword => {
+                            u32::from(self.cpu.bus.read_halfword_address_debug(value_address))
+                        }
+                        Size::Word => self.cpu.bus.read_word_address_debug(value_address),
+                    };
+
+                    match format {
+                        Format::Hex => {
+                            format!("{value:0width$X}", width = size.bytes() as usize * 2)
+                        }
+                        Format::Decimal => value.to_string(),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            println!("{line_address:08X}: {values}");
+        }
+    }
+
+    fn format_instruction(&self, address: u32) -> String {
+        let marker = if address == self.cpu.get_executing_pc() {
+            "->"
+        } else {
+            "  "
+        };
+
+        format!(
+            "{marker} {}: {}",
+            self.describe(address),
+            self.cpu.disassemble(address)
+        )
+    }
+
+    fn describe(&self, address: u32) -> String {
+        match self
+            .symbols
+            .as_ref()
+            .and_then(|symbols| symbols.describe(address))
+        {
+            Some(name) => format!("{address:08X} <{name}>"),
+            None => format!("{address:08X}"),
+        }
+    }
+
+    fn resolve(&self, location: &str) -> Result<u32> {
+        if location == "pc" {
+            return Ok(self.cpu.get_executing_pc());
+        }
+
+        if let Some(hex) = location.strip_prefix("0x") {
+            return Ok(u32::from_str_radix(hex, 16)?);
+        }
+
+        location
+            .parse()
+            .ok()
+            .or_else(|| {
+                self.symbols
+                    .as_ref()
+                    .and_then(|symbols| symbols.address_of(location))
+            })
+            .ok_or_else(|| anyhow!("not an address or known symbol: {location}"))
+    }
+
+    fn resolve_io_register(&self, register: &str) -> Result<u32> {
+        match IoTrace::register_address(register) {
+            Some(address) => Ok(address),
+            None => self.resolve(register),
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+
+    let rom_file =
+        File::open(&args.rom).map_err(|_| anyhow!("failed to open ROM file \"{}\"", args.rom))?;
+    let cartridge = Cartridge::new(rom_file, None)?;
+
+    let boot_mode = if args.skip_bios {
+        BootMode::SkipBios
+    } else {
+        BootMode::Bios
+    };
+    let mut cpu = Cpu::with_boot_mode(cartridge, boot_mode);
+    // Stop on anything the emulator can't handle, so it can be inspected.
+    cpu.set_config(CpuConfig {
+        error_policy: ErrorPolicy::Trap,
+        ..cpu.config()
+    });
+    // Nothing is traced until asked for, which keeps it cheap to leave on.
+    cpu.bus.set_io_trace(Some(IoTrace::new()));
+
+    // Ctrl-C interrupts a running `continue` rather than exiting.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    {
+        let interrupted = Arc::clone(&interrupted);
+        ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst))?;
+    }
+
+    let mut debugger = Debugger {
+        cpu,
+        // Picks up symbols from a devkitARM build next to the ROM, if any.
+        symbols: SymbolTable::load_alongside(Path::new(&args.rom)),
+        watchpoints: Vec::new(),
+        trace: false,
+        interrupted,
+    };
+
+    println!(
+        "{}",
+        debugger.format_instruction(debugger.cpu.get_executing_pc())
+    );
+
+    let mut last_command = None;
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("(gba) ");
+        io::stdout().flush()?;
+
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+
+        let command = match command::parse(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => match last_command.clone() {
+                Some(command) => command,
+                None => continue,
+            },
+            Err(e) => {
+                println!("{e}");
+                continue;
+            }
+        };
+
+        last_command = Some(command.clone());
+        match debugger.run_command(command) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("{e}"),
+        }
+    }
+
+    Ok(())
+}
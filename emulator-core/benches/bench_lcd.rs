@@ -0,0 +1,82 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use emulator_core::{BootMode, Cartridge, Cpu};
+
+const FRAMES_PER_SECOND: u32 = 60;
+const FRAMES_PER_ITERATION: u32 = 10;
+
+const DISPCNT: u32 = 0x04000000;
+const BG0CNT: u32 = 0x04000008;
+const BG0HOFS: u32 = 0x04000010;
+const BLDCNT: u32 = 0x04000050;
+const BLDALPHA: u32 = 0x04000052;
+const BG_PALETTE: u32 = 0x05000000;
+const VRAM: u32 = 0x06000000;
+
+// A mode 0 scene with all four backgrounds shown, each covered in a tile that's half
+// transparent, so every layer has to be looked at for most pixels. The CPU just spins.
+fn mode_0_cpu(blended: bool) -> Cpu {
+    let mut rom = vec![0; 0x200];
+    rom[0x00..0x04].copy_from_slice(&0xEAFFFFFEu32.to_le_bytes()); // b 0x08000000
+
+    let cartridge = Cartridge::new(rom.as_slice(), None).unwrap();
+    let mut cpu = Cpu::with_boot_mode(cartridge, BootMode::SkipBios);
+    cpu.set_uncapped(true);
+
+    for color in 1..16 {
+        cpu.bus
+            .write_halfword_address_debug(color * 0x0421, BG_PALETTE + u32::from(color) * 2);
+    }
+
+    // 4 bit tile 1 alternates between transparent and a color in every row.
+    for (offset, row) in (0..32).step_by(4).zip(1..) {
+        cpu.bus
+            .write_word_address_debug(0x0F0F_0F0F & (row * 0x1111_1111), VRAM + 32 + offset);
+    }
+
+    for bg in 0..4 {
+        let screen_block = 8 + bg;
+        for entry in 0..32 * 32 {
+            cpu.bus
+                .write_halfword_address_debug(1, VRAM + screen_block * 0x800 + entry * 2);
+        }
+        cpu.bus.write_halfword_address_debug(
+            (screen_block << 8) as u16 | (bg % 4) as u16,
+            BG0CNT + bg * 2,
+        );
+        cpu.bus
+            .write_halfword_address_debug(bg as u16, BG0HOFS + bg * 4);
+    }
+    cpu.bus.write_halfword_address_debug(0x0F00, DISPCNT);
+
+    // Blending BG0 onto BG1 leaves every pixel to the per-pixel path.
+    if blended {
+        cpu.bus.write_halfword_address_debug(0x0241, BLDCNT);
+        cpu.bus.write_halfword_address_debug(0x0808, BLDALPHA);
+    }
+
+    cpu
+}
+
+pub fn mode_0_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mode 0 frames");
+    group.throughput(Throughput::Elements(u64::from(FRAMES_PER_ITERATION)));
+
+    for (name, blended) in [("opaque", false), ("blended", true)] {
+        let cpu = mode_0_cpu(blended);
+
+        group.bench_with_input(BenchmarkId::from_parameter(name), &cpu, |b, cpu| {
+            b.iter_batched_ref(
+                || cpu.clone(),
+                |cpu| {
+                    for _ in 0..FRAMES_PER_ITERATION {
+                        cpu.run_frame(FRAMES_PER_SECOND, |_| false);
+                    }
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+}
+
+criterion_group!(lcd, mode_0_benchmark);
+criterion_main!(lcd);
@@ -0,0 +1,77 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use emulator_core::{BootMode, Cartridge, Cpu};
+
+const FRAMES_PER_SECOND: u32 = 60;
+
+// Frames to run before measuring anything, so that boot code isn't included in the results.
+const WARMUP_FRAMES: u32 = 30;
+
+const INSTRUCTIONS_PER_ITERATION: u64 = 100_000;
+const FRAMES_PER_ITERATION: u32 = 10;
+
+// (name, ROM). Each of these spends most of its time in one kind of work.
+const WORKLOADS: &[(&str, &[u8])] = &[
+    ("arm", include_bytes!("../tests/gba_tests_arm.gba")),
+    ("thumb", include_bytes!("../tests/gba_tests_thumb.gba")),
+    ("dma", include_bytes!("../tests/dma_demo.gba")),
+    ("mandelbrot", include_bytes!("../tests/mandelbrot.gba")),
+    ("armwrestler", include_bytes!("../tests/armwrestler.gba")),
+    ("suite", include_bytes!("../tests/suite.gba")),
+];
+
+fn warmed_up_cpu(source: &[u8]) -> Cpu {
+    let cartridge = Cartridge::new(source, None).unwrap();
+    let mut cpu = Cpu::with_boot_mode(cartridge, BootMode::SkipBios);
+    cpu.set_uncapped(true);
+
+    for _ in 0..WARMUP_FRAMES {
+        cpu.run_frame(FRAMES_PER_SECOND, |_| false);
+    }
+
+    cpu
+}
+
+pub fn instructions_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("instructions");
+    group.throughput(Throughput::Elements(INSTRUCTIONS_PER_ITERATION));
+
+    for &(name, source) in WORKLOADS {
+        let cpu = warmed_up_cpu(source);
+
+        group.bench_with_input(BenchmarkId::from_parameter(name), &cpu, |b, cpu| {
+            b.iter_batched_ref(
+                || cpu.clone(),
+                |cpu| {
+                    for _ in 0..INSTRUCTIONS_PER_ITERATION {
+                        cpu.fetch_decode_execute();
+                    }
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+}
+
+pub fn frames_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frames");
+    group.throughput(Throughput::Elements(u64::from(FRAMES_PER_ITERATION)));
+
+    for &(name, source) in WORKLOADS {
+        let cpu = warmed_up_cpu(source);
+
+        group.bench_with_input(BenchmarkId::from_parameter(name), &cpu, |b, cpu| {
+            b.iter_batched_ref(
+                || cpu.clone(),
+                |cpu| {
+                    for _ in 0..FRAMES_PER_ITERATION {
+                        cpu.run_frame(FRAMES_PER_SECOND, |_| false);
+                    }
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+}
+
+criterion_group!(workloads, instructions_benchmark, frames_benchmark);
+criterion_main!(workloads);
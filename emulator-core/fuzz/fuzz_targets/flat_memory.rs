@@ -0,0 +1,24 @@
+#![no_main]
+
+// Runs arbitrary data as code from a flat RAM covering the whole address space, so that opcodes
+// are executed without the memory map getting in the way. Without the `bios` feature, execution
+// slides through the zeroed reset vectors into the data at 0x00000008.
+
+use emulator_core::{Cpu, FlatMemory};
+use libfuzzer_sys::fuzz_target;
+
+const STEP_BUDGET: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let mut flat_memory = FlatMemory::default();
+    for (i, word) in data.chunks(4).enumerate() {
+        let mut le_bytes = [0; 4];
+        le_bytes[..word.len()].copy_from_slice(word);
+        flat_memory.store(8 + i as u32 * 4, 4, u32::from_le_bytes(le_bytes));
+    }
+
+    let mut cpu = Cpu::with_flat_memory(flat_memory);
+    for _ in 0..STEP_BUDGET {
+        cpu.fetch_decode_execute();
+    }
+});
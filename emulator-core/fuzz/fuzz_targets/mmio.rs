@@ -0,0 +1,88 @@
+#![no_main]
+
+// Performs arbitrary memory accesses, mostly aimed at IO registers, while the system runs.
+
+use emulator_core::{BootMode, Cartridge, Cpu};
+use libfuzzer_sys::{arbitrary::Arbitrary, fuzz_target};
+
+#[derive(Arbitrary, Debug)]
+enum Size {
+    Byte,
+    HalfWord,
+    Word,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Access {
+    Read {
+        region: u8,
+        offset: u32,
+        size: Size,
+    },
+    Write {
+        region: u8,
+        offset: u32,
+        size: Size,
+        value: u32,
+    },
+    // Lets DMAs, timers and anything else that was set up actually happen. Kept short, so that a
+    // whole input can't run for long either.
+    Run {
+        cycles: u16,
+    },
+}
+
+// The top byte of an address picks the region, like the GBA's own address decoding.
+fn address(region: u8, offset: u32) -> u32 {
+    (u32::from(region & 0xF) << 24) | (offset & 0x00FF_FFFF)
+}
+
+fuzz_target!(|accesses: Vec<Access>| {
+    // Just enough of a ROM for the header to parse. It's all zeros, which decode as no-ops.
+    const BLANK_ROM: [u8; 0xC0] = [0; 0xC0];
+
+    let cartridge = Cartridge::new(BLANK_ROM.as_slice(), None).unwrap();
+    let mut cpu = Cpu::with_boot_mode(cartridge, BootMode::SkipBios);
+
+    for access in accesses {
+        match access {
+            Access::Read {
+                region,
+                offset,
+                size,
+            } => {
+                let address = address(region, offset);
+                match size {
+                    Size::Byte => {
+                        cpu.bus.read_byte_address_debug(address);
+                    }
+                    Size::HalfWord => {
+                        cpu.bus.read_halfword_address_debug(address);
+                    }
+                    Size::Word => {
+                        cpu.bus.read_word_address_debug(address);
+                    }
+                }
+            }
+            Access::Write {
+                region,
+                offset,
+                size,
+                value,
+            } => {
+                let address = address(region, offset);
+                match size {
+                    Size::Byte => cpu.bus.write_byte_address_debug(value as u8, address),
+                    Size::HalfWord => cpu.bus.write_halfword_address_debug(value as u16, address),
+                    Size::Word => cpu.bus.write_word_address_debug(value, address),
+                }
+            }
+            Access::Run { cycles } => {
+                let target = cpu.bus.cycle_count() + u64::from(cycles);
+                while cpu.bus.cycle_count() < target {
+                    cpu.fetch_decode_execute();
+                }
+            }
+        }
+    }
+});
@@ -0,0 +1,33 @@
+#![no_main]
+
+// Runs arbitrary data as a cartridge ROM, starting straight at the entry point. Covers the
+// instruction decoders as well as anything the resulting code does to the rest of the system.
+
+use emulator_core::{BootMode, Cartridge, Cpu};
+use libfuzzer_sys::fuzz_target;
+
+// Enough for a couple of frames, while keeping each run short.
+const CYCLE_BUDGET: u64 = 1_000_000;
+// Halted or stopped systems still count cycles, but cap steps in case something doesn't.
+const STEP_BUDGET: usize = 1_000_000;
+
+// The cartridge header has to be there for the ROM to load at all.
+const MIN_ROM_SIZE: usize = 0xC0;
+
+fuzz_target!(|data: &[u8]| {
+    let mut rom = data.to_vec();
+    rom.resize(rom.len().max(MIN_ROM_SIZE), 0);
+
+    let Ok(cartridge) = Cartridge::new(rom.as_slice(), None) else {
+        return;
+    };
+    let mut cpu = Cpu::with_boot_mode(cartridge, BootMode::SkipBios);
+
+    for _ in 0..STEP_BUDGET {
+        if cpu.bus.cycle_count() >= CYCLE_BUDGET {
+            break;
+        }
+
+        cpu.fetch_decode_execute();
+    }
+});
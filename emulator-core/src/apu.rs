@@ -1,6 +1,7 @@
 mod dma_fifo;
-
+mod frame_sequencer;
 mod noise;
+mod output;
 mod tone;
 mod tone_and_sweep;
 mod wave;
@@ -8,37 +9,52 @@ mod wave;
 use std::ops::RangeInclusive;
 
 use crate::{bit_manipulation::BitManipulation, bus::TimerStepResult, DataAccess};
+use serde::{Deserialize, Serialize};
 
 use dma_fifo::DmaFifo;
+use frame_sequencer::FrameSequencer;
 use noise::Noise;
+use output::AudioOutput;
 use tone::Tone;
 use tone_and_sweep::ToneAndSweep;
 use wave::Wave;
 
+pub use output::AudioRingBuffer;
+
 #[derive(Clone, Copy, Debug)]
 enum DmaFifoTimerSelect {
     Timer0,
     Timer1,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Apu {
     channel_lr_volume_enable: u16,
     dma_sound_control: u16,
     sound_on_off: u32,
     sound_pwm_control: u32,
 
+    #[serde(default)]
+    frame_sequencer: FrameSequencer,
     fifo_a: DmaFifo,
     fifo_b: DmaFifo,
     tone_and_sweep: ToneAndSweep,
     tone: Tone,
     wave: Wave,
     noise: Noise,
+
+    // Frontend state, so not part of save states.
+    #[serde(skip)]
+    output: Option<AudioOutput>,
 }
 
 impl Apu {
     // returns a value from -1.0 to 1.0
     pub fn sample(&self) -> [f32; 2] {
+        if !self.get_master_enable() {
+            return [0.0; 2];
+        }
+
         let tone_and_sweep_sample = self.tone_and_sweep.sample();
         let tone_sample = self.tone.sample();
         let wave_sample = self.wave.sample();
@@ -120,12 +136,57 @@ impl Apu {
             sample_right += dma_fifo_b_scaled;
         }
 
-        [sample_left, sample_right]
+        [self.apply_bias(sample_left), self.apply_bias(sample_right)]
+    }
+
+    // The rate the mixed output is actually produced at, which goes up as its resolution goes
+    // down. Samples taken any faster than this just repeat.
+    pub fn output_sample_rate(&self) -> u32 {
+        const BASE_SAMPLE_RATE: u32 = 32_768;
+
+        BASE_SAMPLE_RATE << self.get_amplitude_resolution()
+    }
+
+    // The mixed sample is offset by the bias level into the 10-bit range of the PWM output, so
+    // anything that ends up outside of it is clipped, and then loses precision depending on the
+    // amplitude resolution. The speaker is AC coupled, so the bias itself isn't heard.
+    fn apply_bias(&self, sample: f32) -> f32 {
+        const PWM_MAX_LEVEL: i32 = 0x3FF;
+        const PWM_HALF_RANGE: f32 = 512.0;
+
+        let bias = i32::from(self.get_bias_level());
+        let level = ((sample * PWM_HALF_RANGE) as i32 + bias).clamp(0, PWM_MAX_LEVEL);
+
+        // At the lowest resolution setting only the top 9 bits make it out.
+        let dropped_bits = 1 + self.get_amplitude_resolution();
+        let level = level & !((1 << dropped_bits) - 1);
+
+        (level - bias) as f32 / PWM_HALF_RANGE
     }
 }
 
 impl Apu {
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub(super) fn step(&mut self, timer_result: TimerStepResult) {
+        if let Some(clocks) = self.frame_sequencer.step() {
+            if clocks.length {
+                self.tone_and_sweep.clock_length();
+                self.tone.clock_length();
+                self.wave.clock_length();
+                self.noise.clock_length();
+            }
+
+            if clocks.envelope {
+                self.tone_and_sweep.clock_envelope();
+                self.tone.clock_envelope();
+                self.noise.clock_envelope();
+            }
+
+            if clocks.sweep {
+                self.tone_and_sweep.clock_sweep();
+            }
+        }
+
         self.tone_and_sweep.step();
         self.tone.step();
         self.wave.step();
@@ -143,6 +204,31 @@ impl Apu {
 
         self.fifo_a.step(sound_a_overflow);
         self.fifo_b.step(sound_b_overflow);
+
+        if let Some(mut output) = self.output.take() {
+            output.step(self.output_sample_rate(), || self.sample());
+            self.output = Some(output);
+        }
+    }
+
+    pub(super) fn enable_output(&mut self, sample_rate: u32, capacity: usize) -> AudioRingBuffer {
+        let output = AudioOutput::new(sample_rate, capacity);
+        let buffer = output.buffer();
+        self.output = Some(output);
+
+        buffer
+    }
+
+    pub(super) fn disable_output(&mut self) {
+        self.output = None;
+    }
+
+    pub(super) fn take_output(&mut self) -> Option<AudioOutput> {
+        self.output.take()
+    }
+
+    pub(super) fn set_output(&mut self, output: Option<AudioOutput>) {
+        self.output = output;
     }
 
     pub fn write_fifo_a(&mut self, value: u32) {
@@ -174,6 +260,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.tone_and_sweep.write_sweep_register(value, index);
     }
 
@@ -188,6 +278,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.tone_and_sweep.write_duty_length_envelope(value, index)
     }
 
@@ -202,6 +296,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.tone_and_sweep.write_frequency_control(value, index)
     }
 }
@@ -218,6 +316,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.tone.write_duty_length_envelope(value, index)
     }
 
@@ -232,6 +334,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.tone.write_frequency_control(value, index)
     }
 }
@@ -248,6 +354,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.wave.write_stop_wave_ram_select(value, index);
     }
 
@@ -262,6 +372,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.wave.write_length_volume(value, index);
     }
 
@@ -276,6 +390,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.wave.write_frequency_control(value, index)
     }
 
@@ -300,6 +418,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.noise.write_length_envelope(value, index)
     }
 
@@ -314,11 +436,21 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         self.noise.write_frequency_control(value, index)
     }
 }
 
 impl Apu {
+    fn get_master_enable(&self) -> bool {
+        const MASTER_ENABLE_BIT_INDEX: usize = 7;
+
+        self.sound_on_off.get_bit(MASTER_ENABLE_BIT_INDEX)
+    }
+
     fn get_master_volume_right(&self) -> u8 {
         const MASTER_VOLUME_RIGHT_BIT_RANGE: RangeInclusive<usize> = 0..=2;
 
@@ -376,6 +508,10 @@ impl Apu {
     where
         u16: DataAccess<T>,
     {
+        if !self.get_master_enable() {
+            return;
+        }
+
         const CHANNEL_LR_VOLUME_ENABLE_WRITE_MASK: u16 = 0xFF77;
         self.channel_lr_volume_enable = self.channel_lr_volume_enable.set_data(value, index)
             & CHANNEL_LR_VOLUME_ENABLE_WRITE_MASK;
@@ -412,6 +548,21 @@ impl Apu {
         // TODO: Handle bits 0-3 manually in read.
         const SOUND_ON_OFF_WRITE_MASK: u32 = 0x0000_0080;
         self.sound_on_off = self.sound_on_off.set_data(value, index) & SOUND_ON_OFF_WRITE_MASK;
+
+        if !self.get_master_enable() {
+            self.power_off();
+        }
+    }
+
+    // Everything from SOUND1CNT_L up to SOUNDCNT_L is reset, and stays that way until the APU is
+    // powered back on. Wave RAM is left alone, and the frame sequencer starts over from step 0.
+    fn power_off(&mut self) {
+        self.channel_lr_volume_enable = 0;
+        self.frame_sequencer = FrameSequencer::default();
+        self.tone_and_sweep = ToneAndSweep::default();
+        self.tone = Tone::default();
+        self.wave.power_off();
+        self.noise = Noise::default();
     }
 
     pub fn read_sound_pwm_control<T>(&self, index: u32) -> T
@@ -425,13 +576,26 @@ impl Apu {
     where
         u32: DataAccess<T>,
     {
-        const SOUND_PWM_CONTROL_WRITE_MASK: u32 = 0x0000_BFFE;
+        const SOUND_PWM_CONTROL_WRITE_MASK: u32 = 0x0000_C3FE;
         self.sound_pwm_control =
             self.sound_pwm_control.set_data(value, index) & SOUND_PWM_CONTROL_WRITE_MASK;
     }
 }
 
 impl Apu {
+    fn get_bias_level(&self) -> u16 {
+        const BIAS_LEVEL_BIT_RANGE: RangeInclusive<usize> = 0..=9;
+
+        self.sound_pwm_control.get_bit_range(BIAS_LEVEL_BIT_RANGE) as u16
+    }
+
+    fn get_amplitude_resolution(&self) -> u32 {
+        const AMPLITUDE_RESOLUTION_BIT_RANGE: RangeInclusive<usize> = 14..=15;
+
+        self.sound_pwm_control
+            .get_bit_range(AMPLITUDE_RESOLUTION_BIT_RANGE)
+    }
+
     fn get_dma_sound_a_enable(&self) -> (bool, bool) {
         const DMA_SOUND_A_ENABLE_RIGHT_BIT_INDEX: usize = 8;
         const DMA_SOUND_A_ENABLE_LEFT_BIT_INDEX: usize = 9;
@@ -1,13 +1,14 @@
 use std::collections::VecDeque;
 
 use crate::CYCLES_PER_SECOND;
+use serde::{Deserialize, Serialize};
 
 // Number of 32-bit samples.
 const BUFFER_SIZE: usize = 32;
 
 const SAMPLE_FREQUENCY: u64 = 32_768;
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub(super) struct DmaFifo {
     buffer: VecDeque<i8>,
 
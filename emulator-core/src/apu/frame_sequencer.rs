@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+use crate::CYCLES_PER_SECOND;
+
+// Clocks per second
+const FRAME_SEQUENCER_FREQUENCY: u64 = 512;
+
+// CPU cycles per clock
+const FRAME_SEQUENCER_PERIOD: u64 = CYCLES_PER_SECOND / FRAME_SEQUENCER_FREQUENCY;
+
+// Step   Length Ctr  Vol Env     Sweep
+// ---------------------------------------
+// 0      Clock       -           -
+// 1      -           -           -
+// 2      Clock       -           Clock
+// 3      -           -           -
+// 4      Clock       -           -
+// 5      -           -           -
+// 6      Clock       -           Clock
+// 7      -           Clock       -
+// ---------------------------------------
+// Rate   256 Hz      64 Hz       128 Hz
+const LENGTH_COUNTER_CLOCKS: [bool; 8] = [true, false, true, false, true, false, true, false];
+const VOLUME_ENVELOPE_CLOCKS: [bool; 8] = [false, false, false, false, false, false, false, true];
+const SWEEP_CLOCKS: [bool; 8] = [false, false, true, false, false, false, true, false];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameSequencerClocks {
+    pub length: bool,
+    pub envelope: bool,
+    pub sweep: bool,
+}
+
+// Divides the APU clock down to 512 Hz, and steps through the 8 steps above on each tick to
+// clock the length counters, volume envelopes and sweep of the PSG channels.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FrameSequencer {
+    cycles_left: u64,
+    step: u8,
+}
+
+impl FrameSequencer {
+    // Returns what should be clocked this cycle, if anything.
+    pub fn step(&mut self) -> Option<FrameSequencerClocks> {
+        let clocks = (self.cycles_left == 0).then(|| {
+            let step = usize::from(self.step);
+            self.step = (self.step + 1) % 8;
+            self.cycles_left = FRAME_SEQUENCER_PERIOD;
+
+            FrameSequencerClocks {
+                length: LENGTH_COUNTER_CLOCKS[step],
+                envelope: VOLUME_ENVELOPE_CLOCKS[step],
+                sweep: SWEEP_CLOCKS[step],
+            }
+        });
+
+        self.cycles_left -= 1;
+        clocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_sequencer_rates() {
+        let mut frame_sequencer = FrameSequencer::default();
+        let mut clocks = Vec::new();
+        for cycle in 0..CYCLES_PER_SECOND {
+            if let Some(step_clocks) = frame_sequencer.step() {
+                assert_eq!(cycle % FRAME_SEQUENCER_PERIOD, 0);
+                clocks.push(step_clocks);
+            }
+        }
+
+        assert_eq!(clocks.len(), 512);
+        assert_eq!(clocks.iter().filter(|clocks| clocks.length).count(), 256);
+        assert_eq!(clocks.iter().filter(|clocks| clocks.envelope).count(), 64);
+        assert_eq!(clocks.iter().filter(|clocks| clocks.sweep).count(), 128);
+    }
+}
@@ -1,15 +1,7 @@
 use std::ops::RangeInclusive;
 
-use crate::{bit_manipulation::BitManipulation, data_access::DataAccess, CYCLES_PER_SECOND};
-
-// Clocks per second
-const SEQUENCER_CLOCK_FREQUENCY: u64 = 512;
-
-// CPU cycles per clock
-const SEQUENCER_CLOCK_PERIOD: u64 = CYCLES_PER_SECOND / SEQUENCER_CLOCK_FREQUENCY;
-
-const LENGTH_COUNTER_CLOCKS: [bool; 8] = [true, false, true, false, true, false, true, false];
-const VOLUME_ENVELOPE_CLOCKS: [bool; 8] = [false, false, false, false, false, false, false, true];
+use crate::{bit_manipulation::BitManipulation, data_access::DataAccess};
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug)]
 enum EnvelopeBehavior {
@@ -17,24 +9,21 @@ enum EnvelopeBehavior {
     VolumeDecrease,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum CounterStepWidth {
     FifteenBit,
     SevenBit,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Noise {
     length_envelope: u16,
     frequency_control: u16,
 
     length_counter: u8,
 
-    frame_sequencer_idx: u16,
-    clock: u64,
-
     linear_feedback_shift_register: u16,
-    noise_ticks_left: u16,
+    noise_ticks_left: u32,
 
     envelope_ticks_left: u8,
 
@@ -44,74 +33,47 @@ pub struct Noise {
 
 impl Noise {
     pub fn step(&mut self) {
-        if self.clock % SEQUENCER_CLOCK_PERIOD == 0 {
-            if LENGTH_COUNTER_CLOCKS[usize::from(self.frame_sequencer_idx)] {
-                if self.get_length_flag() {
-                    self.length_counter = self.length_counter.saturating_sub(1);
-
-                    if self.length_counter == 0 {
-                        self.enabled = false;
-                    }
-                }
+        if let Some(period) = self.get_frequency_timer_period() {
+            self.noise_ticks_left = self.noise_ticks_left.saturating_sub(1);
+            if self.noise_ticks_left == 0 {
+                self.linear_feedback_shift_register = clock_linear_feedback_shift_register(
+                    self.linear_feedback_shift_register,
+                    self.get_counter_step_width(),
+                );
+                self.noise_ticks_left = period;
             }
+        }
+    }
 
-            if VOLUME_ENVELOPE_CLOCKS[usize::from(self.frame_sequencer_idx)] {
-                self.envelope_ticks_left = self.envelope_ticks_left.saturating_sub(1);
-
-                if self.envelope_ticks_left == 0 {
-                    if self.get_envelope_sweep_period() != 0 {
-                        match self.get_envelope_direction() {
-                            EnvelopeBehavior::VolumeIncrease => {
-                                self.volume = u8::min(self.volume + 1, 0xF)
-                            }
-                            EnvelopeBehavior::VolumeDecrease => {
-                                self.volume = self.volume.saturating_sub(1)
-                            }
-                        }
-                    }
-
-                    self.envelope_ticks_left = if self.get_envelope_sweep_period() == 0 {
-                        8
-                    } else {
-                        self.get_envelope_sweep_period()
-                    }
-                }
-            }
+    // Clocked at 256 Hz by the frame sequencer.
+    pub fn clock_length(&mut self) {
+        if self.get_length_flag() {
+            self.length_counter = self.length_counter.saturating_sub(1);
 
-            self.frame_sequencer_idx = (self.frame_sequencer_idx + 1) % 8;
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
         }
+    }
+
+    // Clocked at 64 Hz by the frame sequencer.
+    pub fn clock_envelope(&mut self) {
+        self.envelope_ticks_left = self.envelope_ticks_left.saturating_sub(1);
 
-        // When clocked by the frequency timer, the low two bits (0 and 1) are XORed, all bits are shifted
-        // right by one, and the result of the XOR is put into the now-empty high bit.
-        self.noise_ticks_left = self.noise_ticks_left.saturating_sub(1);
-        if self.noise_ticks_left == 0 {
-            let xor_result = self.linear_feedback_shift_register.get_bit(0)
-                ^ self.linear_feedback_shift_register.get_bit(1);
-            self.linear_feedback_shift_register =
-                (self.linear_feedback_shift_register >> 1).set_bit(14, xor_result);
-
-            // If width mode is 1 (NR43, 7 bit counter step/width), the XOR result is ALSO put into bit 6 AFTER the shift, resulting in a 7-bit LFSR.
-            match self.get_counter_step_width() {
-                CounterStepWidth::SevenBit => {
-                    self.linear_feedback_shift_register =
-                        self.linear_feedback_shift_register.set_bit(6, xor_result)
+        if self.envelope_ticks_left == 0 {
+            if self.get_envelope_sweep_period() != 0 {
+                match self.get_envelope_direction() {
+                    EnvelopeBehavior::VolumeIncrease => self.volume = u8::min(self.volume + 1, 0xF),
+                    EnvelopeBehavior::VolumeDecrease => self.volume = self.volume.saturating_sub(1),
                 }
-                CounterStepWidth::FifteenBit => {}
-            };
-
-            // GBATEK: Frequency = 524288 Hz / r / 2^(s+1) ;For r=0 assume r=0.5 instead
-            //
-            // This can be equivalently modeled as 262_144 Hz / r / 2^s (still treating r=0 as r=0.5)
-            // Converted to ticks between update (at a tick rate of 16_777_216 Hz), we get:
-            // r * 2^s * 64 (with r=0 treated as r=0.5), or (r << 6) * 2^s, or (r << 6) * (1 << s), with (r << 6)=32 when r=0
-
-            self.noise_ticks_left = match self.get_frequency_dividing_ratio() {
-                0 => 32 << self.get_shift_clock_frequency(),
-                ratio => (u16::from(ratio) << 6) << self.get_shift_clock_frequency(),
-            };
-        }
+            }
 
-        self.clock += 1;
+            self.envelope_ticks_left = if self.get_envelope_sweep_period() == 0 {
+                8
+            } else {
+                self.get_envelope_sweep_period()
+            }
+        }
     }
 
     pub fn sample(&self) -> u8 {
@@ -135,10 +97,15 @@ impl Noise {
     // - Noise channel's LFSR bits are all set to 1.
     // - Wave channel's position is set to 0 but sample buffer is NOT refilled.
     // - Square 1's sweep does several things (see frequency sweep).
+    //
+    // The channel stays disabled if its DAC is off.
     fn trigger(&mut self) {
-        self.enabled = true;
+        const INITIAL_LINEAR_FEEDBACK_SHIFT_REGISTER: u16 = 0x7FFF;
+
+        self.enabled = self.get_dac_enabled();
         self.volume = self.get_envelope_initial_volume();
-        self.linear_feedback_shift_register = !0;
+        self.linear_feedback_shift_register = INITIAL_LINEAR_FEEDBACK_SHIFT_REGISTER;
+        self.noise_ticks_left = self.get_frequency_timer_period().unwrap_or(0);
 
         if self.length_counter == 0 {
             self.length_counter = 64;
@@ -200,6 +167,32 @@ impl Noise {
             .get_bit_range(SHIFT_CLOCK_FREQUENCY_BIT_RANGE) as u8
     }
 
+    // The DAC is off when the envelope can never produce anything but silence, which also turns
+    // the channel off.
+    fn get_dac_enabled(&self) -> bool {
+        const DAC_ENABLE_BIT_RANGE: RangeInclusive<usize> = 11..=15;
+
+        self.length_envelope.get_bit_range(DAC_ENABLE_BIT_RANGE) != 0
+    }
+
+    // GBATEK: Frequency = 524288 Hz / r / 2^(s+1) ;For r=0 assume r=0.5 instead
+    //
+    // At 16_777_216 cycles per second that's a period of 64 * r * 2^s cycles, or 32 * 2^s for
+    // r=0. Shift clock frequencies 14 and 15 don't clock the LFSR at all.
+    fn get_frequency_timer_period(&self) -> Option<u32> {
+        let shift = self.get_shift_clock_frequency();
+        if shift >= 14 {
+            return None;
+        }
+
+        let period = match self.get_frequency_dividing_ratio() {
+            0 => 32,
+            ratio => u32::from(ratio) << 6,
+        };
+
+        Some(period << shift)
+    }
+
     fn get_length_flag(&self) -> bool {
         const LENGTH_FLAG_BIT_INDEX: usize = 14;
 
@@ -224,7 +217,13 @@ impl Noise {
         // Length counter in duty length envelope is always zero.
         self.length_envelope = self.length_envelope.set_data(value, index);
 
-        self.length_counter = self.length_envelope.get_bit_range(LENGTH_COUNTER_BIT_RANGE) as u8;
+        // The written length is how many of the 64 length clocks have already passed.
+        self.length_counter =
+            64 - self.length_envelope.get_bit_range(LENGTH_COUNTER_BIT_RANGE) as u8;
+
+        if !self.get_dac_enabled() {
+            self.enabled = false;
+        }
 
         self.length_envelope = self
             .length_envelope
@@ -254,3 +253,77 @@ impl Noise {
         self.frequency_control = self.frequency_control.set_bit(TRIGGER_BIT_INDEX, false);
     }
 }
+
+// When clocked by the frequency timer, the low two bits (0 and 1) are XORed, all bits are shifted
+// right by one, and the result of the XOR is put into the now-empty high bit. In 7-bit mode the
+// result is also put into bit 6 after the shift, so that only the low 7 bits cycle.
+fn clock_linear_feedback_shift_register(value: u16, width: CounterStepWidth) -> u16 {
+    let xor_result = value.get_bit(0) ^ value.get_bit(1);
+    let value = (value >> 1).set_bit(14, xor_result);
+
+    match width {
+        CounterStepWidth::SevenBit => value.set_bit(6, xor_result),
+        CounterStepWidth::FifteenBit => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_feedback_shift_register_sequence() {
+        for (width, first, period) in [
+            (CounterStepWidth::FifteenBit, 0x3FFF, 0x7FFF),
+            (CounterStepWidth::SevenBit, 0x3FBF, 0x7F),
+        ] {
+            let mut lfsr = clock_linear_feedback_shift_register(0x7FFF, width);
+            assert_eq!(lfsr, first);
+
+            // Bit 0 is the output, which repeats every `period` clocks.
+            let mut output = vec![lfsr.get_bit(0)];
+            for _ in 0..period * 2 {
+                lfsr = clock_linear_feedback_shift_register(lfsr, width);
+                output.push(lfsr.get_bit(0));
+            }
+
+            assert_eq!(output[..period], output[period..period * 2]);
+            assert!((1..period).all(|shift| output[..period] != output[shift..shift + period]));
+        }
+    }
+
+    #[test]
+    fn noise_channel() {
+        let mut noise = Noise::default();
+        noise.write_length_envelope(0xF000u16 | 60, 0);
+        assert_eq!(noise.length_counter, 4);
+
+        // r=3, s=2, 7-bit, triggered.
+        noise.write_frequency_control(0x8023u16 | 0x0008, 0);
+        assert_eq!(noise.get_frequency_timer_period(), Some(3 * 64 * 4));
+        assert!(noise.enabled);
+        assert_eq!(noise.linear_feedback_shift_register, 0x7FFF);
+
+        for _ in 0..(3 * 64 * 4) - 1 {
+            noise.step();
+        }
+        assert_eq!(noise.linear_feedback_shift_register, 0x7FFF);
+        noise.step();
+        assert_eq!(noise.linear_feedback_shift_register, 0x3FBF);
+
+        // Shift clock frequencies 14 and 15 stop the LFSR.
+        noise.write_frequency_control(0x00E0u16, 0);
+        assert_eq!(noise.get_frequency_timer_period(), None);
+        for _ in 0..0x10000 {
+            noise.step();
+        }
+        assert_eq!(noise.linear_feedback_shift_register, 0x3FBF);
+
+        // Turning the DAC off turns the channel off, and keeps triggers from turning it back on.
+        noise.write_length_envelope(0x0000u16, 0);
+        assert!(!noise.enabled);
+        noise.write_frequency_control(0x8000u16, 0);
+        assert!(!noise.enabled);
+        assert_eq!(noise.sample(), 0);
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+
+use crate::CYCLES_PER_SECOND;
+
+// Stereo samples shared between whatever produces them, usually the emulator, and whatever plays
+// them back, usually an audio thread. It's lock free, so that neither side ever waits on the
+// other, which only holds with one thread pushing and one popping. Once full, new samples are
+// dropped until there's room, so a consumer that falls behind only ever loses audio rather than
+// adding latency.
+#[derive(Clone, Debug)]
+pub struct AudioRingBuffer {
+    ring: Arc<SampleRing>,
+}
+
+// Each sample is stored as the bits of both channels together, since there's no atomic f32, so
+// that the channels can never get swapped. Both indices only ever increase, wrapping around, and
+// each is only advanced by one side.
+#[derive(Debug)]
+struct SampleRing {
+    samples: Box<[AtomicU64]>,
+    read_index: AtomicUsize,
+    write_index: AtomicUsize,
+}
+
+impl AudioRingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            ring: Arc::new(SampleRing {
+                samples: (0..capacity).map(|_| AtomicU64::new(0)).collect(),
+                read_index: AtomicUsize::new(0),
+                write_index: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    pub fn push(&self, [left, right]: [f32; 2]) {
+        let ring = &self.ring;
+        let write_index = ring.write_index.load(Ordering::Relaxed);
+        if write_index.wrapping_sub(ring.read_index.load(Ordering::Acquire)) == self.capacity() {
+            return;
+        }
+
+        let bits = u64::from(left.to_bits()) | (u64::from(right.to_bits()) << 32);
+        ring.samples[write_index % self.capacity()].store(bits, Ordering::Relaxed);
+        ring.write_index
+            .store(write_index.wrapping_add(1), Ordering::Release);
+    }
+
+    pub fn pop(&self) -> Option<[f32; 2]> {
+        let ring = &self.ring;
+        let read_index = ring.read_index.load(Ordering::Relaxed);
+        if read_index == ring.write_index.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let bits = ring.samples[read_index % self.capacity()].load(Ordering::Relaxed);
+        ring.read_index
+            .store(read_index.wrapping_add(1), Ordering::Release);
+
+        Some([
+            f32::from_bits(bits as u32),
+            f32::from_bits((bits >> 32) as u32),
+        ])
+    }
+
+    pub fn len(&self) -> usize {
+        let write_index = self.ring.write_index.load(Ordering::Acquire);
+        write_index.wrapping_sub(self.ring.read_index.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.samples.len()
+    }
+}
+
+// Resamples the APU output to the host's sample rate. The APU's own rate depends on SOUNDBIAS, so
+// its samples are averaged together (or repeated) to make up each host sample.
+#[derive(Clone, Debug)]
+pub(crate) struct AudioOutput {
+    buffer: AudioRingBuffer,
+    sample_rate: u32,
+    cycles: u64,
+    next_apu_sample_cycle: u64,
+    host_samples: u64,
+    apu_sample_sum: [f32; 2],
+    apu_sample_count: u32,
+    last_sample: [f32; 2],
+}
+
+impl AudioOutput {
+    pub(super) fn new(sample_rate: u32, capacity: usize) -> Self {
+        Self {
+            buffer: AudioRingBuffer::new(capacity),
+            sample_rate,
+            cycles: 0,
+            next_apu_sample_cycle: 0,
+            host_samples: 0,
+            apu_sample_sum: [0.0; 2],
+            apu_sample_count: 0,
+            last_sample: [0.0; 2],
+        }
+    }
+
+    pub(super) fn buffer(&self) -> AudioRingBuffer {
+        self.buffer.clone()
+    }
+
+    // Called every cycle with the APU's current output and sample rate.
+    pub(super) fn step(&mut self, apu_sample_rate: u32, sample: impl FnOnce() -> [f32; 2]) {
+        self.cycles += 1;
+
+        if self.cycles > self.next_apu_sample_cycle {
+            let [left, right] = sample();
+            self.apu_sample_sum[0] += left;
+            self.apu_sample_sum[1] += right;
+            self.apu_sample_count += 1;
+            self.next_apu_sample_cycle += CYCLES_PER_SECOND / u64::from(apu_sample_rate);
+        }
+
+        if self.cycles > self.host_samples * CYCLES_PER_SECOND / u64::from(self.sample_rate) {
+            if self.apu_sample_count > 0 {
+                let count = self.apu_sample_count as f32;
+                self.last_sample = self.apu_sample_sum.map(|sum| sum / count);
+                self.apu_sample_sum = [0.0; 2];
+                self.apu_sample_count = 0;
+            }
+
+            self.buffer.push(self.last_sample);
+            self.host_samples += 1;
+        }
+    }
+}
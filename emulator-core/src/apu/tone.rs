@@ -1,15 +1,7 @@
 use std::ops::RangeInclusive;
 
-use crate::{bit_manipulation::BitManipulation, data_access::DataAccess, CYCLES_PER_SECOND};
-
-// Clocks per second
-const SEQUENCER_CLOCK_FREQUENCY: u64 = 512;
-
-// CPU cycles per clock
-const SEQUENCER_CLOCK_PERIOD: u64 = CYCLES_PER_SECOND / SEQUENCER_CLOCK_FREQUENCY;
-
-const LENGTH_COUNTER_CLOCKS: [bool; 8] = [true, false, true, false, true, false, true, false];
-const VOLUME_ENVELOPE_CLOCKS: [bool; 8] = [false, false, false, false, false, false, false, true];
+use crate::{bit_manipulation::BitManipulation, data_access::DataAccess};
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug)]
 enum EnvelopeBehavior {
@@ -17,16 +9,13 @@ enum EnvelopeBehavior {
     VolumeDecrease,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Tone {
     duty_length_envelope: u16,
     frequency_control: u16,
 
     length_counter: u8,
 
-    frame_sequencer_idx: u16,
-    clock: u64,
-
     wave_duty_index: u8,
     wave_duty_timer_ticks_left: u16,
     envelope_ticks_left: u8,
@@ -37,43 +26,6 @@ pub struct Tone {
 
 impl Tone {
     pub fn step(&mut self) {
-        if self.clock % SEQUENCER_CLOCK_PERIOD == 0 {
-            if LENGTH_COUNTER_CLOCKS[usize::from(self.frame_sequencer_idx)] {
-                if self.get_length_flag() {
-                    self.length_counter = self.length_counter.saturating_sub(1);
-
-                    if self.length_counter == 0 {
-                        self.enabled = false;
-                    }
-                }
-            }
-
-            if VOLUME_ENVELOPE_CLOCKS[usize::from(self.frame_sequencer_idx)] {
-                self.envelope_ticks_left = self.envelope_ticks_left.saturating_sub(1);
-
-                if self.envelope_ticks_left == 0 {
-                    if self.get_envelope_sweep_period() != 0 {
-                        match self.get_envelope_direction() {
-                            EnvelopeBehavior::VolumeIncrease => {
-                                self.volume = u8::min(self.volume + 1, 0xF)
-                            }
-                            EnvelopeBehavior::VolumeDecrease => {
-                                self.volume = self.volume.saturating_sub(1)
-                            }
-                        }
-                    }
-
-                    self.envelope_ticks_left = if self.get_envelope_sweep_period() == 0 {
-                        8
-                    } else {
-                        self.get_envelope_sweep_period()
-                    }
-                }
-            }
-
-            self.frame_sequencer_idx = (self.frame_sequencer_idx + 1) % 8;
-        }
-
         self.wave_duty_timer_ticks_left = self.wave_duty_timer_ticks_left.saturating_sub(1);
         if self.wave_duty_timer_ticks_left == 0 {
             self.wave_duty_index = (self.wave_duty_index + 1) % 8;
@@ -81,8 +33,37 @@ impl Tone {
             // *4 on the GB, *16 on the GBA -- the GBA core clock runs at 4x the frequency.
             self.wave_duty_timer_ticks_left = (2048 - self.get_frequency()) * 16;
         }
+    }
+
+    // Clocked at 256 Hz by the frame sequencer.
+    pub fn clock_length(&mut self) {
+        if self.get_length_flag() {
+            self.length_counter = self.length_counter.saturating_sub(1);
+
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
+        }
+    }
 
-        self.clock += 1;
+    // Clocked at 64 Hz by the frame sequencer.
+    pub fn clock_envelope(&mut self) {
+        self.envelope_ticks_left = self.envelope_ticks_left.saturating_sub(1);
+
+        if self.envelope_ticks_left == 0 {
+            if self.get_envelope_sweep_period() != 0 {
+                match self.get_envelope_direction() {
+                    EnvelopeBehavior::VolumeIncrease => self.volume = u8::min(self.volume + 1, 0xF),
+                    EnvelopeBehavior::VolumeDecrease => self.volume = self.volume.saturating_sub(1),
+                }
+            }
+
+            self.envelope_ticks_left = if self.get_envelope_sweep_period() == 0 {
+                8
+            } else {
+                self.get_envelope_sweep_period()
+            }
+        }
     }
 
     pub fn sample(&self) -> u8 {
@@ -1,16 +1,7 @@
 use std::{collections::btree_map::Range, ops::RangeInclusive};
 
-use crate::{bit_manipulation::BitManipulation, data_access::DataAccess, CYCLES_PER_SECOND};
-
-// Clocks per second
-const SEQUENCER_CLOCK_FREQUENCY: u64 = 512;
-
-// CPU cycles per clock
-const SEQUENCER_CLOCK_PERIOD: u64 = CYCLES_PER_SECOND / SEQUENCER_CLOCK_FREQUENCY;
-
-const LENGTH_COUNTER_CLOCKS: [bool; 8] = [true, false, true, false, true, false, true, false];
-const VOLUME_ENVELOPE_CLOCKS: [bool; 8] = [false, false, false, false, false, false, false, true];
-const SWEEP_CLOCKS: [bool; 8] = [false, false, true, false, false, false, true, false];
+use crate::{bit_manipulation::BitManipulation, data_access::DataAccess};
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug)]
 enum SweepBehavior {
@@ -24,19 +15,19 @@ enum EnvelopeBehavior {
     VolumeDecrease,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ToneAndSweep {
     sweep_register: u16,
     duty_length_envelope: u16,
     frequency_control: u16,
 
     frequency_sweep_enabled: bool,
+    // Set once a frequency has been calculated in negate mode since the last trigger.
+    #[serde(default)]
+    sweep_negate_used: bool,
 
     length_counter: u8,
 
-    frame_sequencer_idx: u16,
-    clock: u64,
-
     wave_duty_index: u8,
     wave_duty_timer_ticks_left: u16,
     envelope_ticks_left: u8,
@@ -50,109 +41,68 @@ pub struct ToneAndSweep {
 
 impl ToneAndSweep {
     pub fn step(&mut self) {
-        if self.clock % SEQUENCER_CLOCK_PERIOD == 0 {
-            if LENGTH_COUNTER_CLOCKS[usize::from(self.frame_sequencer_idx)] {
-                if self.get_length_flag() {
-                    self.length_counter = self.length_counter.saturating_sub(1);
-
-                    if self.length_counter == 0 {
-                        self.enabled = false;
-                    }
-                }
-            }
+        self.wave_duty_timer_ticks_left = self.wave_duty_timer_ticks_left.saturating_sub(1);
+        if self.wave_duty_timer_ticks_left == 0 {
+            self.wave_duty_index = (self.wave_duty_index + 1) % 8;
 
-            if VOLUME_ENVELOPE_CLOCKS[usize::from(self.frame_sequencer_idx)] {
-                self.envelope_ticks_left = self.envelope_ticks_left.saturating_sub(1);
-
-                if self.envelope_ticks_left == 0 {
-                    if self.get_envelope_sweep_period() != 0 {
-                        match self.get_envelope_direction() {
-                            EnvelopeBehavior::VolumeIncrease => {
-                                self.volume = u8::min(self.volume + 1, 0xF)
-                            }
-                            EnvelopeBehavior::VolumeDecrease => {
-                                self.volume = self.volume.saturating_sub(1)
-                            }
-                        }
-                    }
-
-                    self.envelope_ticks_left = if self.get_envelope_sweep_period() == 0 {
-                        8
-                    } else {
-                        self.get_envelope_sweep_period()
-                    }
-                }
+            // *4 on the GB, *16 on the GBA -- the GBA core clock runs at 4x the frequency.
+            self.wave_duty_timer_ticks_left = (2048 - self.get_frequency()) * 16;
+        }
+    }
+
+    // Clocked at 256 Hz by the frame sequencer.
+    pub fn clock_length(&mut self) {
+        if self.get_length_flag() {
+            self.length_counter = self.length_counter.saturating_sub(1);
+
+            if self.length_counter == 0 {
+                self.enabled = false;
             }
+        }
+    }
+
+    // Clocked at 64 Hz by the frame sequencer.
+    pub fn clock_envelope(&mut self) {
+        self.envelope_ticks_left = self.envelope_ticks_left.saturating_sub(1);
 
-            if SWEEP_CLOCKS[usize::from(self.frame_sequencer_idx)] {
-                self.sweep_ticks_left = self.sweep_ticks_left.saturating_sub(1);
-                if self.sweep_ticks_left == 0 {
-                    if self.frequency_sweep_enabled && self.get_sweep_period() != 0 {
-                        let new_frequency = match self.get_sweep_behavior() {
-                            SweepBehavior::FrequencyIncrease => {
-                                self.frequency_shadow
-                                    + (self.frequency_shadow >> self.get_sweep_shift())
-                            }
-                            SweepBehavior::FrequencyDecrease => {
-                                self.frequency_shadow
-                                    - (self.frequency_shadow >> self.get_sweep_shift())
-                            }
-                        };
-
-                        // The sweep timer is clocked at 128 Hz by the frame sequencer. When it generates a clock and the sweep's
-                        // internal enabled flag is set and the sweep period is not zero, a new frequency is calculated and the overflow check is performed.
-                        //
-                        // If the new frequency is 2047 or less and the sweep shift is not zero, this new frequency is written
-                        // back to the shadow frequency and square 1's frequency in NR13 and NR14, then frequency calculation and
-                        // overflow check are run AGAIN immediately using this new value, but this second new frequency is not written back.
-                        if self.get_sweep_shift() != 0 {
-                            if new_frequency > 2047 {
-                                self.enabled = false;
-                            }
-
-                            self.frequency_shadow = new_frequency;
-                            self.set_frequency(new_frequency);
-
-                            // If the new frequency is 2047 or less and the sweep shift is not zero, this new frequency is written back to
-                            // the shadow frequency and square 1's frequency in NR13 and NR14, then frequency calculation and overflow check
-                            // are run AGAIN immediately using this new value, but this second new frequency is not written back.
-                            let test_frequency = match self.get_sweep_behavior() {
-                                SweepBehavior::FrequencyIncrease => {
-                                    self.frequency_shadow
-                                        + (self.frequency_shadow >> self.get_sweep_shift())
-                                }
-                                SweepBehavior::FrequencyDecrease => {
-                                    self.frequency_shadow
-                                        - (self.frequency_shadow >> self.get_sweep_shift())
-                                }
-                            };
-
-                            if test_frequency > 2047 {
-                                self.enabled = false;
-                            }
-                        }
-                    }
-
-                    self.sweep_ticks_left = if self.get_sweep_period() == 0 {
-                        8
-                    } else {
-                        self.get_sweep_period()
-                    };
+        if self.envelope_ticks_left == 0 {
+            if self.get_envelope_sweep_period() != 0 {
+                match self.get_envelope_direction() {
+                    EnvelopeBehavior::VolumeIncrease => self.volume = u8::min(self.volume + 1, 0xF),
+                    EnvelopeBehavior::VolumeDecrease => self.volume = self.volume.saturating_sub(1),
                 }
             }
 
-            self.frame_sequencer_idx = (self.frame_sequencer_idx + 1) % 8;
+            self.envelope_ticks_left = if self.get_envelope_sweep_period() == 0 {
+                8
+            } else {
+                self.get_envelope_sweep_period()
+            }
         }
+    }
 
-        self.wave_duty_timer_ticks_left = self.wave_duty_timer_ticks_left.saturating_sub(1);
-        if self.wave_duty_timer_ticks_left == 0 {
-            self.wave_duty_index = (self.wave_duty_index + 1) % 8;
-
-            // *4 on the GB, *16 on the GBA -- the GBA core clock runs at 4x the frequency.
-            self.wave_duty_timer_ticks_left = (2048 - self.get_frequency()) * 16;
+    // The sweep timer is clocked at 128 Hz by the frame sequencer. When it generates a
+    // clock and the sweep's internal enabled flag is set and the sweep period is not
+    // zero, a new frequency is calculated and the overflow check is performed.
+    //
+    // If the new frequency is 2047 or less and the sweep shift is not zero, this new
+    // frequency is written back to the shadow frequency and square 1's frequency in NR13
+    // and NR14, then frequency calculation and overflow check are run AGAIN immediately
+    // using this new value, but this second new frequency is not written back.
+    pub fn clock_sweep(&mut self) {
+        self.sweep_ticks_left = self.sweep_ticks_left.saturating_sub(1);
+        if self.sweep_ticks_left == 0 {
+            self.reload_sweep_timer();
+
+            if self.frequency_sweep_enabled && self.get_sweep_period() != 0 {
+                let new_frequency = self.calculate_sweep_frequency();
+                if new_frequency <= 2047 && self.get_sweep_shift() != 0 {
+                    self.frequency_shadow = new_frequency;
+                    self.set_frequency(new_frequency);
+                    self.calculate_sweep_frequency();
+                }
+            }
         }
-
-        self.clock += 1;
     }
 
     pub fn sample(&self) -> u8 {
@@ -182,8 +132,41 @@ impl ToneAndSweep {
             self.length_counter = 64;
         }
 
-        self.frequency_sweep_enabled = self.get_sweep_period() > 0 || self.get_sweep_shift() > 0;
         self.frequency_shadow = self.get_frequency();
+        self.reload_sweep_timer();
+        self.frequency_sweep_enabled = self.get_sweep_period() > 0 || self.get_sweep_shift() > 0;
+        self.sweep_negate_used = false;
+
+        if self.get_sweep_shift() != 0 {
+            self.calculate_sweep_frequency();
+        }
+    }
+
+    // Calculates the next frequency from the shadow frequency, disabling the channel if it
+    // overflows.
+    fn calculate_sweep_frequency(&mut self) -> u16 {
+        let delta = self.frequency_shadow >> self.get_sweep_shift();
+        let new_frequency = match self.get_sweep_behavior() {
+            SweepBehavior::FrequencyIncrease => self.frequency_shadow + delta,
+            SweepBehavior::FrequencyDecrease => {
+                self.sweep_negate_used = true;
+                self.frequency_shadow - delta
+            }
+        };
+
+        if new_frequency > 2047 {
+            self.enabled = false;
+        }
+
+        new_frequency
+    }
+
+    // A sweep period of 0 is treated as 8 by the timer.
+    fn reload_sweep_timer(&mut self) {
+        self.sweep_ticks_left = match self.get_sweep_period() {
+            0 => 8,
+            period => period,
+        };
     }
 }
 
@@ -298,6 +281,13 @@ impl ToneAndSweep {
     {
         const SWEEP_WRITE_MASK: u16 = 0x007F;
         self.sweep_register = self.sweep_register.set_data(value, index) & SWEEP_WRITE_MASK;
+
+        // Leaving negate mode after a frequency has been calculated in it disables the channel.
+        if self.sweep_negate_used
+            && matches!(self.get_sweep_behavior(), SweepBehavior::FrequencyIncrease)
+        {
+            self.enabled = false;
+        }
     }
 
     pub fn read_duty_length_envelope<T>(&self, index: u32) -> T
@@ -348,3 +338,65 @@ impl ToneAndSweep {
         self.frequency_control = self.frequency_control.set_bit(TRIGGER_BIT_INDEX, false);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triggered(sweep: u16, frequency: u16) -> ToneAndSweep {
+        let mut tone_and_sweep = ToneAndSweep::default();
+        tone_and_sweep.write_duty_length_envelope(0xF000u16, 0);
+        tone_and_sweep.write_sweep_register(sweep, 0);
+        tone_and_sweep.write_frequency_control(0x8000 | frequency, 0);
+        tone_and_sweep
+    }
+
+    #[test]
+    fn sweep_overflow_on_trigger() {
+        // 0x7FF + (0x7FF >> 1) overflows straight away.
+        assert!(!triggered(0x0001, 0x7FF).enabled);
+        // Without a shift, nothing is calculated.
+        assert!(triggered(0x0010, 0x7FF).enabled);
+    }
+
+    #[test]
+    fn sweep_negate_quirk() {
+        // Period 1, negate, shift 1.
+        let mut tone_and_sweep = triggered(0x0019, 0x400);
+        assert!(tone_and_sweep.enabled);
+
+        // Calculated once on trigger, so leaving negate mode disables the channel right away.
+        tone_and_sweep.write_sweep_register(0x0011u16, 0);
+        assert!(!tone_and_sweep.enabled);
+
+        // Without a shift nothing is calculated until the sweep is clocked.
+        let mut tone_and_sweep = triggered(0x0018, 0x400);
+        tone_and_sweep.write_sweep_register(0x0010u16, 0);
+        assert!(tone_and_sweep.enabled);
+
+        tone_and_sweep.write_sweep_register(0x0018u16, 0);
+        tone_and_sweep.clock_sweep();
+        assert_eq!(tone_and_sweep.get_frequency(), 0x400);
+        tone_and_sweep.write_sweep_register(0x0010u16, 0);
+        assert!(!tone_and_sweep.enabled);
+    }
+
+    #[test]
+    fn sweep_period_zero() {
+        // With a period of 0, the timer still runs as though it were 8, but never sweeps.
+        let mut tone_and_sweep = triggered(0x0001, 0x100);
+        assert_eq!(tone_and_sweep.sweep_ticks_left, 8);
+
+        for _ in 0..8 {
+            tone_and_sweep.clock_sweep();
+        }
+        assert_eq!(tone_and_sweep.sweep_ticks_left, 8);
+        assert_eq!(tone_and_sweep.get_frequency(), 0x100);
+
+        // Period 1, shift 1.
+        let mut tone_and_sweep = triggered(0x0011, 0x100);
+        tone_and_sweep.clock_sweep();
+        assert_eq!(tone_and_sweep.get_frequency(), 0x180);
+        assert!(tone_and_sweep.enabled);
+    }
+}
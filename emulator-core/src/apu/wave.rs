@@ -1,15 +1,7 @@
 use std::ops::RangeInclusive;
 
-use crate::{bit_manipulation::BitManipulation, data_access::DataAccess, CYCLES_PER_SECOND};
-
-// Clocks per second
-const SEQUENCER_CLOCK_FREQUENCY: u64 = 512;
-
-// CPU cycles per clock
-const SEQUENCER_CLOCK_PERIOD: u64 = CYCLES_PER_SECOND / SEQUENCER_CLOCK_FREQUENCY;
-
-const LENGTH_COUNTER_CLOCKS: [bool; 8] = [true, false, true, false, true, false, true, false];
-const VOLUME_ENVELOPE_CLOCKS: [bool; 8] = [false, false, false, false, false, false, false, true];
+use crate::{bit_manipulation::BitManipulation, data_access::DataAccess};
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug)]
 enum WaveRamDimensions {
@@ -17,7 +9,7 @@ enum WaveRamDimensions {
     TwoBanks,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Wave {
     stop_wave_ram_select: u16,
     length_volume: u16,
@@ -32,27 +24,11 @@ pub struct Wave {
     sample_idx: u8,
     wave_sample_timer_ticks_left: u16,
 
-    frame_sequencer_idx: u8,
-    clock: u64,
     enabled: bool,
 }
 
 impl Wave {
     pub fn step(&mut self) {
-        if self.clock % SEQUENCER_CLOCK_PERIOD == 0 {
-            if LENGTH_COUNTER_CLOCKS[usize::from(self.frame_sequencer_idx)] {
-                if self.get_length_flag() {
-                    self.length_counter = self.length_counter.saturating_sub(1);
-
-                    if self.length_counter == 0 {
-                        self.enabled = false;
-                    }
-                }
-            }
-
-            self.frame_sequencer_idx = (self.frame_sequencer_idx + 1) % 8;
-        }
-
         self.wave_sample_timer_ticks_left = self.wave_sample_timer_ticks_left.saturating_sub(1);
         if self.wave_sample_timer_ticks_left == 0 {
             self.sample_idx += 1;
@@ -67,8 +43,17 @@ impl Wave {
 
             self.wave_sample_timer_ticks_left = (2048 - self.get_sample_rate()) * 8;
         }
+    }
+
+    // Clocked at 256 Hz by the frame sequencer.
+    pub fn clock_length(&mut self) {
+        if self.get_length_flag() {
+            self.length_counter = self.length_counter.saturating_sub(1);
 
-        self.clock += 1;
+            if self.length_counter == 0 {
+                self.enabled = false;
+            }
+        }
     }
 
     // 0 to 15 (inclusive) for now
@@ -116,6 +101,15 @@ impl Wave {
             self.length_counter = 256;
         }
     }
+
+    // Clears everything but wave RAM, which keeps its contents while the APU is powered off.
+    pub fn power_off(&mut self) {
+        *self = Self {
+            wave_ram_low: self.wave_ram_low,
+            wave_ram_high: self.wave_ram_high,
+            ..Self::default()
+        };
+    }
 }
 
 impl Wave {
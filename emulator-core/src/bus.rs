@@ -1,16 +1,53 @@
+mod access_watch;
+mod coverage;
+mod debug_output;
+mod dma_log;
+#[cfg(any(test, feature = "flat-memory"))]
+mod flat_memory;
+mod interrupt_history;
+mod io_registers;
+mod io_trace;
+mod mgba_debug;
+mod profiler;
+
+use std::collections::VecDeque;
 use std::fmt::{Debug, UpperHex};
-use std::ops::RangeInclusive;
+use std::ops::{Range, RangeInclusive};
 
 use crate::apu::Apu;
 use crate::cartridge::Cartridge;
-
-use crate::keypad::Keypad;
-use crate::lcd::{Lcd, LcdStateChangeInfo};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::input::{InputQueue, InputTiming};
+use crate::keypad::{Keypad, KeysState};
+use crate::lcd::{FrameCallback, Lcd, LcdStateChangeInfo};
+use crate::le_bytes::{self, LittleEndianBytes};
+use crate::serial::Serial;
 use crate::timer::Timer;
 use crate::BitManipulation;
 use crate::DataAccess;
 
+use self::debug_output::log_debug_output;
+use self::interrupt_history::InterruptHistory;
+use self::mgba_debug::MgbaDebug;
+
+pub use access_watch::{AccessHit, AccessKind, AccessWatch};
+pub use coverage::{CoverageKind, CoverageRecorder, CoverageSummary};
+pub use debug_output::{DebugOutputCallback, DebugOutputLevel};
+pub use dma_log::{DmaLog, DmaTransfer};
+#[cfg(any(test, feature = "flat-memory"))]
+pub use flat_memory::{FlatMemory, MemoryAccess};
+pub use interrupt_history::InterruptEvent;
+pub use io_registers::{IoRegisterField, IoRegisterInfo};
+pub use io_trace::{IoTrace, IoTraceEntry};
+pub use profiler::{BusProfiler, MemoryRegion, RegionAccessCounts};
+
+#[cfg(feature = "bios")]
 const BIOS: &[u8] = include_bytes!("../gba_bios.bin");
+// Without a BIOS image the region reads as zeros, which decode as no-ops.
+#[cfg(not(feature = "bios"))]
+const BIOS: &[u8] = &[0; 0x4000];
 
 #[derive(Clone, Copy, Debug)]
 pub enum BusAccessType {
@@ -18,7 +55,7 @@ pub enum BusAccessType {
     NonSequential,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 enum BiosReadBehavior {
     TrueValue,
     PrefetchValue,
@@ -29,15 +66,38 @@ pub(super) struct TimerStepResult {
     pub overflows: [bool; 4],
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerState {
+    #[default]
+    Running,
+    Halted,  // CPU is paused until any enabled interrupt is requested
+    Stopped, // CPU and most hardware are paused until a keypad, serial or game pak interrupt
+}
+
+// Older save states were made before IMC was emulated, when EWRAM always ran at its reset
+// timing.
+fn internal_memory_control_backwards_compat() -> u32 {
+    Bus::INTERNAL_MEMORY_CONTROL_RESET_VALUE
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Bus {
+    #[serde_as(as = "Box<[_; 0x8000]>")]
     chip_wram: Box<[u8; 0x8000]>,
+    #[serde_as(as = "Box<[_; 0x40000]>")]
     board_wram: Box<[u8; 0x40000]>,
     cycle_count: u64,
     interrupt_master_enable: u16,
     interrupt_enable: u16,
     interrupt_request: [u16; Self::IRQ_SYNC_BUFFER], // active IRQ is at end
+    interrupt_enable_sync: [u16; Self::IRQ_SYNC_BUFFER], // active IE is at end
+    interrupt_master_enable_sync: [bool; Self::IRQ_SYNC_BUFFER], // active IME is at end
+    acknowledged_interrupts: u16,                    // IF bits cleared since last polled
     waitstate_control: u32,
+    // Missing from older save states.
+    #[serde(default = "internal_memory_control_backwards_compat")]
+    internal_memory_control: u32,
     dma_infos: [DmaInfo; 4],
     pub timers: [Timer; 4],
     pub open_bus_data: u32,
@@ -45,20 +105,229 @@ pub struct Bus {
     open_bus_bios_data: u32,      // most recently fetched BIOS opcode
     bios_read_behavior: BiosReadBehavior,
     prefetch_sequential: bool, // whether the next pre-fetch will use sequential access
+    frame_completed: bool,     // set on vblank, cleared when polled
+    power_state: PowerState,
     pub lcd: Lcd,
     pub apu: Apu,
     pub keypad: Keypad,
+    // Missing from older save states.
+    #[serde(default)]
+    pub serial: Serial,
     pub cartridge: Cartridge,
+    // Missing from older save states.
+    #[serde(default)]
+    mgba_debug: MgbaDebug,
+    // Profiling state, so not part of save states.
+    #[serde(skip)]
+    pub(crate) perf_counters: PerfCounters,
+    #[serde(skip)]
+    profiler: Option<BusProfiler>,
+    #[serde(skip)]
+    coverage: Option<CoverageRecorder>,
+    #[serde(skip)]
+    access_watch: Option<AccessWatch>,
+    #[serde(skip)]
+    interrupt_history: InterruptHistory,
+    #[serde(skip)]
+    dma_log: Option<DmaLog>,
+    #[serde(skip)]
+    io_trace: Option<IoTrace>,
+    #[serde(skip)]
+    pub(crate) loop_accesses: Option<LoopAccesses>, // see `Cpu::check_idle_loop`
+    #[serde(skip)]
+    fetching_opcode: bool, // so that coverage can tell opcode fetches from data reads
+    // Set through `CpuConfig`, so not part of save states.
+    #[serde(skip)]
+    pub(crate) fast_ewram: bool,
+    #[serde(skip)]
+    ewram_lockup: Option<u32>, // see `take_ewram_lockup`
+    #[serde(skip)]
+    ewram_lockup_reported: bool,
+    // Frontend state, so not part of save states.
+    #[serde(skip)]
+    debug_output_callback: Option<DebugOutputCallback>,
+    #[serde(skip)]
+    frame_callback: Option<FrameCallback>,
+    // Pending input belongs to the timeline it was queued on, so loading a state drops it.
+    #[serde(skip)]
+    input_queue: InputQueue,
+    // Replaces the whole memory map when set, see `Cpu::with_flat_memory`.
+    #[cfg(any(test, feature = "flat-memory"))]
+    #[serde(skip)]
+    pub(crate) flat_memory: Option<FlatMemory>,
 }
 
 impl Bus {
     pub fn cycle_count(&self) -> u64 {
         self.cycle_count
     }
+
+    pub fn profiler(&self) -> Option<&BusProfiler> {
+        self.profiler.as_ref()
+    }
+
+    // Profiling is off by default. Replacing the profiler, rather than just turning it on, also
+    // clears its counts.
+    pub fn set_profiler(&mut self, profiler: Option<BusProfiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn coverage(&self) -> Option<&CoverageRecorder> {
+        self.coverage.as_ref()
+    }
+
+    // Coverage recording is off by default, and like the profiler is cleared by replacing it.
+    pub fn set_coverage(&mut self, coverage: Option<CoverageRecorder>) {
+        self.coverage = coverage;
+    }
+
+    pub fn access_watch(&self) -> Option<&AccessWatch> {
+        self.access_watch.as_ref()
+    }
+
+    pub fn access_watch_mut(&mut self) -> Option<&mut AccessWatch> {
+        self.access_watch.as_mut()
+    }
+
+    // Sets the keypad to `keys` once `timing` is reached, rather than whenever this happens to be
+    // called. Frontends should queue input for `lcd.frame_count() + 1` to change it at the next
+    // frame boundary.
+    pub fn queue_input(&mut self, timing: InputTiming, keys: KeysState) {
+        self.input_queue.push(timing, keys);
+    }
+
+    pub fn queued_input_len(&self) -> usize {
+        self.input_queue.len()
+    }
+
+    pub fn clear_input_queue(&mut self) {
+        self.input_queue.clear();
+    }
+
+    // Watching is off by default, since every access has to be checked against the watch.
+    pub fn set_access_watch(&mut self, access_watch: Option<AccessWatch>) {
+        self.access_watch = access_watch;
+    }
+
+    pub fn dma_log(&self) -> Option<&DmaLog> {
+        self.dma_log.as_ref()
+    }
+
+    pub fn dma_log_mut(&mut self) -> Option<&mut DmaLog> {
+        self.dma_log.as_mut()
+    }
+
+    // DMA logging is off by default, and like the profiler is cleared by replacing it.
+    pub fn set_dma_log(&mut self, dma_log: Option<DmaLog>) {
+        self.dma_log = dma_log;
+    }
+
+    pub fn io_trace(&self) -> Option<&IoTrace> {
+        self.io_trace.as_ref()
+    }
+
+    pub fn io_trace_mut(&mut self) -> Option<&mut IoTrace> {
+        self.io_trace.as_mut()
+    }
+
+    // IO tracing is off by default, since every access has to be checked against the traced
+    // registers.
+    pub fn set_io_trace(&mut self, io_trace: Option<IoTrace>) {
+        self.io_trace = io_trace;
+    }
+
+    // The last `INTERRUPT_HISTORY_LENGTH` IRQs taken, oldest first.
+    pub fn interrupt_history(&self) -> &VecDeque<InterruptEvent> {
+        self.interrupt_history.events()
+    }
+
+    // Called as the CPU takes an IRQ, before the instruction at `pc`.
+    pub(super) fn record_interrupt(&mut self, pc: u32) {
+        self.interrupt_history.record(InterruptEvent {
+            cycle: self.cycle_count,
+            interrupt_enable: *self.interrupt_enable_sync.last().unwrap(),
+            interrupt_request: *self.interrupt_request.last().unwrap(),
+            interrupt_master_enable: *self.interrupt_master_enable_sync.last().unwrap(),
+            pc,
+        });
+    }
+
+    pub(super) fn record_executed(&mut self, address: u32, width: u32) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(CoverageKind::Executed, address, width);
+        }
+    }
+
+    pub(super) fn frame_completed(&self) -> bool {
+        self.frame_completed
+    }
+
+    pub(super) fn poll_frame_completed(&mut self) -> bool {
+        let result = self.frame_completed;
+        self.frame_completed = false;
+        result
+    }
+
+    pub fn power_state(&self) -> PowerState {
+        self.power_state
+    }
+
+    pub fn current_waitstates(&self) -> Waitstates {
+        let rom_waitstates = |get_wait_state: fn(&Self, BusAccessType) -> u8| RomWaitstates {
+            first_access: get_wait_state(self, BusAccessType::NonSequential),
+            second_access: get_wait_state(self, BusAccessType::Sequential),
+        };
+
+        Waitstates {
+            sram: self.get_sram_wait_state(),
+            rom: [
+                rom_waitstates(Self::get_rom_0_wait_state),
+                rom_waitstates(Self::get_rom_1_wait_state),
+                rom_waitstates(Self::get_rom_2_wait_state),
+            ],
+            phi_terminal_output: self.get_phi_terminal_output(),
+            prefetch_buffer_enable: self.get_prefetch_buffer_enable(),
+        }
+    }
+
+    pub fn dma_stats(&self) -> [DmaStats; 4] {
+        self.dma_infos.map(|dma| dma.stats)
+    }
+
+    pub fn get_dma_debug(&self) -> [DmaDebugInfo; 4] {
+        self.dma_infos.map(|dma| DmaDebugInfo {
+            enabled: dma.get_dma_enable(),
+            source_addr: dma.source_addr_internal,
+            dest_addr: dma.dest_addr_internal,
+            word_count: dma.word_count_internal,
+            start_timing: dma.get_dma_start_timing(),
+            repeat: dma.get_dma_repeat(),
+            word_transfer: matches!(dma.get_dma_transfer_type(), DmaTransferType::Bit32),
+            stats: dma.stats,
+        })
+    }
+
+    // Interrupts which are both enabled and requested, as currently seen by the CPU. Writes to
+    // IE, IF and IME all pass through the IRQ synchronizer, so they're only reflected here
+    // `IRQ_SYNC_BUFFER - 1` cycles later.
+    pub fn pending_interrupts(&self) -> u16 {
+        let interrupt_enable = *self.interrupt_enable_sync.last().unwrap();
+        let interrupt_request = *self.interrupt_request.last().unwrap();
+
+        interrupt_enable & interrupt_request
+    }
+
+    // Returns all IF bits which have been acknowledged (written as 1) since this was last called.
+    pub fn poll_acknowledged_interrupts(&mut self) -> u16 {
+        let result = self.acknowledged_interrupts;
+        self.acknowledged_interrupts = 0;
+        result
+    }
 }
 
 impl Bus {
     pub const IRQ_SYNC_BUFFER: usize = 5; // sync buffer of 5 means IRQ is delayed by 4 cycles.
+    pub const INTERRUPT_HISTORY_LENGTH: usize = InterruptHistory::LENGTH;
 
     pub fn new(cartridge: Cartridge) -> Self {
         Self {
@@ -68,7 +337,11 @@ impl Bus {
             interrupt_master_enable: 0,
             interrupt_enable: 0,
             interrupt_request: [0; Self::IRQ_SYNC_BUFFER],
+            interrupt_enable_sync: [0; Self::IRQ_SYNC_BUFFER],
+            interrupt_master_enable_sync: [false; Self::IRQ_SYNC_BUFFER],
+            acknowledged_interrupts: 0,
             waitstate_control: 0,
+            internal_memory_control: Self::INTERNAL_MEMORY_CONTROL_RESET_VALUE,
             dma_infos: [
                 DmaInfo::dma_0(),
                 DmaInfo::dma_1(),
@@ -86,14 +359,89 @@ impl Bus {
             open_bus_iwram_data: 0,
             bios_read_behavior: BiosReadBehavior::TrueValue,
             prefetch_sequential: false,
+            frame_completed: false,
+            power_state: PowerState::Running,
             lcd: Lcd::default(),
             apu: Apu::default(),
             keypad: Keypad::default(),
+            serial: Serial::default(),
             cartridge,
+            mgba_debug: MgbaDebug::default(),
+            perf_counters: PerfCounters::default(),
+            profiler: None,
+            coverage: None,
+            access_watch: None,
+            interrupt_history: InterruptHistory::default(),
+            dma_log: None,
+            io_trace: None,
+            loop_accesses: None,
+            fetching_opcode: false,
+            fast_ewram: false,
+            ewram_lockup: None,
+            ewram_lockup_reported: false,
+            debug_output_callback: None,
+            frame_callback: None,
+            input_queue: InputQueue::default(),
+            #[cfg(any(test, feature = "flat-memory"))]
+            flat_memory: None,
         }
     }
 }
 
+impl Bus {
+    pub(super) fn debug_output_callback(&self) -> Option<DebugOutputCallback> {
+        self.debug_output_callback.clone()
+    }
+
+    pub(super) fn set_debug_output_callback(&mut self, callback: Option<DebugOutputCallback>) {
+        self.debug_output_callback = callback;
+    }
+
+    pub(super) fn frame_callback(&self) -> Option<FrameCallback> {
+        self.frame_callback.clone()
+    }
+
+    pub(super) fn set_frame_callback(&mut self, callback: Option<FrameCallback>) {
+        self.frame_callback = callback;
+    }
+
+    // Returns whether the ROM uses AGBPrint, in which case its flush SWI is handled here instead
+    // of by the BIOS.
+    pub(super) fn flush_agb_print(&mut self) -> bool {
+        match self.cartridge.flush_agb_print() {
+            Some(text) => {
+                if !text.is_empty() {
+                    self.emit_debug_output(DebugOutputLevel::Info, text.trim_end_matches('\n'));
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn emit_debug_output(&self, level: DebugOutputLevel, message: &str) {
+        log_debug_output(level, message);
+
+        if let Some(callback) = &self.debug_output_callback {
+            callback(level, message);
+        }
+    }
+}
+
+impl Bus {
+    // Puts IO and open bus state into the condition the BIOS leaves it in after booting.
+    pub(super) fn skip_bios(&mut self) {
+        // The last BIOS opcode fetched before jumping to the cartridge.
+        const POST_BOOT_BIOS_OPCODE: u32 = 0xE129F000;
+        const POST_BOOT_SOUND_BIAS: u32 = 0x0000_0200;
+
+        self.open_bus_bios_data = POST_BOOT_BIOS_OPCODE;
+        self.bios_read_behavior = BiosReadBehavior::PrefetchValue;
+        self.apu.write_sound_pwm_control(POST_BOOT_SOUND_BIAS, 0);
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 enum DmaAddrControl {
     Increment,
@@ -108,15 +456,16 @@ enum DmaTransferType {
     Bit32,
 }
 
-#[derive(Clone, Copy, Debug)]
-enum DmaStartTiming {
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DmaStartTiming {
+    #[default]
     Immediately,
     VBlank,
     HBlank,
     Special,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 struct DmaInfo {
     source_addr: u32,
     source_addr_internal: u32,
@@ -138,6 +487,77 @@ struct DmaInfo {
     dma_requested: bool,
 
     read_latch: u32, // DMA open bus returns last read value, not standard open bus value
+
+    stats: DmaStats,
+}
+
+// Snapshot of a DMA channel's current (internal) transfer state, for debuggers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DmaDebugInfo {
+    pub enabled: bool,
+    pub source_addr: u32,
+    pub dest_addr: u32,
+    pub word_count: u16,
+    pub start_timing: DmaStartTiming,
+    pub repeat: bool,
+    pub word_transfer: bool, // 32-bit units if set, 16-bit otherwise
+    pub stats: DmaStats,
+}
+
+// Game pak bus timings as currently configured through WAITCNT, for debuggers. Waitstates are
+// the number of cycles each access takes on top of the first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Waitstates {
+    pub sram: u8,
+    pub rom: [RomWaitstates; 3], // WS0, WS1 and WS2
+    pub phi_terminal_output: PhiTerminalOutput,
+    pub prefetch_buffer_enable: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RomWaitstates {
+    pub first_access: u8,  // non-sequential
+    pub second_access: u8, // sequential
+}
+
+// Clock driven onto the PHI pin of the cartridge slot. Nothing we emulate makes use of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PhiTerminalOutput {
+    #[default]
+    Disabled,
+    Mhz4_19,
+    Mhz8_38,
+    Mhz16_78,
+}
+
+// Counts of what the system has been doing since it was reset, or since the counters were last
+// cleared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PerfCounters {
+    pub arm_instructions: u64,
+    pub thumb_instructions: u64,
+    pub sequential_accesses: u64, // includes opcode fetches and DMA
+    pub non_sequential_accesses: u64,
+    pub internal_cycles: u64, // cycles the CPU spends without accessing the bus
+    pub dma_units_transferred: u64, // halfwords or words, depending on the transfer type
+    pub irqs_taken: u64,
+    pub idle_loop_cycles_skipped: u64,
+}
+
+// Data accesses made by a loop while it's checked for being idle.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LoopAccesses {
+    pub(crate) reads: Vec<(u32, u32)>, // address and width
+    pub(crate) written: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DmaStats {
+    pub transfers_completed: u64,
+    pub units_transferred: u64, // halfwords or words, depending on the transfer type
+    // Includes the startup cycles, as well as any higher priority DMA that ran in the middle of
+    // this one.
+    pub cycles_consumed: u64,
 }
 
 impl DmaInfo {
@@ -159,6 +579,8 @@ impl DmaInfo {
             dma_requested: false,
 
             read_latch: Default::default(),
+
+            stats: Default::default(),
         }
     }
 
@@ -180,6 +602,8 @@ impl DmaInfo {
             dma_requested: false,
 
             read_latch: Default::default(),
+
+            stats: Default::default(),
         }
     }
 
@@ -201,6 +625,8 @@ impl DmaInfo {
             dma_requested: false,
 
             read_latch: Default::default(),
+
+            stats: Default::default(),
         }
     }
 
@@ -222,6 +648,8 @@ impl DmaInfo {
             dma_requested: false,
 
             read_latch: Default::default(),
+
+            stats: Default::default(),
         }
     }
 }
@@ -318,7 +746,8 @@ impl DmaInfo {
             0 => DmaAddrControl::Increment,
             1 => DmaAddrControl::Decrement,
             2 => DmaAddrControl::Fixed,
-            3 => unreachable!("increment reload illegal for source control"),
+            // Prohibited, but behaves like a plain increment.
+            3 => DmaAddrControl::Increment,
             _ => unreachable!(),
         }
     }
@@ -382,19 +811,43 @@ impl DmaInfo {
 }
 
 impl Bus {
-    pub(super) fn step(&mut self) {
-        // Assume that the IRQ synchronizer is clocked before any MMIO-attached devices that are
-        // also clocked have a chance to update and/or update their IRQ line.
+    fn step_irq_synchronizer(&mut self) {
         let new_irq_in = *self.interrupt_request.first().unwrap();
         self.interrupt_request.rotate_right(1);
         *self.interrupt_request.first_mut().unwrap() = new_irq_in;
 
+        self.interrupt_enable_sync.rotate_right(1);
+        *self.interrupt_enable_sync.first_mut().unwrap() = self.interrupt_enable;
+
+        self.interrupt_master_enable_sync.rotate_right(1);
+        *self.interrupt_master_enable_sync.first_mut().unwrap() = self.get_interrupts_enabled();
+    }
+
+    // An internal (I) cycle, where the CPU is busy without accessing memory.
+    pub(super) fn step_internal(&mut self) {
+        self.perf_counters.internal_cycles += 1;
+        self.step();
+    }
+
+    pub(super) fn step(&mut self) {
+        // Assume that the IRQ synchronizer is clocked before any MMIO-attached devices that are
+        // also clocked have a chance to update and/or update their IRQ line.
+        self.step_irq_synchronizer();
+
         if self.keypad.poll_pending_interrupts() {
             self.request_interrupt(InterruptType::Keypad);
         }
 
+        if self.cartridge.poll_removal_interrupt() {
+            self.request_interrupt(InterruptType::Gamepak);
+        }
+
         let timer_result = self.step_timers();
 
+        if self.serial.step() {
+            self.request_interrupt(InterruptType::Serial);
+        }
+
         self.apu.step(timer_result);
 
         if self.cycle_count % 4 == 0 {
@@ -402,6 +855,15 @@ impl Bus {
 
             self.inform_dma_state_change(state_changes);
 
+            if state_changes.vblank_entered {
+                self.frame_completed = true;
+
+                // The LCD has just swapped buffers, so this is the finished frame.
+                if let Some(callback) = &self.frame_callback {
+                    callback(self.lcd.get_buffer());
+                }
+            }
+
             if state_changes.vblank_entered && self.lcd.get_vblank_irq_enable() {
                 self.request_interrupt(InterruptType::VBlank);
             }
@@ -415,10 +877,58 @@ impl Bus {
             }
         }
 
+        // After the LCD, so input queued for a frame applies on the cycle VBlank starts.
+        if let Some(keys) = self
+            .input_queue
+            .poll(self.lcd.frame_count(), self.cycle_count)
+        {
+            self.keypad.set_state(keys);
+        }
+
+        // DMA reads are data reads, even when the DMA runs in the middle of an opcode fetch.
+        let fetching_opcode = std::mem::replace(&mut self.fetching_opcode, false);
         self.step_dma();
+        self.fetching_opcode = fetching_opcode;
 
         self.cycle_count += 1;
     }
+
+    // Advances a single cycle while the CPU is halted or stopped, waking it once a suitable
+    // interrupt has been requested. Wake-up only depends on IE and IF, not IME.
+    pub(super) fn step_low_power(&mut self) {
+        const STOP_WAKE_INTERRUPT_MASK: u16 = (1 << Bus::KEYPAD_INTERRUPT_BIT_INDEX)
+            | (1 << Bus::SERIAL_INTERRUPT_BIT_INDEX)
+            | (1 << Bus::GAMEPAK_INTERRUPT_BIT_INDEX);
+
+        let wake_mask = match self.power_state {
+            PowerState::Running => return,
+            PowerState::Halted => {
+                self.step();
+                0xFFFF
+            }
+            PowerState::Stopped => {
+                // Only the IRQ synchronizer, keypad and cartridge slot keep running, the LCD, APU,
+                // timers and DMA are all frozen.
+                self.step_irq_synchronizer();
+
+                if self.keypad.poll_pending_interrupts() {
+                    self.request_interrupt(InterruptType::Keypad);
+                }
+
+                if self.cartridge.poll_removal_interrupt() {
+                    self.request_interrupt(InterruptType::Gamepak);
+                }
+
+                self.cycle_count += 1;
+
+                STOP_WAKE_INTERRUPT_MASK
+            }
+        };
+
+        if (self.pending_interrupts() & wake_mask) != 0 {
+            self.power_state = PowerState::Running;
+        }
+    }
 }
 
 impl Bus {
@@ -437,6 +947,78 @@ impl Bus {
         wait_state_0 | wait_state_1 | wait_state_2
     }
 
+    fn is_sram(address: u32) -> bool {
+        (Self::GAME_PAK_SRAM_BASE..=Self::GAME_PAK_SRAM_END).contains(&address)
+    }
+
+    // Whether the next opcode fetch will be a sequential access. Only the timing audit needs to
+    // know.
+    #[cfg(test)]
+    pub(crate) fn prefetch_sequential(&self) -> bool {
+        self.prefetch_sequential
+    }
+
+    fn count_access(&mut self, access_type: BusAccessType) {
+        match access_type {
+            BusAccessType::Sequential => self.perf_counters.sequential_accesses += 1,
+            BusAccessType::NonSequential => self.perf_counters.non_sequential_accesses += 1,
+        }
+    }
+
+    fn count_read(&mut self, address: u32, width: u32, access_type: BusAccessType) {
+        self.count_access(access_type);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record_read(address);
+        }
+
+        if let Some(coverage) = &mut self.coverage {
+            if !self.fetching_opcode {
+                coverage.record(CoverageKind::Read, address, width);
+            }
+        }
+
+        if let Some(access_watch) = &mut self.access_watch {
+            if !self.fetching_opcode {
+                access_watch.record(AccessKind::Read, address, width);
+            }
+        }
+
+        if let Some(loop_accesses) = &mut self.loop_accesses {
+            if !self.fetching_opcode {
+                loop_accesses.reads.push((address, width));
+            }
+        }
+    }
+
+    fn count_write(&mut self, address: u32, width: u32, access_type: BusAccessType) {
+        self.count_access(access_type);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record_write(address);
+        }
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(CoverageKind::Written, address, width);
+        }
+
+        if let Some(access_watch) = &mut self.access_watch {
+            access_watch.record(AccessKind::Write, address, width);
+        }
+
+        if let Some(loop_accesses) = &mut self.loop_accesses {
+            loop_accesses.written = true;
+        }
+    }
+
+    fn trace_io(&mut self, kind: AccessKind, address: u32, width: u32, value: u32) {
+        if let Some(io_trace) = &mut self.io_trace {
+            // Misaligned accesses are forced into alignment by the bus.
+            let address = address & !(width - 1);
+            if (Self::IO_REGISTER_BASE..=Self::IO_REGION_END).contains(&address) {
+                io_trace.record(kind, address, width, value, self.cycle_count);
+            }
+        }
+    }
+
     pub(super) fn fetch_arm_opcode(&mut self, address: u32) -> u32 {
         if Self::is_bios(address) {
             self.bios_read_behavior = BiosReadBehavior::TrueValue;
@@ -449,7 +1031,9 @@ impl Bus {
         } else {
             BusAccessType::NonSequential
         };
+        self.fetching_opcode = true;
         let result = self.read_word_address(address, access_type);
+        self.fetching_opcode = false;
 
         self.prefetch_sequential = true;
         result
@@ -467,7 +1051,9 @@ impl Bus {
         } else {
             BusAccessType::NonSequential
         };
+        self.fetching_opcode = true;
         let result = self.read_halfword_address(address, access_type);
+        self.fetching_opcode = false;
 
         self.prefetch_sequential = true;
         result
@@ -726,12 +1312,18 @@ impl Bus {
     const TIMER_3_CONTROL_BASE: u32 = 0x0400010E;
     const TIMER_3_CONTROL_END: u32 = Self::TIMER_3_CONTROL_BASE + 1;
 
-    const SERIAL_BASE: u32 = 0x04000120;
-    const SERIAL_END: u32 = 0x0400015B;
+    const SIO_DATA_BASE: u32 = 0x04000120;
+    const SIO_DATA_END: u32 = Self::SIO_DATA_BASE + 7;
 
     const SIO_CONTROL_BASE: u32 = 0x04000128;
     const SIO_CONTROL_END: u32 = Self::SIO_CONTROL_BASE + 1;
 
+    const SIO_SEND_DATA_BASE: u32 = 0x0400012A;
+    const SIO_SEND_DATA_END: u32 = Self::SIO_SEND_DATA_BASE + 1;
+
+    const SIO_MODE_SELECT_BASE: u32 = 0x04000134;
+    const SIO_MODE_SELECT_END: u32 = Self::SIO_MODE_SELECT_BASE + 1;
+
     const KEY_STATUS_BASE: u32 = 0x04000130;
     const KEY_STATUS_END: u32 = Self::KEY_STATUS_BASE + 1;
 
@@ -741,6 +1333,16 @@ impl Bus {
     const SIO_JOY_RECV_BASE: u32 = 0x04000150;
     const SIO_JOY_RECV_END: u32 = Self::SIO_JOY_RECV_BASE + 3;
 
+    // The rest of the serial registers, between the ones above, which are only stubbed.
+    const SERIAL_STUB_0_BASE: u32 = 0x0400012C;
+    const SERIAL_STUB_0_END: u32 = 0x0400012F;
+
+    const SERIAL_STUB_1_BASE: u32 = 0x04000136;
+    const SERIAL_STUB_1_END: u32 = 0x0400014F;
+
+    const SERIAL_STUB_2_BASE: u32 = 0x04000154;
+    const SERIAL_STUB_2_END: u32 = 0x0400015B;
+
     const INTERRUPT_ENABLE_BASE: u32 = 0x04000200;
     const INTERRUPT_ENABLE_END: u32 = Self::INTERRUPT_ENABLE_BASE + 1;
 
@@ -756,6 +1358,23 @@ impl Bus {
     const POSTFLG_ADDR: u32 = 0x04000300;
     const HALTCNT_ADDR: u32 = 0x04000301;
 
+    // Undocumented, but some homebrew uses it to speed up EWRAM.
+    const INTERNAL_MEMORY_CONTROL_BASE: u32 = 0x04000800;
+    const INTERNAL_MEMORY_CONTROL_RESET_VALUE: u32 = 0x0D000020;
+    const EWRAM_LOCKUP_WAIT_CONTROL: u32 = 15;
+    // Everything past `IO_REGISTER_END` is unmapped, other than IMC's mirrors.
+    const IO_REGION_END: u32 = 0x04FFFFFF;
+
+    // mGBA debug output registers, see `MgbaDebug`.
+    const MGBA_DEBUG_STRING_BASE: u32 = 0x04FFF600;
+    const MGBA_DEBUG_STRING_END: u32 = Self::MGBA_DEBUG_STRING_BASE + 0xFF;
+
+    const MGBA_DEBUG_FLAGS_BASE: u32 = 0x04FFF700;
+    const MGBA_DEBUG_FLAGS_END: u32 = Self::MGBA_DEBUG_FLAGS_BASE + 1;
+
+    const MGBA_DEBUG_ENABLE_BASE: u32 = 0x04FFF780;
+    const MGBA_DEBUG_ENABLE_END: u32 = Self::MGBA_DEBUG_ENABLE_BASE + 1;
+
     const PALETTE_RAM_BASE: u32 = 0x05000000;
     const PALETTE_RAM_END: u32 = 0x05FFFFFF;
     const PALETTER_RAM_SIZE: u32 = 0x400;
@@ -797,7 +1416,15 @@ impl Bus {
     // Note: we assume that all reads use values from the beginning of the cycle (before any other
     // clocked things are ticked), but writes happen at the end of the cycle (after all clocked
     // things are ticked).
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub(super) fn read_byte_address(&mut self, address: u32, access_type: BusAccessType) -> u8 {
+        self.count_read(address, 1, access_type);
+
+        #[cfg(any(test, feature = "flat-memory"))]
+        if let Some(flat_memory) = &self.flat_memory {
+            return flat_memory.read(address, 1) as u8;
+        }
+
         let result = match address {
             Self::BIOS_BASE..=Self::BIOS_END => {
                 let result = self.read_byte_address_debug(address);
@@ -823,9 +1450,7 @@ impl Bus {
             }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
                 let result = self.read_byte_address_debug(address);
-                self.step();
-                self.step();
-                self.step();
+                self.step_ewram_access(address, 1);
                 result
             }
             Self::IO_REGISTER_BASE..=Self::IO_REGISTER_END => {
@@ -833,6 +1458,11 @@ impl Bus {
                 self.step();
                 result
             }
+            _ if Self::is_internal_memory_control(address) => {
+                let result = self.read_byte_address_debug(address);
+                self.step();
+                result
+            }
             Self::PALETTE_RAM_BASE..=Self::PALETTE_RAM_END => {
                 let result = self.read_byte_address_debug(address);
                 self.step();
@@ -888,12 +1518,19 @@ impl Bus {
             }
         };
 
+        self.trace_io(AccessKind::Read, address, 1, u32::from(result));
         self.prefetch_sequential = false;
         result
     }
 
     pub fn read_byte_address_debug(&self, address: u32) -> u8 {
         match address {
+            Self::BOARD_WRAM_BASE..=Self::CHIP_WRAM_END if self.wram_disabled() => {
+                self.open_bus_data.get_data(address & 0b11)
+            }
+            Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END if !self.ewram_enabled() => {
+                self.read_byte_address_debug(Self::ewram_mirror_address(address))
+            }
             Self::BIOS_BASE..=Self::BIOS_END => match self.bios_read_behavior {
                 BiosReadBehavior::PrefetchValue => self.open_bus_bios_data.get_data(address & 0b11),
                 BiosReadBehavior::TrueValue => {
@@ -1050,9 +1687,18 @@ impl Bus {
                 self.timers[3].read_timer_counter_reload(address & 0b1)
             }
 
+            Self::SIO_DATA_BASE..=Self::SIO_DATA_END => self.serial.read_data(
+                ((address - Self::SIO_DATA_BASE) / 2) as usize,
+                address & 0b1,
+            ),
             Self::SIO_CONTROL_BASE..=Self::SIO_CONTROL_END => {
-                log::debug!("read from stubbed SIOCNT");
-                0
+                self.serial.read_control(address & 0b1)
+            }
+            Self::SIO_SEND_DATA_BASE..=Self::SIO_SEND_DATA_END => {
+                self.serial.read_send_data(address & 0b1)
+            }
+            Self::SIO_MODE_SELECT_BASE..=Self::SIO_MODE_SELECT_END => {
+                self.serial.read_mode_select(address & 0b1)
             }
 
             Self::KEY_STATUS_BASE..=Self::KEY_STATUS_END => {
@@ -1115,19 +1761,70 @@ impl Bus {
                 let offset = (address - Self::GAME_PAK_SRAM_BASE) % Self::GAME_PAK_SRAM_SIZE;
                 self.cartridge.read_sram_byte(offset)
             }
-            Self::SERIAL_BASE..=Self::SERIAL_END => {
+            Self::SERIAL_STUB_0_BASE..=Self::SERIAL_STUB_0_END
+            | Self::SERIAL_STUB_1_BASE..=Self::SERIAL_STUB_1_END
+            | Self::SERIAL_STUB_2_BASE..=Self::SERIAL_STUB_2_END => {
                 log::debug!("read from stubbed serial {:08X}", address);
                 0
             }
+            Self::MGBA_DEBUG_STRING_BASE..=Self::MGBA_DEBUG_STRING_END
+                if self.mgba_debug.is_enabled() =>
+            {
+                self.mgba_debug
+                    .read_string(address - Self::MGBA_DEBUG_STRING_BASE)
+            }
+            Self::MGBA_DEBUG_ENABLE_BASE..=Self::MGBA_DEBUG_ENABLE_END
+                if self.mgba_debug.is_enabled() =>
+            {
+                self.mgba_debug.read_enable(address & 0b1)
+            }
+            _ if Self::is_internal_memory_control(address) => {
+                self.internal_memory_control.get_data(address & 0b11)
+            }
+            _ if Self::is_zero_io_address(address) => 0,
             _ => self.open_bus_data.get_data(address & 0b11),
         }
     }
 
+    // Unused halves of otherwise readable IO registers, as well as the write-only DMA word counts,
+    // read back as zero rather than open bus. All other unmapped IO returns open bus.
+    fn is_zero_io_address(address: u32) -> bool {
+        let unused_halves = [
+            Self::CHANNEL_1_FREQUENCY_CONTROL_END + 1,
+            Self::CHANNEL_2_DUTY_LENGTH_ENVELOPE_END + 1,
+            Self::CHANNEL_2_FREQUENCY_CONTROL_END + 1,
+            Self::CHANNEL_3_FREQUENCY_CONTROL_END + 1,
+            Self::CHANNEL_4_LENGTH_ENVELOPE_END + 1,
+            Self::CHANNEL_4_FREQUENCY_CONTROL_END + 1,
+            Self::INTERRUPT_MASTER_ENABLE_END + 1,
+        ];
+
+        let word_counts = [
+            Self::DMA_0_WORD_COUNT_BASE,
+            Self::DMA_1_WORD_COUNT_BASE,
+            Self::DMA_2_WORD_COUNT_BASE,
+            Self::DMA_3_WORD_COUNT_BASE,
+        ];
+
+        unused_halves
+            .into_iter()
+            .chain(word_counts)
+            .any(|base| (base..=base + 1).contains(&address))
+    }
+
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub(super) fn read_halfword_address(
         &mut self,
         address: u32,
         access_type: BusAccessType,
     ) -> u16 {
+        self.count_read(address, 2, access_type);
+
+        #[cfg(any(test, feature = "flat-memory"))]
+        if let Some(flat_memory) = &self.flat_memory {
+            return flat_memory.read(address, 2) as u16;
+        }
+
         let result = match address {
             Self::BIOS_BASE..=Self::BIOS_END => {
                 let result = self.read_halfword_address_debug(address);
@@ -1146,9 +1843,7 @@ impl Bus {
                 let result = self.read_halfword_address_debug(address);
 
                 self.open_bus_data = (u32::from(result) << u16::BITS) | u32::from(result);
-                self.step();
-                self.step();
-                self.step();
+                self.step_ewram_access(address, 2);
                 result
             }
             Self::CHIP_WRAM_BASE..=Self::CHIP_WRAM_END => {
@@ -1168,6 +1863,11 @@ impl Bus {
                 self.step();
                 result
             }
+            _ if Self::is_internal_memory_control(address) => {
+                let result = self.read_halfword_address_debug(address);
+                self.step();
+                result
+            }
             Self::PALETTE_RAM_BASE..=Self::PALETTE_RAM_END => {
                 let result = self.read_halfword_address_debug(address);
 
@@ -1184,6 +1884,10 @@ impl Bus {
             }
             Self::OAM_BASE..=Self::OAM_END => {
                 let result = self.read_halfword_address_debug(address);
+
+                // OAM is accessed a full word at a time, so the neighbouring halfword is left on
+                // the bus as well.
+                self.open_bus_data = self.read_word_address_debug(address);
                 self.step();
                 result
             }
@@ -1247,6 +1951,7 @@ impl Bus {
             }
         };
 
+        self.trace_io(AccessKind::Read, address, 2, u32::from(result));
         self.prefetch_sequential = false;
         result
     }
@@ -1266,20 +1971,20 @@ impl Bus {
                     word_read.get_data((address & 0b10) >> 1)
                 }
             },
+            Self::BOARD_WRAM_BASE..=Self::CHIP_WRAM_END if self.wram_disabled() => {
+                self.open_bus_data.get_data((aligned_address & 0b10) >> 1)
+            }
+            Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END if !self.ewram_enabled() => {
+                self.read_halfword_address_debug(Self::ewram_mirror_address(aligned_address))
+            }
             Self::CHIP_WRAM_BASE..=Self::CHIP_WRAM_END => {
                 let actual_offset = (aligned_address - Self::CHIP_WRAM_BASE) % Self::CHIP_WRAM_SIZE;
-                let low_byte = self.chip_wram[actual_offset as usize];
-                let high_byte = self.chip_wram[(actual_offset + 1) as usize];
-
-                u16::from_le_bytes([low_byte, high_byte])
+                self.chip_wram.read_u16_le(actual_offset as usize)
             }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
                 let actual_offset =
                     (aligned_address - Self::BOARD_WRAM_BASE) % Self::BOARD_WRAM_SIZE;
-                let low_byte = self.board_wram[actual_offset as usize];
-                let high_byte = self.board_wram[(actual_offset + 1) as usize];
-
-                u16::from_le_bytes([low_byte, high_byte])
+                self.board_wram.read_u16_le(actual_offset as usize)
             }
             Self::PALETTE_RAM_BASE..=Self::PALETTE_RAM_END => {
                 let offset = (aligned_address - Self::PALETTE_RAM_BASE) % Self::PALETTER_RAM_SIZE;
@@ -1316,16 +2021,21 @@ impl Bus {
                 let byte = self.cartridge.read_sram_byte(offset);
                 u16::from_be_bytes([byte, byte])
             }
-            _ => {
-                let low_byte = self.read_byte_address_debug(aligned_address);
-                let high_byte = self.read_byte_address_debug(aligned_address + 1);
-
-                u16::from_le_bytes([low_byte, high_byte])
-            }
+            _ => le_bytes::read_u16_le_with(aligned_address, |address| {
+                self.read_byte_address_debug(address)
+            }),
         }
     }
 
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub(super) fn read_word_address(&mut self, address: u32, access_type: BusAccessType) -> u32 {
+        self.count_read(address, 4, access_type);
+
+        #[cfg(any(test, feature = "flat-memory"))]
+        if let Some(flat_memory) = &self.flat_memory {
+            return flat_memory.read(address, 4);
+        }
+
         let result = match address {
             Self::BIOS_BASE..=Self::BIOS_END => {
                 let result = self.read_word_address_debug(address);
@@ -1342,12 +2052,7 @@ impl Bus {
             }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
                 let result = self.read_word_address_debug(address);
-                self.step();
-                self.step();
-                self.step();
-                self.step();
-                self.step();
-                self.step();
+                self.step_ewram_access(address, 4);
                 result
             }
             Self::CHIP_WRAM_BASE..=Self::CHIP_WRAM_END => {
@@ -1360,6 +2065,11 @@ impl Bus {
                 self.step();
                 result
             }
+            _ if Self::is_internal_memory_control(address) => {
+                let result = self.read_word_address_debug(address);
+                self.step();
+                result
+            }
             Self::PALETTE_RAM_BASE..=Self::PALETTE_RAM_END => {
                 let result = self.read_word_address_debug(address);
                 self.step();
@@ -1430,6 +2140,7 @@ impl Bus {
             }
         };
 
+        self.trace_io(AccessKind::Read, address, 4, result);
         self.open_bus_data = result;
         self.prefetch_sequential = false;
         result
@@ -1442,35 +2153,22 @@ impl Bus {
         match aligned_address {
             Self::BIOS_BASE..=Self::BIOS_END => match self.bios_read_behavior {
                 BiosReadBehavior::PrefetchValue => self.open_bus_bios_data,
-                BiosReadBehavior::TrueValue => u32::from_le_bytes([
-                    BIOS[aligned_address as usize],
-                    BIOS[(aligned_address + 1) as usize],
-                    BIOS[(aligned_address + 2) as usize],
-                    BIOS[(aligned_address + 3) as usize],
-                ]),
+                BiosReadBehavior::TrueValue => BIOS.read_u32_le(aligned_address as usize),
             },
+            Self::BOARD_WRAM_BASE..=Self::CHIP_WRAM_END if self.wram_disabled() => {
+                self.open_bus_data
+            }
+            Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END if !self.ewram_enabled() => {
+                self.read_word_address_debug(Self::ewram_mirror_address(aligned_address))
+            }
             Self::CHIP_WRAM_BASE..=Self::CHIP_WRAM_END => {
                 let actual_offset = (aligned_address - Self::CHIP_WRAM_BASE) % Self::CHIP_WRAM_SIZE;
-                let le_bytes = [
-                    self.chip_wram[actual_offset as usize],
-                    self.chip_wram[(actual_offset + 1) as usize],
-                    self.chip_wram[(actual_offset + 2) as usize],
-                    self.chip_wram[(actual_offset + 3) as usize],
-                ];
-
-                u32::from_le_bytes(le_bytes)
+                self.chip_wram.read_u32_le(actual_offset as usize)
             }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
                 let actual_offset =
                     (aligned_address - Self::BOARD_WRAM_BASE) % Self::BOARD_WRAM_SIZE;
-                let le_bytes = [
-                    self.board_wram[actual_offset as usize],
-                    self.board_wram[(actual_offset + 1) as usize],
-                    self.board_wram[(actual_offset + 2) as usize],
-                    self.board_wram[(actual_offset + 3) as usize],
-                ];
-
-                u32::from_le_bytes(le_bytes)
+                self.board_wram.read_u32_le(actual_offset as usize)
             }
 
             Self::PALETTE_RAM_BASE..=Self::PALETTE_RAM_END => {
@@ -1508,33 +2206,34 @@ impl Bus {
                 let byte = self.cartridge.read_sram_byte(offset);
                 u32::from_be_bytes([byte, byte, byte, byte])
             }
-            _ => {
-                let le_bytes = [
-                    self.read_byte_address_debug(aligned_address),
-                    self.read_byte_address_debug(aligned_address + 1),
-                    self.read_byte_address_debug(aligned_address + 2),
-                    self.read_byte_address_debug(aligned_address + 3),
-                ];
-
-                u32::from_le_bytes(le_bytes)
-            }
+            _ => le_bytes::read_u32_le_with(aligned_address, |address| {
+                self.read_byte_address_debug(address)
+            }),
         }
     }
 
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub(super) fn write_byte_address(
         &mut self,
         value: u8,
         address: u32,
         access_type: BusAccessType,
     ) {
+        self.count_write(address, 1, access_type);
+        self.trace_io(AccessKind::Write, address, 1, u32::from(value));
+
+        #[cfg(any(test, feature = "flat-memory"))]
+        if let Some(flat_memory) = &mut self.flat_memory {
+            flat_memory.write(address, 1, u32::from(value));
+            return;
+        }
+
         match address {
             Self::BIOS_BASE..=Self::BIOS_END => {
                 self.step();
             }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
-                self.step();
-                self.step();
-                self.step();
+                self.step_ewram_access(address, 1);
             }
             Self::CHIP_WRAM_BASE..=Self::CHIP_WRAM_END => {
                 self.step();
@@ -1547,15 +2246,13 @@ impl Bus {
             Self::IO_REGISTER_BASE..=Self::IO_REGISTER_END => {
                 self.step();
             }
-            Self::VRAM_BASE..=Self::VRAM_END => {
-                log::error!("byte write to vram at {:08X}", address);
-                self.step();
-            }
-            Self::PALETTE_RAM_BASE..=Self::PALETTE_RAM_END => {
-                log::error!("byte write to palette ram at {:08X}", address);
+            _ if Self::is_internal_memory_control(address) => {
                 self.step();
             }
-            Self::OAM_BASE..=Self::OAM_END => {
+            // Byte writes to these are widened to halfwords, or ignored, by the LCD.
+            Self::VRAM_BASE..=Self::VRAM_END
+            | Self::PALETTE_RAM_BASE..=Self::PALETTE_RAM_END
+            | Self::OAM_BASE..=Self::OAM_END => {
                 self.step();
             }
             Self::WAIT_STATE_0_ROM_BASE..=Self::WAIT_STATE_0_ROM_END => {
@@ -1587,6 +2284,10 @@ impl Bus {
 
     pub fn write_byte_address_debug(&mut self, value: u8, address: u32) {
         match address {
+            Self::BOARD_WRAM_BASE..=Self::CHIP_WRAM_END if self.wram_disabled() => {}
+            Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END if !self.ewram_enabled() => {
+                self.write_byte_address_debug(value, Self::ewram_mirror_address(address))
+            }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
                 let actual_offset = (address - Self::BOARD_WRAM_BASE) % Self::BOARD_WRAM_SIZE;
                 self.board_wram[actual_offset as usize] = value;
@@ -1846,6 +2547,21 @@ impl Bus {
                 self.timers[3].write_timer_counter_reload(value, address & 0b1)
             }
 
+            Self::SIO_DATA_BASE..=Self::SIO_DATA_END => self.serial.write_data(
+                value,
+                ((address - Self::SIO_DATA_BASE) / 2) as usize,
+                address & 0b1,
+            ),
+            Self::SIO_CONTROL_BASE..=Self::SIO_CONTROL_END => {
+                self.serial.write_control(value, address & 0b1)
+            }
+            Self::SIO_SEND_DATA_BASE..=Self::SIO_SEND_DATA_END => {
+                self.serial.write_send_data(value, address & 0b1)
+            }
+            Self::SIO_MODE_SELECT_BASE..=Self::SIO_MODE_SELECT_END => {
+                self.serial.write_mode_select(value, address & 0b1)
+            }
+
             Self::KEY_CONTROL_BASE..=Self::KEY_CONTROL_END => self
                 .keypad
                 .write_key_interrupt_control(value, address & 0b1),
@@ -1857,7 +2573,7 @@ impl Bus {
                 self.write_interrupt_acknowledge(value, address & 0b1)
             }
             Self::POSTFLG_ADDR => println!("0x{:02x} -> UNIMPLEMENTED POSTFLG", value),
-            Self::HALTCNT_ADDR => {} // println!("0x{:02x} -> UNIMPLEMENTED HALTCNT", value),
+            Self::HALTCNT_ADDR => self.write_halt_control(value),
             Self::WAITSTATE_CONTROL_BASE..=Self::WAITSTATE_CONTROL_END => {
                 self.write_waitstate_control(value, address & 0b11)
             }
@@ -1900,16 +2616,40 @@ impl Bus {
                 let offset = (address - Self::GAME_PAK_SRAM_BASE) % Self::GAME_PAK_SRAM_SIZE;
                 self.cartridge.write_sram_byte(value, offset);
             }
+            Self::MGBA_DEBUG_STRING_BASE..=Self::MGBA_DEBUG_STRING_END => self
+                .mgba_debug
+                .write_string(value, address - Self::MGBA_DEBUG_STRING_BASE),
+            Self::MGBA_DEBUG_FLAGS_BASE..=Self::MGBA_DEBUG_FLAGS_END => {
+                if let Some((level, message)) = self.mgba_debug.write_flags(value, address & 0b1) {
+                    self.emit_debug_output(level, &message);
+                }
+            }
+            Self::MGBA_DEBUG_ENABLE_BASE..=Self::MGBA_DEBUG_ENABLE_END => {
+                self.mgba_debug.write_enable(value, address & 0b1)
+            }
+            _ if Self::is_internal_memory_control(address) => {
+                self.write_internal_memory_control(value, address & 0b11)
+            }
             _ => {}
         }
     }
 
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub(super) fn write_halfword_address(
         &mut self,
         value: u16,
         address: u32,
         access_type: BusAccessType,
     ) {
+        self.count_write(address, 2, access_type);
+        self.trace_io(AccessKind::Write, address, 2, u32::from(value));
+
+        #[cfg(any(test, feature = "flat-memory"))]
+        if let Some(flat_memory) = &mut self.flat_memory {
+            flat_memory.write(address, 2, u32::from(value));
+            return;
+        }
+
         let unaligned_address = address;
         let aligned_address = Self::align_hword(unaligned_address);
 
@@ -1918,13 +2658,14 @@ impl Bus {
                 self.step();
             }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
-                self.step();
-                self.step();
-                self.step();
+                self.step_ewram_access(address, 2);
             }
             Self::IO_REGISTER_BASE..=Self::IO_REGISTER_END => {
                 self.step();
             }
+            _ if Self::is_internal_memory_control(address) => {
+                self.step();
+            }
             Self::OAM_BASE..=Self::OAM_END => {
                 self.step();
             }
@@ -1966,20 +2707,17 @@ impl Bus {
         let aligned_address = Self::align_hword(unaligned_address);
 
         match aligned_address {
+            Self::BOARD_WRAM_BASE..=Self::CHIP_WRAM_END if self.wram_disabled() => {}
+            Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END if !self.ewram_enabled() => self
+                .write_halfword_address_debug(value, Self::ewram_mirror_address(aligned_address)),
             Self::CHIP_WRAM_BASE..=Self::CHIP_WRAM_END => {
                 let actual_offset = (aligned_address - Self::CHIP_WRAM_BASE) % Self::CHIP_WRAM_SIZE;
-                let [low_byte, high_byte] = value.to_le_bytes();
-
-                self.chip_wram[actual_offset as usize] = low_byte;
-                self.chip_wram[(actual_offset + 1) as usize] = high_byte;
+                self.chip_wram.write_u16_le(value, actual_offset as usize);
             }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
                 let actual_offset =
                     (aligned_address - Self::BOARD_WRAM_BASE) % Self::BOARD_WRAM_SIZE;
-                let [low_byte, high_byte] = value.to_le_bytes();
-
-                self.board_wram[actual_offset as usize] = low_byte;
-                self.board_wram[(actual_offset + 1) as usize] = high_byte;
+                self.board_wram.write_u16_le(value, actual_offset as usize);
             }
             Self::OAM_BASE..=Self::OAM_END => {
                 let offset = (aligned_address - Self::OAM_BASE) % Self::OAM_SIZE;
@@ -2017,7 +2755,11 @@ impl Bus {
             Self::GAME_PAK_SRAM_BASE..=Self::GAME_PAK_SRAM_END => {
                 let offset =
                     (unaligned_address - Self::GAME_PAK_SRAM_BASE) % Self::GAME_PAK_SRAM_SIZE;
-                self.cartridge.write_sram_byte(value as u8, offset);
+
+                // SRAM only has an 8-bit data bus, so only the byte of the value that lines up
+                // with the unaligned address is stored.
+                let byte = value.rotate_right((unaligned_address & 0b1) * u8::BITS) as u8;
+                self.cartridge.write_sram_byte(byte, offset);
             }
             _ => {
                 let [low_byte, high_byte] = value.to_le_bytes();
@@ -2028,12 +2770,22 @@ impl Bus {
         }
     }
 
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub(super) fn write_word_address(
         &mut self,
         value: u32,
         address: u32,
         access_type: BusAccessType,
     ) {
+        self.count_write(address, 4, access_type);
+        self.trace_io(AccessKind::Write, address, 4, value);
+
+        #[cfg(any(test, feature = "flat-memory"))]
+        if let Some(flat_memory) = &mut self.flat_memory {
+            flat_memory.write(address, 4, value);
+            return;
+        }
+
         let unaligned_address = address;
         let aligned_address = Self::align_word(unaligned_address);
 
@@ -2042,16 +2794,14 @@ impl Bus {
                 self.step();
             }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
-                self.step();
-                self.step();
-                self.step();
-                self.step();
-                self.step();
-                self.step();
+                self.step_ewram_access(address, 4);
             }
             Self::IO_REGISTER_BASE..=Self::IO_REGISTER_END => {
                 self.step();
             }
+            _ if Self::is_internal_memory_control(address) => {
+                self.step();
+            }
             Self::OAM_BASE..=Self::OAM_END => {
                 self.step();
             }
@@ -2104,29 +2854,29 @@ impl Bus {
         let aligned_address = Self::align_word(unaligned_address);
 
         match aligned_address {
+            Self::BOARD_WRAM_BASE..=Self::CHIP_WRAM_END if self.wram_disabled() => {}
+            Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END if !self.ewram_enabled() => {
+                self.write_word_address_debug(value, Self::ewram_mirror_address(aligned_address))
+            }
             Self::CHIP_WRAM_BASE..=Self::CHIP_WRAM_END => {
                 let actual_offset = (aligned_address - Self::CHIP_WRAM_BASE) % Self::CHIP_WRAM_SIZE;
-                let le_bytes = value.to_le_bytes();
-
-                self.chip_wram[actual_offset as usize] = le_bytes[0];
-                self.chip_wram[(actual_offset + 1) as usize] = le_bytes[1];
-                self.chip_wram[(actual_offset + 2) as usize] = le_bytes[2];
-                self.chip_wram[(actual_offset + 3) as usize] = le_bytes[3];
+                self.chip_wram.write_u32_le(value, actual_offset as usize);
             }
             Self::BOARD_WRAM_BASE..=Self::BOARD_WRAM_END => {
                 let actual_offset =
                     (aligned_address - Self::BOARD_WRAM_BASE) % Self::BOARD_WRAM_SIZE;
-                let le_bytes = value.to_le_bytes();
-
-                self.board_wram[actual_offset as usize] = le_bytes[0];
-                self.board_wram[(actual_offset + 1) as usize] = le_bytes[1];
-                self.board_wram[(actual_offset + 2) as usize] = le_bytes[2];
-                self.board_wram[(actual_offset + 3) as usize] = le_bytes[3];
+                self.board_wram.write_u32_le(value, actual_offset as usize);
             }
 
             Self::DMA_FIFO_A_BASE..=Self::DMA_FIFO_A_END => self.apu.write_fifo_a(value),
             Self::DMA_FIFO_B_BASE..=Self::DMA_FIFO_B_END => self.apu.write_fifo_b(value),
 
+            // IE and IF share a word, make sure both halves are written with a single access.
+            Self::INTERRUPT_ENABLE_BASE => {
+                self.write_interrupt_enable(value as u16, 0);
+                self.write_interrupt_acknowledge((value >> 16) as u16, 0);
+            }
+
             Self::TIMER_0_COUNTER_RELOAD_BASE..=Self::TIMER_0_CONTROL_END => {
                 self.timers[0].write_timer_counter_reload_word(value)
             }
@@ -2176,7 +2926,10 @@ impl Bus {
             Self::GAME_PAK_SRAM_BASE..=Self::GAME_PAK_SRAM_END => {
                 let offset =
                     (unaligned_address - Self::GAME_PAK_SRAM_BASE) % Self::GAME_PAK_SRAM_SIZE;
-                self.cartridge.write_sram_byte(value as u8, offset);
+
+                // Same as halfword writes, only the byte lining up with the address is stored.
+                let byte = value.rotate_right((unaligned_address & 0b11) * u8::BITS) as u8;
+                self.cartridge.write_sram_byte(byte, offset);
             }
             _ => {
                 for (offset, byte) in value.to_le_bytes().into_iter().enumerate() {
@@ -2233,7 +2986,23 @@ impl Bus {
         let written_value = 0.set_data(value, index);
 
         // any bits which are high in the acknowledge write clear the corresponding IRQ waiting bit.
-        *self.interrupt_request.first_mut().unwrap() &= !written_value;
+        // Acknowledging takes effect immediately, so requests still making their way through the
+        // synchronizer are cleared as well.
+        for irq in self.interrupt_request.iter_mut() {
+            *irq &= !written_value;
+        }
+
+        self.acknowledged_interrupts |= written_value;
+    }
+
+    fn write_halt_control(&mut self, value: u8) {
+        const STOP_BIT_INDEX: usize = 7;
+
+        self.power_state = if value.get_bit(STOP_BIT_INDEX) {
+            PowerState::Stopped
+        } else {
+            PowerState::Halted
+        };
     }
 
     fn read_waitstate_control<T>(&self, index: u32) -> T
@@ -2247,12 +3016,50 @@ impl Bus {
     where
         u32: DataAccess<T>,
     {
-        const WAITSTATE_CONTROL_WRITABLE_MASK: u32 = 0b00000000_00000000_11111111_11111111;
+        // Bit 13 is unused, and bit 15 is the read-only game pak type flag (0 for GBA carts).
+        const WAITSTATE_CONTROL_WRITABLE_MASK: u32 = 0b00000000_00000000_01011111_11111111;
 
         let new_waitstate_control = self.waitstate_control.set_data(value, index);
         self.waitstate_control = (new_waitstate_control & WAITSTATE_CONTROL_WRITABLE_MASK)
             | (self.waitstate_control & (!WAITSTATE_CONTROL_WRITABLE_MASK));
     }
+
+    fn write_internal_memory_control(&mut self, value: u8, index: u32) {
+        // Bits 4 and 6-23 are unused. Bits 1-3 and 28-31 do nothing known, but keep their value.
+        const INTERNAL_MEMORY_CONTROL_WRITABLE_MASK: u32 = 0b11111111_00000000_00000000_00101111;
+
+        let new_internal_memory_control = self.internal_memory_control.set_data(value, index);
+        self.internal_memory_control =
+            new_internal_memory_control & INTERNAL_MEMORY_CONTROL_WRITABLE_MASK;
+        self.ewram_lockup_reported = false;
+    }
+
+    // The register is mirrored every 64KiB through the IO region.
+    fn is_internal_memory_control(address: u32) -> bool {
+        (Self::IO_REGISTER_BASE..=Self::IO_REGION_END).contains(&address)
+            && (address & 0xFFFF) & !0b11 == Self::INTERNAL_MEMORY_CONTROL_BASE & 0xFFFF
+    }
+
+    // Disables both IWRAM and EWRAM, which then read as open bus and ignore writes.
+    fn wram_disabled(&self) -> bool {
+        self.internal_memory_control.get_bit(0)
+    }
+
+    // With EWRAM disabled, its region mirrors IWRAM instead.
+    fn ewram_enabled(&self) -> bool {
+        self.internal_memory_control.get_bit(5)
+    }
+
+    fn ewram_mirror_address(address: u32) -> u32 {
+        Self::CHIP_WRAM_BASE + ((address - Self::BOARD_WRAM_BASE) % Self::CHIP_WRAM_SIZE)
+    }
+
+    // The address of the first EWRAM access made with wait control 15 since the register was
+    // last written, which locks up real hardware. Only reported once, since whatever the game
+    // does next isn't something hardware would ever get to run.
+    pub(super) fn take_ewram_lockup(&mut self) -> Option<u32> {
+        self.ewram_lockup.take()
+    }
 }
 
 impl Bus {
@@ -2263,11 +3070,13 @@ impl Bus {
     const TIMER_1_OVERFLOW_INTERRUPT_BIT_INDEX: usize = 4;
     const TIMER_2_OVERFLOW_INTERRUPT_BIT_INDEX: usize = 5;
     const TIMER_3_OVERFLOW_INTERRUPT_BIT_INDEX: usize = 6;
+    const SERIAL_INTERRUPT_BIT_INDEX: usize = 7;
     const DMA_0_INTERRUPT_BIT_INDEX: usize = 8;
     const DMA_1_INTERRUPT_BIT_INDEX: usize = 9;
     const DMA_2_INTERRUPT_BIT_INDEX: usize = 10;
     const DMA_3_INTERRUPT_BIT_INDEX: usize = 11;
     const KEYPAD_INTERRUPT_BIT_INDEX: usize = 12;
+    const GAMEPAK_INTERRUPT_BIT_INDEX: usize = 13;
 
     fn get_interrupts_enabled(&self) -> bool {
         const INTERRUPT_MASTER_ENABLE_BIT_INDEX: usize = 0;
@@ -2276,7 +3085,14 @@ impl Bus {
     }
 
     fn inform_dma_state_change(&mut self, state_changes: LcdStateChangeInfo) {
-        for dma in self.dma_infos.iter_mut() {
+        // DMA3 special timing is video capture mode, which transfers once per scanline for lines
+        // 2 through 161, then automatically disables itself at the start of line 162.
+        const VIDEO_CAPTURE_DMA_INDEX: usize = 3;
+        const VIDEO_CAPTURE_LINES: Range<u16> = 2..162;
+
+        let vcount = self.lcd.read_vcount::<u16>(0);
+
+        for (dma_idx, dma) in self.dma_infos.iter_mut().enumerate() {
             if !dma.get_dma_enable() {
                 continue;
             }
@@ -2285,6 +3101,14 @@ impl Bus {
                 DmaStartTiming::Immediately => false,
                 DmaStartTiming::VBlank => state_changes.vblank_entered,
                 DmaStartTiming::HBlank => state_changes.hblank_entered,
+                DmaStartTiming::Special if dma_idx == VIDEO_CAPTURE_DMA_INDEX => {
+                    if state_changes.scanline_started && vcount == VIDEO_CAPTURE_LINES.end {
+                        dma.clear_dma_enabled();
+                    }
+
+                    state_changes.scanline_started && VIDEO_CAPTURE_LINES.contains(&vcount)
+                }
+                // Sound FIFO DMA is handled directly in `step_dma`.
                 DmaStartTiming::Special => false,
             };
 
@@ -2294,6 +3118,7 @@ impl Bus {
         }
     }
 
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     fn step_dma(&mut self) {
         for dma_idx in 0..self.dma_infos.len() {
             let dma = &mut self.dma_infos[dma_idx];
@@ -2334,54 +3159,117 @@ impl Bus {
                     DmaTransferType::Bit32 => 4,
                 };
 
-                // Any read to an address below this results in an open bus DMA read.
+                // Any read to an address below this, or past the end of the game pak, results in
+                // an open bus DMA read which returns the last value the DMA channel transferred.
                 const MINIMUM_DMA_ADDRESS: u32 = 0x02000000;
+                let is_open_bus_source =
+                    |address| !(MINIMUM_DMA_ADDRESS..=Self::GAME_PAK_SRAM_END).contains(&address);
+
+                let start_cycle = self.cycle_count;
+
+                if let Some(dma_log) = &mut self.dma_log {
+                    dma_log.record(DmaTransfer {
+                        channel: dma_idx,
+                        cycle: start_cycle,
+                        source: dma_source,
+                        dest: dma_dest,
+                        count: dma_length as u32,
+                        word_transfer: matches!(transfer_type, DmaTransferType::Bit32),
+                        start_timing: dma.get_dma_start_timing(),
+                    });
+                }
+
+                // DMA takes 2 internal cycles to start up, or 4 if both source and destination are
+                // in gamepak memory. The CPU is stalled for the whole transfer, since we're only
+                // ever stepped from inside of one of its bus accesses.
+                let startup_cycles = if Self::is_rom(dma_source) && Self::is_rom(dma_dest) {
+                    4
+                } else {
+                    2
+                };
+                for _ in 0..startup_cycles {
+                    self.step();
+                }
 
-                for _ in 0..dma_length {
+                for transfer_idx in 0..dma_length {
                     let dma = &mut self.dma_infos[dma_idx];
 
+                    // The first unit is transferred using non-sequential accesses, all following
+                    // units are sequential.
+                    let access_type = if transfer_idx == 0 {
+                        BusAccessType::NonSequential
+                    } else {
+                        BusAccessType::Sequential
+                    };
+
+                    // Transfers to or from SRAM go through its 8-bit bus just like CPU accesses
+                    // do: reads see a single byte repeated, writes only store a single byte, and
+                    // the address still advances by the full transfer size. DMA addresses are
+                    // always aligned, so the byte stored is the lowest of each unit.
                     match transfer_type {
                         DmaTransferType::Bit16 => {
                             let align_addr = |address| address & (!0b1);
-                            let value = if dma_source < MINIMUM_DMA_ADDRESS {
+                            let value = if is_open_bus_source(dma_source) {
                                 dma.read_latch as u16
                             } else {
-                                let result = self.read_halfword_address(
-                                    align_addr(dma_source),
-                                    BusAccessType::NonSequential,
-                                );
+                                let result = if Self::is_sram(dma_source) {
+                                    let byte =
+                                        self.read_byte_address(align_addr(dma_source), access_type);
+                                    u16::from_le_bytes([byte; 2])
+                                } else {
+                                    self.read_halfword_address(align_addr(dma_source), access_type)
+                                };
                                 self.dma_infos[dma_idx].read_latch =
                                     (u32::from(result) << u16::BITS) | u32::from(result);
                                 result
                             };
 
-                            self.write_halfword_address(
-                                value,
-                                align_addr(dma_dest),
-                                BusAccessType::NonSequential,
-                            );
+                            if Self::is_sram(dma_dest) {
+                                self.write_byte_address(
+                                    value as u8,
+                                    align_addr(dma_dest),
+                                    access_type,
+                                );
+                            } else {
+                                self.write_halfword_address(
+                                    value,
+                                    align_addr(dma_dest),
+                                    access_type,
+                                );
+                            }
                         }
                         DmaTransferType::Bit32 => {
                             let align_addr = |address| address & (!0b11);
-                            let value = if dma_source < MINIMUM_DMA_ADDRESS {
+                            let value = if is_open_bus_source(dma_source) {
                                 dma.read_latch
                             } else {
-                                let result = self.read_word_address(
-                                    align_addr(dma_source),
-                                    BusAccessType::NonSequential,
-                                );
+                                let result = if Self::is_sram(dma_source) {
+                                    let byte =
+                                        self.read_byte_address(align_addr(dma_source), access_type);
+                                    u32::from_le_bytes([byte; 4])
+                                } else {
+                                    self.read_word_address(align_addr(dma_source), access_type)
+                                };
                                 self.dma_infos[dma_idx].read_latch = result;
                                 result
                             };
 
-                            self.write_word_address(
-                                value,
-                                align_addr(dma_dest),
-                                BusAccessType::NonSequential,
-                            );
+                            if Self::is_sram(dma_dest) {
+                                self.write_byte_address(
+                                    value as u8,
+                                    align_addr(dma_dest),
+                                    access_type,
+                                );
+                            } else {
+                                self.write_word_address(value, align_addr(dma_dest), access_type);
+                            }
                         }
                     };
 
+                    // The latched value is what was last driven onto the bus, regardless of
+                    // whether it was actually read or replayed from the latch.
+                    self.open_bus_data = self.dma_infos[dma_idx].read_latch;
+
                     // for every chunk written, update current latch.
                     let dma = &mut self.dma_infos[dma_idx];
 
@@ -2419,8 +3307,15 @@ impl Bus {
                     }
                 }
 
+                let cycles_consumed = self.cycle_count - start_cycle;
+
                 let dma = &mut self.dma_infos[dma_idx];
 
+                dma.stats.transfers_completed += 1;
+                dma.stats.units_transferred += dma_length as u64;
+                self.perf_counters.dma_units_transferred += dma_length as u64;
+                dma.stats.cycles_consumed += cycles_consumed;
+
                 dma.source_addr_internal = dma_source;
                 dma.dest_addr_internal = dma_dest;
 
@@ -2461,11 +3356,12 @@ impl Bus {
             overflows: [false; 4],
         };
 
-        let mut timer_overflow = false;
+        let mut previous_overflow = None;
         let mut interrupt_requests = [false; 4];
 
         for (i, timer) in self.timers.iter_mut().enumerate() {
-            timer_overflow = timer.step(timer_overflow);
+            let timer_overflow = timer.step(previous_overflow);
+            previous_overflow = Some(timer_overflow);
 
             if timer_overflow {
                 result.overflows[i] = true;
@@ -2506,8 +3402,9 @@ impl Bus {
             InterruptType::Dma1 => Self::DMA_1_INTERRUPT_BIT_INDEX,
             InterruptType::Dma2 => Self::DMA_2_INTERRUPT_BIT_INDEX,
             InterruptType::Dma3 => Self::DMA_3_INTERRUPT_BIT_INDEX,
+            InterruptType::Serial => Self::SERIAL_INTERRUPT_BIT_INDEX,
             InterruptType::Keypad => Self::KEYPAD_INTERRUPT_BIT_INDEX,
-            _ => todo!(),
+            InterruptType::Gamepak => Self::GAMEPAK_INTERRUPT_BIT_INDEX,
         };
 
         let old_irq = *self.interrupt_request.first().unwrap();
@@ -2516,11 +3413,46 @@ impl Bus {
     }
 
     pub(super) fn get_irq_pending(&mut self) -> bool {
-        if !self.get_interrupts_enabled() {
+        if !*self.interrupt_master_enable_sync.last().unwrap() {
             false
         } else {
-            let irq = *self.interrupt_request.last().unwrap();
-            (self.interrupt_enable & irq) != 0
+            self.pending_interrupts() != 0
+        }
+    }
+
+    // EWRAM has a 16-bit bus, so word accesses are split in two.
+    fn step_ewram_access(&mut self, address: u32, width: u32) {
+        if self.ewram_wait_control() == Self::EWRAM_LOCKUP_WAIT_CONTROL
+            && !self.fast_ewram
+            && !self.ewram_lockup_reported
+        {
+            self.ewram_lockup = Some(address);
+            self.ewram_lockup_reported = true;
+        }
+
+        let accesses = if width == 4 { 2 } else { 1 };
+        for _ in 0..accesses * (u32::from(self.get_ewram_wait_state()) + 1) {
+            self.step();
+        }
+    }
+
+    fn ewram_wait_control(&self) -> u32 {
+        const EWRAM_WAIT_CONTROL_BITS: RangeInclusive<usize> = 24..=27;
+
+        self.internal_memory_control
+            .get_bit_range(EWRAM_WAIT_CONTROL_BITS)
+    }
+
+    fn get_ewram_wait_state(&self) -> u8 {
+        if self.fast_ewram {
+            return 0;
+        }
+
+        // Real hardware locks up instead, see `take_ewram_lockup`. Carrying on as fast as it can
+        // is as good a guess as any.
+        match self.ewram_wait_control() {
+            Self::EWRAM_LOCKUP_WAIT_CONTROL => 1,
+            wait_control => 15 - wait_control as u8,
         }
     }
 
@@ -2618,7 +3550,7 @@ impl Bus {
         const ROM_2_2_NON_SEQUENTIAL: u8 = 2;
         const ROM_2_3_NON_SEQUENTIAL: u8 = 8;
 
-        const ROM_2_0_SEQUENTIAL: u8 = 2;
+        const ROM_2_0_SEQUENTIAL: u8 = 8;
         const ROM_2_1_SEQUENTIAL: u8 = 1;
 
         match access_type {
@@ -2642,6 +3574,28 @@ impl Bus {
             },
         }
     }
+
+    fn get_phi_terminal_output(&self) -> PhiTerminalOutput {
+        const PHI_TERMINAL_OUTPUT_BIT_RANGE: RangeInclusive<usize> = 11..=12;
+
+        match self
+            .waitstate_control
+            .get_bit_range(PHI_TERMINAL_OUTPUT_BIT_RANGE)
+        {
+            0 => PhiTerminalOutput::Disabled,
+            1 => PhiTerminalOutput::Mhz4_19,
+            2 => PhiTerminalOutput::Mhz8_38,
+            3 => PhiTerminalOutput::Mhz16_78,
+            _ => unreachable!(),
+        }
+    }
+
+    fn get_prefetch_buffer_enable(&self) -> bool {
+        const PREFETCH_BUFFER_ENABLE_BIT_INDEX: usize = 14;
+
+        self.waitstate_control
+            .get_bit(PREFETCH_BUFFER_ENABLE_BIT_INDEX)
+    }
 }
 
 impl Bus {
@@ -0,0 +1,72 @@
+use std::collections::BTreeSet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read, // data reads only, opcode fetches are not reported
+    Write,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessHit {
+    pub kind: AccessKind,
+    pub address: u32, // the watched address, which may be inside a wider access
+}
+
+// Reports data accesses to a set of watched addresses, so that debuggers and scripts can react
+// to them once the instruction that made them has finished. Hits are queued until taken.
+#[derive(Clone, Debug, Default)]
+pub struct AccessWatch {
+    reads: BTreeSet<u32>,
+    writes: BTreeSet<u32>,
+    hits: Vec<AccessHit>,
+}
+
+impl AccessWatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn watch(&mut self, kind: AccessKind, address: u32) {
+        self.addresses_mut(kind).insert(address);
+    }
+
+    pub fn unwatch(&mut self, kind: AccessKind, address: u32) {
+        self.addresses_mut(kind).remove(&address);
+    }
+
+    pub fn is_watched(&self, kind: AccessKind, address: u32) -> bool {
+        self.addresses(kind).contains(&address)
+    }
+
+    pub fn take_hits(&mut self) -> Vec<AccessHit> {
+        std::mem::take(&mut self.hits)
+    }
+
+    pub(super) fn record(&mut self, kind: AccessKind, address: u32, width: u32) {
+        let end = address.saturating_add(width);
+        let watched = match kind {
+            AccessKind::Read => &self.reads,
+            AccessKind::Write => &self.writes,
+        };
+
+        self.hits.extend(
+            watched
+                .range(address..end)
+                .map(|&address| AccessHit { kind, address }),
+        );
+    }
+
+    fn addresses(&self, kind: AccessKind) -> &BTreeSet<u32> {
+        match kind {
+            AccessKind::Read => &self.reads,
+            AccessKind::Write => &self.writes,
+        }
+    }
+
+    fn addresses_mut(&mut self, kind: AccessKind) -> &mut BTreeSet<u32> {
+        match kind {
+            AccessKind::Read => &mut self.reads,
+            AccessKind::Write => &mut self.writes,
+        }
+    }
+}
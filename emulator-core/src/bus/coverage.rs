@@ -0,0 +1,134 @@
+use std::io::{self, Write};
+
+use super::MemoryRegion;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoverageKind {
+    Executed,
+    Read, // data reads only, opcode fetches are covered by `Executed`
+    Written,
+}
+
+impl CoverageKind {
+    pub const ALL: [CoverageKind; 3] = [Self::Executed, Self::Read, Self::Written];
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CoverageSummary {
+    pub rom_size: u32,
+    pub executed_rom_bytes: u32,
+    pub read_rom_bytes: u32,
+}
+
+impl CoverageSummary {
+    pub fn executed_percent(&self) -> f64 {
+        if self.rom_size == 0 {
+            return 0.0;
+        }
+
+        f64::from(self.executed_rom_bytes) * 100.0 / f64::from(self.rom_size)
+    }
+}
+
+// Marks every byte that was executed, read or written, by region offset so that mirrors are
+// covered together.
+//
+// Exported coverage starts with the magic "GBACOV01", followed by a block for each region and
+// kind with any coverage: the region (u8, in `MemoryRegion::ALL` order), the kind (u8, in
+// `CoverageKind::ALL` order), the bitmap length in bytes (u32 LE) and then the bitmap, where bit
+// n of byte m covers region offset (m * 8) + n.
+#[derive(Clone, Debug, Default)]
+pub struct CoverageRecorder {
+    // Allocated on first use, since the ROM region alone needs 4MiB.
+    bitmaps: [[Option<Vec<u8>>; CoverageKind::ALL.len()]; MemoryRegion::ALL.len()],
+}
+
+impl CoverageRecorder {
+    const EXPORT_MAGIC: &'static [u8; 8] = b"GBACOV01";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_covered(&self, kind: CoverageKind, address: u32) -> bool {
+        let region = MemoryRegion::from_address(address);
+        if region == MemoryRegion::Unused {
+            return false;
+        }
+
+        let offset = address % region.size();
+        self.bitmap(region, kind)
+            .is_some_and(|bitmap| bitmap[(offset / 8) as usize] & (1 << (offset % 8)) != 0)
+    }
+
+    // Number of bytes covered in the first `len` bytes of the region.
+    pub fn covered_bytes(&self, kind: CoverageKind, region: MemoryRegion, len: u32) -> u32 {
+        let Some(bitmap) = self.bitmap(region, kind) else {
+            return 0;
+        };
+
+        let len = len.min(region.size());
+        let whole_bytes = (len / 8) as usize;
+        let mut covered: u32 = bitmap[..whole_bytes]
+            .iter()
+            .map(|byte| byte.count_ones())
+            .sum();
+
+        if !len.is_multiple_of(8) {
+            let mask = (1u8 << (len % 8)) - 1;
+            covered += (bitmap[whole_bytes] & mask).count_ones();
+        }
+
+        covered
+    }
+
+    pub fn summary(&self, rom_size: u32) -> CoverageSummary {
+        CoverageSummary {
+            rom_size,
+            executed_rom_bytes: self.covered_bytes(
+                CoverageKind::Executed,
+                MemoryRegion::Rom,
+                rom_size,
+            ),
+            read_rom_bytes: self.covered_bytes(CoverageKind::Read, MemoryRegion::Rom, rom_size),
+        }
+    }
+
+    pub fn export(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(Self::EXPORT_MAGIC)?;
+
+        for (region_index, bitmaps) in self.bitmaps.iter().enumerate() {
+            for (kind_index, bitmap) in bitmaps.iter().enumerate() {
+                let Some(bitmap) = bitmap else {
+                    continue;
+                };
+
+                writer.write_all(&[region_index as u8, kind_index as u8])?;
+                writer.write_all(&(bitmap.len() as u32).to_le_bytes())?;
+                writer.write_all(bitmap)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn record(&mut self, kind: CoverageKind, address: u32, width: u32) {
+        let region = MemoryRegion::from_address(address);
+        if region == MemoryRegion::Unused {
+            return;
+        }
+
+        let bitmap = self.bitmaps[region as usize][kind as usize]
+            .get_or_insert_with(|| vec![0; (region.size() / 8) as usize]);
+
+        // Accesses are always aligned to their width.
+        let start = (address & !(width - 1)) % region.size();
+        for offset in start..start + width {
+            bitmap[(offset / 8) as usize] |= 1 << (offset % 8);
+        }
+    }
+
+    fn bitmap(&self, region: MemoryRegion, kind: CoverageKind) -> Option<&Vec<u8>> {
+        self.bitmaps[region as usize][kind as usize].as_ref()
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugOutputLevel {
+    Fatal,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl DebugOutputLevel {
+    fn log_level(self) -> log::Level {
+        match self {
+            DebugOutputLevel::Fatal | DebugOutputLevel::Error => log::Level::Error,
+            DebugOutputLevel::Warn => log::Level::Warn,
+            DebugOutputLevel::Info => log::Level::Info,
+            DebugOutputLevel::Debug => log::Level::Debug,
+        }
+    }
+}
+
+// Called with every message the running software prints through one of the emulator debug output
+// interfaces.
+pub type DebugOutputCallback = Arc<dyn Fn(DebugOutputLevel, &str) + Send + Sync>;
+
+pub(super) fn log_debug_output(level: DebugOutputLevel, message: &str) {
+    log::log!(level.log_level(), "debug output: {}", message);
+}
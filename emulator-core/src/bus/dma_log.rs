@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+
+use super::DmaStartTiming;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmaTransfer {
+    pub channel: usize,
+    pub cycle: u64, // when the transfer started
+    pub source: u32,
+    pub dest: u32,
+    pub count: u32,          // units transferred, 4 words for sound FIFO transfers
+    pub word_transfer: bool, // 32-bit units if set, 16-bit otherwise
+    pub start_timing: DmaStartTiming,
+}
+
+// Records the most recent DMA transfers, oldest first, from the channels it's enabled for. Every
+// recorded transfer is also logged at trace level.
+#[derive(Clone, Debug)]
+pub struct DmaLog {
+    channels: [bool; 4],
+    transfers: VecDeque<DmaTransfer>,
+}
+
+impl Default for DmaLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DmaLog {
+    pub const LENGTH: usize = 256;
+
+    // Starts out logging every channel.
+    pub fn new() -> Self {
+        Self {
+            channels: [true; 4],
+            transfers: VecDeque::with_capacity(Self::LENGTH),
+        }
+    }
+
+    pub fn channel_enabled(&self, channel: usize) -> bool {
+        self.channels[channel]
+    }
+
+    pub fn set_channel_enabled(&mut self, channel: usize, enabled: bool) {
+        self.channels[channel] = enabled;
+    }
+
+    pub fn transfers(&self) -> &VecDeque<DmaTransfer> {
+        &self.transfers
+    }
+
+    pub fn clear(&mut self) {
+        self.transfers.clear();
+    }
+
+    pub(super) fn record(&mut self, transfer: DmaTransfer) {
+        if !self.channels[transfer.channel] {
+            return;
+        }
+
+        log::trace!(
+            "DMA{} {:08X} -> {:08X}, {} x {} bit, {:?} at cycle {}",
+            transfer.channel,
+            transfer.source,
+            transfer.dest,
+            transfer.count,
+            if transfer.word_transfer { 32 } else { 16 },
+            transfer.start_timing,
+            transfer.cycle
+        );
+
+        if self.transfers.len() == Self::LENGTH {
+            self.transfers.pop_front();
+        }
+        self.transfers.push_back(transfer);
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: u32,
+    pub size: u32, // in bytes
+    pub data: u32,
+}
+
+// A flat, untimed RAM covering the whole address space, which replaces the regular memory map
+// when set on the bus. Used to run single instructions in isolation, without any of the
+// side-effects of the real memory map getting in the way.
+#[derive(Clone, Debug, Default)]
+pub struct FlatMemory {
+    data: HashMap<u32, u8>,
+    writes: Vec<MemoryAccess>,
+}
+
+impl FlatMemory {
+    pub fn store(&mut self, address: u32, size: u32, data: u32) {
+        let aligned_address = address & !(size - 1);
+
+        for (offset, byte) in data
+            .to_le_bytes()
+            .into_iter()
+            .take(size as usize)
+            .enumerate()
+        {
+            self.data
+                .insert(aligned_address.wrapping_add(offset as u32), byte);
+        }
+    }
+
+    pub fn read(&self, address: u32, size: u32) -> u32 {
+        let aligned_address = address & !(size - 1);
+
+        let mut le_bytes = [0; 4];
+        for (offset, byte) in le_bytes.iter_mut().take(size as usize).enumerate() {
+            let byte_address = aligned_address.wrapping_add(offset as u32);
+            *byte = self.data.get(&byte_address).copied().unwrap_or(0);
+        }
+
+        u32::from_le_bytes(le_bytes)
+    }
+
+    pub fn write(&mut self, address: u32, size: u32, data: u32) {
+        self.writes.push(MemoryAccess {
+            address,
+            size,
+            data,
+        });
+        self.store(address, size, data);
+    }
+
+    // All writes in the order they happened, with the unaligned address they were made to.
+    pub fn writes(&self) -> &[MemoryAccess] {
+        &self.writes
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+
+use crate::BitManipulation;
+
+// Named by IE/IF bit.
+const INTERRUPT_NAMES: [&str; 14] = [
+    "VBlank", "HBlank", "VCount", "Timer0", "Timer1", "Timer2", "Timer3", "Serial", "DMA0", "DMA1",
+    "DMA2", "DMA3", "Keypad", "Gamepak",
+];
+
+// The interrupt registers as the CPU saw them when it took an IRQ.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterruptEvent {
+    pub cycle: u64,
+    pub interrupt_enable: u16,
+    pub interrupt_request: u16,
+    pub interrupt_master_enable: bool,
+    pub pc: u32, // of the instruction the IRQ was taken before
+}
+
+impl InterruptEvent {
+    // Which interrupts caused this IRQ, as the IE and IF bits they have in common.
+    pub fn sources(&self) -> u16 {
+        self.interrupt_enable & self.interrupt_request
+    }
+
+    pub fn source_names(&self) -> Vec<&'static str> {
+        let sources = self.sources();
+        INTERRUPT_NAMES
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| sources.get_bit(*bit))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+// The most recent IRQs taken, oldest first, for tracking down misbehaving interrupt handlers.
+#[derive(Clone, Debug, Default)]
+pub(super) struct InterruptHistory {
+    events: VecDeque<InterruptEvent>,
+}
+
+impl InterruptHistory {
+    pub(super) const LENGTH: usize = 64;
+
+    pub(super) fn record(&mut self, event: InterruptEvent) {
+        if self.events.len() == Self::LENGTH {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub(super) fn events(&self) -> &VecDeque<InterruptEvent> {
+        &self.events
+    }
+}
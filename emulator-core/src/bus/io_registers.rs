@@ -0,0 +1,280 @@
+use std::ops::RangeInclusive;
+
+use crate::BitManipulation;
+
+use super::Bus;
+
+#[derive(Clone, Debug)]
+pub struct IoRegisterField {
+    pub name: &'static str,
+    pub bits: RangeInclusive<usize>,
+    pub value: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct IoRegisterInfo {
+    pub name: &'static str,
+    pub address: u32,
+    pub size: u32, // in bytes
+    // `None` for write-only registers, which can't be read back through the bus.
+    pub value: Option<u32>,
+    // Decoded bitfields, only provided for a handful of commonly inspected registers.
+    pub fields: Vec<IoRegisterField>,
+}
+
+pub(super) type FieldLayout = &'static [(&'static str, RangeInclusive<usize>)];
+
+const DISPLAY_CONTROL_FIELDS: FieldLayout = &[
+    ("bg mode", 0..=2),
+    ("cgb mode", 3..=3),
+    ("display frame", 4..=4),
+    ("hblank interval free", 5..=5),
+    ("obj 1d mapping", 6..=6),
+    ("forced blank", 7..=7),
+    ("bg0 enable", 8..=8),
+    ("bg1 enable", 9..=9),
+    ("bg2 enable", 10..=10),
+    ("bg3 enable", 11..=11),
+    ("obj enable", 12..=12),
+    ("win0 enable", 13..=13),
+    ("win1 enable", 14..=14),
+    ("obj win enable", 15..=15),
+];
+
+const BLEND_CONTROL_FIELDS: FieldLayout = &[
+    ("bg0 1st target", 0..=0),
+    ("bg1 1st target", 1..=1),
+    ("bg2 1st target", 2..=2),
+    ("bg3 1st target", 3..=3),
+    ("obj 1st target", 4..=4),
+    ("bd 1st target", 5..=5),
+    ("effect", 6..=7),
+    ("bg0 2nd target", 8..=8),
+    ("bg1 2nd target", 9..=9),
+    ("bg2 2nd target", 10..=10),
+    ("bg3 2nd target", 11..=11),
+    ("obj 2nd target", 12..=12),
+    ("bd 2nd target", 13..=13),
+];
+
+const DMA_CONTROL_FIELDS: FieldLayout = &[
+    ("dest control", 5..=6),
+    ("source control", 7..=8),
+    ("repeat", 9..=9),
+    ("32 bit", 10..=10),
+    ("game pak drq", 11..=11),
+    ("start timing", 12..=13),
+    ("irq", 14..=14),
+    ("enable", 15..=15),
+];
+
+const TIMER_CONTROL_FIELDS: FieldLayout = &[
+    ("prescaler", 0..=1),
+    ("count up", 2..=2),
+    ("irq", 6..=6),
+    ("enable", 7..=7),
+];
+
+const SOUND_CONTROL_LOW_FIELDS: FieldLayout = &[
+    ("right volume", 0..=2),
+    ("left volume", 4..=6),
+    ("right enable", 8..=11),
+    ("left enable", 12..=15),
+];
+
+const SOUND_CONTROL_HIGH_FIELDS: FieldLayout = &[
+    ("psg volume", 0..=1),
+    ("dma a volume", 2..=2),
+    ("dma b volume", 3..=3),
+    ("dma a right", 8..=8),
+    ("dma a left", 9..=9),
+    ("dma a timer", 10..=10),
+    ("dma b right", 12..=12),
+    ("dma b left", 13..=13),
+    ("dma b timer", 14..=14),
+];
+
+const SOUND_CONTROL_EXTENDED_FIELDS: FieldLayout = &[
+    ("sound 1 on", 0..=0),
+    ("sound 2 on", 1..=1),
+    ("sound 3 on", 2..=2),
+    ("sound 4 on", 3..=3),
+    ("master enable", 7..=7),
+];
+
+pub(super) struct IoRegisterDefinition {
+    pub(super) name: &'static str,
+    pub(super) address: u32,
+    pub(super) size: u32,
+    pub(super) readable: bool,
+    pub(super) fields: FieldLayout,
+}
+
+const fn register(
+    name: &'static str,
+    address: u32,
+    size: u32,
+    readable: bool,
+) -> IoRegisterDefinition {
+    IoRegisterDefinition {
+        name,
+        address,
+        size,
+        readable,
+        fields: &[],
+    }
+}
+
+const fn decoded_register(
+    name: &'static str,
+    address: u32,
+    fields: FieldLayout,
+) -> IoRegisterDefinition {
+    IoRegisterDefinition {
+        name,
+        address,
+        size: 2,
+        readable: true,
+        fields,
+    }
+}
+
+pub(super) const IO_REGISTERS: &[IoRegisterDefinition] = &[
+    decoded_register("DISPCNT", 0x04000000, DISPLAY_CONTROL_FIELDS),
+    register("GREENSWAP", 0x04000002, 2, true),
+    register("DISPSTAT", 0x04000004, 2, true),
+    register("VCOUNT", 0x04000006, 2, true),
+    register("BG0CNT", 0x04000008, 2, true),
+    register("BG1CNT", 0x0400000A, 2, true),
+    register("BG2CNT", 0x0400000C, 2, true),
+    register("BG3CNT", 0x0400000E, 2, true),
+    register("BG0HOFS", 0x04000010, 2, false),
+    register("BG0VOFS", 0x04000012, 2, false),
+    register("BG1HOFS", 0x04000014, 2, false),
+    register("BG1VOFS", 0x04000016, 2, false),
+    register("BG2HOFS", 0x04000018, 2, false),
+    register("BG2VOFS", 0x0400001A, 2, false),
+    register("BG3HOFS", 0x0400001C, 2, false),
+    register("BG3VOFS", 0x0400001E, 2, false),
+    register("BG2PA", 0x04000020, 2, false),
+    register("BG2PB", 0x04000022, 2, false),
+    register("BG2PC", 0x04000024, 2, false),
+    register("BG2PD", 0x04000026, 2, false),
+    register("BG2X", 0x04000028, 4, false),
+    register("BG2Y", 0x0400002C, 4, false),
+    register("BG3PA", 0x04000030, 2, false),
+    register("BG3PB", 0x04000032, 2, false),
+    register("BG3PC", 0x04000034, 2, false),
+    register("BG3PD", 0x04000036, 2, false),
+    register("BG3X", 0x04000038, 4, false),
+    register("BG3Y", 0x0400003C, 4, false),
+    register("WIN0H", 0x04000040, 2, false),
+    register("WIN1H", 0x04000042, 2, false),
+    register("WIN0V", 0x04000044, 2, false),
+    register("WIN1V", 0x04000046, 2, false),
+    register("WININ", 0x04000048, 2, true),
+    register("WINOUT", 0x0400004A, 2, true),
+    register("MOSAIC", 0x0400004C, 2, false),
+    decoded_register("BLDCNT", 0x04000050, BLEND_CONTROL_FIELDS),
+    register("BLDALPHA", 0x04000052, 2, true),
+    register("BLDY", 0x04000054, 2, false),
+    register("SOUND1CNT_L", 0x04000060, 2, true),
+    register("SOUND1CNT_H", 0x04000062, 2, true),
+    register("SOUND1CNT_X", 0x04000064, 2, true),
+    register("SOUND2CNT_L", 0x04000068, 2, true),
+    register("SOUND2CNT_H", 0x0400006C, 2, true),
+    register("SOUND3CNT_L", 0x04000070, 2, true),
+    register("SOUND3CNT_H", 0x04000072, 2, true),
+    register("SOUND3CNT_X", 0x04000074, 2, true),
+    register("SOUND4CNT_L", 0x04000078, 2, true),
+    register("SOUND4CNT_H", 0x0400007C, 2, true),
+    decoded_register("SOUNDCNT_L", 0x04000080, SOUND_CONTROL_LOW_FIELDS),
+    decoded_register("SOUNDCNT_H", 0x04000082, SOUND_CONTROL_HIGH_FIELDS),
+    decoded_register("SOUNDCNT_X", 0x04000084, SOUND_CONTROL_EXTENDED_FIELDS),
+    register("SOUNDBIAS", 0x04000088, 2, true),
+    register("WAVE_RAM0", 0x04000090, 4, true),
+    register("WAVE_RAM1", 0x04000094, 4, true),
+    register("WAVE_RAM2", 0x04000098, 4, true),
+    register("WAVE_RAM3", 0x0400009C, 4, true),
+    register("FIFO_A", 0x040000A0, 4, false),
+    register("FIFO_B", 0x040000A4, 4, false),
+    register("DMA0SAD", 0x040000B0, 4, false),
+    register("DMA0DAD", 0x040000B4, 4, false),
+    register("DMA0CNT_L", 0x040000B8, 2, false),
+    decoded_register("DMA0CNT_H", 0x040000BA, DMA_CONTROL_FIELDS),
+    register("DMA1SAD", 0x040000BC, 4, false),
+    register("DMA1DAD", 0x040000C0, 4, false),
+    register("DMA1CNT_L", 0x040000C4, 2, false),
+    decoded_register("DMA1CNT_H", 0x040000C6, DMA_CONTROL_FIELDS),
+    register("DMA2SAD", 0x040000C8, 4, false),
+    register("DMA2DAD", 0x040000CC, 4, false),
+    register("DMA2CNT_L", 0x040000D0, 2, false),
+    decoded_register("DMA2CNT_H", 0x040000D2, DMA_CONTROL_FIELDS),
+    register("DMA3SAD", 0x040000D4, 4, false),
+    register("DMA3DAD", 0x040000D8, 4, false),
+    register("DMA3CNT_L", 0x040000DC, 2, false),
+    decoded_register("DMA3CNT_H", 0x040000DE, DMA_CONTROL_FIELDS),
+    register("TM0CNT_L", 0x04000100, 2, true),
+    decoded_register("TM0CNT_H", 0x04000102, TIMER_CONTROL_FIELDS),
+    register("TM1CNT_L", 0x04000104, 2, true),
+    decoded_register("TM1CNT_H", 0x04000106, TIMER_CONTROL_FIELDS),
+    register("TM2CNT_L", 0x04000108, 2, true),
+    decoded_register("TM2CNT_H", 0x0400010A, TIMER_CONTROL_FIELDS),
+    register("TM3CNT_L", 0x0400010C, 2, true),
+    decoded_register("TM3CNT_H", 0x0400010E, TIMER_CONTROL_FIELDS),
+    register("SIODATA32", 0x04000120, 4, true),
+    register("SIOMULTI2", 0x04000124, 2, true),
+    register("SIOMULTI3", 0x04000126, 2, true),
+    register("SIOCNT", 0x04000128, 2, true),
+    register("SIOMLT_SEND", 0x0400012A, 2, true),
+    register("KEYINPUT", 0x04000130, 2, true),
+    register("KEYCNT", 0x04000132, 2, true),
+    register("RCNT", 0x04000134, 2, true),
+    register("JOY_RECV", 0x04000150, 4, true),
+    register("IE", 0x04000200, 2, true),
+    register("IF", 0x04000202, 2, true),
+    register("WAITCNT", 0x04000204, 2, true),
+    register("IME", 0x04000208, 2, true),
+    register("POSTFLG", 0x04000300, 1, true),
+    register("HALTCNT", 0x04000301, 1, false),
+    register("IMC", 0x04000800, 4, true),
+];
+
+impl Bus {
+    // All implemented memory mapped IO registers, with their current values as seen by the CPU.
+    pub fn io_registers(&self) -> Vec<IoRegisterInfo> {
+        IO_REGISTERS
+            .iter()
+            .map(|definition| {
+                let value = definition.readable.then(|| match definition.size {
+                    1 => u32::from(self.read_byte_address_debug(definition.address)),
+                    2 => u32::from(self.read_halfword_address_debug(definition.address)),
+                    4 => self.read_word_address_debug(definition.address),
+                    _ => unreachable!(),
+                });
+
+                let fields = value
+                    .map(|value| {
+                        definition
+                            .fields
+                            .iter()
+                            .map(|(name, bits)| IoRegisterField {
+                                name,
+                                bits: bits.clone(),
+                                value: value.get_bit_range(bits.clone()),
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                IoRegisterInfo {
+                    name: definition.name,
+                    address: definition.address,
+                    size: definition.size,
+                    value,
+                    fields,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::BitManipulation;
+
+use super::io_registers::{IoRegisterDefinition, IO_REGISTERS};
+use super::AccessKind;
+
+// One access to a traced register. Byte and halfword accesses to wider registers only carry
+// the bits they touched, the rest of `value` reads as zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IoTraceEntry {
+    pub cycle: u64,
+    pub kind: AccessKind,
+    pub register: &'static str,
+    pub value: u32,
+    // e.g. "DISPCNT := bg mode 1, bg0 enable, bg1 enable, obj enable"
+    pub description: String,
+}
+
+// Logs every access to a set of IO registers at info level, with the value decoded into the
+// register's bitfields where they're known, and keeps the most recent ones, oldest first.
+#[derive(Clone, Debug, Default)]
+pub struct IoTrace {
+    registers: BTreeSet<u32>, // by address
+    entries: VecDeque<IoTraceEntry>,
+}
+
+impl IoTrace {
+    pub const LENGTH: usize = 256;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The address of the register called `name`, ignoring case.
+    pub fn register_address(name: &str) -> Option<u32> {
+        IO_REGISTERS
+            .iter()
+            .find(|definition| definition.name.eq_ignore_ascii_case(name))
+            .map(|definition| definition.address)
+    }
+
+    // Traces the register containing `address`, returning its name, or `None` if there's no
+    // known register there.
+    pub fn trace(&mut self, address: u32) -> Option<&'static str> {
+        let definition = definition_containing(address)?;
+        self.registers.insert(definition.address);
+        Some(definition.name)
+    }
+
+    pub fn untrace(&mut self, address: u32) {
+        if let Some(definition) = definition_containing(address) {
+            self.registers.remove(&definition.address);
+        }
+    }
+
+    pub fn traced(&self) -> Vec<&'static str> {
+        IO_REGISTERS
+            .iter()
+            .filter(|definition| self.registers.contains(&definition.address))
+            .map(|definition| definition.name)
+            .collect()
+    }
+
+    pub fn entries(&self) -> &VecDeque<IoTraceEntry> {
+        &self.entries
+    }
+
+    // Removes and returns the entries recorded since the last call, for frontends that print
+    // them as they happen.
+    pub fn take_entries(&mut self) -> Vec<IoTraceEntry> {
+        self.entries.drain(..).collect()
+    }
+
+    pub(super) fn record(
+        &mut self,
+        kind: AccessKind,
+        address: u32,
+        width: u32,
+        value: u32,
+        cycle: u64,
+    ) {
+        if self.registers.is_empty() {
+            return;
+        }
+
+        let end = address.saturating_add(width);
+        // An access can span several registers, like a word write to a pair of halfword ones.
+        for definition in IO_REGISTERS.iter().filter(|definition| {
+            self.registers.contains(&definition.address)
+                && definition.address < end
+                && address < definition.address + definition.size
+        }) {
+            // Line the accessed bytes up with the register's bits.
+            let mut register_value = 0;
+            let mut touched_bits = 0;
+            for byte in
+                address.max(definition.address)..end.min(definition.address + definition.size)
+            {
+                let byte_value = (value >> ((byte - address) * 8)) & 0xFF;
+                let shift = (byte - definition.address) * 8;
+                register_value |= byte_value << shift;
+                touched_bits |= 0xFF << shift;
+            }
+
+            let description = describe(definition, kind, register_value, touched_bits);
+            log::info!("{description}");
+
+            if self.entries.len() == Self::LENGTH {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(IoTraceEntry {
+                cycle,
+                kind,
+                register: definition.name,
+                value: register_value,
+                description,
+            });
+        }
+    }
+}
+
+fn definition_containing(address: u32) -> Option<&'static IoRegisterDefinition> {
+    IO_REGISTERS.iter().find(|definition| {
+        (definition.address..definition.address + definition.size).contains(&address)
+    })
+}
+
+// Set single bit fields are listed by name and wider ones with their value. Fields outside the
+// bytes that were accessed are left out, as are clear ones.
+fn describe(
+    definition: &IoRegisterDefinition,
+    kind: AccessKind,
+    value: u32,
+    touched_bits: u32,
+) -> String {
+    let operator = match kind {
+        AccessKind::Read => "==",
+        AccessKind::Write => ":=",
+    };
+
+    if definition.fields.is_empty() {
+        let digits = (definition.size * 2) as usize;
+        return format!("{} {operator} {value:0digits$X}", definition.name);
+    }
+
+    let fields = definition
+        .fields
+        .iter()
+        .filter(|(_, bits)| {
+            touched_bits.get_bit(*bits.start()) && value.get_bit_range(bits.clone()) != 0
+        })
+        .map(|(name, bits)| {
+            if bits.start() == bits.end() {
+                name.to_string()
+            } else {
+                format!("{name} {}", value.get_bit_range(bits.clone()))
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if fields.is_empty() {
+        format!("{} {operator} none set", definition.name)
+    } else {
+        format!("{} {operator} {}", definition.name, fields.join(", "))
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use super::debug_output::DebugOutputLevel;
+use crate::{BitManipulation, DataAccess};
+
+// The debug output registers implemented by mGBA, which a lot of test ROMs print their results
+// through. They stay unmapped until enabled by writing the magic value to REG_DEBUG_ENABLE.
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct MgbaDebug {
+    enabled: bool,
+    enable: u16,
+    flags: u16,
+    #[serde_as(as = "[_; 0x100]")]
+    string: [u8; Self::STRING_SIZE],
+}
+
+impl Default for MgbaDebug {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            enable: 0,
+            flags: 0,
+            string: [0; Self::STRING_SIZE],
+        }
+    }
+}
+
+impl MgbaDebug {
+    const STRING_SIZE: usize = 0x100;
+
+    const ENABLE_REQUEST: u16 = 0xC0DE;
+    const ENABLE_RESPONSE: u16 = 0x1DEA;
+
+    const FLAGS_LEVEL_BIT_RANGE: std::ops::RangeInclusive<usize> = 0..=2;
+    const FLAGS_SEND_BIT_INDEX: usize = 8;
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(super) fn read_enable(&self, index: u32) -> u8 {
+        Self::ENABLE_RESPONSE.get_data(index)
+    }
+
+    pub(super) fn write_enable(&mut self, value: u8, index: u32) {
+        self.enable = self.enable.set_data(value, index);
+
+        if self.enable == Self::ENABLE_REQUEST {
+            self.enabled = true;
+        }
+    }
+
+    pub(super) fn read_string(&self, offset: u32) -> u8 {
+        self.string[offset as usize]
+    }
+
+    pub(super) fn write_string(&mut self, value: u8, offset: u32) {
+        if self.enabled {
+            self.string[offset as usize] = value;
+        }
+    }
+
+    // Returns the message to print, once the send flag is written.
+    pub(super) fn write_flags(
+        &mut self,
+        value: u8,
+        index: u32,
+    ) -> Option<(DebugOutputLevel, String)> {
+        if !self.enabled {
+            return None;
+        }
+
+        self.flags = self.flags.set_data(value, index);
+
+        if !self.flags.get_bit(Self::FLAGS_SEND_BIT_INDEX) {
+            return None;
+        }
+
+        let level = match self.flags.get_bit_range(Self::FLAGS_LEVEL_BIT_RANGE) {
+            0 => DebugOutputLevel::Fatal,
+            1 => DebugOutputLevel::Error,
+            2 => DebugOutputLevel::Warn,
+            3 => DebugOutputLevel::Info,
+            _ => DebugOutputLevel::Debug,
+        };
+
+        let length = self
+            .string
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(Self::STRING_SIZE);
+        let message = String::from_utf8_lossy(&self.string[..length]).into_owned();
+
+        self.flags = 0;
+        self.string = [0; Self::STRING_SIZE];
+
+        Some((level, message))
+    }
+}
@@ -0,0 +1,165 @@
+use std::{collections::HashMap, fmt::Display};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryRegion {
+    Bios,
+    BoardWram,
+    ChipWram,
+    Io,
+    Palette,
+    Vram,
+    Oam,
+    Rom,
+    Sram,
+    Unused,
+}
+
+impl MemoryRegion {
+    pub const ALL: [MemoryRegion; 10] = [
+        Self::Bios,
+        Self::BoardWram,
+        Self::ChipWram,
+        Self::Io,
+        Self::Palette,
+        Self::Vram,
+        Self::Oam,
+        Self::Rom,
+        Self::Sram,
+        Self::Unused,
+    ];
+
+    pub fn from_address(address: u32) -> Self {
+        match address >> 24 {
+            0x00 if address < 0x4000 => Self::Bios,
+            0x02 => Self::BoardWram,
+            0x03 => Self::ChipWram,
+            0x04 => Self::Io,
+            0x05 => Self::Palette,
+            0x06 => Self::Vram,
+            0x07 => Self::Oam,
+            0x08..=0x0D => Self::Rom,
+            0x0E..=0x0F => Self::Sram,
+            _ => Self::Unused,
+        }
+    }
+
+    // Size of the region before it's mirrored, so offsets into it are stable no matter which
+    // mirror (or ROM waitstate region) was accessed.
+    pub fn size(self) -> u32 {
+        match self {
+            Self::Bios => 0x4000,
+            Self::BoardWram => 0x40000,
+            Self::ChipWram => 0x8000,
+            Self::Io => 0x400,
+            Self::Palette => 0x400,
+            Self::Vram => 0x20000,
+            Self::Oam => 0x400,
+            Self::Rom => 0x2000000,
+            Self::Sram => 0x10000,
+            Self::Unused => 0,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Display for MemoryRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bios => f.write_str("BIOS"),
+            Self::BoardWram => f.write_str("EWRAM"),
+            Self::ChipWram => f.write_str("IWRAM"),
+            Self::Io => f.write_str("IO"),
+            Self::Palette => f.write_str("Palette"),
+            Self::Vram => f.write_str("VRAM"),
+            Self::Oam => f.write_str("OAM"),
+            Self::Rom => f.write_str("ROM"),
+            Self::Sram => f.write_str("SRAM"),
+            Self::Unused => f.write_str("Unused"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegionAccessCounts {
+    pub reads: u64, // includes opcode fetches
+    pub writes: u64,
+}
+
+// Counts timed bus accesses by the CPU and DMA, per memory region and optionally per page within
+// each region. Debug accesses aren't counted.
+#[derive(Clone, Debug, Default)]
+pub struct BusProfiler {
+    region_counts: [RegionAccessCounts; MemoryRegion::ALL.len()],
+    page_counts: Option<HashMap<(MemoryRegion, u32), u64>>,
+}
+
+impl BusProfiler {
+    pub const PAGE_SIZE: u32 = 0x100;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Also counts accesses to each `PAGE_SIZE` page, which is slower.
+    pub fn with_pages() -> Self {
+        Self {
+            page_counts: Some(HashMap::new()),
+            ..Self::default()
+        }
+    }
+
+    pub fn tracks_pages(&self) -> bool {
+        self.page_counts.is_some()
+    }
+
+    pub fn region_counts(&self, region: MemoryRegion) -> RegionAccessCounts {
+        self.region_counts[region.index()]
+    }
+
+    // Reads and writes of the given page, where page 0 starts at the beginning of the region.
+    pub fn page_accesses(&self, region: MemoryRegion, page: u32) -> u64 {
+        self.page_counts
+            .as_ref()
+            .and_then(|page_counts| page_counts.get(&(region, page)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn max_page_accesses(&self, region: MemoryRegion) -> u64 {
+        self.page_counts
+            .iter()
+            .flatten()
+            .filter(|((page_region, _), _)| *page_region == region)
+            .map(|(_, accesses)| *accesses)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub(super) fn record_read(&mut self, address: u32) {
+        let region = MemoryRegion::from_address(address);
+        self.region_counts[region.index()].reads += 1;
+        self.record_page(region, address);
+    }
+
+    pub(super) fn record_write(&mut self, address: u32) {
+        let region = MemoryRegion::from_address(address);
+        self.region_counts[region.index()].writes += 1;
+        self.record_page(region, address);
+    }
+
+    fn record_page(&mut self, region: MemoryRegion, address: u32) {
+        let Some(page_counts) = &mut self.page_counts else {
+            return;
+        };
+
+        if region == MemoryRegion::Unused {
+            return;
+        }
+
+        let page = (address % region.size()) / Self::PAGE_SIZE;
+        *page_counts.entry((region, page)).or_default() += 1;
+    }
+}
@@ -1,15 +1,33 @@
+mod agb_print;
+mod backup_file;
 mod backup_types;
-
+mod compression;
+mod ereader;
+mod gpio;
+mod header;
+mod overrides;
+mod tilt_sensor;
+
+use agb_print::AgbPrint;
 use anyhow::anyhow;
-use backup_types::{BackupType, BACKUP_TYPES_MAP};
+pub use backup_file::BackupFileFormat;
+pub use backup_types::BackupType;
+use backup_types::BACKUP_TYPES_MAP;
+use ereader::EReader;
+pub use ereader::{CardFiles, CardSource, CardSourceHandle};
+pub use gpio::RumbleCallback;
+use gpio::{Gpio, GpioDevices};
+pub use header::CartridgeHeader;
+pub use overrides::GameOverrides;
 use serde_with::serde_as;
+use tilt_sensor::TiltSensor;
 
 use std::{io::Read, ops::Range};
 
 use lazy_static::lazy_static;
 use regex::bytes::Regex;
 
-use crate::{bit_manipulation::BitManipulation, data_access::DataAccess};
+use crate::{bit_manipulation::BitManipulation, data_access::DataAccess, le_bytes};
 use serde::{Deserialize, Serialize};
 
 use anyhow::Result;
@@ -32,48 +50,98 @@ pub enum Backup {
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Cartridge {
+    #[serde(skip)] // save states don't include the ROM, see `Cpu::load_state`
     rom: Vec<u8>,
     backup: Backup,
+    #[serde(skip)]
+    backup_dirty: bool, // set on any write to backup memory, cleared when polled
+    #[serde(skip)]
+    rom_name: Option<String>,
+    // Missing from older save states.
+    #[serde(default)]
+    agb_print: AgbPrint,
+    #[serde(default)]
+    gpio: Gpio,
+    #[serde(default)]
+    tilt_sensor: Option<TiltSensor>,
+    #[serde(default)]
+    ereader: Option<EReader>,
+    // Classic NES Series games rely on their ROM being mirrored across the whole cartridge space,
+    // and check for it as copy protection.
+    #[serde(default)]
+    mirrored_rom: bool,
+    // The compatibility database entry for the game, with any user overrides applied.
+    #[serde(default)]
+    overrides: GameOverrides,
+    // Pulled out of the slot while running, see `set_present`.
+    #[serde(default)]
+    removed: bool,
+    #[serde(default)]
+    removal_interrupt_pending: bool,
+    // Frontend state, so not part of save states.
+    #[serde(skip)]
+    rumble_callback: Option<RumbleCallback>,
+    #[serde(skip)]
+    card_source: Option<CardSourceHandle>,
 }
 
 impl Cartridge {
-    pub fn new<T: Read>(mut input: T, existing_backup: Option<Backup>) -> Result<Self> {
+    // Accepts raw ROM images as well as zip and gzip compressed ones.
+    pub fn new<T: Read>(input: T, existing_backup: Option<Backup>) -> Result<Self> {
+        Self::with_overrides(input, existing_backup, GameOverrides::default())
+    }
+
+    // An empty cartridge slot, for running multiboot images without a game inserted.
+    pub fn empty() -> Self {
+        Self::new(std::io::empty(), None).expect("an empty ROM always loads")
+    }
+
+    // Like `new`, but with settings that take precedence over both detection and the built in
+    // compatibility database.
+    pub fn with_overrides<T: Read>(
+        mut input: T,
+        existing_backup: Option<Backup>,
+        user_overrides: GameOverrides,
+    ) -> Result<Self> {
         let mut data = Vec::new();
         input
             .read_to_end(&mut data)
             .expect("failed to read cartridge input data");
 
-        const GAME_TITLE_BYTE_RANGE: Range<usize> = 0x0A0..0x0AC;
+        let compression::Rom {
+            data,
+            name: rom_name,
+        } = compression::decompress(data)?;
+
         const GAME_CODE_BYTE_RANGE: Range<usize> = 0x0AC..0x0B0;
 
-        if let Some(title_bytes) = data.get(GAME_TITLE_BYTE_RANGE) {
-            let title: String = title_bytes
-                .iter()
-                .copied()
-                .take_while(|val| *val != 0)
-                .map(char::from)
-                .collect();
-            log::info!("{}", title);
-        }
-
-        if let Some(code_bytes) = data.get(GAME_CODE_BYTE_RANGE) {
-            let code: String = code_bytes
-                .iter()
-                .copied()
-                .take_while(|val| *val != 0)
-                .map(char::from)
-                .collect();
-            log::info!("{}", code);
-
-            if let Some(backup_type) = BACKUP_TYPES_MAP.get(code_bytes) {
-                log::info!("{:?}", backup_type);
-            }
+        let header = CartridgeHeader::parse(&data);
+        log::info!("{:?}", header);
+
+        if !header.header_checksum_valid {
+            log::warn!("header checksum mismatch, real hardware would refuse to boot this ROM");
+        }
+
+        if !header.logo_valid {
+            log::warn!("nintendo logo mismatch, real hardware would refuse to boot this ROM");
+        }
+
+        let overrides = GameOverrides::lookup(&header.game_code).merge(user_overrides);
+        if overrides != GameOverrides::default() {
+            log::info!("{:?}", overrides);
         }
 
         let new_backup = {
-            let code_bytes = &data[GAME_CODE_BYTE_RANGE];
+            let backup_type = overrides.backup.or_else(|| {
+                data.get(GAME_CODE_BYTE_RANGE)
+                    .and_then(|code_bytes| BACKUP_TYPES_MAP.get(code_bytes))
+                    .copied()
+            });
+            if let Some(backup_type) = backup_type {
+                log::info!("{:?}", backup_type);
+            }
 
-            match backup_types::BACKUP_TYPES_MAP.get(code_bytes).copied() {
+            match backup_type {
                 Some(BackupType::Eeprom512B) => Backup::Eeprom(Eeprom::new(EepromSize::Eeprom512B)),
                 Some(BackupType::Eeprom8K) => Backup::Eeprom(Eeprom::new(EepromSize::Eeprom8K)),
                 Some(BackupType::Flash {
@@ -92,7 +160,9 @@ impl Cartridge {
                         .into_iter()
                         .filter(|val| *val)
                         .count();
-                    assert!(num_matches <= 1);
+                    if num_matches > 1 {
+                        log::warn!("found strings for {num_matches} backup types, using the first");
+                    }
 
                     if eeprom_match {
                         log::info!("Using eeprom backup with size 8K");
@@ -111,6 +181,28 @@ impl Cartridge {
             }
         };
 
+        let gpio_devices = GpioDevices::detect(&header.game_code);
+        log::info!("{:?}", gpio_devices);
+
+        let tilt_sensor = TiltSensor::detect(&header.game_code).then(TiltSensor::default);
+        if tilt_sensor.is_some() {
+            log::info!("Using tilt sensor");
+        }
+
+        let ereader = EReader::detect(&header.game_code).then(EReader::default);
+        if ereader.is_some() {
+            log::info!("Using e-Reader");
+        }
+
+        let mirrored_rom = header.game_code.starts_with('F');
+        if mirrored_rom {
+            log::info!("Using mirrored ROM");
+        }
+
+        if overrides.rtc == Some(true) {
+            log::warn!("game uses a real-time clock, which isn't emulated yet");
+        }
+
         let rom = data;
 
         let backup = if let Some(existing_backup) = existing_backup {
@@ -130,13 +222,118 @@ impl Cartridge {
             new_backup
         };
 
-        Ok(Self { rom, backup })
+        Ok(Self {
+            rom,
+            backup,
+            backup_dirty: false,
+            rom_name,
+            agb_print: AgbPrint::default(),
+            gpio: Gpio::new(gpio_devices),
+            tilt_sensor,
+            ereader,
+            mirrored_rom,
+            overrides,
+            removed: false,
+            removal_interrupt_pending: false,
+            rumble_callback: None,
+            card_source: None,
+        })
+    }
+
+    pub(crate) fn swap_rom(&mut self, other: &mut Cartridge) {
+        std::mem::swap(&mut self.rom, &mut other.rom);
+    }
+
+    // The file name of the ROM within the archive it was loaded from, if any. Frontends should
+    // derive save file names from this when present, so that saves are shared between compressed
+    // and uncompressed copies of the same ROM.
+    pub fn rom_name(&self) -> Option<&str> {
+        self.rom_name.as_deref()
+    }
+
+    pub fn header(&self) -> CartridgeHeader {
+        CartridgeHeader::parse(&self.rom)
+    }
+
+    pub fn rom_size(&self) -> usize {
+        self.rom.len()
+    }
+
+    pub fn overrides(&self) -> GameOverrides {
+        self.overrides
+    }
+
+    // Tilts the cartridge, for games with a tilt sensor or gyro. Both axes range from -1.0 to
+    // 1.0, and the gyro only uses the X axis as its rotation speed.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        if let Some(tilt_sensor) = &mut self.tilt_sensor {
+            tilt_sensor.set_tilt(x, y);
+        }
+
+        self.gpio.set_gyro_rotation(x);
+    }
+
+    // Sets how much light reaches a solar sensor cartridge, from 0 (dark) to 255 (brightest).
+    pub fn set_solar_level(&mut self, level: u8) {
+        self.gpio.set_solar_level(level);
+    }
+
+    pub fn is_present(&self) -> bool {
+        !self.removed
+    }
+
+    // Pulls the cartridge out of the slot, or pushes it back in, while running. Pulling it out
+    // raises the Game Pak interrupt, which games handle by halting before they crash, and from
+    // then on the bus reads as if no cartridge was ever inserted.
+    pub fn set_present(&mut self, present: bool) {
+        if present != self.removed {
+            return;
+        }
+
+        self.removed = !present;
+        if !present {
+            self.removal_interrupt_pending = true;
+        }
+    }
+
+    pub(crate) fn poll_removal_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.removal_interrupt_pending)
+    }
+
+    pub fn rumble_callback(&self) -> Option<RumbleCallback> {
+        self.rumble_callback.clone()
+    }
+
+    pub fn set_rumble_callback(&mut self, callback: Option<RumbleCallback>) {
+        self.rumble_callback = callback;
+    }
+
+    pub fn has_ereader(&self) -> bool {
+        self.ereader.is_some()
+    }
+
+    pub fn card_source(&self) -> Option<CardSourceHandle> {
+        self.card_source.clone()
+    }
+
+    // Where e-Reader cards come from when the game scans one. Scans fail as if no card was
+    // swiped without one.
+    pub fn set_card_source(&mut self, source: Option<CardSourceHandle>) {
+        self.card_source = source;
     }
 
     pub fn get_backup(&self) -> &Backup {
         &self.backup
     }
 
+    // Whether the backup may have been modified since this was last called. This is conservative,
+    // any write that reaches the backup chip marks it dirty, even if no data ended up changing.
+    pub fn poll_backup_dirty(&mut self) -> bool {
+        let result = self.backup_dirty;
+        self.backup_dirty = false;
+        result
+    }
+
     pub fn set_backup(&mut self, backup: Backup) -> Result<()> {
         let current_variant = std::mem::discriminant(&self.backup);
         let new_variant = std::mem::discriminant(&backup);
@@ -155,74 +352,181 @@ impl Cartridge {
 
 impl Cartridge {
     pub fn read_rom_byte(&self, offset: u32) -> u8 {
-        if offset < (self.rom.len() as u32) {
-            self.rom[offset as usize]
+        if self.removed {
+            return Self::read_empty_slot_byte(offset);
+        }
+
+        if self.gpio.is_present() && Gpio::contains(offset) {
+            if let Some(value) = self.gpio.read_byte(offset) {
+                return value;
+            }
+        }
+
+        if let Some(ereader) = self.ereader.as_ref().filter(|_| EReader::contains(offset)) {
+            return ereader.read_byte(offset);
+        }
+
+        if self.agb_print.is_enabled() {
+            if let Some(value) = self.agb_print.read_byte(offset) {
+                return value;
+            }
+        }
+
+        let rom_offset = if self.mirrored_rom {
+            offset & (self.rom.len().next_power_of_two() as u32 - 1)
+        } else {
+            offset
+        };
+
+        if rom_offset < (self.rom.len() as u32) {
+            self.rom[rom_offset as usize]
         } else {
-            // Reading from GamePak ROM when no Cartridge is inserted
-            //
-            // Because Gamepak uses the same signal-lines for both 16bit data and for lower 16bit halfword address,
-            // the entire gamepak ROM area is effectively filled by incrementing 16bit values (Address/2 AND FFFFh).
-            let hword_value = (offset / 2) & 0xFFFF;
-            hword_value.get_data(offset & 0b1)
+            Self::read_empty_slot_byte(offset)
         }
     }
 
+    // Reading from GamePak ROM when no Cartridge is inserted
+    //
+    // Because Gamepak uses the same signal-lines for both 16bit data and for lower 16bit halfword address,
+    // the entire gamepak ROM area is effectively filled by incrementing 16bit values (Address/2 AND FFFFh).
+    fn read_empty_slot_byte(offset: u32) -> u8 {
+        let hword_value = (offset / 2) & 0xFFFF;
+        hword_value.get_data(offset & 0b1)
+    }
+
     pub fn read_rom_hword(&mut self, offset: u32) -> u16 {
+        let is_eeprom_offset = !self.removed && self.is_eeprom_offset(offset);
         match &mut self.backup {
-            Backup::Eeprom(eeprom) if offset > 0x1FFFF00 || (offset as usize) >= self.rom.len() => {
-                eeprom.read_hword()
-            }
+            Backup::Eeprom(eeprom) if is_eeprom_offset => eeprom.read_hword(),
             _ => self.read_rom_hword_debug(offset),
         }
     }
 
     pub fn read_rom_hword_debug(&self, offset: u32) -> u16 {
-        let low_byte = self.read_rom_byte(offset);
-        let high_byte = self.read_rom_byte(offset + 1);
-
-        u16::from_le_bytes([low_byte, high_byte])
+        le_bytes::read_u16_le_with(offset, |offset| self.read_rom_byte(offset))
     }
 
     pub fn read_rom_word(&self, offset: u32) -> u32 {
-        let le_bytes = [
-            self.read_rom_byte(offset),
-            self.read_rom_byte(offset + 1),
-            self.read_rom_byte(offset + 2),
-            self.read_rom_byte(offset + 3),
-        ];
-
-        u32::from_le_bytes(le_bytes)
+        le_bytes::read_u32_le_with(offset, |offset| self.read_rom_byte(offset))
     }
 
-    pub fn write_rom_byte(&mut self, _value: u8, _offset: u32) {
-        // ROM byte writes ignored
+    // ROM writes are ignored, other than by GPIO devices, the e-Reader and AGBPrint.
+    pub fn write_rom_byte(&mut self, value: u8, offset: u32) {
+        if self.removed {
+            return;
+        }
+
+        if self.gpio.is_present() && Gpio::contains(offset) {
+            self.write_gpio_byte(value, offset);
+            return;
+        }
+
+        if let Some(ereader) = self.ereader.as_mut().filter(|_| EReader::contains(offset)) {
+            ereader.write_byte(value, offset, self.card_source.as_ref());
+            return;
+        }
+
+        self.agb_print.write_byte(value, offset);
     }
 
     pub fn write_rom_hword(&mut self, value: u16, offset: u32) {
+        if self.removed {
+            return;
+        }
+
+        let is_eeprom_offset = self.is_eeprom_offset(offset);
         match &mut self.backup {
-            Backup::Eeprom(eeprom) if offset > 0x1FFFF00 || (offset as usize) >= self.rom.len() => {
+            Backup::Eeprom(eeprom) if is_eeprom_offset => {
                 eeprom.write_hword(value);
+                self.backup_dirty = true;
+            }
+            // GPIO registers are only 4 bits wide.
+            _ if self.gpio.is_present() && Gpio::contains(offset) => {
+                self.write_gpio_byte(value as u8, offset)
+            }
+            // e-Reader registers are written a halfword at a time.
+            _ if self.ereader.is_some() && EReader::contains(offset) => {
+                self.write_rom_byte(value as u8, offset);
+                self.write_rom_byte((value >> 8) as u8, offset + 1);
             }
-            _ => {} // ignore all other ROM hword writes
+            _ => self.agb_print.write_hword(value, offset),
         }
     }
 
-    pub fn write_rom_word(&mut self, _value: u32, _offset: u32) {
-        // ROM word writes ignored
+    pub fn write_rom_word(&mut self, value: u32, offset: u32) {
+        self.write_rom_hword(value as u16, offset);
+        self.write_rom_hword((value >> u16::BITS) as u16, offset + 2);
+    }
+
+    // EEPROM takes up the upper 16MiB of the cartridge space, unless the ROM needs all 32MiB, in
+    // which case only the last 256 bytes are left for it.
+    fn is_eeprom_offset(&self, offset: u32) -> bool {
+        const EEPROM_OFFSET: u32 = 0x1000000;
+        const LARGE_ROM_EEPROM_OFFSET: u32 = 0x1FFFF00;
+
+        if self.rom.len() > EEPROM_OFFSET as usize {
+            offset >= LARGE_ROM_EEPROM_OFFSET
+        } else {
+            offset >= EEPROM_OFFSET
+        }
+    }
+
+    fn write_gpio_byte(&mut self, value: u8, offset: u32) {
+        if let Some(rumble) = self.gpio.write_byte(value, offset) {
+            if let Some(callback) = &self.rumble_callback {
+                callback(rumble);
+            }
+        }
+    }
+
+    // Prints anything pending in the AGBPrint buffer. Returns `None` if the ROM never enabled
+    // AGBPrint.
+    pub fn flush_agb_print(&mut self) -> Option<String> {
+        self.agb_print.is_enabled().then(|| self.agb_print.flush())
     }
 
     pub fn read_sram_byte(&self, offset: u32) -> u8 {
+        // Nothing drives the data lines, so they float high.
+        if self.removed {
+            return 0xFF;
+        }
+
+        if let Some(value) = self
+            .tilt_sensor
+            .as_ref()
+            .and_then(|tilt_sensor| tilt_sensor.read_byte(offset))
+        {
+            return value;
+        }
+
         match &self.backup {
             Backup::Flash(flash) => flash.read_byte(offset),
             Backup::Sram(sram) => sram.read_byte(offset),
-            _ => todo!(),
+            // Nothing drives the data lines, so they float high.
+            _ => 0xFF,
         }
     }
 
     pub fn write_sram_byte(&mut self, value: u8, offset: u32) {
+        if self.removed {
+            return;
+        }
+
+        if let Some(tilt_sensor) = &mut self.tilt_sensor {
+            if tilt_sensor.write_byte(value, offset) {
+                return;
+            }
+        }
+
         match &mut self.backup {
-            Backup::Flash(flash) => flash.write_byte(value, offset),
-            Backup::Sram(sram) => sram.write_byte(value, offset),
+            Backup::Flash(flash) => {
+                flash.write_byte(value, offset);
+                self.backup_dirty = true;
+            }
+            Backup::Sram(sram) => {
+                sram.write_byte(value, offset);
+                self.backup_dirty = true;
+            }
             _ => {
                 log::error!(
                     "attempted to write value {:02X} at SRAM offset {:08X}",
@@ -440,6 +744,11 @@ impl Flash {
     const ATMEL_DEVICE_TYPE: u8 = 0x3D;
     const ATMEL_MANUFACTURER: u8 = 0x1F;
 
+    const MACRONIX_128K_DEVICE_TYPE: u8 = 0x09;
+    const MACRONIX_MANUFACTURER: u8 = 0xC2;
+    const SANYO_128K_DEVICE_TYPE: u8 = 0x13;
+    const SANYO_MANUFACTURER: u8 = 0x62;
+
     fn new(device_type: u8, manufacturer: u8) -> Self {
         // assert!(device_type != Self::ATMEL_DEVICE_TYPE);
         // assert!(manufacturer != Self::ATMEL_MANUFACTURER);
@@ -505,7 +814,10 @@ impl Flash {
                         self.state = FlashCommandState::BankSwitch;
                         self.wanted_write = FlashWantedWrite::CommandData;
                     }
-                    _ => unreachable!(),
+                    _ => {
+                        log::warn!("unknown flash command {:02X}", value);
+                        self.wanted_write = FlashWantedWrite::Write_5555_AA;
+                    }
                 },
                 FlashCommandState::Identification if offset == 0x5555 && value == 0xF0 => {
                     self.state = FlashCommandState::ReadCommand;
@@ -572,24 +884,38 @@ impl Flash {
                     self.state = FlashCommandState::ReadCommand;
                     self.wanted_write = FlashWantedWrite::Write_5555_AA;
                 }
-                _ => unreachable!(
-                    "{:02X} {:08X} {:?} {:?}",
-                    value, offset, self.state, self.wanted_write
-                ),
+                _ => self.abort_command(value, offset),
             },
-            _ => todo!(
-                "{:02X} {:08X} {:?} {:?}",
-                value,
-                offset,
-                self.state,
-                self.wanted_write
-            ),
+            _ => self.abort_command(value, offset),
         }
     }
 
+    // Software writing something unexpected mid-command leaves the chip waiting for a new one.
+    fn abort_command(&mut self, value: u8, offset: u32) {
+        log::warn!(
+            "unexpected flash write {:02X} at {:08X} in {:?} {:?}",
+            value,
+            offset,
+            self.state,
+            self.wanted_write
+        );
+
+        self.state = FlashCommandState::ReadCommand;
+        self.wanted_write = FlashWantedWrite::Write_5555_AA;
+    }
+
     fn is_atmel(&self) -> bool {
         self.device_type == Self::ATMEL_DEVICE_TYPE && self.manufacturer == Self::ATMEL_MANUFACTURER
     }
+
+    // The other chips only have the low bank.
+    fn is_128k(&self) -> bool {
+        matches!(
+            (self.device_type, self.manufacturer),
+            (Self::MACRONIX_128K_DEVICE_TYPE, Self::MACRONIX_MANUFACTURER)
+                | (Self::SANYO_128K_DEVICE_TYPE, Self::SANYO_MANUFACTURER)
+        )
+    }
 }
 
 #[serde_as]
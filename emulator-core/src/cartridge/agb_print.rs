@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::le_bytes;
+
+// AGBPrint, as used by devkitARM and supported by VBA and no$gba. Text is written into a ring
+// buffer in an otherwise unused part of the cartridge address space, and printed once the program
+// issues SWI 0xFA. None of this exists on retail cartridges, so the interface only comes alive
+// once the protect register is unlocked.
+//
+// All offsets are relative to the start of the cartridge ROM.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct AgbPrint {
+    enabled: bool,
+    unprotected: bool,
+    memory: HashMap<u32, u8>, // everything written to the cartridge while unprotected
+}
+
+impl AgbPrint {
+    const PROTECT_OFFSET: u32 = 0x1FE2FFE;
+    const PROTECT_UNLOCK_VALUE: u16 = 0x20;
+
+    // struct { u16 request; u16 bank; u16 get; u16 put; }
+    const STRUCT_OFFSET: u32 = 0x1FE20F8;
+    const STRUCT_BANK_OFFSET: u32 = Self::STRUCT_OFFSET + 2;
+    const STRUCT_GET_OFFSET: u32 = Self::STRUCT_OFFSET + 4;
+    const STRUCT_PUT_OFFSET: u32 = Self::STRUCT_OFFSET + 6;
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(super) fn read_byte(&self, offset: u32) -> Option<u8> {
+        self.memory.get(&offset).copied()
+    }
+
+    fn read_hword(&self, offset: u32) -> u16 {
+        le_bytes::read_u16_le_with(offset, |offset| self.read_byte(offset).unwrap_or(0))
+    }
+
+    pub(super) fn write_byte(&mut self, value: u8, offset: u32) {
+        if self.unprotected {
+            self.memory.insert(offset, value);
+        }
+    }
+
+    pub(super) fn write_hword(&mut self, value: u16, offset: u32) {
+        if offset == Self::PROTECT_OFFSET {
+            self.unprotected = value == Self::PROTECT_UNLOCK_VALUE;
+            self.enabled |= self.unprotected;
+            return;
+        }
+
+        let [low_byte, high_byte] = value.to_le_bytes();
+        self.write_byte(low_byte, offset);
+        self.write_byte(high_byte, offset + 1);
+    }
+
+    // Returns everything in the buffer since the last flush, and marks it as consumed.
+    pub(super) fn flush(&mut self) -> String {
+        let bank = self.read_hword(Self::STRUCT_BANK_OFFSET);
+        let mut get = self.read_hword(Self::STRUCT_GET_OFFSET);
+        let put = self.read_hword(Self::STRUCT_PUT_OFFSET);
+
+        let buffer_offset = u32::from(bank) << 16;
+
+        let mut text = Vec::new();
+        while get != put {
+            text.push(self.read_byte(buffer_offset + u32::from(get)).unwrap_or(0));
+            get = get.wrapping_add(1);
+        }
+
+        for (i, byte) in put.to_le_bytes().into_iter().enumerate() {
+            self.memory.insert(Self::STRUCT_GET_OFFSET + i as u32, byte);
+        }
+
+        String::from_utf8_lossy(&text).into_owned()
+    }
+}
@@ -0,0 +1,314 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use super::{Backup, Cartridge, Eeprom, EepromSize, Flash, Sram};
+use crate::LittleEndianBytes;
+
+const EEPROM_512B_SIZE: usize = 0x200;
+const EEPROM_8K_SIZE: usize = 0x2000;
+const SRAM_SIZE: usize = 0x8000;
+const FLASH_64K_SIZE: usize = 0x10000;
+const FLASH_128K_SIZE: usize = 0x20000;
+
+// mGBA appends the real-time clock state to saves of games with one.
+const MGBA_RTC_FOOTER_SIZE: usize = 16;
+
+const NO_CASH_MAGIC: &[u8; 32] = b"NocashGbaBackupMediaSavDataFile\x1A";
+const NO_CASH_HEADER_SIZE: usize = 0x4C;
+const NO_CASH_COMPRESSED: u32 = 1;
+
+// How backup memory is laid out in save files of other emulators. EEPROM bits are packed most
+// significant bit first in all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackupFileFormat {
+    // The chip contents as is, as mGBA and most other emulators write them.
+    #[default]
+    Raw,
+    // Like raw, except that 512 byte EEPROM saves are padded out to 8KiB, as VisualBoyAdvance
+    // writes them.
+    Vba,
+    // no$gba's, with a header naming the game, and optionally run-length compressed.
+    NoCash,
+}
+
+impl BackupFileFormat {
+    pub const ALL: [BackupFileFormat; 3] = [Self::Raw, Self::Vba, Self::NoCash];
+}
+
+impl Display for BackupFileFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Raw => f.write_str("raw"),
+            Self::Vba => f.write_str("vba"),
+            Self::NoCash => f.write_str("no-cash"),
+        }
+    }
+}
+
+impl FromStr for BackupFileFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.to_string() == s)
+            .ok_or_else(|| anyhow!("unknown save format '{s}', expected raw, vba or no-cash"))
+    }
+}
+
+impl Cartridge {
+    // The backup memory as a save file in `format`, empty for cartridges without any.
+    pub fn export_backup(&self, format: BackupFileFormat) -> Vec<u8> {
+        let mut data = match &self.backup {
+            Backup::Eeprom(eeprom) => eeprom
+                .data
+                .chunks(8)
+                .map(|bits| {
+                    bits.iter()
+                        .fold(0, |byte, &bit| (byte << 1) | u8::from(bit))
+                })
+                .collect(),
+            Backup::Flash(flash) if flash.is_128k() => {
+                [flash.low_bank.as_slice(), flash.high_bank.as_slice()].concat()
+            }
+            Backup::Flash(flash) => flash.low_bank.to_vec(),
+            Backup::Sram(sram) => sram.data[..SRAM_SIZE].to_vec(),
+            Backup::None => Vec::new(),
+        };
+
+        match format {
+            BackupFileFormat::Raw => data,
+            BackupFileFormat::Vba => {
+                if data.len() == EEPROM_512B_SIZE {
+                    data.resize(EEPROM_8K_SIZE, 0xFF);
+                }
+                data
+            }
+            BackupFileFormat::NoCash => {
+                let mut file = vec![0; NO_CASH_HEADER_SIZE];
+                file[..0x20].copy_from_slice(NO_CASH_MAGIC);
+                file[0x20..0x24].copy_from_slice(b"SRAM");
+                file[0x28..0x2C].copy_from_slice(&(data.len() as u32).to_le_bytes());
+                // The game title and code, from the ROM header.
+                if let Some(title) = self.rom.get(0xA0..0xB0) {
+                    file[0x3C..0x4C].copy_from_slice(title);
+                }
+                file.extend(data);
+                file
+            }
+        }
+    }
+
+    // Replaces the backup memory with that of a save file from another emulator, returning the
+    // format it was in. Where the detected backup type doesn't match the save, which happens for
+    // games detected by string search, the EEPROM or flash size is taken from the save instead.
+    pub fn import_backup(&mut self, file: &[u8]) -> Result<BackupFileFormat> {
+        let (mut format, data) = if file.starts_with(NO_CASH_MAGIC) {
+            (BackupFileFormat::NoCash, read_no_cash(file)?)
+        } else {
+            (BackupFileFormat::Raw, file.to_vec())
+        };
+
+        let backup = match self.backup_from_data(&data) {
+            Ok(backup) => backup,
+            Err(e) if data.len() > MGBA_RTC_FOOTER_SIZE => {
+                let without_footer = &data[..data.len() - MGBA_RTC_FOOTER_SIZE];
+                let backup = self.backup_from_data(without_footer).map_err(|_| e)?;
+                log::info!("ignoring the real-time clock state at the end of the save");
+                backup
+            }
+            Err(e) => return Err(e),
+        };
+
+        let is_padded_eeprom = matches!(
+            &backup,
+            Backup::Eeprom(Eeprom {
+                size: EepromSize::Eeprom512B,
+                ..
+            })
+        ) && data.len() == EEPROM_8K_SIZE;
+        if format == BackupFileFormat::Raw && is_padded_eeprom {
+            format = BackupFileFormat::Vba;
+        }
+
+        self.backup = backup;
+        self.backup_dirty = true;
+        Ok(format)
+    }
+
+    fn backup_from_data(&self, data: &[u8]) -> Result<Backup> {
+        let backup = match (&self.backup, data.len()) {
+            (Backup::Eeprom(_), EEPROM_512B_SIZE) => {
+                Backup::Eeprom(eeprom_from_data(EepromSize::Eeprom512B, data))
+            }
+            // Padded, see `BackupFileFormat::Vba`.
+            (
+                Backup::Eeprom(Eeprom {
+                    size: EepromSize::Eeprom512B,
+                    ..
+                }),
+                EEPROM_8K_SIZE,
+            ) => Backup::Eeprom(eeprom_from_data(
+                EepromSize::Eeprom512B,
+                &data[..EEPROM_512B_SIZE],
+            )),
+            (Backup::Eeprom(_), EEPROM_8K_SIZE) => {
+                Backup::Eeprom(eeprom_from_data(EepromSize::Eeprom8K, data))
+            }
+            (Backup::Flash(flash), FLASH_64K_SIZE | FLASH_128K_SIZE) => {
+                let mut new_flash = if data.len() == FLASH_128K_SIZE && !flash.is_128k() {
+                    Flash::new(
+                        Flash::MACRONIX_128K_DEVICE_TYPE,
+                        Flash::MACRONIX_MANUFACTURER,
+                    )
+                } else {
+                    Flash::new(flash.device_type, flash.manufacturer)
+                };
+                new_flash.low_bank.copy_from_slice(&data[..FLASH_64K_SIZE]);
+                if data.len() == FLASH_128K_SIZE {
+                    new_flash.high_bank.copy_from_slice(&data[FLASH_64K_SIZE..]);
+                }
+                Backup::Flash(new_flash)
+            }
+            // Some emulators pad SRAM out to 64KiB, which is how much is addressable.
+            (Backup::Sram(_), SRAM_SIZE | FLASH_64K_SIZE) => {
+                let mut sram = Sram::default();
+                sram.data[..data.len()].copy_from_slice(data);
+                Backup::Sram(sram)
+            }
+            (Backup::None, _) => return Err(anyhow!("the cartridge has no backup memory")),
+            (backup, size) => {
+                let backup_name = match backup {
+                    Backup::Eeprom(_) => "EEPROM",
+                    Backup::Flash(_) => "flash",
+                    Backup::Sram(_) => "SRAM",
+                    Backup::None => unreachable!(),
+                };
+                return Err(anyhow!(
+                    "a {size} byte save doesn't fit the cartridge's {backup_name}"
+                ));
+            }
+        };
+
+        Ok(backup)
+    }
+}
+
+fn eeprom_from_data(size: EepromSize, data: &[u8]) -> Eeprom {
+    let mut eeprom = Eeprom::new(size);
+    for (index, bit) in eeprom.data.iter_mut().enumerate() {
+        *bit = data[index / 8] & (0x80 >> (index % 8)) != 0;
+    }
+    eeprom
+}
+
+// The data of a no$gba save, decompressing it if needed. Compressed data is a sequence of runs,
+// each starting with a byte saying what follows:
+//   00h      end of data
+//   01h-7Fh  that many bytes to copy
+//   80h      a byte to repeat, then a 16-bit repeat count
+//   81h-FFh  a byte to repeat that many times less 80h
+fn read_no_cash(file: &[u8]) -> Result<Vec<u8>> {
+    let truncated = || anyhow!("truncated no$gba save");
+    let header = file.get(..NO_CASH_HEADER_SIZE).ok_or_else(truncated)?;
+    let read_u32 = |offset: usize| header.read_u32_le(offset);
+
+    let size = read_u32(0x28) as usize;
+    let data = &file[NO_CASH_HEADER_SIZE..];
+    if read_u32(0x24) != NO_CASH_COMPRESSED {
+        return Ok(data.get(..size).ok_or_else(truncated)?.to_vec());
+    }
+
+    // Compressed data starts with its length, which isn't needed with the end marker.
+    let mut compressed = data.get(4..).ok_or_else(truncated)?.iter().copied();
+    let mut next = || compressed.next().ok_or_else(truncated);
+    let mut decompressed = Vec::with_capacity(size);
+    loop {
+        match next()? {
+            0x00 => break,
+            count @ 0x01..=0x7F => {
+                for _ in 0..count {
+                    decompressed.push(next()?);
+                }
+            }
+            0x80 => {
+                let value = next()?;
+                let count = [next()?, next()?].read_u16_le(0);
+                decompressed.extend(std::iter::repeat_n(value, usize::from(count)));
+            }
+            count => {
+                let value = next()?;
+                decompressed.extend(std::iter::repeat_n(value, usize::from(count - 0x80)));
+            }
+        }
+    }
+
+    decompressed.truncate(size);
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::build_test_rom;
+
+    #[test]
+    fn backup_file_formats() {
+        let cartridge_for = |game_code: &[u8; 4]| {
+            Cartridge::new(build_test_rom(game_code).as_slice(), None).unwrap()
+        };
+
+        // 512 byte EEPROM, which VisualBoyAdvance pads.
+        let mut cartridge = cartridge_for(b"BJBE");
+        let save = (0..0x200).map(|i| i as u8).collect::<Vec<_>>();
+        assert_eq!(
+            cartridge.import_backup(&save).unwrap(),
+            BackupFileFormat::Raw
+        );
+        assert_eq!(cartridge.export_backup(BackupFileFormat::Raw), save);
+
+        let vba = cartridge.export_backup(BackupFileFormat::Vba);
+        assert_eq!(vba.len(), 0x2000);
+        assert!(vba[0x200..].iter().all(|&byte| byte == 0xFF));
+        for (format, file) in [
+            (BackupFileFormat::Vba, vba),
+            (
+                BackupFileFormat::NoCash,
+                cartridge.export_backup(BackupFileFormat::NoCash),
+            ),
+        ] {
+            let mut imported = cartridge_for(b"BJBE");
+            assert_eq!(imported.import_backup(&file).unwrap(), format);
+            assert_eq!(imported.export_backup(BackupFileFormat::Raw), save);
+        }
+
+        // SRAM, from mGBA with a real-time clock footer and from no$gba compressed.
+        let mut cartridge = cartridge_for(b"AG7J");
+        let save = vec![0x5A; 0x8000];
+        let with_footer = [save.as_slice(), &[0; 16]].concat();
+        assert_eq!(
+            cartridge.import_backup(&with_footer).unwrap(),
+            BackupFileFormat::Raw
+        );
+        assert_eq!(cartridge.export_backup(BackupFileFormat::Raw), save);
+
+        let mut compressed = cartridge.export_backup(BackupFileFormat::NoCash)[..0x4C].to_vec();
+        compressed[0x24] = 1;
+        compressed.extend([5, 0, 0, 0, 0x80, 0x5A, 0x00, 0x80, 0x00]);
+        let mut cartridge = cartridge_for(b"AG7J");
+        assert_eq!(
+            cartridge.import_backup(&compressed).unwrap(),
+            BackupFileFormat::NoCash
+        );
+        assert_eq!(cartridge.export_backup(BackupFileFormat::Raw), save);
+
+        // 128KiB flash, both banks of it.
+        let mut cartridge = cartridge_for(b"AXVE");
+        let save = (0..0x20000).map(|i| (i >> 16) as u8).collect::<Vec<_>>();
+        cartridge.import_backup(&save).unwrap();
+        assert_eq!(cartridge.export_backup(BackupFileFormat::Raw), save);
+        assert!(cartridge.import_backup(&[0; 100]).is_err());
+    }
+}
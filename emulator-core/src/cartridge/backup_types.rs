@@ -1,6 +1,7 @@
 use phf::phf_map;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BackupType {
     Eeprom512B,
     Eeprom8K,
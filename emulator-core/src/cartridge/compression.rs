@@ -0,0 +1,81 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+pub struct Rom {
+    pub data: Vec<u8>,
+    // The file name of the ROM inside of the archive, if it was compressed and the archive recorded
+    // one.
+    pub name: Option<String>,
+}
+
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(ZIP_MAGIC) || data.starts_with(GZIP_MAGIC)
+}
+
+// Uncompressed ROMs are passed through as-is.
+pub fn decompress(data: Vec<u8>) -> Result<Rom> {
+    if data.starts_with(ZIP_MAGIC) {
+        decompress_zip(&data)
+    } else if data.starts_with(GZIP_MAGIC) {
+        decompress_gzip(&data)
+    } else {
+        Ok(Rom { data, name: None })
+    }
+}
+
+fn decompress_zip(data: &[u8]) -> Result<Rom> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() || !entry.name().to_ascii_lowercase().ends_with(".gba") {
+            continue;
+        }
+
+        log::info!("loading {} from zip archive", entry.name());
+
+        let mut rom_data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut rom_data)?;
+
+        // Entries may be nested in directories, only the file name itself is kept.
+        let name = entry.enclosed_name().and_then(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        });
+
+        return Ok(Rom {
+            data: rom_data,
+            name,
+        });
+    }
+
+    Err(anyhow!("zip archive does not contain a .gba file"))
+}
+
+fn decompress_gzip(data: &[u8]) -> Result<Rom> {
+    let mut decoder = GzDecoder::new(data);
+
+    let mut rom_data = Vec::new();
+    decoder.read_to_end(&mut rom_data)?;
+
+    let name = decoder
+        .header()
+        .and_then(|header| header.filename())
+        .map(|name| String::from_utf8_lossy(name).into_owned());
+
+    log::info!(
+        "loaded {} from gzip archive",
+        name.as_deref().unwrap_or("rom")
+    );
+
+    Ok(Rom {
+        data: rom_data,
+        name,
+    })
+}
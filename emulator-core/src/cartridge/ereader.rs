@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+// Supplies the e-Reader with dot-code cards as the game asks for them to be swiped. Cards are
+// handed over already decoded, as the data the scanner would have read off the strip, so
+// decoding scans of printed cards is left to implementations of their own.
+pub trait CardSource: Send {
+    // The data of the next card, or `None` once there are no more to swipe.
+    fn next_card(&mut self) -> Result<Option<Vec<u8>>>;
+}
+
+pub type CardSourceHandle = Arc<Mutex<dyn CardSource>>;
+
+// Pre-decoded card dumps, like the .raw files from card dumping tools, swiped in the order given.
+pub struct CardFiles {
+    paths: VecDeque<PathBuf>,
+}
+
+impl CardFiles {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            paths: paths.into_iter().collect(),
+        }
+    }
+}
+
+impl CardSource for CardFiles {
+    fn next_card(&mut self) -> Result<Option<Vec<u8>>> {
+        let Some(path) = self.paths.pop_front() else {
+            return Ok(None);
+        };
+
+        log::info!("swiping e-Reader card {}", path.display());
+        Ok(Some(std::fs::read(path)?))
+    }
+}
+
+// The e-Reader's dot-code scanner, mapped into the upper end of the wait state 2 ROM region.
+// Only what software sees of a finished scan is emulated: starting a scan loads the next card
+// from the `CardSource`, whose data can then be read back from the data window. The camera, its
+// LED and the raw dot patterns it would have captured are skipped.
+//
+// All offsets are relative to the start of the ROM region.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct EReader {
+    card_data: Vec<u8>,
+    status: u16,
+}
+
+impl EReader {
+    const DATA_OFFSET: u32 = 0x1FA0000;
+    const DATA_SIZE: u32 = 0x2000;
+    const CONTROL_OFFSET: u32 = 0x1FC0000;
+    const STATUS_OFFSET: u32 = 0x1FC0002;
+    const LENGTH_OFFSET: u32 = 0x1FC0004;
+
+    const START_SCAN_BIT: u8 = 0b1;
+
+    const CARD_READY_FLAG: u16 = 0b01;
+    const NO_CARD_FLAG: u16 = 0b10;
+
+    pub(super) fn detect(game_code: &str) -> bool {
+        // e-Reader, Card e-Reader+, Card e-Reader
+        matches!(game_code.get(..3), Some("PSA" | "PEA"))
+    }
+
+    pub(super) fn contains(offset: u32) -> bool {
+        (Self::DATA_OFFSET..Self::DATA_OFFSET + Self::DATA_SIZE).contains(&offset)
+            || (Self::CONTROL_OFFSET..Self::LENGTH_OFFSET + 2).contains(&offset)
+    }
+
+    pub(super) fn read_byte(&self, offset: u32) -> u8 {
+        if let Some(index) = offset
+            .checked_sub(Self::DATA_OFFSET)
+            .filter(|&index| index < Self::DATA_SIZE)
+        {
+            return self.card_data.get(index as usize).copied().unwrap_or(0);
+        }
+
+        let register = match offset & !0b1 {
+            Self::STATUS_OFFSET => self.status,
+            Self::LENGTH_OFFSET => self.card_data.len().min(Self::DATA_SIZE as usize) as u16,
+            _ => 0,
+        };
+        register.to_le_bytes()[(offset & 0b1) as usize]
+    }
+
+    pub(super) fn write_byte(&mut self, value: u8, offset: u32, source: Option<&CardSourceHandle>) {
+        if offset != Self::CONTROL_OFFSET || value & Self::START_SCAN_BIT == 0 {
+            return;
+        }
+
+        let card = match source.map(|source| source.lock().unwrap().next_card()) {
+            Some(Ok(card)) => card,
+            Some(Err(e)) => {
+                log::error!("failed to load e-Reader card: {e}");
+                None
+            }
+            None => None,
+        };
+
+        match card {
+            Some(card) => {
+                if card.len() > Self::DATA_SIZE as usize {
+                    log::warn!(
+                        "e-Reader card is {} bytes, only the first {} are read",
+                        card.len(),
+                        Self::DATA_SIZE
+                    );
+                }
+                self.card_data = card;
+                self.status = Self::CARD_READY_FLAG;
+            }
+            None => {
+                self.card_data.clear();
+                self.status = Self::NO_CARD_FLAG;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::tests::build_test_rom;
+    use crate::{BootMode, Cpu};
+
+    #[test]
+    fn ereader_card_scan() {
+        struct Cards(Vec<Vec<u8>>);
+
+        impl CardSource for Cards {
+            fn next_card(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+                Ok(self.0.pop())
+            }
+        }
+
+        let mut cartridge = Cartridge::new(build_test_rom(b"PSAE").as_slice(), None).unwrap();
+        assert!(cartridge.has_ereader());
+        cartridge.set_card_source(Some(Arc::new(Mutex::new(Cards(vec![vec![
+            0x12, 0x34, 0x56,
+        ]])))));
+        let mut cpu = Cpu::with_boot_mode(cartridge, BootMode::SkipBios);
+
+        cpu.bus.write_halfword_address_debug(1, 0x0DFC0000);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x0DFC0002), 0b01);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x0DFC0004), 3);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x0DFA0000), 0x3412);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0DFA0002), 0x56);
+
+        // Out of cards.
+        cpu.bus.write_halfword_address_debug(1, 0x0DFC0000);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x0DFC0002), 0b10);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x0DFC0004), 0);
+    }
+}
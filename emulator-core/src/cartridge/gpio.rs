@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bit_manipulation::BitManipulation;
+
+// Called with the new motor state whenever a cartridge's rumble motor is switched on or off.
+pub type RumbleCallback = Arc<dyn Fn(bool) + Send + Sync>;
+
+// Hardware wired up to the GPIO port, depending on the game.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub(super) struct GpioDevices {
+    rumble: bool,
+    gyro: bool,
+    #[serde(default)]
+    solar: bool,
+}
+
+impl GpioDevices {
+    pub(super) fn detect(game_code: &str) -> Self {
+        // Region agnostic, so only the first three characters of the game code are checked.
+        match game_code.get(..3) {
+            Some("RZW") => Self {
+                rumble: true,
+                gyro: true,
+                ..Self::default()
+            }, // WarioWare: Twisted!
+            Some("V49") => Self {
+                rumble: true,
+                ..Self::default()
+            }, // Drill Dozer
+            Some("U3I" | "U32" | "U33") => Self {
+                solar: true,
+                ..Self::default()
+            }, // Boktai 1, 2 and 3
+            _ => Self::default(),
+        }
+    }
+
+    fn any(self) -> bool {
+        self.rumble || self.gyro || self.solar
+    }
+}
+
+// The 4-bit GPIO port found on some cartridges, mapped over the ROM header at 0x080000C4.
+//
+// All offsets are relative to the start of the cartridge ROM.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct Gpio {
+    devices: GpioDevices,
+    data: u8,
+    direction: u8, // 1 = output from the GBA, 0 = input from the cartridge
+    readable: bool,
+    rumble: bool,
+    gyro_sample: u16,
+    gyro_clock: bool,
+    gyro_rotation: f32, // -1.0 to 1.0
+    #[serde(default)]
+    solar_level: u8, // 0 = dark, 255 = brightest
+    #[serde(default)]
+    solar_sample: u8,
+    #[serde(default)]
+    solar_counter: u8,
+    #[serde(default)]
+    solar_clock: bool,
+}
+
+impl Gpio {
+    const DATA_OFFSET: u32 = 0xC4;
+    const DIRECTION_OFFSET: u32 = 0xC6;
+    const CONTROL_OFFSET: u32 = 0xC8;
+    const END_OFFSET: u32 = 0xC9;
+
+    const PIN_MASK: u8 = 0b1111;
+
+    const GYRO_RESET_BIT_INDEX: usize = 0;
+    const GYRO_CLOCK_BIT_INDEX: usize = 1;
+    const GYRO_DATA_BIT_INDEX: usize = 2;
+    const RUMBLE_BIT_INDEX: usize = 3;
+
+    const SOLAR_CLOCK_BIT_INDEX: usize = 0;
+    const SOLAR_RESET_BIT_INDEX: usize = 1;
+    const SOLAR_CHIP_SELECT_BIT_INDEX: usize = 2;
+    const SOLAR_FLAG_BIT_INDEX: usize = 3;
+
+    // The gyro's reading while at rest, and how far it moves at full rotation speed.
+    const GYRO_CENTER: f32 = 0x6C0 as f32;
+    const GYRO_RANGE: f32 = 0x400 as f32;
+
+    pub(super) fn new(devices: GpioDevices) -> Self {
+        Self {
+            devices,
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn is_present(&self) -> bool {
+        self.devices.any()
+    }
+
+    pub(super) fn contains(offset: u32) -> bool {
+        (Self::DATA_OFFSET..=Self::END_OFFSET).contains(&offset)
+    }
+
+    pub(super) fn set_gyro_rotation(&mut self, rotation: f32) {
+        self.gyro_rotation = rotation.clamp(-1.0, 1.0);
+    }
+
+    pub(super) fn set_solar_level(&mut self, level: u8) {
+        self.solar_level = level;
+    }
+
+    // Returns `None` while the port is write-only, in which case the ROM shows through.
+    pub(super) fn read_byte(&self, offset: u32) -> Option<u8> {
+        if !self.readable {
+            return None;
+        }
+
+        match offset {
+            Self::DATA_OFFSET => Some(self.data),
+            Self::DIRECTION_OFFSET => Some(self.direction),
+            Self::CONTROL_OFFSET => Some(u8::from(self.readable)),
+            _ => Some(0),
+        }
+    }
+
+    // Returns the new rumble motor state, if it changed.
+    pub(super) fn write_byte(&mut self, value: u8, offset: u32) -> Option<bool> {
+        match offset {
+            Self::DATA_OFFSET => self.write_data(value),
+            Self::DIRECTION_OFFSET => {
+                self.direction = value & Self::PIN_MASK;
+                None
+            }
+            Self::CONTROL_OFFSET => {
+                self.readable = value.get_bit(0);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn write_data(&mut self, value: u8) -> Option<bool> {
+        // Only output pins are driven by the GBA, inputs keep whatever the devices put on them.
+        self.data = (self.data & !self.direction) | (value & self.direction & Self::PIN_MASK);
+
+        if self.devices.gyro {
+            self.step_gyro();
+        }
+
+        if self.devices.solar {
+            self.step_solar();
+        }
+
+        let rumble = self.devices.rumble && self.data.get_bit(Self::RUMBLE_BIT_INDEX);
+        if rumble != self.rumble {
+            self.rumble = rumble;
+            Some(rumble)
+        } else {
+            None
+        }
+    }
+
+    // The gyro latches a reading on reset, and shifts it out MSB first on each falling clock edge.
+    fn step_gyro(&mut self) {
+        if self.data.get_bit(Self::GYRO_RESET_BIT_INDEX) {
+            self.gyro_sample =
+                (Self::GYRO_CENTER + self.gyro_rotation * Self::GYRO_RANGE).round() as u16;
+        }
+
+        let clock = self.data.get_bit(Self::GYRO_CLOCK_BIT_INDEX);
+        if self.gyro_clock && !clock {
+            let bit = self.gyro_sample.get_bit(15);
+            self.gyro_sample <<= 1;
+            self.data = self.data.set_bit(Self::GYRO_DATA_BIT_INDEX, bit);
+        }
+        self.gyro_clock = clock;
+    }
+    // The solar sensor is read by resetting a counter, then clocking it up until it passes the
+    // current light level, at which point the flag pin goes high. More light makes that happen
+    // sooner.
+    fn step_solar(&mut self) {
+        // The sensor ignores the pins unless selected, which is active low.
+        if self.data.get_bit(Self::SOLAR_CHIP_SELECT_BIT_INDEX) {
+            return;
+        }
+
+        if self.data.get_bit(Self::SOLAR_RESET_BIT_INDEX) {
+            self.solar_counter = 0;
+            self.solar_sample = u8::MAX - self.solar_level;
+        }
+
+        let clock = self.data.get_bit(Self::SOLAR_CLOCK_BIT_INDEX);
+        if !self.solar_clock && clock {
+            self.solar_counter = self.solar_counter.wrapping_add(1);
+        }
+        self.solar_clock = clock;
+
+        self.data = self.data.set_bit(
+            Self::SOLAR_FLAG_BIT_INDEX,
+            self.solar_counter >= self.solar_sample,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::tests::build_game_test_cpu;
+    use crate::Cpu;
+
+    #[test]
+    fn gpio_rumble_and_gyro() {
+        let mut cpu = build_game_test_cpu(b"RZWE");
+
+        let rumble_states = Arc::new(Mutex::new(Vec::new()));
+        let callback_states = Arc::clone(&rumble_states);
+        cpu.set_rumble_callback(move |rumble| callback_states.lock().unwrap().push(rumble));
+
+        // The port is write-only until enabled, so the ROM shows through.
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x080000AC), 0x5A52);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x080000C4), 0);
+        cpu.bus.write_halfword_address_debug(1, 0x080000C8);
+        cpu.bus.write_halfword_address_debug(0b1011, 0x080000C6);
+
+        cpu.bus.write_halfword_address_debug(0b1000, 0x080000C4);
+        cpu.bus.write_halfword_address_debug(0b1000, 0x080000C4);
+        cpu.bus.write_halfword_address_debug(0b0000, 0x080000C4);
+        assert_eq!(*rumble_states.lock().unwrap(), [true, false]);
+
+        // Latch a reading at full rotation, then clock it out one bit at a time.
+        cpu.bus.cartridge.set_tilt(1.0, 0.0);
+        cpu.bus.write_halfword_address_debug(0b0011, 0x080000C4);
+        let mut sample = 0u16;
+        for _ in 0..16 {
+            cpu.bus.write_halfword_address_debug(0b0010, 0x080000C4);
+            cpu.bus.write_halfword_address_debug(0b0000, 0x080000C4);
+            let data = cpu.bus.read_halfword_address_debug(0x080000C4);
+            sample = (sample << 1) | u16::from(data.get_bit(2));
+        }
+        assert_eq!(sample, 0x6C0 + 0x400);
+    }
+
+    #[test]
+    fn solar_sensor() {
+        let mut cpu = build_game_test_cpu(b"U3IE");
+        cpu.bus.write_halfword_address_debug(1, 0x080000C8);
+        cpu.bus.write_halfword_address_debug(0b0111, 0x080000C6);
+
+        // Counts the clock pulses it takes for the flag to go high after a reset.
+        let read_light = |cpu: &mut Cpu| {
+            cpu.bus.write_halfword_address_debug(0b0010, 0x080000C4);
+            cpu.bus.write_halfword_address_debug(0b0000, 0x080000C4);
+
+            (0..=u8::MAX)
+                .find(|_| {
+                    cpu.bus.write_halfword_address_debug(0b0001, 0x080000C4);
+                    cpu.bus.write_halfword_address_debug(0b0000, 0x080000C4);
+                    cpu.bus.read_halfword_address_debug(0x080000C4).get_bit(3)
+                })
+                .unwrap()
+        };
+
+        assert_eq!(read_light(&mut cpu), 0xFE);
+        cpu.bus.cartridge.set_solar_level(0xC0);
+        assert_eq!(read_light(&mut cpu), 0x3E);
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::Read;
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+
+use super::compression;
+
+// Compressed Nintendo logo bitmap, which the BIOS checks before booting a cartridge.
+const NINTENDO_LOGO: [u8; 156] = [
+    0x24, 0xFF, 0xAE, 0x51, 0x69, 0x9A, 0xA2, 0x21, 0x3D, 0x84, 0x82, 0x0A, 0x84, 0xE4, 0x09, 0xAD,
+    0x11, 0x24, 0x8B, 0x98, 0xC0, 0x81, 0x7F, 0x21, 0xA3, 0x52, 0xBE, 0x19, 0x93, 0x09, 0xCE, 0x20,
+    0x10, 0x46, 0x4A, 0x4A, 0xF8, 0x27, 0x31, 0xEC, 0x58, 0xC7, 0xE8, 0x33, 0x82, 0xE3, 0xCE, 0xBF,
+    0x85, 0xF4, 0xDF, 0x94, 0xCE, 0x4B, 0x09, 0xC1, 0x94, 0x56, 0x8A, 0xC0, 0x13, 0x72, 0xA7, 0xFC,
+    0x9F, 0x84, 0x4D, 0x73, 0xA3, 0xCA, 0x9A, 0x61, 0x58, 0x97, 0xA3, 0x27, 0xFC, 0x03, 0x98, 0x76,
+    0x23, 0x1D, 0xC7, 0x61, 0x03, 0x04, 0xAE, 0x56, 0xBF, 0x38, 0x84, 0x00, 0x40, 0xA7, 0x0E, 0xFD,
+    0xFF, 0x52, 0xFE, 0x03, 0x6F, 0x95, 0x30, 0xF1, 0x97, 0xFB, 0xC0, 0x85, 0x60, 0xD6, 0x80, 0x25,
+    0xA9, 0x63, 0xBE, 0x03, 0x01, 0x4E, 0x38, 0xE2, 0xF9, 0xA2, 0x34, 0xFF, 0xBB, 0x3E, 0x03, 0x44,
+    0x78, 0x00, 0x90, 0xCB, 0x88, 0x11, 0x3A, 0x94, 0x65, 0xC0, 0x7C, 0x63, 0x87, 0xF0, 0x3C, 0xAF,
+    0xD6, 0x25, 0xE4, 0x8B, 0x38, 0x0A, 0xAC, 0x72, 0x21, 0xD4, 0xF8, 0x07,
+];
+
+const NINTENDO_LOGO_BYTE_RANGE: Range<usize> = 0x004..0x0A0;
+const GAME_TITLE_BYTE_RANGE: Range<usize> = 0x0A0..0x0AC;
+const GAME_CODE_BYTE_RANGE: Range<usize> = 0x0AC..0x0B0;
+const MAKER_CODE_BYTE_RANGE: Range<usize> = 0x0B0..0x0B2;
+const CHECKSUMMED_BYTE_RANGE: Range<usize> = 0x0A0..0x0BD;
+const SOFTWARE_VERSION_BYTE_INDEX: usize = 0x0BC;
+const HEADER_CHECKSUM_BYTE_INDEX: usize = 0x0BD;
+
+const HEADER_SIZE: usize = 0x0C0;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub game_code: String,
+    pub maker_code: String,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub header_checksum_valid: bool,
+    pub logo_valid: bool,
+}
+
+impl CartridgeHeader {
+    // Reads the header from a ROM file compressed in any of the ways `Cartridge::new` accepts.
+    // Uncompressed ROMs are only read as far as the end of the header.
+    pub fn read<T: Read>(mut input: T) -> Result<Self> {
+        let mut data = Vec::with_capacity(HEADER_SIZE);
+        input
+            .by_ref()
+            .take(HEADER_SIZE as u64)
+            .read_to_end(&mut data)?;
+
+        if compression::is_compressed(&data) {
+            input.read_to_end(&mut data)?;
+            data = compression::decompress(data)?.data;
+        }
+
+        if data.len() < HEADER_SIZE {
+            return Err(anyhow!("too short to hold a cartridge header"));
+        }
+
+        Ok(Self::parse(&data))
+    }
+
+    // ROMs too small to hold a full header are treated as if they were zero padded.
+    pub fn parse(rom: &[u8]) -> Self {
+        let mut header = [0; HEADER_SIZE];
+        let header_len = usize::min(rom.len(), HEADER_SIZE);
+        header[..header_len].copy_from_slice(&rom[..header_len]);
+
+        let header_string = |range: Range<usize>| -> String {
+            header[range]
+                .iter()
+                .copied()
+                .take_while(|val| *val != 0)
+                .map(char::from)
+                .collect()
+        };
+
+        let expected_checksum = header[CHECKSUMMED_BYTE_RANGE]
+            .iter()
+            .fold(0u8, |acc, val| acc.wrapping_sub(*val))
+            .wrapping_sub(0x19);
+        let header_checksum = header[HEADER_CHECKSUM_BYTE_INDEX];
+
+        Self {
+            title: header_string(GAME_TITLE_BYTE_RANGE),
+            game_code: header_string(GAME_CODE_BYTE_RANGE),
+            maker_code: header_string(MAKER_CODE_BYTE_RANGE),
+            version: header[SOFTWARE_VERSION_BYTE_INDEX],
+            header_checksum,
+            header_checksum_valid: header_checksum == expected_checksum,
+            logo_valid: header[NINTENDO_LOGO_BYTE_RANGE] == NINTENDO_LOGO,
+        }
+    }
+}
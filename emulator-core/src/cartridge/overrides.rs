@@ -0,0 +1,105 @@
+use phf::phf_map;
+use serde::{Deserialize, Serialize};
+
+use super::backup_types::BackupType;
+
+// Settings for games that detection gets wrong, or can't detect at all. Each field left as `None`
+// is detected from the ROM as usual.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameOverrides {
+    pub backup: Option<BackupType>, // flash includes the chip ID the game expects
+    pub rtc: Option<bool>,
+    // Address of a loop the game busy-waits in, usually for the next vblank.
+    pub idle_loop: Option<u32>,
+}
+
+impl GameOverrides {
+    // Looks the game up in the built in database.
+    pub fn lookup(game_code: &str) -> Self {
+        // Region agnostic, so only the first three characters of the game code are checked.
+        game_code
+            .get(..3)
+            .and_then(|code| COMPATIBILITY_DATABASE.get(code))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Fields set in `overrides` take precedence over the ones set here.
+    pub fn merge(self, overrides: GameOverrides) -> Self {
+        Self {
+            backup: overrides.backup.or(self.backup),
+            rtc: overrides.rtc.or(self.rtc),
+            idle_loop: overrides.idle_loop.or(self.idle_loop),
+        }
+    }
+}
+
+const FLASH_128KB: Option<BackupType> = Some(BackupType::Flash {
+    device_type: 0x09,
+    manufacturer: 0xC2,
+});
+const FLASH_64KB: Option<BackupType> = Some(BackupType::Flash {
+    device_type: 0x1B,
+    manufacturer: 0x32,
+});
+
+static COMPATIBILITY_DATABASE: phf::Map<&'static str, GameOverrides> = phf_map! {
+    // Pokemon Ruby, Sapphire and Emerald
+    "AXV" => GameOverrides { backup: FLASH_128KB, rtc: Some(true), idle_loop: None },
+    "AXP" => GameOverrides { backup: FLASH_128KB, rtc: Some(true), idle_loop: None },
+    "BPE" => GameOverrides { backup: FLASH_128KB, rtc: Some(true), idle_loop: None },
+    // Pokemon FireRed and LeafGreen
+    "BPR" => GameOverrides { backup: FLASH_128KB, rtc: None, idle_loop: None },
+    "BPG" => GameOverrides { backup: FLASH_128KB, rtc: None, idle_loop: None },
+    // Boktai 1, 2 and 3
+    "U3I" => GameOverrides { backup: Some(BackupType::Eeprom8K), rtc: Some(true), idle_loop: None },
+    "U32" => GameOverrides { backup: Some(BackupType::Eeprom8K), rtc: Some(true), idle_loop: None },
+    "U33" => GameOverrides { backup: Some(BackupType::Eeprom8K), rtc: Some(true), idle_loop: None },
+    // Sennen Kazoku
+    "BKA" => GameOverrides { backup: FLASH_128KB, rtc: Some(true), idle_loop: None },
+    // Rockman EXE 4.5: Real Operation
+    "BR4" => GameOverrides { backup: FLASH_64KB, rtc: Some(true), idle_loop: None },
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{Backup, Cartridge};
+    use crate::tests::build_test_rom;
+
+    #[test]
+    fn compatibility_database() {
+        let rom = build_test_rom(b"AXVE");
+
+        // Found in the database, despite the ROM not naming its backup type.
+        let cartridge = Cartridge::new(rom.as_slice(), None).unwrap();
+        let flash = BackupType::Flash {
+            device_type: 0x09,
+            manufacturer: 0xC2,
+        };
+        assert_eq!(cartridge.overrides().backup, Some(flash));
+        assert_eq!(cartridge.overrides().rtc, Some(true));
+        assert!(matches!(cartridge.get_backup(), Backup::Flash(_)));
+
+        // User overrides take precedence, leaving the rest of the entry in place.
+        let user_overrides = GameOverrides {
+            backup: Some(BackupType::Sram),
+            idle_loop: Some(0x0800_0100),
+            ..GameOverrides::default()
+        };
+        let cartridge = Cartridge::with_overrides(rom.as_slice(), None, user_overrides).unwrap();
+        assert_eq!(
+            cartridge.overrides(),
+            GameOverrides {
+                backup: Some(BackupType::Sram),
+                rtc: Some(true),
+                idle_loop: Some(0x0800_0100),
+            }
+        );
+        assert!(matches!(cartridge.get_backup(), Backup::Sram(_)));
+
+        // Unknown games get no overrides.
+        let cartridge = Cartridge::new(build_test_rom(b"ZZZE").as_slice(), None).unwrap();
+        assert_eq!(cartridge.overrides(), GameOverrides::default());
+    }
+}
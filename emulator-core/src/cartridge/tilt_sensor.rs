@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+// The two axis accelerometer in Yoshi Topsy-Turvy and Koro Koro Puzzle, mapped into the otherwise
+// unused SRAM region. A reading is started by writing 0x55 and then 0xAA, after which both axes
+// can be read back as 12-bit values.
+//
+// All offsets are relative to the start of the SRAM region.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct TiltSensor {
+    x: f32, // -1.0 to 1.0
+    y: f32,
+    sample_x: u16,
+    sample_y: u16,
+    armed: bool,
+}
+
+impl TiltSensor {
+    const START_OFFSET: u32 = 0x8000;
+    const LATCH_OFFSET: u32 = 0x8100;
+    const X_LOW_OFFSET: u32 = 0x8200;
+    const X_HIGH_OFFSET: u32 = 0x8300;
+    const Y_LOW_OFFSET: u32 = 0x8400;
+    const Y_HIGH_OFFSET: u32 = 0x8500;
+
+    const START_VALUE: u8 = 0x55;
+    const LATCH_VALUE: u8 = 0xAA;
+
+    // Set in the upper X byte once a reading is available.
+    const READY_FLAG: u8 = 0x80;
+
+    // The reading while held flat, and how far it moves when tilted all the way.
+    const CENTER: f32 = 0x3A0 as f32;
+    const RANGE: f32 = 0x200 as f32;
+
+    pub(super) fn detect(game_code: &str) -> bool {
+        // Yoshi Topsy-Turvy, Koro Koro Puzzle: Happy Panechu!
+        matches!(game_code.get(..3), Some("KYG" | "KHP"))
+    }
+
+    pub(super) fn set_tilt(&mut self, x: f32, y: f32) {
+        self.x = x.clamp(-1.0, 1.0);
+        self.y = y.clamp(-1.0, 1.0);
+    }
+
+    pub(super) fn read_byte(&self, offset: u32) -> Option<u8> {
+        match offset {
+            Self::X_LOW_OFFSET => Some(self.sample_x as u8),
+            Self::X_HIGH_OFFSET => Some(((self.sample_x >> 8) as u8 & 0xF) | Self::READY_FLAG),
+            Self::Y_LOW_OFFSET => Some(self.sample_y as u8),
+            Self::Y_HIGH_OFFSET => Some((self.sample_y >> 8) as u8 & 0xF),
+            _ => None,
+        }
+    }
+
+    // Returns whether the write was handled by the sensor.
+    pub(super) fn write_byte(&mut self, value: u8, offset: u32) -> bool {
+        match offset {
+            Self::START_OFFSET => self.armed = value == Self::START_VALUE,
+            Self::LATCH_OFFSET => {
+                if self.armed && value == Self::LATCH_VALUE {
+                    let sample = |axis: f32| (Self::CENTER + axis * Self::RANGE).round() as u16;
+                    self.sample_x = sample(self.x);
+                    self.sample_y = sample(self.y);
+                }
+                self.armed = false;
+            }
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::build_game_test_cpu;
+
+    #[test]
+    fn tilt_sensor() {
+        let mut cpu = build_game_test_cpu(b"KYGE");
+        cpu.bus.cartridge.set_tilt(-1.0, 0.5);
+
+        // Nothing is sampled unless the reading was started first.
+        cpu.bus.write_byte_address_debug(0xAA, 0x0E008100);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E008200), 0);
+
+        cpu.bus.write_byte_address_debug(0x55, 0x0E008000);
+        cpu.bus.write_byte_address_debug(0xAA, 0x0E008100);
+
+        let x = u16::from_le_bytes([
+            cpu.bus.read_byte_address_debug(0x0E008200),
+            cpu.bus.read_byte_address_debug(0x0E008300),
+        ]);
+        let y = u16::from_le_bytes([
+            cpu.bus.read_byte_address_debug(0x0E008400),
+            cpu.bus.read_byte_address_debug(0x0E008500),
+        ]);
+        assert_eq!(x, 0x8000 | (0x3A0 - 0x200));
+        assert_eq!(y, 0x3A0 + 0x100);
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use crate::CYCLES_PER_SECOND;
+
+// How emulated time is kept in step with the host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimingMode {
+    // Always emulate exactly `CYCLES_PER_SECOND` cycles per second of host time. The host's audio
+    // clock never quite matches this, so audio output will eventually under or overrun.
+    #[default]
+    Strict,
+    // Very slightly speed up or slow down emulation to keep the host's audio buffer at a steady
+    // fill level, trading exact timing for gap free audio.
+    HostAudioSync,
+}
+
+// Frontend facing emulation speed settings. Frontends present frames at their own fixed rate, and
+// use this to work out how much emulated time each of those frames should cover.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EmulationClock {
+    speed_multiplier: f64,
+    // Run as fast as the host allows, with no frame pacing. Used by benchmarks and fast-forward.
+    uncapped: bool,
+    timing_mode: TimingMode,
+    // Applied on top of the speed multiplier, only ever differs from 1 in host audio sync mode.
+    audio_sync_ratio: f64,
+}
+
+impl Default for EmulationClock {
+    fn default() -> Self {
+        Self {
+            speed_multiplier: 1.0,
+            uncapped: false,
+            timing_mode: TimingMode::Strict,
+            audio_sync_ratio: 1.0,
+        }
+    }
+}
+
+impl EmulationClock {
+    // The furthest host audio sync will move away from the configured speed. Small enough for the
+    // change in pitch to be inaudible.
+    const MAX_AUDIO_SYNC_ADJUSTMENT: f64 = 0.005;
+    // Anything outside of this is clamped, speeds come straight from frontend settings.
+    pub const MIN_SPEED_MULTIPLIER: f64 = 0.01;
+    pub const MAX_SPEED_MULTIPLIER: f64 = 100.0;
+
+    pub fn speed_multiplier(&self) -> f64 {
+        self.speed_multiplier
+    }
+
+    // NaN leaves the speed unchanged.
+    pub fn set_speed_multiplier(&mut self, speed_multiplier: f64) {
+        if !speed_multiplier.is_nan() {
+            self.speed_multiplier =
+                speed_multiplier.clamp(Self::MIN_SPEED_MULTIPLIER, Self::MAX_SPEED_MULTIPLIER);
+        }
+    }
+
+    pub fn is_uncapped(&self) -> bool {
+        self.uncapped
+    }
+
+    pub fn set_uncapped(&mut self, uncapped: bool) {
+        self.uncapped = uncapped;
+    }
+
+    pub fn timing_mode(&self) -> TimingMode {
+        self.timing_mode
+    }
+
+    pub fn set_timing_mode(&mut self, timing_mode: TimingMode) {
+        self.timing_mode = timing_mode;
+        self.audio_sync_ratio = 1.0;
+    }
+
+    // Informs the clock how many samples are currently queued for the host's audio output, and
+    // how many it should aim for. Ignored unless in host audio sync mode.
+    pub fn sync_to_audio_buffer(&mut self, buffered_samples: usize, target_samples: usize) {
+        if self.timing_mode != TimingMode::HostAudioSync || target_samples == 0 {
+            return;
+        }
+
+        // Run faster while the buffer is draining, and slower while it's filling up.
+        let fill = (buffered_samples as f64 / target_samples as f64).min(2.0);
+        self.audio_sync_ratio = 1.0 + (1.0 - fill) * Self::MAX_AUDIO_SYNC_ADJUSTMENT;
+    }
+
+    // The number of cycles to emulate for each frame presented by a frontend running at
+    // `frames_per_second`.
+    pub fn cycles_per_frame(&self, frames_per_second: u32) -> u64 {
+        let cycles = CYCLES_PER_SECOND as f64 * self.speed_multiplier * self.audio_sync_ratio
+            / f64::from(frames_per_second);
+        (cycles.round() as u64).max(1)
+    }
+
+    // How long each frame should take in real time, or `None` if frames shouldn't be paced at all.
+    pub fn frame_duration(&self, frames_per_second: u32) -> Option<Duration> {
+        (!self.uncapped).then(|| Duration::from_secs(1) / frames_per_second)
+    }
+
+    // How long emulating `cycles` should take in real time at the current speed, or `None` if
+    // emulation shouldn't be paced at all. For frontends that run a fixed amount of emulated time
+    // at once, rather than presenting at their own rate.
+    pub fn host_duration(&self, cycles: u64) -> Option<Duration> {
+        (!self.uncapped).then(|| {
+            let cycles_per_second =
+                CYCLES_PER_SECOND as f64 * self.speed_multiplier * self.audio_sync_ratio;
+            Duration::from_secs_f64(cycles as f64 / cycles_per_second)
+        })
+    }
+}
@@ -1,18 +1,41 @@
 pub mod arm;
+mod block_transfer;
+mod call_stack;
+mod idle_loop;
+#[cfg(test)]
+mod single_step_tests;
+mod swi_stats;
 pub mod thumb;
+#[cfg(test)]
+mod timing_audit;
 
+use std::collections::HashSet;
 use std::fmt::Display;
+use std::sync::Arc;
 use std::{fmt::Debug, ops::RangeInclusive};
 
-use crate::bus::Bus;
+use crate::apu::AudioRingBuffer;
+#[cfg(any(test, feature = "flat-memory"))]
+use crate::bus::FlatMemory;
+use crate::bus::{Bus, CoverageSummary, DebugOutputLevel, PerfCounters, PowerState};
 use crate::cartridge::Cartridge;
+use crate::clock::{EmulationClock, TimingMode};
 use crate::cpu::arm::decode_arm;
+use crate::error::{EmulatorError, ErrorPolicy};
+use crate::lcd::FrameBuffer;
+use crate::serial::LinkTransportHandle;
 use crate::BitManipulation;
+use serde::{Deserialize, Serialize};
 
-use self::arm::ArmInstruction;
-use self::thumb::{ThumbInstruction, ThumbInstructionType};
+use self::arm::{ArmInstruction, ArmInstructionType};
+use self::call_stack::CallStack;
+use self::idle_loop::IdleLoopCandidate;
+use self::thumb::{decode_thumb, ThumbInstruction, ThumbInstructionType};
 
-#[derive(Clone, Default)]
+pub use call_stack::{Frame, FrameKind};
+pub use swi_stats::{bios_function_name, SwiCount, SwiStats};
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 struct ModeRegisters {
     r0: u32,
     r1: u32,
@@ -33,7 +56,7 @@ struct ModeRegisters {
     spsr: u32,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Cpu {
     current_registers: ModeRegisters,
     r0: u32,
@@ -77,8 +100,65 @@ pub struct Cpu {
     prefetch_opcode: u32,
     pre_decode_arm: ArmInstruction,
     pre_decode_thumb: ThumbInstruction,
+    // Debugger state isn't part of save states.
+    #[serde(skip)]
+    breakpoints: Vec<u32>,
+    #[serde(skip)]
+    resuming_from_breakpoint: Option<u32>, // breakpoint we last stopped at, skipped once on resume
+    // Speed is a frontend setting, so isn't part of save states either.
+    #[serde(skip)]
+    clock: EmulationClock,
+    #[serde(skip)]
+    config: CpuConfig,
+    #[serde(skip)]
+    logged_invalid_opcodes: HashSet<u32>, // at most `MAX_LOGGED_INVALID_OPCODES`
+    #[serde(skip)]
+    pending_error: Option<EmulatorError>, // reported at the end of the current step
+    // Only meaningful for the execution it was built from, so it starts over on state loads.
+    #[serde(skip)]
+    call_stack: CallStack,
+    #[serde(skip)]
+    swi_hook: Option<SwiHook>,
+    #[serde(skip)]
+    swi_stats: SwiStats,
+    #[serde(skip)]
+    idle_loop: Option<IdleLoopCandidate>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepEvent {
+    // Execution stopped before the instruction at `address`. Stepping again executes it.
+    BreakpointHit { address: u32 },
+    SwiExecuted { comment: u32 },
+    // A BKPT at `address` was executed, as a no-op. Debuggers pause here, like mGBA does,
+    // rather than taking the prefetch abort hardware without a debugger attached would.
+    BkptHit { address: u32, comment: u16 },
+    // The instruction at `address` could not be decoded, and the Undefined exception was taken.
+    InvalidOpcode { address: u32, opcode: u32 },
+    // The LCD entered vblank.
+    FrameComplete,
+    // Something went wrong while executing, only reported with `ErrorPolicy::Trap`.
+    Error(EmulatorError),
 }
 
+// A BIOS call about to be made, as seen by a hook registered with `Cpu::on_swi`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwiCall {
+    pub address: u32, // of the SWI instruction
+    pub number: u32,
+    pub arguments: [u32; 4], // r0-r3
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwiAction {
+    // Take the SWI exception and let the BIOS handle the call, as usual.
+    RunBios,
+    // Skip the BIOS entirely, continuing after the SWI with r0-r3 set to these values.
+    Return([u32; 4]),
+}
+
+pub type SwiHook = Arc<dyn Fn(&mut Cpu, SwiCall) -> SwiAction + Send + Sync>;
+
 #[derive(Clone, Copy, Debug)]
 enum ExceptionType {
     Reset,
@@ -91,8 +171,49 @@ enum ExceptionType {
     FastInterruptRequest,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BootMode {
+    // Start executing from the BIOS reset vector, as on hardware.
+    #[default]
+    Bios,
+    // Jump straight to the cartridge entry point, with registers and IO set up the way the BIOS
+    // leaves them after the boot animation.
+    SkipBios,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuConfig {
+    // Covers both instructions the emulator doesn't support and opcodes that don't decode at
+    // all. The latter take the Undefined exception as on hardware, except with
+    // `ErrorPolicy::Ignore`, where they're executed as no-ops instead. Games that hit one
+    // usually only do so because of a gap in the emulator, so that may get them further.
+    pub error_policy: ErrorPolicy,
+    // Fast-forwards through loops that wait without side effects. Loops listed in the
+    // compatibility database are skipped too, however long they are.
+    pub skip_idle_loops: bool,
+    // Runs EWRAM without waitstates, whatever the game sets through the internal memory control
+    // register. A speed hack, but one that rarely breaks anything.
+    pub fast_ewram: bool,
+}
+
 impl Cpu {
+    // AGBPrintFlush, which isn't a real BIOS function.
+    const AGB_PRINT_FLUSH_SWI: u32 = 0xFA;
+    const MAX_LOGGED_INVALID_OPCODES: usize = 64;
+
     pub fn new(cartridge: Cartridge) -> Self {
+        Self::with_boot_mode(cartridge, BootMode::Bios)
+    }
+
+    pub fn with_boot_mode(cartridge: Cartridge, boot_mode: BootMode) -> Self {
+        const BIOS_ENTRY_POINT: u32 = 0x00000000;
+        const ROM_ENTRY_POINT: u32 = 0x08000000;
+
+        // Stack pointers as initialized by the BIOS.
+        const USER_STACK_POINTER: u32 = 0x03007F00;
+        const IRQ_STACK_POINTER: u32 = 0x03007FA0;
+        const SUPERVISOR_STACK_POINTER: u32 = 0x03007FE0;
+
         // treated as SPSR in system and user mode
         let cpsr = Self::SYSTEM_MODE_BITS;
 
@@ -104,9 +225,18 @@ impl Cpu {
             instruction_type: ThumbInstructionType::Invalid { opcode: 0xDEAD },
         };
 
-        let pre_decode_arm = decode_arm(bus.fetch_arm_opcode(0));
-        let prefetch_opcode = bus.fetch_arm_opcode(4);
-        current_registers.r15 = 8;
+        let (entry_point, r13_irq, r13_svc) = match boot_mode {
+            BootMode::Bios => (BIOS_ENTRY_POINT, 0, 0),
+            BootMode::SkipBios => {
+                bus.skip_bios();
+                current_registers.r13 = USER_STACK_POINTER;
+                (ROM_ENTRY_POINT, IRQ_STACK_POINTER, SUPERVISOR_STACK_POINTER)
+            }
+        };
+
+        let pre_decode_arm = decode_arm(bus.fetch_arm_opcode(entry_point));
+        let prefetch_opcode = bus.fetch_arm_opcode(entry_point + 4);
+        current_registers.r15 = entry_point + 8;
 
         Self {
             current_registers,
@@ -134,13 +264,13 @@ impl Cpu {
             r13_fiq: 0,
             r14_fiq: 0,
             spsr_fiq: 0,
-            r13_svc: 0,
+            r13_svc,
             r14_svc: 0,
             spsr_svc: 0,
             r13_abt: 0,
             r14_abt: 0,
             spsr_abt: 0,
-            r13_irq: 0,
+            r13_irq,
             r14_irq: 0,
             spsr_irq: 0,
             r13_und: 0,
@@ -151,7 +281,74 @@ impl Cpu {
             prefetch_opcode,
             pre_decode_arm,
             pre_decode_thumb,
+            breakpoints: Vec::new(),
+            resuming_from_breakpoint: None,
+            clock: EmulationClock::default(),
+            config: CpuConfig::default(),
+            logged_invalid_opcodes: HashSet::new(),
+            pending_error: None,
+            call_stack: CallStack::default(),
+            swi_hook: None,
+            swi_stats: SwiStats::default(),
+            idle_loop: None,
+        }
+    }
+
+    // Boots a multiboot image, as homebrew built for link-loading into EWRAM is. Rather than
+    // running the BIOS side of the transfer, this recreates what the BIOS leaves behind once a
+    // normal mode transfer from a host finishes: the image at the start of EWRAM, the boot mode
+    // and client number filled into its header, and execution at its entry point past the
+    // header. Games that go on talking to the host over the link cable won't get far without one.
+    pub fn with_multiboot_image(cartridge: Cartridge, image: &[u8]) -> anyhow::Result<Self> {
+        const MULTIBOOT_BASE: u32 = 0x02000000;
+        const MULTIBOOT_ENTRY_POINT: u32 = MULTIBOOT_BASE + 0xC0;
+        const MAX_IMAGE_SIZE: usize = 0x40000;
+        // Header fields written by the BIOS, not the image.
+        const BOOT_MODE_ADDRESS: u32 = MULTIBOOT_BASE + 0xC4;
+        const CLIENT_NUMBER_ADDRESS: u32 = MULTIBOOT_BASE + 0xC5;
+        const NORMAL_BOOT_MODE: u8 = 2;
+
+        if image.len() > MAX_IMAGE_SIZE {
+            return Err(anyhow::anyhow!(
+                "multiboot image is {} bytes, more than fits in EWRAM",
+                image.len()
+            ));
+        }
+        if image.len() < (MULTIBOOT_ENTRY_POINT - MULTIBOOT_BASE) as usize {
+            return Err(anyhow::anyhow!(
+                "multiboot image is too small to have a header"
+            ));
+        }
+
+        let mut cpu = Self::with_boot_mode(cartridge, BootMode::SkipBios);
+        for (address, &byte) in (MULTIBOOT_BASE..).zip(image) {
+            cpu.bus.write_byte_address_debug(byte, address);
         }
+        cpu.bus
+            .write_byte_address_debug(NORMAL_BOOT_MODE, BOOT_MODE_ADDRESS);
+        cpu.bus.write_byte_address_debug(1, CLIENT_NUMBER_ADDRESS);
+
+        cpu.pre_decode_arm = decode_arm(cpu.bus.fetch_arm_opcode(MULTIBOOT_ENTRY_POINT));
+        cpu.prefetch_opcode = cpu.bus.fetch_arm_opcode(MULTIBOOT_ENTRY_POINT + 4);
+        cpu.write_register(MULTIBOOT_ENTRY_POINT + 8, Register::R15);
+
+        Ok(cpu)
+    }
+}
+
+impl Cpu {
+    // Runs against a flat RAM instead of the regular memory map, for testing single instructions
+    // in isolation and for fuzzing. Registers and the pipeline are left for the caller to set up.
+    #[cfg(any(test, feature = "flat-memory"))]
+    pub fn with_flat_memory(flat_memory: FlatMemory) -> Self {
+        // Just enough of a ROM for the header to parse.
+        const BLANK_ROM: [u8; 0xC0] = [0; 0xC0];
+
+        let cartridge = Cartridge::new(BLANK_ROM.as_slice(), None).unwrap();
+        let mut cpu = Self::new(cartridge);
+        cpu.bus.flat_memory = Some(flat_memory);
+
+        cpu
     }
 }
 
@@ -199,7 +396,7 @@ impl Display for Cpu {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum CpuMode {
     User,
     Fiq,
@@ -207,10 +404,11 @@ pub enum CpuMode {
     Supervisor,
     Abort,
     Undefined,
+    #[default]
     System,
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Register {
     R0,
     R1,
@@ -281,7 +479,7 @@ impl Display for Register {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum InstructionCondition {
     Equal,
     NotEqual,
@@ -324,13 +522,14 @@ impl Display for InstructionCondition {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub enum InstructionSet {
+    #[default]
     Arm,
     Thumb,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum ShiftType {
     Lsl,
     Lsr,
@@ -412,242 +611,251 @@ impl Cpu {
                 // - store all registers from current mode registers into mode-agnostic register storage.
                 // - load all registers from new mode mode-agnostic register storage into current mode registers
                 if old_mode != new_mode {
-                    match old_mode {
-                        CpuMode::System | CpuMode::User => {
-                            self.r0 = self.current_registers.r0;
-                            self.r1 = self.current_registers.r1;
-                            self.r2 = self.current_registers.r2;
-                            self.r3 = self.current_registers.r3;
-                            self.r4 = self.current_registers.r4;
-                            self.r5 = self.current_registers.r5;
-                            self.r6 = self.current_registers.r6;
-                            self.r7 = self.current_registers.r7;
-                            self.r8 = self.current_registers.r8;
-                            self.r9 = self.current_registers.r9;
-                            self.r10 = self.current_registers.r10;
-                            self.r11 = self.current_registers.r11;
-                            self.r12 = self.current_registers.r12;
-                            self.r13 = self.current_registers.r13;
-                            self.r14 = self.current_registers.r14;
-                            self.r15 = self.current_registers.r15;
-                        }
-                        CpuMode::Fiq => {
-                            self.r0 = self.current_registers.r0;
-                            self.r1 = self.current_registers.r1;
-                            self.r2 = self.current_registers.r2;
-                            self.r3 = self.current_registers.r3;
-                            self.r4 = self.current_registers.r4;
-                            self.r5 = self.current_registers.r5;
-                            self.r6 = self.current_registers.r6;
-                            self.r7 = self.current_registers.r7;
-                            self.r8_fiq = self.current_registers.r8;
-                            self.r9_fiq = self.current_registers.r9;
-                            self.r10_fiq = self.current_registers.r10;
-                            self.r11_fiq = self.current_registers.r11;
-                            self.r12_fiq = self.current_registers.r12;
-                            self.r13_fiq = self.current_registers.r13;
-                            self.r14_fiq = self.current_registers.r14;
-                            self.r15 = self.current_registers.r15;
-                            self.spsr_fiq = self.current_registers.spsr;
-                        }
-                        CpuMode::Supervisor => {
-                            self.r0 = self.current_registers.r0;
-                            self.r1 = self.current_registers.r1;
-                            self.r2 = self.current_registers.r2;
-                            self.r3 = self.current_registers.r3;
-                            self.r4 = self.current_registers.r4;
-                            self.r5 = self.current_registers.r5;
-                            self.r6 = self.current_registers.r6;
-                            self.r7 = self.current_registers.r7;
-                            self.r8 = self.current_registers.r8;
-                            self.r9 = self.current_registers.r9;
-                            self.r10 = self.current_registers.r10;
-                            self.r11 = self.current_registers.r11;
-                            self.r12 = self.current_registers.r12;
-                            self.r13_svc = self.current_registers.r13;
-                            self.r14_svc = self.current_registers.r14;
-                            self.r15 = self.current_registers.r15;
-                            self.spsr_svc = self.current_registers.spsr;
-                        }
-                        CpuMode::Abort => {
-                            self.r0 = self.current_registers.r0;
-                            self.r1 = self.current_registers.r1;
-                            self.r2 = self.current_registers.r2;
-                            self.r3 = self.current_registers.r3;
-                            self.r4 = self.current_registers.r4;
-                            self.r5 = self.current_registers.r5;
-                            self.r6 = self.current_registers.r6;
-                            self.r7 = self.current_registers.r7;
-                            self.r8 = self.current_registers.r8;
-                            self.r9 = self.current_registers.r9;
-                            self.r10 = self.current_registers.r10;
-                            self.r11 = self.current_registers.r11;
-                            self.r12 = self.current_registers.r12;
-                            self.r13_abt = self.current_registers.r13;
-                            self.r14_abt = self.current_registers.r14;
-                            self.r15 = self.current_registers.r15;
-                            self.spsr_abt = self.current_registers.spsr;
-                        }
-                        CpuMode::Irq => {
-                            self.r0 = self.current_registers.r0;
-                            self.r1 = self.current_registers.r1;
-                            self.r2 = self.current_registers.r2;
-                            self.r3 = self.current_registers.r3;
-                            self.r4 = self.current_registers.r4;
-                            self.r5 = self.current_registers.r5;
-                            self.r6 = self.current_registers.r6;
-                            self.r7 = self.current_registers.r7;
-                            self.r8 = self.current_registers.r8;
-                            self.r9 = self.current_registers.r9;
-                            self.r10 = self.current_registers.r10;
-                            self.r11 = self.current_registers.r11;
-                            self.r12 = self.current_registers.r12;
-                            self.r13_irq = self.current_registers.r13;
-                            self.r14_irq = self.current_registers.r14;
-                            self.r15 = self.current_registers.r15;
-                            self.spsr_irq = self.current_registers.spsr;
-                        }
-                        CpuMode::Undefined => {
-                            self.r0 = self.current_registers.r0;
-                            self.r1 = self.current_registers.r1;
-                            self.r2 = self.current_registers.r2;
-                            self.r3 = self.current_registers.r3;
-                            self.r4 = self.current_registers.r4;
-                            self.r5 = self.current_registers.r5;
-                            self.r6 = self.current_registers.r6;
-                            self.r7 = self.current_registers.r7;
-                            self.r8 = self.current_registers.r8;
-                            self.r9 = self.current_registers.r9;
-                            self.r10 = self.current_registers.r10;
-                            self.r11 = self.current_registers.r11;
-                            self.r12 = self.current_registers.r12;
-                            self.r13_und = self.current_registers.r13;
-                            self.r14_und = self.current_registers.r14;
-                            self.r15 = self.current_registers.r15;
-                            self.spsr_und = self.current_registers.spsr;
-                        }
-                    }
-
-                    match new_mode {
-                        CpuMode::User | CpuMode::System => {
-                            self.current_registers.r0 = self.r0;
-                            self.current_registers.r1 = self.r1;
-                            self.current_registers.r2 = self.r2;
-                            self.current_registers.r3 = self.r3;
-                            self.current_registers.r4 = self.r4;
-                            self.current_registers.r5 = self.r5;
-                            self.current_registers.r6 = self.r6;
-                            self.current_registers.r7 = self.r7;
-                            self.current_registers.r8 = self.r8;
-                            self.current_registers.r9 = self.r9;
-                            self.current_registers.r10 = self.r10;
-                            self.current_registers.r11 = self.r11;
-                            self.current_registers.r12 = self.r12;
-                            self.current_registers.r13 = self.r13;
-                            self.current_registers.r14 = self.r14;
-                            self.current_registers.r15 = self.r15;
-                        }
-                        CpuMode::Fiq => {
-                            self.current_registers.r0 = self.r0;
-                            self.current_registers.r1 = self.r1;
-                            self.current_registers.r2 = self.r2;
-                            self.current_registers.r3 = self.r3;
-                            self.current_registers.r4 = self.r4;
-                            self.current_registers.r5 = self.r5;
-                            self.current_registers.r6 = self.r6;
-                            self.current_registers.r7 = self.r7;
-                            self.current_registers.r8 = self.r8_fiq;
-                            self.current_registers.r9 = self.r9_fiq;
-                            self.current_registers.r10 = self.r10_fiq;
-                            self.current_registers.r11 = self.r11_fiq;
-                            self.current_registers.r12 = self.r12_fiq;
-                            self.current_registers.r13 = self.r13_fiq;
-                            self.current_registers.r14 = self.r14_fiq;
-                            self.current_registers.r15 = self.r15;
-                            self.current_registers.spsr = self.spsr_fiq;
-                        }
-                        CpuMode::Supervisor => {
-                            self.current_registers.r0 = self.r0;
-                            self.current_registers.r1 = self.r1;
-                            self.current_registers.r2 = self.r2;
-                            self.current_registers.r3 = self.r3;
-                            self.current_registers.r4 = self.r4;
-                            self.current_registers.r5 = self.r5;
-                            self.current_registers.r6 = self.r6;
-                            self.current_registers.r7 = self.r7;
-                            self.current_registers.r8 = self.r8;
-                            self.current_registers.r9 = self.r9;
-                            self.current_registers.r10 = self.r10;
-                            self.current_registers.r11 = self.r11;
-                            self.current_registers.r12 = self.r12;
-                            self.current_registers.r13 = self.r13_svc;
-                            self.current_registers.r14 = self.r14_svc;
-                            self.current_registers.r15 = self.r15;
-                            self.current_registers.spsr = self.spsr_svc;
-                        }
-                        CpuMode::Abort => {
-                            self.current_registers.r0 = self.r0;
-                            self.current_registers.r1 = self.r1;
-                            self.current_registers.r2 = self.r2;
-                            self.current_registers.r3 = self.r3;
-                            self.current_registers.r4 = self.r4;
-                            self.current_registers.r5 = self.r5;
-                            self.current_registers.r6 = self.r6;
-                            self.current_registers.r7 = self.r7;
-                            self.current_registers.r8 = self.r8;
-                            self.current_registers.r9 = self.r9;
-                            self.current_registers.r10 = self.r10;
-                            self.current_registers.r11 = self.r11;
-                            self.current_registers.r12 = self.r12;
-                            self.current_registers.r13 = self.r13_abt;
-                            self.current_registers.r14 = self.r14_abt;
-                            self.current_registers.r15 = self.r15;
-                            self.current_registers.spsr = self.spsr_abt;
-                        }
-                        CpuMode::Irq => {
-                            self.current_registers.r0 = self.r0;
-                            self.current_registers.r1 = self.r1;
-                            self.current_registers.r2 = self.r2;
-                            self.current_registers.r3 = self.r3;
-                            self.current_registers.r4 = self.r4;
-                            self.current_registers.r5 = self.r5;
-                            self.current_registers.r6 = self.r6;
-                            self.current_registers.r7 = self.r7;
-                            self.current_registers.r8 = self.r8;
-                            self.current_registers.r9 = self.r9;
-                            self.current_registers.r10 = self.r10;
-                            self.current_registers.r11 = self.r11;
-                            self.current_registers.r12 = self.r12;
-                            self.current_registers.r13 = self.r13_irq;
-                            self.current_registers.r14 = self.r14_irq;
-                            self.current_registers.r15 = self.r15;
-                            self.current_registers.spsr = self.spsr_irq;
-                        }
-                        CpuMode::Undefined => {
-                            self.current_registers.r0 = self.r0;
-                            self.current_registers.r1 = self.r1;
-                            self.current_registers.r2 = self.r2;
-                            self.current_registers.r3 = self.r3;
-                            self.current_registers.r4 = self.r4;
-                            self.current_registers.r5 = self.r5;
-                            self.current_registers.r6 = self.r6;
-                            self.current_registers.r7 = self.r7;
-                            self.current_registers.r8 = self.r8;
-                            self.current_registers.r9 = self.r9;
-                            self.current_registers.r10 = self.r10;
-                            self.current_registers.r11 = self.r11;
-                            self.current_registers.r12 = self.r12;
-                            self.current_registers.r13 = self.r13_und;
-                            self.current_registers.r14 = self.r14_und;
-                            self.current_registers.r15 = self.r15;
-                            self.current_registers.spsr = self.spsr_und;
-                        }
-                    }
+                    self.store_mode_registers(old_mode);
+                    self.load_mode_registers(new_mode);
                 }
             }
         }
     }
 
+    // Stores the registers of `mode` from the current registers into their mode-agnostic storage.
+    fn store_mode_registers(&mut self, mode: CpuMode) {
+        match mode {
+            CpuMode::System | CpuMode::User => {
+                self.r0 = self.current_registers.r0;
+                self.r1 = self.current_registers.r1;
+                self.r2 = self.current_registers.r2;
+                self.r3 = self.current_registers.r3;
+                self.r4 = self.current_registers.r4;
+                self.r5 = self.current_registers.r5;
+                self.r6 = self.current_registers.r6;
+                self.r7 = self.current_registers.r7;
+                self.r8 = self.current_registers.r8;
+                self.r9 = self.current_registers.r9;
+                self.r10 = self.current_registers.r10;
+                self.r11 = self.current_registers.r11;
+                self.r12 = self.current_registers.r12;
+                self.r13 = self.current_registers.r13;
+                self.r14 = self.current_registers.r14;
+                self.r15 = self.current_registers.r15;
+            }
+            CpuMode::Fiq => {
+                self.r0 = self.current_registers.r0;
+                self.r1 = self.current_registers.r1;
+                self.r2 = self.current_registers.r2;
+                self.r3 = self.current_registers.r3;
+                self.r4 = self.current_registers.r4;
+                self.r5 = self.current_registers.r5;
+                self.r6 = self.current_registers.r6;
+                self.r7 = self.current_registers.r7;
+                self.r8_fiq = self.current_registers.r8;
+                self.r9_fiq = self.current_registers.r9;
+                self.r10_fiq = self.current_registers.r10;
+                self.r11_fiq = self.current_registers.r11;
+                self.r12_fiq = self.current_registers.r12;
+                self.r13_fiq = self.current_registers.r13;
+                self.r14_fiq = self.current_registers.r14;
+                self.r15 = self.current_registers.r15;
+                self.spsr_fiq = self.current_registers.spsr;
+            }
+            CpuMode::Supervisor => {
+                self.r0 = self.current_registers.r0;
+                self.r1 = self.current_registers.r1;
+                self.r2 = self.current_registers.r2;
+                self.r3 = self.current_registers.r3;
+                self.r4 = self.current_registers.r4;
+                self.r5 = self.current_registers.r5;
+                self.r6 = self.current_registers.r6;
+                self.r7 = self.current_registers.r7;
+                self.r8 = self.current_registers.r8;
+                self.r9 = self.current_registers.r9;
+                self.r10 = self.current_registers.r10;
+                self.r11 = self.current_registers.r11;
+                self.r12 = self.current_registers.r12;
+                self.r13_svc = self.current_registers.r13;
+                self.r14_svc = self.current_registers.r14;
+                self.r15 = self.current_registers.r15;
+                self.spsr_svc = self.current_registers.spsr;
+            }
+            CpuMode::Abort => {
+                self.r0 = self.current_registers.r0;
+                self.r1 = self.current_registers.r1;
+                self.r2 = self.current_registers.r2;
+                self.r3 = self.current_registers.r3;
+                self.r4 = self.current_registers.r4;
+                self.r5 = self.current_registers.r5;
+                self.r6 = self.current_registers.r6;
+                self.r7 = self.current_registers.r7;
+                self.r8 = self.current_registers.r8;
+                self.r9 = self.current_registers.r9;
+                self.r10 = self.current_registers.r10;
+                self.r11 = self.current_registers.r11;
+                self.r12 = self.current_registers.r12;
+                self.r13_abt = self.current_registers.r13;
+                self.r14_abt = self.current_registers.r14;
+                self.r15 = self.current_registers.r15;
+                self.spsr_abt = self.current_registers.spsr;
+            }
+            CpuMode::Irq => {
+                self.r0 = self.current_registers.r0;
+                self.r1 = self.current_registers.r1;
+                self.r2 = self.current_registers.r2;
+                self.r3 = self.current_registers.r3;
+                self.r4 = self.current_registers.r4;
+                self.r5 = self.current_registers.r5;
+                self.r6 = self.current_registers.r6;
+                self.r7 = self.current_registers.r7;
+                self.r8 = self.current_registers.r8;
+                self.r9 = self.current_registers.r9;
+                self.r10 = self.current_registers.r10;
+                self.r11 = self.current_registers.r11;
+                self.r12 = self.current_registers.r12;
+                self.r13_irq = self.current_registers.r13;
+                self.r14_irq = self.current_registers.r14;
+                self.r15 = self.current_registers.r15;
+                self.spsr_irq = self.current_registers.spsr;
+            }
+            CpuMode::Undefined => {
+                self.r0 = self.current_registers.r0;
+                self.r1 = self.current_registers.r1;
+                self.r2 = self.current_registers.r2;
+                self.r3 = self.current_registers.r3;
+                self.r4 = self.current_registers.r4;
+                self.r5 = self.current_registers.r5;
+                self.r6 = self.current_registers.r6;
+                self.r7 = self.current_registers.r7;
+                self.r8 = self.current_registers.r8;
+                self.r9 = self.current_registers.r9;
+                self.r10 = self.current_registers.r10;
+                self.r11 = self.current_registers.r11;
+                self.r12 = self.current_registers.r12;
+                self.r13_und = self.current_registers.r13;
+                self.r14_und = self.current_registers.r14;
+                self.r15 = self.current_registers.r15;
+                self.spsr_und = self.current_registers.spsr;
+            }
+        }
+    }
+
+    // Loads the registers of `mode` from their mode-agnostic storage into the current registers.
+    fn load_mode_registers(&mut self, mode: CpuMode) {
+        match mode {
+            CpuMode::User | CpuMode::System => {
+                self.current_registers.r0 = self.r0;
+                self.current_registers.r1 = self.r1;
+                self.current_registers.r2 = self.r2;
+                self.current_registers.r3 = self.r3;
+                self.current_registers.r4 = self.r4;
+                self.current_registers.r5 = self.r5;
+                self.current_registers.r6 = self.r6;
+                self.current_registers.r7 = self.r7;
+                self.current_registers.r8 = self.r8;
+                self.current_registers.r9 = self.r9;
+                self.current_registers.r10 = self.r10;
+                self.current_registers.r11 = self.r11;
+                self.current_registers.r12 = self.r12;
+                self.current_registers.r13 = self.r13;
+                self.current_registers.r14 = self.r14;
+                self.current_registers.r15 = self.r15;
+            }
+            CpuMode::Fiq => {
+                self.current_registers.r0 = self.r0;
+                self.current_registers.r1 = self.r1;
+                self.current_registers.r2 = self.r2;
+                self.current_registers.r3 = self.r3;
+                self.current_registers.r4 = self.r4;
+                self.current_registers.r5 = self.r5;
+                self.current_registers.r6 = self.r6;
+                self.current_registers.r7 = self.r7;
+                self.current_registers.r8 = self.r8_fiq;
+                self.current_registers.r9 = self.r9_fiq;
+                self.current_registers.r10 = self.r10_fiq;
+                self.current_registers.r11 = self.r11_fiq;
+                self.current_registers.r12 = self.r12_fiq;
+                self.current_registers.r13 = self.r13_fiq;
+                self.current_registers.r14 = self.r14_fiq;
+                self.current_registers.r15 = self.r15;
+                self.current_registers.spsr = self.spsr_fiq;
+            }
+            CpuMode::Supervisor => {
+                self.current_registers.r0 = self.r0;
+                self.current_registers.r1 = self.r1;
+                self.current_registers.r2 = self.r2;
+                self.current_registers.r3 = self.r3;
+                self.current_registers.r4 = self.r4;
+                self.current_registers.r5 = self.r5;
+                self.current_registers.r6 = self.r6;
+                self.current_registers.r7 = self.r7;
+                self.current_registers.r8 = self.r8;
+                self.current_registers.r9 = self.r9;
+                self.current_registers.r10 = self.r10;
+                self.current_registers.r11 = self.r11;
+                self.current_registers.r12 = self.r12;
+                self.current_registers.r13 = self.r13_svc;
+                self.current_registers.r14 = self.r14_svc;
+                self.current_registers.r15 = self.r15;
+                self.current_registers.spsr = self.spsr_svc;
+            }
+            CpuMode::Abort => {
+                self.current_registers.r0 = self.r0;
+                self.current_registers.r1 = self.r1;
+                self.current_registers.r2 = self.r2;
+                self.current_registers.r3 = self.r3;
+                self.current_registers.r4 = self.r4;
+                self.current_registers.r5 = self.r5;
+                self.current_registers.r6 = self.r6;
+                self.current_registers.r7 = self.r7;
+                self.current_registers.r8 = self.r8;
+                self.current_registers.r9 = self.r9;
+                self.current_registers.r10 = self.r10;
+                self.current_registers.r11 = self.r11;
+                self.current_registers.r12 = self.r12;
+                self.current_registers.r13 = self.r13_abt;
+                self.current_registers.r14 = self.r14_abt;
+                self.current_registers.r15 = self.r15;
+                self.current_registers.spsr = self.spsr_abt;
+            }
+            CpuMode::Irq => {
+                self.current_registers.r0 = self.r0;
+                self.current_registers.r1 = self.r1;
+                self.current_registers.r2 = self.r2;
+                self.current_registers.r3 = self.r3;
+                self.current_registers.r4 = self.r4;
+                self.current_registers.r5 = self.r5;
+                self.current_registers.r6 = self.r6;
+                self.current_registers.r7 = self.r7;
+                self.current_registers.r8 = self.r8;
+                self.current_registers.r9 = self.r9;
+                self.current_registers.r10 = self.r10;
+                self.current_registers.r11 = self.r11;
+                self.current_registers.r12 = self.r12;
+                self.current_registers.r13 = self.r13_irq;
+                self.current_registers.r14 = self.r14_irq;
+                self.current_registers.r15 = self.r15;
+                self.current_registers.spsr = self.spsr_irq;
+            }
+            CpuMode::Undefined => {
+                self.current_registers.r0 = self.r0;
+                self.current_registers.r1 = self.r1;
+                self.current_registers.r2 = self.r2;
+                self.current_registers.r3 = self.r3;
+                self.current_registers.r4 = self.r4;
+                self.current_registers.r5 = self.r5;
+                self.current_registers.r6 = self.r6;
+                self.current_registers.r7 = self.r7;
+                self.current_registers.r8 = self.r8;
+                self.current_registers.r9 = self.r9;
+                self.current_registers.r10 = self.r10;
+                self.current_registers.r11 = self.r11;
+                self.current_registers.r12 = self.r12;
+                self.current_registers.r13 = self.r13_und;
+                self.current_registers.r14 = self.r14_und;
+                self.current_registers.r15 = self.r15;
+                self.current_registers.spsr = self.spsr_und;
+            }
+        }
+    }
+
     pub fn read_register(&self, register: Register, pc_calculation: fn(u32) -> u32) -> u32 {
         match register {
             Register::R0 => self.current_registers.r0,
@@ -670,29 +878,6 @@ impl Cpu {
             Register::Cpsr => self.cpsr,
         }
     }
-
-    fn read_user_register(&self, register: Register, pc_calculation: fn(u32) -> u32) -> u32 {
-        match register {
-            Register::R0 => self.r0,
-            Register::R1 => self.r1,
-            Register::R2 => self.r2,
-            Register::R3 => self.r3,
-            Register::R4 => self.r4,
-            Register::R5 => self.r5,
-            Register::R6 => self.r6,
-            Register::R7 => self.r7,
-            Register::R8 => self.r8,
-            Register::R9 => self.r9,
-            Register::R10 => self.r10,
-            Register::R11 => self.r11,
-            Register::R12 => self.r12,
-            Register::R13 => self.r13,
-            Register::R14 => self.r14,
-            Register::R15 => pc_calculation(self.r15),
-            Register::Spsr => unreachable!("no spsr in user mode"),
-            Register::Cpsr => self.cpsr,
-        }
-    }
 }
 
 pub enum Instruction {
@@ -725,9 +910,36 @@ impl Default for Instruction {
 }
 
 impl Cpu {
-    pub fn fetch_decode_execute(&mut self) {
+    pub fn fetch_decode_execute(&mut self) -> Option<StepEvent> {
+        // While halted or stopped no instructions are executed, only the bus is clocked until an
+        // interrupt wakes the CPU back up.
+        if self.bus.power_state() != PowerState::Running {
+            self.bus.step_low_power();
+
+            return self
+                .bus
+                .poll_frame_completed()
+                .then_some(StepEvent::FrameComplete);
+        }
+
+        let executing_pc = self.get_executing_pc();
+        if self.breakpoints.contains(&executing_pc)
+            && self.resuming_from_breakpoint != Some(executing_pc)
+        {
+            self.resuming_from_breakpoint = Some(executing_pc);
+            return Some(StepEvent::BreakpointHit {
+                address: executing_pc,
+            });
+        }
+        self.resuming_from_breakpoint = None;
+
         let irq_wanted = !self.get_irq_disable() && self.bus.get_irq_pending();
         let pc = self.read_register(Register::R15, |pc| pc);
+        let instruction_width = self.get_instruction_width();
+
+        let mut step_event = None;
+        // Kind and call site of the frame this step enters, if any.
+        let mut call = None;
 
         match self.get_instruction_mode() {
             InstructionSet::Arm => {
@@ -750,8 +962,52 @@ impl Cpu {
                 // for the same reasons.
                 if irq_wanted {
                     self.handle_exception(ExceptionType::InterruptRequest);
+                    call = Some((FrameKind::Interrupt, executing_pc));
                 } else {
-                    self.execute_arm(self.pre_decode_arm);
+                    let instruction = self.pre_decode_arm;
+                    // Set when the instruction is handled without being executed.
+                    let mut skip = false;
+
+                    if self.evaluate_instruction_condition(instruction.condition()) {
+                        match instruction.instruction_type() {
+                            ArmInstructionType::Invalid { opcode } => {
+                                step_event = self.handle_invalid_opcode(executing_pc, opcode);
+                                skip = self.config.error_policy == ErrorPolicy::Ignore;
+                            }
+                            // In ARM state, the BIOS function is taken from the upper comment bits.
+                            ArmInstructionType::Swi { comment }
+                                if comment >> 16 == Self::AGB_PRINT_FLUSH_SWI
+                                    && self.bus.flush_agb_print() =>
+                            {
+                                skip = true;
+                            }
+                            ArmInstructionType::Swi { comment } => {
+                                step_event = Some(StepEvent::SwiExecuted { comment });
+                                skip = self.run_swi_hook(executing_pc, comment >> 16);
+                                if !skip {
+                                    call = Some((FrameKind::Swi, executing_pc));
+                                }
+                            }
+                            ArmInstructionType::Bkpt { comment } => {
+                                step_event = Some(StepEvent::BkptHit {
+                                    address: executing_pc,
+                                    comment,
+                                });
+                            }
+                            ArmInstructionType::Bl { .. } | ArmInstructionType::Blx { .. } => {
+                                call = Some((FrameKind::Call, executing_pc));
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    if skip {
+                        self.skip_instruction();
+                    } else {
+                        self.execute_arm(instruction);
+                        self.bus.perf_counters.arm_instructions += 1;
+                        self.bus.record_executed(executing_pc, instruction_width);
+                    }
                 }
             }
             InstructionSet::Thumb => {
@@ -759,23 +1015,212 @@ impl Cpu {
                     unreachable!("unaligned Thumb pc");
                 }
 
-                let decoded_instruction = self.pre_decode_thumb;
-                let prefetched_opcode = self.prefetch_opcode;
-
                 if irq_wanted {
                     self.handle_exception(ExceptionType::InterruptRequest);
+                    call = Some((FrameKind::Interrupt, executing_pc));
                 } else {
-                    self.execute_thumb(self.pre_decode_thumb);
+                    let instruction = self.pre_decode_thumb;
+                    // Set when the instruction is handled without being executed.
+                    let mut skip = false;
+
+                    match instruction.instruction_type {
+                        ThumbInstructionType::Invalid { opcode } => {
+                            step_event =
+                                self.handle_invalid_opcode(executing_pc, u32::from(opcode));
+                            skip = self.config.error_policy == ErrorPolicy::Ignore;
+                        }
+                        ThumbInstructionType::Swi { comment }
+                            if u32::from(comment) == Self::AGB_PRINT_FLUSH_SWI
+                                && self.bus.flush_agb_print() =>
+                        {
+                            skip = true;
+                        }
+                        ThumbInstructionType::Swi { comment } => {
+                            step_event = Some(StepEvent::SwiExecuted {
+                                comment: u32::from(comment),
+                            });
+                            skip = self.run_swi_hook(executing_pc, u32::from(comment));
+                            if !skip {
+                                call = Some((FrameKind::Swi, executing_pc));
+                            }
+                        }
+                        ThumbInstructionType::Bkpt { comment } => {
+                            step_event = Some(StepEvent::BkptHit {
+                                address: executing_pc,
+                                comment,
+                            });
+                        }
+                        // The call site is the first half of the long branch.
+                        ThumbInstructionType::BlPartTwo { .. }
+                        | ThumbInstructionType::BlxPartTwo { .. } => {
+                            call = Some((FrameKind::Call, executing_pc.wrapping_sub(2)));
+                        }
+                        ThumbInstructionType::Blx { .. } => {
+                            call = Some((FrameKind::Call, executing_pc));
+                        }
+                        _ => {}
+                    }
+
+                    if skip {
+                        self.skip_instruction();
+                    } else {
+                        self.execute_thumb(instruction);
+                        self.bus.perf_counters.thumb_instructions += 1;
+                        self.bus.record_executed(executing_pc, instruction_width);
+                    }
                 }
             }
         };
+
+        let next_pc = self.get_executing_pc();
+        self.check_idle_loop(executing_pc, next_pc, call.is_some());
+        match call {
+            Some((kind, call_site)) => {
+                // An interrupted instruction is executed once the interrupt returns.
+                let return_address = match kind {
+                    FrameKind::Interrupt => executing_pc,
+                    FrameKind::Call | FrameKind::Swi => executing_pc + instruction_width,
+                };
+
+                self.call_stack.push(Frame {
+                    kind,
+                    call_site,
+                    target: next_pc,
+                    return_address,
+                });
+            }
+            None if next_pc != executing_pc + instruction_width => {
+                self.call_stack.branched_to(next_pc)
+            }
+            None => {}
+        }
+
+        if let Some(address) = self.bus.take_ewram_lockup() {
+            self.report_error(EmulatorError::EwramLockup { address });
+        }
+
+        if let Some(error) = self.pending_error.take() {
+            step_event = Some(StepEvent::Error(error));
+        }
+
+        // Only poll for frame completion if nothing else happened, so that it's reported on a
+        // later step instead of being lost.
+        if step_event.is_none() && self.bus.poll_frame_completed() {
+            step_event = Some(StepEvent::FrameComplete);
+        }
+
+        step_event
+    }
+
+    fn handle_invalid_opcode(&mut self, address: u32, opcode: u32) -> Option<StepEvent> {
+        match self.config.error_policy {
+            ErrorPolicy::Ignore => None,
+            ErrorPolicy::LogAndContinue => {
+                // Each distinct opcode is only logged once, and only the first few of those.
+                if self.logged_invalid_opcodes.len() < Self::MAX_LOGGED_INVALID_OPCODES
+                    && self.logged_invalid_opcodes.insert(opcode)
+                {
+                    log::warn!("unknown opcode {opcode:08X} at {address:08X}");
+                    if self.logged_invalid_opcodes.len() == Self::MAX_LOGGED_INVALID_OPCODES {
+                        log::warn!("not logging any more unknown opcodes");
+                    }
+                }
+                None
+            }
+            ErrorPolicy::Trap => Some(StepEvent::InvalidOpcode { address, opcode }),
+        }
+    }
+
+    // Counts the call, then gives the SWI hook, if any, a chance to handle it. Returns whether
+    // it did.
+    fn run_swi_hook(&mut self, address: u32, number: u32) -> bool {
+        let handled = self.call_swi_hook(address, number);
+        self.swi_stats.record(number, handled);
+        handled
+    }
+
+    // Returns whether the hook handled the call itself, in which case the SWI should be skipped.
+    fn call_swi_hook(&mut self, address: u32, number: u32) -> bool {
+        let Some(hook) = self.swi_hook.clone() else {
+            return false;
+        };
+
+        let arguments = [Register::R0, Register::R1, Register::R2, Register::R3]
+            .map(|register| self.read_register(register, |pc| pc));
+        let call = SwiCall {
+            address,
+            number,
+            arguments,
+        };
+
+        match hook(self, call) {
+            SwiAction::RunBios => false,
+            SwiAction::Return(results) => {
+                for (register, value) in [Register::R0, Register::R1, Register::R2, Register::R3]
+                    .into_iter()
+                    .zip(results)
+                {
+                    self.write_register(value, register);
+                }
+
+                true
+            }
+        }
+    }
+
+    // Moves on to the next instruction without executing the current one, only advancing the
+    // pipeline.
+    fn skip_instruction(&mut self) {
+        let old_pc = self.read_register(Register::R15, |pc| pc);
+
+        match self.get_instruction_mode() {
+            InstructionSet::Arm => {
+                self.pre_decode_arm = decode_arm(self.prefetch_opcode);
+                self.prefetch_opcode = self.bus.fetch_arm_opcode(old_pc);
+                self.write_register(old_pc + 4, Register::R15);
+            }
+            InstructionSet::Thumb => {
+                self.pre_decode_thumb = decode_thumb(self.prefetch_opcode as u16);
+                self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(old_pc));
+                self.write_register(old_pc + 2, Register::R15);
+            }
+        }
+    }
+
+    pub fn run_until_event(&mut self) -> StepEvent {
+        loop {
+            if let Some(step_event) = self.fetch_decode_execute() {
+                return step_event;
+            }
+        }
     }
 
     pub fn sample_apu(&self) -> [f32; 2] {
         self.bus.apu.sample()
     }
 
+    // Depends on SOUNDBIAS, so it can change at any time.
+    pub fn apu_sample_rate(&self) -> u32 {
+        self.bus.apu.output_sample_rate()
+    }
+
+    // From now on, APU output is resampled to `sample_rate` and collected in a ring buffer holding
+    // up to `capacity` stereo samples, for playback from another thread.
+    pub fn enable_audio_output(&mut self, sample_rate: u32, capacity: usize) -> AudioRingBuffer {
+        self.bus.apu.enable_output(sample_rate, capacity)
+    }
+
+    pub fn disable_audio_output(&mut self) {
+        self.bus.apu.disable_output();
+    }
+
     fn handle_exception(&mut self, exception_type: ExceptionType) {
+        if let ExceptionType::InterruptRequest = exception_type {
+            self.bus.perf_counters.irqs_taken += 1;
+            let interrupted_pc = self.get_executing_pc();
+            self.bus.record_interrupt(interrupted_pc);
+        }
+
         log::trace!("HANDLING EXCEPTION: {:?}", exception_type);
 
         // Even while handling exception, prefetch still occurs.
@@ -823,7 +1268,31 @@ impl Cpu {
             // the next instruction, the SVC instruction having size 2bytes for Thumb or 4 bytes for ARM.
             (ExceptionType::Swi, InstructionSet::Arm) => |pc| pc - 4,
             (ExceptionType::Swi, InstructionSet::Thumb) => |pc| pc - 2,
-            (exception_type, mode) => todo!("{exception_type:?}, {mode:?}"),
+            // Undefined Instruction Exception
+            //
+            // LR is to be the address of the instruction following the undefined one, so that
+            // a handler emulating the instruction can return with MOVS PC, LR. Identical to the
+            // SWI case.
+            (ExceptionType::Undefined, InstructionSet::Arm) => |pc| pc - 4,
+            (ExceptionType::Undefined, InstructionSet::Thumb) => |pc| pc - 2,
+            // Prefetch Abort Exception
+            //
+            // LR is to be the address of the aborted instruction plus 4.
+            (ExceptionType::PrefetchAbort, InstructionSet::Arm) => |pc| pc - 4,
+            (ExceptionType::PrefetchAbort, InstructionSet::Thumb) => |pc| pc,
+            // Data Abort Exception
+            //
+            // LR is to be the address of the aborted instruction plus 8.
+            (ExceptionType::DataAbort, InstructionSet::Arm) => |pc| pc,
+            (ExceptionType::DataAbort, InstructionSet::Thumb) => |pc| pc + 4,
+            // FIQ Exception
+            //
+            // Identical to the IRQ case.
+            (ExceptionType::FastInterruptRequest, InstructionSet::Arm) => |pc| pc - 4,
+            (ExceptionType::FastInterruptRequest, InstructionSet::Thumb) => |pc| pc,
+            // The return address is meaningless after a reset, and 26-bit addressing doesn't exist
+            // on the ARM7TDMI.
+            (ExceptionType::Reset | ExceptionType::AddressExceeds26Bit, _) => |pc| pc,
         };
 
         let old_pc = self.read_register(Register::R15, pc_offset);
@@ -996,6 +1465,10 @@ impl Cpu {
         }
     }
 
+    fn set_instruction_mode(&mut self, instruction_set: InstructionSet) {
+        self.set_cpu_state_bit(matches!(instruction_set, InstructionSet::Thumb));
+    }
+
     fn get_cpu_state_bit(&self) -> bool {
         self.cpsr.get_bit(Self::STATE_BIT_OFFSET)
     }
@@ -1068,3 +1541,268 @@ impl Cpu {
         r15 - bytes_behind
     }
 }
+
+// Breakpoints
+impl Cpu {
+    pub fn add_breakpoint(&mut self, address: u32) {
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u32) {
+        self.breakpoints.retain(|&breakpoint| breakpoint != address);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn get_breakpoints(&self) -> &[u32] {
+        &self.breakpoints
+    }
+}
+
+// Save states
+impl Cpu {
+    // Replaces all emulated state with that of a deserialized save state. Save states don't
+    // include the ROM or any debugger state, so those are carried over from the current `Cpu`.
+    pub fn load_state(&mut self, mut state: Cpu) {
+        state.bus.cartridge.swap_rom(&mut self.bus.cartridge);
+        state.breakpoints = std::mem::take(&mut self.breakpoints);
+        state.clock = self.clock;
+        state.set_config(self.config);
+        state.bus.perf_counters = self.bus.perf_counters;
+        state.bus.set_profiler(self.bus.profiler().cloned());
+        state.bus.set_coverage(self.bus.coverage().cloned());
+        state.bus.set_access_watch(self.bus.access_watch().cloned());
+        state.logged_invalid_opcodes = std::mem::take(&mut self.logged_invalid_opcodes);
+        state.swi_hook = self.swi_hook.take();
+        state
+            .bus
+            .set_debug_output_callback(self.bus.debug_output_callback());
+        state.bus.set_frame_callback(self.bus.frame_callback());
+        state.bus.apu.set_output(self.bus.apu.take_output());
+        state
+            .bus
+            .lcd
+            .set_color_correction(self.bus.lcd.get_color_correction());
+        state
+            .bus
+            .lcd
+            .set_rgba_output(self.bus.lcd.rgba_output_enabled());
+        state.bus.serial.set_transport(self.bus.serial.transport());
+        state
+            .bus
+            .cartridge
+            .set_rumble_callback(self.bus.cartridge.rumble_callback());
+        state
+            .bus
+            .cartridge
+            .set_card_source(self.bus.cartridge.card_source());
+
+        *self = state;
+    }
+}
+
+impl Cpu {
+    // Messages printed by the running software through an emulator debug output interface are
+    // always logged, the callback additionally receives each of them.
+    pub fn set_debug_output_callback(
+        &mut self,
+        callback: impl Fn(DebugOutputLevel, &str) + Send + Sync + 'static,
+    ) {
+        self.bus.set_debug_output_callback(Some(Arc::new(callback)));
+    }
+
+    pub fn clear_debug_output_callback(&mut self) {
+        self.bus.set_debug_output_callback(None);
+    }
+
+    // Called exactly once per frame as vblank starts, with the completed frame. Unlike reading
+    // `Lcd::get_buffer` at an arbitrary time, this never observes a frame that is still being
+    // drawn.
+    pub fn set_frame_callback(&mut self, callback: impl Fn(&FrameBuffer) + Send + Sync + 'static) {
+        self.bus.set_frame_callback(Some(Arc::new(callback)));
+    }
+
+    pub fn clear_frame_callback(&mut self) {
+        self.bus.set_frame_callback(None);
+    }
+
+    // Plugs the other end of the link cable into the given transport, or unplugs it if `None`.
+    pub fn set_link_transport(&mut self, transport: Option<LinkTransportHandle>) {
+        self.bus.serial.set_transport(transport);
+    }
+
+    // Called with the new motor state whenever the cartridge switches its rumble motor on or off.
+    pub fn set_rumble_callback(&mut self, callback: impl Fn(bool) + Send + Sync + 'static) {
+        self.bus
+            .cartridge
+            .set_rumble_callback(Some(Arc::new(callback)));
+    }
+
+    pub fn clear_rumble_callback(&mut self) {
+        self.bus.cartridge.set_rumble_callback(None);
+    }
+
+    // Called before each BIOS call is made, other than AGBPrintFlush. The hook can inspect or
+    // change the CPU, and either let the BIOS run or skip it and supply the results itself.
+    pub fn on_swi(
+        &mut self,
+        hook: impl Fn(&mut Cpu, SwiCall) -> SwiAction + Send + Sync + 'static,
+    ) {
+        self.swi_hook = Some(Arc::new(hook));
+    }
+
+    pub fn clear_swi_hook(&mut self) {
+        self.swi_hook = None;
+    }
+
+    // BIOS calls made since the CPU was created or a state was loaded.
+    pub fn swi_stats(&self) -> &SwiStats {
+        &self.swi_stats
+    }
+
+    pub fn reset_swi_stats(&mut self) {
+        self.swi_stats = SwiStats::default();
+    }
+
+    pub fn perf_counters(&self) -> PerfCounters {
+        self.bus.perf_counters
+    }
+
+    pub fn reset_perf_counters(&mut self) {
+        self.bus.perf_counters = PerfCounters::default();
+    }
+
+    // The checksums of up to the last `n` frames, oldest first, see `Lcd::frame_hash_history`.
+    pub fn frame_hash_history(&self, n: usize) -> Vec<u64> {
+        self.bus.lcd.frame_hash_history(n)
+    }
+
+    // Whether the screen hasn't changed for at least `frames` frames, which usually means the game
+    // has frozen, or is waiting on input.
+    pub fn is_screen_frozen(&self, frames: u64) -> bool {
+        self.bus.lcd.unchanged_frames() >= frames
+    }
+
+    // Only available while coverage is being recorded, see `Bus::set_coverage`.
+    pub fn coverage_summary(&self) -> Option<CoverageSummary> {
+        let rom_size = self.bus.cartridge.rom_size() as u32;
+        self.bus
+            .coverage()
+            .map(|coverage| coverage.summary(rom_size))
+    }
+
+    // How execution reached the current instruction, innermost frame first.
+    pub fn call_stack(&self) -> Vec<Frame> {
+        self.call_stack.frames().iter().rev().copied().collect()
+    }
+
+    pub fn config(&self) -> CpuConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: CpuConfig) {
+        self.config = config;
+        self.bus.fast_ewram = config.fast_ewram;
+    }
+
+    fn report_error(&mut self, error: EmulatorError) {
+        match self.config.error_policy {
+            ErrorPolicy::Ignore => {}
+            ErrorPolicy::LogAndContinue => log::error!("{error}"),
+            ErrorPolicy::Trap => {
+                log::error!("{error}");
+                self.pending_error = Some(error);
+            }
+        }
+    }
+}
+
+impl Cpu {
+    pub fn clock(&self) -> &EmulationClock {
+        &self.clock
+    }
+
+    pub fn set_speed_multiplier(&mut self, speed_multiplier: f64) {
+        self.clock.set_speed_multiplier(speed_multiplier);
+    }
+
+    pub fn set_uncapped(&mut self, uncapped: bool) {
+        self.clock.set_uncapped(uncapped);
+    }
+
+    pub fn set_timing_mode(&mut self, timing_mode: TimingMode) {
+        self.clock.set_timing_mode(timing_mode);
+    }
+
+    pub fn sync_to_audio_buffer(&mut self, buffered_samples: usize, target_samples: usize) {
+        self.clock
+            .sync_to_audio_buffer(buffered_samples, target_samples);
+    }
+
+    // Runs for one frame's worth of cycles at the current speed, for a frontend presenting
+    // `frames_per_second` frames. Stops early if `should_stop` returns true for a step event.
+    pub fn run_frame(
+        &mut self,
+        frames_per_second: u32,
+        mut should_stop: impl FnMut(StepEvent) -> bool,
+    ) -> Option<StepEvent> {
+        let cycle_budget = self.clock.cycles_per_frame(frames_per_second);
+        let cycle_start = self.bus.cycle_count();
+
+        while (self.bus.cycle_count() - cycle_start) < cycle_budget {
+            if let Some(step_event) = self.fetch_decode_execute().filter(|e| should_stop(*e)) {
+                return Some(step_event);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_arm_test_cpu, build_thumb_test_cpu};
+
+    #[test]
+    fn bkpt() {
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0xBE2A, // bkpt #42
+                0x2001, // mov r0, #1
+            ],
+            &[],
+        );
+
+        cpu.fetch_decode_execute();
+        cpu.fetch_decode_execute();
+        assert_eq!(
+            cpu.fetch_decode_execute(),
+            Some(StepEvent::BkptHit {
+                address: 0x08000008,
+                comment: 42,
+            })
+        );
+        assert_eq!(cpu.get_executing_pc(), 0x0800000A);
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::System);
+
+        cpu.fetch_decode_execute();
+        assert_eq!(cpu.read_register(Register::R0, |pc| pc), 1);
+
+        let mut cpu = build_arm_test_cpu(&[
+            0xE1212374, // bkpt #0x1234
+        ]);
+        assert_eq!(
+            cpu.fetch_decode_execute(),
+            Some(StepEvent::BkptHit {
+                address: 0x08000000,
+                comment: 0x1234,
+            })
+        );
+        assert_eq!(cpu.get_executing_pc(), 0x08000004);
+    }
+}
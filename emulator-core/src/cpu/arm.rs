@@ -1,19 +1,22 @@
+use super::block_transfer::BlockTransfer;
 use super::{Cpu, ExceptionType, InstructionCondition, Register, ShiftType};
 
 use crate::bus::BusAccessType;
 use crate::cpu::thumb::decode_thumb;
+use crate::error::EmulatorError;
 use crate::{BitManipulation, DataAccess, InstructionSet};
+use serde::{Deserialize, Serialize};
 
 use std::fmt::Display;
 use std::ops::RangeInclusive;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub(super) enum OffsetModifierType {
     AddToBase,
     SubtractFromBase,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub(super) enum SingleDataMemoryAccessSize {
     Byte,
     HalfWord,
@@ -21,7 +24,7 @@ pub(super) enum SingleDataMemoryAccessSize {
     DoubleWord,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub(super) enum ArmInstructionType {
     B {
         offset: i32,
@@ -96,18 +99,47 @@ pub(super) enum ArmInstructionType {
     Swi {
         comment: u32,
     },
+    // ARMv5, which the ARM7TDMI doesn't have. Treated as a debugger trap, see `StepEvent`.
+    Bkpt {
+        comment: u16,
+    },
     Swp {
         access_size: SwpAccessSize,
         base_register: Register,
         dest_register: Register,
         source_register: Register,
     },
+    // Coprocessor registers are plain indices, c0-c15.
+    Cdp {
+        coprocessor: u32,
+        opcode_1: u32,
+        destination_register: u32,
+        first_operand: u32,
+        second_operand: u32,
+        opcode_2: u32,
+    },
+    Mrc {
+        coprocessor: u32,
+        opcode_1: u32,
+        destination_register: Register,
+        first_operand: u32,
+        second_operand: u32,
+        opcode_2: u32,
+    },
+    Mcr {
+        coprocessor: u32,
+        opcode_1: u32,
+        source_register: Register,
+        first_operand: u32,
+        second_operand: u32,
+        opcode_2: u32,
+    },
     Invalid {
         opcode: u32,
     },
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct ArmInstruction {
     instruction_type: ArmInstructionType,
     condition: InstructionCondition,
@@ -117,51 +149,55 @@ impl ArmInstruction {
     pub(super) fn instruction_type(&self) -> ArmInstructionType {
         self.instruction_type
     }
+
+    pub(super) fn condition(&self) -> InstructionCondition {
+        self.condition
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum SingleDataTransferIndexType {
     PostIndex { non_privileged: bool },
     PreIndex { write_back: bool },
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum BlockDataTransferIndexType {
     PostIndex,
     PreIndex,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 enum SingleDataTransferType {
     Ldr,
     Str,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum BlockDataTransferType {
     Ldm,
     Stm,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum PsrTransferType {
     Mrs,
     Msr,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum PsrTransferPsr {
     Cpsr,
     Spsr,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct SingleDataTransferOffsetInfo {
     value: SingleDataTransferOffsetValue,
     sign: bool,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum SingleDataTransferOffsetValue {
     Immediate {
         offset: u32,
@@ -176,7 +212,7 @@ pub enum SingleDataTransferOffsetValue {
     },
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum AluSecondOperandInfo {
     Register {
         shift_info: ArmRegisterOrImmediate,
@@ -189,7 +225,7 @@ pub enum AluSecondOperandInfo {
     },
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum SwpAccessSize {
     Word,
     Byte,
@@ -336,13 +372,13 @@ impl Cpu {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum ArmRegisterOrImmediate {
     Immediate(u32),
     Register(Register),
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum AluOperation {
     And,
     Eor,
@@ -362,7 +398,7 @@ pub enum AluOperation {
     Mvn,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum MultiplyOperation {
     Mul,
     Mla,
@@ -373,7 +409,7 @@ pub enum MultiplyOperation {
     Smlal,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum MsrSourceInfo {
     Register(Register),
     Immediate { value: u32 },
@@ -435,7 +471,8 @@ pub fn decode_arm(opcode: u32) -> ArmInstruction {
 
     let mask_result = opcode & OPCODE_MASK;
     let maybe_instruction_type = if mask_result == MUST_BE_000 {
-        None.or_else(|| try_decode_arm_branch_exchange(opcode))
+        None.or_else(|| try_decode_arm_breakpoint(opcode))
+            .or_else(|| try_decode_arm_branch_exchange(opcode))
             .or_else(|| try_decode_arm_data_process(opcode))
             .or_else(|| try_decode_arm_multiply(opcode))
             .or_else(|| try_decode_arm_psr_transfer(opcode))
@@ -453,7 +490,8 @@ pub fn decode_arm(opcode: u32) -> ArmInstruction {
     } else if mask_result == MUST_BE_110 {
         None
     } else if mask_result == MUST_BE_111 {
-        try_decode_arm_swi(opcode)
+        None.or_else(|| try_decode_arm_swi(opcode))
+            .or_else(|| try_decode_arm_coprocessor(opcode))
     } else {
         None
     };
@@ -506,9 +544,28 @@ fn try_decode_arm_branch_exchange(opcode: u32) -> Option<ArmInstructionType> {
 
             match opcode.get_bit_range(OPCODE_BIT_RANGE) {
                 0b0001 => ArmInstructionType::Bx { operand },
-                0b0010 => todo!("Jazelle bytecode"),
                 0b0011 => ArmInstructionType::Blx { operand },
-                _ => unreachable!(),
+                // Includes BXJ (Jazelle bytecode), which doesn't exist on the ARM7TDMI.
+                _ => ArmInstructionType::Invalid { opcode },
+            }
+        })
+}
+
+fn try_decode_arm_breakpoint(opcode: u32) -> Option<ArmInstructionType> {
+    const BREAKPOINT_MASK: u32 = 0b00001111_11110000_00000000_11110000;
+    const BREAKPOINT_MASK_RESULT: u32 = 0b00000001_00100000_00000000_01110000;
+
+    opcode
+        .match_mask(BREAKPOINT_MASK, BREAKPOINT_MASK_RESULT)
+        .then(|| {
+            const COMMENT_HIGH_BIT_RANGE: RangeInclusive<usize> = 8..=19;
+            const COMMENT_LOW_BIT_RANGE: RangeInclusive<usize> = 0..=3;
+
+            let comment = (opcode.get_bit_range(COMMENT_HIGH_BIT_RANGE) << 4)
+                | opcode.get_bit_range(COMMENT_LOW_BIT_RANGE);
+
+            ArmInstructionType::Bkpt {
+                comment: comment as u16,
             }
         })
 }
@@ -526,6 +583,65 @@ fn try_decode_arm_swi(opcode: u32) -> Option<ArmInstructionType> {
     Some(ArmInstructionType::Swi { comment })
 }
 
+// CDP, MRC and MCR. LDC and STC aren't decoded, and stay invalid.
+fn try_decode_arm_coprocessor(opcode: u32) -> Option<ArmInstructionType> {
+    const MUST_BE_1110_BIT_RANGE: RangeInclusive<usize> = 24..=27;
+    // CDP's opcode_1 is a bit wider, taking the place of the load bit.
+    const CDP_OPCODE_1_BIT_RANGE: RangeInclusive<usize> = 20..=23;
+    const OPCODE_1_BIT_RANGE: RangeInclusive<usize> = 21..=23;
+    const LOAD_BIT_INDEX: usize = 20;
+    const FIRST_OPERAND_BIT_RANGE: RangeInclusive<usize> = 16..=19;
+    const DESTINATION_BIT_RANGE: RangeInclusive<usize> = 12..=15;
+    const ARM_REGISTER_OFFSET: usize = 12;
+    const COPROCESSOR_BIT_RANGE: RangeInclusive<usize> = 8..=11;
+    const OPCODE_2_BIT_RANGE: RangeInclusive<usize> = 5..=7;
+    const REGISTER_TRANSFER_BIT_INDEX: usize = 4;
+    const SECOND_OPERAND_BIT_RANGE: RangeInclusive<usize> = 0..=3;
+
+    if opcode.get_bit_range(MUST_BE_1110_BIT_RANGE) != 0b1110 {
+        return None;
+    }
+
+    let coprocessor = opcode.get_bit_range(COPROCESSOR_BIT_RANGE);
+    let first_operand = opcode.get_bit_range(FIRST_OPERAND_BIT_RANGE);
+    let second_operand = opcode.get_bit_range(SECOND_OPERAND_BIT_RANGE);
+    let opcode_2 = opcode.get_bit_range(OPCODE_2_BIT_RANGE);
+
+    if !opcode.get_bit(REGISTER_TRANSFER_BIT_INDEX) {
+        return Some(ArmInstructionType::Cdp {
+            coprocessor,
+            opcode_1: opcode.get_bit_range(CDP_OPCODE_1_BIT_RANGE),
+            destination_register: opcode.get_bit_range(DESTINATION_BIT_RANGE),
+            first_operand,
+            second_operand,
+            opcode_2,
+        });
+    }
+
+    let opcode_1 = opcode.get_bit_range(OPCODE_1_BIT_RANGE);
+    let register = get_register_at_offset(opcode, ARM_REGISTER_OFFSET);
+
+    Some(if opcode.get_bit(LOAD_BIT_INDEX) {
+        ArmInstructionType::Mrc {
+            coprocessor,
+            opcode_1,
+            destination_register: register,
+            first_operand,
+            second_operand,
+            opcode_2,
+        }
+    } else {
+        ArmInstructionType::Mcr {
+            coprocessor,
+            opcode_1,
+            source_register: register,
+            first_operand,
+            second_operand,
+            opcode_2,
+        }
+    })
+}
+
 fn try_decode_arm_data_process(opcode: u32) -> Option<ArmInstructionType> {
     const DATA_PROCESS_MASK: u32 = 0b00001100_00000000_00000000_00000000;
     const DATA_PROCESS_MASK_RESULT: u32 = 0b00000000_00000000_00000000_00000000;
@@ -1220,6 +1336,7 @@ fn try_decode_arm_single_data_swap(opcode: u32) -> Option<ArmInstructionType> {
 }
 
 impl Cpu {
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub fn execute_arm(&mut self, instruction: ArmInstruction) {
         if self.evaluate_instruction_condition(instruction.condition) {
             match instruction.instruction_type {
@@ -1332,6 +1449,8 @@ impl Cpu {
                     operand_register_rs,
                 ),
                 ArmInstructionType::Swi { comment: _ } => self.handle_exception(ExceptionType::Swi),
+                // Executed as a no-op once the debugger has been told about it.
+                ArmInstructionType::Bkpt { .. } => self.skip_instruction(),
                 ArmInstructionType::Swp {
                     access_size,
                     base_register,
@@ -1340,7 +1459,15 @@ impl Cpu {
                 } => {
                     self.execute_arm_swp(access_size, base_register, dest_register, source_register)
                 }
-                _ => todo!("{:#08x?}", instruction),
+                // BLX was introduced in ARMv5, and is undefined on the ARM7TDMI. No coprocessors
+                // are attached either, so nothing answers coprocessor instructions.
+                ArmInstructionType::Blx { .. }
+                | ArmInstructionType::Cdp { .. }
+                | ArmInstructionType::Mrc { .. }
+                | ArmInstructionType::Mcr { .. }
+                | ArmInstructionType::Invalid { .. } => {
+                    self.handle_exception(ExceptionType::Undefined)
+                }
             }
         } else {
             // If instruction condition fails, we still need to increment to the next instruction.
@@ -1378,7 +1505,7 @@ impl Cpu {
                 ..
             } => {
                 // TODO: This may possible be a merged IS cycle.
-                self.bus.step(); // if shift by register, we take an extra I cycle to calculate this.
+                self.bus.step_internal(); // if shift by register, we take an extra I cycle to calculate this.
                 |pc| pc + 4
             }
             _ => |pc| pc,
@@ -1658,7 +1785,7 @@ impl Cpu {
         // cycle 1
         // pre-fetch still occurs, but we won't bother storing it anywhere or performing decode.
         self.bus.fetch_arm_opcode(old_pc);
-        let operand_value = self.read_register(operand, |_| todo!());
+        let operand_value = self.read_register(operand, |pc| pc);
 
         // cycle 2
         let new_state_bit = operand_value.get_bit(NEW_STATE_BIT_INDEX);
@@ -1839,7 +1966,13 @@ impl Cpu {
                 self.bus
                     .write_word_address(value, actual_address, BusAccessType::NonSequential)
             }
-            _ => todo!("{:?}", access_size),
+            // Only exists from ARMv5TE onwards.
+            SingleDataMemoryAccessSize::DoubleWord => {
+                self.report_error(EmulatorError::UnimplementedInstruction {
+                    address: old_pc - 8,
+                    mnemonic: "strd",
+                });
+            }
         };
 
         self.write_register(old_pc + 4, Register::R15);
@@ -1975,13 +2108,20 @@ impl Cpu {
                     .rotate_right(rotation)
             }
             (SingleDataMemoryAccessSize::Word, true) => unreachable!(),
-            _ => todo!("{:?} sign extend: {}", access_size, sign_extend),
+            // Only exists from ARMv5TE onwards, so leave the destination untouched.
+            (SingleDataMemoryAccessSize::DoubleWord, _) => {
+                self.report_error(EmulatorError::UnimplementedInstruction {
+                    address: old_pc - 8,
+                    mnemonic: "ldrd",
+                });
+                self.read_register(destination_register, |pc| pc)
+            }
         };
 
         // third cycle: store result in destination register.
         // TODO: This may possible a merged IS cycle.
         self.write_register(value, destination_register);
-        self.bus.step();
+        self.bus.step_internal();
 
         // if R15 is affected by this instruciton, add cycles to refill prefetch.
         let r15_modified = matches!(destination_register, Register::R15)
@@ -2017,138 +2157,33 @@ impl Cpu {
         self.pre_decode_arm = decode_arm(self.prefetch_opcode);
         self.prefetch_opcode = self.bus.fetch_arm_opcode(old_pc);
 
-        fn write_register_user_mode(cpu: &mut Cpu, value: u32, register: Register) {
-            let old_mode = cpu.get_cpu_mode();
-            cpu.set_cpu_mode(super::CpuMode::User);
-            cpu.write_register(value, register);
-            cpu.set_cpu_mode(old_mode);
-        }
-
-        let empty_rlist = register_bit_list.into_iter().all(|val| !val);
-
         // "not including R15".
-        let mut current_address = self.read_register(base_register, |_| unreachable!());
-
-        let mut r15_written = false;
-
-        // cycles 1-n: read data
-        match offset_modifier {
-            OffsetModifierType::AddToBase => {
-                for (register_idx, register_loaded) in register_bit_list.into_iter().enumerate() {
-                    if register_loaded {
-                        if matches!(index_type, BlockDataTransferIndexType::PreIndex) {
-                            current_address += 4;
-                        }
-
-                        // The mis-aligned low bit(s) are ignored, the memory access goes to a forcibly aligned (rounded-down) memory address.
-                        let value = self
-                            .bus
-                            .read_word_address(current_address, BusAccessType::NonSequential);
-                        let register = Register::from_index(register_idx as u32);
-
-                        r15_written |= matches!(register, Register::R15);
+        let base_address = self.read_register(base_register, |_| unreachable!());
+        let transfer = BlockTransfer::new(
+            base_address,
+            &register_bit_list,
+            offset_modifier,
+            index_type,
+        );
 
-                        if force_user_mode {
-                            write_register_user_mode(self, value, register);
-                        } else {
-                            self.write_register(value, register);
-                        };
+        // cycles 2-(1+n): read data, then an I cycle to write the final register back.
+        let r15_written = self.block_load(&transfer, base_register, write_back, force_user_mode);
 
-                        if matches!(index_type, BlockDataTransferIndexType::PostIndex) {
-                            current_address += 4;
-                        }
-                    }
+        if r15_written {
+            // Restoring the CPSR along with R15 may have switched to Thumb.
+            let new_pc = self.read_register(Register::R15, |pc| pc);
+            match self.get_instruction_mode() {
+                InstructionSet::Arm => {
+                    self.pre_decode_arm = decode_arm(self.bus.fetch_arm_opcode(new_pc));
+                    self.prefetch_opcode = self.bus.fetch_arm_opcode(new_pc + 4);
+                    self.write_register(new_pc + 8, Register::R15);
                 }
-            }
-            OffsetModifierType::SubtractFromBase => {
-                // Lowest register index goes at lowest address. When decrementing after load, lowest register index needs to be considered last.
-                //  In order to achieve this, iterate in reverse order.
-                for (register_idx, register_loaded) in
-                    register_bit_list.into_iter().enumerate().rev()
-                {
-                    if register_loaded {
-                        if matches!(index_type, BlockDataTransferIndexType::PreIndex) {
-                            current_address -= 4;
-                        }
-
-                        // The mis-aligned low bit(s) are ignored, the memory access goes to a forcibly aligned (rounded-down) memory address.
-                        let value = self
-                            .bus
-                            .read_word_address(current_address, BusAccessType::NonSequential);
-                        let register = Register::from_index(register_idx as u32);
-
-                        r15_written |= matches!(register, Register::R15);
-
-                        if force_user_mode {
-                            write_register_user_mode(self, value, register);
-                        } else {
-                            self.write_register(value, register);
-                        };
-
-                        if matches!(index_type, BlockDataTransferIndexType::PostIndex) {
-                            current_address -= 4;
-                        }
-                    }
+                InstructionSet::Thumb => {
+                    self.pre_decode_thumb = decode_thumb(self.bus.fetch_thumb_opcode(new_pc));
+                    self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(new_pc + 2));
+                    self.write_register(new_pc + 4, Register::R15);
                 }
             }
-        }
-
-        if empty_rlist {
-            if matches!(index_type, BlockDataTransferIndexType::PreIndex) {
-                match offset_modifier {
-                    OffsetModifierType::AddToBase => current_address += 0x40,
-                    OffsetModifierType::SubtractFromBase => current_address -= 0x40,
-                };
-            }
-
-            // The mis-aligned low bit(s) are ignored, the memory access goes to a forcibly aligned (rounded-down) memory address.
-            let value = self
-                .bus
-                .read_word_address(current_address, BusAccessType::NonSequential);
-            let register = Register::R15;
-
-            r15_written |= true;
-
-            if force_user_mode {
-                write_register_user_mode(self, value, register);
-            } else {
-                self.write_register(value, register);
-            };
-
-            if matches!(index_type, BlockDataTransferIndexType::PostIndex) {
-                match offset_modifier {
-                    OffsetModifierType::AddToBase => current_address += 0x40,
-                    OffsetModifierType::SubtractFromBase => current_address -= 0x40,
-                };
-            }
-        }
-
-        let base_in_rlist = register_bit_list
-            .into_iter()
-            .enumerate()
-            .filter_map(|(register_idx, register_loaded)| {
-                register_loaded.then(|| Register::from_index(register_idx as u32))
-            })
-            .any(|loaded_register| {
-                std::mem::discriminant(&loaded_register) == std::mem::discriminant(&base_register)
-            });
-
-        // Writeback with Rb included in Rlist: no writeback (LDM/ARMv4).
-        if !base_in_rlist && write_back {
-            self.write_register(current_address, base_register);
-        }
-
-        // cycles 2-(1+n): I cycle: write result into register.
-
-        // Write final register back.
-        // TODO: This may possibly be a merged IS cycle.
-        self.bus.step();
-
-        if r15_written {
-            let new_pc = self.read_register(Register::R15, |pc| pc);
-            self.pre_decode_arm = decode_arm(self.bus.fetch_arm_opcode(new_pc));
-            self.prefetch_opcode = self.bus.fetch_arm_opcode(new_pc + 4);
-            self.write_register(new_pc + 8, Register::R15);
         } else {
             self.write_register(old_pc + 4, Register::R15);
         }
@@ -2168,90 +2203,23 @@ impl Cpu {
         self.pre_decode_arm = decode_arm(self.prefetch_opcode);
         self.prefetch_opcode = self.bus.fetch_arm_opcode(old_pc);
 
-        enum IncrementTiming {
-            BeforeWrite,
-            AfterWrite,
-        }
-
-        let raw_registers = register_bit_list
-            .into_iter()
-            .enumerate()
-            .filter_map(|(register_idx, register_loaded)| {
-                register_loaded.then(|| Register::from_index(register_idx as u32))
-            })
-            .collect::<Vec<_>>();
-        let read_register_pc_calculation = |pc| pc + 4;
-
         // "not including R15".
         let base_address = self.read_register(base_register, |_| unreachable!());
+        let transfer = BlockTransfer::new(
+            base_address,
+            &register_bit_list,
+            offset_modifier,
+            index_type,
+        );
 
-        // number of non-zero registers
-        //
-        // empty rlist behaves as if we had _all_ registers when performing offset calculations.
-        // Empty Rlist: R15 loaded/stored (ARMv4 only), and Rb=Rb+/-40h (ARMv4-v5).
-        let (num_registers, stored_registers) = if raw_registers.is_empty() {
-            (16, vec![Register::R15])
-        } else {
-            (raw_registers.len() as u32, raw_registers)
-        };
-
-        let increment_timing = match (offset_modifier, index_type) {
-            (OffsetModifierType::AddToBase, BlockDataTransferIndexType::PreIndex)
-            | (OffsetModifierType::SubtractFromBase, BlockDataTransferIndexType::PostIndex) => {
-                IncrementTiming::BeforeWrite
-            }
-            (OffsetModifierType::AddToBase, BlockDataTransferIndexType::PostIndex)
-            | (OffsetModifierType::SubtractFromBase, BlockDataTransferIndexType::PreIndex) => {
-                IncrementTiming::AfterWrite
-            }
-        };
-
-        let new_base = match offset_modifier {
-            OffsetModifierType::AddToBase => base_address + (4 * num_registers),
-            OffsetModifierType::SubtractFromBase => base_address - (4 * num_registers),
-        };
-
-        // we store registers from low to high address no matter what, but the final value of "current_address" does
-        // not represent the true write-back address.
-        let mut current_address = match offset_modifier {
-            OffsetModifierType::AddToBase => base_address,
-            OffsetModifierType::SubtractFromBase => base_address - (4 * num_registers),
-        };
-
-        // Writeback with Rb included in Rlist: Store OLD base if Rb is FIRST entry in Rlist, otherwise store NEW base
-        let base_value_if_read = if stored_registers.first() == Some(&base_register) {
-            base_address
-        } else {
-            new_base
-        };
-
-        for register in stored_registers {
-            if matches!(increment_timing, IncrementTiming::BeforeWrite) {
-                current_address += 4;
-            }
-
-            let register_value = if register == base_register {
-                base_value_if_read
-            } else if force_user_mode {
-                self.read_user_register(register, read_register_pc_calculation)
-            } else {
-                self.read_register(register, read_register_pc_calculation)
-            };
-
-            self.bus.write_word_address(
-                register_value,
-                current_address,
-                BusAccessType::NonSequential,
-            );
-
-            if matches!(increment_timing, IncrementTiming::AfterWrite) {
-                current_address += 4;
-            }
-        }
-
-        if write_back {
-            self.write_register(new_base, base_register);
-        }
+        // cycles 2-(1+n): write data
+        self.block_store(
+            &transfer,
+            base_register,
+            write_back,
+            force_user_mode,
+            |pc| pc + 4,
+        );
 
         self.write_register(old_pc + 4, Register::R15);
     }
@@ -2271,7 +2239,7 @@ impl Cpu {
 
         let accumulate_rdlo_value =
             self.read_register(accumulate_register_rdlo, |_| unreachable!());
-        let destination_rdhi_value = self.read_register(destination_register_rdhi, |_| todo!());
+        let destination_rdhi_value = self.read_register(destination_register_rdhi, |pc| pc);
         let rm_value = self.read_register(operand_register_rm, |_| unreachable!());
         let rs_value = self.read_register(operand_register_rs, |_| unreachable!());
 
@@ -2363,11 +2331,17 @@ impl Cpu {
                 self.write_register(low_word, accumulate_register_rdlo);
                 self.write_register(high_word, destination_register_rdhi);
             }
-            _ => todo!("multiply impl for {:?}", operation),
+            // Only exists from ARMv6 onwards.
+            MultiplyOperation::Umaal => {
+                self.report_error(EmulatorError::UnimplementedInstruction {
+                    address: old_pc - 8,
+                    mnemonic: "umaal",
+                });
+            }
         }
 
         for _ in 0..4 {
-            self.bus.step();
+            self.bus.step_internal();
         }
         self.write_register(old_pc + 4, Register::R15);
     }
@@ -2805,7 +2779,8 @@ impl Display for ArmInstruction {
                 MultiplyOperation::Umaal => write!(f, "umaal TODO"),
             },
             ArmInstructionType::Swi { comment } => write!(f, "swi #{}", comment),
-            ArmInstructionType::Blx { .. } => todo!("display blx"),
+            ArmInstructionType::Bkpt { comment } => write!(f, "bkpt #{}", comment),
+            ArmInstructionType::Blx { operand } => write!(f, "blx{} {}", self.condition, operand),
             ArmInstructionType::Swp {
                 access_size,
                 base_register,
@@ -2825,6 +2800,60 @@ impl Display for ArmInstruction {
                 )?;
                 Ok(())
             }
+            ArmInstructionType::Cdp {
+                coprocessor,
+                opcode_1,
+                destination_register,
+                first_operand,
+                second_operand,
+                opcode_2,
+            } => write!(
+                f,
+                "cdp{} p{}, {}, c{}, c{}, c{}, {}",
+                self.condition,
+                coprocessor,
+                opcode_1,
+                destination_register,
+                first_operand,
+                second_operand,
+                opcode_2
+            ),
+            ArmInstructionType::Mrc {
+                coprocessor,
+                opcode_1,
+                destination_register,
+                first_operand,
+                second_operand,
+                opcode_2,
+            } => write!(
+                f,
+                "mrc{} p{}, {}, {}, c{}, c{}, {}",
+                self.condition,
+                coprocessor,
+                opcode_1,
+                destination_register,
+                first_operand,
+                second_operand,
+                opcode_2
+            ),
+            ArmInstructionType::Mcr {
+                coprocessor,
+                opcode_1,
+                source_register,
+                first_operand,
+                second_operand,
+                opcode_2,
+            } => write!(
+                f,
+                "mcr{} p{}, {}, {}, c{}, c{}, {}",
+                self.condition,
+                coprocessor,
+                opcode_1,
+                source_register,
+                first_operand,
+                second_operand,
+                opcode_2
+            ),
             ArmInstructionType::Invalid { opcode } => write!(f, "INVALID 0x{opcode:08X}"),
         }
     }
@@ -2925,3 +2954,35 @@ impl Display for MsrSourceInfo {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{CpuConfig, CpuMode};
+    use crate::error::ErrorPolicy;
+    use crate::tests::build_arm_test_cpu;
+
+    #[test]
+    fn coprocessor_instructions_are_undefined() {
+        let build_cpu = |opcode: u32| {
+            let mut cpu = build_arm_test_cpu(&[opcode]);
+            // Unlike invalid opcodes, these are emulated, so they never trap.
+            cpu.set_config(CpuConfig {
+                error_policy: ErrorPolicy::Trap,
+                ..CpuConfig::default()
+            });
+            cpu
+        };
+
+        for (opcode, disassembly) in [
+            (0xEE100F10, "mrc p15, 0, r0, c0, c0, 0"),
+            (0xEE0E1E31, "mcr p14, 0, r1, c14, c1, 1"),
+            (0xEE412385, "cdp p3, 4, c2, c1, c5, 4"),
+        ] {
+            let mut cpu = build_cpu(opcode);
+            assert_eq!(cpu.disassemble(0x08000000).to_string(), disassembly);
+            assert_eq!(cpu.fetch_decode_execute(), None);
+            assert_eq!(cpu.get_executing_pc(), 0x00000004);
+            assert_eq!(cpu.get_cpu_mode(), CpuMode::Undefined);
+        }
+    }
+}
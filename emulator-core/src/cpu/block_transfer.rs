@@ -0,0 +1,448 @@
+// The register list handling shared by LDM/STM and Thumb's PUSH, POP, LDMIA and STMIA, which are
+// all block transfers with a different fixed addressing mode.
+
+use super::arm::{BlockDataTransferIndexType, OffsetModifierType};
+use super::{Cpu, CpuMode, InstructionSet, Register};
+use crate::bus::BusAccessType;
+
+// The registers a block transfer accesses, lowest address first, and the base it leaves behind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct BlockTransfer {
+    registers: Vec<Register>,
+    start_address: u32,
+    new_base: u32,
+}
+
+impl BlockTransfer {
+    // `register_bit_list` is indexed by register number, so Thumb lists with LR or PC set have to
+    // be widened first.
+    pub(super) fn new(
+        base_address: u32,
+        register_bit_list: &[bool],
+        offset_modifier: OffsetModifierType,
+        index_type: BlockDataTransferIndexType,
+    ) -> Self {
+        let mut registers = register_bit_list
+            .iter()
+            .enumerate()
+            .filter(|(_, &transferred)| transferred)
+            .map(|(register_idx, _)| Register::from_index(register_idx as u32))
+            .collect::<Vec<_>>();
+
+        // Empty Rlist: R15 loaded/stored (ARMv4 only), and Rb=Rb+/-40h (ARMv4-v5). R15 goes
+        // where it would have if all 16 registers were transferred.
+        let transfer_size = if registers.is_empty() {
+            registers.push(Register::R15);
+            0x40
+        } else {
+            4 * registers.len() as u32
+        };
+
+        // Registers are transferred from the lowest address up no matter what, so a decrementing
+        // transfer starts from where it ends up.
+        let (lowest_address, new_base) = match offset_modifier {
+            OffsetModifierType::AddToBase => (base_address, base_address + transfer_size),
+            OffsetModifierType::SubtractFromBase => {
+                let new_base = base_address - transfer_size;
+                (new_base, new_base)
+            }
+        };
+
+        let start_address = match (offset_modifier, index_type) {
+            (OffsetModifierType::AddToBase, BlockDataTransferIndexType::PreIndex)
+            | (OffsetModifierType::SubtractFromBase, BlockDataTransferIndexType::PostIndex) => {
+                lowest_address + 4
+            }
+            (OffsetModifierType::AddToBase, BlockDataTransferIndexType::PostIndex)
+            | (OffsetModifierType::SubtractFromBase, BlockDataTransferIndexType::PreIndex) => {
+                lowest_address
+            }
+        };
+
+        Self {
+            registers,
+            start_address,
+            new_base,
+        }
+    }
+
+    fn accesses(&self) -> impl Iterator<Item = (Register, u32)> + '_ {
+        self.registers
+            .iter()
+            .enumerate()
+            .map(|(i, &register)| (register, self.start_address + 4 * i as u32))
+    }
+}
+
+impl Cpu {
+    // Loads every register, with one N cycle each followed by the I cycle. With `user_bank` (the
+    // S bit of LDM), user mode registers are loaded instead, unless R15 is in the list, in which
+    // case the CPSR is restored from the SPSR along with it.
+    //
+    // Returns whether R15 was loaded, in which case the caller has to refill the pipeline, in
+    // whichever instruction set the CPU is now in.
+    pub(super) fn block_load(
+        &mut self,
+        transfer: &BlockTransfer,
+        base_register: Register,
+        write_back: bool,
+        user_bank: bool,
+    ) -> bool {
+        let r15_loaded = transfer.registers.contains(&Register::R15);
+
+        for (register, address) in transfer.accesses() {
+            // The mis-aligned low bit(s) are ignored, the memory access goes to a forcibly aligned (rounded-down) memory address.
+            let value = self
+                .bus
+                .read_word_address(address, BusAccessType::NonSequential);
+
+            if register == Register::R15 {
+                if user_bank {
+                    let saved_cpsr = self.read_register(Register::Spsr, |_| unreachable!());
+                    self.write_register(saved_cpsr, Register::Cpsr);
+                }
+
+                // There's no interworking on ARMv4, so the low bits are ignored rather than
+                // switching instruction sets.
+                let pc_value = match self.get_instruction_mode() {
+                    InstructionSet::Arm => value & !0b11,
+                    InstructionSet::Thumb => value & !0b1,
+                };
+                self.write_register(pc_value, register);
+            } else if user_bank && !r15_loaded {
+                self.with_user_bank(|cpu| cpu.write_register(value, register));
+            } else {
+                self.write_register(value, register);
+            }
+        }
+
+        // Writeback with Rb included in Rlist: no writeback (LDM/ARMv4).
+        if write_back && !transfer.registers.contains(&base_register) {
+            self.write_register(transfer.new_base, base_register);
+        }
+
+        // Write final register back.
+        // TODO: This may possibly be a merged IS cycle.
+        self.bus.step_internal();
+
+        r15_loaded
+    }
+
+    // Stores every register, with one N cycle each. With `user_bank` (the S bit of STM), user mode
+    // registers are stored instead.
+    pub(super) fn block_store(
+        &mut self,
+        transfer: &BlockTransfer,
+        base_register: Register,
+        write_back: bool,
+        user_bank: bool,
+        pc_calculation: fn(u32) -> u32,
+    ) {
+        for (i, (register, address)) in transfer.accesses().enumerate() {
+            // Writeback with Rb included in Rlist: Store OLD base if Rb is FIRST entry in Rlist, otherwise store NEW base
+            let value = if register == base_register && write_back && i > 0 {
+                transfer.new_base
+            } else if user_bank {
+                self.with_user_bank(|cpu| cpu.read_register(register, pc_calculation))
+            } else {
+                self.read_register(register, pc_calculation)
+            };
+
+            self.bus
+                .write_word_address(value, address, BusAccessType::NonSequential);
+        }
+
+        if write_back {
+            self.write_register(transfer.new_base, base_register);
+        }
+    }
+
+    // Switching modes swaps the banked registers in and out, which leaves the user ones in place
+    // for `f` whatever mode the CPU is in.
+    fn with_user_bank<T>(&mut self, f: impl FnOnce(&mut Cpu) -> T) -> T {
+        let old_mode = self.get_cpu_mode();
+        self.set_cpu_mode(CpuMode::User);
+        let result = f(self);
+        self.set_cpu_mode(old_mode);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::build_arm_test_cpu;
+
+    const BASE_ADDRESS: u32 = 0x03000100;
+
+    fn bit_list(registers: &[u32]) -> [bool; 16] {
+        let mut bit_list = [false; 16];
+        for &register in registers {
+            bit_list[register as usize] = true;
+        }
+        bit_list
+    }
+
+    fn build_transfer(
+        register_bit_list: &[bool],
+        offset_modifier: OffsetModifierType,
+        index_type: BlockDataTransferIndexType,
+    ) -> BlockTransfer {
+        BlockTransfer::new(BASE_ADDRESS, register_bit_list, offset_modifier, index_type)
+    }
+
+    #[test]
+    fn addressing_modes() {
+        let register_bit_list = bit_list(&[1, 2]);
+        for (offset_modifier, index_type, start_address, new_base) in [
+            (
+                OffsetModifierType::AddToBase,
+                BlockDataTransferIndexType::PostIndex,
+                BASE_ADDRESS,
+                BASE_ADDRESS + 8,
+            ),
+            (
+                OffsetModifierType::AddToBase,
+                BlockDataTransferIndexType::PreIndex,
+                BASE_ADDRESS + 4,
+                BASE_ADDRESS + 8,
+            ),
+            (
+                OffsetModifierType::SubtractFromBase,
+                BlockDataTransferIndexType::PostIndex,
+                BASE_ADDRESS - 4,
+                BASE_ADDRESS - 8,
+            ),
+            (
+                OffsetModifierType::SubtractFromBase,
+                BlockDataTransferIndexType::PreIndex,
+                BASE_ADDRESS - 8,
+                BASE_ADDRESS - 8,
+            ),
+        ] {
+            let transfer = build_transfer(&register_bit_list, offset_modifier, index_type);
+            assert_eq!(
+                transfer.accesses().collect::<Vec<_>>(),
+                [
+                    (Register::R1, start_address),
+                    (Register::R2, start_address + 4)
+                ]
+            );
+            assert_eq!(transfer.new_base, new_base);
+        }
+    }
+
+    #[test]
+    fn empty_rlist() {
+        for (offset_modifier, index_type, address, new_base) in [
+            (
+                OffsetModifierType::AddToBase,
+                BlockDataTransferIndexType::PostIndex,
+                BASE_ADDRESS,
+                BASE_ADDRESS + 0x40,
+            ),
+            (
+                OffsetModifierType::AddToBase,
+                BlockDataTransferIndexType::PreIndex,
+                BASE_ADDRESS + 4,
+                BASE_ADDRESS + 0x40,
+            ),
+            (
+                OffsetModifierType::SubtractFromBase,
+                BlockDataTransferIndexType::PostIndex,
+                BASE_ADDRESS - 0x3C,
+                BASE_ADDRESS - 0x40,
+            ),
+            (
+                OffsetModifierType::SubtractFromBase,
+                BlockDataTransferIndexType::PreIndex,
+                BASE_ADDRESS - 0x40,
+                BASE_ADDRESS - 0x40,
+            ),
+        ] {
+            let transfer = build_transfer(&[false; 16], offset_modifier, index_type);
+            assert_eq!(
+                transfer.accesses().collect::<Vec<_>>(),
+                [(Register::R15, address)]
+            );
+            assert_eq!(transfer.new_base, new_base);
+        }
+
+        // R15 is the one register transferred.
+        let mut cpu = build_arm_test_cpu(&[]);
+        cpu.write_register(BASE_ADDRESS, Register::R0);
+        let transfer = build_transfer(
+            &[false; 16],
+            OffsetModifierType::AddToBase,
+            BlockDataTransferIndexType::PostIndex,
+        );
+        cpu.block_store(&transfer, Register::R0, true, false, |pc| pc + 4);
+        assert_eq!(
+            cpu.bus.read_word_address_debug(BASE_ADDRESS),
+            cpu.read_register(Register::R15, |pc| pc + 4)
+        );
+        assert_eq!(
+            cpu.read_register(Register::R0, |pc| pc),
+            BASE_ADDRESS + 0x40
+        );
+
+        cpu.bus.write_word_address_debug(0x08000100, BASE_ADDRESS);
+        cpu.write_register(BASE_ADDRESS, Register::R0);
+        assert!(cpu.block_load(&transfer, Register::R0, true, false));
+        assert_eq!(cpu.read_register(Register::R15, |pc| pc), 0x08000100);
+        assert_eq!(
+            cpu.read_register(Register::R0, |pc| pc),
+            BASE_ADDRESS + 0x40
+        );
+    }
+
+    #[test]
+    fn base_in_rlist_write_back() {
+        let mut cpu = build_arm_test_cpu(&[]);
+        let pc_calculation: fn(u32) -> u32 = |pc| pc + 4;
+
+        // Stores the old base when it's the first register, and the new one otherwise.
+        for (registers, base_register, stored_base) in [
+            (&[0, 1][..], Register::R0, BASE_ADDRESS),
+            (&[0, 1][..], Register::R1, BASE_ADDRESS + 8),
+        ] {
+            cpu.write_register(0x1234, Register::R0);
+            cpu.write_register(0x5678, Register::R1);
+            cpu.write_register(BASE_ADDRESS, base_register);
+
+            let transfer = build_transfer(
+                &bit_list(registers),
+                OffsetModifierType::AddToBase,
+                BlockDataTransferIndexType::PostIndex,
+            );
+            cpu.block_store(&transfer, base_register, true, false, pc_calculation);
+
+            let stored_base_address = match base_register {
+                Register::R0 => BASE_ADDRESS,
+                _ => BASE_ADDRESS + 4,
+            };
+            assert_eq!(
+                cpu.bus.read_word_address_debug(stored_base_address),
+                stored_base
+            );
+            assert_eq!(cpu.read_register(base_register, |pc| pc), BASE_ADDRESS + 8);
+        }
+
+        // Without writeback, the old base is always stored.
+        cpu.write_register(0x1234, Register::R0);
+        cpu.write_register(BASE_ADDRESS, Register::R1);
+        let transfer = build_transfer(
+            &bit_list(&[0, 1]),
+            OffsetModifierType::AddToBase,
+            BlockDataTransferIndexType::PostIndex,
+        );
+        cpu.block_store(&transfer, Register::R1, false, false, pc_calculation);
+        assert_eq!(
+            cpu.bus.read_word_address_debug(BASE_ADDRESS + 4),
+            BASE_ADDRESS
+        );
+        assert_eq!(cpu.read_register(Register::R1, |pc| pc), BASE_ADDRESS);
+
+        // Loading the base leaves the loaded value rather than writing back.
+        cpu.bus.write_word_address_debug(0xAAAA, BASE_ADDRESS);
+        cpu.bus.write_word_address_debug(0xBBBB, BASE_ADDRESS + 4);
+        cpu.write_register(BASE_ADDRESS, Register::R1);
+        assert!(!cpu.block_load(&transfer, Register::R1, true, false));
+        assert_eq!(cpu.read_register(Register::R0, |pc| pc), 0xAAAA);
+        assert_eq!(cpu.read_register(Register::R1, |pc| pc), 0xBBBB);
+    }
+
+    #[test]
+    fn user_bank_transfer() {
+        let banked_registers = bit_list(&[8, 9, 10, 11, 12, 13, 14]);
+        let transfer = build_transfer(
+            &banked_registers,
+            OffsetModifierType::AddToBase,
+            BlockDataTransferIndexType::PostIndex,
+        );
+
+        for mode in [
+            CpuMode::Fiq,
+            CpuMode::Irq,
+            CpuMode::Supervisor,
+            CpuMode::Abort,
+            CpuMode::Undefined,
+            CpuMode::System,
+        ] {
+            // Registers that the mode banks are left alone, the rest are shared with user mode.
+            let banked = match mode {
+                CpuMode::Fiq => 8..15,
+                CpuMode::System => 15..15,
+                _ => 13..15,
+            };
+
+            let mut cpu = build_arm_test_cpu(&[]);
+            cpu.set_cpu_mode(CpuMode::User);
+            for i in 8..15 {
+                cpu.write_register(0x100 + i, Register::from_index(i));
+            }
+            cpu.set_cpu_mode(mode);
+            for i in 8..15 {
+                cpu.write_register(0x200 + i, Register::from_index(i));
+            }
+            cpu.write_register(BASE_ADDRESS, Register::R0);
+
+            cpu.block_store(&transfer, Register::R0, false, true, |pc| pc + 4);
+            for (i, address) in (8..15).zip((BASE_ADDRESS..).step_by(4)) {
+                assert_eq!(
+                    cpu.bus.read_word_address_debug(address),
+                    (if banked.contains(&i) { 0x100 } else { 0x200 }) + i,
+                    "{mode:?}"
+                );
+            }
+
+            for (i, address) in (8..15).zip((BASE_ADDRESS..).step_by(4)) {
+                cpu.bus.write_word_address_debug(0x300 + i, address);
+            }
+            assert!(!cpu.block_load(&transfer, Register::R0, false, true));
+            assert_eq!(cpu.get_cpu_mode(), mode);
+
+            for i in 8..15 {
+                let expected = (if banked.contains(&i) { 0x200 } else { 0x300 }) + i;
+                assert_eq!(
+                    cpu.read_register(Register::from_index(i), |pc| pc),
+                    expected,
+                    "{mode:?} r{i}"
+                );
+            }
+
+            cpu.set_cpu_mode(CpuMode::User);
+            for i in 8..15 {
+                assert_eq!(
+                    cpu.read_register(Register::from_index(i), |pc| pc),
+                    0x300 + i,
+                    "{mode:?} r{i}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn user_bank_load_with_r15_restores_cpsr() {
+        let mut cpu = build_arm_test_cpu(&[]);
+        cpu.set_cpu_mode(CpuMode::Supervisor);
+        let saved_cpsr = cpu.read_register(Register::Cpsr, |_| unreachable!());
+        cpu.write_register((saved_cpsr & !0b11111) | 0b10000 | 0b100000, Register::Spsr);
+        cpu.write_register(BASE_ADDRESS, Register::R0);
+        cpu.bus.write_word_address_debug(0x1234, BASE_ADDRESS);
+        cpu.bus
+            .write_word_address_debug(0x08000101, BASE_ADDRESS + 4);
+
+        let transfer = build_transfer(
+            &bit_list(&[1, 15]),
+            OffsetModifierType::AddToBase,
+            BlockDataTransferIndexType::PostIndex,
+        );
+        assert!(cpu.block_load(&transfer, Register::R0, false, true));
+
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::User);
+        assert!(matches!(cpu.get_instruction_mode(), InstructionSet::Thumb));
+        assert_eq!(cpu.read_register(Register::R15, |pc| pc), 0x08000100);
+        assert_eq!(cpu.read_register(Register::R1, |pc| pc), 0x1234);
+    }
+}
@@ -0,0 +1,52 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameKind {
+    Call, // BL or BLX
+    Swi,
+    Interrupt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub call_site: u32, // the branch or SWI, or the instruction that was interrupted
+    pub target: u32,
+    pub return_address: u32,
+}
+
+// Calls are tracked as they're made, and considered to have returned once execution branches
+// back to their return address. This is only a heuristic, since nothing forces code to return
+// the way it was called, but it holds up for compiler generated code.
+#[derive(Clone, Debug, Default)]
+pub(super) struct CallStack {
+    frames: Vec<Frame>, // innermost last
+}
+
+impl CallStack {
+    // Code that never returns, like a scheduler switching stacks, would otherwise grow this
+    // forever.
+    const MAX_DEPTH: usize = 256;
+
+    pub(super) fn push(&mut self, frame: Frame) {
+        if self.frames.len() == Self::MAX_DEPTH {
+            self.frames.remove(0);
+        }
+
+        self.frames.push(frame);
+    }
+
+    // Unwinds to the innermost frame returning to the given address, if there is one. Frames
+    // above it are assumed to have returned through some other path.
+    pub(super) fn branched_to(&mut self, address: u32) {
+        if let Some(index) = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_address == address)
+        {
+            self.frames.truncate(index);
+        }
+    }
+
+    pub(super) fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+}
@@ -0,0 +1,129 @@
+// Detects loops that spin waiting for something else to happen, like polling VCOUNT or a flag set
+// by an interrupt handler, and fast-forwards through them by only clocking the bus, as if the CPU
+// were halted.
+//
+// A loop is idle if an iteration leaves every register exactly as it found it and doesn't write
+// to memory. Each following iteration will then do exactly the same thing, until one of the values
+// it read changes or an interrupt is taken, so that's all that has to be watched for.
+
+use super::{Cpu, Register};
+use crate::bus::LoopAccesses;
+use crate::CYCLES_PER_FRAME;
+
+impl Cpu {
+    // Loops longer than this, in bytes, are rarely idle, so aren't checked unless they're in the
+    // compatibility database.
+    const MAX_IDLE_LOOP_LENGTH: u32 = 32;
+
+    // Bounds a single skip, so that callers stepping the CPU still get control back regularly.
+    const MAX_IDLE_LOOP_SKIP_CYCLES: u64 = CYCLES_PER_FRAME;
+
+    // Called after each instruction. `interrupted` is set if the step entered an exception or a
+    // call, which ends any iteration being checked.
+    pub(super) fn check_idle_loop(&mut self, executing_pc: u32, next_pc: u32, interrupted: bool) {
+        if !self.config.skip_idle_loops {
+            return;
+        }
+
+        let known_idle_loop = self.bus.cartridge.overrides().idle_loop;
+
+        let backward_branch = next_pc <= executing_pc;
+        let is_loop_head = backward_branch
+            && !interrupted
+            && match known_idle_loop {
+                Some(address) => next_pc == address,
+                None => executing_pc - next_pc <= Self::MAX_IDLE_LOOP_LENGTH,
+            };
+
+        if !is_loop_head {
+            // Leaving the loop, or taking an exception from inside of it.
+            let left_loop = self.idle_loop.as_ref().is_some_and(|candidate| {
+                interrupted
+                    || next_pc < candidate.head
+                    || (next_pc - candidate.head > Self::MAX_IDLE_LOOP_LENGTH
+                        && known_idle_loop != Some(candidate.head))
+            });
+            if left_loop {
+                self.idle_loop = None;
+                self.bus.loop_accesses = None;
+            }
+            return;
+        }
+
+        let state = self.idle_loop_state();
+        let accesses = self.bus.loop_accesses.take();
+        let idle = match (self.idle_loop, accesses) {
+            (Some(candidate), Some(accesses))
+                if candidate.head == next_pc
+                    && candidate.state == state
+                    && candidate.dma_units == self.bus.perf_counters.dma_units_transferred
+                    && !accesses.written =>
+            {
+                self.skip_idle_loop(accesses.reads);
+                true
+            }
+            _ => false,
+        };
+
+        // Either way, the next iteration is checked from scratch.
+        self.idle_loop = (!idle).then_some(IdleLoopCandidate {
+            head: next_pc,
+            state,
+            dma_units: self.bus.perf_counters.dma_units_transferred,
+        });
+        self.bus.loop_accesses = (!idle).then(LoopAccesses::default);
+    }
+
+    // Clocks the bus until one of `reads` changes, an interrupt would be taken, or a frame
+    // completes, leaving the CPU at the start of the loop to run it for real.
+    fn skip_idle_loop(&mut self, mut reads: Vec<(u32, u32)>) {
+        reads.sort_unstable();
+        reads.dedup();
+
+        let read_value = |cpu: &Self, address: u32, width: u32| match width {
+            1 => u32::from(cpu.bus.read_byte_address_debug(address)),
+            2 => u32::from(cpu.bus.read_halfword_address_debug(address)),
+            _ => cpu.bus.read_word_address_debug(address),
+        };
+
+        // Each read along with the value it saw, checked in place after every step.
+        let watched: Vec<(u32, u32, u32)> = reads
+            .into_iter()
+            .map(|(address, width)| (address, width, read_value(self, address, width)))
+            .collect();
+
+        let start = self.bus.cycle_count();
+        while self.bus.cycle_count() - start < Self::MAX_IDLE_LOOP_SKIP_CYCLES {
+            self.bus.step();
+
+            if (!self.get_irq_disable() && self.bus.get_irq_pending())
+                || self.bus.frame_completed()
+                || watched
+                    .iter()
+                    .any(|&(address, width, value)| read_value(self, address, width) != value)
+            {
+                break;
+            }
+        }
+
+        self.bus.perf_counters.idle_loop_cycles_skipped += self.bus.cycle_count() - start;
+    }
+
+    fn idle_loop_state(&self) -> [u32; 17] {
+        let mut state = [0; 17];
+        for (index, value) in state.iter_mut().take(16).enumerate() {
+            *value = self.read_register(Register::from_index(index as u32), |pc| pc);
+        }
+        state[16] = self.cpsr;
+
+        state
+    }
+}
+
+// The start of a possible idle loop, along with the state it should be back to after an iteration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct IdleLoopCandidate {
+    head: u32,
+    state: [u32; 17], // r0-r15 as seen by the current mode, then CPSR
+    dma_units: u64,   // DMA can change memory without the loop writing to it
+}
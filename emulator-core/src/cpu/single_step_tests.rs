@@ -0,0 +1,291 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+
+use super::{arm::decode_arm, thumb::decode_thumb, Cpu, InstructionSet};
+use crate::bus::{FlatMemory, MemoryAccess};
+
+// Per-instruction test vectors from https://github.com/SingleStepTests/ARM7TDMI. They aren't
+// checked in due to their size, and can be placed here either as plain or gzipped JSON.
+const TEST_VECTOR_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/single_step");
+
+const MAX_REPORTED_FAILURES: usize = 20;
+
+// Instruction fetches (0) and data reads (1) are both just reads as far as we're concerned.
+const TRANSACTION_KIND_WRITE: u32 = 2;
+
+#[derive(Deserialize)]
+struct TestCase {
+    initial: CpuState,
+    #[serde(rename = "final")]
+    final_state: CpuState,
+    transactions: Vec<Transaction>,
+    opcode: u32,
+}
+
+#[derive(Deserialize)]
+struct CpuState {
+    #[serde(rename = "R")]
+    r: [u32; 16], // user and system mode registers
+    #[serde(rename = "R_fiq")]
+    r_fiq: [u32; 7], // r8-r14
+    #[serde(rename = "R_svc")]
+    r_svc: [u32; 2], // r13-r14
+    #[serde(rename = "R_abt")]
+    r_abt: [u32; 2],
+    #[serde(rename = "R_irq")]
+    r_irq: [u32; 2],
+    #[serde(rename = "R_und")]
+    r_und: [u32; 2],
+    #[serde(rename = "CPSR")]
+    cpsr: u32,
+    #[serde(rename = "SPSR")]
+    spsr: [u32; 5], // fiq, svc, abt, irq, und
+    pipeline: [u32; 2],
+}
+
+impl CpuState {
+    fn named_registers(&self) -> Vec<(String, u32)> {
+        let mut registers = Vec::new();
+
+        for (i, value) in self.r.into_iter().enumerate() {
+            registers.push((format!("r{i}"), value));
+        }
+        for (i, value) in self.r_fiq.into_iter().enumerate() {
+            registers.push((format!("r{}_fiq", i + 8), value));
+        }
+        for (mode, banked) in [
+            ("svc", self.r_svc),
+            ("abt", self.r_abt),
+            ("irq", self.r_irq),
+            ("und", self.r_und),
+        ] {
+            registers.push((format!("r13_{mode}"), banked[0]));
+            registers.push((format!("r14_{mode}"), banked[1]));
+        }
+
+        registers.push(("cpsr".to_string(), self.cpsr));
+        for (mode, value) in ["fiq", "svc", "abt", "irq", "und"]
+            .into_iter()
+            .zip(self.spsr)
+        {
+            registers.push((format!("spsr_{mode}"), value));
+        }
+
+        registers
+    }
+}
+
+#[derive(Deserialize)]
+struct Transaction {
+    kind: u32,
+    size: u32,
+    addr: u32,
+    data: u32,
+}
+
+impl Transaction {
+    fn to_memory_access(&self) -> MemoryAccess {
+        MemoryAccess {
+            address: self.addr,
+            size: self.size,
+            data: mask_to_size(self.data, self.size),
+        }
+    }
+}
+
+fn mask_to_size(data: u32, size: u32) -> u32 {
+    match size {
+        1 => data & 0xFF,
+        2 => data & 0xFFFF,
+        _ => data,
+    }
+}
+
+impl Cpu {
+    fn load_single_step_state(&mut self, state: &CpuState) {
+        [
+            self.r0, self.r1, self.r2, self.r3, self.r4, self.r5, self.r6, self.r7, self.r8,
+            self.r9, self.r10, self.r11, self.r12, self.r13, self.r14, self.r15,
+        ] = state.r;
+        [
+            self.r8_fiq,
+            self.r9_fiq,
+            self.r10_fiq,
+            self.r11_fiq,
+            self.r12_fiq,
+            self.r13_fiq,
+            self.r14_fiq,
+        ] = state.r_fiq;
+        [self.r13_svc, self.r14_svc] = state.r_svc;
+        [self.r13_abt, self.r14_abt] = state.r_abt;
+        [self.r13_irq, self.r14_irq] = state.r_irq;
+        [self.r13_und, self.r14_und] = state.r_und;
+        [
+            self.spsr_fiq,
+            self.spsr_svc,
+            self.spsr_abt,
+            self.spsr_irq,
+            self.spsr_und,
+        ] = state.spsr;
+
+        self.cpsr = state.cpsr;
+        self.load_mode_registers(self.get_cpu_mode());
+
+        match self.get_instruction_mode() {
+            InstructionSet::Arm => self.pre_decode_arm = decode_arm(state.pipeline[0]),
+            InstructionSet::Thumb => self.pre_decode_thumb = decode_thumb(state.pipeline[0] as u16),
+        }
+        self.prefetch_opcode = state.pipeline[1];
+    }
+
+    // The pipeline is pre-decoded, so isn't included.
+    fn single_step_state(&mut self) -> CpuState {
+        self.store_mode_registers(self.get_cpu_mode());
+
+        CpuState {
+            r: [
+                self.r0, self.r1, self.r2, self.r3, self.r4, self.r5, self.r6, self.r7, self.r8,
+                self.r9, self.r10, self.r11, self.r12, self.r13, self.r14, self.r15,
+            ],
+            r_fiq: [
+                self.r8_fiq,
+                self.r9_fiq,
+                self.r10_fiq,
+                self.r11_fiq,
+                self.r12_fiq,
+                self.r13_fiq,
+                self.r14_fiq,
+            ],
+            r_svc: [self.r13_svc, self.r14_svc],
+            r_abt: [self.r13_abt, self.r14_abt],
+            r_irq: [self.r13_irq, self.r14_irq],
+            r_und: [self.r13_und, self.r14_und],
+            cpsr: self.cpsr,
+            spsr: [
+                self.spsr_fiq,
+                self.spsr_svc,
+                self.spsr_abt,
+                self.spsr_irq,
+                self.spsr_und,
+            ],
+            pipeline: [0; 2],
+        }
+    }
+}
+
+// Returns a description of every difference from the expected final state.
+fn run_test_case(test_case: &TestCase) -> Vec<String> {
+    let mut flat_memory = FlatMemory::default();
+    for transaction in &test_case.transactions {
+        if transaction.kind != TRANSACTION_KIND_WRITE {
+            flat_memory.store(transaction.addr, transaction.size, transaction.data);
+        }
+    }
+
+    let mut cpu = Cpu::with_flat_memory(flat_memory);
+    cpu.load_single_step_state(&test_case.initial);
+    cpu.fetch_decode_execute();
+
+    let expected_registers = test_case.final_state.named_registers();
+    let actual_registers = cpu.single_step_state().named_registers();
+
+    let mut differences = expected_registers
+        .into_iter()
+        .zip(actual_registers)
+        .filter(|((_, expected), (_, actual))| expected != actual)
+        .map(|((name, expected), (_, actual))| {
+            format!("{name}: expected {expected:08X}, got {actual:08X}")
+        })
+        .collect::<Vec<_>>();
+
+    let expected_writes = test_case
+        .transactions
+        .iter()
+        .filter(|transaction| transaction.kind == TRANSACTION_KIND_WRITE)
+        .map(Transaction::to_memory_access)
+        .collect::<Vec<_>>();
+    let actual_writes = cpu
+        .bus
+        .flat_memory
+        .as_ref()
+        .unwrap()
+        .writes()
+        .iter()
+        .map(|write| MemoryAccess {
+            data: mask_to_size(write.data, write.size),
+            ..*write
+        })
+        .collect::<Vec<_>>();
+
+    if expected_writes != actual_writes {
+        differences.push(format!(
+            "writes: expected {expected_writes:X?}, got {actual_writes:X?}"
+        ));
+    }
+
+    differences
+}
+
+fn load_test_cases(path: &Path) -> Vec<TestCase> {
+    let file = BufReader::new(File::open(path).unwrap());
+
+    let reader: Box<dyn Read> = if path.extension().is_some_and(|extension| extension == "gz") {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    serde_json::from_reader(reader)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {e}", path.display()))
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests ARM7TDMI vectors in tests/single_step"]
+fn single_step_tests() {
+    let mut paths = fs::read_dir(TEST_VECTOR_DIRECTORY)
+        .expect("missing single step test vector directory")
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut failures = Vec::new();
+    let mut total = 0;
+
+    for path in paths {
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+
+        for (i, test_case) in load_test_cases(&path).iter().enumerate() {
+            total += 1;
+
+            // Unimplemented instructions panic, which shouldn't stop the remaining tests from
+            // running.
+            let differences = panic::catch_unwind(AssertUnwindSafe(|| run_test_case(test_case)))
+                .unwrap_or_else(|_| vec!["panicked".to_string()]);
+
+            if !differences.is_empty() {
+                failures.push(format!(
+                    "{file_name} #{i} (opcode {:08X}): {}",
+                    test_case.opcode,
+                    differences.join(", ")
+                ));
+            }
+        }
+    }
+
+    for failure in failures.iter().take(MAX_REPORTED_FAILURES) {
+        println!("{failure}");
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {total} single step tests failed",
+        failures.len()
+    );
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+
+// Named by SWI number, as in GBATEK.
+const BIOS_FUNCTION_NAMES: [&str; 0x2B] = [
+    "SoftReset",
+    "RegisterRamReset",
+    "Halt",
+    "Stop",
+    "IntrWait",
+    "VBlankIntrWait",
+    "Div",
+    "DivArm",
+    "Sqrt",
+    "ArcTan",
+    "ArcTan2",
+    "CpuSet",
+    "CpuFastSet",
+    "GetBiosChecksum",
+    "BgAffineSet",
+    "ObjAffineSet",
+    "BitUnPack",
+    "LZ77UnCompWram",
+    "LZ77UnCompVram",
+    "HuffUnComp",
+    "RLUnCompWram",
+    "RLUnCompVram",
+    "Diff8bitUnFilterWram",
+    "Diff8bitUnFilterVram",
+    "Diff16bitUnFilter",
+    "SoundBias",
+    "SoundDriverInit",
+    "SoundDriverMode",
+    "SoundDriverMain",
+    "SoundDriverVSync",
+    "SoundChannelClear",
+    "MidiKey2Freq",
+    "SoundWhatever0",
+    "SoundWhatever1",
+    "SoundWhatever2",
+    "SoundWhatever3",
+    "SoundWhatever4",
+    "MultiBoot",
+    "HardReset",
+    "CustomHalt",
+    "SoundDriverVSyncOff",
+    "SoundDriverVSyncOn",
+    "SoundGetJumpList",
+];
+
+// The BIOS function a SWI number calls, if it's one of the documented ones.
+pub fn bios_function_name(number: u32) -> Option<&'static str> {
+    BIOS_FUNCTION_NAMES.get(number as usize).copied()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SwiCount {
+    pub calls: u64,
+    pub handled_by_hook: u64, // skipped the BIOS, see `Cpu::on_swi`
+}
+
+// How often each BIOS function has been called, which shows both what a game leans on and how
+// much of that a SWI hook already covers. AGBPrint flushes are handled by the emulator, so
+// they aren't counted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SwiStats {
+    counts: BTreeMap<u32, SwiCount>, // by SWI number
+}
+
+impl SwiStats {
+    pub fn counts(&self) -> &BTreeMap<u32, SwiCount> {
+        &self.counts
+    }
+
+    pub fn total_calls(&self) -> u64 {
+        self.counts.values().map(|count| count.calls).sum()
+    }
+
+    // The most called functions first, ties going to the lower SWI number.
+    pub fn by_calls(&self) -> Vec<(u32, SwiCount)> {
+        let mut counts = self
+            .counts
+            .iter()
+            .map(|(&number, &count)| (number, count))
+            .collect::<Vec<_>>();
+        counts.sort_by_key(|(number, count)| (std::cmp::Reverse(count.calls), *number));
+        counts
+    }
+
+    pub(super) fn record(&mut self, number: u32, handled_by_hook: bool) {
+        let count = self.counts.entry(number).or_default();
+        count.calls += 1;
+        if handled_by_hook {
+            count.handled_by_hook += 1;
+        }
+    }
+}
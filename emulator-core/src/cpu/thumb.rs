@@ -1,10 +1,13 @@
 use crate::{bus::BusAccessType, cpu::arm::decode_arm, BitManipulation, InstructionSet};
+use serde::{Deserialize, Serialize};
 
+use super::arm::{BlockDataTransferIndexType, OffsetModifierType};
+use super::block_transfer::BlockTransfer;
 use super::{Cpu, ExceptionType, InstructionCondition, Register, ShiftType};
 
-use std::{cmp::Ordering, fmt::Display, ops::RangeInclusive};
+use std::{fmt::Display, ops::RangeInclusive};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum ThumbRegisterOperation {
     Lsl,
     Lsr,
@@ -27,14 +30,14 @@ pub enum ThumbRegisterOperation {
     Mvn,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum ThumbHighRegisterOperation {
     Add,
     Cmp,
     Mov,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum ThumbRegisterOrImmediate {
     Immediate(u32),
     Register(Register),
@@ -55,14 +58,14 @@ impl Cpu {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum ThumbLoadStoreDataSize {
     Byte,
     HalfWord,
     Word,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum ThumbInstructionType {
     Ldr {
         base_register: Register,
@@ -98,6 +101,9 @@ pub enum ThumbInstructionType {
     BlPartTwo {
         offset: u16,
     },
+    BlxPartTwo {
+        offset: u16,
+    },
     Bx {
         operand: Register,
     },
@@ -129,12 +135,16 @@ pub enum ThumbInstructionType {
     Swi {
         comment: u16,
     },
+    // ARMv5, which the ARM7TDMI doesn't have. Treated as a debugger trap, see `StepEvent`.
+    Bkpt {
+        comment: u16,
+    },
     Invalid {
         opcode: u16,
     },
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct ThumbInstruction {
     pub instruction_type: ThumbInstructionType,
 }
@@ -145,7 +155,7 @@ fn get_register_at_offset(opcode: u16, offset: usize) -> Register {
     Register::from_index(u32::from(register_index))
 }
 
-pub(super) fn decode_thumb(opcode: u16) -> ThumbInstruction {
+pub fn decode_thumb(opcode: u16) -> ThumbInstruction {
     let maybe_instruction_type = None
         .or_else(|| try_decode_thumb_register_operation(opcode))
         .or_else(|| try_decode_thumb_memory_load_store(opcode))
@@ -814,6 +824,7 @@ fn try_decode_thumb_jump_call(opcode: u16) -> Option<ThumbInstructionType> {
         .or_else(|| try_decode_thumb_long_branch_link_1(opcode))
         .or_else(|| try_decode_thumb_long_branch_link_2(opcode))
         .or_else(|| try_decode_thumb_swi(opcode))
+        .or_else(|| try_decode_thumb_breakpoint(opcode))
 }
 
 fn try_decode_thumb_conditional_branch(opcode: u16) -> Option<ThumbInstructionType> {
@@ -902,16 +913,19 @@ fn try_decode_thumb_long_branch_link_1(opcode: u16) -> Option<ThumbInstructionTy
 //      11101b: BLX label  ;branch long with link switch to ARM mode (ARM9) (UNUSED)
 // 10-0   nn - Lower 11 bits of Target Address (BLX: Bit0 Must be zero)
 fn try_decode_thumb_long_branch_link_2(opcode: u16) -> Option<ThumbInstructionType> {
-    const OPCODE_MUST_BE_11111_BIT_RANGE: RangeInclusive<usize> = 11..=15;
+    const OPCODE_BIT_RANGE: RangeInclusive<usize> = 11..=15;
     const OPCODE_TARGET_ADDRESS_LOWER_11_BITS_RANGE: RangeInclusive<usize> = 0..=10;
-
-    if opcode.get_bit_range(OPCODE_MUST_BE_11111_BIT_RANGE) != 0b11111 {
-        return None;
-    }
+    const BLX_MUST_BE_ZERO_BIT_INDEX: usize = 0;
 
     let offset = opcode.get_bit_range(OPCODE_TARGET_ADDRESS_LOWER_11_BITS_RANGE) << 1;
 
-    Some(ThumbInstructionType::BlPartTwo { offset })
+    match opcode.get_bit_range(OPCODE_BIT_RANGE) {
+        0b11111 => Some(ThumbInstructionType::BlPartTwo { offset }),
+        0b11101 if !opcode.get_bit(BLX_MUST_BE_ZERO_BIT_INDEX) => {
+            Some(ThumbInstructionType::BlxPartTwo { offset })
+        }
+        _ => None,
+    }
 }
 
 fn try_decode_thumb_swi(opcode: u16) -> Option<ThumbInstructionType> {
@@ -927,7 +941,21 @@ fn try_decode_thumb_swi(opcode: u16) -> Option<ThumbInstructionType> {
     Some(ThumbInstructionType::Swi { comment })
 }
 
+fn try_decode_thumb_breakpoint(opcode: u16) -> Option<ThumbInstructionType> {
+    const MUST_BE_10111110_BIT_RANGE: RangeInclusive<usize> = 8..=15;
+    const COMMENT_BIT_RANGE: RangeInclusive<usize> = 0..=7;
+
+    if opcode.get_bit_range(MUST_BE_10111110_BIT_RANGE) != 0b10111110 {
+        return None;
+    }
+
+    let comment = opcode.get_bit_range(COMMENT_BIT_RANGE);
+
+    Some(ThumbInstructionType::Bkpt { comment })
+}
+
 impl Cpu {
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub(super) fn execute_thumb(&mut self, instruction: ThumbInstruction) {
         match instruction.instruction_type {
             ThumbInstructionType::Register {
@@ -972,6 +1000,7 @@ impl Cpu {
             }
             ThumbInstructionType::BlPartOne { offset } => self.execute_thumb_bl_part_1(offset),
             ThumbInstructionType::BlPartTwo { offset } => self.execute_thumb_bl_part_2(offset),
+            ThumbInstructionType::BlxPartTwo { offset } => self.execute_thumb_blx_part_2(offset),
             ThumbInstructionType::Bx { operand } => self.execute_thumb_bx(operand),
             ThumbInstructionType::Push {
                 register_bit_list,
@@ -1001,8 +1030,12 @@ impl Cpu {
                 unsigned_offset,
             ),
             ThumbInstructionType::Swi { comment: _ } => self.handle_exception(ExceptionType::Swi),
-            ThumbInstructionType::Invalid { opcode } => unreachable!("Invalid(0x{:04X})", opcode),
-            _ => todo!("{:#016x?}", instruction),
+            // Executed as a no-op once the debugger has been told about it.
+            ThumbInstructionType::Bkpt { .. } => self.skip_instruction(),
+            // BLX was introduced in ARMv5, and is undefined on the ARM7TDMI.
+            ThumbInstructionType::Blx { .. } | ThumbInstructionType::Invalid { .. } => {
+                self.handle_exception(ExceptionType::Undefined)
+            }
         }
     }
 }
@@ -1457,7 +1490,7 @@ impl Cpu {
         // cycle 3: write back into result register.
         // TODO: This may possibly be merged IS cycle.
         self.write_register(result_value, destination_register);
-        self.bus.step();
+        self.bus.step_internal();
 
         // Assert that we never write out to R15, so we can unconditionally advance PC.
         assert!(!matches!(destination_register, Register::R15));
@@ -1543,6 +1576,10 @@ impl Cpu {
 
     // PC = LR + (nn SHL 1), and LR = PC+2 OR 1
     // PC = $ + 4 already due to prefetch
+    //
+    // The two halves are independent instructions, and an interrupt may be taken between them.
+    // This is fine since the upper half of the target only lives in the LR of the current mode,
+    // which exception entry never touches, and is still there once the handler returns.
     fn execute_thumb_bl_part_2(&mut self, offset: u16) {
         // cycle 1: prefetch next instruction
         let old_pc = self.read_register(Register::R15, |pc| pc);
@@ -1562,6 +1599,34 @@ impl Cpu {
         self.write_register(new_pc + 4, Register::R15);
     }
 
+    // PC = (LR + (nn SHL 1)) AND NOT 3, LR = PC+2 OR 1, and switch to ARM state.
+    // PC = $ + 4 already due to prefetch
+    //
+    // BLX was only introduced in ARMv5, but is implemented for completeness so that interworking
+    // veneers emitted for newer cores still run.
+    fn execute_thumb_blx_part_2(&mut self, offset: u16) {
+        // cycle 1: prefetch next instruction
+        let old_pc = self.read_register(Register::R15, |pc| pc);
+        self.pre_decode_thumb = decode_thumb(self.prefetch_opcode as u16);
+        self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(old_pc));
+
+        let old_lr = self.read_register(Register::R14, |_| unreachable!());
+
+        let new_pc = old_lr.wrapping_add(u32::from(offset)) & (!0b11);
+        let new_lr = (old_pc - 2) | 1;
+
+        self.write_register(new_lr, Register::R14);
+        self.set_instruction_mode(InstructionSet::Arm);
+
+        // cycle 2
+        self.pre_decode_arm = decode_arm(self.bus.fetch_arm_opcode(new_pc));
+
+        // cycle 3
+        self.prefetch_opcode = self.bus.fetch_arm_opcode(new_pc + 4);
+
+        self.write_register(new_pc + 8, Register::R15);
+    }
+
     fn execute_thumb_bx(&mut self, operand: Register) {
         // cycle 1: prefetch next instruction
         let old_pc = self.read_register(Register::R15, |pc| pc);
@@ -1609,31 +1674,18 @@ impl Cpu {
         self.pre_decode_thumb = decode_thumb(self.prefetch_opcode as u16);
         self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(old_pc));
 
-        // Lowest register index goes at lowest address. As this is equivalent to STMDB, lowest register index needs to be considered last.
-        //  In order to achieve this, iterate in reverse order.
-        if push_lr {
-            let lr_value = self.read_register(Register::R14, |_| unreachable!());
+        // Equivalent to STMDB R13!, {Rlist, LR}.
+        let mut full_register_bit_list = [false; 16];
+        full_register_bit_list[..8].copy_from_slice(&register_bit_list);
+        full_register_bit_list[14] = push_lr;
 
-            let new_r13 = self.read_register(Register::R13, |_| unreachable!()) - 4;
-            self.write_register(new_r13, Register::R13);
-            self.bus
-                .write_word_address(lr_value, new_r13, BusAccessType::NonSequential);
-        }
-
-        for (register_idx, register_pushed) in register_bit_list.into_iter().enumerate().rev() {
-            if register_pushed {
-                let pushed_register = Register::from_index(register_idx as u32);
-                let pushed_register_value = self.read_register(pushed_register, |_| unreachable!());
-
-                let new_r13 = self.read_register(Register::R13, |_| unreachable!()) - 4;
-                self.write_register(new_r13, Register::R13);
-                self.bus.write_word_address(
-                    pushed_register_value,
-                    new_r13,
-                    BusAccessType::NonSequential,
-                );
-            }
-        }
+        let transfer = BlockTransfer::new(
+            self.read_register(Register::R13, |_| unreachable!()),
+            &full_register_bit_list,
+            OffsetModifierType::SubtractFromBase,
+            BlockDataTransferIndexType::PreIndex,
+        );
+        self.block_store(&transfer, Register::R13, true, false, |pc| pc + 2);
 
         self.write_register(old_pc + 2, Register::R15);
     }
@@ -1644,40 +1696,21 @@ impl Cpu {
         self.pre_decode_thumb = decode_thumb(self.prefetch_opcode as u16);
         self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(old_pc));
 
-        for (register_idx, register_popped) in register_bit_list.into_iter().enumerate() {
-            if register_popped {
-                let popped_register = Register::from_index(register_idx as u32);
-                let old_r13 = self.read_register(Register::R13, |_| unreachable!());
-                let popped_register_value = self
-                    .bus
-                    .read_word_address(old_r13, BusAccessType::NonSequential);
-
-                self.write_register(old_r13 + 4, Register::R13);
-
-                // Ensure that we don't update R15 here so we don't have to check for prefetch flush here.
-                assert!(!matches!(popped_register, Register::R15));
-                self.write_register(popped_register_value, popped_register);
-            }
-        }
-
-        // Write final register back.
-        // TODO: This may possibly be a merged IS cycle.
-        self.bus.step();
-
-        if pop_pc {
-            // POP {PC} ignores the least significant bit of the return address (processor remains in thumb state even if bit0 was cleared).
-            let old_r13 = self.read_register(Register::R13, |_| unreachable!());
-            let pc_value = self
-                .bus
-                .read_word_address(old_r13, BusAccessType::NonSequential)
-                & (!1);
-
-            self.write_register(old_r13 + 4, Register::R13);
-            self.write_register(pc_value, Register::R15);
-
-            self.pre_decode_thumb = decode_thumb(self.bus.fetch_thumb_opcode(pc_value));
-            self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(pc_value + 2));
-            self.write_register(pc_value + 4, Register::R15);
+        // Equivalent to LDMIA R13!, {Rlist, PC}.
+        let mut full_register_bit_list = [false; 16];
+        full_register_bit_list[..8].copy_from_slice(&register_bit_list);
+        full_register_bit_list[15] = pop_pc;
+
+        let transfer = BlockTransfer::new(
+            self.read_register(Register::R13, |_| unreachable!()),
+            &full_register_bit_list,
+            OffsetModifierType::AddToBase,
+            BlockDataTransferIndexType::PostIndex,
+        );
+
+        // POP {PC} ignores the least significant bit of the return address (processor remains in thumb state even if bit0 was cleared).
+        if self.block_load(&transfer, Register::R13, true, false) {
+            self.refill_thumb_pipeline();
         } else {
             self.write_register(old_pc + 2, Register::R15);
         }
@@ -1693,49 +1726,13 @@ impl Cpu {
         self.pre_decode_thumb = decode_thumb(self.prefetch_opcode as u16);
         self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(old_pc));
 
-        let raw_registers = register_bit_list
-            .into_iter()
-            .enumerate()
-            .filter_map(|(register_idx, register_loaded)| {
-                register_loaded.then(|| Register::from_index(register_idx as u32))
-            })
-            .collect::<Vec<_>>();
-
-        let base_address = self.read_register(base_register, |_| unreachable!());
-
-        let (num_registers, stored_registers) = if raw_registers.is_empty() {
-            (16, vec![Register::R15])
-        } else {
-            (raw_registers.len() as u32, raw_registers)
-        };
-
-        let new_base = base_address + (4 * num_registers);
-        let mut current_address = base_address;
-
-        // Writeback with Rb included in Rlist: Store OLD base if Rb is FIRST entry in Rlist, otherwise store NEW base
-        let base_value_if_read = if stored_registers.first() == Some(&base_register) {
-            base_address
-        } else {
-            new_base
-        };
-
-        for register in stored_registers {
-            let register_value = if register == base_register {
-                base_value_if_read
-            } else {
-                self.read_register(register, |pc| pc + 2)
-            };
-
-            self.bus.write_word_address(
-                register_value,
-                current_address,
-                BusAccessType::NonSequential,
-            );
-
-            current_address += 4;
-        }
-
-        self.write_register(new_base, base_register);
+        let transfer = BlockTransfer::new(
+            self.read_register(base_register, |_| unreachable!()),
+            &register_bit_list,
+            OffsetModifierType::AddToBase,
+            BlockDataTransferIndexType::PostIndex,
+        );
+        self.block_store(&transfer, base_register, true, false, |pc| pc + 2);
 
         self.write_register(old_pc + 2, Register::R15);
     }
@@ -1750,58 +1747,29 @@ impl Cpu {
         self.pre_decode_thumb = decode_thumb(self.prefetch_opcode as u16);
         self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(old_pc));
 
-        let raw_registers = register_bit_list
-            .into_iter()
-            .enumerate()
-            .filter_map(|(register_idx, register_loaded)| {
-                register_loaded.then(|| Register::from_index(register_idx as u32))
-            })
-            .collect::<Vec<_>>();
-
-        let mut r15_written = false;
-
-        let base_address = self.read_register(base_register, |_| unreachable!());
-
-        let (num_registers, stored_registers) = if raw_registers.is_empty() {
-            (16, vec![Register::R15])
-        } else {
-            (raw_registers.len() as u32, raw_registers)
-        };
-
-        let new_base = base_address + (4 * num_registers);
-        let mut current_address = base_address;
-
-        for register in stored_registers {
-            let loaded_value = self
-                .bus
-                .read_word_address(current_address, BusAccessType::NonSequential);
-
-            self.write_register(loaded_value, register);
-
-            // Ensure that we flush prefetch properly if R15 is loaded as part of this routine.
-            // This is only possible when we handle the edge case for an empty rlist.
-            if matches!(register, Register::R15) {
-                r15_written |= true;
-            }
-
-            current_address += 4;
-        }
+        let transfer = BlockTransfer::new(
+            self.read_register(base_register, |_| unreachable!()),
+            &register_bit_list,
+            OffsetModifierType::AddToBase,
+            BlockDataTransferIndexType::PostIndex,
+        );
 
-        // Write final register back.
-        // TODO: This may possibly be a merged IS cycle.
-        self.write_register(new_base, base_register);
-        self.bus.step();
-
-        if r15_written {
-            let new_pc = self.read_register(Register::R15, |pc| pc);
-            self.pre_decode_thumb = decode_thumb(self.bus.fetch_thumb_opcode(new_pc));
-            self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(new_pc + 2));
-            self.write_register(new_pc + 4, Register::R15);
+        // R15 can only be loaded by an empty rlist.
+        if self.block_load(&transfer, base_register, true, false) {
+            self.refill_thumb_pipeline();
         } else {
             self.write_register(old_pc + 2, Register::R15);
         }
     }
 
+    // Refills the pipeline after R15 was loaded from memory.
+    fn refill_thumb_pipeline(&mut self) {
+        let new_pc = self.read_register(Register::R15, |pc| pc);
+        self.pre_decode_thumb = decode_thumb(self.bus.fetch_thumb_opcode(new_pc));
+        self.prefetch_opcode = u32::from(self.bus.fetch_thumb_opcode(new_pc + 2));
+        self.write_register(new_pc + 4, Register::R15);
+    }
+
     fn execute_thumb_add_special(
         &mut self,
         source_register: Register,
@@ -1878,6 +1846,52 @@ impl Display for ThumbRegisterOrImmediate {
     }
 }
 
+// Writes a register list such as `{r0-r2, r4, lr}`, collapsing runs of consecutive registers
+// into ranges. `extra_register` is the LR/PC bit of PUSH/POP, which always comes last.
+fn write_register_list(
+    f: &mut std::fmt::Formatter<'_>,
+    register_bit_list: [bool; 8],
+    extra_register: Option<Register>,
+) -> std::fmt::Result {
+    f.write_str("{")?;
+
+    let mut printed_register = false;
+    let mut register_idx = 0;
+    while register_idx < register_bit_list.len() {
+        if !register_bit_list[register_idx] {
+            register_idx += 1;
+            continue;
+        }
+
+        let start_idx = register_idx;
+        while register_idx < register_bit_list.len() && register_bit_list[register_idx] {
+            register_idx += 1;
+        }
+        let end_idx = register_idx - 1;
+
+        if printed_register {
+            f.write_str(", ")?;
+        }
+
+        if start_idx == end_idx {
+            write!(f, "r{}", start_idx)?;
+        } else {
+            write!(f, "r{}-r{}", start_idx, end_idx)?;
+        }
+        printed_register = true;
+    }
+
+    if let Some(extra_register) = extra_register {
+        if printed_register {
+            f.write_str(", ")?;
+        }
+
+        write!(f, "{}", extra_register)?;
+    }
+
+    f.write_str("}")
+}
+
 impl Display for ThumbInstruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.instruction_type {
@@ -1886,13 +1900,48 @@ impl Display for ThumbInstruction {
                 destination_register,
                 source,
                 second_operand,
-            } => {
-                write!(
-                    f,
-                    "{} {}, {}, {}",
-                    operation, destination_register, source, second_operand
-                )
-            }
+            } => match operation {
+                // Only ever encoded with a single source operand.
+                ThumbRegisterOperation::Mov
+                | ThumbRegisterOperation::Cmp
+                | ThumbRegisterOperation::And
+                | ThumbRegisterOperation::Eor
+                | ThumbRegisterOperation::Adc
+                | ThumbRegisterOperation::Sbc
+                | ThumbRegisterOperation::Ror
+                | ThumbRegisterOperation::Tst
+                | ThumbRegisterOperation::Neg
+                | ThumbRegisterOperation::Cmn
+                | ThumbRegisterOperation::Orr
+                | ThumbRegisterOperation::Mul
+                | ThumbRegisterOperation::Bic
+                | ThumbRegisterOperation::Mvn => {
+                    write!(
+                        f,
+                        "{} {}, {}",
+                        operation, destination_register, second_operand
+                    )
+                }
+                ThumbRegisterOperation::Lsl
+                | ThumbRegisterOperation::Lsr
+                | ThumbRegisterOperation::Asr
+                | ThumbRegisterOperation::Add
+                | ThumbRegisterOperation::Sub => {
+                    if destination_register == source {
+                        write!(
+                            f,
+                            "{} {}, {}",
+                            operation, destination_register, second_operand
+                        )
+                    } else {
+                        write!(
+                            f,
+                            "{} {}, {}, {}",
+                            operation, destination_register, source, second_operand
+                        )
+                    }
+                }
+            },
             ThumbInstructionType::HighRegister {
                 destination_register,
                 operation,
@@ -1907,11 +1956,9 @@ impl Display for ThumbInstruction {
                 sign_extend,
                 size,
             } => {
-                f.write_str("ld")?;
+                f.write_str("ldr")?;
                 if sign_extend {
                     f.write_str("s")?;
-                } else {
-                    f.write_str("r")?;
                 }
 
                 match size {
@@ -1919,15 +1966,12 @@ impl Display for ThumbInstruction {
                     ThumbLoadStoreDataSize::HalfWord => f.write_str("h")?,
                     ThumbLoadStoreDataSize::Word => {}
                 };
-                f.write_str(" ")?;
 
                 write!(
                     f,
-                    "{}, [{}, {}]",
+                    " {}, [{}, {}]",
                     destination_register, base_register, offset
-                )?;
-
-                Ok(())
+                )
             }
             ThumbInstructionType::Str {
                 base_register,
@@ -1942,16 +1986,16 @@ impl Display for ThumbInstruction {
                     ThumbLoadStoreDataSize::HalfWord => f.write_str("h")?,
                     ThumbLoadStoreDataSize::Word => {}
                 };
-                f.write_str(" ")?;
-
-                write!(f, "{}, [{}, {}]", source_register, base_register, offset)?;
 
-                Ok(())
+                write!(f, " {}, [{}, {}]", source_register, base_register, offset)
             }
-            ThumbInstructionType::BlPartOne { offset } => write!(f, "bl_1 0x{:08x}", offset),
-            ThumbInstructionType::BlPartTwo { offset } => write!(f, "bl_2 0x{:04x}", offset),
+            // The two halves of a long branch are only meaningful together, so each half is shown
+            // with the part of the offset that it contributes.
+            ThumbInstructionType::BlPartOne { offset } => write!(f, "bl_1 0x{:08X}", offset),
+            ThumbInstructionType::BlPartTwo { offset } => write!(f, "bl_2 0x{:04X}", offset),
+            ThumbInstructionType::BlxPartTwo { offset } => write!(f, "blx_2 0x{:04X}", offset),
             ThumbInstructionType::B { condition, offset } => {
-                write!(f, "b{} 0x{:08X}", condition, offset)
+                write!(f, "b{} 0x{:08X}", condition, i32::from(offset))
             }
             ThumbInstructionType::Bx { operand } => write!(f, "bx {}", operand),
             ThumbInstructionType::Blx { operand } => write!(f, "blx {}", operand),
@@ -1959,117 +2003,15 @@ impl Display for ThumbInstruction {
                 register_bit_list,
                 push_lr,
             } => {
-                f.write_str("push {")?;
-                let mut start_idx = 0;
-                let mut printed_register = false;
-
-                for (register_idx, register_used) in register_bit_list.into_iter().enumerate() {
-                    if !register_used {
-                        let idx_delta = register_idx - start_idx;
-                        if idx_delta == 1 {
-                            if printed_register {
-                                f.write_str(", ")?;
-                            }
-                            write!(f, "r{}", start_idx)?;
-                            printed_register = true;
-                        } else if idx_delta > 1 {
-                            if printed_register {
-                                f.write_str(", ")?;
-                            }
-
-                            write!(f, "r{}-r{}", start_idx, register_idx - 1)?;
-                            printed_register = true;
-                        }
-
-                        start_idx = register_idx + 1;
-                    }
-                }
-
-                let idx_delta = register_bit_list.len() - start_idx;
-                if idx_delta == 1 {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-                    write!(f, "r{}", start_idx)?;
-                    printed_register = true;
-                } else if idx_delta > 1 {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-
-                    write!(f, "r{}-r{}", start_idx, register_bit_list.len() - 1)?;
-                    printed_register = true;
-                }
-
-                if push_lr {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-
-                    f.write_str("r14")?;
-                }
-
-                f.write_str("}")?;
-
-                Ok(())
+                f.write_str("push ")?;
+                write_register_list(f, register_bit_list, push_lr.then_some(Register::R14))
             }
             ThumbInstructionType::Pop {
                 register_bit_list,
                 pop_pc,
             } => {
-                f.write_str("pop {")?;
-                let mut start_idx = 0;
-                let mut printed_register = false;
-
-                for (register_idx, register_used) in register_bit_list.into_iter().enumerate() {
-                    if !register_used {
-                        let idx_delta = register_idx - start_idx;
-                        if idx_delta == 1 {
-                            if printed_register {
-                                f.write_str(", ")?;
-                            }
-                            write!(f, "r{}", start_idx)?;
-                            printed_register = true;
-                        } else if idx_delta > 1 {
-                            if printed_register {
-                                f.write_str(", ")?;
-                            }
-
-                            write!(f, "r{}-r{}", start_idx, register_idx - 1)?;
-                            printed_register = true;
-                        }
-
-                        start_idx = register_idx + 1;
-                    }
-                }
-
-                let idx_delta = register_bit_list.len() - start_idx;
-                if idx_delta == 1 {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-                    write!(f, "r{}", start_idx)?;
-                    printed_register = true;
-                } else if idx_delta > 1 {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-
-                    write!(f, "r{}-r{}", start_idx, register_bit_list.len() - 1)?;
-                    printed_register = true;
-                }
-
-                if pop_pc {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-
-                    f.write_str("r15")?;
-                }
-
-                f.write_str("}")?;
-
-                Ok(())
+                f.write_str("pop ")?;
+                write_register_list(f, register_bit_list, pop_pc.then_some(Register::R15))
             }
             ThumbInstructionType::AddSpecial {
                 source_register,
@@ -2083,116 +2025,32 @@ impl Display for ThumbInstruction {
                     f.write_str("add")?;
                 }
 
-                write!(
-                    f,
-                    " {}, {}, #{}",
-                    dest_register, source_register, unsigned_offset
-                )?;
-
-                Ok(())
+                if dest_register == source_register {
+                    write!(f, " {}, #{}", dest_register, unsigned_offset)
+                } else {
+                    write!(
+                        f,
+                        " {}, {}, #{}",
+                        dest_register, source_register, unsigned_offset
+                    )
+                }
             }
             ThumbInstructionType::LdmiaWriteBack {
                 base_register,
                 register_bit_list,
             } => {
-                write!(f, "ldmia {}!, {{", base_register)?;
-
-                let mut start_idx = 0;
-                let mut printed_register = false;
-
-                for (register_idx, register_used) in register_bit_list.into_iter().enumerate() {
-                    if !register_used {
-                        let idx_delta = register_idx - start_idx;
-                        if idx_delta == 1 {
-                            if printed_register {
-                                f.write_str(", ")?;
-                            }
-                            write!(f, "r{}", start_idx)?;
-                            printed_register = true;
-                        } else if idx_delta > 1 {
-                            if printed_register {
-                                f.write_str(", ")?;
-                            }
-
-                            write!(f, "r{}-r{}", start_idx, register_idx - 1)?;
-                            printed_register = true;
-                        }
-
-                        start_idx = register_idx + 1;
-                    }
-                }
-
-                let idx_delta = register_bit_list.len() - start_idx;
-                if idx_delta == 1 {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-                    write!(f, "r{}", start_idx)?;
-                } else if idx_delta > 1 {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-
-                    write!(f, "r{}-r{}", start_idx, register_bit_list.len() - 1)?;
-                }
-
-                f.write_str("}")?;
-
-                Ok(())
+                write!(f, "ldmia {}!, ", base_register)?;
+                write_register_list(f, register_bit_list, None)
             }
             ThumbInstructionType::StmiaWriteBack {
                 base_register,
                 register_bit_list,
             } => {
-                write!(f, "stmia {}!, {{", base_register)?;
-
-                let mut start_idx = 0;
-                let mut printed_register = false;
-
-                for (register_idx, register_used) in register_bit_list.into_iter().enumerate() {
-                    if !register_used {
-                        let idx_delta = register_idx - start_idx;
-                        match idx_delta.cmp(&1) {
-                            Ordering::Equal => {
-                                if printed_register {
-                                    f.write_str(", ")?;
-                                }
-                                write!(f, "r{}", start_idx)?;
-                                printed_register = true;
-                            }
-                            Ordering::Greater => {
-                                if printed_register {
-                                    f.write_str(", ")?;
-                                }
-
-                                write!(f, "r{}-r{}", start_idx, register_idx - 1)?;
-                                printed_register = true;
-                            }
-                            _ => {}
-                        }
-
-                        start_idx = register_idx + 1;
-                    }
-                }
-
-                let idx_delta = register_bit_list.len() - start_idx;
-                if idx_delta == 1 {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-                } else if idx_delta > 1 {
-                    if printed_register {
-                        f.write_str(", ")?;
-                    }
-
-                    write!(f, "r{}-r{}", start_idx, register_bit_list.len() - 1)?;
-                }
-
-                f.write_str("}")?;
-
-                Ok(())
+                write!(f, "stmia {}!, ", base_register)?;
+                write_register_list(f, register_bit_list, None)
             }
             ThumbInstructionType::Swi { comment } => write!(f, "swi #{}", comment),
+            ThumbInstructionType::Bkpt { comment } => write!(f, "bkpt #{}", comment),
             ThumbInstructionType::Invalid { opcode } => write!(f, "INVALID 0x{opcode:04X}"),
         }
     }
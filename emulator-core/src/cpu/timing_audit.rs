@@ -0,0 +1,244 @@
+// Checks the bus cycles each Thumb instruction takes against the ARM7TDMI's documented S/N/I
+// counts, as listed in GBATEK, so a timing regression shows up as the instruction that caused it
+// rather than as a changed checksum somewhere down the line.
+//
+// The documented counts include the fetch made by the instruction after, since that's where a
+// data access leaves the bus non-sequential, but not the instruction's own fetch. Measurements
+// are shifted to match, using whether the fetch before and after the instruction is sequential.
+
+use super::thumb::{
+    ThumbHighRegisterOperation, ThumbInstructionType, ThumbRegisterOperation,
+    ThumbRegisterOrImmediate,
+};
+use super::{Cpu, InstructionSet, Register};
+use crate::bus::PowerState;
+use crate::tests::build_thumb_test_cpu;
+use crate::Cartridge;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct CycleCounts {
+    sequential: u64,
+    non_sequential: u64,
+    internal: u64,
+}
+
+impl CycleCounts {
+    fn sequential(sequential: u64) -> Self {
+        Self {
+            sequential,
+            ..Self::default()
+        }
+    }
+
+    fn non_sequential(non_sequential: u64) -> Self {
+        Self {
+            non_sequential,
+            ..Self::default()
+        }
+    }
+}
+
+// Instruction classes known not to take their documented cycles yet. These aren't audited, so
+// fixing one means removing it here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KnownDeviation {
+    ShiftByRegister, // the I cycle for reading the shift amount isn't taken
+    Multiply,        // none of the I cycles are taken
+    PipelineRefill,  // refills are fetched sequentially, so 3S instead of 2S+1N
+    Load,            // the fetch after the I cycle is non-sequential instead of sequential
+    BlockTransfer,   // every register is transferred with a non-sequential access
+}
+
+fn documented_cycles(
+    cpu: &Cpu,
+    instruction_type: ThumbInstructionType,
+) -> Result<CycleCounts, KnownDeviation> {
+    match instruction_type {
+        ThumbInstructionType::Register {
+            operation:
+                ThumbRegisterOperation::Lsl
+                | ThumbRegisterOperation::Lsr
+                | ThumbRegisterOperation::Asr
+                | ThumbRegisterOperation::Ror,
+            second_operand: ThumbRegisterOrImmediate::Register(_),
+            ..
+        } => Err(KnownDeviation::ShiftByRegister),
+        ThumbInstructionType::Register {
+            operation: ThumbRegisterOperation::Mul,
+            ..
+        } => Err(KnownDeviation::Multiply),
+        ThumbInstructionType::Register { .. } => Ok(CycleCounts::sequential(1)),
+        ThumbInstructionType::HighRegister {
+            operation: ThumbHighRegisterOperation::Add | ThumbHighRegisterOperation::Mov,
+            destination_register: Register::R15,
+            ..
+        } => Err(KnownDeviation::PipelineRefill),
+        ThumbInstructionType::HighRegister { .. }
+        | ThumbInstructionType::AddSpecial { .. }
+        | ThumbInstructionType::BlPartOne { .. }
+        | ThumbInstructionType::Bkpt { .. } => Ok(CycleCounts::sequential(1)),
+        ThumbInstructionType::B { condition, .. }
+            if !cpu.evaluate_instruction_condition(condition) =>
+        {
+            Ok(CycleCounts::sequential(1))
+        }
+        ThumbInstructionType::B { .. }
+        | ThumbInstructionType::BlPartTwo { .. }
+        | ThumbInstructionType::BlxPartTwo { .. }
+        | ThumbInstructionType::Bx { .. }
+        | ThumbInstructionType::Blx { .. }
+        | ThumbInstructionType::Swi { .. } => Err(KnownDeviation::PipelineRefill),
+        // Undefined instructions take the exception, refilling the pipeline.
+        ThumbInstructionType::Invalid { .. } => Err(KnownDeviation::PipelineRefill),
+        ThumbInstructionType::Ldr { .. } => Err(KnownDeviation::Load),
+        ThumbInstructionType::Str { .. } => Ok(CycleCounts::non_sequential(2)),
+        ThumbInstructionType::Push {
+            register_bit_list,
+            push_lr,
+        } => {
+            let count = register_bit_list.iter().filter(|&&bit| bit).count() + usize::from(push_lr);
+            single_store_cycles(count)
+        }
+        ThumbInstructionType::StmiaWriteBack {
+            register_bit_list, ..
+        } => single_store_cycles(register_bit_list.iter().filter(|&&bit| bit).count()),
+        ThumbInstructionType::Pop { pop_pc: true, .. } => Err(KnownDeviation::PipelineRefill),
+        ThumbInstructionType::Pop { .. } | ThumbInstructionType::LdmiaWriteBack { .. } => {
+            Err(KnownDeviation::BlockTransfer)
+        }
+    }
+}
+
+// Storing a single register takes the same 2N as STR. Any more, or none (which transfers r15 on
+// the ARM7TDMI), runs into the block transfer deviation.
+fn single_store_cycles(count: usize) -> Result<CycleCounts, KnownDeviation> {
+    if count == 1 {
+        Ok(CycleCounts::non_sequential(2))
+    } else {
+        Err(KnownDeviation::BlockTransfer)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum AuditResult {
+    Matched,
+    Mismatched(String),
+    Allowed, // one of the known deviations
+    // Not a plain Thumb instruction: ARM code, an interrupt being taken, a halted CPU, or an
+    // instruction that a DMA cut into.
+    Skipped,
+}
+
+// Executes one step, auditing it if it ran a single Thumb instruction.
+fn audit_step(cpu: &mut Cpu) -> AuditResult {
+    if !matches!(cpu.get_instruction_mode(), InstructionSet::Thumb)
+        || cpu.bus.power_state() != PowerState::Running
+    {
+        cpu.fetch_decode_execute();
+        return AuditResult::Skipped;
+    }
+
+    let instruction = cpu.pre_decode_thumb;
+    let executing_pc = cpu.get_executing_pc();
+    let documented = documented_cycles(cpu, instruction.instruction_type);
+    let before = cpu.perf_counters();
+    let fetch_sequential_before = cpu.bus.prefetch_sequential();
+
+    cpu.fetch_decode_execute();
+
+    let after = cpu.perf_counters();
+    if after.thumb_instructions != before.thumb_instructions + 1
+        || after.irqs_taken != before.irqs_taken
+        || after.dma_units_transferred != before.dma_units_transferred
+    {
+        return AuditResult::Skipped;
+    }
+
+    let documented = match documented {
+        Ok(documented) => documented,
+        Err(_) => return AuditResult::Allowed,
+    };
+
+    let mut measured = CycleCounts {
+        sequential: after.sequential_accesses - before.sequential_accesses,
+        non_sequential: after.non_sequential_accesses - before.non_sequential_accesses,
+        internal: after.internal_cycles - before.internal_cycles,
+    };
+    if fetch_sequential_before {
+        measured.sequential -= 1;
+    } else {
+        measured.non_sequential -= 1;
+    }
+    if cpu.bus.prefetch_sequential() {
+        measured.sequential += 1;
+    } else {
+        measured.non_sequential += 1;
+    }
+
+    if measured == documented {
+        AuditResult::Matched
+    } else {
+        AuditResult::Mismatched(format!(
+            "{instruction} at {executing_pc:08X}: documented {documented:?}, took {measured:?}"
+        ))
+    }
+}
+
+fn audit(cpu: &mut Cpu, steps: usize) -> (usize, Vec<String>) {
+    let mut matched = 0;
+    let mut mismatches = Vec::new();
+    for _ in 0..steps {
+        match audit_step(cpu) {
+            AuditResult::Matched => matched += 1,
+            AuditResult::Mismatched(mismatch) => mismatches.push(mismatch),
+            AuditResult::Allowed | AuditResult::Skipped => {}
+        }
+    }
+
+    (matched, mismatches)
+}
+
+#[test]
+fn thumb_timing_audit() {
+    let mut cpu = build_thumb_test_cpu(
+        &[
+            0x2001, // mov r0, #1
+            0x0101, // lsl r1, r0, #4
+            0x1842, // add r2, r0, r1
+            0x4002, // and r2, r0
+            0x4680, // mov r8, r0
+            0x2302, // mov r3, #2
+            0x061B, // lsl r3, r3, #24
+            0x6018, // str r0, [r3]
+            0x8058, // strh r0, [r3, #2]
+            0xB401, // push {r0}
+            0xAC01, // add r4, sp, #4
+            0xB002, // add sp, #8
+            0x2801, // cmp r0, #1
+            0xD100, // bne #0 (not taken)
+            0xE7FE, // b #-4
+        ],
+        &[],
+    );
+
+    // Gets into Thumb mode first, which isn't audited.
+    while !matches!(cpu.get_instruction_mode(), InstructionSet::Thumb) {
+        cpu.fetch_decode_execute();
+    }
+
+    let (matched, mismatches) = audit(&mut cpu, 20);
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+    assert_eq!(matched, 14);
+    assert_eq!(cpu.read_register(Register::R4, |pc| pc), 0x03007F00);
+}
+
+#[test]
+fn thumb_timing_audit_rom() {
+    let source = include_bytes!("../../tests/gba_tests_thumb.gba");
+    let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
+    let mut cpu = Cpu::new(cartridge);
+
+    let (matched, mismatches) = audit(&mut cpu, 2_000_000);
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+    assert!(matched > 0);
+}
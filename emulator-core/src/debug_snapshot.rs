@@ -0,0 +1,156 @@
+use std::{
+    array,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    Bus, BusProfiler, Cpu, CpuMode, DmaDebugInfo, DmaLog, Frame, InstructionSet, InterruptEvent,
+    IoRegisterInfo, Lcd, PerfCounters, Register, Rgb555, Waitstates,
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimerSnapshot {
+    pub counter: u16,
+    pub reload: u16,
+}
+
+// Everything a debugger frontend typically displays, captured at a single point in time.
+#[derive(Clone, Debug)]
+pub struct DebugSnapshot {
+    pub frame_buffer: Box<[[Rgb555; Lcd::LCD_WIDTH]; Lcd::LCD_HEIGHT]>,
+    pub registers: [u32; 16], // r0-r15, in the current mode
+    pub sign_flag: bool,
+    pub zero_flag: bool,
+    pub carry_flag: bool,
+    pub overflow_flag: bool,
+    pub irq_disable: bool,
+    pub fiq_disable: bool,
+    pub instruction_mode: InstructionSet,
+    pub cpu_mode: CpuMode,
+    pub executing_pc: u32,
+    pub instruction_width: u32,
+    pub cycle_count: u64,
+    pub scanline: u16,
+    pub scanline_dot: u16,
+    pub irq_buffer: [u16; Bus::IRQ_SYNC_BUFFER],
+    pub open_bus_data: u32,
+    pub timers: [TimerSnapshot; 4],
+    pub dma: [DmaDebugInfo; 4],
+    pub waitstates: Waitstates,
+    pub io_registers: Vec<IoRegisterInfo>,
+    pub perf_counters: PerfCounters,
+    pub bus_profiler: Option<BusProfiler>,
+    pub dma_log: Option<DmaLog>,
+    pub call_stack: Vec<Frame>,                 // innermost first
+    pub interrupt_history: Vec<InterruptEvent>, // oldest first
+}
+
+impl Default for DebugSnapshot {
+    fn default() -> Self {
+        Self {
+            frame_buffer: Box::new([[Rgb555::default(); Lcd::LCD_WIDTH]; Lcd::LCD_HEIGHT]),
+            registers: [0; 16],
+            sign_flag: false,
+            zero_flag: false,
+            carry_flag: false,
+            overflow_flag: false,
+            irq_disable: false,
+            fiq_disable: false,
+            instruction_mode: InstructionSet::default(),
+            cpu_mode: CpuMode::default(),
+            executing_pc: 0,
+            instruction_width: 0,
+            cycle_count: 0,
+            scanline: 0,
+            scanline_dot: 0,
+            irq_buffer: [0; Bus::IRQ_SYNC_BUFFER],
+            open_bus_data: 0,
+            timers: [TimerSnapshot::default(); 4],
+            dma: [DmaDebugInfo::default(); 4],
+            waitstates: Waitstates::default(),
+            io_registers: Vec::new(),
+            perf_counters: PerfCounters::default(),
+            bus_profiler: None,
+            dma_log: None,
+            call_stack: Vec::new(),
+            interrupt_history: Vec::new(),
+        }
+    }
+}
+
+impl Cpu {
+    pub fn snapshot_debug_state(&self) -> DebugSnapshot {
+        let mut snapshot = DebugSnapshot::default();
+        self.snapshot_debug_state_into(&mut snapshot);
+        snapshot
+    }
+
+    // Like `snapshot_debug_state`, but reuses the allocations of an existing snapshot.
+    pub fn snapshot_debug_state_into(&self, snapshot: &mut DebugSnapshot) {
+        *snapshot.frame_buffer = *self.bus.lcd.get_buffer();
+        snapshot.registers =
+            array::from_fn(|i| self.read_register(Register::from_index(i as u32), |pc| pc));
+        snapshot.sign_flag = self.get_sign_flag();
+        snapshot.zero_flag = self.get_zero_flag();
+        snapshot.carry_flag = self.get_carry_flag();
+        snapshot.overflow_flag = self.get_overflow_flag();
+        snapshot.irq_disable = self.get_irq_disable();
+        snapshot.fiq_disable = self.get_fiq_disable();
+        snapshot.instruction_mode = self.get_instruction_mode();
+        snapshot.cpu_mode = self.get_cpu_mode();
+        snapshot.executing_pc = self.get_executing_pc();
+        snapshot.instruction_width = self.get_instruction_width();
+        snapshot.cycle_count = self.bus.cycle_count();
+        snapshot.scanline = self.bus.lcd.read_vcount(0);
+        snapshot.scanline_dot = self.bus.lcd.current_scanline_dot();
+        snapshot.irq_buffer = self.bus.get_interrupt_request_debug();
+        snapshot.open_bus_data = self.bus.open_bus_data;
+        snapshot.timers = self.bus.timers.each_ref().map(|timer| TimerSnapshot {
+            counter: timer.get_current_counter(),
+            reload: timer.get_current_reload(),
+        });
+        snapshot.dma = self.bus.get_dma_debug();
+        snapshot.waitstates = self.bus.current_waitstates();
+        snapshot.io_registers = self.bus.io_registers();
+        snapshot.perf_counters = self.perf_counters();
+        snapshot.bus_profiler = self.bus.profiler().cloned();
+        snapshot.dma_log = self.bus.dma_log().cloned();
+        snapshot.call_stack = self.call_stack();
+        snapshot.interrupt_history.clear();
+        snapshot
+            .interrupt_history
+            .extend(self.bus.interrupt_history().iter().copied());
+    }
+}
+
+// Hands snapshots from the emulator thread to any number of reader threads. Snapshots are
+// captured into a back buffer and then swapped in, so readers are only ever blocked for the swap
+// rather than the whole capture.
+pub struct SharedDebugSnapshot {
+    front: Arc<RwLock<DebugSnapshot>>,
+    back: DebugSnapshot,
+}
+
+impl Default for SharedDebugSnapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedDebugSnapshot {
+    pub fn new() -> Self {
+        Self {
+            front: Arc::new(RwLock::new(DebugSnapshot::default())),
+            back: DebugSnapshot::default(),
+        }
+    }
+
+    pub fn reader(&self) -> Arc<RwLock<DebugSnapshot>> {
+        Arc::clone(&self.front)
+    }
+
+    pub fn publish(&mut self, cpu: &Cpu) {
+        cpu.snapshot_debug_state_into(&mut self.back);
+        std::mem::swap(&mut *self.front.write().unwrap(), &mut self.back);
+    }
+}
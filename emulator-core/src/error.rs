@@ -0,0 +1,46 @@
+use std::fmt::Display;
+
+// Something the running software asked for that the emulator can't do. Rather than panicking,
+// these are logged and emulation carries on as best it can, optionally stopping as well
+// depending on the `ErrorPolicy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmulatorError {
+    // An instruction that decodes, but which isn't supported. It's executed as a no-op.
+    UnimplementedInstruction {
+        address: u32,
+        mnemonic: &'static str,
+    },
+    // EWRAM was accessed at `address` with its wait control set to 15 through the internal
+    // memory control register. Hardware locks up, the emulator carries on with 1 waitstate.
+    EwramLockup {
+        address: u32,
+    },
+}
+
+impl Display for EmulatorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnimplementedInstruction { address, mnemonic } => {
+                write!(f, "unimplemented instruction {mnemonic} at {address:08X}")
+            }
+            Self::EwramLockup { address } => {
+                write!(f, "EWRAM access at {address:08X} would lock up hardware")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+// How errors are handled, the same for every kind of error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    // Carry on without saying anything.
+    Ignore,
+    // Log the error and carry on.
+    #[default]
+    LogAndContinue,
+    // Log the error, and also report it from `Cpu::fetch_decode_execute` as a `StepEvent::Error`
+    // so that frontends can stop.
+    Trap,
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use crate::KeysState;
+
+// Which keys fire repeatedly while held, and how fast.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutofireConfig {
+    pub keys: KeysState,
+    // Keys are pressed for this many frames, then released for as many.
+    pub period_frames: u32,
+}
+
+impl AutofireConfig {
+    pub const DEFAULT_PERIOD_FRAMES: u32 = 2;
+}
+
+impl Default for AutofireConfig {
+    fn default() -> Self {
+        Self {
+            keys: KeysState::default(),
+            period_frames: Self::DEFAULT_PERIOD_FRAMES,
+        }
+    }
+}
+
+// Turns the keys a player holds into the keys the game sees, for frontends to apply once per
+// frame before handing them to the keypad. Each key's pattern starts over when it's pressed, so
+// a tap always registers.
+#[derive(Clone, Debug, Default)]
+pub struct Autofire {
+    config: AutofireConfig,
+    held_frames: [u32; u16::BITS as usize], // by KEYINPUT bit, 0 while released
+}
+
+impl Autofire {
+    pub fn new(config: AutofireConfig) -> Self {
+        Self {
+            config,
+            held_frames: Default::default(),
+        }
+    }
+
+    pub fn config(&self) -> AutofireConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: AutofireConfig) {
+        self.config = config;
+    }
+
+    // The keys to press this frame, advancing to the next one.
+    pub fn apply(&mut self, held: KeysState) -> KeysState {
+        let period = self.config.period_frames.max(1);
+
+        let mut released = 0;
+        for (bit, held_frames) in self.held_frames.iter_mut().enumerate() {
+            if held.bits() & (1 << bit) == 0 {
+                *held_frames = 0;
+                continue;
+            }
+
+            if self.config.keys.bits() & (1 << bit) != 0 && (*held_frames / period) % 2 == 1 {
+                released |= 1 << bit;
+            }
+            *held_frames = held_frames.saturating_add(1);
+        }
+
+        KeysState::from_bits(held.bits() & !released)
+    }
+}
+
+// When a queued keypad state takes effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputTiming {
+    // Once `Lcd::frame_count` reaches this, which happens as VBlank starts, just before games
+    // built around a VBlank handler read the keypad.
+    Frame(u64),
+    // Once the bus has run for this many cycles in total, see `Bus::cycle_count`.
+    Cycle(u64),
+}
+
+// Keypad states waiting to be applied, so that input lands at the same point in emulated time
+// however the frontend's thread happens to be scheduled, as movies and netplay need. Timings
+// already in the past apply on the next cycle.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InputQueue {
+    events: Vec<(InputTiming, KeysState)>, // in the order queued
+}
+
+impl InputQueue {
+    pub(crate) fn push(&mut self, timing: InputTiming, keys: KeysState) {
+        self.events.push((timing, keys));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // The keypad state to switch to now, if any queued one is due. Of several due at once, the
+    // last one queued wins.
+    pub(crate) fn poll(&mut self, frame: u64, cycle: u64) -> Option<KeysState> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut due_keys = None;
+        self.events.retain(|&(timing, keys)| {
+            let due = match timing {
+                InputTiming::Frame(event_frame) => frame >= event_frame,
+                InputTiming::Cycle(event_cycle) => cycle >= event_cycle,
+            };
+            if due {
+                due_keys = Some(keys);
+            }
+            !due
+        });
+
+        due_keys
+    }
+}
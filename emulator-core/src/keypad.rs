@@ -1,8 +1,11 @@
-use std::ops::RangeInclusive;
+use std::ops::{BitOr, RangeInclusive};
+use std::str::FromStr;
 
 use crate::{BitManipulation, DataAccess};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Key {
     A,
     B,
@@ -16,7 +19,68 @@ pub enum Key {
     L,
 }
 
-#[derive(Clone, Debug)]
+// Key names are case insensitive, so that "Start" and "start" both parse.
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let key = match s.to_ascii_lowercase().as_str() {
+            "a" => Key::A,
+            "b" => Key::B,
+            "select" => Key::Select,
+            "start" => Key::Start,
+            "right" => Key::Right,
+            "left" => Key::Left,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "r" => Key::R,
+            "l" => Key::L,
+            _ => return Err(anyhow!("unknown key \"{s}\"")),
+        };
+
+        Ok(key)
+    }
+}
+
+// A snapshot of which keys are held, so that frontends can update all keys at once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeysState {
+    pressed: u16, // 1 = pressed, 0 = released
+}
+
+impl KeysState {
+    pub fn set_pressed(&mut self, key: Key, pressed: bool) {
+        self.pressed = self.pressed.set_bit(Keypad::key_bit_index(key), pressed);
+    }
+
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.pressed.get_bit(Keypad::key_bit_index(key))
+    }
+
+    // Pressed keys as KEYINPUT bits, but with 1 = pressed. Bits above the keys are ignored.
+    pub fn from_bits(bits: u16) -> Self {
+        Self {
+            pressed: bits.get_bit_range(Keypad::KEY_BIT_RANGE),
+        }
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.pressed
+    }
+}
+
+// Keys held in either state, for combining several players' input on one keypad.
+impl BitOr for KeysState {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self {
+            pressed: self.pressed | other.pressed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Keypad {
     key_status: u16, // 0 = pressed, 1 = released
     interrupt_control: u16,
@@ -48,11 +112,11 @@ impl Keypad {
     const BUTTON_DOWN_BIT_INDEX: usize = 7;
     const BUTTON_R_BIT_INDEX: usize = 8;
     const BUTTON_L_BIT_INDEX: usize = 9;
-}
 
-impl Keypad {
-    pub fn set_pressed(&mut self, key: Key, pressed: bool) {
-        let bit_index = match key {
+    const KEY_BIT_RANGE: RangeInclusive<usize> = 0..=9;
+
+    fn key_bit_index(key: Key) -> usize {
+        match key {
             Key::A => Self::BUTTON_A_BIT_INDEX,
             Key::B => Self::BUTTON_B_BIT_INDEX,
             Key::Select => Self::BUTTON_SELECT_BIT_INDEX,
@@ -63,9 +127,26 @@ impl Keypad {
             Key::Down => Self::BUTTON_DOWN_BIT_INDEX,
             Key::R => Self::BUTTON_R_BIT_INDEX,
             Key::L => Self::BUTTON_L_BIT_INDEX,
-        };
+        }
+    }
+}
+
+impl Keypad {
+    pub fn set_pressed(&mut self, key: Key, pressed: bool) {
+        self.key_status = self.key_status.set_bit(Self::key_bit_index(key), !pressed);
+    }
 
-        self.key_status = self.key_status.set_bit(bit_index, !pressed);
+    // Replaces the state of every key at once, rather than one at a time through `set_pressed`.
+    pub fn set_state(&mut self, state: KeysState) {
+        let released = !state.pressed;
+
+        self.key_status = self.key_status.set_bit_range(released, Self::KEY_BIT_RANGE);
+    }
+
+    pub fn get_state(&self) -> KeysState {
+        KeysState {
+            pressed: (!self.key_status).get_bit_range(Self::KEY_BIT_RANGE),
+        }
     }
 }
 
@@ -92,15 +173,12 @@ impl Keypad {
     }
 
     pub fn poll_pending_interrupts(&mut self) -> bool {
-        const IRQ_MASK_BIT_RANGE: RangeInclusive<usize> = 0..=9;
-
         if !self.get_irq_enabled() {
             return false;
         }
 
-        // Keep in mind that 0 means pressed and 1 means released, so we must invert this bitmask.
-        let pressed_bits = !self.key_status.get_bit_range(IRQ_MASK_BIT_RANGE);
-        let irq_bits = self.interrupt_control.get_bit_range(IRQ_MASK_BIT_RANGE);
+        let pressed_bits = self.get_state().pressed;
+        let irq_bits = self.interrupt_control.get_bit_range(Self::KEY_BIT_RANGE);
 
         match self.get_irq_condition() {
             // In logical OR mode, an interrupt is requested when at least one of the selected buttons is pressed.
@@ -1,18 +1,43 @@
+mod background_map;
+mod color_correction;
 mod layer_0;
 mod layer_1;
 mod layer_2;
 mod layer_3;
+mod rgba_output;
+mod scanline;
 
+pub use background_map::{BackgroundMap, BackgroundViewport};
+pub use color_correction::ColorCorrection;
 use layer_0::Layer0;
 use layer_1::Layer1;
 use layer_2::Layer2;
 use layer_3::Layer3;
-
-use crate::{BitManipulation, DataAccess};
-
-use std::{array, cmp::Ordering, fmt::Debug, ops::RangeInclusive};
-
-#[derive(Clone, Debug)]
+use rgba_output::RgbaOutput;
+use scanline::ScanlineCompositor;
+
+use crate::{BitManipulation, DataAccess, LittleEndianBytes};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use xxhash_rust::xxh3::Xxh3;
+
+use std::{
+    array,
+    cmp::Ordering,
+    collections::VecDeque,
+    fmt::Debug,
+    hash::Hasher,
+    io::{self, Write},
+    ops::RangeInclusive,
+    sync::Arc,
+};
+
+pub type FrameBuffer = [[Rgb555; Lcd::LCD_WIDTH]; Lcd::LCD_HEIGHT];
+
+// Called once per frame on entering vblank, with the frame that was just completed.
+pub type FrameCallback = Arc<dyn Fn(&FrameBuffer) + Send + Sync>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 enum LcdState {
     Visible,
     HBlank,
@@ -24,6 +49,7 @@ pub struct LcdStateChangeInfo {
     pub vblank_entered: bool,
     pub hblank_entered: bool,
     pub vcount_matched: bool,
+    pub scanline_started: bool, // set at the first dot of every line, including during vblank
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -54,26 +80,26 @@ impl BgMode {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 struct PixelInfo {
     priority: u16,
     color: Rgb555,
     pixel_type: PixelType,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 struct SpritePixelInfo {
     pixel_info: PixelInfo,
     semi_transparent: bool,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 struct SpritePixelQueryInfo {
     sprite_pixel_info: Option<SpritePixelInfo>,
     obj_window: bool,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 enum PixelType {
     Layer0,
     Layer1,
@@ -159,7 +185,7 @@ struct DisplayedSelectionInfo {
     effects_displayed: bool,
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
 pub struct Rgb555(u16);
 
 impl Rgb555 {
@@ -176,7 +202,7 @@ impl Rgb555 {
         Self(inner)
     }
 
-    fn to_int(self) -> u16 {
+    pub fn to_int(self) -> u16 {
         self.0
     }
 
@@ -196,6 +222,18 @@ impl Rgb555 {
         self.0.get_bit_range(Rgb555::BLUE_INTENSITY_BIT_RANGE) as u8
     }
 
+    // Expands each 5-bit channel to 8 bits, replicating the high bits into the low bits so that
+    // full intensity maps to 0xFF.
+    pub fn to_rgb888(self) -> [u8; 3] {
+        let expand = |channel: u8| (channel << 3) | (channel >> 2);
+
+        [
+            expand(self.red()),
+            expand(self.green()),
+            expand(self.blue()),
+        ]
+    }
+
     const MAX_VALUE: u8 = 31;
 
     fn blend(self, coeff_self: f64, other: Rgb555, coeff_other: f64) -> Self {
@@ -224,7 +262,7 @@ impl Debug for Rgb555 {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 struct ObjectAttributeInfo {
     attribute_0: u16,
     attribute_1: u16,
@@ -428,7 +466,7 @@ impl ObjectAttributeInfo {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 struct ObjectRotationScalingInfo {
     pub a: u16,
     pub b: u16,
@@ -436,7 +474,8 @@ struct ObjectRotationScalingInfo {
     pub d: u16,
 }
 
-#[derive(Clone, Debug)]
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Lcd {
     dot: u16,
     vcount: u16,
@@ -453,19 +492,51 @@ pub struct Lcd {
     window_in_control: u16,
     window_out_control: u16,
     state: LcdState,
+    #[serde_as(as = "Box<[_; 0x100]>")]
     bg_palette_ram: Box<[Rgb555; 0x100]>,
+    #[serde_as(as = "Box<[_; 0x100]>")]
     obj_palette_ram: Box<[Rgb555; 0x100]>,
+    #[serde_as(as = "Box<[_; 0x18000]>")]
     vram: Box<[u8; 0x18000]>,
+    #[serde_as(as = "Box<[_; 0x80]>")]
     obj_attributes: Box<[ObjectAttributeInfo; 0x80]>,
     obj_rotations: Box<[ObjectRotationScalingInfo; 0x20]>,
-    buffer: Box<[[Rgb555; Self::LCD_WIDTH]; Self::LCD_HEIGHT]>, // access as buffer[y][x]
-    back_buffer: Box<[[Rgb555; Self::LCD_WIDTH]; Self::LCD_HEIGHT]>,
+    // The last completed frame, frontends only ever see this one. Lines are drawn into
+    // `back_buffer`, and the two are swapped on entering vblank.
+    #[serde_as(as = "Box<[[_; 240]; 160]>")]
+    buffer: Box<FrameBuffer>, // access as buffer[y][x]
+    #[serde_as(as = "Box<[[_; 240]; 160]>")]
+    back_buffer: Box<FrameBuffer>,
+    // Number of frames completed since power on.
+    #[serde(default)]
+    frame_count: u64,
+    // Checksums of the most recent frames, oldest first, and how many frames in a row the latest
+    // one has repeated for. Rebuilt soon enough after loading a state to not be worth saving.
+    #[serde(skip)]
+    frame_hashes: VecDeque<u64>,
+    #[serde(skip)]
+    unchanged_frames: u64,
+    // A display setting, so not part of save states.
+    #[serde(skip)]
+    color_correction: ColorCorrection,
+    // Only kept up to date while a frontend asks for it, see `set_rgba_output`.
+    #[serde(skip)]
+    rgba_output: Option<RgbaOutput>,
     layer_0: Layer0,
     layer_1: Layer1,
     layer_2: Layer2,
     layer_3: Layer3,
 
+    #[serde_as(as = "[_; 240]")]
     sprite_scanline: [SpritePixelQueryInfo; Self::LCD_WIDTH],
+    // Scratch space for the line being drawn. A state saved partway through a line loses the
+    // unblended pixels drawn so far, which only shows until the line is drawn again.
+    #[serde(skip)]
+    scanline: ScanlineCompositor,
+
+    // Set when a DISPSTAT write makes the current line match, reported on the next step.
+    #[serde(default)]
+    vcount_match_pending: bool,
 }
 
 fn half_word_fixed_point_to_float(val: u16) -> f64 {
@@ -510,6 +581,11 @@ impl Default for Lcd {
             obj_rotations: Box::new([ObjectRotationScalingInfo::default(); 0x20]),
             buffer: Box::new([[Rgb555::default(); Self::LCD_WIDTH]; Self::LCD_HEIGHT]),
             back_buffer: Box::new([[Rgb555::default(); Self::LCD_WIDTH]; Self::LCD_HEIGHT]),
+            frame_count: 0,
+            frame_hashes: VecDeque::new(),
+            unchanged_frames: 0,
+            color_correction: ColorCorrection::default(),
+            rgba_output: None,
             layer_0: Layer0::default(),
             layer_1: Layer1::default(),
             layer_2: Layer2::default(),
@@ -519,6 +595,9 @@ impl Default for Lcd {
                 obj_window: false,
                 sprite_pixel_info: None,
             }),
+            scanline: ScanlineCompositor::default(),
+
+            vcount_match_pending: false,
         }
     }
 }
@@ -526,11 +605,15 @@ impl Default for Lcd {
 impl Lcd {
     pub const LCD_WIDTH: usize = 240;
     pub const LCD_HEIGHT: usize = 160;
+    // Ten seconds worth of frames.
+    pub const FRAME_HASH_HISTORY_LEN: usize = 600;
 
+    #[cfg_attr(feature = "profile", tracing::instrument(level = "trace", skip_all))]
     pub fn step(&mut self) -> LcdStateChangeInfo {
         let mut vblank_entered = false;
         let mut hblank_entered = false;
-        let mut vcount_matched = false;
+        let mut vcount_matched = std::mem::take(&mut self.vcount_match_pending);
+        let scanline_started = self.dot == 0;
 
         if self.vcount < 160 {
             if self.dot == 0 {
@@ -539,6 +622,12 @@ impl Lcd {
                 self.state = LcdState::Visible;
                 self.sprite_scanline = self.get_sprite_scanline(self.vcount, 0, 0);
             } else if self.dot == 240 {
+                let line = &mut self.back_buffer[usize::from(self.vcount)];
+                self.scanline.resolve(line);
+                if let Some(rgba_output) = &mut self.rgba_output {
+                    rgba_output.draw_line(usize::from(self.vcount), line);
+                }
+
                 hblank_entered = true;
                 self.set_hblank_flag(true);
                 self.state = LcdState::HBlank;
@@ -553,6 +642,11 @@ impl Lcd {
             self.set_vblank_flag(true);
             self.state = LcdState::VBlank;
             std::mem::swap(&mut self.buffer, &mut self.back_buffer);
+            if let Some(rgba_output) = &mut self.rgba_output {
+                rgba_output.swap();
+            }
+            self.frame_count += 1;
+            self.record_frame_hash();
 
             self.layer_0.handle_vblank();
             self.layer_1.handle_vblank();
@@ -664,164 +758,28 @@ impl Lcd {
                 None
             };
 
-            let pixels = {
-                // Ensure that we do a stable sort.
-                let mut pixels_unsorted = [
-                    sprite_pixel_info,
-                    layer_0_pixel_info,
-                    layer_1_pixel_info,
-                    layer_2_pixel_info,
-                    layer_3_pixel_info,
-                ];
-                pixels_unsorted.sort_by(|pixel_one, pixel_two| match (pixel_one, pixel_two) {
-                    (
-                        Some(PixelInfo {
-                            priority: priority_one,
-                            ..
-                        }),
-                        Some(PixelInfo {
-                            priority: priority_two,
-                            ..
-                        }),
-                    ) => Ord::cmp(&priority_one, &priority_two),
-                    (Some(_), None) => Ordering::Less,
-                    (None, Some(_)) => Ordering::Greater,
-                    (None, None) => Ordering::Equal,
-                });
-
-                pixels_unsorted
-            };
-
-            let first_pixel = pixels[0];
-            let second_pixel = pixels[1];
-
-            let backdrop_info = (self.bg_palette_ram[0], PixelType::Backdrop);
-
-            let first_pixel_info = if let Some(PixelInfo {
-                color, pixel_type, ..
-            }) = first_pixel
-            {
-                (color, pixel_type)
-            } else {
-                backdrop_info
-            };
-
-            let second_pixel_info = if let Some(PixelInfo {
-                color, pixel_type, ..
-            }) = second_pixel
-            {
-                (color, pixel_type)
-            } else {
-                backdrop_info
-            };
-
-            // If we have a semi-transparent sprite with highest priority, alpha blending takes priority.
-            //
-            // In this case, we need to ensure that the highest-priority pixel is a sprite, but if so,
-            // the first special effect target doesn't need to select sprite.
-            let drawn_pixel = if sprite_semi_transparent
-                && matches!(first_pixel_info.1, PixelType::Sprite)
-                && self.special_effect_second_pixel(second_pixel_info.1)
-            {
-                first_pixel_info.0.blend(
-                    self.get_alpha_first_target_coefficient(),
-                    second_pixel_info.0,
-                    self.get_alpha_second_target_coefficient(),
-                )
-            } else {
-                match (
+            let layers = [
+                sprite_pixel_info,
+                layer_0_pixel_info,
+                layer_1_pixel_info,
+                layer_2_pixel_info,
+                layer_3_pixel_info,
+            ];
+
+            // Pixels that only need the topmost layer are left to be found along with the rest of
+            // the line at hblank.
+            let needs_blending = sprite_semi_transparent
+                || (displayed_selection.effects_displayed
+                    && !matches!(self.get_color_special_effect(), ColorSpecialEffect::None));
+            if needs_blending {
+                self.back_buffer[usize::from(pixel_y)][usize::from(pixel_x)] = self.compose_pixel(
+                    layers,
+                    sprite_semi_transparent,
                     displayed_selection.effects_displayed,
-                    self.get_color_special_effect(),
-                ) {
-                    (true, ColorSpecialEffect::AlphaBlending) => {
-                        if self.special_effect_first_pixel(first_pixel_info.1)
-                            && self.special_effect_second_pixel(second_pixel_info.1)
-                        {
-                            first_pixel_info.0.blend(
-                                self.get_alpha_first_target_coefficient(),
-                                second_pixel_info.0,
-                                self.get_alpha_second_target_coefficient(),
-                            )
-                        } else {
-                            first_pixel_info.0
-                        }
-                    }
-                    (true, ColorSpecialEffect::BrightnessIncrease) => {
-                        let pixel = pixels[0];
-
-                        let backdrop_info = (self.bg_palette_ram[0], PixelType::Backdrop);
-
-                        let (pixel_color, pixel_type) =
-                            if let Some(PixelInfo {
-                                color, pixel_type, ..
-                            }) = pixel
-                            {
-                                (color, pixel_type)
-                            } else {
-                                backdrop_info
-                            };
-
-                        if self.special_effect_first_pixel(pixel_type) {
-                            let new_red = pixel_color.red()
-                                + ((f64::from(31 - pixel_color.red())
-                                    * self.get_brightness_coefficient())
-                                    as u8);
-                            let new_green = pixel_color.green()
-                                + ((f64::from(31 - pixel_color.green())
-                                    * self.get_brightness_coefficient())
-                                    as u8);
-                            let new_blue = pixel_color.blue()
-                                + ((f64::from(31 - pixel_color.blue())
-                                    * self.get_brightness_coefficient())
-                                    as u8);
-
-                            Rgb555::new(new_red, new_green, new_blue)
-                        } else {
-                            pixel_color
-                        }
-                    }
-                    (true, ColorSpecialEffect::BrightnessDecrease) => {
-                        let pixel = pixels[0];
-
-                        let backdrop_info = (self.bg_palette_ram[0], PixelType::Backdrop);
-
-                        let (pixel_color, pixel_type) =
-                            if let Some(PixelInfo {
-                                color, pixel_type, ..
-                            }) = pixel
-                            {
-                                (color, pixel_type)
-                            } else {
-                                backdrop_info
-                            };
-
-                        if self.special_effect_first_pixel(pixel_type) {
-                            let new_red = pixel_color.red()
-                                - ((f64::from(pixel_color.red())
-                                    * self.get_brightness_coefficient())
-                                    as u8);
-                            let new_green = pixel_color.green()
-                                - ((f64::from(pixel_color.green())
-                                    * self.get_brightness_coefficient())
-                                    as u8);
-                            let new_blue = pixel_color.blue()
-                                - ((f64::from(pixel_color.blue())
-                                    * self.get_brightness_coefficient())
-                                    as u8);
-
-                            Rgb555::new(new_red, new_green, new_blue)
-                        } else {
-                            pixel_color
-                        }
-                    }
-                    (true, ColorSpecialEffect::None) | (false, _) => match pixels[0] {
-                        Some(PixelInfo { color, .. }) => color,
-                        None => self.bg_palette_ram[0],
-                    },
-                }
-            };
-
-            self.back_buffer[usize::from(pixel_y)][usize::from(pixel_x)] = drawn_pixel;
+                );
+            } else {
+                self.scanline.defer(pixel_x, layers, self.bg_palette_ram[0]);
+            }
         }
 
         self.dot += 1;
@@ -834,15 +792,16 @@ impl Lcd {
                 self.vcount = 0;
             }
 
-            if self.vcount == self.get_vcount_setting() {
-                vcount_matched = true;
-            }
+            let matched = self.vcount == self.get_vcount_setting();
+            self.set_vcounter_flag(matched);
+            vcount_matched |= matched;
         }
 
         LcdStateChangeInfo {
             hblank_entered,
             vblank_entered,
             vcount_matched,
+            scanline_started,
         }
     }
 
@@ -857,6 +816,16 @@ impl Lcd {
         const WORLD_WIDTH: u16 = 512;
         const WORLD_HEIGHT: u16 = 256;
 
+        // GBATEK: The number of OBJ render cycles per line is 1210 (or 954 with H-Blank Interval
+        // Free set). Normal OBJs take 1 cycle per pixel of width, and rotation/scaling OBJs
+        // 10 + 2 cycles per pixel of (doubled, if double size) width. OBJs that don't fit in what's
+        // left of the budget aren't drawn, along with any after them.
+        let mut render_cycles_left: u16 = if self.get_hblank_interval_free() {
+            954
+        } else {
+            1210
+        };
+
         let mut results = array::from_fn(|_| SpritePixelQueryInfo {
             obj_window: false,
             sprite_pixel_info: None,
@@ -883,11 +852,28 @@ impl Lcd {
             let c = half_word_fixed_point_to_float(rotation_info.c);
             let d = half_word_fixed_point_to_float(rotation_info.d);
 
-            let width_range = if obj.get_rotation_scaling_flag() && obj.get_double_size_flag() {
-                sprite_width * 2
+            let (width_range, height_range) =
+                if obj.get_rotation_scaling_flag() && obj.get_double_size_flag() {
+                    (sprite_width * 2, sprite_height * 2)
+                } else {
+                    (sprite_width, sprite_height)
+                };
+
+            let on_scanline = base_corner_offset_y < height_range
+                && (obj.get_rotation_scaling_flag() || !obj.get_obj_disable_flag());
+            if !on_scanline {
+                continue;
+            }
+
+            let render_cycles = if obj.get_rotation_scaling_flag() {
+                10 + (2 * width_range)
             } else {
-                sprite_width
+                width_range
+            };
+            let Some(remaining_cycles) = render_cycles_left.checked_sub(render_cycles) else {
+                break;
             };
+            render_cycles_left = remaining_cycles;
 
             for base_corner_offset_x in 0..width_range {
                 let pixel_x = (sprite_x + base_corner_offset_x) % WORLD_WIDTH;
@@ -1206,10 +1192,24 @@ impl Lcd {
         u16: DataAccess<T>,
     {
         // Not all bits in lcd status are writable.
-        const LCD_STATUS_WRITABLE_MASK: u16 = 0b11111111_10111000;
+        const LCD_STATUS_WRITABLE_MASK: u16 = 0b11111111_00111000;
         let new_status = self.lcd_status.set_data(value, index);
         self.lcd_status = (new_status & LCD_STATUS_WRITABLE_MASK)
-            | (self.lcd_status & (!LCD_STATUS_WRITABLE_MASK))
+            | (self.lcd_status & (!LCD_STATUS_WRITABLE_MASK));
+
+        // The comparison is continuous, so changing the setting to the current line mid-line
+        // sets the flag (and can request an interrupt) right away.
+        let matched = self.vcount == self.get_vcount_setting();
+        if matched && !self.get_vcounter_flag() {
+            self.vcount_match_pending = true;
+        }
+        self.set_vcounter_flag(matched);
+    }
+
+    // The position of the PPU within the current scanline, from 0 to 307. Dots from 240 on are
+    // in hblank.
+    pub fn current_scanline_dot(&self) -> u16 {
+        self.dot
     }
 
     pub fn read_mosaic_size<T>(&self, index: u32) -> T
@@ -1445,23 +1445,13 @@ impl Lcd {
     pub fn read_vram_hword(&self, offset: u32) -> u16 {
         assert!(offset & 0b1 == 0);
 
-        let low_byte = self.vram[offset as usize];
-        let high_byte = self.vram[(offset + 1) as usize];
-
-        u16::from_le_bytes([low_byte, high_byte])
+        self.vram.read_u16_le(offset as usize)
     }
 
     pub fn read_vram_word(&self, offset: u32) -> u32 {
         assert!(offset & 0b11 == 0);
 
-        let le_bytes = [
-            self.vram[offset as usize],
-            self.vram[(offset + 1) as usize],
-            self.vram[(offset + 2) as usize],
-            self.vram[(offset + 3) as usize],
-        ];
-
-        u32::from_le_bytes(le_bytes)
+        self.vram.read_u32_le(offset as usize)
     }
 
     pub fn write_vram_byte(&mut self, value: u8, offset: u32) {
@@ -1515,18 +1505,13 @@ impl Lcd {
     pub fn write_vram_hword(&mut self, value: u16, offset: u32) {
         assert!(offset & 0b1 == 0);
 
-        let [low_byte, high_byte] = value.to_le_bytes();
-
-        self.vram[offset as usize] = low_byte;
-        self.vram[(offset + 1) as usize] = high_byte;
+        self.vram.write_u16_le(value, offset as usize);
     }
 
     pub fn write_vram_word(&mut self, value: u32, offset: u32) {
         assert!(offset & 0b11 == 0);
 
-        for (byte_offset, byte) in value.to_le_bytes().into_iter().enumerate() {
-            self.vram[(offset as usize) + byte_offset] = byte;
-        }
+        self.vram.write_u32_le(value, offset as usize);
     }
 
     pub fn read_oam_byte(&self, offset: u32) -> u8 {
@@ -2002,6 +1987,12 @@ impl Lcd {
         }
     }
 
+    fn get_hblank_interval_free(&self) -> bool {
+        const HBLANK_INTERVAL_FREE_BIT_INDEX: usize = 5;
+
+        self.lcd_control.get_bit(HBLANK_INTERVAL_FREE_BIT_INDEX)
+    }
+
     fn get_obj_tile_mapping(&self) -> ObjectTileMapping {
         const TILE_MAPPING_BIT_INDEX: usize = 6;
 
@@ -2072,6 +2063,18 @@ impl Lcd {
         self.lcd_status = self.lcd_status.set_bit(HBLANK_FLAG_BIT_INDEX, set);
     }
 
+    fn get_vcounter_flag(&self) -> bool {
+        const VCOUNTER_FLAG_BIT_INDEX: usize = 2;
+
+        self.lcd_status.get_bit(VCOUNTER_FLAG_BIT_INDEX)
+    }
+
+    fn set_vcounter_flag(&mut self, set: bool) {
+        const VCOUNTER_FLAG_BIT_INDEX: usize = 2;
+
+        self.lcd_status = self.lcd_status.set_bit(VCOUNTER_FLAG_BIT_INDEX, set);
+    }
+
     pub fn get_vblank_irq_enable(&self) -> bool {
         const VBLANK_IRQ_ENABLE_BIT_INDEX: usize = 3;
 
@@ -2132,6 +2135,160 @@ impl Lcd {
             + 1) as u16
     }
 
+    // Draws a single pixel from the OBJ and BG0-3 pixels at its position, in that order, applying
+    // any blending.
+    fn compose_pixel(
+        &self,
+        layers: [Option<PixelInfo>; 5],
+        sprite_semi_transparent: bool,
+        effects_displayed: bool,
+    ) -> Rgb555 {
+        let pixels = {
+            // Ensure that we do a stable sort.
+            let mut pixels_unsorted = layers;
+            pixels_unsorted.sort_by(|pixel_one, pixel_two| match (pixel_one, pixel_two) {
+                (
+                    Some(PixelInfo {
+                        priority: priority_one,
+                        ..
+                    }),
+                    Some(PixelInfo {
+                        priority: priority_two,
+                        ..
+                    }),
+                ) => Ord::cmp(&priority_one, &priority_two),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            });
+
+            pixels_unsorted
+        };
+
+        let first_pixel = pixels[0];
+        let second_pixel = pixels[1];
+
+        let backdrop_info = (self.bg_palette_ram[0], PixelType::Backdrop);
+
+        let first_pixel_info = if let Some(PixelInfo {
+            color, pixel_type, ..
+        }) = first_pixel
+        {
+            (color, pixel_type)
+        } else {
+            backdrop_info
+        };
+
+        let second_pixel_info = if let Some(PixelInfo {
+            color, pixel_type, ..
+        }) = second_pixel
+        {
+            (color, pixel_type)
+        } else {
+            backdrop_info
+        };
+
+        // If we have a semi-transparent sprite with highest priority, alpha blending takes priority.
+        //
+        // In this case, we need to ensure that the highest-priority pixel is a sprite, but if so,
+        // the first special effect target doesn't need to select sprite.
+        if sprite_semi_transparent
+            && matches!(first_pixel_info.1, PixelType::Sprite)
+            && self.special_effect_second_pixel(second_pixel_info.1)
+        {
+            first_pixel_info.0.blend(
+                self.get_alpha_first_target_coefficient(),
+                second_pixel_info.0,
+                self.get_alpha_second_target_coefficient(),
+            )
+        } else {
+            match (effects_displayed, self.get_color_special_effect()) {
+                (true, ColorSpecialEffect::AlphaBlending) => {
+                    if self.special_effect_first_pixel(first_pixel_info.1)
+                        && self.special_effect_second_pixel(second_pixel_info.1)
+                    {
+                        first_pixel_info.0.blend(
+                            self.get_alpha_first_target_coefficient(),
+                            second_pixel_info.0,
+                            self.get_alpha_second_target_coefficient(),
+                        )
+                    } else {
+                        first_pixel_info.0
+                    }
+                }
+                (true, ColorSpecialEffect::BrightnessIncrease) => {
+                    let pixel = pixels[0];
+
+                    let backdrop_info = (self.bg_palette_ram[0], PixelType::Backdrop);
+
+                    let (pixel_color, pixel_type) =
+                        if let Some(PixelInfo {
+                            color, pixel_type, ..
+                        }) = pixel
+                        {
+                            (color, pixel_type)
+                        } else {
+                            backdrop_info
+                        };
+
+                    if self.special_effect_first_pixel(pixel_type) {
+                        let new_red = pixel_color.red()
+                            + ((f64::from(31 - pixel_color.red())
+                                * self.get_brightness_coefficient())
+                                as u8);
+                        let new_green = pixel_color.green()
+                            + ((f64::from(31 - pixel_color.green())
+                                * self.get_brightness_coefficient())
+                                as u8);
+                        let new_blue = pixel_color.blue()
+                            + ((f64::from(31 - pixel_color.blue())
+                                * self.get_brightness_coefficient())
+                                as u8);
+
+                        Rgb555::new(new_red, new_green, new_blue)
+                    } else {
+                        pixel_color
+                    }
+                }
+                (true, ColorSpecialEffect::BrightnessDecrease) => {
+                    let pixel = pixels[0];
+
+                    let backdrop_info = (self.bg_palette_ram[0], PixelType::Backdrop);
+
+                    let (pixel_color, pixel_type) =
+                        if let Some(PixelInfo {
+                            color, pixel_type, ..
+                        }) = pixel
+                        {
+                            (color, pixel_type)
+                        } else {
+                            backdrop_info
+                        };
+
+                    if self.special_effect_first_pixel(pixel_type) {
+                        let new_red = pixel_color.red()
+                            - ((f64::from(pixel_color.red()) * self.get_brightness_coefficient())
+                                as u8);
+                        let new_green = pixel_color.green()
+                            - ((f64::from(pixel_color.green()) * self.get_brightness_coefficient())
+                                as u8);
+                        let new_blue = pixel_color.blue()
+                            - ((f64::from(pixel_color.blue()) * self.get_brightness_coefficient())
+                                as u8);
+
+                        Rgb555::new(new_red, new_green, new_blue)
+                    } else {
+                        pixel_color
+                    }
+                }
+                (true, ColorSpecialEffect::None) | (false, _) => match pixels[0] {
+                    Some(PixelInfo { color, .. }) => color,
+                    None => self.bg_palette_ram[0],
+                },
+            }
+        }
+    }
+
     fn special_effect_first_pixel(&self, pixel_type: PixelType) -> bool {
         const BG0_FIRST_PIXEL_BIT_INDEX: usize = 0;
         const BG1_FIRST_PIXEL_BIT_INDEX: usize = 1;
@@ -2461,7 +2618,231 @@ impl Lcd {
 }
 
 impl Lcd {
-    pub fn get_buffer(&self) -> &[[Rgb555; Self::LCD_WIDTH]; Self::LCD_HEIGHT] {
+    pub fn get_buffer(&self) -> &FrameBuffer {
         &self.buffer
     }
+
+    // Increases by one every time `get_buffer` starts returning a new frame, so a frontend can
+    // tell whether it missed a frame or is about to show the same one twice.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    // Hashes the current frame, as `calculate_lcd_checksum` does.
+    pub fn buffer_checksum(&self) -> u64 {
+        let mut hasher = Xxh3::default();
+
+        for pixel in self.buffer.iter().flatten() {
+            hasher.write_u8(pixel.red());
+            hasher.write_u8(pixel.green());
+            hasher.write_u8(pixel.blue());
+        }
+
+        hasher.finish()
+    }
+
+    // The checksums of up to the last `n` frames, oldest first, for noticing a game that stopped
+    // drawing or matching against an expected sequence of frames. Only the last
+    // `FRAME_HASH_HISTORY_LEN` are kept.
+    pub fn frame_hash_history(&self, n: usize) -> Vec<u64> {
+        let skipped = self.frame_hashes.len().saturating_sub(n);
+        self.frame_hashes.iter().skip(skipped).copied().collect()
+    }
+
+    // How many frames in a row the current one has been shown for, not counting the first.
+    // Unlike the history, this isn't limited, so a game that froze a while ago can be told
+    // from one that froze just now.
+    pub fn unchanged_frames(&self) -> u64 {
+        self.unchanged_frames
+    }
+
+    fn record_frame_hash(&mut self) {
+        let hash = self.buffer_checksum();
+        if self.frame_hashes.back() == Some(&hash) {
+            self.unchanged_frames += 1;
+        } else {
+            self.unchanged_frames = 0;
+        }
+
+        if self.frame_hashes.len() == Self::FRAME_HASH_HISTORY_LEN {
+            self.frame_hashes.pop_front();
+        }
+        self.frame_hashes.push_back(hash);
+    }
+
+    pub fn get_color_correction(&self) -> ColorCorrection {
+        self.color_correction
+    }
+
+    pub fn set_color_correction(&mut self, color_correction: ColorCorrection) {
+        self.color_correction = color_correction;
+        if let Some(rgba_output) = &mut self.rgba_output {
+            rgba_output.set_color_correction(color_correction, &self.buffer);
+        }
+    }
+
+    // Keeps an RGBA8888 copy of every frame, with color correction applied, converted line by
+    // line as they're drawn. Much cheaper for frontends than converting whole frames themselves,
+    // and the conversion happens on the emulation thread, not the render thread.
+    pub fn set_rgba_output(&mut self, enabled: bool) {
+        self.rgba_output = match (enabled, self.rgba_output.take()) {
+            (true, Some(rgba_output)) => Some(rgba_output),
+            (true, None) => Some(RgbaOutput::new(self.color_correction, &self.buffer)),
+            (false, _) => None,
+        };
+    }
+
+    pub fn rgba_output_enabled(&self) -> bool {
+        self.rgba_output.is_some()
+    }
+
+    // Row-major, 4 bytes per pixel with full alpha, matching `get_buffer`. `None` unless enabled
+    // with `set_rgba_output`.
+    pub fn get_buffer_rgba8888(&self) -> Option<&[u8]> {
+        self.rgba_output.as_ref().map(RgbaOutput::buffer)
+    }
+
+    // Row-major, 3 bytes per pixel, with the configured color correction applied.
+    pub fn get_buffer_rgb888(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.write_buffer_rgb888(&mut buffer);
+        buffer
+    }
+
+    // Like `get_buffer_rgb888`, but replaces the contents of `buffer`, reusing its allocation.
+    pub fn write_buffer_rgb888(&self, buffer: &mut Vec<u8>) {
+        buffer.clear();
+
+        if let Some(rgba) = self.get_buffer_rgba8888() {
+            buffer.extend(
+                rgba.chunks_exact(4)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]),
+            );
+            return;
+        }
+
+        buffer.extend(
+            self.get_buffer()
+                .iter()
+                .flatten()
+                .flat_map(|pixel| self.color_correction.apply(*pixel)),
+        );
+    }
+
+    // The whole map of background `bg` (0-3), or None if the current mode doesn't show it as a
+    // tiled background.
+    pub fn background_map(&self, bg: usize) -> Option<BackgroundMap> {
+        let vram = self.vram.as_slice();
+        let bg_palette = self.bg_palette_ram.as_slice();
+
+        match (self.get_bg_mode(), bg) {
+            (BgMode::Mode0 | BgMode::Mode1, 0) => Some(BackgroundMap::text(
+                self.read_layer0_bg_control(0),
+                (self.read_layer0_x_offset(0), self.read_layer0_y_offset(0)),
+                vram,
+                bg_palette,
+            )),
+            (BgMode::Mode0 | BgMode::Mode1, 1) => Some(BackgroundMap::text(
+                self.read_layer1_bg_control(0),
+                (self.read_layer1_x_offset(0), self.read_layer1_y_offset(0)),
+                vram,
+                bg_palette,
+            )),
+            (BgMode::Mode0, 2) => Some(BackgroundMap::text(
+                self.read_layer2_bg_control(0),
+                (
+                    self.read_layer2_text_x_offset(0),
+                    self.read_layer2_text_y_offset(0),
+                ),
+                vram,
+                bg_palette,
+            )),
+            (BgMode::Mode0, 3) => Some(BackgroundMap::text(
+                self.read_layer3_bg_control(0),
+                (
+                    self.read_layer3_text_x_offset(0),
+                    self.read_layer3_text_y_offset(0),
+                ),
+                vram,
+                bg_palette,
+            )),
+            (BgMode::Mode1 | BgMode::Mode2, 2) => Some(BackgroundMap::affine(
+                self.read_layer2_bg_control(0),
+                (
+                    self.read_layer2_affine_x_offset(0),
+                    self.read_layer2_affine_y_offset(0),
+                ),
+                [
+                    self.read_layer2_affine_param_a(0),
+                    self.read_layer2_affine_param_b(0),
+                    self.read_layer2_affine_param_c(0),
+                    self.read_layer2_affine_param_d(0),
+                ],
+                vram,
+                bg_palette,
+            )),
+            (BgMode::Mode2, 3) => Some(BackgroundMap::affine(
+                self.read_layer3_bg_control(0),
+                (
+                    self.read_layer3_affine_x_offset(0),
+                    self.read_layer3_affine_y_offset(0),
+                ),
+                [
+                    self.read_layer3_affine_param_a(0),
+                    self.read_layer3_affine_param_b(0),
+                    self.read_layer3_affine_param_c(0),
+                    self.read_layer3_affine_param_d(0),
+                ],
+                vram,
+                bg_palette,
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn export_frame_png<W: Write>(&self, writer: W) -> anyhow::Result<()> {
+        Self::encode_buffer_png(self.get_buffer(), writer)
+    }
+
+    pub fn export_frame_raw<W: Write>(&self, writer: W) -> io::Result<()> {
+        Self::encode_buffer_raw(self.get_buffer(), writer)
+    }
+
+    // Row-major, 3 bytes per pixel.
+    pub fn buffer_to_rgb888(buffer: &[[Rgb555; Self::LCD_WIDTH]; Self::LCD_HEIGHT]) -> Vec<u8> {
+        buffer
+            .iter()
+            .flatten()
+            .flat_map(|pixel| pixel.to_rgb888())
+            .collect()
+    }
+
+    pub fn encode_buffer_png<W: Write>(
+        buffer: &[[Rgb555; Self::LCD_WIDTH]; Self::LCD_HEIGHT],
+        writer: W,
+    ) -> anyhow::Result<()> {
+        let mut encoder =
+            png::Encoder::new(writer, Self::LCD_WIDTH as u32, Self::LCD_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut png_writer = encoder.write_header()?;
+        png_writer.write_image_data(&Self::buffer_to_rgb888(buffer))?;
+        png_writer.finish()?;
+
+        Ok(())
+    }
+
+    // Dumps the unconverted RGB555 values (little-endian, row-major), which allows pixel-exact
+    // comparisons without any loss from the 8-bit expansion.
+    pub fn encode_buffer_raw<W: Write>(
+        buffer: &[[Rgb555; Self::LCD_WIDTH]; Self::LCD_HEIGHT],
+        mut writer: W,
+    ) -> io::Result<()> {
+        for pixel in buffer.iter().flatten() {
+            writer.write_all(&pixel.to_int().to_le_bytes())?;
+        }
+
+        writer.flush()
+    }
 }
@@ -0,0 +1,174 @@
+use std::ops::RangeInclusive;
+
+use crate::{BitManipulation, LittleEndianBytes};
+
+use super::{half_word_fixed_point_to_float, word_fixed_point_to_float, Lcd, Rgb555};
+
+const TILE_SIZE: usize = 8;
+const SCREEN_BLOCK_TILES: usize = 32;
+const SCREEN_BLOCK_SIZE: usize = 0x800;
+
+// Where the screen currently sits on a background map, in map pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackgroundViewport {
+    // The top left corner of the screen, which wraps around the edges of the map.
+    Text { x: u16, y: u16 },
+    // The corners of the screen, clockwise from the top left. Any of them may lie outside the map.
+    Affine { corners: [(f64, f64); 4] },
+}
+
+// A background's whole map, drawn without scrolling, mosaic, windows or effects, for debuggers
+// to show alongside where the screen is looking at it.
+#[derive(Clone, Debug)]
+pub struct BackgroundMap {
+    pub width: usize, // in pixels
+    pub height: usize,
+    pub pixels: Vec<Option<Rgb555>>, // row-major, None where transparent
+    pub viewport: BackgroundViewport,
+}
+
+impl BackgroundMap {
+    pub(super) fn text(
+        bg_control: u16,
+        (x_offset, y_offset): (u16, u16),
+        vram: &[u8],
+        bg_palette: &[Rgb555],
+    ) -> Self {
+        const OFFSET_BIT_RANGE: RangeInclusive<usize> = 0..=8;
+
+        let (width_tiles, height_tiles) = match bg_control.get_bit_range(14..=15) {
+            0 => (32, 32),
+            1 => (64, 32),
+            2 => (32, 64),
+            _ => (64, 64),
+        };
+        let map_data_base = map_data_base(bg_control);
+        let tile_data_base = tile_data_base(bg_control);
+        let eight_bit = bg_control.get_bit(7);
+
+        let width = width_tiles * TILE_SIZE;
+        let height = height_tiles * TILE_SIZE;
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (tile_x, tile_y) = (x / TILE_SIZE, y / TILE_SIZE);
+
+                // Maps wider or taller than 32 tiles are made of 32x32 tile screen blocks, laid
+                // out left to right then top to bottom.
+                let screen_block = (tile_x / SCREEN_BLOCK_TILES)
+                    + ((tile_y / SCREEN_BLOCK_TILES) * (width_tiles / SCREEN_BLOCK_TILES));
+                let map_data_idx = map_data_base
+                    + (screen_block * SCREEN_BLOCK_SIZE)
+                    + ((((tile_y % SCREEN_BLOCK_TILES) * SCREEN_BLOCK_TILES)
+                        + (tile_x % SCREEN_BLOCK_TILES))
+                        * 2);
+                let map_data = vram.read_u16_le(map_data_idx);
+
+                let tile_number = usize::from(map_data.get_bit_range(0..=9));
+                let mut tile_data_x = x % TILE_SIZE;
+                if map_data.get_bit(10) {
+                    tile_data_x = 7 - tile_data_x;
+                }
+                let mut tile_data_y = y % TILE_SIZE;
+                if map_data.get_bit(11) {
+                    tile_data_y = 7 - tile_data_y;
+                }
+                let palette_number = map_data.get_bit_range(12..=15) as u8;
+
+                let palette_idx = if eight_bit {
+                    let tile_idx =
+                        tile_data_base + (64 * tile_number) + (tile_data_y * 8) + tile_data_x;
+                    vram.get(tile_idx).copied().unwrap_or(0)
+                } else {
+                    let tile_idx =
+                        tile_data_base + (32 * tile_number) + (tile_data_y * 4) + (tile_data_x / 2);
+                    let tile_data = vram.get(tile_idx).copied().unwrap_or(0);
+                    let palette_idx_low = if tile_data_x.is_multiple_of(2) {
+                        tile_data.get_bit_range(0..=3)
+                    } else {
+                        tile_data.get_bit_range(4..=7)
+                    };
+
+                    if palette_idx_low == 0 {
+                        0
+                    } else {
+                        palette_idx_low.set_bit_range(palette_number, 4..=7)
+                    }
+                };
+
+                pixels.push((palette_idx != 0).then(|| bg_palette[usize::from(palette_idx)]));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+            viewport: BackgroundViewport::Text {
+                x: x_offset.get_bit_range(OFFSET_BIT_RANGE) % (width as u16),
+                y: y_offset.get_bit_range(OFFSET_BIT_RANGE) % (height as u16),
+            },
+        }
+    }
+
+    // `reference` is the reference point registers, and `params` the A-D rotation and scaling
+    // registers, as written rather than as latched part way through a frame.
+    pub(super) fn affine(
+        bg_control: u16,
+        (x_reference, y_reference): (u32, u32),
+        params: [u16; 4],
+        vram: &[u8],
+        bg_palette: &[Rgb555],
+    ) -> Self {
+        let map_tiles = 16 << bg_control.get_bit_range(14..=15);
+        let map_data_base = map_data_base(bg_control);
+        let tile_data_base = tile_data_base(bg_control);
+
+        let size = map_tiles * TILE_SIZE;
+        let mut pixels = Vec::with_capacity(size * size);
+
+        for y in 0..size {
+            for x in 0..size {
+                let map_data_idx = map_data_base + ((y / TILE_SIZE) * map_tiles) + (x / TILE_SIZE);
+                let tile_number = usize::from(vram.get(map_data_idx).copied().unwrap_or(0));
+
+                let tile_idx =
+                    tile_data_base + (tile_number * 64) + ((y % TILE_SIZE) * 8) + (x % TILE_SIZE);
+                let palette_idx = vram.get(tile_idx).copied().unwrap_or(0);
+
+                pixels.push((palette_idx != 0).then(|| bg_palette[usize::from(palette_idx)]));
+            }
+        }
+
+        let x_reference = word_fixed_point_to_float(x_reference);
+        let y_reference = word_fixed_point_to_float(y_reference);
+        let [a, b, c, d] = params.map(half_word_fixed_point_to_float);
+        let (right, bottom) = (Lcd::LCD_WIDTH as f64, Lcd::LCD_HEIGHT as f64);
+        let corners = [(0.0, 0.0), (right, 0.0), (right, bottom), (0.0, bottom)].map(
+            |(screen_x, screen_y)| {
+                (
+                    x_reference + (a * screen_x) + (b * screen_y),
+                    y_reference + (c * screen_x) + (d * screen_y),
+                )
+            },
+        );
+
+        Self {
+            width: size,
+            height: size,
+            pixels,
+            viewport: BackgroundViewport::Affine { corners },
+        }
+    }
+}
+
+fn map_data_base(bg_control: u16) -> usize {
+    usize::from(bg_control.get_bit_range(8..=12)) * SCREEN_BLOCK_SIZE
+}
+
+fn tile_data_base(bg_control: u16) -> usize {
+    const CHARACTER_BASE_BLOCK_SIZE: usize = 0x4000;
+
+    usize::from(bg_control.get_bit_range(2..=3)) * CHARACTER_BASE_BLOCK_SIZE
+}
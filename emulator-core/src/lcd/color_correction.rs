@@ -0,0 +1,125 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use super::Rgb555;
+
+// How RGB555 output is converted to RGB888 for display on a modern screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorCorrection {
+    // Each channel expanded to 8 bits as is, as if the GBA had an sRGB display.
+    #[default]
+    Raw,
+    // The original unlit AGB-001 panel, which is dark, washed out, and bleeds colors into each
+    // other. Games designed for it tend to look oversaturated without this.
+    Agb,
+    // The backlit AGS-101 panel, which is close to sRGB but slightly less saturated.
+    Ags101,
+}
+
+struct Profile {
+    panel_gamma: f32,
+    // Mixes the linear panel channels into linear sRGB channels, one row per output channel.
+    matrix: [[f32; 3]; 3],
+    output_scale: f32,
+}
+
+const DISPLAY_GAMMA: f32 = 2.2;
+
+// Based on the correction byuu worked out for higan, which weighs the channels out of 255.
+const AGB_PROFILE: Profile = Profile {
+    panel_gamma: 4.0,
+    matrix: [
+        [1.0, 50.0 / 255.0, 0.0],
+        [10.0 / 255.0, 230.0 / 255.0, 30.0 / 255.0],
+        [50.0 / 255.0, 10.0 / 255.0, 220.0 / 255.0],
+    ],
+    output_scale: 255.0 / 280.0,
+};
+
+const AGS_101_PROFILE: Profile = Profile {
+    panel_gamma: 2.2,
+    matrix: [[0.86, 0.11, 0.03], [0.04, 0.90, 0.06], [0.02, 0.09, 0.89]],
+    output_scale: 1.0,
+};
+
+impl ColorCorrection {
+    pub const ALL: [ColorCorrection; 3] = [Self::Raw, Self::Agb, Self::Ags101];
+
+    pub fn apply(self, pixel: Rgb555) -> [u8; 3] {
+        let profile = match self {
+            Self::Raw => return pixel.to_rgb888(),
+            Self::Agb => &AGB_PROFILE,
+            Self::Ags101 => &AGS_101_PROFILE,
+        };
+
+        let linear = [pixel.red(), pixel.green(), pixel.blue()].map(|channel| {
+            (f32::from(channel) / f32::from(Rgb555::MAX_VALUE)).powf(profile.panel_gamma)
+        });
+
+        profile.matrix.map(|weights| {
+            let mixed: f32 = weights
+                .iter()
+                .zip(linear)
+                .map(|(weight, channel)| weight * channel)
+                .sum();
+            let corrected = mixed.powf(1.0 / DISPLAY_GAMMA) * profile.output_scale;
+
+            (corrected.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+    }
+}
+
+impl Display for ColorCorrection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Raw => f.write_str("raw"),
+            Self::Agb => f.write_str("agb"),
+            Self::Ags101 => f.write_str("ags-101"),
+        }
+    }
+}
+
+impl FromStr for ColorCorrection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|correction| correction.to_string() == s)
+            .ok_or_else(|| {
+                anyhow::anyhow!("unknown color correction '{s}', expected raw, agb or ags-101")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_correction_profiles() {
+        let white = Rgb555::from_int(0x7FFF);
+        let red = Rgb555::from_int(0x001F);
+        let black = Rgb555::from_int(0);
+
+        assert_eq!(ColorCorrection::Raw.apply(white), [0xFF; 3]);
+        assert_eq!(ColorCorrection::Raw.apply(red), [0xFF, 0, 0]);
+
+        for correction in ColorCorrection::ALL {
+            assert_eq!(correction.apply(black), [0; 3], "{correction}");
+            assert_eq!(
+                correction.to_string().parse::<ColorCorrection>().unwrap(),
+                correction
+            );
+        }
+
+        // The unlit panel can't reach full brightness, and bleeds red into the other channels.
+        let [agb_red, agb_green, agb_blue] = ColorCorrection::Agb.apply(red);
+        assert!(agb_red < 0xFF);
+        assert!(agb_green > 0 && agb_blue > 0);
+        assert!(ColorCorrection::Agb
+            .apply(white)
+            .iter()
+            .all(|&channel| channel < 0xFF));
+    }
+}
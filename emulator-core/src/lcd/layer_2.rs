@@ -1,13 +1,14 @@
 use std::ops::RangeInclusive;
 
-use crate::{BitManipulation, DataAccess};
+use crate::{BitManipulation, DataAccess, LittleEndianBytes};
+use serde::{Deserialize, Serialize};
 
 use super::{
     half_word_fixed_point_to_float, word_fixed_point_to_float, AffineDisplayOverflow,
     AffineScreenSize, BgMode, DisplayFrame, PaletteDepth, Rgb555, TextScreenSize,
 };
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub(super) struct Layer2 {
     bg_control: u16,
     text_x_offset: u16,
@@ -81,9 +82,7 @@ impl Layer2 {
 
                 let map_data_idx = map_data_base + (map_data_offset * 2);
 
-                let map_data_low = vram[map_data_idx];
-                let map_data_high = vram[map_data_idx + 1];
-                let map_data = u16::from_le_bytes([map_data_low, map_data_high]);
+                let map_data = vram.read_u16_le(map_data_idx);
 
                 let tile_number = map_data.get_bit_range(0..=9);
                 let horizontal_flip = map_data.get_bit(10);
@@ -224,9 +223,7 @@ impl Layer2 {
                 let pixel_idx = (usize::from(y) * super::Lcd::LCD_WIDTH) + usize::from(x);
                 let pixel_offset = pixel_idx * 2;
 
-                let pixel_low = vram[pixel_offset];
-                let pixel_high = vram[pixel_offset + 1];
-                let pixel_int = u16::from_le_bytes([pixel_low, pixel_high]);
+                let pixel_int = vram.read_u16_le(pixel_offset);
 
                 Some(Rgb555::from_int(pixel_int))
             }
@@ -274,9 +271,7 @@ impl Layer2 {
                 let pixel_idx = (usize::from(y) * usize::from(MODE_WIDTH)) + usize::from(x);
                 let pixel_offset = pixel_idx * 2;
 
-                let pixel_low = vram[pixel_offset];
-                let pixel_high = vram[pixel_offset + 1];
-                let pixel_int = u16::from_le_bytes([pixel_low, pixel_high]);
+                let pixel_int = vram.read_u16_le(pixel_offset);
 
                 Some(Rgb555::from_int(pixel_int))
             }
@@ -1,13 +1,14 @@
 use std::ops::RangeInclusive;
 
-use crate::{BitManipulation, DataAccess};
+use crate::{BitManipulation, DataAccess, LittleEndianBytes};
+use serde::{Deserialize, Serialize};
 
 use super::{
     half_word_fixed_point_to_float, word_fixed_point_to_float, AffineDisplayOverflow,
     AffineScreenSize, BgMode, PaletteDepth, Rgb555, TextScreenSize,
 };
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub(super) struct Layer3 {
     bg_control: u16,
     text_x_offset: u16,
@@ -80,9 +81,7 @@ impl Layer3 {
 
                 let map_data_idx = map_data_base + (map_data_offset * 2);
 
-                let map_data_low = vram[map_data_idx];
-                let map_data_high = vram[map_data_idx + 1];
-                let map_data = u16::from_le_bytes([map_data_low, map_data_high]);
+                let map_data = vram.read_u16_le(map_data_idx);
 
                 let tile_number = map_data.get_bit_range(0..=9);
                 let horizontal_flip = map_data.get_bit(10);
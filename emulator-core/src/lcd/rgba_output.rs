@@ -0,0 +1,67 @@
+use super::{ColorCorrection, FrameBuffer, Lcd, Rgb555};
+
+const BYTES_PER_PIXEL: usize = 4;
+const LINE_BYTES: usize = Lcd::LCD_WIDTH * BYTES_PER_PIXEL;
+const FRAME_BYTES: usize = LINE_BYTES * Lcd::LCD_HEIGHT;
+
+// Frames already converted to RGBA8888, so frontends can hand them straight to the display rather
+// than converting every pixel themselves. Each line is converted as it's finished, through a
+// table covering every RGB555 color, and double buffered alongside the RGB555 frames.
+#[derive(Clone, Debug)]
+pub(super) struct RgbaOutput {
+    palette: Box<[[u8; BYTES_PER_PIXEL]; 0x8000]>,
+    buffer: Box<[u8]>,
+    back_buffer: Box<[u8]>,
+}
+
+impl RgbaOutput {
+    pub(super) fn new(color_correction: ColorCorrection, frame: &FrameBuffer) -> Self {
+        let mut rgba_output = Self {
+            palette: Box::new([[0; BYTES_PER_PIXEL]; 0x8000]),
+            buffer: vec![0; FRAME_BYTES].into_boxed_slice(),
+            back_buffer: vec![0; FRAME_BYTES].into_boxed_slice(),
+        };
+        rgba_output.set_color_correction(color_correction, frame);
+
+        rgba_output
+    }
+
+    // Rebuilds the table, and converts `frame` again so the change shows up straight away.
+    pub(super) fn set_color_correction(
+        &mut self,
+        color_correction: ColorCorrection,
+        frame: &FrameBuffer,
+    ) {
+        for (color, entry) in self.palette.iter_mut().enumerate() {
+            let [red, green, blue] = color_correction.apply(Rgb555::from_int(color as u16));
+            *entry = [red, green, blue, 0xFF];
+        }
+
+        for (line, output) in frame.iter().zip(self.buffer.chunks_exact_mut(LINE_BYTES)) {
+            Self::convert(&self.palette, line, output);
+        }
+    }
+
+    pub(super) fn draw_line(&mut self, y: usize, line: &[Rgb555; Lcd::LCD_WIDTH]) {
+        let output = &mut self.back_buffer[y * LINE_BYTES..][..LINE_BYTES];
+        Self::convert(&self.palette, line, output);
+    }
+
+    pub(super) fn swap(&mut self) {
+        std::mem::swap(&mut self.buffer, &mut self.back_buffer);
+    }
+
+    pub(super) fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    fn convert(
+        palette: &[[u8; BYTES_PER_PIXEL]; 0x8000],
+        line: &[Rgb555; Lcd::LCD_WIDTH],
+        output: &mut [u8],
+    ) {
+        for (pixel, output) in line.iter().zip(output.chunks_exact_mut(BYTES_PER_PIXEL)) {
+            output.copy_from_slice(&palette[usize::from(pixel.to_int() & 0x7FFF)]);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use super::{Lcd, PixelInfo, Rgb555};
+
+// Candidates for each pixel: OBJ, BG0-3, then the backdrop. Ties in priority go to whichever
+// comes first.
+const CANDIDATES: usize = 6;
+const BACKDROP: usize = 5;
+const BACKDROP_PRIORITY: u32 = 4; // behind everything
+
+// Keys are packed two to a u64, as 32 bit lanes, so each step of the minimum handles two pixels.
+const LANES: usize = 2;
+const WORDS: usize = Lcd::LCD_WIDTH / LANES;
+const LANE_HIGH_BITS: u64 = 0x8000_0000_8000_0000;
+
+// No pixel, or one already drawn by the scalar path. Like every key, it leaves the top bit of
+// its lane clear, which `lane_min` relies on.
+const EMPTY: u32 = 0x7FFF_FFFF;
+const EMPTY_WORD: u64 = 0x7FFF_FFFF_7FFF_FFFF;
+
+// Finds the topmost pixel for a whole scanline at once. Most pixels only need whichever layer is
+// on top, with no blending involved, so while the line is drawn they're stored here as packed
+// priority, layer and color, leaving the rest to a branchless minimum per column at hblank.
+// Pixels that need blending are still drawn one at a time, as they're reached.
+//
+// The minimum works on pairs of pixels packed into a u64 rather than `std::arch` vectors, which
+// would need unsafe code for loads and stores.
+#[derive(Clone, Debug)]
+pub(super) struct ScanlineCompositor {
+    candidates: Box<[[u64; WORDS]; CANDIDATES]>,
+}
+
+impl Default for ScanlineCompositor {
+    fn default() -> Self {
+        Self {
+            candidates: Box::new([[EMPTY_WORD; WORDS]; CANDIDATES]),
+        }
+    }
+}
+
+impl ScanlineCompositor {
+    // Leaves the pixel at `x` to be drawn at the end of the line. `layers` are OBJ and BG0-3.
+    pub(super) fn defer(&mut self, x: u16, layers: [Option<PixelInfo>; 5], backdrop: Rgb555) {
+        let (word, lane) = (usize::from(x) / LANES, usize::from(x) % LANES);
+
+        for (layer, pixel_info) in layers.into_iter().enumerate() {
+            let key = pixel_info.map_or(EMPTY, |pixel_info| {
+                key(u32::from(pixel_info.priority), layer, pixel_info.color)
+            });
+            set_lane(&mut self.candidates[layer][word], lane, key);
+        }
+        set_lane(
+            &mut self.candidates[BACKDROP][word],
+            lane,
+            key(BACKDROP_PRIORITY, BACKDROP, backdrop),
+        );
+    }
+
+    // Draws every deferred pixel into `line`.
+    pub(super) fn resolve(&mut self, line: &mut [Rgb555; Lcd::LCD_WIDTH]) {
+        let mut topmost = [EMPTY_WORD; WORDS];
+        for candidates in self.candidates.iter() {
+            for (topmost, &candidate) in topmost.iter_mut().zip(candidates) {
+                *topmost = lane_min(*topmost, candidate);
+            }
+        }
+
+        for (pixels, topmost) in line.chunks_exact_mut(LANES).zip(topmost) {
+            for (lane, pixel) in pixels.iter_mut().enumerate() {
+                let key = (topmost >> (lane * 32)) as u32;
+                if key != EMPTY {
+                    *pixel = Rgb555::from_int(key as u16);
+                }
+            }
+        }
+
+        // Nothing carries over into the next line.
+        for candidates in self.candidates.iter_mut() {
+            candidates.fill(EMPTY_WORD);
+        }
+    }
+}
+
+// Orders by priority, then layer, with the color in the low bits.
+fn key(priority: u32, layer: usize, color: Rgb555) -> u32 {
+    (priority << 19) | ((layer as u32) << 16) | u32::from(color.to_int())
+}
+
+fn set_lane(word: &mut u64, lane: usize, key: u32) {
+    let shift = lane * 32;
+    *word = (*word & !(0xFFFF_FFFF << shift)) | (u64::from(key) << shift);
+}
+
+// The smaller of each pair of lanes. Setting the top bit of every lane in `a` keeps the
+// subtraction from borrowing from one lane into the next, and that bit then stays set only in
+// the lanes where `a` is at least `b`.
+fn lane_min(a: u64, b: u64) -> u64 {
+    let a_not_less = ((a | LANE_HIGH_BITS) - b) & LANE_HIGH_BITS;
+    let mask = (a_not_less >> 31) * 0xFFFF_FFFF;
+    (b & mask) | (a & !mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcd::PixelType;
+
+    #[test]
+    fn lane_min_matches_scalar() {
+        let values = [0, 1, 0x7FFF, 0x20_7FFF, 0x25_0000, EMPTY - 1, EMPTY];
+        for &a_low in &values {
+            for &a_high in &values {
+                for &b_low in &values {
+                    for &b_high in &values {
+                        let a = u64::from(a_low) | (u64::from(a_high) << 32);
+                        let b = u64::from(b_low) | (u64::from(b_high) << 32);
+                        let expected =
+                            u64::from(a_low.min(b_low)) | (u64::from(a_high.min(b_high)) << 32);
+                        assert_eq!(lane_min(a, b), expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn matches_compose_pixel() {
+        const LAYER_TYPES: [PixelType; 5] = [
+            PixelType::Sprite,
+            PixelType::Layer0,
+            PixelType::Layer1,
+            PixelType::Layer2,
+            PixelType::Layer3,
+        ];
+
+        let mut lcd = Lcd::default();
+        lcd.bg_palette_ram[0] = Rgb555::from_int(0x1234);
+
+        // xorshift, so every run covers the same pixels.
+        let mut state = 0x2545F491u32;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        let mut compositor = ScanlineCompositor::default();
+        for _ in 0..16 {
+            let mut expected = [Rgb555::default(); Lcd::LCD_WIDTH];
+            for (x, expected) in expected.iter_mut().enumerate() {
+                let layers = LAYER_TYPES.map(|pixel_type| {
+                    let value = random();
+                    (value % 3 != 0).then(|| PixelInfo {
+                        priority: ((value >> 8) % 4) as u16,
+                        color: Rgb555::from_int((value >> 16) as u16 & 0x7FFF),
+                        pixel_type,
+                    })
+                });
+
+                *expected = lcd.compose_pixel(layers, false, false);
+                compositor.defer(x as u16, layers, lcd.bg_palette_ram[0]);
+            }
+
+            let mut line = [Rgb555::default(); Lcd::LCD_WIDTH];
+            compositor.resolve(&mut line);
+            assert_eq!(line.map(Rgb555::to_int), expected.map(Rgb555::to_int));
+        }
+    }
+}
@@ -0,0 +1,93 @@
+// Typed access to little-endian values in byte arrays, like WRAM and VRAM. The GBA is little
+// endian, so values are always assembled from their bytes explicitly, rather than reinterpreting
+// memory in the host's byte order, which keeps the emulator correct on big-endian hosts.
+//
+// Offsets are in bytes, and panic if out of bounds like indexing does.
+pub trait LittleEndianBytes {
+    fn read_u16_le(&self, offset: usize) -> u16;
+
+    fn read_u32_le(&self, offset: usize) -> u32;
+
+    fn write_u16_le(&mut self, value: u16, offset: usize);
+
+    fn write_u32_le(&mut self, value: u32, offset: usize);
+}
+
+impl LittleEndianBytes for [u8] {
+    fn read_u16_le(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self[offset], self[offset + 1]])
+    }
+
+    fn read_u32_le(&self, offset: usize) -> u32 {
+        u32::from_le_bytes([
+            self[offset],
+            self[offset + 1],
+            self[offset + 2],
+            self[offset + 3],
+        ])
+    }
+
+    fn write_u16_le(&mut self, value: u16, offset: usize) {
+        self[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_u32_le(&mut self, value: u32, offset: usize) {
+        self[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+// Assembles values from memory that can only be read a byte at a time, like the bus or the
+// cartridge, with the byte at `offset` as the least significant.
+pub fn read_u16_le_with(offset: u32, mut read_byte: impl FnMut(u32) -> u8) -> u16 {
+    [read_byte(offset), read_byte(offset + 1)].read_u16_le(0)
+}
+
+pub fn read_u32_le_with(offset: u32, mut read_byte: impl FnMut(u32) -> u8) -> u32 {
+    [
+        read_byte(offset),
+        read_byte(offset + 1),
+        read_byte(offset + 2),
+        read_byte(offset + 3),
+    ]
+    .read_u32_le(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut memory = [0u8; 8];
+
+        // Unaligned offsets included, nothing here depends on alignment.
+        for offset in 0..=4 {
+            memory.fill(0);
+            memory.write_u32_le(0x12345678, offset);
+            assert_eq!(memory[offset..offset + 4], [0x78, 0x56, 0x34, 0x12]);
+            assert_eq!(memory.read_u32_le(offset), 0x12345678);
+            assert_eq!(memory.read_u16_le(offset), 0x5678);
+            assert_eq!(memory.read_u16_le(offset + 2), 0x1234);
+        }
+
+        for offset in 0..=6 {
+            memory.fill(0);
+            memory.write_u16_le(0xABCD, offset);
+            assert_eq!(memory[offset..offset + 2], [0xCD, 0xAB]);
+            assert_eq!(memory.read_u16_le(offset), 0xABCD);
+        }
+
+        for value in [0, 1, 0x80, 0xFF00, 0x00FF_FF00, 0x8000_0001, u32::MAX] {
+            memory.write_u32_le(value, 3);
+            assert_eq!(memory.read_u32_le(3), value);
+            assert_eq!(read_u32_le_with(3, |offset| memory[offset as usize]), value);
+
+            memory.write_u16_le(value as u16, 5);
+            assert_eq!(memory.read_u16_le(5), value as u16);
+            assert_eq!(
+                read_u16_le_with(5, |offset| memory[offset as usize]),
+                value as u16
+            );
+        }
+    }
+}
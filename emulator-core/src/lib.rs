@@ -1,39 +1,110 @@
+// Memory is only ever accessed through safe, explicitly little-endian helpers (see le_bytes).
+#![forbid(unsafe_code)]
+
 mod apu;
 mod bit_manipulation;
 mod bus;
 mod cartridge;
+mod clock;
 mod cpu;
 mod data_access;
+mod debug_snapshot;
+mod error;
+mod input;
 mod keypad;
 mod lcd;
+mod le_bytes;
+mod memory_search;
+mod multi_system;
+mod netplay;
+mod save_state;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod serial;
+mod state_diff;
+mod symbols;
+pub mod test_support;
 mod timer;
 
 use bit_manipulation::BitManipulation;
 use data_access::DataAccess;
-
-pub use bus::Bus;
-pub use cartridge::Cartridge;
+use le_bytes::LittleEndianBytes;
+
+pub use apu::AudioRingBuffer;
+pub use bus::{
+    AccessHit, AccessKind, AccessWatch, Bus, BusProfiler, CoverageKind, CoverageRecorder,
+    CoverageSummary, DebugOutputCallback, DebugOutputLevel, DmaDebugInfo, DmaLog, DmaStartTiming,
+    DmaStats, DmaTransfer, InterruptEvent, IoRegisterField, IoRegisterInfo, IoTrace, IoTraceEntry,
+    MemoryRegion, PerfCounters, PhiTerminalOutput, PowerState, RegionAccessCounts, RomWaitstates,
+    Waitstates,
+};
+#[cfg(feature = "flat-memory")]
+pub use bus::{FlatMemory, MemoryAccess};
+pub use cartridge::{
+    BackupFileFormat, BackupType, CardFiles, CardSource, CardSourceHandle, Cartridge,
+    CartridgeHeader, GameOverrides, RumbleCallback,
+};
+pub use clock::{EmulationClock, TimingMode};
+pub use cpu::bios_function_name;
+pub use cpu::BootMode;
 pub use cpu::Cpu;
+pub use cpu::CpuConfig;
 pub use cpu::CpuMode;
+pub use cpu::Frame;
+pub use cpu::FrameKind;
 pub use cpu::Instruction;
 pub use cpu::InstructionSet;
 pub use cpu::Register;
-pub use keypad::Key;
-pub use lcd::{Lcd, Rgb555};
+pub use cpu::StepEvent;
+pub use cpu::SwiAction;
+pub use cpu::SwiCall;
+pub use cpu::SwiCount;
+pub use cpu::SwiHook;
+pub use cpu::SwiStats;
+pub use debug_snapshot::{DebugSnapshot, SharedDebugSnapshot, TimerSnapshot};
+pub use error::{EmulatorError, ErrorPolicy};
+pub use input::{Autofire, AutofireConfig, InputTiming};
+pub use keypad::{Key, KeysState};
+pub use lcd::{
+    BackgroundMap, BackgroundViewport, ColorCorrection, FrameBuffer, FrameCallback, Lcd, Rgb555,
+};
+pub use memory_search::{MemorySearch, SearchCandidate, SearchFilter, SearchWidth};
+pub use multi_system::MultiSystem;
+pub use netplay::Lockstep;
+pub use save_state::{ChunkTag, SaveState, Thumbnail};
+#[cfg(feature = "scripting")]
+pub use scripting::Script;
+pub use serial::{LinkMessage, LinkTransport, LinkTransportHandle};
+pub use state_diff::StateDiff;
+pub use symbols::{Symbol, SymbolTable};
 
 pub const CYCLES_PER_SECOND: u64 = 16_777_216;
+// 228 lines of 1232 cycles each, about 16.743 ms.
+pub const CYCLES_PER_FRAME: u64 = 280_896;
 
 pub fn calculate_lcd_checksum(cpu: &Cpu) -> u64 {
+    cpu.bus.lcd.buffer_checksum()
+}
+
+// Covers the registers, work RAM and the current frame, which is enough to notice two emulators
+// running the same game drifting apart without hashing a whole save state.
+pub fn calculate_state_hash(cpu: &Cpu) -> u64 {
     use std::hash::Hasher;
     use xxhash_rust::xxh3::Xxh3;
 
     let mut hasher = Xxh3::default();
+    hasher.write_u64(cpu.bus.cycle_count());
 
-    for pixel in cpu.bus.lcd.get_buffer().iter().flatten() {
-        hasher.write_u8(pixel.red());
-        hasher.write_u8(pixel.green());
-        hasher.write_u8(pixel.blue());
+    for index in 0..16 {
+        hasher.write_u32(cpu.read_register(Register::from_index(index), |pc| pc));
     }
+    hasher.write_u32(cpu.read_register(Register::Cpsr, |pc| pc));
+
+    for address in StateDiff::WRAM_RANGES.into_iter().flatten().step_by(4) {
+        hasher.write_u32(cpu.bus.read_word_address_debug(address));
+    }
+
+    hasher.write_u64(calculate_lcd_checksum(cpu));
 
     hasher.finish()
 }
@@ -41,40 +112,19 @@ pub fn calculate_lcd_checksum(cpu: &Cpu) -> u64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_support::{run_rom, run_rom_checkpoints, ScriptedInput};
 
-    fn assert_checksum(cpu: &Cpu, checksum: u64) {
-        assert_eq!(checksum, calculate_lcd_checksum(cpu));
-    }
-
-    fn press_key(cpu: &mut Cpu, key: Key) {
-        // Tune this if this isn't long enough for key presses to register.
-        //
-        // This is currently about 100ms of cpu time (1/10 of a second).
-        const KEY_PRESS_DELAY: usize = (CYCLES_PER_SECOND / 10) as usize;
-
-        cpu.bus.keypad.set_pressed(key, true);
-        for _ in 0..KEY_PRESS_DELAY {
-            cpu.fetch_decode_execute();
-        }
-        cpu.bus.keypad.set_pressed(key, false);
-        for _ in 0..KEY_PRESS_DELAY {
-            cpu.fetch_decode_execute();
-        }
-    }
+    // Long enough to get past any boot screen.
+    const BOOT_CYCLES: u64 = 100_000_000;
 
     macro_rules! simple_ppu_test {
         ($name:ident, $path:literal, $checksum:literal) => {
             #[test]
             fn $name() {
                 let source = include_bytes!($path);
-                let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
-                let mut cpu = Cpu::new(cartridge);
-
-                while cpu.bus.cycle_count() < 125_000_000 {
-                    cpu.fetch_decode_execute();
-                }
+                let checksum = run_rom(source, &ScriptedInput::new(), 125_000_000);
 
-                assert_checksum(&cpu, $checksum);
+                assert_eq!(checksum, $checksum);
             }
         };
     }
@@ -177,34 +227,24 @@ mod tests {
         const ARM_LOAD_TESTS_PART_2: u64 = 0x7569D8F3583A88BD;
         const ARM_LDM_STM_TESTS_1: u64 = 0x2F4688257C51FD03;
 
-        let source = include_bytes!("../tests/armwrestler.gba");
-        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
-        let mut cpu = Cpu::new(cartridge);
-
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
-            cpu.fetch_decode_execute();
+        let mut input = ScriptedInput::new().checkpoint();
+        for _ in 0..6 {
+            input = input.press(Key::Start).checkpoint();
         }
 
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
-
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, ARM_ALU_PART_1);
-
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, ARM_ALU_PART_2);
-
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, ARM_LOAD_TESTS_PART_1);
-
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, ARM_LOAD_TESTS_PART_2);
-
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, ARM_LDM_STM_TESTS_1);
-
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
+        let source = include_bytes!("../tests/armwrestler.gba");
+        assert_eq!(
+            run_rom_checkpoints(source, &input, BOOT_CYCLES),
+            [
+                INITIAL_CHECKSUM,
+                ARM_ALU_PART_1,
+                ARM_ALU_PART_2,
+                ARM_LOAD_TESTS_PART_1,
+                ARM_LOAD_TESTS_PART_2,
+                ARM_LDM_STM_TESTS_1,
+                INITIAL_CHECKSUM,
+            ]
+        );
     }
 
     #[test]
@@ -217,282 +257,2831 @@ mod tests {
         const THUMB_LDR_STR_TEST: u64 = 0xF4F5CBE6217EF9F0;
         const THUMB_LDM_STM_TEST: u64 = 0xDED0DBE7F075848E;
 
+        // Scroll down to the Thumb tests, run each of them and scroll back up.
+        let mut input = ScriptedInput::new()
+            .checkpoint()
+            .press_repeated(Key::Down, 3)
+            .checkpoint();
+        for _ in 0..4 {
+            input = input.press(Key::Start).checkpoint();
+        }
+        input = input.press_repeated(Key::Up, 3).checkpoint();
+
         let source = include_bytes!("../tests/armwrestler.gba");
-        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
-        let mut cpu = Cpu::new(cartridge);
+        assert_eq!(
+            run_rom_checkpoints(source, &input, BOOT_CYCLES),
+            [
+                INITIAL_CHECKSUM,
+                THUMB_TESTS_SELECTED_CHECKSUM,
+                THUMB_ALU_TEST,
+                THUMB_LDR_STR_TEST,
+                THUMB_LDM_STM_TEST,
+                THUMB_TESTS_SELECTED_CHECKSUM,
+                INITIAL_CHECKSUM,
+            ]
+        );
+    }
 
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
-            cpu.fetch_decode_execute();
+    // Each of suite.gba's tests is selected by moving down its menu, then run with A. The result
+    // screens are checked after `extra_cycles`, for the tests that take a while to run. Those
+    // also print every result through the mGBA debug registers, which slows them down further.
+    macro_rules! suite_test {
+        ($name:ident, $menu_index:literal, $selected:literal, $success:literal) => {
+            suite_test!($name, $menu_index, $selected, $success, 0);
+        };
+        ($name:ident, $menu_index:literal, $selected:literal, $success:literal, $extra_cycles:expr) => {
+            #[test]
+            fn $name() {
+                const INITIAL_CHECKSUM: u64 = 0x3B32CCEB3BAE455B;
+
+                let input = ScriptedInput::new()
+                    .checkpoint()
+                    .press_repeated(Key::Down, $menu_index)
+                    .checkpoint()
+                    .press(Key::A)
+                    .wait_cycles($extra_cycles)
+                    .checkpoint();
+
+                let source = include_bytes!("../tests/suite.gba");
+                assert_eq!(
+                    run_rom_checkpoints(source, &input, BOOT_CYCLES),
+                    [INITIAL_CHECKSUM, $selected, $success]
+                );
+            }
+        };
+    }
+
+    suite_test!(
+        suite_memory,
+        0,
+        0x3B32CCEB3BAE455B,
+        0x7849B12FEBF63283,
+        2 * CYCLES_PER_SECOND
+    );
+    suite_test!(suite_timer_irq, 4, 0x0ACF818559806EA9, 0xE50DD1D11F9F8C0F);
+    suite_test!(suite_shifter, 5, 0x44BFA86E38A2027E, 0xF82D049DDEF321AC);
+    suite_test!(suite_carry, 6, 0x584DECF1B2656938, 0x89F7F1CFD8DC70E3);
+    suite_test!(suite_bios_math, 8, 0x2950FA409FCAF1D2, 0x43AD9E744E911293);
+    suite_test!(
+        suite_dma,
+        9,
+        0xB5E03F00EB8D896A,
+        0x0B05ACFFFB452786,
+        4 * CYCLES_PER_SECOND
+    );
+
+    #[test]
+    fn openbuster() {
+        const SCREEN_CHECKSUMS: &[u64] = &[
+            0x3B65E15802C43DC1, // IWRAM - LDRB
+            0xD04B19A7B4641F90, // IWRAM - STRB
+            0x95BBB22BA233B656, // IWRAM - LDRH
+            0x2D336CF7F9C81FA7, // EWRAM - LDRB/LDRH (0)
+            0x6DCC7C311AFC714B, // EWRAM - LDRB/LDRH (1)
+            0x43C0582E38FE5868, // MMIO - LDRB/LDRH (0)
+            0x52060C8F3830A037, // MMIO - LDRB/LDRH (1)
+            0x15F16BEADE6D951F, // VRAM - LDRB/LDRH (0)
+            0xF10287B271137E86, // VRAM - LDRB/LDRH (1)
+        ];
+        const ALL_PASSED_CHECKSUM: u64 = 0x444CF2773FFA0FBA; // passed: 144 total: 144
+
+        let mut input = ScriptedInput::new();
+        for _ in SCREEN_CHECKSUMS {
+            input = input.checkpoint().press(Key::A);
         }
+        input = input.checkpoint();
+
+        let source = include_bytes!("../tests/openbuster.gba");
+        let mut expected = SCREEN_CHECKSUMS.to_vec();
+        expected.push(ALL_PASSED_CHECKSUM);
+        assert_eq!(run_rom_checkpoints(source, &input, BOOT_CYCLES), expected);
+    }
 
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
+    #[test]
+    fn bios_open_bus() {
+        const PASS_CHECKSUM: u64 = 0xC01DFDB8318FFCE5;
 
-        // Scroll down to Thumb tests.
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        assert_checksum(&cpu, THUMB_TESTS_SELECTED_CHECKSUM);
+        let source = include_bytes!("../tests/bios_open_bus.gba");
+        assert_eq!(
+            run_rom(source, &ScriptedInput::new(), BOOT_CYCLES),
+            PASS_CHECKSUM
+        );
+    }
+
+    #[test]
+    fn io_open_bus() {
+        let source = include_bytes!("../tests/openbuster.gba");
+        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
+        let mut cpu = Cpu::new(cartridge);
+        cpu.bus.open_bus_data = 0xDEADBEEF;
+
+        // Write-only IO registers return open bus.
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x04000010), 0xBEEF); // BG0HOFS
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x04000012), 0xDEAD); // BG0VOFS
+        assert_eq!(cpu.bus.read_byte_address_debug(0x04000013), 0xDE);
+
+        // Unused halves of readable registers and DMA word counts read as zero instead.
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x04000066), 0); // SOUND1CNT_X high
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x0400007E), 0); // SOUND4CNT_H high
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x040000B8), 0); // DMA0CNT_L
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x040000DC), 0); // DMA3CNT_L
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x0400020A), 0); // IME high
+
+        // The unused region between the BIOS and EWRAM returns open bus.
+        assert_eq!(cpu.bus.read_word_address_debug(0x00004000), 0xDEADBEEF);
+        assert_eq!(cpu.bus.read_word_address_debug(0x01000000), 0xDEADBEEF);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x01FFFFFF), 0xDE);
+    }
 
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, THUMB_ALU_TEST);
+    #[test]
+    fn dma_startup_timing() {
+        // Cycles an immediate DMA3 of `units` halfwords takes, according to the channel's stats,
+        // and how long the CPU was stalled for including the cycle that started it.
+        let dma_cycles = |source: u32, dest: u32, units: u16| {
+            let mut cpu = build_thumb_test_cpu(&[], &[]);
+            cpu.bus.write_word_address_debug(source, 0x040000D4);
+            cpu.bus.write_word_address_debug(dest, 0x040000D8);
+            cpu.bus.write_halfword_address_debug(units, 0x040000DC);
+            cpu.bus.write_halfword_address_debug(0x8000, 0x040000DE);
+
+            let start_cycle = cpu.bus.cycle_count();
+            cpu.bus.step();
+            let stats = cpu.bus.dma_stats()[3];
+            assert_eq!(stats.units_transferred, u64::from(units));
+            (stats.cycles_consumed, cpu.bus.cycle_count() - start_cycle)
+        };
 
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, THUMB_LDR_STR_TEST);
+        // 2 internal cycles to start up, then a non-sequential read and write followed by
+        // sequential ones. IWRAM takes a single cycle for any access.
+        assert_eq!(dma_cycles(0x03000000, 0x03000100, 1), (2 + 2, 1 + 2 + 2));
+        assert_eq!(
+            dma_cycles(0x03000000, 0x03000100, 8),
+            (2 + (8 * 2), 1 + 2 + (8 * 2))
+        );
+
+        // WS0 reads take 4 waitstates for non-sequential accesses and 2 for sequential ones.
+        assert_eq!(
+            dma_cycles(0x08000000, 0x03000100, 8).0,
+            2 + (5 + 1) + (7 * (3 + 1))
+        );
+
+        // Between two gamepak regions startup takes 4 cycles instead. WS1 writes take 4
+        // waitstates either way.
+        assert_eq!(
+            dma_cycles(0x08000000, 0x0A000100, 8).0,
+            4 + (5 + 5) + (7 * (5 + 3))
+        );
+
+        // HBlank DMAs run on the same cycle the LCD enters hblank, and not before.
+        const DISPSTAT: u32 = 0x04000004;
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        cpu.bus.write_halfword_address_debug(0x1234, 0x03000000);
+        cpu.bus.write_word_address_debug(0x03000000, 0x040000D4);
+        cpu.bus.write_word_address_debug(0x03000100, 0x040000D8);
+        cpu.bus.write_halfword_address_debug(1, 0x040000DC);
+        cpu.bus.write_halfword_address_debug(0xA000, 0x040000DE);
+        loop {
+            let in_hblank = cpu.bus.read_halfword_address_debug(DISPSTAT).get_bit(1);
+            cpu.bus.step();
+            let entered_hblank =
+                !in_hblank && cpu.bus.read_halfword_address_debug(DISPSTAT).get_bit(1);
+
+            let transfers = cpu.bus.dma_stats()[3].transfers_completed;
+            if entered_hblank {
+                assert_eq!(transfers, 1);
+                break;
+            }
+            assert_eq!(transfers, 0);
+        }
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x03000100), 0x1234);
+    }
 
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, THUMB_LDM_STM_TEST);
+    #[test]
+    fn dma3_video_capture() {
+        const VCOUNT: u32 = 0x04000006;
+        const DMA3CNT_H: u32 = 0x040000DE;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        for line in 0..228 {
+            cpu.bus
+                .write_halfword_address_debug(line, 0x02000000 + u32::from(line * 2));
+        }
+        while cpu.bus.read_halfword_address_debug(VCOUNT) != 0 {
+            cpu.bus.step();
+        }
 
-        press_key(&mut cpu, Key::Start);
-        assert_checksum(&cpu, THUMB_TESTS_SELECTED_CHECKSUM);
+        // Repeating DMA3 of a single halfword at a time, with special start timing.
+        cpu.bus.write_word_address_debug(0x02000000, 0x040000D4);
+        cpu.bus.write_word_address_debug(0x03000000, 0x040000D8);
+        cpu.bus.write_halfword_address_debug(1, 0x040000DC);
+        cpu.bus.write_halfword_address_debug(0xB200, DMA3CNT_H);
+
+        // Each transfer happens on the first dot of its line, for lines 2 through 161.
+        let mut transfer_lines = Vec::new();
+        loop {
+            let line = cpu.bus.read_halfword_address_debug(VCOUNT);
+            let dot = cpu.bus.lcd.current_scanline_dot();
+            let transfers = cpu.bus.dma_stats()[3].transfers_completed;
+            cpu.bus.step();
+
+            let next_line = cpu.bus.read_halfword_address_debug(VCOUNT);
+            if cpu.bus.dma_stats()[3].transfers_completed != transfers {
+                assert_eq!(dot, 0);
+                transfer_lines.push(line);
+            }
+            if line == 227 && next_line == 0 {
+                break;
+            }
+        }
+        assert_eq!(transfer_lines, (2..162).collect::<Vec<_>>());
 
-        // Scroll back up to initial state.
-        press_key(&mut cpu, Key::Up);
-        press_key(&mut cpu, Key::Up);
-        press_key(&mut cpu, Key::Up);
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
+        // Which disables the channel once line 162 starts, despite it repeating.
+        assert!(!cpu.bus.read_halfword_address_debug(DMA3CNT_H).get_bit(15));
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x03000000 + 318), 159);
     }
 
     #[test]
-    fn suite_memory() {
-        const INITIAL_CHECKSUM: u64 = 0x3B32CCEB3BAE455B;
-        const MEMORY_TEST_SELECTED_CHECKSUM: u64 = 0x3B32CCEB3BAE455B;
-        const MEMORY_SUCCESS_SCREEN_CHECKSUM: u64 = 0x7849B12FEBF63283;
+    fn dma_open_bus_latch() {
+        const WORDS: u16 = 0x8400;
+        const HALFWORDS: u16 = 0x8000;
+
+        // Immediate DMAs of `count` units, with `control` picking their size.
+        let run_dma = |cpu: &mut Cpu, channel: u32, source: u32, dest: u32, count, control| {
+            let base = 0x040000B0 + (channel * 12);
+            cpu.bus.write_word_address_debug(source, base);
+            cpu.bus.write_word_address_debug(dest, base + 4);
+            cpu.bus.write_halfword_address_debug(count, base + 8);
+            cpu.bus.write_halfword_address_debug(control, base + 10);
+            cpu.bus.step();
+        };
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        cpu.bus.write_word_address_debug(0x11223344, 0x02000000);
+        cpu.bus.write_halfword_address_debug(0xABCD, 0x02000010);
+
+        // Sources below EWRAM, including the BIOS, replay the last value the channel read.
+        run_dma(&mut cpu, 3, 0x02000000, 0x03000000, 1, WORDS);
+        run_dma(&mut cpu, 3, 0x00004000, 0x03000010, 2, WORDS);
+        assert_eq!(cpu.bus.read_word_address_debug(0x03000010), 0x11223344);
+        assert_eq!(cpu.bus.read_word_address_debug(0x03000014), 0x11223344);
+
+        // Every channel has a latch of its own, and DMA2 hasn't read anything yet.
+        cpu.bus.write_word_address_debug(0xFFFFFFFF, 0x03000020);
+        run_dma(&mut cpu, 2, 0x01000000, 0x03000020, 1, WORDS);
+        assert_eq!(cpu.bus.read_word_address_debug(0x03000020), 0);
+
+        // Halfwords are latched on both halves of the bus, and so are replayed by word transfers
+        // from past the end of the game pak.
+        run_dma(&mut cpu, 3, 0x02000010, 0x03000030, 1, HALFWORDS);
+        run_dma(&mut cpu, 3, 0x10000000, 0x03000040, 1, WORDS);
+        assert_eq!(cpu.bus.read_word_address_debug(0x03000040), 0xABCDABCD);
+
+        // IO registers which read as zero are actually read, so they replace the latch.
+        run_dma(&mut cpu, 3, 0x04000066, 0x03000050, 1, HALFWORDS); // SOUND1CNT_X high
+        cpu.bus.write_word_address_debug(0xFFFFFFFF, 0x03000060);
+        run_dma(&mut cpu, 3, 0x00004000, 0x03000060, 1, WORDS);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x03000050), 0);
+        assert_eq!(cpu.bus.read_word_address_debug(0x03000060), 0);
+    }
 
+    #[test]
+    fn sram_access_width() {
         let source = include_bytes!("../tests/suite.gba");
         let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
         let mut cpu = Cpu::new(cartridge);
 
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
-            cpu.fetch_decode_execute();
+        // Only the byte lining up with the address makes it through the 8-bit bus.
+        cpu.bus.write_word_address_debug(0x44332211, 0x0E000000);
+        cpu.bus.write_word_address_debug(0x44332211, 0x0E000001);
+        cpu.bus.write_halfword_address_debug(0x6655, 0x0E000002);
+        cpu.bus.write_halfword_address_debug(0x8877, 0x0E000003);
+
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000000), 0x11);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000001), 0x22);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000002), 0x55);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000003), 0x88);
+
+        // Wider reads see the addressed byte repeated across the whole bus.
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x0E000001), 0x2222);
+        assert_eq!(cpu.bus.read_word_address_debug(0x0E000003), 0x88888888);
+
+        // Immediate DMA3s of `count` units, with `control` picking their size.
+        let run_dma = |cpu: &mut Cpu, source: u32, dest: u32, count, control| {
+            cpu.bus.write_word_address_debug(source, 0x040000D4);
+            cpu.bus.write_word_address_debug(dest, 0x040000D8);
+            cpu.bus.write_halfword_address_debug(count, 0x040000DC);
+            cpu.bus.write_halfword_address_debug(control, 0x040000DE);
+            cpu.bus.step();
+        };
+
+        // DMA goes through the same bus, and its addresses are always aligned, so only the lowest
+        // byte of each unit is stored. The rest of the unit's addresses are skipped over.
+        cpu.bus.write_word_address_debug(0x44332211, 0x02000000);
+        cpu.bus.write_word_address_debug(0x88776655, 0x02000004);
+        let untouched = cpu.bus.read_byte_address_debug(0x0E000011);
+        run_dma(&mut cpu, 0x02000000, 0x0E000010, 2, 0x8400);
+        run_dma(&mut cpu, 0x02000000, 0x0E000020, 2, 0x8000);
+
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000010), 0x11);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000011), untouched);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000014), 0x55);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000020), 0x11);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000022), 0x33);
+
+        // Reading from SRAM repeats each byte across the whole unit.
+        run_dma(&mut cpu, 0x0E000014, 0x02000100, 1, 0x8400);
+        run_dma(&mut cpu, 0x0E000022, 0x02000110, 1, 0x8000);
+        assert_eq!(cpu.bus.read_word_address_debug(0x02000100), 0x55555555);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x02000110), 0x3333);
+    }
+
+    // Builds a ROM that switches to Thumb state and starts executing `thumb_code` at 0x08000008,
+    // with `arm_code` placed at 0x08000100.
+    pub(crate) fn build_thumb_test_cpu(thumb_code: &[u16], arm_code: &[u32]) -> Cpu {
+        build_thumb_test_cpu_with_overrides(thumb_code, arm_code, GameOverrides::default())
+    }
+
+    // Builds a ROM that starts executing `arm_code` at 0x08000000.
+    pub(crate) fn build_arm_test_cpu(arm_code: &[u32]) -> Cpu {
+        let mut rom = build_test_rom(&[0; 4]);
+        for (i, opcode) in arm_code.iter().enumerate() {
+            rom[i * 4..i * 4 + 4].copy_from_slice(&opcode.to_le_bytes());
+        }
+
+        let cartridge = Cartridge::new(rom.as_slice(), None).unwrap();
+        Cpu::with_boot_mode(cartridge, BootMode::SkipBios)
+    }
+
+    pub(crate) fn build_thumb_test_cpu_with_overrides(
+        thumb_code: &[u16],
+        arm_code: &[u32],
+        overrides: GameOverrides,
+    ) -> Cpu {
+        const ARM_CODE_OFFSET: usize = 0x100;
+
+        let mut rom = build_test_rom(&[0; 4]);
+
+        // add r0, pc, #1
+        // bx r0
+        rom[0x00..0x04].copy_from_slice(&0xE28F0001u32.to_le_bytes());
+        rom[0x04..0x08].copy_from_slice(&0xE12FFF10u32.to_le_bytes());
+
+        for (i, opcode) in thumb_code.iter().enumerate() {
+            let offset = 0x08 + i * 2;
+            rom[offset..offset + 2].copy_from_slice(&opcode.to_le_bytes());
+        }
+
+        for (i, opcode) in arm_code.iter().enumerate() {
+            let offset = ARM_CODE_OFFSET + i * 4;
+            rom[offset..offset + 4].copy_from_slice(&opcode.to_le_bytes());
         }
 
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
+        let cartridge = Cartridge::with_overrides(rom.as_slice(), None, overrides).unwrap();
+        Cpu::with_boot_mode(cartridge, BootMode::SkipBios)
+    }
 
-        assert_checksum(&cpu, MEMORY_TEST_SELECTED_CHECKSUM);
+    // A blank ROM, with `game_code` in its header for the compatibility database and cartridge
+    // hardware detection to go by.
+    pub(crate) fn build_test_rom(game_code: &[u8; 4]) -> Vec<u8> {
+        let mut rom = vec![0; 0x200];
+        rom[0xAC..0xB0].copy_from_slice(game_code);
+        rom
+    }
 
-        press_key(&mut cpu, Key::A);
+    // Boots straight into a blank ROM, see `build_test_rom`.
+    pub(crate) fn build_game_test_cpu(game_code: &[u8; 4]) -> Cpu {
+        let cartridge = Cartridge::new(build_test_rom(game_code).as_slice(), None).unwrap();
+        Cpu::with_boot_mode(cartridge, BootMode::SkipBios)
+    }
 
-        let start_cycles = cpu.bus.cycle_count();
+    #[test]
+    fn idle_loop_skip() {
+        const LOOP_HEAD: u32 = 0x0800000C;
+        const LOOP_EXIT: u32 = 0x08000012;
+
+        let thumb_code = [
+            0x2004, // mov r0, #4
+            0x0600, // lsl r0, r0, #24
+            0x88C1, // loop: ldrh r1, [r0, #6] (VCOUNT)
+            0x29A0, // cmp r1, #160
+            0xD1FC, // bne loop
+            0xE7FE, // b #-4
+        ];
+
+        // Returns the cycle the loop was left on and the number of steps it took.
+        let run = |mut cpu: Cpu| {
+            let mut steps = 0;
+            while cpu.get_executing_pc() != LOOP_EXIT {
+                cpu.fetch_decode_execute();
+                steps += 1;
+            }
+
+            assert_eq!(cpu.read_register(Register::R1, |pc| pc), 160);
+            (cpu.bus.cycle_count(), steps, cpu.perf_counters())
+        };
+
+        let (exact_cycles, exact_steps, counters) = run(build_thumb_test_cpu(&thumb_code, &[]));
+        assert_eq!(counters.idle_loop_cycles_skipped, 0);
+
+        let mut cpu = build_thumb_test_cpu(&thumb_code, &[]);
+        cpu.set_config(CpuConfig {
+            skip_idle_loops: true,
+            ..CpuConfig::default()
+        });
+        let (cycles, steps, counters) = run(cpu);
+        assert!(counters.idle_loop_cycles_skipped > 0);
+        assert!(steps < exact_steps / 4);
+        // At most a couple of iterations late, when VCOUNT changes just after being read.
+        assert!(cycles.abs_diff(exact_cycles) < 64);
+
+        // Loops in the compatibility database are only skipped with skipping turned on.
+        let overrides = GameOverrides {
+            idle_loop: Some(LOOP_HEAD),
+            ..GameOverrides::default()
+        };
+        let cpu = build_thumb_test_cpu_with_overrides(&thumb_code, &[], overrides);
+        let (_, _, counters) = run(cpu.clone());
+        assert_eq!(counters.idle_loop_cycles_skipped, 0);
+
+        let mut cpu = cpu;
+        cpu.set_config(CpuConfig {
+            skip_idle_loops: true,
+            ..CpuConfig::default()
+        });
+        let (_, steps, counters) = run(cpu);
+        assert!(counters.idle_loop_cycles_skipped > 0);
+        assert!(steps < exact_steps / 4);
+    }
+
+    #[test]
+    fn thumb_bl_interrupted_between_halves() {
+        const BL_PART_TWO_ADDRESS: u32 = 0x0800000A;
+        const FUNCTION_ADDRESS: u32 = 0x08000010;
+        const RETURN_ADDRESS: u32 = 0x0800000C;
+
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0xF000, // bl 0x08000010 (part 1)
+                0xF802, // bl 0x08000010 (part 2)
+                0xE7FC, // b 0x08000008
+                0x46C0, // nop
+                0x3501, // add r5, #1
+                0x4770, // bx lr
+            ],
+            &[
+                0xE3A03301, // mov r3, #0x04000000
+                0xE2833C02, // add r3, r3, #0x200
+                0xE3A020FF, // mov r2, #0xFF
+                0xE1C320B2, // strh r2, [r3, #2]
+                0xE12FFF1E, // bx lr
+            ],
+        );
+
+        // Point the BIOS IRQ handler at our ARM code, which only acknowledges the interrupt.
+        cpu.bus.write_word_address_debug(0x08000100, 0x03007FFC);
+
+        // Timer 0 interrupt every 1009 cycles. Being prime, this drifts relative to the loop so
+        // that the interrupt eventually lands between the two halves of the BL.
+        cpu.bus
+            .write_halfword_address_debug((0x10000 - 1009) as u16, 0x04000100);
+        cpu.bus.write_halfword_address_debug(0x00C0, 0x04000102);
+        cpu.bus.write_halfword_address_debug(1 << 3, 0x04000200);
+        cpu.bus.write_halfword_address_debug(1, 0x04000208);
+
+        let mut interrupted_between_halves = 0;
+        for _ in 0..100_000 {
+            let previous_pc = cpu.get_executing_pc();
+            let previous_mode = cpu.get_cpu_mode();
 
-        // Memory test takes a while, so wait an extra second for test to run.
-        while cpu.bus.cycle_count() - start_cycles < CYCLES_PER_SECOND {
             cpu.fetch_decode_execute();
+
+            if previous_mode != CpuMode::Irq
+                && cpu.get_cpu_mode() == CpuMode::Irq
+                && previous_pc == BL_PART_TWO_ADDRESS
+            {
+                interrupted_between_halves += 1;
+            }
+
+            if cpu.get_cpu_mode() == CpuMode::System && cpu.get_executing_pc() == FUNCTION_ADDRESS {
+                assert!(matches!(cpu.get_instruction_mode(), InstructionSet::Thumb));
+                assert_eq!(
+                    cpu.read_register(Register::R14, |_| unreachable!()),
+                    RETURN_ADDRESS | 1
+                );
+            }
         }
 
-        assert_checksum(&cpu, MEMORY_SUCCESS_SCREEN_CHECKSUM);
+        assert!(interrupted_between_halves > 0);
+        assert!(cpu.read_register(Register::R5, |_| unreachable!()) > 0);
     }
 
     #[test]
-    fn suite_timer_irq() {
-        const INITIAL_CHECKSUM: u64 = 0x3B32CCEB3BAE455B;
-        const TIMER_IRQ_TEST_SELECTED_CHECKSUM: u64 = 0x0ACF818559806EA9;
-        const TIMER_IRQ_SUCCESS_SCREEN_CHECKSUM: u64 = 0xE50DD1D11F9F8C0F;
+    fn interrupt_history() {
+        const LOOP_ADDRESS: u32 = 0x08000008;
+
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0xE7FE, // b 0x08000008
+            ],
+            &[
+                0xE3A03301, // mov r3, #0x04000000
+                0xE2833C02, // add r3, r3, #0x200
+                0xE3A020FF, // mov r2, #0xFF
+                0xE1C320B2, // strh r2, [r3, #2]
+                0xE12FFF1E, // bx lr
+            ],
+        );
+        cpu.bus.write_word_address_debug(0x08000100, 0x03007FFC);
+        assert!(cpu.bus.interrupt_history().is_empty());
+
+        // Timer 0 interrupt every 1009 cycles.
+        cpu.bus
+            .write_halfword_address_debug((0x10000 - 1009) as u16, 0x04000100);
+        cpu.bus.write_halfword_address_debug(0x00C0, 0x04000102);
+        cpu.bus.write_halfword_address_debug(1 << 3, 0x04000200);
+        cpu.bus.write_halfword_address_debug(1, 0x04000208);
+
+        for _ in 0..100_000 {
+            cpu.fetch_decode_execute();
+        }
 
-        let source = include_bytes!("../tests/suite.gba");
-        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
-        let mut cpu = Cpu::new(cartridge);
+        let history = cpu.bus.interrupt_history();
+        assert_eq!(history.len(), Bus::INTERRUPT_HISTORY_LENGTH);
+        assert!(cpu.perf_counters().irqs_taken > Bus::INTERRUPT_HISTORY_LENGTH as u64);
+        for event in history {
+            assert_eq!(event.source_names(), ["Timer0"]);
+            assert_eq!(event.interrupt_enable, 1 << 3);
+            assert!(event.interrupt_master_enable);
+            assert_eq!(event.pc, LOOP_ADDRESS);
+        }
+        assert!(history
+            .iter()
+            .zip(history.iter().skip(1))
+            .all(|(earlier, later)| later.cycle > earlier.cycle));
+    }
+
+    // Requests interrupts from the given timers, by having them overflow on every cycle for a
+    // little while.
+    fn request_timer_interrupts(cpu: &mut Cpu, timers: &[u32]) {
+        for &timer in timers {
+            let base = 0x04000100 + (timer * 4);
+            cpu.bus.write_halfword_address_debug(0xFFFF, base);
+            cpu.bus.write_halfword_address_debug(0x00C0, base + 2);
+        }
+        for _ in 0..16 {
+            cpu.bus.step();
+        }
+        for &timer in timers {
+            cpu.bus
+                .write_halfword_address_debug(0, 0x04000100 + (timer * 4) + 2);
+        }
+    }
 
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
+    #[test]
+    fn interrupt_acknowledge_and_master_enable() {
+        const IE: u32 = 0x04000200;
+        const IF: u32 = 0x04000202;
+        const IME: u32 = 0x04000208;
+        const TIMER_0: u16 = 1 << 3;
+        const TIMER_1: u16 = 1 << 4;
+
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0xE7FE, // b 0x08000008
+            ],
+            &[
+                0xE3A03301, // mov r3, #0x04000000
+                0xE2833C02, // add r3, r3, #0x200
+                0xE3A020FF, // mov r2, #0xFF
+                0xE1C320B2, // strh r2, [r3, #2]
+                0xE12FFF1E, // bx lr
+            ],
+        );
+        cpu.bus.write_word_address_debug(0x08000100, 0x03007FFC);
+
+        request_timer_interrupts(&mut cpu, &[0, 1]);
+        assert_eq!(cpu.bus.read_halfword_address_debug(IF), TIMER_0 | TIMER_1);
+
+        // Writing zeroes to IF leaves requests alone, only the bits written as ones are cleared.
+        cpu.bus.write_halfword_address_debug(0, IF);
+        assert_eq!(cpu.bus.read_halfword_address_debug(IF), TIMER_0 | TIMER_1);
+        cpu.bus.write_halfword_address_debug(TIMER_0, IF);
+        assert_eq!(cpu.bus.read_halfword_address_debug(IF), TIMER_1);
+        assert_eq!(cpu.bus.poll_acknowledged_interrupts(), TIMER_0);
+
+        // With IME clear, an interrupt that's both enabled and requested is never taken.
+        cpu.bus.write_halfword_address_debug(TIMER_1, IE);
+        cpu.bus.write_halfword_address_debug(0, IME);
+        for _ in 0..100 {
             cpu.fetch_decode_execute();
         }
+        assert_eq!(cpu.bus.pending_interrupts(), TIMER_1);
+        assert_eq!(cpu.perf_counters().irqs_taken, 0);
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::System);
+
+        // Setting it lets the interrupt through, which the handler then acknowledges.
+        cpu.bus.write_halfword_address_debug(1, IME);
+        for _ in 0..100 {
+            cpu.fetch_decode_execute();
+        }
+        assert_eq!(cpu.perf_counters().irqs_taken, 1);
+        assert_eq!(cpu.bus.read_halfword_address_debug(IF), 0);
+        assert_eq!(cpu.bus.poll_acknowledged_interrupts() & TIMER_1, TIMER_1);
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::System);
+    }
 
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
+    #[test]
+    fn halt_and_stop_wake_up() {
+        const IE: u32 = 0x04000200;
+        const IF: u32 = 0x04000202;
+        const HALTCNT: u32 = 0x04000301;
+        const KEYCNT: u32 = 0x04000132;
+        const TIMER_0: u16 = 1 << 3;
+        const KEYPAD: u16 = 1 << 12;
+
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0x3501, // add r5, #1
+                0xE7FD, // b 0x08000008
+            ],
+            &[],
+        );
+        let run = |cpu: &mut Cpu, steps| {
+            for _ in 0..steps {
+                cpu.fetch_decode_execute();
+            }
+        };
 
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
+        // Halting only waits for an interrupt that's both enabled and requested, even with IME
+        // clear. A request alone isn't enough.
+        cpu.bus.write_byte_address_debug(0, HALTCNT);
+        assert_eq!(cpu.bus.power_state(), PowerState::Halted);
+        request_timer_interrupts(&mut cpu, &[0]);
+        run(&mut cpu, 100);
+        assert_eq!(cpu.bus.power_state(), PowerState::Halted);
+        assert_eq!(cpu.read_register(Register::R5, |_| unreachable!()), 0);
+
+        cpu.bus.write_halfword_address_debug(TIMER_0, IE);
+        run(&mut cpu, 100);
+        assert_eq!(cpu.bus.power_state(), PowerState::Running);
+        assert_ne!(cpu.read_register(Register::R5, |_| unreachable!()), 0);
+
+        // Stopping freezes the timers, and only keypad, serial and game pak interrupts wake the
+        // CPU back up.
+        cpu.bus.write_halfword_address_debug(0xFFFF, IF);
+        cpu.bus.write_halfword_address_debug(TIMER_0 | KEYPAD, IE);
+        cpu.bus.write_halfword_address_debug(0xFFFF, 0x04000100);
+        cpu.bus.write_halfword_address_debug(0x00C0, 0x04000102);
+        cpu.bus.write_byte_address_debug(0x80, HALTCNT);
+        run(&mut cpu, 100);
+        assert_eq!(cpu.bus.power_state(), PowerState::Stopped);
+        assert_eq!(cpu.bus.read_halfword_address_debug(IF), 0);
+
+        // An interrupt whenever A is pressed.
+        cpu.bus.write_halfword_address_debug(0x4001, KEYCNT);
+        cpu.bus.keypad.set_pressed(Key::A, true);
+        run(&mut cpu, 100);
+        assert_eq!(cpu.bus.power_state(), PowerState::Running);
+        assert_eq!(cpu.bus.read_halfword_address_debug(IF) & KEYPAD, KEYPAD);
+    }
 
-        assert_checksum(&cpu, TIMER_IRQ_TEST_SELECTED_CHECKSUM);
+    #[test]
+    fn interrupt_on_cpsr_irq_enable() {
+        let mut cpu = build_arm_test_cpu(&[
+            0xE10F0000, // mrs r0, cpsr
+            0xE3800080, // orr r0, r0, #0x80
+            0xE121F000, // msr cpsr_c, r0
+            0xE3C00080, // bic r0, r0, #0x80
+            0xE121F000, // msr cpsr_c, r0
+            0xE2855001, // add r5, r5, #1
+            0xEAFFFFFE, // b 0x08000018
+        ]);
+
+        for _ in 0..3 {
+            cpu.fetch_decode_execute();
+        }
+        assert!(cpu.get_irq_disable());
 
-        press_key(&mut cpu, Key::A);
+        cpu.bus.write_halfword_address_debug(1 << 3, 0x04000200);
+        cpu.bus.write_halfword_address_debug(1, 0x04000208);
+        request_timer_interrupts(&mut cpu, &[0]);
 
-        assert_checksum(&cpu, TIMER_IRQ_SUCCESS_SCREEN_CHECKSUM);
+        // The interrupt is held off until the MSR clearing the I bit has executed, and is taken
+        // straight after it, before the next instruction.
+        for _ in 0..2 {
+            cpu.fetch_decode_execute();
+            assert_eq!(cpu.get_cpu_mode(), CpuMode::System);
+        }
+        assert!(!cpu.get_irq_disable());
+        let cpsr = cpu.read_register(Register::Cpsr, |_| unreachable!());
+
+        cpu.fetch_decode_execute();
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::Irq);
+        assert_eq!(cpu.read_register(Register::R5, |_| unreachable!()), 0);
+        // SUBS PC, LR, #4 returns to the instruction after the MSR.
+        assert_eq!(
+            cpu.read_register(Register::R14, |_| unreachable!()),
+            0x08000018
+        );
+        assert_eq!(cpu.read_register(Register::Spsr, |_| unreachable!()), cpsr);
     }
 
     #[test]
-    fn suite_shifter() {
-        const INITIAL_CHECKSUM: u64 = 0x3B32CCEB3BAE455B;
-        const SHIFTER_TEST_SELECTED_CHECKSUM: u64 = 0x44BFA86E38A2027E;
-        const SHIFTER_SUCCESS_SCREEN_CHECKSUM: u64 = 0xF82D049DDEF321AC;
+    fn thumb_blx_immediate() {
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0xF000, // blx 0x08000100 (part 1)
+                0xE87A, // blx 0x08000100 (part 2)
+            ],
+            &[
+                0xEAFFFFFE, // b 0x08000100
+            ],
+        );
+
+        for _ in 0..16 {
+            cpu.fetch_decode_execute();
+        }
 
-        let source = include_bytes!("../tests/suite.gba");
+        assert!(matches!(cpu.get_instruction_mode(), InstructionSet::Arm));
+        assert_eq!(cpu.get_executing_pc(), 0x08000100);
+        assert_eq!(
+            cpu.read_register(Register::R14, |_| unreachable!()),
+            0x0800000D
+        );
+    }
+
+    #[test]
+    fn mgba_debug_output() {
+        use std::sync::{Arc, Mutex};
+
+        let source = include_bytes!("../tests/gba_tests_arm.gba");
         let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
         let mut cpu = Cpu::new(cartridge);
 
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let callback_messages = Arc::clone(&messages);
+        cpu.set_debug_output_callback(move |level, message| {
+            callback_messages
+                .lock()
+                .unwrap()
+                .push((level, message.to_string()));
+        });
+
+        // Unmapped until enabled.
+        cpu.bus.open_bus_data = 0;
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x04FFF780), 0);
+        cpu.bus.write_halfword_address_debug(0xC0DE, 0x04FFF780);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x04FFF780), 0x1DEA);
+
+        for (i, byte) in b"hello".iter().enumerate() {
+            cpu.bus
+                .write_byte_address_debug(*byte, 0x04FFF600 + i as u32);
+        }
+        cpu.bus.write_halfword_address_debug(0x0103, 0x04FFF700);
+
+        cpu.bus
+            .write_word_address_debug(u32::from_le_bytes(*b"bye\0"), 0x04FFF600);
+        cpu.bus.write_halfword_address_debug(0x0101, 0x04FFF700);
+
+        assert_eq!(
+            *messages.lock().unwrap(),
+            [
+                (DebugOutputLevel::Info, "hello".to_string()),
+                (DebugOutputLevel::Error, "bye".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn agb_print_output() {
+        use std::sync::{Arc, Mutex};
+
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0xDFFA, // swi 0xFA
+                0xE7FE, // b 0x0800000A
+            ],
+            &[],
+        );
+
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let callback_messages = Arc::clone(&messages);
+        cpu.set_debug_output_callback(move |level, message| {
+            callback_messages
+                .lock()
+                .unwrap()
+                .push((level, message.to_string()));
+        });
+
+        // What AGBPrintInit and AGBPrint("hello\n") leave behind.
+        cpu.bus.write_halfword_address_debug(0x20, 0x09FE2FFE);
+        cpu.bus.write_halfword_address_debug(0xFD, 0x09FE20FA);
+        cpu.bus.write_halfword_address_debug(0, 0x09FE20FC);
+        cpu.bus.write_halfword_address_debug(6, 0x09FE20FE);
+        for (i, chars) in b"hello\n".chunks(2).enumerate() {
+            let value = u16::from_le_bytes([chars[0], chars[1]]);
+            cpu.bus
+                .write_halfword_address_debug(value, 0x08FD0000 + i as u32 * 2);
+        }
+
+        for _ in 0..8 {
             cpu.fetch_decode_execute();
         }
 
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
+        // The SWI is handled without ever entering the BIOS.
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::System);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x09FE20FC), 6);
+        assert_eq!(
+            *messages.lock().unwrap(),
+            [(DebugOutputLevel::Info, "hello".to_string())]
+        );
+    }
 
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
+    #[test]
+    fn cartridge_header() {
+        let source = include_bytes!("../tests/gba_tests_arm.gba");
+        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
 
-        assert_checksum(&cpu, SHIFTER_TEST_SELECTED_CHECKSUM);
+        assert_eq!(
+            cartridge.header(),
+            CartridgeHeader {
+                title: "GBA Tests".to_string(),
+                game_code: "1337".to_string(),
+                maker_code: "JS".to_string(),
+                version: 0,
+                header_checksum: 0x69,
+                header_checksum_valid: true,
+                logo_valid: true,
+            }
+        );
+
+        // Corrupting the header should be caught by both the checksum and logo validation.
+        let mut corrupted = source.to_vec();
+        corrupted[0x010] ^= 0xFF;
+        corrupted[0x0A0] ^= 0xFF;
+
+        let header = CartridgeHeader::parse(&corrupted);
+        assert!(!header.header_checksum_valid);
+        assert!(!header.logo_valid);
+
+        // Compressed ROMs are decompressed first, so the library can list them too.
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, source).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert_eq!(
+            CartridgeHeader::read(gzipped.as_slice()).unwrap(),
+            cartridge.header()
+        );
+        assert_eq!(
+            CartridgeHeader::read(source.as_slice()).unwrap(),
+            cartridge.header()
+        );
+        assert!(CartridgeHeader::read(&source[..0x40]).is_err());
+    }
 
-        press_key(&mut cpu, Key::A);
+    #[test]
+    fn waitstate_control_rom_copy_loop() {
+        const ITERATIONS: u64 = 100;
+
+        // Cycles taken by a halfword copy loop running from WS0, reading from `source`.
+        let copy_loop_cycles = |source: u32, waitstate_control: u16| {
+            let [source_low, source_high] = [source as u16, (source >> 16) as u16];
+            let mut cpu = build_thumb_test_cpu(
+                &[
+                    0x4902,      // ldr r1, [pc, #8]
+                    0x4A03,      // ldr r2, [pc, #12]
+                    0x880B,      // ldrh r3, [r1]
+                    0x8013,      // strh r3, [r2]
+                    0x3102,      // add r1, #2
+                    0xE7FB,      // b 0x0800000C
+                    source_low,  // .word source
+                    source_high, //
+                    0x0000,      // .word 0x03000000
+                    0x0300,      //
+                ],
+                &[],
+            );
+            cpu.bus
+                .write_halfword_address_debug(waitstate_control, 0x04000204);
+
+            // Run up to the start of the loop.
+            while cpu.get_executing_pc() != 0x0800000C {
+                cpu.fetch_decode_execute();
+            }
+
+            let start_cycle = cpu.bus.cycle_count();
+            for _ in 0..ITERATIONS * 4 {
+                cpu.fetch_decode_execute();
+            }
+            cpu.bus.cycle_count() - start_cycle
+        };
 
-        assert_checksum(&cpu, SHIFTER_SUCCESS_SCREEN_CHECKSUM);
+        // Instructions are fetched from WS0, so only the LDRH is affected by WS1 and WS2 timings.
+        // Every one of them is a non-sequential access.
+        assert_eq!(
+            copy_loop_cycles(0x0A000000, 0b11 << 5) - copy_loop_cycles(0x0A000000, 0b10 << 5),
+            ITERATIONS * (8 - 2)
+        );
+        assert_eq!(
+            copy_loop_cycles(0x0C000000, 0b00 << 8) - copy_loop_cycles(0x0C000000, 0b10 << 8),
+            ITERATIONS * (4 - 2)
+        );
+
+        // WS0 timings apply to instruction fetches as well, so just check they're not ignored.
+        assert!(copy_loop_cycles(0x08000000, 0b11 << 2) > copy_loop_cycles(0x08000000, 0b10 << 2));
+        assert!(copy_loop_cycles(0x08000000, 0) > copy_loop_cycles(0x08000000, 1 << 4));
     }
 
     #[test]
-    fn suite_carry() {
-        const INITIAL_CHECKSUM: u64 = 0x3B32CCEB3BAE455B;
-        const CARRY_TEST_SELECTED_CHECKSUM: u64 = 0x584DECF1B2656938;
-        const CARRY_SUCCESS_SCREEN_CHECKSUM: u64 = 0x89F7F1CFD8DC70E3;
+    fn waitstate_control_dma() {
+        const UNITS: u64 = 64;
+
+        // Cycles taken by an immediate DMA3 of 16-bit units from `source` to IWRAM.
+        let dma_cycles = |source: u32, waitstate_control: u16| {
+            let mut cpu = build_thumb_test_cpu(&[], &[]);
+            cpu.bus
+                .write_halfword_address_debug(waitstate_control, 0x04000204);
+            cpu.bus.write_word_address_debug(source, 0x040000D4);
+            cpu.bus.write_word_address_debug(0x03000000, 0x040000D8);
+            cpu.bus
+                .write_halfword_address_debug(UNITS as u16, 0x040000DC);
+            cpu.bus.write_halfword_address_debug(0x8000, 0x040000DE);
+
+            let start_cycle = cpu.bus.cycle_count();
+            cpu.bus.step();
+            cpu.bus.cycle_count() - start_cycle
+        };
 
-        let source = include_bytes!("../tests/suite.gba");
-        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
-        let mut cpu = Cpu::new(cartridge);
+        // The first unit is a non-sequential access, the rest are all sequential.
+        assert_eq!(
+            dma_cycles(0x08000000, 0b00 << 2) - dma_cycles(0x08000000, 0b10 << 2),
+            4 - 2
+        );
+        assert_eq!(
+            dma_cycles(0x0A000000, 0) - dma_cycles(0x0A000000, 1 << 7),
+            (UNITS - 1) * (4 - 1)
+        );
+        assert_eq!(
+            dma_cycles(0x0C000000, 0) - dma_cycles(0x0C000000, 1 << 10),
+            (UNITS - 1) * (8 - 1)
+        );
+    }
 
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
-            cpu.fetch_decode_execute();
+    #[test]
+    fn current_waitstates() {
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        // SRAM 8, WS0 3/1, WS1 2/4, WS2 8/8, 16.78MHz PHI output and prefetch enabled. The game
+        // pak type flag is read-only.
+        cpu.bus.write_halfword_address_debug(0xFB57, 0x04000204);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x04000204), 0x5B57);
+
+        assert_eq!(
+            cpu.bus.current_waitstates(),
+            Waitstates {
+                sram: 8,
+                rom: [
+                    RomWaitstates {
+                        first_access: 3,
+                        second_access: 1,
+                    },
+                    RomWaitstates {
+                        first_access: 2,
+                        second_access: 4,
+                    },
+                    RomWaitstates {
+                        first_access: 8,
+                        second_access: 8,
+                    },
+                ],
+                phi_terminal_output: PhiTerminalOutput::Mhz16_78,
+                prefetch_buffer_enable: true,
+            }
+        );
+    }
+
+    #[test]
+    fn host_audio_sync_timing() {
+        const FPS: u32 = 60;
+        const TARGET_SAMPLES: usize = 4096;
+
+        let mut clock = EmulationClock::default();
+        let strict_cycles = clock.cycles_per_frame(FPS);
+
+        // Strict timing ignores the host completely.
+        clock.sync_to_audio_buffer(0, TARGET_SAMPLES);
+        assert_eq!(clock.cycles_per_frame(FPS), strict_cycles);
+
+        clock.set_timing_mode(TimingMode::HostAudioSync);
+
+        clock.sync_to_audio_buffer(TARGET_SAMPLES, TARGET_SAMPLES);
+        assert_eq!(clock.cycles_per_frame(FPS), strict_cycles);
+
+        clock.sync_to_audio_buffer(0, TARGET_SAMPLES);
+        let draining_cycles = clock.cycles_per_frame(FPS);
+        assert!(draining_cycles > strict_cycles);
+
+        clock.sync_to_audio_buffer(TARGET_SAMPLES * 100, TARGET_SAMPLES);
+        let filling_cycles = clock.cycles_per_frame(FPS);
+        assert!(filling_cycles < strict_cycles);
+
+        // Never far enough off to be audible.
+        for cycles in [draining_cycles, filling_cycles] {
+            assert!(cycles.abs_diff(strict_cycles) <= strict_cycles / 100);
         }
 
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
+        // Switching back restores exact timing.
+        clock.set_timing_mode(TimingMode::Strict);
+        assert_eq!(clock.cycles_per_frame(FPS), strict_cycles);
+    }
 
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
+    #[test]
+    fn clock_host_duration() {
+        let mut clock = EmulationClock::default();
+        assert_eq!(
+            clock.host_duration(CYCLES_PER_SECOND),
+            Some(std::time::Duration::from_secs(1))
+        );
+
+        let frame = clock.host_duration(CYCLES_PER_FRAME).unwrap();
+        assert_eq!(frame.as_micros(), 16_742);
+
+        // Slowing down stretches the same emulated time over longer.
+        clock.set_speed_multiplier(0.5);
+        let slow_frame = clock.host_duration(CYCLES_PER_FRAME).unwrap();
+        assert_eq!(slow_frame.as_micros(), 33_485);
+
+        clock.set_uncapped(true);
+        assert_eq!(clock.host_duration(CYCLES_PER_FRAME), None);
+    }
 
-        assert_checksum(&cpu, CARRY_TEST_SELECTED_CHECKSUM);
+    #[test]
+    fn clock_speed_multiplier_clamped() {
+        let mut clock = EmulationClock::default();
+
+        clock.set_speed_multiplier(0.0);
+        assert_eq!(
+            clock.speed_multiplier(),
+            EmulationClock::MIN_SPEED_MULTIPLIER
+        );
+        clock.set_speed_multiplier(-2.0);
+        assert_eq!(
+            clock.speed_multiplier(),
+            EmulationClock::MIN_SPEED_MULTIPLIER
+        );
+
+        clock.set_speed_multiplier(f64::INFINITY);
+        assert_eq!(
+            clock.speed_multiplier(),
+            EmulationClock::MAX_SPEED_MULTIPLIER
+        );
+        assert!(clock.host_duration(CYCLES_PER_FRAME).is_some());
+
+        clock.set_speed_multiplier(2.0);
+        clock.set_speed_multiplier(f64::NAN);
+        assert_eq!(clock.speed_multiplier(), 2.0);
+    }
 
-        press_key(&mut cpu, Key::A);
+    // One end of an in-memory link cable.
+    struct ChannelLinkTransport {
+        parent: bool,
+        sender: std::sync::mpsc::Sender<LinkMessage>,
+        receiver: std::sync::mpsc::Receiver<LinkMessage>,
+    }
 
-        assert_checksum(&cpu, CARRY_SUCCESS_SCREEN_CHECKSUM);
+    impl LinkTransport for ChannelLinkTransport {
+        fn is_parent(&self) -> bool {
+            self.parent
+        }
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+
+        fn send(&mut self, message: LinkMessage) {
+            self.sender.send(message).unwrap();
+        }
+
+        fn poll(&mut self) -> Option<LinkMessage> {
+            self.receiver.try_recv().ok()
+        }
     }
 
     #[test]
-    fn suite_bios_math() {
-        const INITIAL_CHECKSUM: u64 = 0x3B32CCEB3BAE455B;
-        const BIOS_MATH_TEST_SELECTED_CHECKSUM: u64 = 0x2950FA409FCAF1D2;
-        const BIOS_MATH_SUCCESS_SCREEN_CHECKSUM: u64 = 0x43AD9E744E911293;
+    fn link_cable_multiplayer_transfer() {
+        use std::sync::{mpsc::channel, Arc, Mutex};
+
+        let (parent_sender, child_receiver) = channel();
+        let (child_sender, parent_receiver) = channel();
+
+        let mut parent = build_thumb_test_cpu(&[], &[]);
+        parent.set_link_transport(Some(Arc::new(Mutex::new(ChannelLinkTransport {
+            parent: true,
+            sender: parent_sender,
+            receiver: parent_receiver,
+        }))));
+
+        let mut child = build_thumb_test_cpu(&[], &[]);
+        child.set_link_transport(Some(Arc::new(Mutex::new(ChannelLinkTransport {
+            parent: false,
+            sender: child_sender,
+            receiver: child_receiver,
+        }))));
+
+        for (cpu, send_data) in [(&mut parent, 0x1234), (&mut child, 0xABCD)] {
+            // Multiplayer mode at 115200 baud, with the serial IRQ enabled.
+            cpu.bus.write_halfword_address_debug(0x6003, 0x04000128);
+            cpu.bus.write_halfword_address_debug(send_data, 0x0400012A);
+            cpu.bus.write_halfword_address_debug(1 << 7, 0x04000200);
+        }
 
-        let source = include_bytes!("../tests/suite.gba");
-        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
-        let mut cpu = Cpu::new(cartridge);
+        // Parent has ID 0, the child ID 1, and both are ready.
+        assert_eq!(
+            parent.bus.read_halfword_address_debug(0x04000128),
+            0x6003 | (1 << 3)
+        );
+        assert_eq!(
+            child.bus.read_halfword_address_debug(0x04000128),
+            0x6003 | (1 << 2) | (1 << 3) | (1 << 4)
+        );
+
+        parent.bus.write_halfword_address_debug(0x6083, 0x04000128);
+
+        let mut cycles = 0;
+        while parent
+            .bus
+            .read_halfword_address_debug(0x04000128)
+            .get_bit(7)
+        {
+            parent.bus.step();
+            child.bus.step();
+
+            cycles += 1;
+            assert!(cycles < CYCLES_PER_SECOND, "transfer never completed");
+        }
+
+        // Finish up any interrupt still passing through the IRQ synchronizer.
+        for _ in 0..Bus::IRQ_SYNC_BUFFER {
+            parent.bus.step();
+            child.bus.step();
+        }
+
+        for cpu in [&parent, &child] {
+            assert_eq!(cpu.bus.read_halfword_address_debug(0x04000120), 0x1234);
+            assert_eq!(cpu.bus.read_halfword_address_debug(0x04000122), 0xABCD);
+            assert_eq!(cpu.bus.read_halfword_address_debug(0x04000124), 0xFFFF);
+            assert_eq!(cpu.bus.read_halfword_address_debug(0x04000126), 0xFFFF);
+            assert_ne!(cpu.bus.pending_interrupts() & (1 << 7), 0);
+        }
+    }
+
+    #[test]
+    fn serial_normal_transfer_without_link() {
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        // 32-bit normal mode using the 256KHz internal clock, with nothing connected.
+        cpu.bus.write_word_address_debug(0x12345678, 0x04000120);
+        cpu.bus.write_halfword_address_debug(0x1081, 0x04000128);
+
+        let start_cycle = cpu.bus.cycle_count();
+        while cpu.bus.read_halfword_address_debug(0x04000128).get_bit(7) {
+            cpu.bus.step();
+        }
+
+        // 64 cycles per bit, plus the cycle on which the start bit is noticed.
+        assert_eq!(cpu.bus.cycle_count() - start_cycle, 32 * 64 + 1);
+        assert_eq!(cpu.bus.read_word_address_debug(0x04000120), 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn multi_system_transfer() {
+        let cpus = [0x1111, 0x2222, 0x3333]
+            .into_iter()
+            .map(|send_data| {
+                let mut cpu = build_thumb_test_cpu(
+                    &[
+                        0xE7FE, // b 0x08000008
+                    ],
+                    &[],
+                );
+
+                // Multiplayer mode at 115200 baud, with the serial IRQ enabled.
+                cpu.bus.write_halfword_address_debug(0x6003, 0x04000128);
+                cpu.bus.write_halfword_address_debug(send_data, 0x0400012A);
+                cpu.bus.write_halfword_address_debug(1 << 7, 0x04000200);
+                cpu
+            })
+            .collect();
+
+        let mut system = MultiSystem::new(cpus).unwrap();
+        assert_eq!(system.player_count(), 3);
+        assert_eq!(system.lcd_buffers().len(), 3);
+
+        for player_id in 0..3 {
+            let control = system
+                .cpu(player_id)
+                .bus
+                .read_halfword_address_debug(0x04000128);
+            assert_eq!(control.get_bit(2), player_id != 0);
+        }
 
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
+        system
+            .cpu_mut(0)
+            .bus
+            .write_halfword_address_debug(0x6083, 0x04000128);
+        system.run_cycles(CYCLES_PER_SECOND / 100);
+
+        for player_id in 0..3 {
+            let bus = &system.cpu(player_id).bus;
+            assert!(!bus.read_halfword_address_debug(0x04000128).get_bit(7));
+            assert_eq!(bus.read_word_address_debug(0x04000120), 0x22221111);
+            assert_eq!(bus.read_word_address_debug(0x04000124), 0xFFFF3333);
+            assert_ne!(bus.pending_interrupts() & (1 << 7), 0);
+        }
+
+        assert!(MultiSystem::new(system.into_cpus().into_iter().take(1).collect()).is_err());
+    }
+
+    #[test]
+    fn rom_out_of_bounds_reads() {
+        let cpu = build_game_test_cpu(b"TEST");
+
+        // Past the end of the ROM, the bus is left holding the lower address bits.
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x08000200), 0x0100);
+        assert_eq!(cpu.bus.read_word_address_debug(0x09000204), 0x01030102);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0A000201), 0x01);
+
+        // Classic NES Series games see their ROM mirrored instead.
+        let cpu = build_game_test_cpu(b"FSME");
+        let first_word = cpu.bus.read_word_address_debug(0x08000000);
+        assert_eq!(cpu.bus.read_word_address_debug(0x08000200), first_word);
+        assert_eq!(cpu.bus.read_word_address_debug(0x09FFFE00), first_word);
+        assert_eq!(
+            cpu.bus.read_halfword_address_debug(0x080002AC),
+            u16::from_le_bytes(*b"FS")
+        );
+    }
+
+    #[test]
+    fn unimplemented_instruction_error_policy() {
+        let build_cpu = || {
+            build_arm_test_cpu(&[
+                0xE1C020D0, // ldrd r2, [r0]
+                0xE0410392, // umaal r0, r1, r2, r3
+                0xEAFFFFFE, // b 0x08000008
+            ])
+        };
+
+        // By default errors are only logged, and the instructions skipped.
+        let mut cpu = build_cpu();
+        let r2 = cpu.read_register(Register::R2, |_| unreachable!());
+        assert_eq!(cpu.fetch_decode_execute(), None);
+        assert_eq!(cpu.fetch_decode_execute(), None);
+        assert_eq!(cpu.get_executing_pc(), 0x08000008);
+        assert_eq!(cpu.read_register(Register::R2, |_| unreachable!()), r2);
+
+        let mut cpu = build_cpu();
+        cpu.set_config(CpuConfig {
+            error_policy: ErrorPolicy::Trap,
+            ..CpuConfig::default()
+        });
+        assert_eq!(
+            cpu.fetch_decode_execute(),
+            Some(StepEvent::Error(EmulatorError::UnimplementedInstruction {
+                address: 0x08000000,
+                mnemonic: "ldrd",
+            }))
+        );
+        assert_eq!(
+            cpu.fetch_decode_execute(),
+            Some(StepEvent::Error(EmulatorError::UnimplementedInstruction {
+                address: 0x08000004,
+                mnemonic: "umaal",
+            }))
+        );
+        assert_eq!(cpu.fetch_decode_execute(), None);
+    }
+
+    #[test]
+    fn unimplemented_opcode_config() {
+        let build_cpu = |error_policy| {
+            let mut cpu = build_arm_test_cpu(&[
+                0xEC000000, // coprocessor transfer
+                0xEAFFFFFE, // b 0x08000004
+            ]);
+            cpu.set_config(CpuConfig {
+                error_policy,
+                ..CpuConfig::default()
+            });
+            cpu
+        };
+
+        let mut cpu = build_cpu(ErrorPolicy::Ignore);
+        assert_eq!(cpu.fetch_decode_execute(), None);
+        assert_eq!(cpu.get_executing_pc(), 0x08000004);
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::System);
+
+        let mut cpu = build_cpu(ErrorPolicy::LogAndContinue);
+        assert_eq!(cpu.fetch_decode_execute(), None);
+        assert_eq!(cpu.get_executing_pc(), 0x00000004);
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::Undefined);
+
+        let mut cpu = build_cpu(ErrorPolicy::Trap);
+        assert_eq!(
+            cpu.fetch_decode_execute(),
+            Some(StepEvent::InvalidOpcode {
+                address: 0x08000000,
+                opcode: 0xEC000000,
+            })
+        );
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::Undefined);
+    }
+
+    #[test]
+    fn undefined_exception_entry() {
+        let mut cpu = build_arm_test_cpu(&[
+            0xE3A00001, // mov r0, #1
+            0xE3500001, // cmp r0, #1
+            0xEC000000, // coprocessor transfer
+        ]);
+        cpu.set_config(CpuConfig {
+            error_policy: ErrorPolicy::LogAndContinue,
+            ..CpuConfig::default()
+        });
+
+        cpu.fetch_decode_execute();
+        cpu.fetch_decode_execute();
+        let cpsr = cpu.read_register(Register::Cpsr, |_| unreachable!());
+
+        cpu.fetch_decode_execute();
+        assert_eq!(cpu.get_executing_pc(), 0x00000004);
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::Undefined);
+        assert!(matches!(cpu.get_instruction_mode(), InstructionSet::Arm));
+        assert!(cpu.get_irq_disable());
+        // MOVS PC, LR returns to the instruction after the undefined one, with the flags set by
+        // the CMP restored.
+        assert_eq!(
+            cpu.read_register(Register::R14, |_| unreachable!()),
+            0x0800000C
+        );
+        assert_eq!(cpu.read_register(Register::Spsr, |_| unreachable!()), cpsr);
+        assert!(cpsr.get_bit(30));
+    }
+
+    #[test]
+    fn multiboot_image() {
+        let mut image = vec![0; 0x100];
+        image[0xC0..0xC4].copy_from_slice(&0xE3A00001u32.to_le_bytes()); // mov r0, #1
+        image[0xC4..0xC6].copy_from_slice(&[0xFF, 0xFF]); // filled in on boot
+        image[0xC8..0xCC].copy_from_slice(&0xEAFFFFFEu32.to_le_bytes()); // b .
+
+        let mut cpu = Cpu::with_multiboot_image(Cartridge::empty(), &image).unwrap();
+        assert_eq!(cpu.get_executing_pc(), 0x020000C0);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x020000C4), 2);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x020000C5), 1);
+
+        cpu.fetch_decode_execute();
+        assert_eq!(cpu.read_register(Register::R0, |pc| pc), 1);
+
+        assert!(Cpu::with_multiboot_image(Cartridge::empty(), &[0; 0x40]).is_err());
+        assert!(Cpu::with_multiboot_image(Cartridge::empty(), &vec![0; 0x40001]).is_err());
+    }
+
+    #[test]
+    fn cartridge_removal() {
+        const IF: u32 = 0x04000202;
+        const GAMEPAK_INTERRUPT_BIT_INDEX: usize = 13;
+
+        let rom = vec![0xAB; 0x200];
+        let cartridge = Cartridge::new(rom.as_slice(), None).unwrap();
+        let mut cpu = Cpu::with_boot_mode(cartridge, BootMode::SkipBios);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x08000100), 0xABAB);
+
+        cpu.bus.cartridge.set_present(false);
+        for _ in 0..16 {
+            cpu.bus.step();
+        }
+        assert!(cpu
+            .bus
+            .read_halfword_address_debug(IF)
+            .get_bit(GAMEPAK_INTERRUPT_BIT_INDEX));
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x08000100), 0x0080);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x0E000000), 0xFF);
+
+        cpu.bus.write_halfword_address_debug(0xFFFF, IF);
+        cpu.bus.cartridge.set_present(true);
+        for _ in 0..16 {
+            cpu.bus.step();
+        }
+        assert!(!cpu
+            .bus
+            .read_halfword_address_debug(IF)
+            .get_bit(GAMEPAK_INTERRUPT_BIT_INDEX));
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x08000100), 0xABAB);
+    }
+
+    #[test]
+    fn keypad_interrupt_conditions() {
+        const IF: u32 = 0x04000202;
+        const KEYCNT: u32 = 0x04000132;
+        const KEYPAD_INTERRUPT_BIT_INDEX: usize = 12;
+
+        // Whether holding down exactly `keys` requests a keypad interrupt.
+        let requests_interrupt = |cpu: &mut Cpu, keys: &[Key]| {
+            let mut state = KeysState::default();
+            for &key in keys {
+                state.set_pressed(key, true);
+            }
+            cpu.bus.keypad.set_state(state);
+
+            cpu.bus.write_halfword_address_debug(0xFFFF, IF);
+            for _ in 0..16 {
+                cpu.bus.step();
+            }
+            cpu.bus
+                .read_halfword_address_debug(IF)
+                .get_bit(KEYPAD_INTERRUPT_BIT_INDEX)
+        };
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        // Logical OR of A and B.
+        cpu.bus.write_halfword_address_debug(0x4003, KEYCNT);
+        assert!(!requests_interrupt(&mut cpu, &[]));
+        assert!(!requests_interrupt(&mut cpu, &[Key::Start]));
+        assert!(requests_interrupt(&mut cpu, &[Key::A]));
+        assert!(requests_interrupt(&mut cpu, &[Key::B, Key::Start]));
+        assert!(requests_interrupt(&mut cpu, &[Key::A, Key::B]));
+
+        // Logical AND of A and B, other keys don't matter either way.
+        cpu.bus.write_halfword_address_debug(0xC003, KEYCNT);
+        assert!(!requests_interrupt(&mut cpu, &[Key::A]));
+        assert!(!requests_interrupt(&mut cpu, &[Key::B, Key::Start]));
+        assert!(requests_interrupt(&mut cpu, &[Key::A, Key::B]));
+        assert!(requests_interrupt(&mut cpu, &[Key::A, Key::B, Key::Start]));
+
+        // Nothing at all without the interrupt enabled.
+        cpu.bus.write_halfword_address_debug(0x8003, KEYCNT);
+        assert!(!requests_interrupt(&mut cpu, &[Key::A, Key::B]));
+    }
+
+    #[test]
+    fn autofire() {
+        let mut turbo_keys = KeysState::default();
+        turbo_keys.set_pressed(Key::A, true);
+        let mut autofire = Autofire::new(AutofireConfig {
+            keys: turbo_keys,
+            period_frames: 2,
+        });
+
+        let mut held = KeysState::default();
+        held.set_pressed(Key::A, true);
+        held.set_pressed(Key::B, true);
+        let a_pressed = (0..6)
+            .map(|_| autofire.apply(held))
+            .inspect(|keys| assert!(keys.is_pressed(Key::B)))
+            .map(|keys| keys.is_pressed(Key::A))
+            .collect::<Vec<_>>();
+        assert_eq!(a_pressed, [true, true, false, false, true, true]);
+
+        // Pressing again starts the pattern over.
+        autofire.apply(held);
+        autofire.apply(KeysState::default());
+        assert!(autofire.apply(held).is_pressed(Key::A));
+    }
+
+    #[test]
+    fn queued_input() {
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        let mut a_pressed = KeysState::default();
+        a_pressed.set_pressed(Key::A, true);
+
+        cpu.bus.queue_input(InputTiming::Frame(1), a_pressed);
+        while cpu.bus.lcd.frame_count() == 0 {
+            assert!(!cpu.bus.keypad.get_state().is_pressed(Key::A));
+            cpu.bus.step();
+        }
+        assert!(cpu.bus.keypad.get_state().is_pressed(Key::A));
+        assert_eq!(cpu.bus.queued_input_len(), 0);
+
+        let release_cycle = cpu.bus.cycle_count() + 100;
+        cpu.bus
+            .queue_input(InputTiming::Cycle(release_cycle), KeysState::default());
+        while cpu.bus.cycle_count() <= release_cycle {
+            assert!(cpu.bus.keypad.get_state().is_pressed(Key::A));
+            cpu.bus.step();
+        }
+        assert!(!cpu.bus.keypad.get_state().is_pressed(Key::A));
+    }
+
+    #[test]
+    fn perf_counters() {
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0x46C0, // nop
+                0xE7FD, // b 0x08000008
+            ],
+            &[],
+        );
+
+        for _ in 0..10 {
             cpu.fetch_decode_execute();
         }
 
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
+        let counters = cpu.perf_counters();
+        assert_eq!(counters.arm_instructions, 2);
+        assert_eq!(counters.thumb_instructions, 8);
+        assert_eq!(counters.irqs_taken, 0);
+        assert_ne!(counters.sequential_accesses, 0);
+        assert_ne!(counters.non_sequential_accesses, 0);
+
+        cpu.reset_perf_counters();
+        assert_eq!(cpu.perf_counters(), PerfCounters::default());
+
+        // An immediate DMA3 of 4 words from EWRAM to IWRAM.
+        cpu.bus.write_word_address_debug(0x02000000, 0x040000D4);
+        cpu.bus.write_word_address_debug(0x03000000, 0x040000D8);
+        cpu.bus.write_halfword_address_debug(4, 0x040000DC);
+        cpu.bus.write_halfword_address_debug(0x8400, 0x040000DE);
+        cpu.bus.step();
+
+        let counters = cpu.perf_counters();
+        assert_eq!(counters.dma_units_transferred, 4);
+        assert_eq!(counters.non_sequential_accesses, 2);
+        assert_eq!(counters.sequential_accesses, 6);
+    }
+
+    #[test]
+    fn dma_log() {
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        // Immediate DMAs of 4 words from EWRAM to IWRAM, on DMA3 and then DMA0.
+        let start_dma = |cpu: &mut Cpu, channel: u32| {
+            let base = 0x040000B0 + (channel * 12);
+            cpu.bus.write_word_address_debug(0x02000000, base);
+            cpu.bus.write_word_address_debug(0x03000000, base + 4);
+            cpu.bus.write_halfword_address_debug(4, base + 8);
+            cpu.bus.write_halfword_address_debug(0x8400, base + 10);
+            cpu.bus.step();
+        };
+
+        start_dma(&mut cpu, 3);
+        assert!(cpu.bus.dma_log().is_none());
+
+        let mut dma_log = DmaLog::new();
+        dma_log.set_channel_enabled(0, false);
+        cpu.bus.set_dma_log(Some(dma_log));
+        let start_cycle = cpu.bus.cycle_count();
+        start_dma(&mut cpu, 3);
+        start_dma(&mut cpu, 0);
+
+        let transfers = cpu.bus.dma_log().unwrap().transfers();
+        assert_eq!(transfers.len(), 1);
+        assert!(transfers[0].cycle >= start_cycle);
+        assert_eq!(
+            transfers[0],
+            DmaTransfer {
+                channel: 3,
+                cycle: transfers[0].cycle,
+                source: 0x02000000,
+                dest: 0x03000000,
+                count: 4,
+                word_transfer: true,
+                start_timing: DmaStartTiming::Immediately,
+            }
+        );
 
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
+        cpu.bus.dma_log_mut().unwrap().set_channel_enabled(0, true);
+        start_dma(&mut cpu, 0);
+        let transfers = cpu.bus.dma_log().unwrap().transfers();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[1].channel, 0);
+    }
 
-        assert_checksum(&cpu, BIOS_MATH_TEST_SELECTED_CHECKSUM);
+    #[test]
+    fn ewram_waitstates() {
+        use bus::BusAccessType::NonSequential;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        let word_read_cycles = |cpu: &mut Cpu| {
+            let start_cycle = cpu.bus.cycle_count();
+            cpu.bus.read_word_address(0x02000000, NonSequential);
+            cpu.bus.cycle_count() - start_cycle
+        };
+
+        assert_eq!(cpu.bus.read_word_address_debug(0x04000800), 0x0D000020);
+        assert_eq!(word_read_cycles(&mut cpu), 6);
 
-        press_key(&mut cpu, Key::A);
+        // One waitstate, the fastest setting that works on hardware.
+        cpu.bus.write_word_address_debug(0xFE000020, 0x04000800);
+        assert_eq!(cpu.bus.read_word_address_debug(0x04000800), 0xFE000020);
+        assert_eq!(word_read_cycles(&mut cpu), 4);
 
-        assert_checksum(&cpu, BIOS_MATH_SUCCESS_SCREEN_CHECKSUM);
+        cpu.set_config(CpuConfig {
+            fast_ewram: true,
+            ..CpuConfig::default()
+        });
+        assert_eq!(word_read_cycles(&mut cpu), 2);
     }
 
     #[test]
-    fn suite_dma() {
-        const INITIAL_CHECKSUM: u64 = 0x3B32CCEB3BAE455B;
-        const DMA_TEST_SELECTED_CHECKSUM: u64 = 0xB5E03F00EB8D896A;
-        const DMA_SUCCESS_SCREEN_CHECKSUM: u64 = 0x0B05ACFFFB452786;
+    fn internal_memory_control() {
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0x2002, // mov r0, #2
+                0x0600, // lsl r0, r0, #24
+                0x6801, // ldr r1, [r0]
+                0x6801, // ldr r1, [r0]
+            ],
+            &[],
+        );
+        cpu.set_config(CpuConfig {
+            error_policy: ErrorPolicy::Trap,
+            ..CpuConfig::default()
+        });
+
+        // Wait control 15 locks up hardware on the next EWRAM access, which is reported once.
+        cpu.bus.write_word_address_debug(0x0F000020, 0x04000800);
+        for _ in 0..4 {
+            assert_eq!(cpu.fetch_decode_execute(), None);
+        }
+        assert_eq!(
+            cpu.fetch_decode_execute(),
+            Some(StepEvent::Error(EmulatorError::EwramLockup {
+                address: 0x02000000
+            }))
+        );
+        assert_eq!(cpu.fetch_decode_execute(), None);
+
+        // Mirrored every 64KiB.
+        cpu.bus.write_word_address_debug(0x0D000020, 0x04FF0800);
+        assert_eq!(cpu.bus.read_word_address_debug(0x04000800), 0x0D000020);
+        assert_eq!(cpu.bus.read_word_address_debug(0x04010800), 0x0D000020);
+
+        cpu.bus.write_word_address_debug(0x11223344, 0x02000010);
+        cpu.bus.write_word_address_debug(0x55667788, 0x03000010);
+
+        // With EWRAM disabled, its region mirrors IWRAM.
+        cpu.bus.write_word_address_debug(0x0D000000, 0x04000800);
+        assert_eq!(cpu.bus.read_word_address_debug(0x02000010), 0x55667788);
+        assert_eq!(cpu.bus.read_halfword_address_debug(0x02008012), 0x5566);
+        cpu.bus.write_byte_address_debug(0x99, 0x02000011);
+        assert_eq!(cpu.bus.read_word_address_debug(0x03000010), 0x55669988);
+
+        // With both disabled, they read as open bus and ignore writes.
+        cpu.bus.write_word_address_debug(0x0D000021, 0x04000800);
+        cpu.bus.open_bus_data = 0xDEADBEEF;
+        assert_eq!(cpu.bus.read_word_address_debug(0x02000010), 0xDEADBEEF);
+        assert_eq!(cpu.bus.read_byte_address_debug(0x03000011), 0xBE);
+        cpu.bus.write_word_address_debug(0, 0x03000010);
+
+        cpu.bus.write_word_address_debug(0x0D000020, 0x04000800);
+        assert_eq!(cpu.bus.read_word_address_debug(0x02000010), 0x11223344);
+        assert_eq!(cpu.bus.read_word_address_debug(0x03000010), 0x55669988);
+    }
 
-        let source = include_bytes!("../tests/suite.gba");
-        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
-        let mut cpu = Cpu::new(cartridge);
+    #[test]
+    fn io_trace() {
+        use bus::BusAccessType::NonSequential;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        cpu.bus
+            .write_halfword_address(0x1301, 0x04000000, NonSequential);
+        assert!(cpu.bus.io_trace().is_none());
+
+        let mut io_trace = IoTrace::new();
+        let dispcnt = IoTrace::register_address("dispcnt").unwrap();
+        assert_eq!(io_trace.trace(dispcnt), Some("DISPCNT"));
+        assert_eq!(io_trace.trace(0x04000009), Some("BG0CNT"));
+        assert_eq!(io_trace.trace(0x04000400), None);
+        cpu.bus.set_io_trace(Some(io_trace));
+
+        cpu.bus
+            .write_halfword_address(0x1301, 0x04000000, NonSequential);
+        cpu.bus.read_halfword_address(0x04000000, NonSequential);
+        cpu.bus.write_byte_address(0x10, 0x04000001, NonSequential);
+        // Untraced, even though it's next to a traced register.
+        cpu.bus
+            .write_halfword_address(0x0010, 0x04000010, NonSequential);
+        // Spans DISPCNT and GREENSWAP, only the former of which is traced.
+        cpu.bus
+            .write_word_address(0x0001_0000, 0x04000000, NonSequential);
+        cpu.bus
+            .write_halfword_address(0x1F84, 0x04000008, NonSequential);
+
+        let descriptions = cpu
+            .bus
+            .io_trace_mut()
+            .unwrap()
+            .take_entries()
+            .into_iter()
+            .map(|entry| entry.description)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            descriptions,
+            [
+                "DISPCNT := bg mode 1, bg0 enable, bg1 enable, obj enable",
+                "DISPCNT == bg mode 1, bg0 enable, bg1 enable, obj enable",
+                "DISPCNT := obj enable",
+                "DISPCNT := none set",
+                "BG0CNT := 1F84",
+            ]
+        );
+        assert!(cpu.bus.io_trace().unwrap().entries().is_empty());
+        assert_eq!(cpu.bus.io_trace().unwrap().traced(), ["DISPCNT", "BG0CNT"]);
+    }
+
+    #[test]
+    fn bus_profiler() {
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0x46C0, // nop
+                0xE7FD, // b 0x08000008
+            ],
+            &[],
+        );
+        assert!(cpu.bus.profiler().is_none());
+
+        cpu.bus.set_profiler(Some(BusProfiler::new()));
+        for _ in 0..10 {
+            cpu.fetch_decode_execute();
+        }
+
+        let profiler = cpu.bus.profiler().unwrap();
+        assert!(!profiler.tracks_pages());
+        assert_ne!(profiler.region_counts(MemoryRegion::Rom).reads, 0);
+        assert_eq!(profiler.region_counts(MemoryRegion::Rom).writes, 0);
+        assert_eq!(profiler.page_accesses(MemoryRegion::Rom, 0), 0);
+
+        cpu.bus.set_profiler(Some(BusProfiler::with_pages()));
+
+        // An immediate DMA3 of 4 words from EWRAM to IWRAM, through mirrors of both.
+        cpu.bus.write_word_address_debug(0x02040000, 0x040000D4);
+        cpu.bus.write_word_address_debug(0x03008100, 0x040000D8);
+        cpu.bus.write_halfword_address_debug(4, 0x040000DC);
+        cpu.bus.write_halfword_address_debug(0x8400, 0x040000DE);
+        cpu.bus.step();
+
+        let profiler = cpu.bus.profiler().unwrap();
+        assert_eq!(
+            profiler.region_counts(MemoryRegion::BoardWram),
+            RegionAccessCounts {
+                reads: 4,
+                writes: 0
+            }
+        );
+        assert_eq!(
+            profiler.region_counts(MemoryRegion::ChipWram),
+            RegionAccessCounts {
+                reads: 0,
+                writes: 4
+            }
+        );
+        // Debug writes to the DMA registers aren't counted.
+        assert_eq!(
+            profiler.region_counts(MemoryRegion::Io),
+            RegionAccessCounts::default()
+        );
+        assert_eq!(profiler.page_accesses(MemoryRegion::BoardWram, 0), 4);
+        assert_eq!(profiler.page_accesses(MemoryRegion::ChipWram, 1), 4);
+        assert_eq!(profiler.max_page_accesses(MemoryRegion::ChipWram), 4);
+    }
 
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
+    #[test]
+    fn call_stack() {
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0xF000, 0xF802, // bl 0x08000010
+                0xE7FE, // b 0x0800000C
+                0x46C0, // nop
+                0x46C0, // nop
+                0x4770, // bx lr
+            ],
+            &[],
+        );
+
+        // Into Thumb state, then through both halves of the BL.
+        for _ in 0..4 {
             cpu.fetch_decode_execute();
         }
 
-        assert_checksum(&cpu, INITIAL_CHECKSUM);
+        let expected = [Frame {
+            kind: FrameKind::Call,
+            call_site: 0x08000008,
+            target: 0x08000010,
+            return_address: 0x0800000C,
+        }];
+        assert_eq!(cpu.call_stack(), expected);
 
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
-        press_key(&mut cpu, Key::Down);
+        cpu.fetch_decode_execute();
+        assert_eq!(cpu.call_stack(), expected);
 
-        assert_checksum(&cpu, DMA_TEST_SELECTED_CHECKSUM);
+        cpu.fetch_decode_execute();
+        assert_eq!(cpu.get_executing_pc(), 0x0800000C);
+        assert!(cpu.call_stack().is_empty());
+    }
 
-        press_key(&mut cpu, Key::A);
+    #[test]
+    fn symbol_table() {
+        let strings = b"\0main\0$t\0gData\0";
+        let mut symbols = vec![0; 16]; // the null symbol
+        for (name, value, size, info, section) in [
+            (1u32, 0x08000291u32, 0x20u32, 0x12u8, 1u16), // global Thumb function
+            (6, 0x08000290, 0, 0x00, 1),                  // mapping symbol
+            (9, 0x03000000, 4, 0x11, 2),                  // global object
+        ] {
+            symbols.extend(name.to_le_bytes());
+            symbols.extend(value.to_le_bytes());
+            symbols.extend(size.to_le_bytes());
+            symbols.extend([info, 0]);
+            symbols.extend(section.to_le_bytes());
+        }
 
-        let start_cycles = cpu.bus.cycle_count();
+        let section_header = |section_type: u32, offset: usize, size: usize, link: u32| {
+            let mut header = [0; 40];
+            header[4..8].copy_from_slice(&section_type.to_le_bytes());
+            header[16..20].copy_from_slice(&(offset as u32).to_le_bytes());
+            header[20..24].copy_from_slice(&(size as u32).to_le_bytes());
+            header[24..28].copy_from_slice(&link.to_le_bytes());
+            header
+        };
+
+        let symbols_offset = 52;
+        let strings_offset = symbols_offset + symbols.len();
+        let section_headers_offset = strings_offset + strings.len();
+
+        let mut elf = vec![0; 52];
+        elf[0..6].copy_from_slice(b"\x7FELF\x01\x01");
+        elf[0x20..0x24].copy_from_slice(&(section_headers_offset as u32).to_le_bytes());
+        elf[0x30..0x32].copy_from_slice(&3u16.to_le_bytes());
+        elf.extend(&symbols);
+        elf.extend(strings);
+        elf.extend([0; 40]);
+        elf.extend(section_header(2, symbols_offset, symbols.len(), 2));
+        elf.extend(section_header(3, strings_offset, strings.len(), 0));
+
+        let table = SymbolTable::from_elf(&elf).unwrap();
+        assert_eq!(table.symbols().len(), 2);
+        assert_eq!(table.describe(0x08000290).as_deref(), Some("main"));
+        assert_eq!(table.describe(0x080002A0).as_deref(), Some("main+0x10"));
+        assert_eq!(table.describe(0x080002B0), None);
+        assert_eq!(table.address_of("gData"), Some(0x03000000));
+        assert!(SymbolTable::from_elf(&elf[..0x30]).is_err());
+
+        let table = SymbolTable::from_map(
+            " .text          0x08000000      0x1c4 main.o
+                0x08000000                _start
+                0x08000100                main
+                0x0000000008000200                __text_end = .",
+        );
+        assert_eq!(table.symbols().len(), 2);
+        assert_eq!(table.describe(0x08000104).as_deref(), Some("main+0x4"));
+        assert_eq!(table.address_of("__text_end"), None);
+        assert_eq!(table.lookup(0x07FFFFFF), None);
+
+        let table = SymbolTable::from_sym(
+            "08000000 _start
+; comment
+08000100 .thm
+08000200 update ; game loop",
+        );
+        assert_eq!(table.symbols().len(), 2);
+        assert_eq!(table.describe(0x08000200).as_deref(), Some("update"));
+        assert_eq!(table.describe(0x08000104).as_deref(), Some("_start+0x104"));
+    }
 
-        // DMA test takes a while, so wait an extra second for test to run.
-        while cpu.bus.cycle_count() - start_cycles < CYCLES_PER_SECOND {
+    #[test]
+    fn coverage() {
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0x46C0, // nop
+                0xE7FD, // b 0x08000008
+            ],
+            &[],
+        );
+        assert_eq!(cpu.coverage_summary(), None);
+
+        cpu.bus.set_coverage(Some(CoverageRecorder::new()));
+        for _ in 0..10 {
             cpu.fetch_decode_execute();
         }
 
-        assert_checksum(&cpu, DMA_SUCCESS_SCREEN_CHECKSUM);
+        // Both ARM instructions, then the Thumb loop.
+        let summary = cpu.coverage_summary().unwrap();
+        assert_eq!(
+            summary,
+            CoverageSummary {
+                rom_size: 0x200,
+                executed_rom_bytes: 12,
+                read_rom_bytes: 0,
+            }
+        );
+        assert_eq!(summary.executed_percent(), 12.0 * 100.0 / 512.0);
+
+        let coverage = cpu.bus.coverage().unwrap();
+        assert!(coverage.is_covered(CoverageKind::Executed, 0x0800000A));
+        // Mirrors of the ROM are covered together.
+        assert!(coverage.is_covered(CoverageKind::Executed, 0x0A00000A));
+        // Prefetched, but never executed.
+        assert!(!coverage.is_covered(CoverageKind::Executed, 0x0800000C));
+        assert!(!coverage.is_covered(CoverageKind::Read, 0x0800000C));
+
+        // An immediate DMA3 of 2 words from EWRAM to IWRAM.
+        cpu.bus.write_word_address_debug(0x02000000, 0x040000D4);
+        cpu.bus.write_word_address_debug(0x03000010, 0x040000D8);
+        cpu.bus.write_halfword_address_debug(2, 0x040000DC);
+        cpu.bus.write_halfword_address_debug(0x8400, 0x040000DE);
+        cpu.bus.step();
+
+        let coverage = cpu.bus.coverage().unwrap();
+        assert_eq!(
+            coverage.covered_bytes(CoverageKind::Read, MemoryRegion::BoardWram, 0x40000),
+            8
+        );
+        assert!(coverage.is_covered(CoverageKind::Written, 0x03000017));
+        assert!(!coverage.is_covered(CoverageKind::Written, 0x03000018));
+
+        let mut exported = Vec::new();
+        coverage.export(&mut exported).unwrap();
+        assert!(exported.starts_with(b"GBACOV01"));
+        // ROM executed, EWRAM read and IWRAM written.
+        assert_eq!(
+            exported.len(),
+            8 + (6 * 3) + (0x2000000 / 8) + (0x40000 / 8) + (0x8000 / 8)
+        );
     }
 
     #[test]
-    fn openbuster() {
-        const SCREEN_CHECKSUMS: &[u64] = &[
-            0x3B65E15802C43DC1, // IWRAM - LDRB
-            0xD04B19A7B4641F90, // IWRAM - STRB
-            0x95BBB22BA233B656, // IWRAM - LDRH
-            0x2D336CF7F9C81FA7, // EWRAM - LDRB/LDRH (0)
-            0x6DCC7C311AFC714B, // EWRAM - LDRB/LDRH (1)
-            0x43C0582E38FE5868, // MMIO - LDRB/LDRH (0)
-            0x52060C8F3830A037, // MMIO - LDRB/LDRH (1)
-            0x15F16BEADE6D951F, // VRAM - LDRB/LDRH (0)
-            0xF10287B271137E86, // VRAM - LDRB/LDRH (1)
+    fn thumb_disassembly() {
+        // Every encoding should disassemble to something, even if it's just INVALID.
+        for opcode in 0..=u16::MAX {
+            let disassembly = cpu::thumb::decode_thumb(opcode).to_string();
+            assert!(!disassembly.is_empty(), "{opcode:04X}");
+        }
+
+        let cases = [
+            (0x1C08, "add r0, r1, #0"),
+            (0x2005, "mov r0, #5"),
+            (0x4240, "neg r0, r0"),
+            (0x4348, "mul r0, r1"),
+            (0x5E08, "ldrsh r0, [r1, r0]"),
+            (0xB082, "sub sp, #8"),
+            (0xA802, "add r0, sp, #8"),
+            (0xB510, "push {r4, lr}"),
+            (0xBD1F, "pop {r0-r4, pc}"),
+            (0xC080, "stmia r0!, {r7}"),
+            (0xC8A5, "ldmia r0!, {r0, r2, r5, r7}"),
+            (0xE7FE, "b 0xFFFFFFFC"),
+            (0x4718, "bx r3"),
         ];
-        const ALL_PASSED_CHECKSUM: u64 = 0x444CF2773FFA0FBA; // passed: 144 total: 144
 
-        let source = include_bytes!("../tests/openbuster.gba");
-        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
-        let mut cpu = Cpu::new(cartridge);
+        for (opcode, expected) in cases {
+            assert_eq!(cpu::thumb::decode_thumb(opcode).to_string(), expected);
+        }
 
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
+        assert_eq!(cpu::arm::decode_arm(0xE12FFF33).to_string(), "blx r3");
+    }
+
+    #[test]
+    fn swi_hook() {
+        use std::sync::{Arc, Mutex};
+
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0x2007, // mov r0, #7
+                0x2102, // mov r1, #2
+                0xDF06, // swi 6
+                0xDF06, // swi 6
+            ],
+            &[],
+        );
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let hook_calls = calls.clone();
+        cpu.on_swi(move |_, call| {
+            let mut calls = hook_calls.lock().unwrap();
+            calls.push(call);
+            // Handle the first division, and leave the second to the BIOS.
+            if calls.len() == 1 {
+                let [numerator, denominator, ..] = call.arguments;
+                SwiAction::Return([numerator / denominator, numerator % denominator, 3, 0])
+            } else {
+                SwiAction::RunBios
+            }
+        });
+
+        for _ in 0..5 {
             cpu.fetch_decode_execute();
         }
 
-        for &screen_checksum in SCREEN_CHECKSUMS {
-            assert_checksum(&cpu, screen_checksum);
-            press_key(&mut cpu, Key::A);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [SwiCall {
+                address: 0x0800000C,
+                number: 6,
+                arguments: [7, 2, 0, 0],
+            }]
+        );
+        assert_eq!(cpu.get_executing_pc(), 0x0800000E);
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::System);
+        assert_eq!(cpu.read_register(Register::R0, |pc| pc), 3);
+        assert_eq!(cpu.read_register(Register::R1, |pc| pc), 1);
+        assert_eq!(cpu.read_register(Register::R2, |pc| pc), 3);
+        assert!(cpu.call_stack().is_empty());
+
+        cpu.fetch_decode_execute();
+        assert_eq!(calls.lock().unwrap().len(), 2);
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::Supervisor);
+        assert_eq!(cpu.get_executing_pc(), 0x00000008);
+    }
+
+    #[test]
+    fn swi_stats() {
+        let mut cpu = build_thumb_test_cpu(
+            &[
+                0xDF06, // swi 6
+                0xDF06, // swi 6
+                0xDF06, // swi 6
+                0xDF08, // swi 8
+            ],
+            &[],
+        );
+        // Handle every Div, leaving Sqrt to the BIOS.
+        cpu.on_swi(|_, call| {
+            if call.number == 6 {
+                SwiAction::Return([0; 4])
+            } else {
+                SwiAction::RunBios
+            }
+        });
+        assert_eq!(cpu.swi_stats().total_calls(), 0);
+
+        for _ in 0..6 {
+            cpu.fetch_decode_execute();
         }
+        assert_eq!(cpu.get_cpu_mode(), CpuMode::Supervisor);
+        assert_eq!(cpu.swi_stats().total_calls(), 4);
+
+        assert_eq!(
+            cpu.swi_stats().by_calls(),
+            [
+                (
+                    6,
+                    SwiCount {
+                        calls: 3,
+                        handled_by_hook: 3
+                    }
+                ),
+                (
+                    8,
+                    SwiCount {
+                        calls: 1,
+                        handled_by_hook: 0
+                    }
+                ),
+            ]
+        );
+        assert_eq!(bios_function_name(6), Some("Div"));
+        assert_eq!(bios_function_name(0x2A), Some("SoundGetJumpList"));
+        assert_eq!(bios_function_name(0x2B), None);
+
+        cpu.reset_swi_stats();
+        assert!(cpu.swi_stats().counts().is_empty());
+    }
 
-        assert_checksum(&cpu, ALL_PASSED_CHECKSUM);
+    #[test]
+    fn sound_bias() {
+        const SOUNDCNT_H: u32 = 0x04000082;
+        const SOUNDCNT_X: u32 = 0x04000084;
+        const SOUNDBIAS: u32 = 0x04000088;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        assert_eq!(cpu.apu_sample_rate(), 32_768);
+        cpu.bus.write_halfword_address_debug(0x0080, SOUNDCNT_X);
+
+        // An empty FIFO outputs its lowest level, on both sides.
+        cpu.bus.write_halfword_address_debug(0x0300, SOUNDCNT_H);
+        assert_eq!(cpu.sample_apu(), [-0.25, -0.25]);
+
+        // Without any bias the negative half of the waveform is clipped.
+        cpu.bus.write_halfword_address_debug(0x0000, SOUNDBIAS);
+        assert_eq!(cpu.sample_apu(), [0.0, 0.0]);
+
+        cpu.bus.write_halfword_address_debug(0x0100, SOUNDBIAS);
+        assert_eq!(cpu.sample_apu(), [-0.25, -0.25]);
+
+        // 6-bit resolution at 262.144kHz.
+        cpu.bus.write_halfword_address_debug(0xC200, SOUNDBIAS);
+        assert_eq!(cpu.apu_sample_rate(), 262_144);
+        assert_eq!(cpu.bus.read_halfword_address_debug(SOUNDBIAS), 0xC200);
     }
 
     #[test]
-    fn bios_open_bus() {
-        const PASS_CHECKSUM: u64 = 0xC01DFDB8318FFCE5;
+    fn sound_power_off() {
+        const SOUND1CNT_H: u32 = 0x04000062;
+        const SOUNDCNT_L: u32 = 0x04000080;
+        const SOUNDCNT_X: u32 = 0x04000084;
+        const WAVE_RAM: u32 = 0x04000090;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        // Ignored while the APU is off.
+        cpu.bus.write_halfword_address_debug(0xF080, SOUND1CNT_H);
+        assert_eq!(cpu.bus.read_halfword_address_debug(SOUND1CNT_H), 0);
+
+        cpu.bus.write_halfword_address_debug(0x0080, SOUNDCNT_X);
+        cpu.bus.write_halfword_address_debug(0xF080, SOUND1CNT_H);
+        cpu.bus.write_halfword_address_debug(0xFF77, SOUNDCNT_L);
+        cpu.bus.write_halfword_address_debug(0x1234, WAVE_RAM);
+        assert_eq!(cpu.bus.read_halfword_address_debug(SOUND1CNT_H), 0xF080);
+        assert_eq!(cpu.bus.read_halfword_address_debug(SOUNDCNT_L), 0xFF77);
+
+        // Powering off clears everything but wave RAM.
+        cpu.bus.write_halfword_address_debug(0x0000, SOUNDCNT_X);
+        assert_eq!(cpu.bus.read_halfword_address_debug(SOUND1CNT_H), 0);
+        assert_eq!(cpu.bus.read_halfword_address_debug(SOUNDCNT_L), 0);
+        assert_eq!(cpu.bus.read_halfword_address_debug(WAVE_RAM), 0x1234);
+        assert_eq!(cpu.sample_apu(), [0.0, 0.0]);
+
+        cpu.bus.write_halfword_address_debug(0x0080, SOUNDCNT_X);
+        assert_eq!(cpu.bus.read_halfword_address_debug(SOUND1CNT_H), 0);
+    }
 
-        let source = include_bytes!("../tests/bios_open_bus.gba");
-        let cartridge = Cartridge::new(source.as_slice(), None).unwrap();
-        let mut cpu = Cpu::new(cartridge);
+    #[test]
+    fn lcd_status_writes() {
+        const DISPSTAT: u32 = 0x04000004;
+        const VCOUNT: u32 = 0x04000006;
+        const IF: u32 = 0x04000202;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        cpu.bus.write_halfword_address_debug(0x1234, VCOUNT);
+        assert_eq!(cpu.bus.read_halfword_address_debug(VCOUNT), 0);
+
+        // The flag bits and unused bits can't be written, but matching the current line sets the
+        // VCOUNT flag and requests an interrupt straight away.
+        cpu.bus.write_halfword_address_debug(0x01E7, DISPSTAT);
+        assert_eq!(cpu.bus.read_halfword_address_debug(DISPSTAT), 0x0120);
+        cpu.bus.write_halfword_address_debug(0x00E7, DISPSTAT);
+        assert_eq!(cpu.bus.read_halfword_address_debug(DISPSTAT), 0x0024);
+
+        for _ in 0..16 {
+            cpu.bus.step();
+        }
+        assert!(cpu.bus.read_halfword_address_debug(IF).get_bit(2));
+        assert!(cpu.bus.lcd.current_scanline_dot() > 0);
+    }
+
+    fn render_frame(cpu: &mut Cpu) {
+        while !cpu.bus.poll_frame_completed() {
+            cpu.bus.step();
+        }
+    }
+
+    #[test]
+    fn obj_render_cycle_budget() {
+        const DISPCNT: u32 = 0x04000000;
+        const OBJ_PALETTE: u32 = 0x05000200;
+        const OBJ_VRAM: u32 = 0x06010000;
+        const OAM: u32 = 0x07000000;
+        const RED: u16 = 0x001F;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        // Mode 0, OBJs enabled with 1D mapping, and a solid 64x64 4bpp sprite.
+        cpu.bus.write_halfword_address_debug(0x1040, DISPCNT);
+        cpu.bus.write_halfword_address_debug(RED, OBJ_PALETTE + 2);
+        for offset in (0..0x800).step_by(2) {
+            cpu.bus
+                .write_halfword_address_debug(0x1111, OBJ_VRAM + offset);
+        }
+
+        let sprite_at = |cpu: &mut Cpu, index: u32, x: Option<u16>| {
+            let address = OAM + (index * 8);
+            match x {
+                Some(x) => {
+                    cpu.bus.write_halfword_address_debug(0x0000, address);
+                    cpu.bus
+                        .write_halfword_address_debug(0xC000 | x, address + 2);
+                }
+                // Disabled.
+                None => cpu.bus.write_halfword_address_debug(0x0200, address),
+            }
+        };
+
+        let pixel_after_frame = |cpu: &mut Cpu, x: usize| {
+            render_frame(cpu);
+            cpu.bus.lcd.get_buffer()[0][x].to_int()
+        };
+
+        for index in 0..128 {
+            sprite_at(&mut cpu, index, None);
+        }
+
+        // 19 64 pixel wide sprites take 1216 cycles, so the last one doesn't fit.
+        for index in 0..18 {
+            sprite_at(&mut cpu, index, Some(0));
+        }
+        sprite_at(&mut cpu, 18, Some(100));
+        assert_ne!(pixel_after_frame(&mut cpu, 100), RED);
+        assert_eq!(pixel_after_frame(&mut cpu, 0), RED);
 
-        // skip boot screen
-        while cpu.bus.cycle_count() < 100_000_000 {
+        sprite_at(&mut cpu, 17, None);
+        assert_eq!(pixel_after_frame(&mut cpu, 100), RED);
+    }
+
+    #[test]
+    fn affine_obj() {
+        const DISPCNT: u32 = 0x04000000;
+        const OBJ_PALETTE: u32 = 0x05000200;
+        const OBJ_VRAM: u32 = 0x06010000;
+        const OAM: u32 = 0x07000000;
+        const RED: u16 = 0x001F;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        // Mode 0, OBJs enabled with 1D mapping, and a solid 8x8 4bpp sprite.
+        cpu.bus.write_halfword_address_debug(0x1040, DISPCNT);
+        cpu.bus.write_halfword_address_debug(RED, OBJ_PALETTE + 2);
+        for offset in (0..0x20).step_by(2) {
+            cpu.bus
+                .write_halfword_address_debug(0x1111, OBJ_VRAM + offset);
+        }
+
+        for index in 0..128 {
+            cpu.bus
+                .write_halfword_address_debug(0x0200, OAM + (index * 8));
+        }
+
+        // Identity in the last parameter group. The first is left all zero, which would stretch
+        // the centre pixel over the whole sprite.
+        for (index, parameter) in [0x0100, 0x0000, 0x0000, 0x0100].into_iter().enumerate() {
+            let address = OAM + (((31 * 4) + index as u32) * 8) + 6;
+            cpu.bus.write_halfword_address_debug(parameter, address);
+        }
+
+        // Double size at (0, 0), drawn in the middle of its 16x16 area.
+        cpu.bus.write_halfword_address_debug(0x0300, OAM);
+        cpu.bus.write_halfword_address_debug(0x3E00, OAM + 2);
+        // At x=508, wrapping around to the left edge of the screen.
+        cpu.bus.write_halfword_address_debug(0x0114, OAM + 8);
+        cpu.bus.write_halfword_address_debug(0x3E00 | 508, OAM + 10);
+
+        render_frame(&mut cpu);
+        let buffer = cpu.bus.lcd.get_buffer();
+        let drawn = |y: usize, x: usize| buffer[y][x].to_int() == RED;
+
+        assert!(!drawn(3, 4));
+        assert!(!drawn(4, 3));
+        assert!(drawn(4, 4));
+        assert!(drawn(11, 11));
+        assert!(!drawn(11, 12));
+        assert!(!drawn(12, 11));
+
+        assert!(drawn(20, 0));
+        assert!(drawn(27, 3));
+        assert!(!drawn(20, 4));
+    }
+
+    #[test]
+    fn video_memory_byte_writes() {
+        const DISPCNT: u32 = 0x04000000;
+        const PALETTE: u32 = 0x05000000;
+        const VRAM: u32 = 0x06000000;
+        const OBJ_VRAM: u32 = 0x06010000;
+        const BITMAP_OBJ_VRAM: u32 = 0x06014000;
+        const OAM: u32 = 0x07000000;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        let write_byte = |cpu: &mut Cpu, value: u8, address: u32| {
+            cpu.bus
+                .write_byte_address(value, address, bus::BusAccessType::NonSequential);
+        };
+
+        // The byte is written to both halves of the halfword it's in.
+        write_byte(&mut cpu, 0x12, PALETTE + 1);
+        write_byte(&mut cpu, 0x34, VRAM + 2);
+        assert_eq!(cpu.bus.read_halfword_address_debug(PALETTE), 0x1212);
+        assert_eq!(cpu.bus.read_halfword_address_debug(VRAM + 2), 0x3434);
+
+        // OBJ VRAM and OAM ignore byte writes altogether.
+        write_byte(&mut cpu, 0x56, OBJ_VRAM + 2);
+        write_byte(&mut cpu, 0x56, OAM + 3);
+        assert_eq!(cpu.bus.read_halfword_address_debug(OBJ_VRAM + 2), 0);
+        assert_eq!(cpu.bus.read_halfword_address_debug(OAM + 2), 0);
+
+        // In bitmap modes, the BG region extends into the first half of tile mode OBJ VRAM.
+        cpu.bus.write_halfword_address_debug(0x0003, DISPCNT);
+        write_byte(&mut cpu, 0x78, OBJ_VRAM);
+        write_byte(&mut cpu, 0x78, BITMAP_OBJ_VRAM);
+        assert_eq!(cpu.bus.read_halfword_address_debug(OBJ_VRAM), 0x7878);
+        assert_eq!(cpu.bus.read_halfword_address_debug(BITMAP_OBJ_VRAM), 0);
+    }
+
+    #[test]
+    fn access_watch() {
+        const WATCHED: u32 = 0x02000002;
+
+        // mov r0, #2
+        // lsl r0, r0, #24
+        // str r1, [r0]
+        // ldrb r2, [r0, #3]
+        let mut cpu = build_thumb_test_cpu(&[0x2002, 0x0600, 0x6001, 0x78C2], &[]);
+        let mut access_watch = AccessWatch::new();
+        access_watch.watch(AccessKind::Read, WATCHED);
+        access_watch.watch(AccessKind::Write, WATCHED);
+        cpu.bus.set_access_watch(Some(access_watch));
+
+        for _ in 0..6 {
             cpu.fetch_decode_execute();
         }
 
-        assert_checksum(&cpu, PASS_CHECKSUM);
+        // The word store covers the watched byte, the byte load just after it doesn't.
+        assert_eq!(
+            cpu.bus.access_watch_mut().unwrap().take_hits(),
+            vec![AccessHit {
+                kind: AccessKind::Write,
+                address: WATCHED
+            }]
+        );
+        assert!(cpu.bus.access_watch_mut().unwrap().take_hits().is_empty());
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn script_hooks() {
+        // mov r0, #2
+        // lsl r0, r0, #24
+        // str r1, [r0]
+        // b .
+        let cpu = build_thumb_test_cpu(&[0x2002, 0x0600, 0x6001, 0xE7FE], &[]);
+        let mut script = Script::new(
+            r#"
+            on_write(0x02000000, |address| write32(0x02000100, address));
+            on_frame(|| {
+                write32(0x02000104, read32(0x02000104) + 1);
+                press("start");
+            });
+            "#,
+            cpu,
+        )
+        .unwrap();
+
+        while !matches!(script.step().unwrap(), Some(StepEvent::FrameComplete)) {}
+
+        let cpu = script.into_cpu();
+        assert_eq!(cpu.bus.read_word_address_debug(0x02000100), 0x02000000);
+        assert_eq!(cpu.bus.read_word_address_debug(0x02000104), 1);
+        assert!(cpu.bus.keypad.get_state().is_pressed(Key::Start));
+    }
+
+    #[test]
+    fn lockstep_netplay() {
+        const INPUT_DELAY: usize = 2;
+
+        let mut cpus = [
+            build_thumb_test_cpu(&[], &[]),
+            build_thumb_test_cpu(&[], &[]),
+        ];
+        let mut sessions = [
+            Lockstep::new(INPUT_DELAY as u32),
+            Lockstep::new(INPUT_DELAY as u32),
+        ];
+        let mut start_pressed = KeysState::default();
+        start_pressed.set_pressed(Key::Start, true);
+
+        // Without a delay, nothing can run until the peer's input for the frame arrives.
+        let mut undelayed = Lockstep::new(0);
+        undelayed.set_local_input(start_pressed);
+        assert_eq!(undelayed.begin_frame(&cpus[0]), None);
+
+        // Only the first player presses anything, from the second frame on. The second player's
+        // memory is changed between two state hashes.
+        let mut frame_keys = [Vec::new(), Vec::new()];
+        for frame in 0..=Lockstep::HASH_INTERVAL {
+            sessions[0].set_local_input(if frame > 0 {
+                start_pressed
+            } else {
+                KeysState::default()
+            });
+            sessions[1].set_local_input(KeysState::default());
+
+            let packets = [sessions[0].packet(), sessions[1].packet()];
+            sessions[0].receive(&packets[1]).unwrap();
+            sessions[1].receive(&packets[0]).unwrap();
+
+            if frame == 1 {
+                cpus[1].bus.write_byte_address_debug(0xFF, 0x02000000);
+            }
+
+            for (player, cpu) in cpus.iter_mut().enumerate() {
+                let keys = sessions[player].begin_frame(cpu).unwrap();
+                frame_keys[player].push(keys);
+                render_frame(cpu);
+            }
+        }
+
+        assert_eq!(frame_keys[0], frame_keys[1]);
+        assert_eq!(frame_keys[0][INPUT_DELAY], KeysState::default());
+        assert_eq!(frame_keys[0][INPUT_DELAY + 1], start_pressed);
+
+        // The hashes for the last frame are only compared once they have been exchanged.
+        assert_eq!(sessions[0].desync_frame(), None);
+        let packet = sessions[1].packet();
+        sessions[0].receive(&packet).unwrap();
+        assert_eq!(sessions[0].desync_frame(), Some(Lockstep::HASH_INTERVAL));
+        assert_eq!(cpus[0].diff_state(&cpus[1]).wram_pages, vec![0x02000000]);
+    }
+
+    #[test]
+    fn state_diff() {
+        const DISPCNT: u32 = 0x04000000;
+        const VRAM: u32 = 0x06000000;
+
+        let a = build_thumb_test_cpu(&[], &[]);
+        let mut b = a.clone();
+        assert!(a.diff_state(&b).is_empty());
+
+        b.bus.write_word_address_debug(1, 0x03007F00);
+        b.bus.write_halfword_address_debug(0x0403, DISPCNT);
+        b.bus.write_halfword_address_debug(0x001F, VRAM + 0x800);
+        b.bus.write_halfword_address_debug(0x1234, 0x04000100); // TM0CNT_L
+        b.fetch_decode_execute();
+
+        let diff = a.diff_state(&b);
+        assert_eq!(diff.registers, vec![Register::R0, Register::R15]);
+        assert!(diff.cycle_count);
+        assert_eq!(diff.wram_pages, vec![0x03007C00]);
+        assert!(diff.io_registers.contains(&"DISPCNT"));
+        assert_eq!(diff.video_memory_pages, vec![VRAM + 0x800]);
+        assert_eq!(diff.timers, vec![0]);
+        assert!(diff.dma_channels.is_empty());
+    }
+
+    #[test]
+    fn save_state_versioning() {
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        cpu.fetch_decode_execute();
+        cpu.bus.write_word_address_debug(0x12345678, 0x02000100);
+
+        let load = |state: &SaveState| {
+            let mut loaded = build_thumb_test_cpu(&[], &[]);
+            loaded.load_state(state.to_cpu().unwrap());
+            loaded
+        };
+
+        let state = SaveState::from_cpu(&cpu).unwrap();
+        let mut file = Vec::new();
+        state.write(&mut file).unwrap();
+        let read = SaveState::read(file.as_slice()).unwrap();
+        assert_eq!(read, state);
+        assert_eq!(read.version(), SaveState::VERSION);
+        assert!(cpu.diff_state(&load(&read)).is_empty());
+        assert!(SaveState::read(&file[..file.len() - 1]).is_err());
+
+        // Written before save states had a header.
+        let headerless = serde_cbor::to_vec(&cpu).unwrap();
+        let read = SaveState::read(headerless.as_slice()).unwrap();
+        assert_eq!(read.version(), 0);
+        assert!(cpu.diff_state(&load(&read)).is_empty());
+
+        // From a newer build, with a chunk this one doesn't know and without one it does.
+        let mut newer = state.clone();
+        newer.set_chunk(*b"NEW ", vec![1, 2, 3]);
+        newer.remove_chunk(*b"SIO ");
+        let mut file = Vec::new();
+        newer.write(&mut file).unwrap();
+        file[4..8].copy_from_slice(&(SaveState::VERSION + 1).to_le_bytes());
+        let read = SaveState::read(file.as_slice()).unwrap();
+        assert_eq!(read.chunk(*b"NEW "), Some([1, 2, 3].as_slice()));
+        assert!(cpu.diff_state(&load(&read)).is_empty());
+    }
+
+    #[test]
+    fn save_state_thumbnail() {
+        const DISPCNT: u32 = 0x04000000;
+        const VRAM: u32 = 0x06000000;
+
+        // Mode 3, with a white 2x2 block in the top left corner.
+        let mut cpu = build_thumb_test_cpu(&[0xE7FE], &[]); // b 0x08000008
+        cpu.bus.write_halfword_address_debug(0x0403, DISPCNT);
+        for offset in [0, 2, 480, 482] {
+            cpu.bus.write_halfword_address_debug(0x7FFF, VRAM + offset);
+        }
+        // The first frame may have started before the display was set up.
+        for _ in 0..2 {
+            while cpu.run_until_event() != StepEvent::FrameComplete {}
+        }
+
+        let mut file = Vec::new();
+        SaveState::from_cpu(&cpu).unwrap().write(&mut file).unwrap();
+        let thumbnail = SaveState::read(file.as_slice())
+            .unwrap()
+            .thumbnail()
+            .unwrap()
+            .unwrap();
+        assert_eq!(thumbnail, Thumbnail::from_lcd(&cpu.bus.lcd));
+        assert_eq!((thumbnail.width, thumbnail.height), (120, 80));
+        assert_eq!(thumbnail.rgb[0..6], [0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00]);
+
+        let headerless = serde_cbor::to_vec(&cpu).unwrap();
+        let state = SaveState::read(headerless.as_slice()).unwrap();
+        assert_eq!(state.thumbnail().unwrap(), None);
+    }
+
+    #[test]
+    fn memory_search() {
+        const LIVES: u32 = 0x02000100;
+        const TIMER: u32 = 0x03000200;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        cpu.bus.write_halfword_address_debug(3, LIVES);
+        cpu.bus.write_halfword_address_debug(1000, TIMER);
+
+        let mut search = MemorySearch::new(&cpu.bus, SearchWidth::Halfword);
+        assert_eq!(search.candidates().len(), (0x40000 + 0x8000) / 2);
+
+        search.filter(&cpu.bus, SearchFilter::EqualTo(3));
+        assert!(search
+            .candidates()
+            .iter()
+            .any(|candidate| candidate.address == LIVES));
+
+        cpu.bus.write_halfword_address_debug(2, LIVES);
+        cpu.bus.write_halfword_address_debug(990, TIMER);
+        search.filter(&cpu.bus, SearchFilter::ChangedBy(-1));
+        assert_eq!(
+            search.candidates(),
+            [SearchCandidate {
+                address: LIVES,
+                value: 2,
+                previous: 3,
+            }]
+        );
+
+        search.filter(&cpu.bus, SearchFilter::Unchanged);
+        assert_eq!(search.candidates().len(), 1);
+        assert_eq!(search.snapshots(), 4);
+
+        let mut search = MemorySearch::new(&cpu.bus, SearchWidth::Halfword);
+        cpu.bus.write_halfword_address_debug(980, TIMER);
+        search.filter(&cpu.bus, SearchFilter::Decreased);
+        search.filter(&cpu.bus, SearchFilter::GreaterThan(900));
+        assert_eq!(search.candidates()[0].address, TIMER);
+        assert_eq!(search.candidates().len(), 1);
+
+        search.remove(TIMER);
+        assert!(search.candidates().is_empty());
+    }
+
+    #[test]
+    fn frame_callback() {
+        use std::sync::{Arc, Mutex};
+
+        const DISPCNT: u32 = 0x04000000;
+        const VRAM: u32 = 0x06000000;
+        const RED: u16 = 0x001F;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        let first_pixels = Arc::new(Mutex::new(Vec::new()));
+
+        let callback_first_pixels = Arc::clone(&first_pixels);
+        cpu.set_frame_callback(move |frame| {
+            callback_first_pixels
+                .lock()
+                .unwrap()
+                .push(frame[0][0].to_int());
+        });
+
+        // Mode 3 with BG2 enabled, so VRAM is drawn directly as a bitmap.
+        cpu.bus.write_halfword_address_debug(0x0403, DISPCNT);
+        render_frame(&mut cpu);
+        cpu.bus.write_halfword_address_debug(RED, VRAM);
+        render_frame(&mut cpu);
+
+        // Fired once per frame, with the frame that was just completed.
+        assert_eq!(first_pixels.lock().unwrap().len(), 2);
+        assert_eq!(first_pixels.lock().unwrap()[1], RED);
+        assert_eq!(cpu.bus.lcd.get_buffer()[0][0].to_int(), RED);
+
+        cpu.clear_frame_callback();
+        render_frame(&mut cpu);
+        assert_eq!(first_pixels.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn lcd_background_map() {
+        const DISPCNT: u32 = 0x04000000;
+        const BG0CNT: u32 = 0x04000008;
+        const BG0HOFS: u32 = 0x04000010;
+        const BG2CNT: u32 = 0x0400000C;
+        const BG2PA: u32 = 0x04000020;
+        const BG2PD: u32 = 0x04000026;
+        const BG2X: u32 = 0x04000028;
+        const BG_PALETTE: u32 = 0x05000000;
+        const VRAM: u32 = 0x06000000;
+        const RED: u16 = 0x001F;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        cpu.bus.write_halfword_address_debug(RED, BG_PALETTE + 2);
+
+        // 4 bit tile 1 is solid color 1, and is placed second on a 32x32 map in screen block 8.
+        for offset in (0..32).step_by(2) {
+            cpu.bus
+                .write_halfword_address_debug(0x1111, VRAM + 32 + offset);
+        }
+        cpu.bus.write_halfword_address_debug(1, VRAM + 0x4000 + 2);
+        cpu.bus.write_halfword_address_debug(8 << 8, BG0CNT);
+        cpu.bus.write_halfword_address_debug(5, BG0HOFS);
+
+        let map = cpu.bus.lcd.background_map(0).unwrap();
+        assert_eq!((map.width, map.height), (256, 256));
+        assert!(map.pixels[0].is_none());
+        assert_eq!(map.pixels[8].map(Rgb555::to_int), Some(RED));
+        assert_eq!(map.pixels[(7 * 256) + 15].map(Rgb555::to_int), Some(RED));
+        assert_eq!(map.viewport, BackgroundViewport::Text { x: 5, y: 0 });
+
+        // Mode 1 makes BG2 affine, and doesn't show BG3 at all.
+        cpu.bus.write_halfword_address_debug(0x0001, DISPCNT);
+        cpu.bus.write_halfword_address_debug(0x0100, BG2PA);
+        cpu.bus.write_halfword_address_debug(0x0100, BG2PD);
+        cpu.bus.write_word_address_debug(0x0800, BG2X);
+        cpu.bus.write_halfword_address_debug(0, BG2CNT);
+
+        let map = cpu.bus.lcd.background_map(2).unwrap();
+        assert_eq!((map.width, map.height), (128, 128));
+        let BackgroundViewport::Affine { corners } = map.viewport else {
+            panic!("expected an affine viewport, got {:?}", map.viewport);
+        };
+        assert_eq!(corners[0], (8.0, 0.0));
+        assert_eq!(corners[2], (248.0, 160.0));
+        assert!(cpu.bus.lcd.background_map(3).is_none());
+    }
+
+    #[test]
+    fn lcd_rgba_output() {
+        const DISPCNT: u32 = 0x04000000;
+        const VRAM: u32 = 0x06000000;
+        const RED: u16 = 0x001F;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        assert!(cpu.bus.lcd.get_buffer_rgba8888().is_none());
+
+        // The first frame is already partly drawn, so start from the next one.
+        cpu.bus.write_halfword_address_debug(0x0403, DISPCNT);
+        render_frame(&mut cpu);
+        cpu.bus.write_halfword_address_debug(RED, VRAM);
+        render_frame(&mut cpu);
+
+        // Enabling it converts the frame already shown, without waiting for the next one.
+        cpu.bus.lcd.set_rgba_output(true);
+        let rgba = cpu.bus.lcd.get_buffer_rgba8888().unwrap();
+        assert_eq!(rgba.len(), Lcd::LCD_WIDTH * Lcd::LCD_HEIGHT * 4);
+        assert_eq!(rgba[..8], [0xFF, 0, 0, 0xFF, 0, 0, 0, 0xFF]);
+
+        cpu.bus.write_halfword_address_debug(0, VRAM);
+        cpu.bus.write_halfword_address_debug(RED, VRAM + 2);
+        render_frame(&mut cpu);
+        let rgba = cpu.bus.lcd.get_buffer_rgba8888().unwrap();
+        assert_eq!(rgba[..8], [0, 0, 0, 0xFF, 0xFF, 0, 0, 0xFF]);
+
+        // Changing the color correction applies to the current frame straight away too.
+        cpu.bus.lcd.set_color_correction(ColorCorrection::Agb);
+        let [red, green, blue] = ColorCorrection::Agb.apply(cpu.bus.lcd.get_buffer()[0][1]);
+        let rgba = cpu.bus.lcd.get_buffer_rgba8888().unwrap();
+        assert_eq!(rgba[4..8], [red, green, blue, 0xFF]);
+
+        let expected = cpu
+            .bus
+            .lcd
+            .get_buffer()
+            .iter()
+            .flatten()
+            .flat_map(|pixel| ColorCorrection::Agb.apply(*pixel))
+            .collect::<Vec<_>>();
+        assert_eq!(cpu.bus.lcd.get_buffer_rgb888(), expected);
+
+        cpu.bus.lcd.set_rgba_output(false);
+        assert!(cpu.bus.lcd.get_buffer_rgba8888().is_none());
+    }
+
+    #[test]
+    fn lcd_frame_count() {
+        const DISPCNT: u32 = 0x04000000;
+        const VRAM: u32 = 0x06000000;
+        const RED: u16 = 0x001F;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        assert_eq!(cpu.bus.lcd.frame_count(), 0);
+
+        cpu.bus.write_halfword_address_debug(0x0403, DISPCNT);
+        render_frame(&mut cpu);
+        assert_eq!(cpu.bus.lcd.frame_count(), 1);
+
+        // Lines drawn during a frame don't show up until it's complete.
+        cpu.bus.write_halfword_address_debug(RED, VRAM);
+        while cpu.bus.read_halfword_address_debug(0x04000006) < 80 {
+            cpu.bus.step();
+        }
+        assert_eq!(cpu.bus.lcd.frame_count(), 1);
+        assert_eq!(cpu.bus.lcd.get_buffer()[0][0].to_int(), 0);
+
+        render_frame(&mut cpu);
+        assert_eq!(cpu.bus.lcd.frame_count(), 2);
+        assert_eq!(cpu.bus.lcd.get_buffer()[0][0].to_int(), RED);
+    }
+
+    #[test]
+    fn audio_output() {
+        const SAMPLE_RATE: u32 = 32_768;
+        // A frame is 280896 cycles.
+        const SAMPLES_PER_FRAME: usize = 549;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        render_frame(&mut cpu);
+
+        let audio_buffer = cpu.enable_audio_output(SAMPLE_RATE, SAMPLES_PER_FRAME * 2);
+        assert!(audio_buffer.is_empty());
+
+        render_frame(&mut cpu);
+        assert!((SAMPLES_PER_FRAME - 1..=SAMPLES_PER_FRAME).contains(&audio_buffer.len()));
+
+        // Sound is off, so it's silence.
+        let sample = audio_buffer.pop().unwrap();
+        assert_eq!(sample, [0.0; 2]);
+
+        // Once full, new samples are dropped.
+        render_frame(&mut cpu);
+        render_frame(&mut cpu);
+        assert_eq!(audio_buffer.len(), audio_buffer.capacity());
+        assert_eq!(audio_buffer.pop(), Some([0.0; 2]));
+        audio_buffer.push([0.25, -0.5]);
+        audio_buffer.push([1.0, 1.0]);
+        while audio_buffer.len() > 1 {
+            audio_buffer.pop();
+        }
+        assert_eq!(audio_buffer.pop(), Some([0.25, -0.5]));
+
+        cpu.disable_audio_output();
+        while audio_buffer.pop().is_some() {}
+        render_frame(&mut cpu);
+        assert!(audio_buffer.is_empty());
+    }
+
+    #[test]
+    fn little_endian_bytes() {
+        let value = 0x12345678u32;
+        let mut memory = [0u8; 4];
+        memory.write_u32_le(value, 0);
+
+        // DataAccess picks out bytes and halfwords arithmetically, so its indices have to line up
+        // with where they are in memory.
+        for i in 0..4 {
+            let byte: u8 = value.get_data(i);
+            assert_eq!(memory[i as usize], byte);
+        }
+        for i in 0..2 {
+            let hword: u16 = value.get_data(i);
+            assert_eq!(memory.read_u16_le(2 * i as usize), hword);
+        }
+
+        // And the same through the bus, for every byte array backed region.
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+        for address in [0x02000010, 0x03000010, 0x06000010] {
+            cpu.bus.write_word_address_debug(value, address);
+            cpu.bus.write_halfword_address_debug(0xABCD, address + 6);
+            for (i, byte) in [0x78, 0x56, 0x34, 0x12].into_iter().enumerate() {
+                assert_eq!(cpu.bus.read_byte_address_debug(address + i as u32), byte);
+            }
+            assert_eq!(cpu.bus.read_halfword_address_debug(address + 2), 0x1234);
+            assert_eq!(cpu.bus.read_byte_address_debug(address + 6), 0xCD);
+        }
+    }
+
+    #[test]
+    fn frame_hash_history() {
+        // b .
+        let mut cpu = build_thumb_test_cpu(&[0xE7FE], &[]);
+        for _ in 0..10 {
+            render_frame(&mut cpu);
+        }
+
+        let history = cpu.frame_hash_history(20);
+        assert_eq!(history.len(), cpu.bus.lcd.frame_count() as usize);
+        assert_eq!(history.last(), Some(&calculate_lcd_checksum(&cpu)));
+        assert_eq!(cpu.frame_hash_history(3).len(), 3);
+        assert!(cpu.is_screen_frozen(5));
+
+        // Showing the backdrop and changing its color changes the next frame.
+        cpu.bus.write_halfword_address_debug(0, 0x04000000);
+        cpu.bus.write_halfword_address_debug(0x001F, 0x05000000);
+        render_frame(&mut cpu);
+        render_frame(&mut cpu);
+        assert!(!cpu.is_screen_frozen(5));
+        assert_eq!(cpu.bus.lcd.unchanged_frames(), 1);
+
+        let history = cpu.frame_hash_history(3);
+        assert_ne!(history[0], history[1]);
+        assert_eq!(history[1], history[2]);
     }
 }
@@ -0,0 +1,140 @@
+use std::fmt::Display;
+
+use crate::{Bus, StateDiff};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchWidth {
+    #[default]
+    Byte,
+    Halfword,
+    Word,
+}
+
+impl SearchWidth {
+    pub const ALL: [SearchWidth; 3] = [Self::Byte, Self::Halfword, Self::Word];
+
+    pub fn size(self) -> u32 {
+        match self {
+            Self::Byte => 1,
+            Self::Halfword => 2,
+            Self::Word => 4,
+        }
+    }
+
+    fn read(self, bus: &Bus, address: u32) -> u32 {
+        match self {
+            Self::Byte => u32::from(bus.read_byte_address_debug(address)),
+            Self::Halfword => u32::from(bus.read_halfword_address_debug(address)),
+            Self::Word => bus.read_word_address_debug(address),
+        }
+    }
+}
+
+impl Display for SearchWidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Byte => f.write_str("8 bit"),
+            Self::Halfword => f.write_str("16 bit"),
+            Self::Word => f.write_str("32 bit"),
+        }
+    }
+}
+
+// How a candidate's current value has to relate to a known value, or to its value at the
+// previous snapshot, to stay in the search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchFilter {
+    EqualTo(u32),
+    GreaterThan(u32),
+    LessThan(u32),
+    Increased,
+    Decreased,
+    ChangedBy(i64), // exactly this much, negative for decreases
+    Changed,
+    Unchanged,
+}
+
+impl SearchFilter {
+    fn matches(self, previous: u32, current: u32) -> bool {
+        match self {
+            Self::EqualTo(value) => current == value,
+            Self::GreaterThan(value) => current > value,
+            Self::LessThan(value) => current < value,
+            Self::Increased => current > previous,
+            Self::Decreased => current < previous,
+            Self::ChangedBy(delta) => i64::from(current) - i64::from(previous) == delta,
+            Self::Changed => current != previous,
+            Self::Unchanged => current == previous,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchCandidate {
+    pub address: u32,
+    pub value: u32,    // at the most recent snapshot
+    pub previous: u32, // at the snapshot before that, the same as `value` to begin with
+}
+
+// Narrows down where a game keeps some value (lives, money, a timer, ...) by repeatedly
+// snapshotting work RAM and keeping only the addresses whose values changed in the expected way,
+// the first step to building a cheat for it. Values are unsigned and aligned to their width.
+#[derive(Clone, Debug)]
+pub struct MemorySearch {
+    width: SearchWidth,
+    candidates: Vec<SearchCandidate>,
+    snapshots: u32,
+}
+
+impl MemorySearch {
+    // Starts with every EWRAM and IWRAM address as a candidate.
+    pub fn new(bus: &Bus, width: SearchWidth) -> Self {
+        let candidates = StateDiff::WRAM_RANGES
+            .into_iter()
+            .flat_map(|range| range.step_by(width.size() as usize))
+            .map(|address| {
+                let value = width.read(bus, address);
+                SearchCandidate {
+                    address,
+                    value,
+                    previous: value,
+                }
+            })
+            .collect();
+
+        Self {
+            width,
+            candidates,
+            snapshots: 1,
+        }
+    }
+
+    pub fn width(&self) -> SearchWidth {
+        self.width
+    }
+
+    pub fn candidates(&self) -> &[SearchCandidate] {
+        &self.candidates
+    }
+
+    // Including the one taken when the search started.
+    pub fn snapshots(&self) -> u32 {
+        self.snapshots
+    }
+
+    // Takes a new snapshot, dropping every candidate whose value doesn't pass `filter`.
+    pub fn filter(&mut self, bus: &Bus, filter: SearchFilter) {
+        let width = self.width;
+        self.candidates.retain_mut(|candidate| {
+            let current = width.read(bus, candidate.address);
+            candidate.previous = std::mem::replace(&mut candidate.value, current);
+            filter.matches(candidate.previous, current)
+        });
+        self.snapshots += 1;
+    }
+
+    pub fn remove(&mut self, address: u32) {
+        self.candidates
+            .retain(|candidate| candidate.address != address);
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{ensure, Result};
+
+use crate::{Cpu, Lcd, LinkMessage, LinkTransport, Rgb555};
+
+// Messages waiting to be picked up by each player, plus the data of a transfer the parent has
+// started but which hasn't been handed out yet.
+#[derive(Default)]
+struct LinkHub {
+    inboxes: [VecDeque<LinkMessage>; MultiSystem::MAX_PLAYERS],
+    started_transfer: Option<u16>,
+}
+
+// A single player's connection to the hub.
+struct HubTransport {
+    player_id: usize,
+    hub: Arc<Mutex<LinkHub>>,
+}
+
+impl LinkTransport for HubTransport {
+    fn is_parent(&self) -> bool {
+        self.player_id == 0
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn send(&mut self, message: LinkMessage) {
+        // Children are never asked for a reply, since the hub reads their data directly.
+        if let (true, LinkMessage::Transfer(data)) = (self.is_parent(), message) {
+            self.hub.lock().unwrap().started_transfer = Some(data as u16);
+        }
+    }
+
+    fn poll(&mut self) -> Option<LinkMessage> {
+        self.hub.lock().unwrap().inboxes[self.player_id].pop_front()
+    }
+}
+
+// Two to four systems connected by a multiplayer link cable, all running in a single process. The
+// first system is the parent. Systems are kept in lockstep, so that none of them ever gets more
+// than a single instruction ahead of the others.
+pub struct MultiSystem {
+    cpus: Vec<Cpu>,
+    hub: Arc<Mutex<LinkHub>>,
+}
+
+impl MultiSystem {
+    pub const MAX_PLAYERS: usize = 4;
+
+    pub fn new(mut cpus: Vec<Cpu>) -> Result<Self> {
+        ensure!(
+            (2..=Self::MAX_PLAYERS).contains(&cpus.len()),
+            "multiplayer link needs 2 to {} players, got {}",
+            Self::MAX_PLAYERS,
+            cpus.len()
+        );
+
+        let hub = Arc::new(Mutex::new(LinkHub::default()));
+        for (player_id, cpu) in cpus.iter_mut().enumerate() {
+            cpu.set_link_transport(Some(Arc::new(Mutex::new(HubTransport {
+                player_id,
+                hub: Arc::clone(&hub),
+            }))));
+        }
+
+        Ok(Self { cpus, hub })
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.cpus.len()
+    }
+
+    pub fn cpu(&self, player_id: usize) -> &Cpu {
+        &self.cpus[player_id]
+    }
+
+    pub fn cpu_mut(&mut self, player_id: usize) -> &mut Cpu {
+        &mut self.cpus[player_id]
+    }
+
+    // Disconnects all systems from each other, and hands them back.
+    pub fn into_cpus(mut self) -> Vec<Cpu> {
+        for cpu in &mut self.cpus {
+            cpu.set_link_transport(None);
+        }
+
+        self.cpus
+    }
+
+    pub fn lcd_buffers(&self) -> Vec<&[[Rgb555; Lcd::LCD_WIDTH]; Lcd::LCD_HEIGHT]> {
+        self.cpus
+            .iter()
+            .map(|cpu| cpu.bus.lcd.get_buffer())
+            .collect()
+    }
+
+    // Runs every system for at least `cycles` cycles.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let targets = self
+            .cpus
+            .iter()
+            .map(|cpu| cpu.bus.cycle_count() + cycles)
+            .collect::<Vec<_>>();
+
+        loop {
+            // Always step whichever system is furthest behind.
+            let next = self
+                .cpus
+                .iter()
+                .enumerate()
+                .filter(|(player_id, cpu)| cpu.bus.cycle_count() < targets[*player_id])
+                .min_by_key(|(_, cpu)| cpu.bus.cycle_count())
+                .map(|(player_id, _)| player_id);
+
+            let Some(player_id) = next else {
+                break;
+            };
+
+            self.cpus[player_id].fetch_decode_execute();
+            self.distribute_transfer();
+        }
+    }
+
+    // Runs every system for a single frame's worth of cycles at the given frame rate.
+    pub fn run_frame(&mut self, frames_per_second: u32) {
+        let cycles = self.cpus[0].clock().cycles_per_frame(frames_per_second);
+        self.run_cycles(cycles);
+    }
+
+    // Once the parent starts a transfer, hands everyone's data to every player.
+    fn distribute_transfer(&mut self) {
+        let mut hub = self.hub.lock().unwrap();
+
+        let Some(parent_data) = hub.started_transfer.take() else {
+            return;
+        };
+
+        let mut data = [0xFFFF; Self::MAX_PLAYERS];
+        data[0] = parent_data;
+        for (player_id, cpu) in self.cpus.iter().enumerate().skip(1) {
+            if let Some(child_data) = cpu.bus.serial.multiplayer_send_data() {
+                data[player_id] = child_data;
+            }
+        }
+
+        for inbox in &mut hub.inboxes[..self.cpus.len()] {
+            inbox.push_back(LinkMessage::MultiplayerData(data));
+        }
+    }
+}
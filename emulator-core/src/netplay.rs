@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use anyhow::{anyhow, Result};
+
+use crate::{calculate_state_hash, Cpu, KeysState};
+
+// Keeps two emulators running the same game in lockstep, by having both apply the combined input
+// of both players on exactly the same frames. Local input is delayed by a fixed number of frames
+// to give it time to reach the peer, and a frame only runs once the peer's input for it has
+// arrived. Every so often both sides hash their state, so a desync is noticed when it happens
+// rather than when the games visibly diverge.
+//
+// Nothing here touches the network. Frontends send `packet` to the peer every frame over any
+// unreliable transport, and hand whatever arrives to `receive`. Packets repeat every input the
+// peer hasn't acknowledged yet, so losing some of them only costs latency.
+//
+// A packet is the magic "GBANET01", then as little-endian integers: the next frame of the
+// receiver's input the sender still needs (u64), the frame of the first input (u64), the number
+// of inputs (u16), each input (u16, `KeysState::bits`), the number of state hashes (u8) and
+// each hash as its frame (u64) followed by the hash (u64).
+#[derive(Clone, Debug)]
+pub struct Lockstep {
+    input_delay: u64,
+    frame: u64, // the next frame to run
+    // Inputs from `first_local_frame` on, kept until they have both run and been acknowledged.
+    local_inputs: VecDeque<KeysState>,
+    first_local_frame: u64,
+    remote_inputs: BTreeMap<u64, KeysState>,
+    local_hashes: BTreeMap<u64, u64>,
+    remote_hashes: BTreeMap<u64, u64>,
+    desync_frame: Option<u64>,
+}
+
+impl Lockstep {
+    // How often, in frames, state hashes are compared.
+    pub const HASH_INTERVAL: u64 = 60;
+
+    const PACKET_MAGIC: &'static [u8; 8] = b"GBANET01";
+    // Keeps packets well below common MTUs even when the peer has fallen far behind.
+    const MAX_PACKET_INPUTS: usize = 256;
+    // Hashes are resent until compared, but only the latest few, in case the peer stopped.
+    const MAX_PACKET_HASHES: usize = 4;
+
+    // Both sides have to use the same delay. The frames before the first delayed input run with
+    // no keys held.
+    pub fn new(input_delay: u32) -> Self {
+        let input_delay = u64::from(input_delay);
+
+        Self {
+            input_delay,
+            frame: 0,
+            local_inputs: (0..input_delay).map(|_| KeysState::default()).collect(),
+            first_local_frame: 0,
+            remote_inputs: (0..input_delay)
+                .map(|frame| (frame, KeysState::default()))
+                .collect(),
+            local_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            desync_frame: None,
+        }
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn input_delay(&self) -> u64 {
+        self.input_delay
+    }
+
+    // The first frame whose state hashes didn't match, if any.
+    pub fn desync_frame(&self) -> Option<u64> {
+        self.desync_frame
+    }
+
+    // Queues the local keys for `input_delay` frames from now. Only one input is queued per frame,
+    // so calling this again before the next frame runs does nothing.
+    pub fn set_local_input(&mut self, keys: KeysState) {
+        let next_local_frame = self.first_local_frame + self.local_inputs.len() as u64;
+        if next_local_frame <= self.frame + self.input_delay {
+            self.local_inputs.push_back(keys);
+        }
+    }
+
+    // The combined keys to run the next frame with, or `None` while still waiting on the peer.
+    // Once this returns keys the frame counts as run, so the caller must run exactly one frame
+    // with them.
+    pub fn begin_frame(&mut self, cpu: &Cpu) -> Option<KeysState> {
+        let local_keys = *self
+            .local_inputs
+            .get((self.frame - self.first_local_frame) as usize)?;
+        let remote_keys = self.remote_inputs.remove(&self.frame)?;
+
+        if self.frame.is_multiple_of(Self::HASH_INTERVAL) {
+            self.local_hashes
+                .insert(self.frame, calculate_state_hash(cpu));
+            self.compare_hashes();
+        }
+
+        self.frame += 1;
+
+        Some(local_keys | remote_keys)
+    }
+
+    pub fn packet(&self) -> Vec<u8> {
+        let mut packet = Self::PACKET_MAGIC.to_vec();
+        packet.extend_from_slice(&self.next_remote_frame().to_le_bytes());
+        packet.extend_from_slice(&self.first_local_frame.to_le_bytes());
+
+        let inputs = self.local_inputs.iter().take(Self::MAX_PACKET_INPUTS);
+        packet.extend_from_slice(&(inputs.len() as u16).to_le_bytes());
+        for keys in inputs {
+            packet.extend_from_slice(&keys.bits().to_le_bytes());
+        }
+
+        let hashes = self.local_hashes.iter().rev().take(Self::MAX_PACKET_HASHES);
+        packet.push(hashes.len() as u8);
+        for (frame, hash) in hashes {
+            packet.extend_from_slice(&frame.to_le_bytes());
+            packet.extend_from_slice(&hash.to_le_bytes());
+        }
+
+        packet
+    }
+
+    pub fn receive(&mut self, packet: &[u8]) -> Result<()> {
+        let mut reader = PacketReader(packet);
+        if reader.take(Self::PACKET_MAGIC.len())? != Self::PACKET_MAGIC {
+            return Err(anyhow!("not a netplay packet"));
+        }
+
+        // Local inputs the peer has, and that have run here, are never needed again.
+        let acknowledged_frame = reader.u64()?.min(self.frame);
+        while self.first_local_frame < acknowledged_frame && !self.local_inputs.is_empty() {
+            self.local_inputs.pop_front();
+            self.first_local_frame += 1;
+        }
+
+        let first_frame = reader.u64()?;
+        let input_count = reader.u16()?;
+        let end_frame = first_frame
+            .checked_add(u64::from(input_count))
+            .ok_or_else(|| anyhow!("netplay packet inputs run past the last frame"))?;
+        // A well-behaved peer can't be further ahead than this, so anything past it is dropped
+        // rather than kept around forever.
+        let last_accepted_frame = self.frame + self.input_delay + Self::MAX_PACKET_INPUTS as u64;
+        for frame in first_frame..end_frame {
+            let keys = KeysState::from_bits(reader.u16()?);
+            if (self.frame..=last_accepted_frame).contains(&frame) {
+                self.remote_inputs.entry(frame).or_insert(keys);
+            }
+        }
+
+        for _ in 0..reader.u8()? {
+            let frame = reader.u64()?;
+            let hash = reader.u64()?;
+            self.remote_hashes.entry(frame).or_insert(hash);
+        }
+        self.compare_hashes();
+
+        Ok(())
+    }
+
+    fn next_remote_frame(&self) -> u64 {
+        (self.frame..)
+            .find(|frame| !self.remote_inputs.contains_key(frame))
+            .unwrap()
+    }
+
+    fn compare_hashes(&mut self) {
+        let compared: Vec<u64> = self
+            .local_hashes
+            .keys()
+            .copied()
+            .filter(|frame| self.remote_hashes.contains_key(frame))
+            .collect();
+
+        for frame in compared {
+            let local_hash = self.local_hashes.remove(&frame).unwrap();
+            let remote_hash = self.remote_hashes.remove(&frame).unwrap();
+            if local_hash != remote_hash && self.desync_frame.is_none() {
+                log::error!(
+                    "netplay desync at frame {frame}: state hash {local_hash:016X} here, \
+                    {remote_hash:016X} on the peer"
+                );
+                self.desync_frame = Some(frame);
+            }
+        }
+
+        // A peer that stopped sending hashes shouldn't make us keep ours forever.
+        while self.local_hashes.len() > Self::MAX_PACKET_HASHES {
+            self.local_hashes.pop_first();
+        }
+        while self.remote_hashes.len() > Self::MAX_PACKET_HASHES {
+            self.remote_hashes.pop_first();
+        }
+    }
+}
+
+struct PacketReader<'a>(&'a [u8]);
+
+impl<'a> PacketReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(anyhow!("truncated netplay packet"));
+        }
+
+        let (data, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(data)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_packet(first_frame: u64, inputs: &[u16]) -> Vec<u8> {
+        let mut packet = Lockstep::PACKET_MAGIC.to_vec();
+        packet.extend_from_slice(&0u64.to_le_bytes());
+        packet.extend_from_slice(&first_frame.to_le_bytes());
+        packet.extend_from_slice(&(inputs.len() as u16).to_le_bytes());
+        for input in inputs {
+            packet.extend_from_slice(&input.to_le_bytes());
+        }
+        packet.push(0);
+        packet
+    }
+
+    #[test]
+    fn overflowing_input_frames() {
+        let mut session = Lockstep::new(2);
+
+        assert!(session.receive(&input_packet(u64::MAX, &[0, 0])).is_err());
+        assert_eq!(session.remote_inputs.len(), 2);
+    }
+
+    #[test]
+    fn far_future_inputs() {
+        let mut session = Lockstep::new(2);
+        let last_accepted_frame = 2 + Lockstep::MAX_PACKET_INPUTS as u64;
+
+        session
+            .receive(&input_packet(last_accepted_frame, &[1, 1]))
+            .unwrap();
+        session.receive(&input_packet(1 << 40, &[1; 16])).unwrap();
+
+        assert_eq!(
+            session.remote_inputs.keys().copied().collect::<Vec<_>>(),
+            vec![0, 1, last_accepted_frame]
+        );
+    }
+}
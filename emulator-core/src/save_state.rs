@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Result};
+use serde_cbor::Value;
+
+use crate::le_bytes::LittleEndianBytes;
+use crate::{Cpu, Lcd};
+
+pub type ChunkTag = [u8; 4];
+
+const MAGIC: [u8; 4] = *b"GBAS";
+
+const CPU_CHUNK: ChunkTag = *b"CPU ";
+const BUS_CHUNK: ChunkTag = *b"BUS ";
+// Bus fields split out into chunks of their own. Whatever is left of the bus goes in "BUS ".
+const SUBSYSTEM_CHUNKS: [(ChunkTag, &str); 6] = [
+    (*b"LCD ", "lcd"),
+    (*b"APU ", "apu"),
+    (*b"TIMR", "timers"),
+    (*b"KEYP", "keypad"),
+    (*b"SIO ", "serial"),
+    (*b"CART", "cartridge"),
+];
+// A PNG of the screen at the time, see `SaveState::thumbnail`.
+const THUMBNAIL_CHUNK: ChunkTag = *b"THMB";
+
+// Upgrades a serialized `Cpu` from one version to the next, indexed by the version it upgrades
+// from, for changes that serde defaults can't paper over, like renamed or reinterpreted fields.
+type Migration = fn(&mut BTreeMap<Value, Value>);
+
+const MIGRATIONS: [Migration; SaveState::VERSION as usize] = [migrate_headerless];
+
+// Headerless states hold the same fields, only the container changed.
+fn migrate_headerless(_cpu: &mut BTreeMap<Value, Value>) {}
+
+// A save state file: a magic and version header, followed by chunks of CBOR, one per subsystem,
+// each as a tag, a little-endian u32 length and the data.
+//
+// Loading is tolerant. Unknown chunks are skipped, so states from newer builds load as far as
+// they're understood, and missing fields and chunks take their serde defaults where they have
+// one. Files from before the header existed, a bare serialized `Cpu`, load as version 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaveState {
+    version: u32,
+    chunks: Vec<(ChunkTag, Vec<u8>)>, // in file order
+}
+
+impl SaveState {
+    pub const VERSION: u32 = 1;
+
+    // Screens are downscaled by this much in both directions for thumbnails.
+    pub const THUMBNAIL_SCALE: usize = 2;
+
+    pub fn from_cpu(cpu: &Cpu) -> Result<Self> {
+        let mut state = Self::from_value(Self::VERSION, serde_cbor::value::to_value(cpu)?)?;
+        state.set_chunk(
+            THUMBNAIL_CHUNK,
+            Thumbnail::from_lcd(&cpu.bus.lcd).encode_png()?,
+        );
+        Ok(state)
+    }
+
+    // Only splits the file into chunks, so it's cheap to peek at one without loading the rest.
+    pub fn read(mut reader: impl Read) -> Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let Some(data) = data.strip_prefix(&MAGIC) else {
+            return Self::from_value(0, serde_cbor::from_slice(&data)?);
+        };
+
+        let truncated = || anyhow!("truncated save state");
+        let version = data.get(..4).ok_or_else(truncated)?.read_u32_le(0);
+        if version > Self::VERSION {
+            log::warn!(
+                "save state is from a newer version ({version}, expected {}), loading what's understood",
+                Self::VERSION
+            );
+        }
+
+        let mut chunks = Vec::new();
+        let mut remaining = &data[4..];
+        while !remaining.is_empty() {
+            let header = remaining.get(..8).ok_or_else(truncated)?;
+            let tag = [header[0], header[1], header[2], header[3]];
+            let length = header.read_u32_le(4) as usize;
+            let payload = remaining.get(8..8 + length).ok_or_else(truncated)?.to_vec();
+
+            chunks.push((tag, payload));
+            remaining = &remaining[8 + length..];
+        }
+
+        Ok(Self { version, chunks })
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        for (tag, payload) in &self.chunks {
+            writer.write_all(tag)?;
+            writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+            writer.write_all(payload)?;
+        }
+
+        Ok(())
+    }
+
+    // The emulated state, ready for `Cpu::load_state`.
+    pub fn to_cpu(&self) -> Result<Cpu> {
+        for (tag, _) in &self.chunks {
+            let known = [CPU_CHUNK, BUS_CHUNK, THUMBNAIL_CHUNK].contains(tag)
+                || SUBSYSTEM_CHUNKS
+                    .iter()
+                    .any(|(known_tag, _)| known_tag == tag);
+            if !known {
+                log::debug!("skipping unknown save state chunk \"{}\"", tag_name(tag));
+            }
+        }
+
+        let mut bus = self.chunk_fields(BUS_CHUNK)?;
+        for (tag, field) in SUBSYSTEM_CHUNKS {
+            if let Some(payload) = self.chunk(tag) {
+                bus.insert(
+                    Value::Text(field.to_string()),
+                    serde_cbor::from_slice(payload)?,
+                );
+            }
+        }
+
+        let mut cpu = self.chunk_fields(CPU_CHUNK)?;
+        cpu.insert(Value::Text("bus".to_string()), Value::Map(bus));
+
+        for migration in &MIGRATIONS[self.version.min(Self::VERSION) as usize..] {
+            migration(&mut cpu);
+        }
+
+        Ok(serde_cbor::value::from_value(Value::Map(cpu))?)
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    // Decodes only the thumbnail chunk, for previews in a save state picker. States written
+    // before thumbnails existed have none.
+    pub fn thumbnail(&self) -> Result<Option<Thumbnail>> {
+        self.chunk(THUMBNAIL_CHUNK)
+            .map(Thumbnail::decode_png)
+            .transpose()
+    }
+
+    pub fn chunk(&self, tag: ChunkTag) -> Option<&[u8]> {
+        self.chunks
+            .iter()
+            .find(|(chunk_tag, _)| *chunk_tag == tag)
+            .map(|(_, payload)| payload.as_slice())
+    }
+
+    // Replaces the chunk tagged `tag`, or adds it at the end. Frontends can keep their own data
+    // in chunks of their own, which the core skips when loading.
+    pub fn set_chunk(&mut self, tag: ChunkTag, payload: Vec<u8>) {
+        match self
+            .chunks
+            .iter_mut()
+            .find(|(chunk_tag, _)| *chunk_tag == tag)
+        {
+            Some((_, existing)) => *existing = payload,
+            None => self.chunks.push((tag, payload)),
+        }
+    }
+
+    pub fn remove_chunk(&mut self, tag: ChunkTag) {
+        self.chunks.retain(|(chunk_tag, _)| *chunk_tag != tag);
+    }
+
+    fn from_value(version: u32, cpu: Value) -> Result<Self> {
+        let mut cpu = into_map(cpu)?;
+        let mut bus = into_map(
+            cpu.remove(&Value::Text("bus".to_string()))
+                .ok_or_else(|| anyhow!("save state has no bus"))?,
+        )?;
+
+        let mut subsystem_chunks = Vec::new();
+        for (tag, field) in SUBSYSTEM_CHUNKS {
+            if let Some(value) = bus.remove(&Value::Text(field.to_string())) {
+                subsystem_chunks.push((tag, serde_cbor::to_vec(&value)?));
+            }
+        }
+
+        let mut chunks = vec![
+            (CPU_CHUNK, serde_cbor::to_vec(&Value::Map(cpu))?),
+            (BUS_CHUNK, serde_cbor::to_vec(&Value::Map(bus))?),
+        ];
+        chunks.extend(subsystem_chunks);
+
+        Ok(Self { version, chunks })
+    }
+
+    // Fields of a chunk holding a struct, none if it's missing.
+    fn chunk_fields(&self, tag: ChunkTag) -> Result<BTreeMap<Value, Value>> {
+        match self.chunk(tag) {
+            Some(payload) => into_map(serde_cbor::from_slice(payload)?),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+}
+
+fn into_map(value: Value) -> Result<BTreeMap<Value, Value>> {
+    match value {
+        Value::Map(fields) => Ok(fields),
+        _ => Err(anyhow!("malformed save state")),
+    }
+}
+
+fn tag_name(tag: &ChunkTag) -> String {
+    String::from_utf8_lossy(tag).into_owned()
+}
+
+// A downscaled screen, row-major with 3 bytes per pixel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+impl Thumbnail {
+    // Each pixel averages the block of screen pixels it covers.
+    pub fn from_lcd(lcd: &Lcd) -> Self {
+        const SCALE: usize = SaveState::THUMBNAIL_SCALE;
+
+        let width = Lcd::LCD_WIDTH / SCALE;
+        let height = Lcd::LCD_HEIGHT / SCALE;
+        let buffer = lcd.get_buffer();
+
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sums = [0; 3];
+                for row in &buffer[y * SCALE..(y + 1) * SCALE] {
+                    for pixel in &row[x * SCALE..(x + 1) * SCALE] {
+                        for (sum, channel) in sums.iter_mut().zip(pixel.to_rgb888()) {
+                            *sum += usize::from(channel);
+                        }
+                    }
+                }
+                rgb.extend(sums.map(|sum| (sum / (SCALE * SCALE)) as u8));
+            }
+        }
+
+        Self { width, height, rgb }
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>> {
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut png_writer = encoder.write_header()?;
+        png_writer.write_image_data(&self.rgb)?;
+        png_writer.finish()?;
+
+        Ok(png_data)
+    }
+
+    fn decode_png(png_data: &[u8]) -> Result<Self> {
+        let mut reader = png::Decoder::new(png_data).read_info()?;
+        let mut rgb = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut rgb)?;
+        if info.color_type != png::ColorType::Rgb || info.bit_depth != png::BitDepth::Eight {
+            return Err(anyhow!("unsupported save state thumbnail format"));
+        }
+        rgb.truncate(info.buffer_size());
+
+        Ok(Self {
+            width: info.width as usize,
+            height: info.height as usize,
+            rgb,
+        })
+    }
+}
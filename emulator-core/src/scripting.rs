@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Result};
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, AST};
+
+use crate::{AccessKind, AccessWatch, Cpu, Key, StepEvent};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Default)]
+struct Hooks {
+    frame: Vec<FnPtr>,
+    execute: HashMap<u32, Vec<FnPtr>>,
+    access: Vec<(AccessKind, u32, FnPtr)>,
+    access_changed: bool, // the bus access watch needs rebuilding
+}
+
+// A Rhai script driving the emulator. Loading runs the script once, which registers callbacks
+// with the functions below; those then run as the emulator is stepped through `Script::step` or
+// `Script::run_frame`:
+//
+//   on_frame(callback)             after each frame completes
+//   on_execute(address, callback)  before the instruction at address, called with the address
+//   on_read(address, callback)     after an instruction reads address, called with the address
+//   on_write(address, callback)    after an instruction writes address, called with the address
+//
+// Callbacks can use read8/16/32(address), write8/16/32(address, value), press(key),
+// release(key), is_pressed(key) and frame_count(), where keys are named as in `Key`'s
+// `FromStr`. Memory is accessed with the debug accessors, so it has no side effects and does
+// not trigger access callbacks.
+//
+// Registered functions have to be 'static, so the script keeps the emulator behind a handle
+// they share, and frontends get at it through `cpu` between steps. It's only locked for the
+// length of a single access, never while a callback runs.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    hooks: Arc<Mutex<Hooks>>,
+    cpu: Arc<Mutex<Cpu>>,
+}
+
+impl Script {
+    pub fn load(path: &Path, cpu: Cpu) -> Result<Self> {
+        Self::new(&std::fs::read_to_string(path)?, cpu)
+    }
+
+    pub fn new(source: &str, cpu: Cpu) -> Result<Self> {
+        let hooks = Arc::new(Mutex::new(Hooks::default()));
+        let cpu = Arc::new(Mutex::new(cpu));
+
+        let mut engine = Engine::new();
+        engine.on_print(|message| log::info!("script: {message}"));
+        engine.on_debug(|message, _, position| log::debug!("script {position}: {message}"));
+        register_hooks(&mut engine, &hooks);
+        register_memory(&mut engine, &cpu);
+        register_input(&mut engine, &cpu);
+
+        let ast = engine.compile(source).map_err(|e| anyhow!("{e}"))?;
+        engine.run_ast(&ast).map_err(|e| anyhow!("{e}"))?;
+
+        Ok(Self {
+            engine,
+            ast,
+            hooks,
+            cpu,
+        })
+    }
+
+    pub fn cpu(&self) -> MutexGuard<'_, Cpu> {
+        self.cpu.lock().unwrap()
+    }
+
+    pub fn into_cpu(self) -> Cpu {
+        // The engine's functions hold the only other references to the handle.
+        drop(self.engine);
+        match Arc::try_unwrap(self.cpu) {
+            Ok(cpu) => cpu.into_inner().unwrap(),
+            Err(_) => unreachable!("the script's functions outlived its engine"),
+        }
+    }
+
+    // Runs one instruction like `Cpu::fetch_decode_execute`, with callbacks for the address
+    // about to execute run first and callbacks for the accesses it made run after.
+    pub fn step(&mut self) -> Result<Option<StepEvent>> {
+        let executing_pc = {
+            let mut cpu = self.cpu();
+            self.sync_access_watch(&mut cpu);
+            cpu.get_executing_pc()
+        };
+        let execute_hooks = self
+            .hooks
+            .lock()
+            .unwrap()
+            .execute
+            .get(&executing_pc)
+            .cloned();
+        for callback in execute_hooks.into_iter().flatten() {
+            self.call(&callback, Some(executing_pc))?;
+        }
+
+        let (event, hits) = {
+            let mut cpu = self.cpu();
+            let event = cpu.fetch_decode_execute();
+            let hits = cpu
+                .bus
+                .access_watch_mut()
+                .map(AccessWatch::take_hits)
+                .unwrap_or_default();
+            (event, hits)
+        };
+        for hit in hits {
+            let callbacks: Vec<_> = self
+                .hooks
+                .lock()
+                .unwrap()
+                .access
+                .iter()
+                .filter(|(kind, address, _)| *kind == hit.kind && *address == hit.address)
+                .map(|(_, _, callback)| callback.clone())
+                .collect();
+            for callback in callbacks {
+                self.call(&callback, Some(hit.address))?;
+            }
+        }
+
+        if matches!(event, Some(StepEvent::FrameComplete)) {
+            let frame_hooks = self.hooks.lock().unwrap().frame.clone();
+            for callback in frame_hooks {
+                self.call(&callback, None)?;
+            }
+        }
+
+        Ok(event)
+    }
+
+    // Steps until the frame completes, or anything else stops execution.
+    pub fn run_frame(&mut self) -> Result<Option<StepEvent>> {
+        loop {
+            if let Some(event) = self.step()? {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    fn sync_access_watch(&self, cpu: &mut Cpu) {
+        let mut hooks = self.hooks.lock().unwrap();
+        if !std::mem::take(&mut hooks.access_changed) {
+            return;
+        }
+
+        let mut access_watch = AccessWatch::new();
+        for &(kind, address, _) in &hooks.access {
+            access_watch.watch(kind, address);
+        }
+        cpu.bus.set_access_watch(Some(access_watch));
+    }
+
+    fn call(&self, callback: &FnPtr, address: Option<u32>) -> Result<()> {
+        let result = match address {
+            Some(address) => {
+                callback.call::<Dynamic>(&self.engine, &self.ast, (i64::from(address),))
+            }
+            None => callback.call::<Dynamic>(&self.engine, &self.ast, ()),
+        };
+
+        result
+            .map(|_| ())
+            .map_err(|e| anyhow!("script callback {}: {e}", callback.fn_name()))
+    }
+}
+
+fn register_hooks(engine: &mut Engine, hooks: &Arc<Mutex<Hooks>>) {
+    let frame_hooks = Arc::clone(hooks);
+    engine.register_fn("on_frame", move |callback: FnPtr| {
+        frame_hooks.lock().unwrap().frame.push(callback);
+    });
+
+    let execute_hooks = Arc::clone(hooks);
+    engine.register_fn("on_execute", move |address: i64, callback: FnPtr| {
+        execute_hooks
+            .lock()
+            .unwrap()
+            .execute
+            .entry(address as u32)
+            .or_default()
+            .push(callback);
+    });
+
+    for (name, kind) in [
+        ("on_read", AccessKind::Read),
+        ("on_write", AccessKind::Write),
+    ] {
+        let access_hooks = Arc::clone(hooks);
+        engine.register_fn(name, move |address: i64, callback: FnPtr| {
+            let mut hooks = access_hooks.lock().unwrap();
+            hooks.access.push((kind, address as u32, callback));
+            hooks.access_changed = true;
+        });
+    }
+}
+
+// Fails rather than blocking if the emulator is somehow already locked, since nothing would be
+// left to unlock it.
+fn with_cpu<T>(cpu: &Mutex<Cpu>, f: impl FnOnce(&mut Cpu) -> T) -> ScriptResult<T> {
+    match cpu.try_lock() {
+        Ok(mut cpu) => Ok(f(&mut cpu)),
+        Err(_) => Err("the emulator is already in use".into()),
+    }
+}
+
+fn register_memory(engine: &mut Engine, cpu: &Arc<Mutex<Cpu>>) {
+    let shared = Arc::clone(cpu);
+    engine.register_fn("read8", move |address: i64| {
+        with_cpu(&shared, |cpu| {
+            i64::from(cpu.bus.read_byte_address_debug(address as u32))
+        })
+    });
+    let shared = Arc::clone(cpu);
+    engine.register_fn("read16", move |address: i64| {
+        with_cpu(&shared, |cpu| {
+            i64::from(cpu.bus.read_halfword_address_debug(address as u32))
+        })
+    });
+    let shared = Arc::clone(cpu);
+    engine.register_fn("read32", move |address: i64| {
+        with_cpu(&shared, |cpu| {
+            i64::from(cpu.bus.read_word_address_debug(address as u32))
+        })
+    });
+
+    let shared = Arc::clone(cpu);
+    engine.register_fn("write8", move |address: i64, value: i64| {
+        with_cpu(&shared, |cpu| {
+            cpu.bus
+                .write_byte_address_debug(value as u8, address as u32)
+        })
+    });
+    let shared = Arc::clone(cpu);
+    engine.register_fn("write16", move |address: i64, value: i64| {
+        with_cpu(&shared, |cpu| {
+            cpu.bus
+                .write_halfword_address_debug(value as u16, address as u32)
+        })
+    });
+    let shared = Arc::clone(cpu);
+    engine.register_fn("write32", move |address: i64, value: i64| {
+        with_cpu(&shared, |cpu| {
+            cpu.bus
+                .write_word_address_debug(value as u32, address as u32)
+        })
+    });
+
+    let shared = Arc::clone(cpu);
+    engine.register_fn("frame_count", move || {
+        with_cpu(&shared, |cpu| cpu.bus.lcd.frame_count() as i64)
+    });
+}
+
+fn parse_key(name: &str) -> ScriptResult<Key> {
+    name.parse()
+        .map_err(|e: anyhow::Error| e.to_string().into())
+}
+
+fn register_input(engine: &mut Engine, cpu: &Arc<Mutex<Cpu>>) {
+    for (name, pressed) in [("press", true), ("release", false)] {
+        let shared = Arc::clone(cpu);
+        engine.register_fn(name, move |key: &str| {
+            let key = parse_key(key)?;
+            with_cpu(&shared, |cpu| cpu.bus.keypad.set_pressed(key, pressed))
+        });
+    }
+
+    let shared = Arc::clone(cpu);
+    engine.register_fn("is_pressed", move |key: &str| {
+        let key = parse_key(key)?;
+        with_cpu(&shared, |cpu| cpu.bus.keypad.get_state().is_pressed(key))
+    });
+}
@@ -0,0 +1,477 @@
+use std::{
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{BitManipulation, DataAccess, CYCLES_PER_SECOND};
+
+// A single message sent over the link cable. Transfers are started by whichever side drives the
+// clock (the parent in multiplayer mode), and the other side answers each of them with a reply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkMessage {
+    Transfer(u32),
+    Reply(u32),
+    // Every player's data for a multiplayer transfer, with 0xFFFF for missing players. Used by
+    // links with more than two players, which send this to everyone once the parent's transfer
+    // has been answered by all of the children.
+    MultiplayerData([u16; 4]),
+}
+
+// Connects the serial port to another emulator instance. Calls are made from the middle of
+// emulation, so none of them should block.
+pub trait LinkTransport: Send {
+    // Only matters in multiplayer mode, where only the parent may start transfers.
+    fn is_parent(&self) -> bool;
+
+    fn is_connected(&self) -> bool;
+
+    fn send(&mut self, message: LinkMessage);
+
+    fn poll(&mut self) -> Option<LinkMessage>;
+}
+
+pub type LinkTransportHandle = Arc<Mutex<dyn LinkTransport>>;
+
+#[derive(Clone, Copy, Debug)]
+enum SerialMode {
+    Normal8Bit,
+    Normal32Bit,
+    Multiplayer,
+    Uart,
+    GeneralPurpose,
+    JoyBus,
+}
+
+// What a finished transfer received from the cable.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+enum ReceivedData {
+    Normal(u32),
+    Multiplayer([u16; 4]), // every player's data, including our own
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+enum TransferState {
+    #[default]
+    Idle,
+    // We're driving the clock, and are waiting for both the transfer to finish on our end and the
+    // other side to reply.
+    Sending {
+        cycles_remaining: u32,
+        timeout_remaining: u32,
+        received: Option<ReceivedData>,
+    },
+    // Waiting for the other side to start a transfer, in normal mode using the external clock.
+    Receiving,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Serial {
+    data: [u16; 4],   // SIODATA32 or SIOMULTI0-3
+    control: u16,     // SIOCNT
+    send_data: u16,   // SIOMLT_SEND or SIODATA8
+    mode_select: u16, // RCNT
+    state: TransferState,
+    // Set when the start bit is written, and acted on once the whole register has been written.
+    start_requested: bool,
+    // A transfer started by the other side before we were ready to receive it.
+    pending_transfer: Option<u32>,
+    poll_cycles: u32,
+    // Frontend state, so not part of save states.
+    #[serde(skip)]
+    transport: Option<LinkTransportHandle>,
+}
+
+impl Serial {
+    const NORMAL_INTERNAL_CLOCK_BIT_INDEX: usize = 0;
+    const NORMAL_INTERNAL_CLOCK_RATE_BIT_INDEX: usize = 1;
+    const MULTIPLAYER_BAUD_RATE_BIT_RANGE: RangeInclusive<usize> = 0..=1;
+    const MULTIPLAYER_CHILD_BIT_INDEX: usize = 2;
+    const MULTIPLAYER_READY_BIT_INDEX: usize = 3;
+    const MULTIPLAYER_ID_BIT_RANGE: RangeInclusive<usize> = 4..=5;
+    const MULTIPLAYER_ERROR_BIT_INDEX: usize = 6;
+    const START_BIT_INDEX: usize = 7;
+    const MODE_BIT_RANGE: RangeInclusive<usize> = 12..=13;
+    const IRQ_ENABLE_BIT_INDEX: usize = 14;
+
+    const MODE_SELECT_GENERAL_PURPOSE_BIT_INDEX: usize = 15;
+    const MODE_SELECT_JOY_BUS_BIT_INDEX: usize = 14;
+
+    // Start, data and stop bits, for both the parent and a single child.
+    const MULTIPLAYER_TRANSFER_BITS: u32 = 2 * 18;
+
+    // How long we wait for the other side to reply once the transfer itself has finished. Network
+    // latency stretches the transfer out by up to this long, which games just see as a slow
+    // transfer. If nobody replies by then, the transfer completes as if nothing was connected.
+    const REPLY_TIMEOUT_CYCLES: u32 = (CYCLES_PER_SECOND / 10) as u32;
+
+    // How often the transport is checked for incoming messages.
+    const POLL_INTERVAL_CYCLES: u32 = 64;
+
+    // What's read from the data lines when nothing drives them.
+    const DISCONNECTED_DATA: u32 = 0xFFFFFFFF;
+    const DISCONNECTED_MULTIPLAYER_DATA: u16 = 0xFFFF;
+
+    fn get_mode(&self) -> SerialMode {
+        if self
+            .mode_select
+            .get_bit(Self::MODE_SELECT_GENERAL_PURPOSE_BIT_INDEX)
+        {
+            if self
+                .mode_select
+                .get_bit(Self::MODE_SELECT_JOY_BUS_BIT_INDEX)
+            {
+                SerialMode::JoyBus
+            } else {
+                SerialMode::GeneralPurpose
+            }
+        } else {
+            match self.control.get_bit_range(Self::MODE_BIT_RANGE) {
+                0 => SerialMode::Normal8Bit,
+                1 => SerialMode::Normal32Bit,
+                2 => SerialMode::Multiplayer,
+                3 => SerialMode::Uart,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn is_parent(&self) -> bool {
+        match &self.transport {
+            Some(transport) => transport.lock().unwrap().is_parent(),
+            None => true,
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.transport
+            .as_ref()
+            .is_some_and(|transport| transport.lock().unwrap().is_connected())
+    }
+
+    fn send_message(&self, message: LinkMessage) {
+        if let Some(transport) = &self.transport {
+            transport.lock().unwrap().send(message);
+        }
+    }
+
+    fn transfer_cycles(&self) -> u32 {
+        const NORMAL_SLOW_CYCLES_PER_BIT: u32 = (CYCLES_PER_SECOND / 262_144) as u32; // 256KHz
+        const NORMAL_FAST_CYCLES_PER_BIT: u32 = (CYCLES_PER_SECOND / 2_097_152) as u32; // 2MHz
+
+        let normal_cycles_per_bit = if self
+            .control
+            .get_bit(Self::NORMAL_INTERNAL_CLOCK_RATE_BIT_INDEX)
+        {
+            NORMAL_FAST_CYCLES_PER_BIT
+        } else {
+            NORMAL_SLOW_CYCLES_PER_BIT
+        };
+
+        match self.get_mode() {
+            SerialMode::Normal8Bit => 8 * normal_cycles_per_bit,
+            SerialMode::Normal32Bit => 32 * normal_cycles_per_bit,
+            _ => {
+                let baud_rate = match self
+                    .control
+                    .get_bit_range(Self::MULTIPLAYER_BAUD_RATE_BIT_RANGE)
+                {
+                    0 => 9600,
+                    1 => 38400,
+                    2 => 57600,
+                    3 => 115200,
+                    _ => unreachable!(),
+                };
+
+                Self::MULTIPLAYER_TRANSFER_BITS * (CYCLES_PER_SECOND as u32 / baud_rate)
+            }
+        }
+    }
+
+    // The data we put on the cable for the current transfer.
+    fn outgoing_data(&self) -> u32 {
+        match self.get_mode() {
+            SerialMode::Normal8Bit => u32::from(self.send_data & 0xFF),
+            SerialMode::Normal32Bit => u32::from(self.data[0]) | (u32::from(self.data[1]) << 16),
+            _ => u32::from(self.send_data),
+        }
+    }
+
+    // Interprets a reply to a transfer we started.
+    fn reply_data(&self, value: u32) -> ReceivedData {
+        match self.get_mode() {
+            SerialMode::Multiplayer => ReceivedData::Multiplayer([
+                self.send_data,
+                value as u16,
+                Self::DISCONNECTED_MULTIPLAYER_DATA,
+                Self::DISCONNECTED_MULTIPLAYER_DATA,
+            ]),
+            _ => ReceivedData::Normal(value),
+        }
+    }
+
+    fn start_transfer(&mut self) {
+        let drives_clock = match self.get_mode() {
+            SerialMode::Normal8Bit | SerialMode::Normal32Bit => {
+                self.control.get_bit(Self::NORMAL_INTERNAL_CLOCK_BIT_INDEX)
+            }
+            SerialMode::Multiplayer => self.is_parent(),
+            mode => {
+                log::debug!("unimplemented serial transfer in {:?} mode", mode);
+                return;
+            }
+        };
+
+        if !drives_clock {
+            self.state = TransferState::Receiving;
+            return;
+        }
+
+        let received = if self.is_connected() {
+            self.send_message(LinkMessage::Transfer(self.outgoing_data()));
+            None
+        } else {
+            Some(self.reply_data(Self::DISCONNECTED_DATA))
+        };
+
+        self.state = TransferState::Sending {
+            cycles_remaining: self.transfer_cycles(),
+            timeout_remaining: Self::REPLY_TIMEOUT_CYCLES,
+            received,
+        };
+    }
+
+    // Answers a transfer started by the other side, if we're ready for one.
+    fn receive_transfer(&mut self, value: u32) -> bool {
+        let ready = match self.get_mode() {
+            SerialMode::Normal8Bit | SerialMode::Normal32Bit => {
+                matches!(self.state, TransferState::Receiving)
+            }
+            // Children take part in every transfer, whether or not they've set the start bit.
+            SerialMode::Multiplayer => !self.is_parent(),
+            _ => false,
+        };
+
+        if !ready {
+            self.pending_transfer = Some(value);
+            return false;
+        }
+
+        let own_data = self.outgoing_data();
+        self.send_message(LinkMessage::Reply(own_data));
+
+        let received = match self.get_mode() {
+            SerialMode::Multiplayer => ReceivedData::Multiplayer([
+                value as u16,
+                own_data as u16,
+                Self::DISCONNECTED_MULTIPLAYER_DATA,
+                Self::DISCONNECTED_MULTIPLAYER_DATA,
+            ]),
+            _ => ReceivedData::Normal(value),
+        };
+
+        self.complete_transfer(received)
+    }
+
+    // Stores the data seen on the cable, returning whether an interrupt should be requested.
+    fn complete_transfer(&mut self, received: ReceivedData) -> bool {
+        match (self.get_mode(), received) {
+            (SerialMode::Normal8Bit, ReceivedData::Normal(value)) => {
+                self.send_data = (self.send_data & 0xFF00) | (value as u16 & 0xFF)
+            }
+            (SerialMode::Normal32Bit, ReceivedData::Normal(value)) => {
+                self.data[0] = value as u16;
+                self.data[1] = (value >> 16) as u16;
+            }
+            (SerialMode::Multiplayer, ReceivedData::Multiplayer(data)) => self.data = data,
+            // The mode was changed mid-transfer.
+            (mode, received) => {
+                log::debug!(
+                    "discarding serial transfer {:X?} in {:?} mode",
+                    received,
+                    mode
+                )
+            }
+        }
+
+        self.state = TransferState::Idle;
+        self.control = self.control.set_bit(Self::START_BIT_INDEX, false);
+
+        self.control.get_bit(Self::IRQ_ENABLE_BIT_INDEX)
+    }
+}
+
+impl Serial {
+    // Returns whether a serial interrupt should be requested.
+    pub fn step(&mut self) -> bool {
+        if self.start_requested {
+            self.start_requested = false;
+            self.start_transfer();
+        }
+
+        if self.transport.is_none() && matches!(self.state, TransferState::Idle) {
+            return false;
+        }
+
+        let mut interrupt = false;
+
+        if matches!(self.state, TransferState::Receiving) {
+            if let Some(value) = self.pending_transfer.take() {
+                interrupt |= self.receive_transfer(value);
+            }
+        }
+
+        self.poll_cycles += 1;
+        if self.poll_cycles >= Self::POLL_INTERVAL_CYCLES {
+            self.poll_cycles = 0;
+            interrupt |= self.poll_transport();
+        }
+
+        if let TransferState::Sending {
+            cycles_remaining,
+            timeout_remaining,
+            received,
+        } = &mut self.state
+        {
+            if *cycles_remaining > 0 {
+                *cycles_remaining -= 1;
+            } else if *timeout_remaining > 0 && received.is_none() {
+                *timeout_remaining -= 1;
+            } else {
+                let received = *received;
+                let received = received.unwrap_or_else(|| self.reply_data(Self::DISCONNECTED_DATA));
+                interrupt |= self.complete_transfer(received);
+            }
+        }
+
+        interrupt
+    }
+
+    fn poll_transport(&mut self) -> bool {
+        let mut interrupt = false;
+
+        while let Some(message) = self
+            .transport
+            .as_ref()
+            .and_then(|transport| transport.lock().unwrap().poll())
+        {
+            match message {
+                LinkMessage::Transfer(value) => interrupt |= self.receive_transfer(value),
+                LinkMessage::Reply(value) => interrupt |= self.receive_data(self.reply_data(value)),
+                LinkMessage::MultiplayerData(data) => {
+                    interrupt |= self.receive_data(ReceivedData::Multiplayer(data))
+                }
+            }
+        }
+
+        interrupt
+    }
+
+    // Handles the result of a transfer, returning whether an interrupt should be requested.
+    fn receive_data(&mut self, data: ReceivedData) -> bool {
+        if let TransferState::Sending { received, .. } = &mut self.state {
+            *received = Some(data);
+            return false;
+        }
+
+        // In links of more than two players, children only hear about transfers once they're done.
+        let is_multiplayer_child =
+            matches!(self.get_mode(), SerialMode::Multiplayer) && !self.is_parent();
+        if is_multiplayer_child && matches!(data, ReceivedData::Multiplayer(_)) {
+            self.complete_transfer(data)
+        } else {
+            log::debug!("unexpected link data {:X?}", data);
+            false
+        }
+    }
+
+    // What this player would send in a multiplayer transfer, or `None` if it's not taking part.
+    pub(crate) fn multiplayer_send_data(&self) -> Option<u16> {
+        matches!(self.get_mode(), SerialMode::Multiplayer).then_some(self.send_data)
+    }
+
+    pub fn transport(&self) -> Option<LinkTransportHandle> {
+        self.transport.clone()
+    }
+
+    pub fn set_transport(&mut self, transport: Option<LinkTransportHandle>) {
+        self.transport = transport;
+    }
+}
+
+impl Serial {
+    pub fn read_data<T>(&self, data_idx: usize, index: u32) -> T
+    where
+        u16: DataAccess<T>,
+    {
+        self.data[data_idx].get_data(index)
+    }
+
+    pub fn write_data<T>(&mut self, value: T, data_idx: usize, index: u32)
+    where
+        u16: DataAccess<T>,
+    {
+        self.data[data_idx] = self.data[data_idx].set_data(value, index);
+    }
+
+    pub fn read_control<T>(&self, index: u32) -> T
+    where
+        u16: DataAccess<T>,
+    {
+        let mut control = self.control;
+
+        if matches!(self.get_mode(), SerialMode::Multiplayer) {
+            let child = !self.is_parent();
+
+            control = control
+                .set_bit(Self::MULTIPLAYER_CHILD_BIT_INDEX, child)
+                .set_bit(Self::MULTIPLAYER_READY_BIT_INDEX, self.is_connected())
+                .set_bit_range(u16::from(child), Self::MULTIPLAYER_ID_BIT_RANGE)
+                .set_bit(Self::MULTIPLAYER_ERROR_BIT_INDEX, false);
+        }
+
+        control.get_data(index)
+    }
+
+    pub fn write_control<T>(&mut self, value: T, index: u32)
+    where
+        u16: DataAccess<T>,
+    {
+        let old_start = self.control.get_bit(Self::START_BIT_INDEX);
+        self.control = self.control.set_data(value, index);
+
+        // The mode lives in the upper byte, so byte-wise writes wouldn't have set it yet.
+        if !old_start && self.control.get_bit(Self::START_BIT_INDEX) {
+            self.start_requested = true;
+        }
+    }
+
+    pub fn read_send_data<T>(&self, index: u32) -> T
+    where
+        u16: DataAccess<T>,
+    {
+        self.send_data.get_data(index)
+    }
+
+    pub fn write_send_data<T>(&mut self, value: T, index: u32)
+    where
+        u16: DataAccess<T>,
+    {
+        self.send_data = self.send_data.set_data(value, index);
+    }
+
+    pub fn read_mode_select<T>(&self, index: u32) -> T
+    where
+        u16: DataAccess<T>,
+    {
+        self.mode_select.get_data(index)
+    }
+
+    pub fn write_mode_select<T>(&mut self, value: T, index: u32)
+    where
+        u16: DataAccess<T>,
+    {
+        self.mode_select = self.mode_select.set_data(value, index);
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt::Display;
+use std::ops::Range;
+
+use crate::{Cpu, Register};
+
+// Which parts of two emulator states differ, for tracking down where two runs that should have
+// matched (netplay peers, a rewound and a replayed run, ...) first went their separate ways.
+// Memory is compared in pages, reported by the address they start at.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub registers: Vec<Register>, // r0-r15 in the current mode, and the CPSR
+    pub cycle_count: bool,
+    pub wram_pages: Vec<u32>,
+    pub io_registers: Vec<&'static str>,
+    pub video_memory_pages: Vec<u32>, // palette RAM, VRAM and OAM
+    pub timers: Vec<usize>,
+    pub dma_channels: Vec<usize>,
+}
+
+impl StateDiff {
+    pub const PAGE_SIZE: u32 = 0x400;
+
+    pub(crate) const WRAM_RANGES: [Range<u32>; 2] =
+        [0x02000000..0x02040000, 0x03000000..0x03008000];
+    const VIDEO_MEMORY_RANGES: [Range<u32>; 3] = [
+        0x05000000..0x05000400,
+        0x06000000..0x06018000,
+        0x07000000..0x07000400,
+    ];
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn differing_pages(a: &Cpu, b: &Cpu, ranges: &[Range<u32>]) -> Vec<u32> {
+        ranges
+            .iter()
+            .flat_map(|range| range.clone().step_by(Self::PAGE_SIZE as usize))
+            .filter(|&page| {
+                (page..page + Self::PAGE_SIZE).step_by(4).any(|address| {
+                    a.bus.read_word_address_debug(address) != b.bus.read_word_address_debug(address)
+                })
+            })
+            .collect()
+    }
+}
+
+// One line per differing part, or "no differences".
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no differences");
+        }
+
+        let mut lines = Vec::new();
+        if !self.registers.is_empty() {
+            lines.push(format!("registers: {:?}", self.registers));
+        }
+        if self.cycle_count {
+            lines.push("cycle count".to_string());
+        }
+        for (name, pages) in [
+            ("WRAM pages", &self.wram_pages),
+            ("video memory pages", &self.video_memory_pages),
+        ] {
+            if !pages.is_empty() {
+                let pages: Vec<String> = pages.iter().map(|page| format!("{page:08X}")).collect();
+                lines.push(format!("{name}: {}", pages.join(", ")));
+            }
+        }
+        if !self.io_registers.is_empty() {
+            lines.push(format!("IO registers: {}", self.io_registers.join(", ")));
+        }
+        if !self.timers.is_empty() {
+            lines.push(format!("timers: {:?}", self.timers));
+        }
+        if !self.dma_channels.is_empty() {
+            lines.push(format!("DMA channels: {:?}", self.dma_channels));
+        }
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl Cpu {
+    // Compares against another state of the same game. Doesn't cover everything in a save state,
+    // like the APU or cartridge, but anything that differs there usually spills over into one of
+    // the parts compared here soon enough.
+    pub fn diff_state(&self, other: &Cpu) -> StateDiff {
+        let registers = (0..16)
+            .map(Register::from_index)
+            .chain([Register::Cpsr])
+            .filter(|&register| {
+                self.read_register(register, |pc| pc) != other.read_register(register, |pc| pc)
+            })
+            .collect();
+
+        let other_io_registers = other.bus.io_registers();
+        let io_registers = self
+            .bus
+            .io_registers()
+            .into_iter()
+            .zip(other_io_registers)
+            .filter(|(a, b)| a.value != b.value)
+            .map(|(register, _)| register.name)
+            .collect();
+
+        let timers = (0..4)
+            .filter(|&i| self.bus.timers[i] != other.bus.timers[i])
+            .collect();
+
+        let self_dma = self.bus.get_dma_debug();
+        let other_dma = other.bus.get_dma_debug();
+        let dma_channels = (0..4).filter(|&i| self_dma[i] != other_dma[i]).collect();
+
+        StateDiff {
+            registers,
+            cycle_count: self.bus.cycle_count() != other.bus.cycle_count(),
+            wram_pages: StateDiff::differing_pages(self, other, &StateDiff::WRAM_RANGES),
+            io_registers,
+            video_memory_pages: StateDiff::differing_pages(
+                self,
+                other,
+                &StateDiff::VIDEO_MEMORY_RANGES,
+            ),
+            timers,
+            dma_channels,
+        }
+    }
+}
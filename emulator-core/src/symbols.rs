@@ -0,0 +1,256 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    pub size: u32, // 0 when unknown, as with .map and .sym files
+}
+
+// Names for addresses, loaded from a devkitARM ELF, a GNU ld .map file or a no$gba style .sym
+// file, for debuggers to show next to raw addresses.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>, // sorted by address
+}
+
+impl SymbolTable {
+    const ELF_MAGIC: &'static [u8] = b"\x7FELF";
+    // Tried in order when looking for symbols next to a ROM.
+    const SIDECAR_EXTENSIONS: [&'static str; 3] = ["elf", "map", "sym"];
+
+    // ELF files are recognized by their contents, anything else by its extension.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path)?;
+        if data.starts_with(Self::ELF_MAGIC) {
+            return Self::from_elf(&data);
+        }
+
+        let text = String::from_utf8_lossy(&data);
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("map") => Ok(Self::from_map(&text)),
+            _ => Ok(Self::from_sym(&text)),
+        }
+    }
+
+    // Looks for symbols next to a ROM, named like it. For example "game.gba" picks up
+    // "game.elf", "game.map" or "game.sym".
+    pub fn load_alongside(rom_path: &Path) -> Option<Self> {
+        Self::SIDECAR_EXTENSIONS
+            .iter()
+            .map(|extension| rom_path.with_extension(extension))
+            .filter(|path| path.is_file())
+            .find_map(|path| match Self::load(&path) {
+                Ok(symbols) => Some(symbols),
+                Err(e) => {
+                    log::warn!("failed to load symbols from {}: {e}", path.display());
+                    None
+                }
+            })
+    }
+
+    pub fn from_elf(data: &[u8]) -> Result<Self> {
+        const SHT_SYMTAB: u32 = 2;
+        const STT_FUNC: u8 = 2;
+        const STT_SECTION: u8 = 3;
+        const STT_FILE: u8 = 4;
+        const SHN_UNDEF: u16 = 0;
+        const SECTION_HEADER_SIZE: usize = 40;
+        const SYMBOL_SIZE: usize = 16;
+
+        if !data.starts_with(Self::ELF_MAGIC) {
+            return Err(anyhow!("not an ELF file"));
+        }
+
+        // 32-bit and little endian, as produced for the ARM7TDMI.
+        if data.get(4..6) != Some([1, 1].as_slice()) {
+            return Err(anyhow!("only 32-bit little endian ELF files are supported"));
+        }
+
+        let section_header_offset = read_elf_word(data, 0x20)? as usize;
+        let section_header_count = usize::from(read_elf_halfword(data, 0x30)?);
+
+        let section_header = |index: usize| {
+            let offset = section_header_offset + (index * SECTION_HEADER_SIZE);
+            data.get(offset..offset + SECTION_HEADER_SIZE)
+                .ok_or_else(|| anyhow!("section header {index} is out of bounds"))
+        };
+
+        let mut symbols = Vec::new();
+        for index in 0..section_header_count {
+            let header = section_header(index)?;
+            if read_elf_word(header, 4)? != SHT_SYMTAB {
+                continue;
+            }
+
+            let symbols_offset = read_elf_word(header, 16)? as usize;
+            let symbols_size = read_elf_word(header, 20)? as usize;
+            let symbols_data = data
+                .get(symbols_offset..symbols_offset + symbols_size)
+                .ok_or_else(|| anyhow!("symbol table is out of bounds"))?;
+
+            let strings_header = section_header(read_elf_word(header, 24)? as usize)?;
+            let strings_offset = read_elf_word(strings_header, 16)? as usize;
+            let strings_size = read_elf_word(strings_header, 20)? as usize;
+            let strings = data
+                .get(strings_offset..strings_offset + strings_size)
+                .ok_or_else(|| anyhow!("string table is out of bounds"))?;
+
+            for symbol in symbols_data.chunks_exact(SYMBOL_SIZE) {
+                let name_offset = read_elf_word(symbol, 0)? as usize;
+                let mut address = read_elf_word(symbol, 4)?;
+                let size = read_elf_word(symbol, 8)?;
+                let symbol_type = symbol[12] & 0xF;
+                let section_index = read_elf_halfword(symbol, 14)?;
+
+                if section_index == SHN_UNDEF
+                    || symbol_type == STT_SECTION
+                    || symbol_type == STT_FILE
+                {
+                    continue;
+                }
+
+                let Some(name) = strings
+                    .get(name_offset..)
+                    .and_then(|name| name.split(|c| *c == 0).next())
+                    .map(String::from_utf8_lossy)
+                else {
+                    continue;
+                };
+
+                if is_ignored_name(&name) {
+                    continue;
+                }
+
+                // Thumb functions have the lowest bit set, like the BX target they'd be called
+                // through.
+                if symbol_type == STT_FUNC {
+                    address &= !1;
+                }
+
+                symbols.push(Symbol {
+                    name: name.into_owned(),
+                    address,
+                    size,
+                });
+            }
+        }
+
+        Ok(Self::from_symbols(symbols))
+    }
+
+    // Symbols are the lines that are just an address and a name, like
+    //                 0x08000290                main
+    pub fn from_map(text: &str) -> Self {
+        let symbols = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let address = fields.next()?.strip_prefix("0x")?;
+                let name = fields.next()?;
+                // Anything else on the line means it's a section, or an assignment to a symbol.
+                if fields.next().is_some() || name.starts_with("0x") || is_ignored_name(name) {
+                    return None;
+                }
+
+                let address = u32::try_from(u64::from_str_radix(address, 16).ok()?).ok()?;
+                Some(Symbol {
+                    name: name.to_string(),
+                    address,
+                    size: 0,
+                })
+            })
+            .collect();
+
+        Self::from_symbols(symbols)
+    }
+
+    // Lines are an address in hex and a name, like "08000290 main". Comments start with ';'.
+    pub fn from_sym(text: &str) -> Self {
+        let symbols = text
+            .lines()
+            .filter_map(|line| {
+                let line = line.split(';').next()?;
+                let mut fields = line.split_whitespace();
+                let address = u32::from_str_radix(fields.next()?, 16).ok()?;
+                let name = fields.next()?;
+                if is_ignored_name(name) {
+                    return None;
+                }
+
+                Some(Symbol {
+                    name: name.to_string(),
+                    address,
+                    size: 0,
+                })
+            })
+            .collect();
+
+        Self::from_symbols(symbols)
+    }
+
+    pub fn from_symbols(mut symbols: Vec<Symbol>) -> Self {
+        symbols.sort_by_key(|symbol| symbol.address);
+        symbols.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+        Self { symbols }
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    // The symbol containing the given address, along with the offset into it. Symbols without a
+    // size are assumed to extend up to the next symbol.
+    pub fn lookup(&self, address: u32) -> Option<(&Symbol, u32)> {
+        let index = self
+            .symbols
+            .partition_point(|symbol| symbol.address <= address)
+            .checked_sub(1)?;
+        let symbol = &self.symbols[index];
+        let offset = address - symbol.address;
+
+        if symbol.size != 0 && offset >= symbol.size {
+            return None;
+        }
+
+        Some((symbol, offset))
+    }
+
+    // Formats an address as "name" or "name+0x10", for display next to it.
+    pub fn describe(&self, address: u32) -> Option<String> {
+        self.lookup(address).map(|(symbol, offset)| {
+            if offset == 0 {
+                symbol.name.clone()
+            } else {
+                format!("{}+0x{:X}", symbol.name, offset)
+            }
+        })
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u32> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| symbol.address)
+    }
+}
+
+// Mapping symbols like "$t" and "$d" mark where ARM, Thumb and data start, and local labels
+// starting with '.' aren't interesting either.
+fn is_ignored_name(name: &str) -> bool {
+    name.is_empty() || name.starts_with('$') || name.starts_with('.')
+}
+
+fn read_elf_word(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| anyhow!("ELF file is truncated"))
+}
+
+fn read_elf_halfword(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| anyhow!("ELF file is truncated"))
+}
@@ -1,6 +1,7 @@
 use std::ops::RangeInclusive;
 
 use crate::{BitManipulation, DataAccess};
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug)]
 enum PrescalerInterval {
@@ -10,7 +11,7 @@ enum PrescalerInterval {
     Div1024,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Timer {
     tick: u64,
 
@@ -22,32 +23,35 @@ pub struct Timer {
 }
 
 impl Timer {
-    pub fn step(&mut self, previous_overflow: bool) -> bool {
+    // `previous_overflow` is whether the previous timer overflowed this cycle, or `None` for
+    // timer 0, which has no previous timer to count up from.
+    pub fn step(&mut self, previous_overflow: Option<bool>) -> bool {
         // if timer disabled, don't handle any counting logic.
         if !self.get_timer_start_stop() {
             return false;
         }
 
+        // Count-up timers ignore the prescaler entirely, and only ever tick when the previous
+        // timer overflows.
+        if let Some(previous_overflow) = previous_overflow.filter(|_| self.get_count_up_timing()) {
+            self.startup_delay = false;
+            return self.increment(previous_overflow);
+        }
+
         if self.startup_delay {
             self.startup_delay = false;
             return false;
         }
 
-        let increment = if self.get_count_up_timing() {
-            previous_overflow
-        } else {
-            let increment_mask = match self.get_prescaler_interval() {
-                PrescalerInterval::Div1 => 0x0,
-                PrescalerInterval::Div64 => 0x3F,
-                PrescalerInterval::Div256 => 0xFF,
-                PrescalerInterval::Div1024 => 0x3FF,
-            };
-
-            (self.tick & increment_mask) == increment_mask
-        };
+        let increment_mask = self.get_prescaler_mask();
+        let increment = (self.tick & increment_mask) == increment_mask;
 
         self.tick += 1;
 
+        self.increment(increment)
+    }
+
+    fn increment(&mut self, increment: bool) -> bool {
         if increment {
             let (new_counter, overflow) = self.counter.overflowing_add(1);
 
@@ -118,8 +122,12 @@ impl Timer {
         // The reload value is copied into the counter only upon following two situations:
         // - Automatically upon timer overflows
         // - When the timer start bit becomes changed from 0 to 1. (handled here)
+        //
+        // The prescaler phase is also reset, so the first increment always happens a full
+        // prescaler interval after the timer is started.
         if !old_start_bit && new_start_bit {
             self.counter = self.reload;
+            self.tick = 0;
             self.startup_delay = true;
         }
     }
@@ -138,6 +146,15 @@ impl Timer {
         }
     }
 
+    fn get_prescaler_mask(&self) -> u64 {
+        match self.get_prescaler_interval() {
+            PrescalerInterval::Div1 => 0x0,
+            PrescalerInterval::Div64 => 0x3F,
+            PrescalerInterval::Div256 => 0xFF,
+            PrescalerInterval::Div1024 => 0x3FF,
+        }
+    }
+
     fn get_count_up_timing(&self) -> bool {
         const COUNT_UP_TIMING_BIT_INDEX: usize = 2;
 
@@ -157,6 +174,34 @@ impl Timer {
     }
 }
 
+impl Timer {
+    // Given the cycle at which `step` will next be called, returns the cycle of the `step` call
+    // that will overflow this timer, assuming its registers aren't written in the meantime.
+    //
+    // Returns `None` for stopped timers, and for count-up timers since their overflow depends on
+    // the previous timer. `has_previous_timer` is false for timer 0, which can't count up.
+    pub fn predict_next_overflow_cycle(
+        &self,
+        current_cycle: u64,
+        has_previous_timer: bool,
+    ) -> Option<u64> {
+        if !self.get_timer_start_stop() || (has_previous_timer && self.get_count_up_timing()) {
+            return None;
+        }
+
+        let increment_mask = self.get_prescaler_mask();
+        let increments_remaining = 0x10000 - u64::from(self.counter);
+        let startup_delay = u64::from(self.startup_delay);
+
+        let cycles_until_first_increment = increment_mask - (self.tick & increment_mask);
+        let cycles_until_overflow = startup_delay
+            + cycles_until_first_increment
+            + ((increments_remaining - 1) * (increment_mask + 1));
+
+        Some(current_cycle + cycles_until_overflow)
+    }
+}
+
 // Public debugging interface
 impl Timer {
     pub fn get_current_counter(&self) -> u16 {
@@ -167,3 +212,196 @@ impl Timer {
         self.reload
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::build_thumb_test_cpu;
+    use crate::Cpu;
+
+    const TIMER_START_BIT: u16 = 1 << 7;
+    const TIMER_COUNT_UP_BIT: u16 = 1 << 2;
+    const TIMER_IRQ_ENABLE_BIT: u16 = 1 << 6;
+
+    fn started_timer(control: u16, reload: u16) -> Timer {
+        let mut timer = Timer::default();
+        timer.write_timer_counter_reload_word(
+            u32::from(reload) | (u32::from(control | TIMER_START_BIT) << 16),
+        );
+        timer
+    }
+
+    // Steps `timer` until its counter next changes, returning how many steps that took.
+    fn steps_until_increment(timer: &mut Timer) -> u32 {
+        let counter = timer.get_current_counter();
+        (1..=0x1000)
+            .find(|_| {
+                timer.step(None);
+                timer.get_current_counter() != counter
+            })
+            .unwrap()
+    }
+
+    fn write_timer(cpu: &mut Cpu, timer: u32, control: u16, reload: u16) {
+        let base = 0x04000100 + (timer * 4);
+        cpu.bus.write_halfword_address_debug(reload, base);
+        cpu.bus.write_halfword_address_debug(control, base + 2);
+    }
+
+    fn read_counter(cpu: &mut Cpu, timer: u32) -> u16 {
+        cpu.bus
+            .read_halfword_address_debug(0x04000100 + (timer * 4))
+    }
+
+    #[test]
+    fn timer_overflow_prediction() {
+        // (control, initial reload value)
+        const TIMER_CONFIGS: &[(u16, u16)] = &[
+            (0, 0xFFFF),
+            (0, 0xFF00),
+            (1, 0xFFF0),
+            (3, 0xFFFE),
+            // Only taken into account when there's a previous timer to count up from.
+            (TIMER_COUNT_UP_BIT, 0xFFF0),
+        ];
+
+        for &(control, reload) in TIMER_CONFIGS {
+            let mut timer = started_timer(control, reload);
+
+            let mut cycle = 1_000;
+            let predicted = timer.predict_next_overflow_cycle(cycle, false).unwrap();
+
+            while !timer.step(None) {
+                cycle += 1;
+            }
+
+            assert_eq!(predicted, cycle);
+
+            // Subsequent overflows must be predicted correctly as well, mid-prescaler interval.
+            cycle += 1;
+            let predicted = timer.predict_next_overflow_cycle(cycle, false).unwrap();
+
+            while !timer.step(None) {
+                cycle += 1;
+            }
+
+            assert_eq!(predicted, cycle);
+        }
+
+        let timer = started_timer(TIMER_COUNT_UP_BIT, 0);
+        assert_eq!(timer.predict_next_overflow_cycle(0, true), None);
+        assert_eq!(Timer::default().predict_next_overflow_cycle(0, false), None);
+    }
+
+    #[test]
+    fn prescaler_phase_resets_on_start() {
+        let mut timer = started_timer(1, 0);
+        // One step of startup delay, then a full prescaler interval.
+        assert_eq!(steps_until_increment(&mut timer), 1 + 64);
+
+        for _ in 0..30 {
+            timer.step(None);
+        }
+        timer.write_timer_control(1u16, 0);
+        timer.write_timer_control(1 | TIMER_START_BIT, 0);
+        assert_eq!(timer.get_current_counter(), 0);
+        assert_eq!(steps_until_increment(&mut timer), 1 + 64);
+
+        // Changing other bits while running leaves the phase alone.
+        for _ in 0..30 {
+            timer.step(None);
+        }
+        timer.write_timer_control(1 | TIMER_START_BIT | TIMER_IRQ_ENABLE_BIT, 0);
+        assert_eq!(steps_until_increment(&mut timer), 64 - 30);
+    }
+
+    #[test]
+    fn reload_applies_on_overflow_and_start() {
+        let mut timer = started_timer(0, 0xFFFD);
+        timer.step(None);
+        assert_eq!(timer.get_current_counter(), 0xFFFD);
+
+        // Written reload values don't touch the running counter.
+        timer.write_timer_counter_reload(0x1234u16, 0);
+        timer.step(None);
+        timer.step(None);
+        assert_eq!(timer.get_current_counter(), 0xFFFF);
+        assert_eq!(timer.get_current_reload(), 0x1234);
+
+        assert!(timer.step(None));
+        assert_eq!(timer.get_current_counter(), 0x1234);
+
+        // Nor do they once the timer is stopped, until it's started again.
+        timer.write_timer_control(0u16, 0);
+        timer.write_timer_counter_reload(0x5678u16, 0);
+        assert_eq!(timer.get_current_counter(), 0x1234);
+        timer.write_timer_control(TIMER_START_BIT, 0);
+        assert_eq!(timer.get_current_counter(), 0x5678);
+    }
+
+    #[test]
+    fn count_up_timers() {
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        // Timer 0 ignores the count-up bit, there's no timer before it to count.
+        write_timer(&mut cpu, 0, TIMER_START_BIT | TIMER_COUNT_UP_BIT, 0xFFFC);
+        // Timer 1 counts timer 0's overflows, with its prescaler ignored.
+        write_timer(&mut cpu, 1, TIMER_START_BIT | TIMER_COUNT_UP_BIT | 3, 0);
+        // Timer 2 is stopped, so timer 3 never counts.
+        write_timer(&mut cpu, 3, TIMER_START_BIT | TIMER_COUNT_UP_BIT, 0);
+
+        let mut overflows = 0;
+        let mut previous_counter = read_counter(&mut cpu, 0);
+        for _ in 0..41 {
+            cpu.bus.step();
+
+            let counter = read_counter(&mut cpu, 0);
+            if counter < previous_counter {
+                overflows += 1;
+            }
+            previous_counter = counter;
+
+            assert_eq!(read_counter(&mut cpu, 1), overflows);
+        }
+
+        // One step of startup delay, then an overflow every four.
+        assert_eq!(overflows, 10);
+        assert_eq!(read_counter(&mut cpu, 3), 0);
+    }
+
+    #[test]
+    fn timer_interrupts() {
+        const IF: u32 = 0x04000202;
+        const TIMER_0: u16 = 1 << 3;
+        const TIMER_1: u16 = 1 << 4;
+        const TIMER_2: u16 = 1 << 5;
+
+        let mut cpu = build_thumb_test_cpu(&[], &[]);
+
+        // Timer 0 overflows every step but only timer 1, counting up from it, requests anything.
+        write_timer(&mut cpu, 0, TIMER_START_BIT, 0xFFFF);
+        write_timer(
+            &mut cpu,
+            1,
+            TIMER_START_BIT | TIMER_COUNT_UP_BIT | TIMER_IRQ_ENABLE_BIT,
+            0xFFFE,
+        );
+        write_timer(&mut cpu, 2, TIMER_START_BIT | TIMER_IRQ_ENABLE_BIT, 0xFFF0);
+
+        // Requests take a few cycles to show up in IF, having to get through the IRQ synchronizer.
+        let interrupts_after = |cpu: &mut Cpu, steps| {
+            for _ in 0..steps {
+                cpu.bus.step();
+            }
+            cpu.bus.read_halfword_address_debug(IF) & (TIMER_0 | TIMER_1 | TIMER_2)
+        };
+
+        // Timer 1 overflows on timer 0's second overflow.
+        assert_eq!(interrupts_after(&mut cpu, 6), 0);
+        assert_eq!(interrupts_after(&mut cpu, 1), TIMER_1);
+
+        // Timer 2 takes 16 increments after its own startup delay.
+        assert_eq!(interrupts_after(&mut cpu, 13), TIMER_1);
+        assert_eq!(interrupts_after(&mut cpu, 1), TIMER_1 | TIMER_2);
+    }
+}
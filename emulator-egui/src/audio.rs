@@ -0,0 +1,117 @@
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    time::Duration,
+};
+
+use anyhow::Result;
+use emulator_core::AudioRingBuffer;
+use rodio::{OutputStream, Sink, Source};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+// About 100ms, enough to ride out the emulator thread falling behind for a frame or two.
+pub const BUFFER_CAPACITY: usize = (SAMPLE_RATE / 10) as usize;
+
+// Plays whatever the current emulator instance outputs. rodio pulls samples on its own audio
+// thread, straight out of the emulator's ring buffer.
+pub struct AudioPlayer {
+    // Playback stops once the stream is dropped.
+    _stream: OutputStream,
+    sink: Sink,
+    muted: bool,
+    volume: f32,
+}
+
+impl AudioPlayer {
+    // Also returns the sender to hand each newly created emulator's ring buffer to the player.
+    pub fn new() -> Result<(Self, Sender<AudioRingBuffer>)> {
+        let (stream, stream_handle) = OutputStream::try_default()?;
+        let sink = Sink::try_new(&stream_handle)?;
+
+        let (buffer_sender, buffer_receiver) = channel();
+        sink.append(RingBufferSource {
+            buffer_receiver,
+            buffer: None,
+            last_sample: [0.0; 2],
+            next_channel: 0,
+        });
+
+        let player = Self {
+            _stream: stream,
+            sink,
+            muted: false,
+            volume: 1.0,
+        };
+
+        Ok((player, buffer_sender))
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.update_sink_volume();
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        self.update_sink_volume();
+    }
+
+    fn update_sink_volume(&self) {
+        self.sink
+            .set_volume(if self.muted { 0.0 } else { self.volume });
+    }
+}
+
+struct RingBufferSource {
+    buffer_receiver: Receiver<AudioRingBuffer>,
+    buffer: Option<AudioRingBuffer>,
+    // Repeated when the emulator falls behind, which is less jarring than dropping to silence.
+    last_sample: [f32; 2],
+    next_channel: usize,
+}
+
+impl Source for RingBufferSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Iterator for RingBufferSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_channel == 0 {
+            if let Some(buffer) = self.buffer_receiver.try_iter().last() {
+                self.buffer = Some(buffer);
+            }
+
+            if let Some(sample) = self.buffer.as_ref().and_then(AudioRingBuffer::pop) {
+                self.last_sample = sample;
+            }
+        }
+
+        let sample = self.last_sample[self.next_channel];
+        self.next_channel = (self.next_channel + 1) % 2;
+
+        Some(sample)
+    }
+}
@@ -2,27 +2,68 @@ use std::{
     array,
     fmt::Debug,
     fs::File,
-    path::PathBuf,
+    io::BufWriter,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicU64, AtomicUsize, Ordering},
+        atomic::{AtomicUsize, Ordering},
         mpsc::{channel, Sender},
-        Arc, Mutex,
+        Arc, Mutex, RwLock,
     },
     thread,
+    time::Duration,
 };
 
+use audio::AudioPlayer;
 use eframe::{
     egui::{
-        self, load::SizedTexture, CollapsingHeader, ImageSource, ScrollArea, Slider, TextEdit,
-        TextStyle, TextureOptions, Ui,
+        self, collapsing_header::CollapsingState, load::SizedTexture, pos2, vec2, Button,
+        CollapsingHeader, Color32, ComboBox, DragValue, Grid, ImageSource, Label, Rect, RichText,
+        ScrollArea, Sense, Shape, Slider, Stroke, TextEdit, TextStyle, TextureOptions, Ui,
+        WidgetText,
     },
     epaint::ColorImage,
 };
+use egui_dock::{DockArea, DockState, TabViewer};
 use emulator_core::{
-    Bus, Cartridge, Cpu, CpuMode, Instruction, InstructionSet, Key, Lcd, Register, Rgb555,
-    CYCLES_PER_SECOND,
+    AccessKind, AccessWatch, Autofire, AutofireConfig, BackgroundMap, BackgroundViewport,
+    BusProfiler, Cartridge, ColorCorrection, Cpu, CpuConfig, DebugSnapshot, DmaLog, DmaStartTiming,
+    ErrorPolicy, FrameKind, InputTiming, Instruction, InstructionSet, IoRegisterInfo, Key,
+    KeysState, Lcd, MemoryRegion, MemorySearch, Register, Rgb555, SearchFilter, SearchWidth,
+    SharedDebugSnapshot, StepEvent, SymbolTable,
 };
+use panel::Panel;
 use rfd::FileDialog;
+use rom_library::{RomLibrary, ROM_EXTENSIONS};
+
+mod audio;
+mod panel;
+mod rom_library;
+
+const FRAMES_PER_SECOND: u32 = 60;
+
+const HEATMAP_COLUMNS: u32 = 64;
+const HEATMAP_CELL_SIZE: f32 = 8.0;
+
+const DOCK_STATE_STORAGE_KEY: &str = "dock_state";
+
+const AUTOFIRE_STORAGE_KEY: &str = "autofire";
+
+// The on-screen keypad, in the order its turbo toggles are listed.
+const VIRTUAL_KEYPAD_KEYS: [Key; 10] = [
+    Key::Up,
+    Key::Down,
+    Key::Left,
+    Key::Right,
+    Key::A,
+    Key::B,
+    Key::L,
+    Key::R,
+    Key::Select,
+    Key::Start,
+];
+
+// Listing every candidate early in a search would mean hundreds of thousands of rows.
+const MAX_LISTED_CANDIDATES: usize = 256;
 
 fn main() {
     env_logger::init();
@@ -36,69 +77,150 @@ fn main() {
     .unwrap();
 }
 
+fn save_screenshot(
+    display_buffer: &[[Rgb555; Lcd::LCD_WIDTH]; Lcd::LCD_HEIGHT],
+    path: &Path,
+) -> anyhow::Result<()> {
+    let screenshot_file = BufWriter::new(File::create(path)?);
+
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "rgb555")
+    {
+        Lcd::encode_buffer_raw(display_buffer, screenshot_file)?;
+    } else {
+        Lcd::encode_buffer_png(display_buffer, screenshot_file)?;
+    }
+
+    Ok(())
+}
+
 #[derive(Debug)]
 enum EmulatorCommand {
     Run,
     Pause,
     Step(u64),
+    StepOver,
+    StepOut,
+    RunTo(u32),
     LoadRom(PathBuf),
     KeyPressed(Key),
     KeyReleased(Key),
+    SetAutofire(AutofireConfig),
     CreateNewSaveState,
     UpdateSaveState(usize),
     LoadSaveState(usize),
+    SetSolarLevel(u8),
+    ResetPerfCounters,
+    SetBusProfiling(bool),
+    // The channels to log DMA transfers from, or None to stop logging.
+    SetDmaLogging(Option<[bool; 4]>),
+    ResetBusProfiler,
+    StartMemorySearch(SearchWidth),
+    FilterMemorySearch(SearchFilter),
+    // Only applied while paused, so the write can't land in the middle of a frame.
+    WriteIo {
+        address: u32,
+        size: u32, // in bytes
+        value: u32,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 enum EmulatorState {
     Running,
+    // Like `Running`, but also stopping at the target.
+    RunningTo(RunTarget),
     Paused,
 }
 
+#[derive(Clone, Copy, Debug)]
+enum RunTarget {
+    Address(u32),
+    // The return address of a call, which only counts once the call stack is back down to
+    // `depth` frames, as recursive calls return to the same address.
+    Return { address: u32, depth: usize },
+}
+
+impl RunTarget {
+    fn address(self) -> u32 {
+        match self {
+            Self::Address(address) | Self::Return { address, .. } => address,
+        }
+    }
+
+    fn reached(self, cpu: &Cpu) -> bool {
+        match self {
+            Self::Address(_) => true,
+            Self::Return { depth, .. } => cpu.call_stack().len() <= depth,
+        }
+    }
+}
+
+// Special timing means something different for each channel.
+fn dma_start_timing_name(channel: usize, start_timing: DmaStartTiming) -> String {
+    match (channel, start_timing) {
+        (1 | 2, DmaStartTiming::Special) => "Sound FIFO".to_string(),
+        (3, DmaStartTiming::Special) => "Video Capture".to_string(),
+        (_, start_timing) => format!("{:?}", start_timing),
+    }
+}
+
+// Keeps any transfers already logged when only the channels change.
+fn set_dma_logging(cpu: &mut Cpu, channels: Option<[bool; 4]>) {
+    let Some(channels) = channels else {
+        cpu.bus.set_dma_log(None);
+        return;
+    };
+
+    if cpu.bus.dma_log().is_none() {
+        cpu.bus.set_dma_log(Some(DmaLog::new()));
+    }
+    let dma_log = cpu.bus.dma_log_mut().unwrap();
+    for (channel, enabled) in channels.into_iter().enumerate() {
+        dma_log.set_channel_enabled(channel, enabled);
+    }
+}
+
+// Explicit steps run through breakpoints.
+fn step_instruction(cpu: &mut Cpu) {
+    if let Some(StepEvent::BreakpointHit { .. }) = cpu.fetch_decode_execute() {
+        cpu.fetch_decode_execute();
+    }
+}
+
 struct MemoryViewInfo {
     offset: u32,
     buffer: Box<[u8; 0x1000]>,
 }
 
-struct DisassemblyInfo {
-    pc: u32,
-    buffer: Box<[Instruction; 0x1000]>,
-    instruction_width: u32,
-}
-
-struct RegisterValue {
-    register: Register,
-    value: u32,
+struct TilemapViewInfo {
+    background: usize,
+    // None if the current mode doesn't show the background as a tilemap.
+    map: Option<BackgroundMap>,
 }
 
-struct CpuInfo {
-    sign_flag: bool,
-    zero_flag: bool,
-    carry_flag: bool,
-    overflow_flag: bool,
-    irq_disable: bool,
-    fiq_disable: bool,
-    instruction_mode: InstructionSet,
-    cpu_mode: CpuMode,
-    irq_buffer: [u16; Bus::IRQ_SYNC_BUFFER],
-    open_bus_data: u32,
+struct DisassemblyInfo {
+    // Where the listing is centered, or None to follow the executing PC.
+    view_address: Option<u32>,
+    start_address: u32,
+    executing_pc: u32,
+    instruction_set: InstructionSet,
+    buffer: Box<[Instruction; 0x1000]>,
 }
 
-impl Default for CpuInfo {
-    fn default() -> Self {
-        Self {
-            sign_flag: false,
-            zero_flag: false,
-            carry_flag: false,
-            overflow_flag: false,
-            irq_disable: false,
-            fiq_disable: false,
-            instruction_mode: InstructionSet::Arm,
-            cpu_mode: CpuMode::System,
-            irq_buffer: [0; Bus::IRQ_SYNC_BUFFER],
-            open_bus_data: Default::default(),
+impl DisassemblyInfo {
+    fn instruction_width(&self) -> u32 {
+        match self.instruction_set {
+            InstructionSet::Arm => 4,
+            InstructionSet::Thumb => 2,
         }
     }
+
+    fn address(&self, row: usize) -> u32 {
+        self.start_address
+            .wrapping_add(self.instruction_width() * (row as u32))
+    }
 }
 
 #[derive(Clone, Default)]
@@ -107,121 +229,276 @@ struct BreakpointInfo {
     active: bool,
 }
 
-#[derive(Clone, Default)]
-struct TimerInfo {
-    reload: u16,
-    counter: u16,
+// Holds a value in memory, rewritten before every frame while active.
+#[derive(Clone)]
+struct CheatInfo {
+    address: u32,
+    width: SearchWidth,
+    value: u32,
+    active: bool,
+}
+
+impl CheatInfo {
+    fn apply(&self, cpu: &mut Cpu) {
+        match self.width {
+            SearchWidth::Byte => cpu
+                .bus
+                .write_byte_address_debug(self.value as u8, self.address),
+            SearchWidth::Halfword => cpu
+                .bus
+                .write_halfword_address_debug(self.value as u16, self.address),
+            SearchWidth::Word => cpu.bus.write_word_address_debug(self.value, self.address),
+        }
+    }
 }
 
 struct MyEguiApp {
-    display_buffer: Arc<Mutex<[[Rgb555; Lcd::LCD_WIDTH]; Lcd::LCD_HEIGHT]>>,
+    debug_snapshot: Arc<RwLock<DebugSnapshot>>,
     memory_view_info: Arc<Mutex<MemoryViewInfo>>,
+    tilemap_view_info: Arc<Mutex<TilemapViewInfo>>,
     disassembly_info: Arc<Mutex<DisassemblyInfo>>,
-    registers_info: Arc<Mutex<Box<[RegisterValue]>>>,
-    cpu_info: Arc<Mutex<CpuInfo>>,
-    timer_info: Arc<Mutex<Box<[TimerInfo]>>>,
     breakpoints: Arc<Mutex<Vec<BreakpointInfo>>>,
+    // Addresses to pause on writes to, checked at the end of each frame.
+    watchpoints: Arc<Mutex<Vec<u32>>>,
+    cheats: Arc<Mutex<Vec<CheatInfo>>>,
+    memory_search: Arc<Mutex<Option<MemorySearch>>>,
+    search_width: SearchWidth,
+    search_value: String,
+    symbols: Arc<RwLock<Option<SymbolTable>>>,
     emulator_command_sender: Sender<EmulatorCommand>,
     step_count: u64,
-    cycles_executed: Arc<AtomicU64>,
     num_save_states: Arc<AtomicUsize>,
+    rom_library: RomLibrary,
+    solar_level: u8,
+    bus_profiling: bool,
+    dma_logging: bool,
+    dma_log_channels: [bool; 4],
+    heatmap_region: MemoryRegion,
+    color_correction: ColorCorrection,
+    dock_state: DockState<Panel>,
+    disassembly_go_to: String,
+    // The executing PC the disassembly was last scrolled to.
+    disassembly_followed_pc: Option<u32>,
+    // The IO register being edited, by address, and the hex typed in so far.
+    io_register_edit: Option<(u32, String)>,
+    // Keys held down through the on-screen keypad.
+    virtual_keys: KeysState,
+    autofire: AutofireConfig,
+    // None if there's no audio device to play on.
+    audio_player: Option<AudioPlayer>,
 }
 
 impl MyEguiApp {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
-        // Restore app state using cc.storage (requires the "persistence" feature).
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
         // for e.g. egui::PaintCallback.
 
-        let display_buffer = Arc::new(Mutex::new(
-            [[Rgb555::default(); Lcd::LCD_WIDTH]; Lcd::LCD_HEIGHT],
-        ));
+        let dock_state = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, DOCK_STATE_STORAGE_KEY))
+            .unwrap_or_else(panel::default_dock_state);
+        let autofire: AutofireConfig = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, AUTOFIRE_STORAGE_KEY))
+            .unwrap_or_default();
+
+        let (audio_player, audio_buffer_sender) = match AudioPlayer::new() {
+            Ok((audio_player, audio_buffer_sender)) => {
+                (Some(audio_player), Some(audio_buffer_sender))
+            }
+            Err(e) => {
+                println!("failed to open audio output: {e}");
+                (None, None)
+            }
+        };
+
+        let mut shared_debug_snapshot = SharedDebugSnapshot::new();
+        let debug_snapshot = shared_debug_snapshot.reader();
         let memory_view_info = Arc::new(Mutex::new(MemoryViewInfo {
             buffer: Box::new([0; 0x1000]),
             offset: 0x00000000,
         }));
+        let tilemap_view_info = Arc::new(Mutex::new(TilemapViewInfo {
+            background: 0,
+            map: None,
+        }));
         let disassembly_info = Arc::new(Mutex::new(DisassemblyInfo {
+            view_address: None,
+            start_address: 0x00000000,
+            executing_pc: 0x00000000,
+            instruction_set: InstructionSet::Arm,
             buffer: Box::new(array::from_fn(|_| Instruction::default())),
-            pc: 0x00000000,
-            instruction_width: 0,
         }));
-        let registers_info = Arc::new(Mutex::new(Box::new([]) as Box<[_]>));
-        let cpu_info = Arc::new(Mutex::new(CpuInfo::default()));
         let breakpoints = Arc::new(Mutex::new(Vec::<BreakpointInfo>::new()));
-        let timer_info = Arc::new(Mutex::new(Box::new([]) as Box<[_]>));
+        let watchpoints = Arc::new(Mutex::new(Vec::new()));
+        let cheats = Arc::new(Mutex::new(Vec::<CheatInfo>::new()));
+        let memory_search = Arc::new(Mutex::new(None));
+        let symbols = Arc::new(RwLock::new(None));
 
-        let cycles_executed = Arc::new(AtomicU64::new(0));
         let num_save_states = Arc::new(AtomicUsize::new(0));
 
         let (emulator_command_sender, emulator_command_receiver) = channel();
+        emulator_command_sender
+            .send(EmulatorCommand::SetAutofire(autofire))
+            .unwrap();
 
         {
-            let display_buffer = Arc::clone(&display_buffer);
-            let cycles_executed = Arc::clone(&cycles_executed);
             let memory_view_info = Arc::clone(&memory_view_info);
+            let tilemap_view_info = Arc::clone(&tilemap_view_info);
             let disassembly_info = Arc::clone(&disassembly_info);
-            let registers_info = Arc::clone(&registers_info);
-            let cpu_info = Arc::clone(&cpu_info);
             let breakpoints = Arc::clone(&breakpoints);
-            let timer_info = Arc::clone(&timer_info);
+            let watchpoints = Arc::clone(&watchpoints);
+            let cheats = Arc::clone(&cheats);
+            let memory_search = Arc::clone(&memory_search);
+            let symbols = Arc::clone(&symbols);
             let num_save_states = Arc::clone(&num_save_states);
 
             thread::spawn(move || {
-                let cartridge = Cartridge::new(
-                    include_bytes!("../../emulator-core/tests/suite.gba").as_slice(),
-                    None,
-                )
-                .unwrap();
-                let mut cpu = Cpu::new(cartridge);
+                // Nothing runs until a ROM is picked from the library or file dialog.
+                let mut cpu: Option<Cpu> = None;
                 let mut state = EmulatorState::Paused;
 
                 let mut save_states = Vec::new();
+                // Kept across ROM loads, like the brightness slider it comes from.
+                let mut solar_level = 0;
+                let mut bus_profiling = false;
+                let mut dma_logging = None;
+                // What the player holds, before turbo keys are applied.
+                let mut held_keys = KeysState::default();
+                let mut autofire = Autofire::default();
 
                 loop {
                     for command in emulator_command_receiver.try_iter() {
-                        match command {
-                            EmulatorCommand::Pause => state = EmulatorState::Paused,
-                            EmulatorCommand::Run => {
-                                // on run, ensure that we _always_ run at least one instruction
-                                let old_pc = cpu.get_executing_pc();
-                                while cpu.get_executing_pc() == old_pc {
-                                    cpu.fetch_decode_execute();
+                        if let EmulatorCommand::LoadRom(path) = command {
+                            // Picks up symbols from a devkitARM build next to the ROM, if any.
+                            *symbols.write().unwrap() = SymbolTable::load_alongside(&path);
+                            // Candidates from another game's memory mean nothing.
+                            *memory_search.lock().unwrap() = None;
+
+                            let file = match File::open(path) {
+                                Ok(file) => file,
+                                Err(e) => {
+                                    println!("{e:?}");
+                                    continue;
+                                }
+                            };
+
+                            let mut cartridge = match Cartridge::new(file, None) {
+                                Ok(cart) => cart,
+                                Err(e) => {
+                                    println!("{e:?}");
+                                    continue;
                                 }
-                                state = EmulatorState::Running
+                            };
+
+                            cartridge.set_solar_level(solar_level);
+                            let mut new_cpu = Cpu::new(cartridge);
+                            // Stop on anything the emulator can't handle, so it can be inspected.
+                            new_cpu.set_config(CpuConfig {
+                                error_policy: ErrorPolicy::Trap,
+                                ..new_cpu.config()
+                            });
+                            new_cpu
+                                .bus
+                                .set_profiler(bus_profiling.then(BusProfiler::with_pages));
+                            set_dma_logging(&mut new_cpu, dma_logging);
+                            if let Some(audio_buffer_sender) = &audio_buffer_sender {
+                                let audio_buffer = new_cpu.enable_audio_output(
+                                    audio::SAMPLE_RATE,
+                                    audio::BUFFER_CAPACITY,
+                                );
+                                audio_buffer_sender.send(audio_buffer).unwrap();
+                            }
+                            cpu = Some(new_cpu);
+                            continue;
+                        }
+
+                        if let EmulatorCommand::SetDmaLogging(channels) = command {
+                            dma_logging = channels;
+                            if let Some(cpu) = &mut cpu {
+                                set_dma_logging(cpu, channels);
+                            }
+                            continue;
+                        }
+
+                        if let EmulatorCommand::SetBusProfiling(enabled) = command {
+                            bus_profiling = enabled;
+                            if let Some(cpu) = &mut cpu {
+                                cpu.bus.set_profiler(enabled.then(BusProfiler::with_pages));
+                            }
+                            continue;
+                        }
+
+                        if let EmulatorCommand::SetAutofire(config) = command {
+                            autofire.set_config(config);
+                            continue;
+                        }
+
+                        if let EmulatorCommand::SetSolarLevel(level) = command {
+                            solar_level = level;
+                            if let Some(cpu) = &mut cpu {
+                                cpu.bus.cartridge.set_solar_level(level);
                             }
+                            continue;
+                        }
+
+                        let Some(cpu) = &mut cpu else {
+                            println!("ignoring {command:?}, no rom loaded");
+                            continue;
+                        };
+
+                        match command {
+                            EmulatorCommand::Pause => state = EmulatorState::Paused,
+                            // Resuming from a breakpoint is handled by the core, which always
+                            // executes the instruction it last stopped at.
+                            EmulatorCommand::Run => state = EmulatorState::Running,
                             EmulatorCommand::Step(count) => {
                                 for _ in 0..count {
-                                    cpu.fetch_decode_execute();
+                                    step_instruction(cpu);
                                 }
 
                                 state = EmulatorState::Paused
                             }
-                            EmulatorCommand::LoadRom(path) => {
-                                let file = match File::open(path) {
-                                    Ok(file) => file,
-                                    Err(e) => {
-                                        println!("{e:?}");
-                                        continue;
-                                    }
-                                };
-
-                                let cartridge = match Cartridge::new(file, None) {
-                                    Ok(cart) => cart,
-                                    Err(e) => {
-                                        println!("{e:?}");
-                                        continue;
+                            EmulatorCommand::StepOver => {
+                                let depth = cpu.call_stack().len();
+                                step_instruction(cpu);
+
+                                // If that made a call, run until it returns.
+                                let call_stack = cpu.call_stack();
+                                state = match call_stack.first() {
+                                    Some(frame) if call_stack.len() > depth => {
+                                        EmulatorState::RunningTo(RunTarget::Return {
+                                            address: frame.return_address,
+                                            depth,
+                                        })
                                     }
+                                    _ => EmulatorState::Paused,
                                 };
-
-                                cpu = Cpu::new(cartridge);
                             }
-                            EmulatorCommand::KeyPressed(key) => {
-                                cpu.bus.keypad.set_pressed(key, true)
+                            EmulatorCommand::StepOut => {
+                                let call_stack = cpu.call_stack();
+                                match call_stack.first() {
+                                    Some(frame) => {
+                                        state = EmulatorState::RunningTo(RunTarget::Return {
+                                            address: frame.return_address,
+                                            depth: call_stack.len() - 1,
+                                        })
+                                    }
+                                    None => println!("not in a call, nothing to step out of"),
+                                }
                             }
-                            EmulatorCommand::KeyReleased(key) => {
-                                cpu.bus.keypad.set_pressed(key, false)
+                            EmulatorCommand::RunTo(address) => {
+                                state = EmulatorState::RunningTo(RunTarget::Address(address))
                             }
+                            EmulatorCommand::LoadRom(_)
+                            | EmulatorCommand::SetSolarLevel(_)
+                            | EmulatorCommand::SetBusProfiling(_)
+                            | EmulatorCommand::SetAutofire(_)
+                            | EmulatorCommand::SetDmaLogging(_) => unreachable!(),
+                            // Applied from the next frame boundary, see `InputTiming::Frame`.
+                            EmulatorCommand::KeyPressed(key) => held_keys.set_pressed(key, true),
+                            EmulatorCommand::KeyReleased(key) => held_keys.set_pressed(key, false),
                             EmulatorCommand::CreateNewSaveState => {
                                 let new_save_state = cpu.clone();
                                 save_states.push(new_save_state);
@@ -235,143 +512,209 @@ impl MyEguiApp {
                                 let new_save_state = cpu.clone();
                                 save_states[idx] = new_save_state;
                             }
+                            EmulatorCommand::ResetPerfCounters => cpu.reset_perf_counters(),
+                            EmulatorCommand::ResetBusProfiler => {
+                                if cpu.bus.profiler().is_some() {
+                                    cpu.bus.set_profiler(Some(BusProfiler::with_pages()));
+                                }
+                            }
                             EmulatorCommand::LoadSaveState(idx) => {
                                 if idx > save_states.len() {
                                     panic!("got a request to load save state at index {}, but only have {} indices available", idx, save_states.len());
                                 }
 
-                                cpu = save_states[idx].clone();
+                                *cpu = save_states[idx].clone();
+                                cpu.bus.cartridge.set_solar_level(solar_level);
+                            }
+                            EmulatorCommand::StartMemorySearch(width) => {
+                                *memory_search.lock().unwrap() =
+                                    Some(MemorySearch::new(&cpu.bus, width));
                             }
+                            EmulatorCommand::FilterMemorySearch(filter) => {
+                                if let Some(search) = memory_search.lock().unwrap().as_mut() {
+                                    search.filter(&cpu.bus, filter);
+                                }
+                            }
+                            EmulatorCommand::WriteIo { address, .. }
+                                if !matches!(state, EmulatorState::Paused) =>
+                            {
+                                println!("ignoring write to {address:08X}, pause first")
+                            }
+                            EmulatorCommand::WriteIo {
+                                address,
+                                size,
+                                value,
+                            } => match size {
+                                1 => cpu.bus.write_byte_address_debug(value as u8, address),
+                                2 => cpu.bus.write_halfword_address_debug(value as u16, address),
+                                _ => cpu.bus.write_word_address_debug(value, address),
+                            },
                         }
                     }
 
+                    let Some(cpu) = &mut cpu else {
+                        thread::sleep(Duration::from_millis(10));
+                        continue;
+                    };
+
                     match state {
-                        EmulatorState::Running => {
-                            let cycle_start = cpu.bus.cycle_count();
-                            'frame_loop: while (cpu.bus.cycle_count() - cycle_start)
-                                < (CYCLES_PER_SECOND / 60)
-                            {
-                                for breakpoint in breakpoints.lock().unwrap().iter_mut() {
-                                    if breakpoint.active
-                                        && breakpoint.address == cpu.get_executing_pc()
-                                    {
-                                        state = EmulatorState::Paused;
-                                        break 'frame_loop; // if we hit a breakpoint, immediately stop executing for this frame
-                                    }
+                        EmulatorState::Running | EmulatorState::RunningTo(_) => {
+                            cpu.clear_breakpoints();
+                            for breakpoint in breakpoints.lock().unwrap().iter() {
+                                if breakpoint.active {
+                                    cpu.add_breakpoint(breakpoint.address);
                                 }
-                                cpu.fetch_decode_execute();
                             }
-                        }
-                        EmulatorState::Paused => {}
-                    }
+                            let user_breakpoints = cpu.get_breakpoints().to_vec();
 
-                    {
-                        display_buffer
-                            .lock()
-                            .unwrap()
-                            .copy_from_slice(cpu.bus.lcd.get_buffer());
-                        {
-                            let mut memory_view_info_lock = memory_view_info.lock().unwrap();
-                            for offset in 0..memory_view_info_lock.buffer.len() {
-                                memory_view_info_lock.buffer[offset] =
-                                    cpu.bus.read_byte_address_debug(
-                                        memory_view_info_lock.offset + (offset as u32),
-                                    )
+                            // A temporary breakpoint, which only lasts as long as this run.
+                            let run_target = match state {
+                                EmulatorState::RunningTo(run_target) => Some(run_target),
+                                _ => None,
+                            };
+                            if let Some(run_target) = run_target {
+                                cpu.add_breakpoint(run_target.address());
                             }
-                        }
 
-                        {
-                            let executing_pc = cpu.get_executing_pc();
+                            let watchpoints_lock = watchpoints.lock().unwrap();
+                            if watchpoints_lock.is_empty() {
+                                cpu.bus.set_access_watch(None);
+                            } else {
+                                let mut access_watch = AccessWatch::new();
+                                for address in watchpoints_lock.iter() {
+                                    access_watch.watch(AccessKind::Write, *address);
+                                }
+                                cpu.bus.set_access_watch(Some(access_watch));
+                            }
+                            drop(watchpoints_lock);
 
-                            let mut disassembly_info_lock = disassembly_info.lock().unwrap();
-                            let instruction_width = cpu.get_instruction_width();
-                            for offset in 0..disassembly_info_lock.buffer.len() {
-                                disassembly_info_lock.buffer[offset] = cpu.disassemble(
-                                    executing_pc + ((offset as u32) * instruction_width),
-                                );
+                            for cheat in cheats.lock().unwrap().iter() {
+                                if cheat.active {
+                                    cheat.apply(cpu);
+                                }
                             }
-                            disassembly_info_lock.instruction_width = cpu.get_instruction_width();
-                            disassembly_info_lock.pc = executing_pc;
-                        }
 
-                        {
-                            const REGISTERS_TO_READ: &[Register] = &[
-                                Register::R0,
-                                Register::R1,
-                                Register::R2,
-                                Register::R3,
-                                Register::R4,
-                                Register::R5,
-                                Register::R6,
-                                Register::R7,
-                                Register::R8,
-                                Register::R9,
-                                Register::R10,
-                                Register::R11,
-                                Register::R12,
-                                Register::R13,
-                                Register::R14,
-                                Register::R15,
-                            ];
-
-                            let register_values = REGISTERS_TO_READ
-                                .iter()
-                                .map(|&register| {
-                                    let value = cpu.read_register(register, |pc| pc);
-                                    RegisterValue { register, value }
-                                })
-                                .collect::<Box<_>>();
+                            let next_frame = cpu.bus.lcd.frame_count() + 1;
+                            cpu.bus.queue_input(
+                                InputTiming::Frame(next_frame),
+                                autofire.apply(held_keys),
+                            );
 
-                            *registers_info.lock().unwrap() = register_values;
+                            let stop_event = cpu.run_frame(FRAMES_PER_SECOND, |step_event| {
+                                matches!(
+                                    step_event,
+                                    StepEvent::BreakpointHit { .. }
+                                        | StepEvent::BkptHit { .. }
+                                        | StepEvent::InvalidOpcode { .. }
+                                        | StepEvent::Error(_)
+                                )
+                            });
+
+                            match stop_event {
+                                // At the target, but still inside a recursive call.
+                                Some(StepEvent::BreakpointHit { address })
+                                    if run_target.is_some_and(|run_target| {
+                                        run_target.address() == address && !run_target.reached(cpu)
+                                    }) && !user_breakpoints.contains(&address) => {}
+                                Some(StepEvent::BkptHit { address, comment }) => {
+                                    println!("bkpt #{comment} hit at {address:08X}");
+                                    state = EmulatorState::Paused;
+                                }
+                                Some(StepEvent::Error(error)) => {
+                                    println!("stopped: {error}");
+                                    state = EmulatorState::Paused;
+                                }
+                                Some(_) => state = EmulatorState::Paused,
+                                None => {}
+                            }
+
+                            let watch_hits = cpu
+                                .bus
+                                .access_watch_mut()
+                                .map(AccessWatch::take_hits)
+                                .unwrap_or_default();
+                            if let Some(hit) = watch_hits.first() {
+                                println!("watchpoint hit, {:08X} written", hit.address);
+                                state = EmulatorState::Paused;
+                            }
                         }
+                        EmulatorState::Paused => {}
+                    }
 
-                        {
-                            let new_cpu_info = CpuInfo {
-                                sign_flag: cpu.get_sign_flag(),
-                                zero_flag: cpu.get_zero_flag(),
-                                carry_flag: cpu.get_carry_flag(),
-                                overflow_flag: cpu.get_overflow_flag(),
-                                irq_disable: cpu.get_irq_disable(),
-                                fiq_disable: cpu.get_fiq_disable(),
-                                instruction_mode: cpu.get_instruction_mode(),
-                                cpu_mode: cpu.get_cpu_mode(),
-                                irq_buffer: cpu.bus.get_interrupt_request_debug(),
-                                open_bus_data: cpu.bus.open_bus_data,
-                            };
-                            *cpu_info.lock().unwrap() = new_cpu_info;
+                    shared_debug_snapshot.publish(cpu);
+
+                    {
+                        let mut memory_view_info_lock = memory_view_info.lock().unwrap();
+                        for offset in 0..memory_view_info_lock.buffer.len() {
+                            memory_view_info_lock.buffer[offset] = cpu.bus.read_byte_address_debug(
+                                memory_view_info_lock.offset + (offset as u32),
+                            )
                         }
                     }
 
                     {
-                        let timer_infos = cpu
+                        let mut tilemap_view_info_lock = tilemap_view_info.lock().unwrap();
+                        tilemap_view_info_lock.map = cpu
                             .bus
-                            .timers
-                            .iter()
-                            .map(|timer| TimerInfo {
-                                counter: timer.get_current_counter(),
-                                reload: timer.get_current_reload(),
-                            })
-                            .collect::<Box<[_]>>();
-
-                        *timer_info.lock().unwrap() = timer_infos;
+                            .lcd
+                            .background_map(tilemap_view_info_lock.background);
+                    }
+
+                    {
+                        let executing_pc = cpu.get_executing_pc();
+
+                        let mut disassembly_info_lock = disassembly_info.lock().unwrap();
+                        disassembly_info_lock.executing_pc = executing_pc;
+                        disassembly_info_lock.instruction_set = cpu.get_instruction_mode();
+
+                        // Rows before the center address are shown too, so there's context
+                        // leading up to it.
+                        let instruction_width = disassembly_info_lock.instruction_width();
+                        let center_address =
+                            disassembly_info_lock.view_address.unwrap_or(executing_pc)
+                                & !(instruction_width - 1);
+                        let center_row = disassembly_info_lock.buffer.len() / 2;
+                        disassembly_info_lock.start_address =
+                            center_address.wrapping_sub(instruction_width * (center_row as u32));
+
+                        for row in 0..disassembly_info_lock.buffer.len() {
+                            let address = disassembly_info_lock.address(row);
+                            disassembly_info_lock.buffer[row] = cpu.disassemble(address);
+                        }
                     }
-                    cycles_executed.store(cpu.bus.cycle_count(), Ordering::SeqCst);
                 }
             });
         }
 
         Self {
-            display_buffer,
+            debug_snapshot,
             emulator_command_sender,
             step_count: 1,
-            cycles_executed,
             memory_view_info,
+            tilemap_view_info,
             disassembly_info,
-            registers_info,
-            cpu_info,
-            timer_info,
             breakpoints,
+            watchpoints,
+            cheats,
+            memory_search,
+            search_width: SearchWidth::default(),
+            search_value: String::new(),
+            symbols,
             num_save_states,
+            rom_library: RomLibrary::load(),
+            solar_level: 0,
+            bus_profiling: false,
+            dma_logging: false,
+            dma_log_channels: [true; 4],
+            heatmap_region: MemoryRegion::ChipWram,
+            color_correction: ColorCorrection::default(),
+            dock_state,
+            disassembly_go_to: String::new(),
+            disassembly_followed_pc: None,
+            io_register_edit: None,
+            virtual_keys: KeysState::default(),
+            autofire,
+            audio_player,
         }
     }
 }
@@ -392,11 +735,13 @@ impl MyEguiApp {
 
         if ui.button("Choose ROM").clicked() {
             let sender = self.emulator_command_sender.clone();
+            let notify_rom_opened = self.rom_library.rom_opened_notifier();
             thread::spawn(move || {
                 if let Some(file) = FileDialog::new()
-                    .add_filter("GBA ROM", &["gba"])
+                    .add_filter("GBA ROM", ROM_EXTENSIONS)
                     .pick_file()
                 {
+                    notify_rom_opened(file.clone());
                     sender.send(EmulatorCommand::LoadRom(file)).unwrap();
                 } else {
                     println!("user cancelled file selection");
@@ -404,6 +749,80 @@ impl MyEguiApp {
             });
         }
 
+        if ui.button("Load Symbols").clicked() {
+            let symbols = Arc::clone(&self.symbols);
+            thread::spawn(move || {
+                if let Some(file) = FileDialog::new()
+                    .add_filter("Symbols", &["elf", "map", "sym"])
+                    .pick_file()
+                {
+                    match SymbolTable::load(&file) {
+                        Ok(loaded) => *symbols.write().unwrap() = Some(loaded),
+                        Err(e) => println!("failed to load symbols: {e}"),
+                    }
+                } else {
+                    println!("user cancelled file selection");
+                }
+            });
+        }
+
+        if ui.button("Save Screenshot").clicked() {
+            let display_buffer = *self.debug_snapshot.read().unwrap().frame_buffer;
+            thread::spawn(move || {
+                if let Some(file) = FileDialog::new()
+                    .add_filter("PNG Image", &["png"])
+                    .add_filter("Raw RGB555", &["rgb555"])
+                    .save_file()
+                {
+                    match save_screenshot(&display_buffer, &file) {
+                        Ok(()) => println!("saved screenshot to {}", file.display()),
+                        Err(e) => println!("failed to save screenshot: {e}"),
+                    }
+                } else {
+                    println!("user cancelled file selection");
+                }
+            });
+        }
+
+        if let Some(audio_player) = &mut self.audio_player {
+            ui.horizontal(|ui| {
+                let mut muted = audio_player.muted();
+                if ui.checkbox(&mut muted, "Mute").changed() {
+                    audio_player.set_muted(muted);
+                }
+
+                let mut volume = audio_player.volume();
+                if ui
+                    .add(Slider::new(&mut volume, 0.0..=1.0).text("Volume"))
+                    .changed()
+                {
+                    audio_player.set_volume(volume);
+                }
+            });
+        }
+
+        ComboBox::from_label("Color Correction")
+            .selected_text(self.color_correction.to_string())
+            .show_ui(ui, |ui| {
+                for correction in ColorCorrection::ALL {
+                    ui.selectable_value(
+                        &mut self.color_correction,
+                        correction,
+                        correction.to_string(),
+                    );
+                }
+            });
+
+        // Only affects games with a solar sensor, like Boktai.
+        if ui
+            .add(Slider::new(&mut self.solar_level, 0..=u8::MAX).text("Sunlight"))
+            .changed()
+        {
+            self.emulator_command_sender
+                .send(EmulatorCommand::SetSolarLevel(self.solar_level))
+                .unwrap();
+        }
+
         if ui.button("Create Save State").clicked() {
             self.emulator_command_sender
                 .send(EmulatorCommand::CreateNewSaveState)
@@ -434,17 +853,14 @@ impl MyEguiApp {
 
     fn emulator_window(&self, ui: &mut Ui) {
         let rgb_data = self
-            .display_buffer
-            .lock()
+            .debug_snapshot
+            .read()
             .unwrap()
+            .frame_buffer
             .iter()
             .flat_map(|row| {
-                row.iter().flat_map(|pixel| {
-                    let red = (pixel.red() << 3) | (pixel.red() >> 2);
-                    let green = (pixel.green() << 3) | (pixel.green() >> 2);
-                    let blue = (pixel.blue() << 3) | (pixel.blue() >> 2);
-                    [red, green, blue]
-                })
+                row.iter()
+                    .flat_map(|pixel| self.color_correction.apply(*pixel))
             })
             .collect::<Vec<_>>();
 
@@ -507,61 +923,232 @@ impl MyEguiApp {
         });
     }
 
-    fn disassembler(&self, ui: &mut Ui) {
-        let mut view_string = String::new();
-        {
-            let disassembly_info_lock = self.disassembly_info.lock().unwrap();
-            for (offset, instruction) in disassembly_info_lock.buffer.iter().enumerate() {
-                let address = disassembly_info_lock.pc
-                    + (disassembly_info_lock.instruction_width * (offset as u32));
-                view_string.push_str(&format!("{:08X}: {}\n", address, instruction));
+    fn tilemap_viewer(&self, ui: &mut Ui) {
+        let mut tilemap_view_info_lock = self.tilemap_view_info.lock().unwrap();
+
+        ui.horizontal(|ui| {
+            for background in 0..4 {
+                ui.selectable_value(
+                    &mut tilemap_view_info_lock.background,
+                    background,
+                    format!("BG{background}"),
+                );
+            }
+        });
+
+        let Some(map) = &tilemap_view_info_lock.map else {
+            ui.label("not shown as a tilemap in the current mode");
+            return;
+        };
+
+        // Transparent pixels are drawn as a checkerboard, so they stand out from black ones.
+        let rgb_data = map
+            .pixels
+            .iter()
+            .enumerate()
+            .flat_map(|(i, pixel)| match pixel {
+                Some(pixel) => self.color_correction.apply(*pixel),
+                None => {
+                    let checker = ((i % map.width) / 8 + (i / map.width) / 8) % 2;
+                    [[64; 3], [96; 3]][checker]
+                }
+            })
+            .collect::<Vec<_>>();
+        let image = ColorImage::from_rgb([map.width, map.height], &rgb_data);
+        let texture = ui
+            .ctx()
+            .load_texture("tilemap-texture", image, TextureOptions::NEAREST);
+
+        let scale = (ui.available_width() / map.width as f32).max(0.25);
+        let (rect, _) = ui.allocate_exact_size(
+            vec2(map.width as f32, map.height as f32) * scale,
+            Sense::hover(),
+        );
+        let painter = ui.painter_at(rect);
+        painter.image(
+            texture.id(),
+            rect,
+            Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+            Color32::WHITE,
+        );
+
+        let to_screen = |(x, y): (f64, f64)| rect.min + (vec2(x as f32, y as f32) * scale);
+        let stroke = Stroke::new(2.0, Color32::YELLOW);
+        match map.viewport {
+            // The screen wraps around the map's edges, so is drawn again a map's width and/or
+            // height back, for the parts that wrapped.
+            BackgroundViewport::Text { x, y } => {
+                let (width, height) = (map.width as f64, map.height as f64);
+                for (x_wrap, y_wrap) in [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)] {
+                    let min = (f64::from(x) - x_wrap, f64::from(y) - y_wrap);
+                    let max = (
+                        min.0 + Lcd::LCD_WIDTH as f64,
+                        min.1 + Lcd::LCD_HEIGHT as f64,
+                    );
+                    painter.rect_stroke(
+                        Rect::from_min_max(to_screen(min), to_screen(max)),
+                        0.0,
+                        stroke,
+                    );
+                }
+            }
+            BackgroundViewport::Affine { corners } => {
+                painter.add(Shape::closed_line(
+                    corners.iter().copied().map(to_screen).collect(),
+                    stroke,
+                ));
             }
         }
+    }
 
-        ScrollArea::vertical().show(ui, |ui| {
-            ui.add(
-                TextEdit::multiline(&mut view_string)
-                    .interactive(false)
-                    .font(TextStyle::Monospace)
-                    .layouter(&mut |ui, val, _wrap_width| {
-                        ui.fonts(|fonts| {
-                            fonts.layout_no_wrap(
-                                val.to_string(),
-                                TextStyle::Monospace.resolve(ui.style()),
-                                ui.visuals().widgets.inactive.text_color(),
-                            )
-                        })
-                    }),
+    fn disassembler(&mut self, ui: &mut Ui) {
+        let mut disassembly_info_lock = self.disassembly_info.lock().unwrap();
+        let symbols_lock = self.symbols.read().unwrap();
+        let mut breakpoints_lock = self.breakpoints.lock().unwrap();
+
+        let mut scroll_to_center = false;
+        ui.horizontal(|ui| {
+            let mut follow_pc = disassembly_info_lock.view_address.is_none();
+            if ui.checkbox(&mut follow_pc, "Follow PC").changed() {
+                disassembly_info_lock.view_address =
+                    (!follow_pc).then_some(disassembly_info_lock.executing_pc);
+                scroll_to_center = true;
+            }
+
+            // Takes a hex address or a symbol name.
+            let go_to = ui.add(
+                TextEdit::singleline(&mut self.disassembly_go_to)
+                    .hint_text("Go to address")
+                    .desired_width(120.0),
             );
+            if go_to.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                let text = self.disassembly_go_to.trim();
+                let address = u32::from_str_radix(text.trim_start_matches("0x"), 16)
+                    .ok()
+                    .or_else(|| {
+                        symbols_lock
+                            .as_ref()
+                            .and_then(|symbols| symbols.address_of(text))
+                    });
+
+                match address {
+                    Some(address) => {
+                        disassembly_info_lock.view_address = Some(address);
+                        scroll_to_center = true;
+                    }
+                    None => println!("not an address or known symbol: {text}"),
+                }
+            }
+
+            ui.label(match disassembly_info_lock.instruction_set {
+                InstructionSet::Arm => "ARM",
+                InstructionSet::Thumb => "Thumb",
+            });
+        });
+
+        // Keeps the PC in view as it moves, while still allowing scrolling around when stopped.
+        let executing_pc = disassembly_info_lock.executing_pc;
+        if disassembly_info_lock.view_address.is_none()
+            && self.disassembly_followed_pc != Some(executing_pc)
+        {
+            self.disassembly_followed_pc = Some(executing_pc);
+            scroll_to_center = true;
+        }
+
+        let row_height = ui.text_style_height(&TextStyle::Monospace) + ui.spacing().item_spacing.y;
+        let num_rows = disassembly_info_lock.buffer.len();
+        let mut scroll_area = ScrollArea::vertical().auto_shrink([false; 2]);
+        if scroll_to_center {
+            let center_row = num_rows / 2;
+            let offset = (center_row as f32 * row_height) - (ui.available_height() / 2.0);
+            scroll_area = scroll_area.vertical_scroll_offset(offset.max(0.0));
+        }
+
+        scroll_area.show_rows(ui, row_height, num_rows, |ui, rows| {
+            for row in rows {
+                let address = disassembly_info_lock.address(row);
+                let instruction = &disassembly_info_lock.buffer[row];
+
+                ui.horizontal(|ui| {
+                    let breakpoint_index = breakpoints_lock
+                        .iter()
+                        .position(|breakpoint| breakpoint.address == address);
+                    let mut has_breakpoint =
+                        breakpoint_index.is_some_and(|index| breakpoints_lock[index].active);
+
+                    if ui.checkbox(&mut has_breakpoint, "").changed() {
+                        match breakpoint_index {
+                            Some(index) => breakpoints_lock[index].active = has_breakpoint,
+                            None => breakpoints_lock.push(BreakpointInfo {
+                                address,
+                                active: true,
+                            }),
+                        }
+                    }
+
+                    let mut text =
+                        RichText::new(format!("{address:08X}: {instruction}")).monospace();
+                    if address == executing_pc {
+                        text = text.background_color(ui.visuals().selection.bg_fill);
+                    }
+                    ui.add(Label::new(text).sense(Sense::click()))
+                        .context_menu(|ui| {
+                            if ui.button("Run To Cursor").clicked() {
+                                self.emulator_command_sender
+                                    .send(EmulatorCommand::RunTo(address))
+                                    .unwrap();
+                                ui.close_menu();
+                            }
+                        });
+
+                    // Label the start of each symbol, like an assembly listing.
+                    if let Some((symbol, 0)) = symbols_lock
+                        .as_ref()
+                        .and_then(|symbols| symbols.lookup(address))
+                    {
+                        ui.label(
+                            RichText::new(format!("<{}>", symbol.name))
+                                .monospace()
+                                .weak(),
+                        );
+                    }
+                });
+            }
         });
     }
 
     fn register_info(&self, ui: &mut Ui) {
-        let registers_info_lock = self.registers_info.lock().unwrap();
-        for register_info in registers_info_lock.iter() {
+        let debug_snapshot_lock = self.debug_snapshot.read().unwrap();
+        for (i, value) in debug_snapshot_lock.registers.iter().enumerate() {
             ui.horizontal(|ui| {
-                ui.label(&format!("{}", register_info.register));
-                ui.add(
-                    TextEdit::singleline(&mut format!("{:08X}", register_info.value))
-                        .interactive(false),
-                );
+                ui.label(format!("{}", Register::from_index(i as u32)));
+                ui.add(TextEdit::singleline(&mut format!("{:08X}", value)).interactive(false));
             });
         }
     }
 
     fn cpu_info(&self, ui: &mut Ui) {
+        let cpu_info_lock = self.debug_snapshot.read().unwrap();
+
         ui.horizontal(|ui| {
             ui.label("CPU Cycles");
+            ui.add(
+                TextEdit::singleline(&mut format!("{}", cpu_info_lock.cycle_count))
+                    .interactive(false),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("PPU position");
             ui.add(
                 TextEdit::singleline(&mut format!(
-                    "{}",
-                    self.cycles_executed.load(Ordering::SeqCst)
+                    "line {}, dot {}",
+                    cpu_info_lock.scanline, cpu_info_lock.scanline_dot
                 ))
                 .interactive(false),
             );
         });
 
-        let cpu_info_lock = self.cpu_info.lock().unwrap();
         let info_fields: [(&str, &dyn Debug); 8] = [
             ("sign flag", &cpu_info_lock.sign_flag),
             ("zero flag", &cpu_info_lock.zero_flag),
@@ -605,9 +1192,7 @@ impl MyEguiApp {
         CollapsingHeader::new("Timers")
             .default_open(true)
             .show(ui, |ui| {
-                let timer_info_lock = self.timer_info.lock().unwrap();
-
-                for (i, info) in timer_info_lock.iter().enumerate() {
+                for (i, info) in cpu_info_lock.timers.iter().enumerate() {
                     ui.collapsing(format!("Timer {}", i), |ui| {
                         ui.horizontal(|ui| {
                             ui.label("Counter");
@@ -629,6 +1214,602 @@ impl MyEguiApp {
             });
     }
 
+    // Buttons stay pressed for as long as they're held with the mouse or a finger, so this works
+    // on touch screens and without the keyboard focus the emulator window otherwise needs.
+    fn virtual_keypad(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            self.virtual_key(ui, Key::L, "L");
+            ui.add_space(120.0);
+            self.virtual_key(ui, Key::R, "R");
+        });
+
+        ui.horizontal(|ui| {
+            Grid::new("virtual_dpad").show(ui, |ui| {
+                ui.label("");
+                self.virtual_key(ui, Key::Up, "⏶");
+                ui.end_row();
+
+                self.virtual_key(ui, Key::Left, "⏴");
+                ui.label("");
+                self.virtual_key(ui, Key::Right, "⏵");
+                ui.end_row();
+
+                ui.label("");
+                self.virtual_key(ui, Key::Down, "⏷");
+                ui.end_row();
+            });
+
+            ui.add_space(20.0);
+            self.virtual_key(ui, Key::B, "B");
+            self.virtual_key(ui, Key::A, "A");
+        });
+
+        ui.horizontal(|ui| {
+            self.virtual_key(ui, Key::Select, "Select");
+            self.virtual_key(ui, Key::Start, "Start");
+        });
+
+        // Applies to keyboard input too.
+        ui.separator();
+        ui.label("Turbo");
+        let mut changed = false;
+        ui.horizontal_wrapped(|ui| {
+            for key in VIRTUAL_KEYPAD_KEYS {
+                let mut turbo = self.autofire.keys.is_pressed(key);
+                if ui.checkbox(&mut turbo, format!("{key:?}")).changed() {
+                    self.autofire.keys.set_pressed(key, turbo);
+                    changed = true;
+                }
+            }
+        });
+        changed |= ui
+            .add(
+                DragValue::new(&mut self.autofire.period_frames)
+                    .clamp_range(1..=30)
+                    .suffix(" frames"),
+            )
+            .on_hover_text("How long turbo keys stay pressed, then released")
+            .changed();
+
+        if changed {
+            self.emulator_command_sender
+                .send(EmulatorCommand::SetAutofire(self.autofire))
+                .unwrap();
+        }
+    }
+
+    fn virtual_key(&mut self, ui: &mut Ui, key: Key, text: &str) {
+        let held = self.virtual_keys.is_pressed(key);
+        let response = ui.add(Button::new(text).min_size(vec2(32.0, 32.0)).selected(held));
+
+        let pressed = response.is_pointer_button_down_on();
+        if pressed == held {
+            return;
+        }
+
+        self.virtual_keys.set_pressed(key, pressed);
+        let command = if pressed {
+            EmulatorCommand::KeyPressed(key)
+        } else {
+            EmulatorCommand::KeyReleased(key)
+        };
+        self.emulator_command_sender.send(command).unwrap();
+    }
+
+    fn io_registers(&mut self, ui: &mut Ui) {
+        let debug_snapshot = Arc::clone(&self.debug_snapshot);
+        let debug_snapshot_lock = debug_snapshot.read().unwrap();
+
+        ScrollArea::vertical().show(ui, |ui| {
+            for register in &debug_snapshot_lock.io_registers {
+                if register.fields.is_empty() {
+                    ui.horizontal(|ui| self.io_register_header(ui, register));
+                    continue;
+                }
+
+                CollapsingState::load_with_default_open(
+                    ui.ctx(),
+                    ui.make_persistent_id(register.address),
+                    false,
+                )
+                .show_header(ui, |ui| self.io_register_header(ui, register))
+                .body(|ui| {
+                    for field in &register.fields {
+                        ui.horizontal(|ui| {
+                            ui.label(format!(
+                                "{} [{}:{}]",
+                                field.name,
+                                field.bits.end(),
+                                field.bits.start()
+                            ));
+                            ui.add(
+                                TextEdit::singleline(&mut format!("{}", field.value))
+                                    .interactive(false),
+                            );
+                        });
+                    }
+                });
+            }
+        });
+    }
+
+    // Double-clicking the value edits it, Enter writes it to the register.
+    fn io_register_header(&mut self, ui: &mut Ui, register: &IoRegisterInfo) {
+        let width = register.size as usize * 2;
+        ui.monospace(format!("{:08X} {:<12}", register.address, register.name));
+
+        match &mut self.io_register_edit {
+            Some((address, text)) if *address == register.address => {
+                let edit = ui.add(
+                    TextEdit::singleline(text)
+                        .font(TextStyle::Monospace)
+                        .desired_width(80.0),
+                );
+                edit.request_focus();
+
+                if edit.lost_focus() {
+                    if ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                        match u32::from_str_radix(text.trim().trim_start_matches("0x"), 16) {
+                            Ok(value) => self
+                                .emulator_command_sender
+                                .send(EmulatorCommand::WriteIo {
+                                    address: register.address,
+                                    size: register.size,
+                                    value,
+                                })
+                                .unwrap(),
+                            Err(_) => println!("not a hex value: {text}"),
+                        }
+                    }
+                    self.io_register_edit = None;
+                }
+            }
+            _ => {
+                let value = match register.value {
+                    Some(value) => format!("{value:0width$X}"),
+                    None => "write only".to_string(),
+                };
+
+                let label =
+                    ui.add(Label::new(RichText::new(value).monospace()).sense(Sense::click()));
+                if label.double_clicked() {
+                    let text = register
+                        .value
+                        .map(|value| format!("{value:0width$X}"))
+                        .unwrap_or_default();
+                    self.io_register_edit = Some((register.address, text));
+                }
+            }
+        }
+    }
+
+    fn dma_info(&mut self, ui: &mut Ui) {
+        let debug_snapshot_lock = self.debug_snapshot.read().unwrap();
+
+        for (i, info) in debug_snapshot_lock.dma.iter().enumerate() {
+            CollapsingHeader::new(format!("DMA {}", i))
+                .default_open(true)
+                .show(ui, |ui| {
+                    let start_timing = dma_start_timing_name(i, info.start_timing);
+                    let transfer_size = if info.word_transfer {
+                        "32 bit"
+                    } else {
+                        "16 bit"
+                    };
+
+                    let info_fields: [(&str, String); 10] = [
+                        ("enabled", format!("{}", info.enabled)),
+                        ("source", format!("{:08X}", info.source_addr)),
+                        ("dest", format!("{:08X}", info.dest_addr)),
+                        ("count", format!("{:04X}", info.word_count)),
+                        ("timing", start_timing),
+                        ("repeat", format!("{}", info.repeat)),
+                        ("transfer size", transfer_size.to_string()),
+                        (
+                            "transfers completed",
+                            format!("{}", info.stats.transfers_completed),
+                        ),
+                        (
+                            "units transferred",
+                            format!("{}", info.stats.units_transferred),
+                        ),
+                        ("cycles consumed", format!("{}", info.stats.cycles_consumed)),
+                    ];
+
+                    for (name, mut value) in info_fields {
+                        ui.horizontal(|ui| {
+                            ui.label(name);
+                            ui.add(TextEdit::singleline(&mut value).interactive(false));
+                        });
+                    }
+                });
+        }
+        drop(debug_snapshot_lock);
+
+        CollapsingHeader::new("Transfer Log")
+            .default_open(true)
+            .show(ui, |ui| self.dma_log(ui));
+    }
+
+    fn interrupt_history(&self, ui: &mut Ui) {
+        let debug_snapshot_lock = self.debug_snapshot.read().unwrap();
+        let symbols_lock = self.symbols.read().unwrap();
+
+        ScrollArea::vertical().show(ui, |ui| {
+            Grid::new("interrupt_history").striped(true).show(ui, |ui| {
+                for heading in ["cycle", "sources", "IE", "IF", "IME", "interrupted pc"] {
+                    ui.label(heading);
+                }
+                ui.end_row();
+
+                // Newest first.
+                for event in debug_snapshot_lock.interrupt_history.iter().rev() {
+                    ui.monospace(event.cycle.to_string());
+                    ui.label(event.source_names().join(", "));
+                    ui.monospace(format!("{:04X}", event.interrupt_enable));
+                    ui.monospace(format!("{:04X}", event.interrupt_request));
+                    ui.monospace(format!("{}", u8::from(event.interrupt_master_enable)));
+                    match symbols_lock
+                        .as_ref()
+                        .and_then(|symbols| symbols.describe(event.pc))
+                    {
+                        Some(name) => ui.monospace(format!("{:08X} <{name}>", event.pc)),
+                        None => ui.monospace(format!("{:08X}", event.pc)),
+                    };
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    fn dma_log(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let mut changed = ui
+                .checkbox(&mut self.dma_logging, "Log Transfers")
+                .changed();
+            for (channel, enabled) in self.dma_log_channels.iter_mut().enumerate() {
+                changed |= ui.checkbox(enabled, format!("DMA {channel}")).changed();
+            }
+
+            if changed {
+                self.emulator_command_sender
+                    .send(EmulatorCommand::SetDmaLogging(
+                        self.dma_logging.then_some(self.dma_log_channels),
+                    ))
+                    .unwrap();
+            }
+        });
+
+        let debug_snapshot_lock = self.debug_snapshot.read().unwrap();
+        let Some(dma_log) = &debug_snapshot_lock.dma_log else {
+            return;
+        };
+
+        ScrollArea::vertical().id_source("dma_log").show(ui, |ui| {
+            Grid::new("dma_log").striped(true).show(ui, |ui| {
+                for heading in ["cycle", "channel", "source", "dest", "count", "timing"] {
+                    ui.label(heading);
+                }
+                ui.end_row();
+
+                // Newest first.
+                for transfer in dma_log.transfers().iter().rev() {
+                    ui.monospace(transfer.cycle.to_string());
+                    ui.monospace(transfer.channel.to_string());
+                    ui.monospace(format!("{:08X}", transfer.source));
+                    ui.monospace(format!("{:08X}", transfer.dest));
+                    ui.monospace(format!(
+                        "{} x {} bit",
+                        transfer.count,
+                        if transfer.word_transfer { 32 } else { 16 }
+                    ));
+                    ui.monospace(dma_start_timing_name(
+                        transfer.channel,
+                        transfer.start_timing,
+                    ));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    fn waitstate_info(&self, ui: &mut Ui) {
+        let waitstates = self.debug_snapshot.read().unwrap().waitstates;
+
+        let mut info_fields = vec![("SRAM".to_string(), format!("{}", waitstates.sram))];
+        for (i, rom) in waitstates.rom.iter().enumerate() {
+            info_fields.push((
+                format!("WS{} first/second", i),
+                format!("{}/{}", rom.first_access, rom.second_access),
+            ));
+        }
+        info_fields.push((
+            "PHI output".to_string(),
+            format!("{:?}", waitstates.phi_terminal_output),
+        ));
+        info_fields.push((
+            "prefetch".to_string(),
+            format!("{}", waitstates.prefetch_buffer_enable),
+        ));
+
+        for (name, mut value) in info_fields {
+            ui.horizontal(|ui| {
+                ui.label(name);
+                ui.add(TextEdit::singleline(&mut value).interactive(false));
+            });
+        }
+    }
+
+    fn performance(&self, ui: &mut Ui) {
+        let counters = self.debug_snapshot.read().unwrap().perf_counters;
+
+        let info_fields = [
+            ("ARM instructions", counters.arm_instructions),
+            ("Thumb instructions", counters.thumb_instructions),
+            ("sequential accesses", counters.sequential_accesses),
+            ("non-sequential accesses", counters.non_sequential_accesses),
+            ("internal cycles", counters.internal_cycles),
+            ("DMA units transferred", counters.dma_units_transferred),
+            ("IRQs taken", counters.irqs_taken),
+            (
+                "idle loop cycles skipped",
+                counters.idle_loop_cycles_skipped,
+            ),
+        ];
+
+        for (name, value) in info_fields {
+            ui.horizontal(|ui| {
+                ui.label(name);
+                ui.add(TextEdit::singleline(&mut value.to_string()).interactive(false));
+            });
+        }
+
+        if ui.button("Reset").clicked() {
+            self.emulator_command_sender
+                .send(EmulatorCommand::ResetPerfCounters)
+                .unwrap();
+        }
+    }
+
+    fn memory_heatmap(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut self.bus_profiling, "Enabled").changed() {
+                self.emulator_command_sender
+                    .send(EmulatorCommand::SetBusProfiling(self.bus_profiling))
+                    .unwrap();
+            }
+
+            if ui.button("Reset").clicked() {
+                self.emulator_command_sender
+                    .send(EmulatorCommand::ResetBusProfiler)
+                    .unwrap();
+            }
+        });
+
+        let debug_snapshot_lock = self.debug_snapshot.read().unwrap();
+        let Some(profiler) = &debug_snapshot_lock.bus_profiler else {
+            return;
+        };
+
+        Grid::new("region_counts").striped(true).show(ui, |ui| {
+            ui.label("region");
+            ui.label("reads");
+            ui.label("writes");
+            ui.end_row();
+
+            for region in MemoryRegion::ALL {
+                let counts = profiler.region_counts(region);
+                ui.label(region.to_string());
+                ui.label(counts.reads.to_string());
+                ui.label(counts.writes.to_string());
+                ui.end_row();
+            }
+        });
+
+        ComboBox::from_label("Region")
+            .selected_text(self.heatmap_region.to_string())
+            .show_ui(ui, |ui| {
+                for region in MemoryRegion::ALL {
+                    if region != MemoryRegion::Unused {
+                        ui.selectable_value(&mut self.heatmap_region, region, region.to_string());
+                    }
+                }
+            });
+
+        let region = self.heatmap_region;
+        let num_pages = region.size() / BusProfiler::PAGE_SIZE;
+        let num_rows = num_pages.div_ceil(HEATMAP_COLUMNS);
+        // Scaled logarithmically, otherwise a single hot loop washes out everything else.
+        let max_heat = (profiler.max_page_accesses(region) as f32).ln_1p();
+
+        ScrollArea::vertical().show_rows(
+            ui,
+            HEATMAP_CELL_SIZE,
+            num_rows as usize,
+            |ui, row_range| {
+                ui.spacing_mut().item_spacing.y = 0.0;
+
+                for row in row_range {
+                    let (rect, response) = ui.allocate_exact_size(
+                        vec2(HEATMAP_COLUMNS as f32, 1.0) * HEATMAP_CELL_SIZE,
+                        Sense::hover(),
+                    );
+
+                    for column in 0..HEATMAP_COLUMNS {
+                        let page = (row as u32 * HEATMAP_COLUMNS) + column;
+                        if page >= num_pages {
+                            break;
+                        }
+
+                        let accesses = profiler.page_accesses(region, page);
+                        let color = if accesses == 0 {
+                            Color32::from_gray(32)
+                        } else {
+                            let heat = (accesses as f32).ln_1p() / max_heat;
+                            Color32::from_rgb((heat * 255.0) as u8, (heat * heat * 255.0) as u8, 0)
+                        };
+
+                        let cell = Rect::from_min_size(
+                            rect.min + vec2(column as f32 * HEATMAP_CELL_SIZE, 0.0),
+                            vec2(HEATMAP_CELL_SIZE, HEATMAP_CELL_SIZE),
+                        );
+                        ui.painter().rect_filled(cell.shrink(0.5), 0.0, color);
+                    }
+
+                    if let Some(hover_position) = response.hover_pos() {
+                        let column = ((hover_position.x - rect.min.x) / HEATMAP_CELL_SIZE) as u32;
+                        let page = (row as u32 * HEATMAP_COLUMNS) + column.min(HEATMAP_COLUMNS - 1);
+                        let offset = page * BusProfiler::PAGE_SIZE;
+                        response.on_hover_text(format!(
+                            "{region} {:08X}-{:08X}: {} accesses",
+                            offset,
+                            offset + BusProfiler::PAGE_SIZE - 1,
+                            profiler.page_accesses(region, page)
+                        ));
+                    }
+                }
+            },
+        );
+    }
+
+    fn cheat_search(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ComboBox::from_label("Width")
+                .selected_text(self.search_width.to_string())
+                .show_ui(ui, |ui| {
+                    for width in SearchWidth::ALL {
+                        ui.selectable_value(&mut self.search_width, width, width.to_string());
+                    }
+                });
+
+            if ui.button("New Search").clicked() {
+                self.emulator_command_sender
+                    .send(EmulatorCommand::StartMemorySearch(self.search_width))
+                    .unwrap();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.search_value)
+                    .hint_text("Value")
+                    .desired_width(80.0),
+            );
+
+            let value = parse_search_value(&self.search_value);
+            let unsigned_value = value.and_then(|value| u32::try_from(value).ok());
+            let filters = [
+                ("=", unsigned_value.map(SearchFilter::EqualTo)),
+                (">", unsigned_value.map(SearchFilter::GreaterThan)),
+                ("<", unsigned_value.map(SearchFilter::LessThan)),
+                ("Changed By", value.map(SearchFilter::ChangedBy)),
+            ];
+            for (name, filter) in filters {
+                let clicked = ui
+                    .add_enabled(filter.is_some(), Button::new(name))
+                    .clicked();
+                if let Some(filter) = filter.filter(|_| clicked) {
+                    self.emulator_command_sender
+                        .send(EmulatorCommand::FilterMemorySearch(filter))
+                        .unwrap();
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let filters = [
+                ("Increased", SearchFilter::Increased),
+                ("Decreased", SearchFilter::Decreased),
+                ("Changed", SearchFilter::Changed),
+                ("Unchanged", SearchFilter::Unchanged),
+            ];
+            for (name, filter) in filters {
+                if ui.button(name).clicked() {
+                    self.emulator_command_sender
+                        .send(EmulatorCommand::FilterMemorySearch(filter))
+                        .unwrap();
+                }
+            }
+        });
+
+        if let Some(search) = self.memory_search.lock().unwrap().as_ref() {
+            let candidates = search.candidates();
+            ui.label(format!(
+                "{} candidates after {} snapshots",
+                candidates.len(),
+                search.snapshots()
+            ));
+
+            ScrollArea::vertical()
+                .id_source("candidates")
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for candidate in candidates.iter().take(MAX_LISTED_CANDIDATES) {
+                        ui.horizontal(|ui| {
+                            ui.monospace(format!(
+                                "{:08X}: {} (was {})",
+                                candidate.address, candidate.value, candidate.previous
+                            ));
+
+                            if ui.button("Watch").clicked() {
+                                let mut watchpoints_lock = self.watchpoints.lock().unwrap();
+                                if !watchpoints_lock.contains(&candidate.address) {
+                                    watchpoints_lock.push(candidate.address);
+                                }
+                            }
+
+                            if ui.button("Cheat").clicked() {
+                                self.cheats.lock().unwrap().push(CheatInfo {
+                                    address: candidate.address,
+                                    width: search.width(),
+                                    value: candidate.value,
+                                    active: true,
+                                });
+                            }
+                        });
+                    }
+                });
+        }
+
+        CollapsingHeader::new("Cheats")
+            .default_open(true)
+            .show(ui, |ui| {
+                let mut cheats_lock = self.cheats.lock().unwrap();
+                cheats_lock.retain_mut(|cheat| {
+                    ui.horizontal(|ui| {
+                        ui.monospace(format!("{:08X} ({})", cheat.address, cheat.width));
+                        ui.add(
+                            DragValue::new(&mut cheat.value)
+                                .clamp_range(0..=u32::MAX >> (32 - cheat.width.size() * 8)),
+                        );
+                        ui.checkbox(&mut cheat.active, "Active");
+                        !ui.button("Remove").clicked()
+                    })
+                    .inner
+                });
+            });
+
+        CollapsingHeader::new("Watchpoints")
+            .default_open(true)
+            .show(ui, |ui| {
+                let symbols_lock = self.symbols.read().unwrap();
+                let mut watchpoints_lock = self.watchpoints.lock().unwrap();
+                watchpoints_lock.retain(|address| {
+                    ui.horizontal(|ui| {
+                        ui.monospace(format!("{address:08X}"));
+                        if let Some(name) = symbols_lock
+                            .as_ref()
+                            .and_then(|symbols| symbols.describe(*address))
+                        {
+                            ui.label(name);
+                        }
+                        !ui.button("Remove").clicked()
+                    })
+                    .inner
+                });
+            });
+    }
+
     fn debugger(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
             if ui.button("Step").clicked() {
@@ -637,6 +1818,18 @@ impl MyEguiApp {
                     .unwrap();
             }
 
+            if ui.button("Step Over").clicked() {
+                self.emulator_command_sender
+                    .send(EmulatorCommand::StepOver)
+                    .unwrap();
+            }
+
+            if ui.button("Step Out").clicked() {
+                self.emulator_command_sender
+                    .send(EmulatorCommand::StepOut)
+                    .unwrap();
+            }
+
             ui.add(
                 Slider::new(&mut self.step_count, 1..=10_000_000)
                     .logarithmic(true)
@@ -648,6 +1841,7 @@ impl MyEguiApp {
             .default_open(true)
             .show(ui, |ui| {
                 let mut breakpoints_lock = self.breakpoints.lock().unwrap();
+                let symbols_lock = self.symbols.read().unwrap();
 
                 for breakpoint in breakpoints_lock.iter_mut() {
                     ui.horizontal(|ui| {
@@ -657,9 +1851,16 @@ impl MyEguiApp {
                         );
                         ui.checkbox(&mut breakpoint.active, "Active");
 
-                        let mut stopped_at =
-                            breakpoint.address == self.disassembly_info.lock().unwrap().pc;
+                        let mut stopped_at = breakpoint.address
+                            == self.disassembly_info.lock().unwrap().executing_pc;
                         ui.checkbox(&mut stopped_at, "Stopped");
+
+                        if let Some(name) = symbols_lock
+                            .as_ref()
+                            .and_then(|symbols| symbols.describe(breakpoint.address))
+                        {
+                            ui.label(name);
+                        }
                     });
                 }
 
@@ -667,20 +1868,75 @@ impl MyEguiApp {
                     breakpoints_lock.push(BreakpointInfo::default());
                 }
             });
+
+        CollapsingHeader::new("Call Stack")
+            .default_open(true)
+            .show(ui, |ui| {
+                let debug_snapshot_lock = self.debug_snapshot.read().unwrap();
+                let symbols_lock = self.symbols.read().unwrap();
+                let describe = |address: u32| match symbols_lock
+                    .as_ref()
+                    .and_then(|symbols| symbols.describe(address))
+                {
+                    Some(name) => format!("{address:08X} <{name}>"),
+                    None => format!("{address:08X}"),
+                };
+
+                ui.label(
+                    RichText::new(format!("pc {}", describe(debug_snapshot_lock.executing_pc)))
+                        .monospace(),
+                );
+
+                for (depth, frame) in debug_snapshot_lock.call_stack.iter().enumerate() {
+                    let kind = match frame.kind {
+                        FrameKind::Call => "call",
+                        FrameKind::Swi => "swi",
+                        FrameKind::Interrupt => "irq",
+                    };
+
+                    ui.label(
+                        RichText::new(format!(
+                            "#{depth} {} from {} ({kind})",
+                            describe(frame.target),
+                            describe(frame.call_site)
+                        ))
+                        .monospace(),
+                    );
+                }
+            });
     }
 }
 
+// Decimal, or hex with a 0x prefix.
+fn parse_search_value(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+
+    Some(if negative { -value } else { value })
+}
+
 impl eframe::App for MyEguiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.request_repaint();
 
-        egui::Window::new("Controls").show(ctx, |ui| self.controls(ui));
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("View", |ui| panel::view_menu(ui, &mut self.dock_state));
+            });
+        });
 
-        egui::Window::new("Emulator Window")
-            .collapsible(false)
-            .default_height(Lcd::LCD_HEIGHT as f32 * 4.0)
-            .default_width(Lcd::LCD_WIDTH as f32 * 4.0)
-            .show(ctx, |ui| self.emulator_window(ui));
+        // The tab viewer needs the rest of the app, so the dock state is moved out while the
+        // dock area borrows both.
+        let mut dock_state = std::mem::replace(&mut self.dock_state, DockState::new(Vec::new()));
+        DockArea::new(&mut dock_state).show(ctx, self);
+        self.dock_state = dock_state;
 
         const KEYS_TO_CHECK: &[Key] = &[
             Key::A,
@@ -719,11 +1975,46 @@ impl eframe::App for MyEguiApp {
                     .unwrap();
             };
         }
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, DOCK_STATE_STORAGE_KEY, &self.dock_state);
+        eframe::set_value(storage, AUTOFIRE_STORAGE_KEY, &self.autofire);
+    }
+}
+
+impl TabViewer for MyEguiApp {
+    type Tab = Panel;
+
+    fn title(&mut self, tab: &mut Self::Tab) -> WidgetText {
+        tab.to_string().into()
+    }
 
-        egui::Window::new("Memory Viewer").show(ctx, |ui| self.memory_viewer(ui));
-        egui::Window::new("Instruction Disassembler").show(ctx, |ui| self.disassembler(ui));
-        egui::Window::new("Register Viewer").show(ctx, |ui| self.register_info(ui));
-        egui::Window::new("CPU Info").show(ctx, |ui| self.cpu_info(ui));
-        egui::Window::new("Debugger").show(ctx, |ui| self.debugger(ui));
+    fn ui(&mut self, ui: &mut Ui, tab: &mut Self::Tab) {
+        match tab {
+            Panel::Emulator => self.emulator_window(ui),
+            Panel::Controls => self.controls(ui),
+            Panel::RomLibrary => {
+                if let Some(path) = self.rom_library.show(ui) {
+                    self.emulator_command_sender
+                        .send(EmulatorCommand::LoadRom(path))
+                        .unwrap();
+                }
+            }
+            Panel::MemoryViewer => self.memory_viewer(ui),
+            Panel::Disassembler => self.disassembler(ui),
+            Panel::Registers => self.register_info(ui),
+            Panel::CpuInfo => self.cpu_info(ui),
+            Panel::Dma => self.dma_info(ui),
+            Panel::Waitstates => self.waitstate_info(ui),
+            Panel::Performance => self.performance(ui),
+            Panel::MemoryHeatmap => self.memory_heatmap(ui),
+            Panel::IoRegisters => self.io_registers(ui),
+            Panel::VirtualKeypad => self.virtual_keypad(ui),
+            Panel::Debugger => self.debugger(ui),
+            Panel::CheatSearch => self.cheat_search(ui),
+            Panel::TilemapViewer => self.tilemap_viewer(ui),
+            Panel::Interrupts => self.interrupt_history(ui),
+        }
     }
 }
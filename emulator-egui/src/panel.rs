@@ -0,0 +1,130 @@
+use std::fmt::Display;
+
+use eframe::egui::Ui;
+use egui_dock::{DockState, NodeIndex};
+use serde::{Deserialize, Serialize};
+
+// Every view the UI can show, each living in a tab of the dock area.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Panel {
+    Emulator,
+    Controls,
+    RomLibrary,
+    MemoryViewer,
+    Disassembler,
+    Registers,
+    CpuInfo,
+    Dma,
+    Waitstates,
+    Performance,
+    MemoryHeatmap,
+    IoRegisters,
+    Debugger,
+    CheatSearch,
+    TilemapViewer,
+    Interrupts,
+    VirtualKeypad,
+}
+
+impl Panel {
+    pub const ALL: [Panel; 17] = [
+        Self::Emulator,
+        Self::Controls,
+        Self::RomLibrary,
+        Self::MemoryViewer,
+        Self::Disassembler,
+        Self::Registers,
+        Self::CpuInfo,
+        Self::Dma,
+        Self::Waitstates,
+        Self::Performance,
+        Self::MemoryHeatmap,
+        Self::IoRegisters,
+        Self::Debugger,
+        Self::CheatSearch,
+        Self::TilemapViewer,
+        Self::Interrupts,
+        Self::VirtualKeypad,
+    ];
+}
+
+impl Display for Panel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Emulator => f.write_str("Emulator Window"),
+            Self::Controls => f.write_str("Controls"),
+            Self::RomLibrary => f.write_str("ROM Library"),
+            Self::MemoryViewer => f.write_str("Memory Viewer"),
+            Self::Disassembler => f.write_str("Instruction Disassembler"),
+            Self::Registers => f.write_str("Register Viewer"),
+            Self::CpuInfo => f.write_str("CPU Info"),
+            Self::Dma => f.write_str("DMA"),
+            Self::Waitstates => f.write_str("Waitstates"),
+            Self::Performance => f.write_str("Performance"),
+            Self::MemoryHeatmap => f.write_str("Memory Heatmap"),
+            Self::IoRegisters => f.write_str("IO Registers"),
+            Self::Debugger => f.write_str("Debugger"),
+            Self::CheatSearch => f.write_str("Cheat Search"),
+            Self::TilemapViewer => f.write_str("Tilemap Viewer"),
+            Self::Interrupts => f.write_str("Interrupt History"),
+            Self::VirtualKeypad => f.write_str("Virtual Keypad"),
+        }
+    }
+}
+
+// The layout used until the user rearranges anything: the screen in the middle, controls on the
+// left, and the debug views stacked on the right.
+pub fn default_dock_state() -> DockState<Panel> {
+    let mut dock_state = DockState::new(vec![Panel::Emulator]);
+    let surface = dock_state.main_surface_mut();
+
+    let [emulator, _] = surface.split_left(
+        NodeIndex::root(),
+        0.2,
+        vec![Panel::Controls, Panel::RomLibrary, Panel::VirtualKeypad],
+    );
+    let [_, debugger] = surface.split_right(
+        emulator,
+        0.6,
+        vec![
+            Panel::Debugger,
+            Panel::Disassembler,
+            Panel::Registers,
+            Panel::CpuInfo,
+        ],
+    );
+    surface.split_below(
+        debugger,
+        0.5,
+        vec![
+            Panel::MemoryViewer,
+            Panel::IoRegisters,
+            Panel::Dma,
+            Panel::Interrupts,
+            Panel::Waitstates,
+            Panel::Performance,
+            Panel::MemoryHeatmap,
+            Panel::CheatSearch,
+            Panel::TilemapViewer,
+        ],
+    );
+
+    dock_state
+}
+
+// Shows or hides each panel, adding newly shown ones to the focused tab group.
+pub fn view_menu(ui: &mut Ui, dock_state: &mut DockState<Panel>) {
+    for panel in Panel::ALL {
+        let location = dock_state.find_tab(&panel);
+        let mut open = location.is_some();
+
+        if ui.checkbox(&mut open, panel.to_string()).changed() {
+            match location {
+                Some(location) => {
+                    dock_state.remove_tab(location);
+                }
+                None => dock_state.push_to_focused_leaf(panel),
+            }
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+use anyhow::Result;
+use eframe::{
+    egui::{self, load::SizedTexture, CollapsingHeader, Grid, ImageSource, ScrollArea, Ui, Vec2},
+    epaint::{ColorImage, TextureHandle},
+};
+use emulator_core::{CartridgeHeader, Lcd, SaveState, Thumbnail};
+use rfd::FileDialog;
+
+const LIBRARY_CONFIG_FILE_NAME: &str = "rom_library.txt";
+const MAX_RECENT_ROMS: usize = 10;
+const THUMBNAIL_SCALE: f32 = 0.5;
+
+// Raw ROMs are loaded whatever they're called, compressed ones are told apart by their contents.
+pub const ROM_EXTENSIONS: &[&str] = &["gba", "agb", "bin", "zip", "gz"];
+
+// Save states written by emulator-native, see `state_file_name` there.
+const SAVE_STATE_SLOTS: std::ops::RangeInclusive<u8> = 1..=4;
+
+#[derive(Clone, Debug)]
+pub struct RomInfo {
+    pub path: PathBuf,
+    pub title: String,
+    pub game_code: String,
+}
+
+impl RomInfo {
+    pub fn read(path: &Path) -> Result<Self> {
+        let header = CartridgeHeader::read(BufReader::new(File::open(path)?))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            title: header.title,
+            game_code: header.game_code,
+        })
+    }
+
+    fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+// Recent ROMs are read once and cached, rather than hitting the disk every frame.
+struct RecentRom {
+    label: String,
+    // `None` if the ROM has no save states to take a thumbnail from.
+    thumbnail: Option<TextureHandle>,
+}
+
+// Things that happen outside of the UI thread (file dialogs) and need to be reflected in the
+// library.
+enum LibraryEvent {
+    AddDirectory(PathBuf),
+    RomOpened(PathBuf),
+}
+
+pub struct RomLibrary {
+    directories: Vec<PathBuf>,
+    recent: Vec<PathBuf>,
+    roms: Vec<RomInfo>,
+    recent_cache: HashMap<PathBuf, RecentRom>,
+    event_sender: Sender<LibraryEvent>,
+    event_receiver: Receiver<LibraryEvent>,
+}
+
+impl RomLibrary {
+    pub fn load() -> Self {
+        let (event_sender, event_receiver) = channel();
+
+        let mut library = Self {
+            directories: Vec::new(),
+            recent: Vec::new(),
+            roms: Vec::new(),
+            recent_cache: HashMap::new(),
+            event_sender,
+            event_receiver,
+        };
+
+        match fs::read_to_string(LIBRARY_CONFIG_FILE_NAME) {
+            Ok(config) => {
+                for line in config.lines() {
+                    match line.split_once('\t') {
+                        Some(("directory", path)) => library.directories.push(path.into()),
+                        Some(("recent", path)) => library.recent.push(path.into()),
+                        _ => println!("ignoring invalid rom library config line: {line}"),
+                    }
+                }
+            }
+            Err(e) => println!("not loading rom library config: {e}"),
+        }
+
+        library.rescan();
+        library
+    }
+
+    fn save(&self) {
+        let mut config = String::new();
+        for directory in &self.directories {
+            config.push_str(&format!("directory\t{}\n", directory.display()));
+        }
+        for rom in &self.recent {
+            config.push_str(&format!("recent\t{}\n", rom.display()));
+        }
+
+        if let Err(e) = fs::write(LIBRARY_CONFIG_FILE_NAME, config) {
+            println!("failed to save rom library config: {e}");
+        }
+    }
+
+    fn rescan(&mut self) {
+        self.roms.clear();
+        self.recent_cache.clear();
+
+        for directory in &self.directories {
+            let entries = match fs::read_dir(directory) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    println!("failed to scan {}: {e}", directory.display());
+                    continue;
+                }
+            };
+
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let is_rom = path.extension().is_some_and(|extension| {
+                    let extension = extension.to_string_lossy().to_ascii_lowercase();
+                    ROM_EXTENSIONS.contains(&extension.as_str())
+                });
+                if !is_rom {
+                    continue;
+                }
+
+                match RomInfo::read(&path) {
+                    Ok(info) => self.roms.push(info),
+                    Err(e) => println!("failed to read rom header of {}: {e}", path.display()),
+                }
+            }
+        }
+
+        self.roms.sort_by(|a, b| a.title.cmp(&b.title));
+    }
+
+    // For ROMs loaded from somewhere other than the library itself, e.g. the file picker.
+    pub fn rom_opened_notifier(&self) -> impl Fn(PathBuf) + Send + 'static {
+        let sender = self.event_sender.clone();
+        move |path| sender.send(LibraryEvent::RomOpened(path)).unwrap()
+    }
+
+    fn add_recent(&mut self, path: PathBuf) {
+        self.recent.retain(|recent| *recent != path);
+        self.recent.insert(0, path.clone());
+        self.recent.truncate(MAX_RECENT_ROMS);
+
+        // The ROM may have gained save states since we last looked.
+        self.recent_cache.remove(&path);
+        self.save();
+    }
+
+    fn recent_rom(&mut self, ui: &Ui, path: &Path) -> &RecentRom {
+        self.recent_cache
+            .entry(path.to_path_buf())
+            .or_insert_with(|| {
+                let label = match RomInfo::read(path) {
+                    Ok(info) => format!("{}\n{}", info.title, info.file_name()),
+                    Err(_) => path.display().to_string(),
+                };
+
+                let thumbnail = load_save_state_thumbnail(path).map(|image| {
+                    ui.ctx().load_texture(
+                        format!("thumbnail-{}", path.display()),
+                        image,
+                        Default::default(),
+                    )
+                });
+
+                RecentRom { label, thumbnail }
+            })
+    }
+
+    // Returns the ROM to load, if one was picked.
+    pub fn show(&mut self, ui: &mut Ui) -> Option<PathBuf> {
+        for event in self.event_receiver.try_iter().collect::<Vec<_>>() {
+            match event {
+                LibraryEvent::AddDirectory(directory) => {
+                    if !self.directories.contains(&directory) {
+                        self.directories.push(directory);
+                        self.save();
+                        self.rescan();
+                    }
+                }
+                LibraryEvent::RomOpened(path) => self.add_recent(path),
+            }
+        }
+
+        let mut to_load = None;
+
+        ui.horizontal(|ui| {
+            if ui.button("Add Directory").clicked() {
+                let sender = self.event_sender.clone();
+                thread::spawn(move || {
+                    if let Some(directory) = FileDialog::new().pick_folder() {
+                        sender.send(LibraryEvent::AddDirectory(directory)).unwrap();
+                    } else {
+                        println!("user cancelled directory selection");
+                    }
+                });
+            }
+
+            if ui.button("Rescan").clicked() {
+                self.rescan();
+            }
+        });
+
+        CollapsingHeader::new("Directories").show(ui, |ui| {
+            let mut to_remove = None;
+            for (i, directory) in self.directories.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(directory.display().to_string());
+                    if ui.button("Remove").clicked() {
+                        to_remove = Some(i);
+                    }
+                });
+            }
+
+            if let Some(i) = to_remove {
+                self.directories.remove(i);
+                self.save();
+                self.rescan();
+            }
+        });
+
+        let thumbnail_size = Vec2::new(
+            Lcd::LCD_WIDTH as f32 * THUMBNAIL_SCALE,
+            Lcd::LCD_HEIGHT as f32 * THUMBNAIL_SCALE,
+        );
+
+        CollapsingHeader::new("Recent")
+            .default_open(true)
+            .show(ui, |ui| {
+                for path in self.recent.clone() {
+                    ui.horizontal(|ui| {
+                        let recent_rom = self.recent_rom(ui, &path);
+
+                        match &recent_rom.thumbnail {
+                            Some(texture) => {
+                                ui.image(ImageSource::Texture(SizedTexture {
+                                    id: texture.id(),
+                                    size: thumbnail_size,
+                                }));
+                            }
+                            None => {
+                                ui.allocate_exact_size(thumbnail_size, egui::Sense::hover());
+                            }
+                        }
+
+                        if ui
+                            .selectable_label(false, &recent_rom.label)
+                            .double_clicked()
+                        {
+                            to_load = Some(path.clone());
+                        }
+                    });
+                }
+            });
+
+        CollapsingHeader::new("All ROMs")
+            .default_open(true)
+            .show(ui, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("rom-library-grid").striped(true).show(ui, |ui| {
+                        ui.strong("Title");
+                        ui.strong("Code");
+                        ui.strong("File");
+                        ui.end_row();
+
+                        for rom in &self.roms {
+                            if ui.selectable_label(false, &rom.title).double_clicked() {
+                                to_load = Some(rom.path.clone());
+                            }
+                            ui.label(&rom.game_code);
+                            ui.label(rom.file_name());
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+
+        if let Some(path) = &to_load {
+            self.add_recent(path.clone());
+        }
+
+        to_load
+    }
+}
+
+// Uses the screen of the most recently written save state as the thumbnail.
+fn load_save_state_thumbnail(rom_path: &Path) -> Option<ColorImage> {
+    let (_, state_path) = SAVE_STATE_SLOTS
+        .filter_map(|slot| {
+            let state_path = PathBuf::from(format!("{}.ss{slot}", rom_path.display()));
+            let modified = fs::metadata(&state_path).ok()?.modified().ok()?;
+            Some((modified, state_path))
+        })
+        .max_by_key(|(modified, _)| *modified)?;
+
+    let state_file = BufReader::new(File::open(&state_path).ok()?);
+    let thumbnail = SaveState::read(state_file).and_then(|state| match state.thumbnail()? {
+        Some(thumbnail) => Ok(thumbnail),
+        // Older states have no thumbnail, so take it from the screen they hold.
+        None => Ok(Thumbnail::from_lcd(&state.to_cpu()?.bus.lcd)),
+    });
+    let thumbnail = match thumbnail {
+        Ok(thumbnail) => thumbnail,
+        Err(e) => {
+            println!("failed to read save state {}: {e}", state_path.display());
+            return None;
+        }
+    };
+
+    Some(ColorImage::from_rgb(
+        [thumbnail.width, thumbnail.height],
+        &thumbnail.rgb,
+    ))
+}
@@ -0,0 +1,95 @@
+// Post-processing applied to the scaled frame. The viewport describes where the GBA image sits in
+// the render target (x, y, width, height in physical pixels), which lets effects line up with the
+// original 240x160 pixel grid.
+
+struct Uniforms {
+    viewport: vec4<f32>,
+    mode: u32,
+}
+
+// Modes, matching `PostProcess` on the Rust side:
+// 0 - none, 1 - color correction, 2 - LCD grid, 3 - CRT
+
+@group(0) @binding(0) var frame_texture: texture_2d<f32>;
+@group(0) @binding(1) var frame_sampler: sampler;
+@group(0) @binding(2) var<uniform> uniforms: Uniforms;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coord = fma(position, vec2<f32>(0.5, -0.5), vec2<f32>(0.5, 0.5));
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+// Approximates the colors of the original (unlit, non-sRGB) GBA LCD. The sampled texture is
+// already linear, so the matrix is applied directly.
+fn color_correct(color: vec3<f32>) -> vec3<f32> {
+    let correction = mat3x3<f32>(
+        vec3<f32>(0.82, 0.125, 0.195),
+        vec3<f32>(0.24, 0.665, 0.075),
+        vec3<f32>(-0.06, 0.21, 0.73),
+    );
+
+    return clamp(correction * color * 0.94, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Darkens the gaps between LCD cells and splits each cell into vertical RGB subpixels.
+fn lcd_grid(color: vec3<f32>, cell: vec2<f32>) -> vec3<f32> {
+    let edge_distance = min(cell, vec2<f32>(1.0) - cell);
+    let grid = mix(0.55, 1.0, smoothstep(0.0, 0.12, min(edge_distance.x, edge_distance.y)));
+
+    let subpixel = cell.x * 3.0;
+    let mask = vec3<f32>(
+        select(0.7, 1.0, subpixel < 1.0),
+        select(0.7, 1.0, subpixel >= 1.0 && subpixel < 2.0),
+        select(0.7, 1.0, subpixel >= 2.0),
+    );
+
+    return color * mask * grid * 1.15;
+}
+
+// Horizontal scanlines plus a slight vignette.
+fn crt(color: vec3<f32>, cell: vec2<f32>, position: vec2<f32>) -> vec3<f32> {
+    let scanline = mix(0.6, 1.0, sin(cell.y * 3.14159265));
+
+    let centered = position - vec2<f32>(0.5);
+    let vignette = clamp(1.0 - dot(centered, centered) * 0.8, 0.0, 1.0);
+
+    return color * scanline * vignette * 1.2;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let sampled = textureSample(frame_texture, frame_sampler, in.tex_coord);
+
+    // Position within the GBA image, in the range 0..1.
+    let position = (in.position.xy - uniforms.viewport.xy) / uniforms.viewport.zw;
+    let inside = all(position >= vec2<f32>(0.0)) && all(position < vec2<f32>(1.0));
+    if (!inside) {
+        return sampled;
+    }
+
+    let cell = fract(position * vec2<f32>(240.0, 160.0));
+
+    var color = sampled.rgb;
+    switch uniforms.mode {
+        case 1u: {
+            color = color_correct(color);
+        }
+        case 2u: {
+            color = lcd_grid(color_correct(color), cell);
+        }
+        case 3u: {
+            color = crt(color, cell, position);
+        }
+        default: {}
+    }
+
+    return vec4<f32>(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), sampled.a);
+}
@@ -0,0 +1,294 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use emulator_core::Lcd;
+
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIIF_KEYFRAME: u32 = 0x10;
+
+const VIDEO_CHUNK_ID: &[u8; 4] = b"00dc";
+const AUDIO_CHUNK_ID: &[u8; 4] = b"01wb";
+
+const AUDIO_CHANNELS: u16 = 2;
+const AUDIO_BITS_PER_SAMPLE: u16 = 16;
+const AUDIO_BLOCK_ALIGN: u16 = AUDIO_CHANNELS * (AUDIO_BITS_PER_SAMPLE / 8);
+
+// 24-bit BGR, no row padding is required since 240 * 3 is a multiple of 4.
+const VIDEO_FRAME_SIZE: u32 = (Lcd::LCD_WIDTH * Lcd::LCD_HEIGHT * 3) as u32;
+
+struct IndexEntry {
+    chunk_id: [u8; 4],
+    offset: u32,
+    size: u32,
+}
+
+// Writes an uncompressed AVI (RGB24 video, 16-bit stereo PCM audio). Since the final frame and
+// sample counts aren't known up front, the relevant header fields are patched in `finish`.
+pub struct AviRecorder {
+    writer: BufWriter<File>,
+
+    riff_size_offset: u64,
+    total_frames_offset: u64,
+    video_length_offset: u64,
+    audio_length_offset: u64,
+    movi_size_offset: u64,
+    movi_start: u64,
+
+    index: Vec<IndexEntry>,
+    frame_count: u32,
+    audio_block_count: u32,
+    pending_samples: Vec<i16>,
+}
+
+impl AviRecorder {
+    // Frames per second are given as the fraction `frame_rate / frame_scale`, since the GBA's
+    // isn't a whole number.
+    pub fn new(
+        path: impl AsRef<Path>,
+        frame_rate: u32,
+        frame_scale: u32,
+        sample_rate: u32,
+    ) -> Result<Self> {
+        let frames_per_second = f64::from(frame_rate) / f64::from(frame_scale);
+
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(b"RIFF")?;
+        let riff_size_offset = writer.stream_position()?;
+        write_u32(&mut writer, 0)?;
+        writer.write_all(b"AVI ")?;
+
+        let hdrl_size_offset = begin_list(&mut writer, b"hdrl")?;
+
+        // Main AVI header.
+        writer.write_all(b"avih")?;
+        write_u32(&mut writer, 56)?;
+        write_u32(&mut writer, (1_000_000.0 / frames_per_second) as u32)?; // microseconds per frame
+        write_u32(
+            &mut writer,
+            (f64::from(VIDEO_FRAME_SIZE) * frames_per_second).ceil() as u32
+                + (sample_rate * u32::from(AUDIO_BLOCK_ALIGN)),
+        )?; // max bytes per second
+        write_u32(&mut writer, 0)?; // padding granularity
+        write_u32(&mut writer, AVIF_HASINDEX)?;
+        let total_frames_offset = writer.stream_position()?;
+        write_u32(&mut writer, 0)?; // total frames
+        write_u32(&mut writer, 0)?; // initial frames
+        write_u32(&mut writer, 2)?; // streams
+        write_u32(&mut writer, VIDEO_FRAME_SIZE)?; // suggested buffer size
+        write_u32(&mut writer, Lcd::LCD_WIDTH as u32)?;
+        write_u32(&mut writer, Lcd::LCD_HEIGHT as u32)?;
+        for _ in 0..4 {
+            write_u32(&mut writer, 0)?; // reserved
+        }
+
+        // Video stream.
+        let video_strl_size_offset = begin_list(&mut writer, b"strl")?;
+        writer.write_all(b"strh")?;
+        write_u32(&mut writer, 56)?;
+        writer.write_all(b"vids")?;
+        writer.write_all(b"DIB ")?;
+        write_u32(&mut writer, 0)?; // flags
+        write_u16(&mut writer, 0)?; // priority
+        write_u16(&mut writer, 0)?; // language
+        write_u32(&mut writer, 0)?; // initial frames
+        write_u32(&mut writer, frame_scale)?; // scale
+        write_u32(&mut writer, frame_rate)?; // rate
+        write_u32(&mut writer, 0)?; // start
+        let video_length_offset = writer.stream_position()?;
+        write_u32(&mut writer, 0)?; // length
+        write_u32(&mut writer, VIDEO_FRAME_SIZE)?; // suggested buffer size
+        write_u32(&mut writer, u32::MAX)?; // quality
+        write_u32(&mut writer, 0)?; // sample size
+        write_u16(&mut writer, 0)?; // frame left
+        write_u16(&mut writer, 0)?; // frame top
+        write_u16(&mut writer, Lcd::LCD_WIDTH as u16)?; // frame right
+        write_u16(&mut writer, Lcd::LCD_HEIGHT as u16)?; // frame bottom
+
+        writer.write_all(b"strf")?;
+        write_u32(&mut writer, 40)?;
+        write_u32(&mut writer, 40)?; // header size
+        write_u32(&mut writer, Lcd::LCD_WIDTH as u32)?;
+        write_u32(&mut writer, Lcd::LCD_HEIGHT as u32)?; // positive height, rows are bottom-up
+        write_u16(&mut writer, 1)?; // planes
+        write_u16(&mut writer, 24)?; // bit count
+        write_u32(&mut writer, 0)?; // BI_RGB
+        write_u32(&mut writer, VIDEO_FRAME_SIZE)?;
+        for _ in 0..4 {
+            write_u32(&mut writer, 0)?; // resolution and palette info
+        }
+        end_list(&mut writer, video_strl_size_offset)?;
+
+        // Audio stream.
+        let audio_strl_size_offset = begin_list(&mut writer, b"strl")?;
+        writer.write_all(b"strh")?;
+        write_u32(&mut writer, 56)?;
+        writer.write_all(b"auds")?;
+        write_u32(&mut writer, 0)?; // handler
+        write_u32(&mut writer, 0)?; // flags
+        write_u16(&mut writer, 0)?; // priority
+        write_u16(&mut writer, 0)?; // language
+        write_u32(&mut writer, 0)?; // initial frames
+        write_u32(&mut writer, 1)?; // scale
+        write_u32(&mut writer, sample_rate)?; // rate
+        write_u32(&mut writer, 0)?; // start
+        let audio_length_offset = writer.stream_position()?;
+        write_u32(&mut writer, 0)?; // length
+        write_u32(
+            &mut writer,
+            (f64::from(sample_rate * u32::from(AUDIO_BLOCK_ALIGN)) / frames_per_second) as u32,
+        )?;
+        write_u32(&mut writer, u32::MAX)?; // quality
+        write_u32(&mut writer, u32::from(AUDIO_BLOCK_ALIGN))?; // sample size
+        for _ in 0..4 {
+            write_u16(&mut writer, 0)?; // frame rect, unused for audio
+        }
+
+        writer.write_all(b"strf")?;
+        write_u32(&mut writer, 18)?;
+        write_u16(&mut writer, 1)?; // WAVE_FORMAT_PCM
+        write_u16(&mut writer, AUDIO_CHANNELS)?;
+        write_u32(&mut writer, sample_rate)?;
+        write_u32(&mut writer, sample_rate * u32::from(AUDIO_BLOCK_ALIGN))?;
+        write_u16(&mut writer, AUDIO_BLOCK_ALIGN)?;
+        write_u16(&mut writer, AUDIO_BITS_PER_SAMPLE)?;
+        write_u16(&mut writer, 0)?; // extra format bytes
+        end_list(&mut writer, audio_strl_size_offset)?;
+
+        end_list(&mut writer, hdrl_size_offset)?;
+
+        let movi_size_offset = begin_list(&mut writer, b"movi")?;
+        // Index offsets are relative to the "movi" fourcc.
+        let movi_start = movi_size_offset + 4;
+
+        Ok(Self {
+            writer,
+            riff_size_offset,
+            total_frames_offset,
+            video_length_offset,
+            audio_length_offset,
+            movi_size_offset,
+            movi_start,
+            index: Vec::new(),
+            frame_count: 0,
+            audio_block_count: 0,
+            pending_samples: Vec::new(),
+        })
+    }
+
+    // Samples are interleaved left/right, and are flushed alongside the next video frame so that
+    // both streams stay in step with emulated time.
+    pub fn push_audio_sample(&mut self, sample: f32) {
+        let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+        self.pending_samples.push(sample);
+    }
+
+    pub fn write_frame(&mut self, lcd: &Lcd) -> Result<()> {
+        let mut frame_data = Vec::with_capacity(VIDEO_FRAME_SIZE as usize);
+        let color_correction = lcd.get_color_correction();
+        for row in lcd.get_buffer().iter().rev() {
+            for pixel in row {
+                let [red, green, blue] = color_correction.apply(*pixel);
+                frame_data.extend_from_slice(&[blue, green, red]);
+            }
+        }
+
+        self.write_chunk(VIDEO_CHUNK_ID, &frame_data)?;
+        self.frame_count += 1;
+
+        let audio_data = self
+            .pending_samples
+            .drain(..)
+            .flat_map(i16::to_le_bytes)
+            .collect::<Vec<_>>();
+        self.audio_block_count += (audio_data.len() / usize::from(AUDIO_BLOCK_ALIGN)) as u32;
+        self.write_chunk(AUDIO_CHUNK_ID, &audio_data)?;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        end_list(&mut self.writer, self.movi_size_offset)?;
+
+        self.writer.write_all(b"idx1")?;
+        write_u32(&mut self.writer, (self.index.len() * 16) as u32)?;
+        for entry in &self.index {
+            self.writer.write_all(&entry.chunk_id)?;
+            write_u32(&mut self.writer, AVIIF_KEYFRAME)?;
+            write_u32(&mut self.writer, entry.offset)?;
+            write_u32(&mut self.writer, entry.size)?;
+        }
+
+        let end = self.writer.stream_position()?;
+
+        patch_u32(&mut self.writer, self.riff_size_offset, (end - 8) as u32)?;
+        patch_u32(&mut self.writer, self.total_frames_offset, self.frame_count)?;
+        patch_u32(&mut self.writer, self.video_length_offset, self.frame_count)?;
+        patch_u32(
+            &mut self.writer,
+            self.audio_length_offset,
+            self.audio_block_count,
+        )?;
+
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, chunk_id: &[u8; 4], data: &[u8]) -> Result<()> {
+        let offset = self.writer.stream_position()? - self.movi_start;
+
+        self.writer.write_all(chunk_id)?;
+        write_u32(&mut self.writer, data.len() as u32)?;
+        self.writer.write_all(data)?;
+
+        // Chunks are word aligned.
+        if !data.len().is_multiple_of(2) {
+            self.writer.write_all(&[0])?;
+        }
+
+        self.index.push(IndexEntry {
+            chunk_id: *chunk_id,
+            offset: offset as u32,
+            size: data.len() as u32,
+        });
+
+        Ok(())
+    }
+}
+
+fn write_u16<W: Write>(writer: &mut W, value: u16) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn patch_u32<W: Write + Seek>(writer: &mut W, offset: u64, value: u32) -> Result<()> {
+    let current = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(offset))?;
+    write_u32(writer, value)?;
+    writer.seek(SeekFrom::Start(current))?;
+    Ok(())
+}
+
+// Returns the offset of the list's size field, to be passed to `end_list`.
+fn begin_list<W: Write + Seek>(writer: &mut W, list_type: &[u8; 4]) -> Result<u64> {
+    writer.write_all(b"LIST")?;
+    let size_offset = writer.stream_position()?;
+    write_u32(writer, 0)?;
+    writer.write_all(list_type)?;
+    Ok(size_offset)
+}
+
+fn end_list<W: Write + Seek>(writer: &mut W, size_offset: u64) -> Result<()> {
+    let end = writer.stream_position()?;
+    patch_u32(writer, size_offset, (end - size_offset - 4) as u32)
+}
@@ -0,0 +1,205 @@
+use clap::ValueEnum;
+use emulator_core::Lcd;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ScalingMode {
+    /// Stretch the image to fill the whole window.
+    Stretch,
+    /// Scale as large as possible while preserving the 3:2 aspect ratio, letterboxing the rest.
+    #[default]
+    Aspect,
+    /// Only scale by whole multiples of the native resolution.
+    Integer,
+}
+
+impl ScalingMode {
+    pub fn next(self) -> Self {
+        match self {
+            ScalingMode::Stretch => ScalingMode::Aspect,
+            ScalingMode::Aspect => ScalingMode::Integer,
+            ScalingMode::Integer => ScalingMode::Stretch,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Filter {
+    #[default]
+    Nearest,
+    Bilinear,
+}
+
+impl Filter {
+    pub fn next(self) -> Self {
+        match self {
+            Filter::Nearest => Filter::Bilinear,
+            Filter::Bilinear => Filter::Nearest,
+        }
+    }
+}
+
+// The region of the target that the GBA image is scaled into.
+pub struct Viewport {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Viewport {
+    pub fn new(scaling_mode: ScalingMode, target_width: usize, target_height: usize) -> Self {
+        let (width, height) = match scaling_mode {
+            ScalingMode::Stretch => (target_width, target_height),
+            ScalingMode::Aspect => {
+                let scale = f64::min(
+                    target_width as f64 / Lcd::LCD_WIDTH as f64,
+                    target_height as f64 / Lcd::LCD_HEIGHT as f64,
+                );
+
+                (
+                    (Lcd::LCD_WIDTH as f64 * scale) as usize,
+                    (Lcd::LCD_HEIGHT as f64 * scale) as usize,
+                )
+            }
+            ScalingMode::Integer => {
+                let scale = usize::max(
+                    1,
+                    usize::min(
+                        target_width / Lcd::LCD_WIDTH,
+                        target_height / Lcd::LCD_HEIGHT,
+                    ),
+                );
+
+                (Lcd::LCD_WIDTH * scale, Lcd::LCD_HEIGHT * scale)
+            }
+        };
+
+        Self {
+            x: target_width.saturating_sub(width) / 2,
+            y: target_height.saturating_sub(height) / 2,
+            width,
+            height,
+        }
+    }
+}
+
+// One coordinate to sample from, along either axis: the source pixels on either side, and how far
+// towards the second one it is, out of 256.
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    first: usize,
+    second: usize,
+    weight: u32,
+}
+
+// Scales RGB888 LCD output, as given by `Lcd::get_buffer_rgb888`, into an RGBA8 frame. Where each
+// column and row samples from only depends on the frame size, scaling mode and filter, so it's
+// worked out once and kept until one of those changes, rather than for every pixel of every frame.
+#[derive(Default)]
+pub struct FrameScaler {
+    settings: Option<(usize, usize, ScalingMode, Filter)>,
+    viewport_origin: (usize, usize),
+    columns: Vec<Sample>,
+    rows: Vec<Sample>,
+}
+
+impl FrameScaler {
+    // Anything outside of the scaled image is cleared to black.
+    pub fn draw(
+        &mut self,
+        source: &[u8],
+        frame: &mut [u8],
+        frame_width: usize,
+        frame_height: usize,
+        scaling_mode: ScalingMode,
+        filter: Filter,
+    ) {
+        let settings = (frame_width, frame_height, scaling_mode, filter);
+        if self.settings != Some(settings) {
+            self.update(settings);
+        }
+
+        let (viewport_x, viewport_y) = self.viewport_origin;
+        for (y, row) in frame.chunks_exact_mut(frame_width * 4).enumerate() {
+            let row_sample = y.checked_sub(viewport_y).and_then(|y| self.rows.get(y));
+
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let column_sample = x.checked_sub(viewport_x).and_then(|x| self.columns.get(x));
+
+                let [red, green, blue] = match (column_sample, row_sample) {
+                    (Some(column), Some(row)) => sample(source, column, row),
+                    _ => [0, 0, 0],
+                };
+
+                pixel.copy_from_slice(&[red, green, blue, 255]);
+            }
+        }
+    }
+
+    fn update(&mut self, settings: (usize, usize, ScalingMode, Filter)) {
+        let (frame_width, frame_height, scaling_mode, filter) = settings;
+        let viewport = Viewport::new(scaling_mode, frame_width, frame_height);
+
+        let axis_samples = |samples: &mut Vec<Sample>, source_size: usize, size: usize| {
+            samples.clear();
+            samples.extend((0..size).map(|i| match filter {
+                Filter::Nearest => {
+                    let source = (i * source_size) / size;
+                    Sample {
+                        first: source,
+                        second: source,
+                        weight: 0,
+                    }
+                }
+                Filter::Bilinear => {
+                    // Sample from pixel centers so that the image isn't shifted by half a pixel.
+                    let source = (((i as f32 + 0.5) * source_size as f32) / size as f32) - 0.5;
+                    let source = source.clamp(0.0, (source_size - 1) as f32);
+
+                    let first = source as usize;
+                    Sample {
+                        first,
+                        second: usize::min(first + 1, source_size - 1),
+                        weight: ((source - first as f32) * 256.0).round() as u32,
+                    }
+                }
+            }));
+        };
+        axis_samples(&mut self.columns, Lcd::LCD_WIDTH, viewport.width);
+        axis_samples(&mut self.rows, Lcd::LCD_HEIGHT, viewport.height);
+
+        self.viewport_origin = (viewport.x, viewport.y);
+        self.settings = Some(settings);
+    }
+}
+
+fn source_pixel(source: &[u8], x: usize, y: usize) -> [u8; 3] {
+    let index = ((y * Lcd::LCD_WIDTH) + x) * 3;
+    [source[index], source[index + 1], source[index + 2]]
+}
+
+// Blends the four source pixels around a sample in fixed point, which is exact for nearest
+// neighbour samples since they have no weight.
+fn sample(source: &[u8], column: &Sample, row: &Sample) -> [u8; 3] {
+    let top_left = source_pixel(source, column.first, row.first);
+    let top_right = source_pixel(source, column.second, row.first);
+    let bottom_left = source_pixel(source, column.first, row.second);
+    let bottom_right = source_pixel(source, column.second, row.second);
+
+    let blend = |first: u32, second: u32, weight: u32| first * (256 - weight) + second * weight;
+
+    std::array::from_fn(|channel| {
+        let top = blend(
+            u32::from(top_left[channel]),
+            u32::from(top_right[channel]),
+            column.weight,
+        );
+        let bottom = blend(
+            u32::from(bottom_left[channel]),
+            u32::from(bottom_right[channel]),
+            column.weight,
+        );
+
+        ((blend(top, bottom, row.weight) + (1 << 15)) >> 16) as u8
+    })
+}
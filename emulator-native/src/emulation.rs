@@ -0,0 +1,400 @@
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use emulator_core::{
+    calculate_lcd_checksum, Autofire, AutofireConfig, Cpu, InputTiming, KeysState, CYCLES_PER_FRAME,
+};
+
+use crate::avi_recorder::AviRecorder;
+use crate::frame_channel::FrameSender;
+use crate::netplay::UdpNetplay;
+use crate::pacer::FramePacer;
+use crate::sample_source::SampleSourceSender;
+use crate::{
+    emulate, emulate_to_vblank, export_save_data, load_state, save_screenshot, save_state,
+    start_recording, state_file_name, stop_recording, write_coverage, write_save_data, Args, Speed,
+    AUDIO_BUFFER_TARGET_SAMPLES, SOLAR_LEVEL_STEP,
+};
+
+// Requests from the window to the emulation thread, applied before emulating the next frame.
+#[derive(Clone, Copy, Debug)]
+pub enum Command {
+    SetKeys(KeysState),
+    TogglePause,
+    AdvanceFrame,
+    CycleSpeed,
+    SaveState(u8),
+    LoadState(u8),
+    RaiseSolarLevel,
+    LowerSolarLevel,
+    ToggleRecording,
+    Screenshot,
+    LogChecksum,
+}
+
+// Sent back from the emulation thread for the window to show.
+#[derive(Clone, Debug)]
+pub enum Notification {
+    Message(String),
+    Fps(f64),
+}
+
+// Runs the emulator on its own thread, so that window events never stall emulation and the
+// window never waits on emulation. Frames are handed over through a `FrameSender`.
+pub struct EmulationThread {
+    commands: Sender<Command>,
+    exit: Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EmulationThread {
+    pub fn spawn(
+        cpu: Cpu,
+        args: Args,
+        save_file_name: String,
+        source_sender: SampleSourceSender,
+        frame_sender: FrameSender,
+        netplay: Option<UdpNetplay>,
+    ) -> Result<(Self, Receiver<Notification>)> {
+        let (command_sender, commands) = mpsc::channel();
+        let (exit_sender, exit) = mpsc::channel();
+        let (notification_sender, notifications) = mpsc::channel();
+
+        let recorder = args.record.as_deref().and_then(start_recording);
+        let mut turbo_keys = KeysState::default();
+        for &key in &args.turbo_keys {
+            turbo_keys.set_pressed(key, true);
+        }
+        let autofire = Autofire::new(AutofireConfig {
+            keys: turbo_keys,
+            period_frames: args.turbo_period,
+        });
+        let emulation = Emulation {
+            cpu,
+            args,
+            save_file_name,
+            source_sender,
+            frame_sender,
+            notifications: notification_sender,
+            recorder,
+            netplay,
+            paused: false,
+            advance_frame: false,
+            speed: Speed::Full,
+            solar_level: 0,
+            keys_state: KeysState::default(),
+            autofire,
+        };
+
+        let thread = thread::Builder::new()
+            .name("emulation".to_string())
+            .spawn(move || emulation.run(commands, exit))?;
+
+        let emulation_thread = Self {
+            commands: command_sender,
+            exit: exit_sender,
+            thread: Some(thread),
+        };
+
+        Ok((emulation_thread, notifications))
+    }
+
+    // Commands sent after the thread finished are ignored, since the window is about to close
+    // anyway.
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    // Set once the frame limit is reached, or if emulation panicked.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    // Stops emulation, waiting for save data and any recording to be written out.
+    pub fn exit(&mut self) {
+        let _ = self.exit.send(());
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("emulation thread panicked");
+            }
+        }
+    }
+}
+
+struct Emulation {
+    cpu: Cpu,
+    args: Args,
+    save_file_name: String,
+    source_sender: SampleSourceSender,
+    frame_sender: FrameSender,
+    notifications: Sender<Notification>,
+    recorder: Option<AviRecorder>,
+    netplay: Option<UdpNetplay>,
+    paused: bool,
+    advance_frame: bool,
+    speed: Speed,
+    // Light reaching solar sensor cartridges, like Boktai's.
+    solar_level: u8,
+    keys_state: KeysState,
+    autofire: Autofire,
+}
+
+impl Emulation {
+    fn run(mut self, commands: Receiver<Command>, exit: Receiver<()>) {
+        let init = Instant::now();
+        let mut last_frame = Instant::now();
+        let mut pacer = FramePacer::default();
+        let mut i = 0;
+        let save_interval = Duration::from_secs(self.args.save_interval);
+        let mut last_save = Instant::now();
+
+        loop {
+            if !matches!(exit.try_recv(), Err(TryRecvError::Empty)) {
+                break;
+            }
+
+            while let Ok(command) = commands.try_recv() {
+                self.handle_command(command);
+            }
+
+            if last_save.elapsed() >= save_interval {
+                last_save = Instant::now();
+
+                if self.cpu.bus.cartridge.poll_backup_dirty() {
+                    match write_save_data(&self.cpu, &self.save_file_name) {
+                        Ok(()) => log::info!("wrote save data to {}", self.save_file_name),
+                        Err(e) => log::error!("failed to write save data: {e}"),
+                    }
+                }
+            }
+
+            let emulated_frame = self.emulate_frame();
+
+            if let Some(frame) = self.netplay.as_mut().and_then(UdpNetplay::poll_desync) {
+                self.show(format!("netplay desync at frame {frame}"));
+            }
+
+            let lcd = &self.cpu.bus.lcd;
+            self.frame_sender
+                .send(|buffer| lcd.write_buffer_rgb888(buffer));
+
+            if let Some(active_recorder) = self.recorder.as_mut().filter(|_| emulated_frame) {
+                if let Err(e) = active_recorder.write_frame(&self.cpu.bus.lcd) {
+                    log::error!("failed to write frame to recording, stopping: {e}");
+                    if let Some(failed_recorder) = self.recorder.take() {
+                        stop_recording(failed_recorder);
+                    }
+                }
+            }
+
+            // Each frame covers exactly one GBA frame of emulated time, so when paced it takes
+            // that long, regardless of the host's refresh rate. Slow motion only works if frames
+            // are paced, so it implies a framerate limit. Netplay is paced too, since both sides
+            // have to run at the same rate. Pausing is paced to avoid spinning.
+            let paced = self.args.limit_framerate
+                || self.speed != Speed::Full
+                || self.paused
+                || self.netplay.is_some();
+            match self
+                .cpu
+                .clock()
+                .host_duration(CYCLES_PER_FRAME)
+                .filter(|_| paced)
+            {
+                Some(frame_duration) => pacer.wait(frame_duration),
+                None => pacer.reset(),
+            }
+
+            let time_elapsed = last_frame.elapsed();
+            let _ = self
+                .notifications
+                .send(Notification::Fps(1.0 / time_elapsed.as_secs_f64()));
+
+            last_frame = Instant::now();
+            if emulated_frame {
+                if self.args.frames.is_some_and(|frames| i >= frames) {
+                    break;
+                }
+
+                i += 1;
+            }
+        }
+
+        log::info!("ran for {:?}", init.elapsed());
+        self.finish();
+    }
+
+    // Returns whether a frame was emulated, rather than waiting on being unpaused or the netplay
+    // peer.
+    fn emulate_frame(&mut self) -> bool {
+        if let Some(netplay) = &mut self.netplay {
+            // Pausing and frame advance would only stall the peer, so they're ignored and every
+            // frame runs with whatever input both sides agreed on.
+            match netplay.poll(self.autofire.apply(self.keys_state), &self.cpu) {
+                Some(frame_keys_state) => {
+                    self.cpu.bus.keypad.set_state(frame_keys_state);
+                    emulate_to_vblank(&mut self.cpu, &mut self.source_sender, &mut self.recorder);
+                    true
+                }
+                None => false,
+            }
+        } else if self.advance_frame {
+            self.advance_frame = false;
+
+            self.queue_keys();
+            emulate_to_vblank(&mut self.cpu, &mut self.source_sender, &mut self.recorder);
+            true
+        } else if !self.paused {
+            self.queue_keys();
+            self.cpu.sync_to_audio_buffer(
+                self.source_sender.buffered_samples(),
+                AUDIO_BUFFER_TARGET_SAMPLES,
+            );
+            emulate(
+                &mut self.cpu,
+                &mut self.source_sender,
+                &mut self.recorder,
+                |_, cycles_elapsed| cycles_elapsed >= CYCLES_PER_FRAME,
+            );
+            true
+        } else {
+            false
+        }
+    }
+
+    // Input changes at the next frame boundary, wherever the last frame happened to stop, so
+    // that it lands at the same point in emulated time as it would during playback.
+    fn queue_keys(&mut self) {
+        let keys_state = self.autofire.apply(self.keys_state);
+        let next_frame = self.cpu.bus.lcd.frame_count() + 1;
+        self.cpu
+            .bus
+            .queue_input(InputTiming::Frame(next_frame), keys_state);
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::SetKeys(keys_state) => self.keys_state = keys_state,
+            Command::TogglePause => {
+                self.paused = !self.paused;
+                let message = if self.paused { "paused" } else { "resumed" };
+                log::info!("{message}");
+                self.show(message);
+            }
+            Command::AdvanceFrame => {
+                // Frame advance always leaves the emulator paused afterwards.
+                self.paused = true;
+                self.advance_frame = true;
+            }
+            Command::CycleSpeed => {
+                self.speed = self.speed.next();
+                self.cpu.set_speed_multiplier(self.speed.factor());
+                let message = format!("speed: {}%", self.speed.factor() * 100.0);
+                log::info!("{message}");
+                self.show(message);
+            }
+            Command::SaveState(slot) => {
+                let state_file_name = state_file_name(&self.args.rom, slot);
+                match save_state(&self.cpu, &state_file_name) {
+                    Ok(()) => {
+                        log::info!("saved state to {state_file_name}");
+                        self.show(format!("state {slot} saved"));
+                    }
+                    Err(e) => {
+                        log::error!("failed to save state: {e}");
+                        self.show(format!("failed to save state {slot}"));
+                    }
+                }
+            }
+            Command::LoadState(slot) => {
+                let state_file_name = state_file_name(&self.args.rom, slot);
+                match load_state(&mut self.cpu, &state_file_name) {
+                    Ok(()) => {
+                        self.cpu.bus.cartridge.set_solar_level(self.solar_level);
+                        log::info!("loaded state from {state_file_name}");
+                        self.show(format!("state {slot} loaded"));
+                    }
+                    Err(e) => {
+                        log::error!("failed to load state: {e}");
+                        self.show(format!("failed to load state {slot}"));
+                    }
+                }
+            }
+            Command::RaiseSolarLevel | Command::LowerSolarLevel => {
+                self.solar_level = if matches!(command, Command::RaiseSolarLevel) {
+                    self.solar_level.saturating_add(SOLAR_LEVEL_STEP)
+                } else {
+                    self.solar_level.saturating_sub(SOLAR_LEVEL_STEP)
+                };
+                self.cpu.bus.cartridge.set_solar_level(self.solar_level);
+                let message = format!("solar level: {}", self.solar_level);
+                log::info!("{message}");
+                self.show(message);
+            }
+            Command::ToggleRecording => match self.recorder.take() {
+                Some(active_recorder) => {
+                    stop_recording(active_recorder);
+                    self.show("recording stopped");
+                }
+                None => {
+                    let recording_file_name = (0..)
+                        .map(|idx| format!("{}.recording{idx}.avi", self.args.rom))
+                        .find(|file_name| !Path::new(file_name).exists())
+                        .unwrap();
+                    self.recorder = start_recording(&recording_file_name);
+                    if self.recorder.is_some() {
+                        self.show("recording started");
+                    }
+                }
+            },
+            Command::Screenshot => {
+                match save_screenshot(&self.cpu, &self.args.rom, self.args.raw_screenshots) {
+                    Ok(file_name) => {
+                        log::info!("saved screenshot to {file_name}");
+                        self.show("screenshot saved");
+                    }
+                    Err(e) => log::error!("failed to save screenshot: {e}"),
+                }
+            }
+            Command::LogChecksum => {
+                log::error!(
+                    "current checksum: {:016X}",
+                    calculate_lcd_checksum(&self.cpu)
+                );
+            }
+        }
+    }
+
+    fn show(&self, message: impl Into<String>) {
+        let _ = self
+            .notifications
+            .send(Notification::Message(message.into()));
+    }
+
+    fn finish(mut self) {
+        if let Some(active_recorder) = self.recorder.take() {
+            stop_recording(active_recorder);
+        }
+
+        log::info!("writing save data to {}", self.save_file_name);
+        if let Err(e) = write_save_data(&self.cpu, &self.save_file_name) {
+            log::error!("failed to write save data: {e}");
+            return;
+        }
+        log::info!("finished writing save data to {}", self.save_file_name);
+
+        if let Some(export_file_name) = &self.args.export_save {
+            export_save_data(&self.cpu, export_file_name, self.args.export_save_format)
+                .expect("failed to export save data");
+            log::info!("exported save data to {export_file_name}");
+        }
+
+        if let Some(coverage_file_name) = &self.args.coverage {
+            write_coverage(&self.cpu, coverage_file_name).expect("failed to write coverage");
+            log::info!("wrote coverage to {coverage_file_name}");
+        }
+    }
+}
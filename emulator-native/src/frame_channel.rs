@@ -0,0 +1,70 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+
+// Set in `Shared::middle` when the buffer it points to holds a frame the receiver hasn't seen.
+const NEW_FRAME: usize = 0b100;
+const INDEX_MASK: usize = 0b011;
+
+// Triple buffered frames, handed from the emulation thread to the render thread without either
+// ever waiting on the other. At any time one buffer is being written, one is being shown, and the
+// third holds the newest finished frame, which each side swaps its own buffer with.
+//
+// Each buffer is only ever owned by one side at a time, so its lock is never contended. It's only
+// there to share the buffers without unsafe code.
+struct Shared {
+    buffers: [Mutex<Vec<u8>>; 3],
+    middle: AtomicUsize, // index of the buffer neither side owns, plus `NEW_FRAME`
+}
+
+pub struct FrameSender {
+    shared: Arc<Shared>,
+    back: usize,
+}
+
+pub struct FrameReceiver {
+    shared: Arc<Shared>,
+    front: usize,
+}
+
+pub fn frame_channel() -> (FrameSender, FrameReceiver) {
+    let shared = Arc::new(Shared {
+        buffers: Default::default(),
+        middle: AtomicUsize::new(1),
+    });
+
+    let frame_sender = FrameSender {
+        shared: Arc::clone(&shared),
+        back: 0,
+    };
+
+    let frame_receiver = FrameReceiver { shared, front: 2 };
+
+    (frame_sender, frame_receiver)
+}
+
+impl FrameSender {
+    // Publishes a new frame, filled in by `write`. A frame the receiver never got to is dropped.
+    pub fn send(&mut self, write: impl FnOnce(&mut Vec<u8>)) {
+        write(&mut self.shared.buffers[self.back].lock().unwrap());
+
+        let previous = self
+            .shared
+            .middle
+            .swap(self.back | NEW_FRAME, Ordering::AcqRel);
+        self.back = previous & INDEX_MASK;
+    }
+}
+
+impl FrameReceiver {
+    // The newest frame sent, which is empty until the first one is.
+    pub fn frame(&mut self) -> MutexGuard<'_, Vec<u8>> {
+        if self.shared.middle.load(Ordering::Acquire) & NEW_FRAME != 0 {
+            let previous = self.shared.middle.swap(self.front, Ordering::AcqRel);
+            self.front = previous & INDEX_MASK;
+        }
+
+        self.shared.buffers[self.front].lock().unwrap()
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver},
+        Arc,
+    },
+    thread,
+};
+
+use anyhow::Result;
+use emulator_core::{LinkMessage, LinkTransport};
+
+const TRANSFER_TAG: u8 = 0;
+const REPLY_TAG: u8 = 1;
+const MULTIPLAYER_DATA_TAG: u8 = 2;
+
+// A link cable to another emulator over TCP. Each message is a tag byte followed by the data as
+// either a little-endian u32, or four little-endian u16s for multiplayer data. Incoming messages
+// are read on a background thread, so that polling from the emulator never blocks.
+pub struct TcpLinkTransport {
+    parent: bool,
+    stream: TcpStream,
+    receiver: Receiver<LinkMessage>,
+    connected: Arc<AtomicBool>,
+}
+
+impl TcpLinkTransport {
+    // Waits for the other emulator to connect. The listening side is always the parent.
+    pub fn listen(address: impl ToSocketAddrs) -> Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        log::info!(
+            "waiting for link cable connection on {}",
+            listener.local_addr()?
+        );
+
+        let (stream, peer_address) = listener.accept()?;
+        log::info!("link cable connected to {peer_address}");
+
+        Self::new(stream, true)
+    }
+
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        log::info!("link cable connected to {}", stream.peer_addr()?);
+
+        Self::new(stream, false)
+    }
+
+    fn new(stream: TcpStream, parent: bool) -> Result<Self> {
+        // Messages are tiny and latency sensitive, so shouldn't be held back to be batched.
+        stream.set_nodelay(true)?;
+
+        let (sender, receiver) = channel();
+        let connected = Arc::new(AtomicBool::new(true));
+
+        let mut reader = stream.try_clone()?;
+        let reader_connected = Arc::clone(&connected);
+        thread::spawn(move || {
+            let error = loop {
+                match read_message(&mut reader) {
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            break anyhow::anyhow!("emulator stopped");
+                        }
+                    }
+                    Err(e) => break e,
+                }
+            };
+
+            log::info!("link cable disconnected: {error}");
+            reader_connected.store(false, Ordering::Relaxed);
+        });
+
+        Ok(Self {
+            parent,
+            stream,
+            receiver,
+            connected,
+        })
+    }
+}
+
+impl LinkTransport for TcpLinkTransport {
+    fn is_parent(&self) -> bool {
+        self.parent
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    fn send(&mut self, message: LinkMessage) {
+        let buffer = match message {
+            LinkMessage::Transfer(data) => [&[TRANSFER_TAG], &data.to_le_bytes()[..]].concat(),
+            LinkMessage::Reply(data) => [&[REPLY_TAG], &data.to_le_bytes()[..]].concat(),
+            LinkMessage::MultiplayerData(data) => {
+                let mut buffer = vec![MULTIPLAYER_DATA_TAG];
+                for player_data in data {
+                    buffer.extend_from_slice(&player_data.to_le_bytes());
+                }
+                buffer
+            }
+        };
+
+        if let Err(e) = self.stream.write_all(&buffer) {
+            log::error!("failed to send link cable message: {e}");
+            self.connected.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn poll(&mut self) -> Option<LinkMessage> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+fn read_message(reader: &mut impl Read) -> Result<LinkMessage> {
+    let mut tag = [0; 1];
+    reader.read_exact(&mut tag)?;
+
+    let message = match tag[0] {
+        TRANSFER_TAG | REPLY_TAG => {
+            let mut data = [0; 4];
+            reader.read_exact(&mut data)?;
+            let data = u32::from_le_bytes(data);
+
+            if tag[0] == TRANSFER_TAG {
+                LinkMessage::Transfer(data)
+            } else {
+                LinkMessage::Reply(data)
+            }
+        }
+        MULTIPLAYER_DATA_TAG => {
+            let mut data = [0; 8];
+            reader.read_exact(&mut data)?;
+
+            LinkMessage::MultiplayerData(std::array::from_fn(|i| {
+                u16::from_le_bytes([data[i * 2], data[i * 2 + 1]])
+            }))
+        }
+        tag => anyhow::bail!("invalid link cable message tag {tag}"),
+    };
+
+    Ok(message)
+}
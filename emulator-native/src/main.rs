@@ -1,9 +1,29 @@
+mod avi_recorder;
+mod display;
+mod emulation;
+mod frame_channel;
+mod link;
+mod netplay;
+mod osd;
+mod pacer;
+mod post_process;
 mod sample_source;
 
-use sample_source::sample_source;
-
-use std::time::Duration;
-use std::{fs::File, time::Instant};
+use avi_recorder::AviRecorder;
+use display::{Filter, FrameScaler, ScalingMode, Viewport};
+use emulation::{Command, EmulationThread, Notification};
+use frame_channel::frame_channel;
+use link::TcpLinkTransport;
+use netplay::UdpNetplay;
+use osd::Osd;
+use post_process::{PostProcess, PostProcessRenderer};
+use sample_source::{sample_source, SampleSourceSender};
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, Result};
 use clap::Parser;
@@ -11,18 +31,28 @@ use pixels::{wgpu::TextureFormat, PixelsBuilder, SurfaceTexture};
 use rodio::{OutputStream, Sink};
 use winit::event_loop::EventLoop;
 use winit::{
-    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent},
     event_loop::ControlFlow,
-    window::WindowBuilder,
+    window::{Fullscreen, WindowBuilder},
 };
 
-use emulator_core::{calculate_lcd_checksum, Cartridge, Cpu, Key, Lcd, CYCLES_PER_SECOND};
+use emulator_core::{
+    AutofireConfig, BackupFileFormat, BootMode, CardFiles, Cartridge, CartridgeHeader,
+    ColorCorrection, CoverageRecorder, Cpu, CpuConfig, ErrorPolicy, Key, KeysState, SaveState,
+    TimingMode, CYCLES_PER_FRAME, CYCLES_PER_SECOND,
+};
 
-const APU_SAMPLE_RATE: u32 = 44_100;
-const FPS_TARGET: u32 = 60;
+const HOST_SAMPLE_RATE: u32 = 44_100;
+// Amount of (interleaved stereo) audio to keep queued when syncing to the host audio clock.
+const AUDIO_BUFFER_TARGET_SAMPLES: usize = (HOST_SAMPLE_RATE / 10 * 2) as usize;
+// How much Page Up/Down change the light level reaching solar sensor cartridges.
+const SOLAR_LEVEL_STEP: u8 = 0x10;
+// Amount of (interleaved stereo) audio that can be queued for playback before more is dropped.
+const AUDIO_RING_CAPACITY: usize = (HOST_SAMPLE_RATE * 2) as usize;
 
-#[derive(Debug, Parser)]
+#[derive(Clone, Debug, Parser)]
 struct Args {
+    /// ROM to run, or a multiboot image to boot from EWRAM if it ends in .mb.
     rom: String,
 
     #[clap(short, long)]
@@ -30,6 +60,129 @@ struct Args {
 
     #[clap(long)]
     limit_framerate: bool,
+
+    /// Never pace frames, even when slowed down or paused. Useful for benchmarking.
+    #[clap(long)]
+    uncapped: bool,
+
+    /// Let emulation speed drift very slightly to keep audio in step with the host's audio
+    /// output, rather than running at exactly the GBA's clock rate.
+    #[clap(long)]
+    sync_to_audio: bool,
+
+    /// Save screenshots as raw little-endian RGB555 instead of PNG.
+    #[clap(long)]
+    raw_screenshots: bool,
+
+    /// Record video and audio to an uncompressed AVI file from startup.
+    #[clap(long)]
+    record: Option<String>,
+
+    /// How the image is scaled to fit the window. Cycle at runtime with F6.
+    #[clap(long, value_enum, default_value_t)]
+    scaling: ScalingMode,
+
+    /// Texture filtering used when scaling. Toggle at runtime with F7.
+    #[clap(long, value_enum, default_value_t)]
+    filter: Filter,
+
+    /// Start in borderless fullscreen. Toggle at runtime with F11.
+    #[clap(long)]
+    fullscreen: bool,
+
+    /// Post-processing shader applied to the output. Cycle at runtime with F8.
+    #[clap(long, value_enum, default_value_t)]
+    post_process: PostProcess,
+
+    /// Color correction applied to the LCD output: raw, agb (original unlit screen) or ags-101
+    /// (backlit screen).
+    #[clap(long, default_value_t)]
+    color_correction: ColorCorrection,
+
+    /// Don't draw status messages or the FPS counter over the image.
+    #[clap(long)]
+    no_osd: bool,
+
+    /// Skip the BIOS boot animation and start directly at the cartridge entry point.
+    #[clap(long)]
+    skip_bios: bool,
+
+    /// Execute opcodes the emulator doesn't know as no-ops, instead of taking the Undefined
+    /// exception. May get further into games that hit a missing instruction.
+    #[clap(long)]
+    ignore_unimplemented: bool,
+
+    /// Fast-forward through loops that only wait for a register or interrupt to change. Speeds up
+    /// menus and other idle screens a lot, at a small cost in timing accuracy.
+    #[clap(long)]
+    skip_idle_loops: bool,
+
+    /// Run EWRAM without waitstates, like games that overclock it through the undocumented
+    /// internal memory control register, only faster still.
+    #[clap(long)]
+    fast_ewram: bool,
+
+    /// Load the given save state slot (1-4) on startup.
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=4))]
+    autoload_state: Option<u8>,
+
+    /// Wait for another instance to connect a link cable on the given address, as the parent.
+    #[clap(long, conflicts_with = "link_connect")]
+    link_listen: Option<String>,
+
+    /// Connect a link cable to another instance listening on the given address.
+    #[clap(long)]
+    link_connect: Option<String>,
+
+    /// Play over the network in lockstep with another instance, sending and receiving input on
+    /// the given local UDP address. Both instances need the same ROM, save data and options.
+    #[clap(long, requires = "netplay_peer")]
+    netplay_bind: Option<String>,
+
+    /// UDP address of the other instance to play with, see --netplay-bind.
+    #[clap(long, requires = "netplay_bind")]
+    netplay_peer: Option<String>,
+
+    /// Frames local input is delayed by during netplay, to hide network latency. Has to match on
+    /// both instances.
+    #[clap(long, default_value_t = 3)]
+    input_delay: u32,
+
+    /// How often, in seconds, modified cartridge save data is written to disk.
+    #[clap(long, default_value_t = 5)]
+    save_interval: u64,
+
+    /// Replace the cartridge save data with a save file from another emulator (raw, as written by
+    /// mGBA, VisualBoyAdvance or no$gba). The format is detected from the file.
+    #[clap(long)]
+    import_save: Option<String>,
+
+    /// Write the cartridge save data to the given file on exit, for use in other emulators.
+    #[clap(long)]
+    export_save: Option<String>,
+
+    /// Format of the file written by --export-save: raw, vba or no-cash.
+    #[clap(long, default_value_t)]
+    export_save_format: BackupFileFormat,
+
+    /// Pre-decoded e-Reader card to swipe when the e-Reader scans a card. Can be given more than
+    /// once, each scan takes the next card.
+    #[clap(long = "card")]
+    cards: Vec<PathBuf>,
+
+    /// Key to fire repeatedly while held (a, b, l, r, start, select, up, down, left or right).
+    /// Can be given more than once.
+    #[clap(long = "turbo")]
+    turbo_keys: Vec<Key>,
+
+    /// How many frames turbo keys stay pressed, then released, while held.
+    #[clap(long, default_value_t = AutofireConfig::DEFAULT_PERIOD_FRAMES)]
+    turbo_period: u32,
+
+    /// Record which ROM and memory bytes are executed, read and written, and write the coverage
+    /// bitmaps to the given file on exit.
+    #[clap(long)]
+    coverage: Option<String>,
 }
 
 #[allow(unused)]
@@ -44,21 +197,245 @@ fn press_key(cpu: &mut Cpu, key: Key) {
     }
 }
 
+fn save_screenshot(cpu: &Cpu, rom_path: &str, raw: bool) -> Result<String> {
+    let extension = if raw { "rgb555" } else { "png" };
+
+    let screenshot_file_name = (0..)
+        .map(|idx| format!("{rom_path}.screenshot{idx}.{extension}"))
+        .find(|file_name| !Path::new(file_name).exists())
+        .unwrap();
+
+    let screenshot_file = BufWriter::new(File::create(&screenshot_file_name)?);
+    if raw {
+        cpu.bus.lcd.export_frame_raw(screenshot_file)?;
+    } else {
+        cpu.bus.lcd.export_frame_png(screenshot_file)?;
+    }
+
+    Ok(screenshot_file_name)
+}
+
+// For ROMs loaded from an archive, the save is named after the ROM inside of it so that it's shared
+// with an uncompressed copy in the same directory.
+fn save_file_name(rom_path: &str, cartridge: &Cartridge) -> String {
+    match cartridge.rom_name() {
+        Some(rom_name) => Path::new(rom_path)
+            .with_file_name(format!("{rom_name}.sav"))
+            .display()
+            .to_string(),
+        None => format!("{rom_path}.sav"),
+    }
+}
+
+// Writes to a temporary file first, so that a crash in the middle of writing can't leave a
+// truncated save file behind.
+fn write_save_data(cpu: &Cpu, save_file_name: &str) -> Result<()> {
+    write_file_atomically(save_file_name, |writer| {
+        serde_cbor::to_writer(writer, cpu.bus.cartridge.get_backup())?;
+        Ok(())
+    })
+}
+
+// Writes to a temporary file that only replaces `file_name` once it's safely on disk, so a crash
+// or full disk partway through leaves the old file intact.
+fn write_file_atomically(
+    file_name: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<()> {
+    let temp_file_name = format!("{file_name}.tmp");
+
+    let mut writer = BufWriter::new(File::create(&temp_file_name)?);
+    write(&mut writer)?;
+    writer.into_inner()?.sync_all()?;
+    std::fs::rename(&temp_file_name, file_name)?;
+
+    Ok(())
+}
+
+fn export_save_data(cpu: &Cpu, file_name: &str, format: BackupFileFormat) -> Result<()> {
+    std::fs::write(file_name, cpu.bus.cartridge.export_backup(format))?;
+
+    Ok(())
+}
+
+fn write_coverage(cpu: &Cpu, coverage_file_name: &str) -> Result<()> {
+    let Some(coverage) = cpu.bus.coverage() else {
+        return Ok(());
+    };
+
+    coverage.export(BufWriter::new(File::create(coverage_file_name)?))?;
+
+    if let Some(summary) = cpu.coverage_summary() {
+        log::info!(
+            "executed {} of {} ROM bytes ({:.2}%)",
+            summary.executed_rom_bytes,
+            summary.rom_size,
+            summary.executed_percent()
+        );
+    }
+
+    Ok(())
+}
+
+fn state_file_name(rom_path: &str, slot: u8) -> String {
+    format!("{rom_path}.ss{slot}")
+}
+
+fn save_state(cpu: &Cpu, file_name: &str) -> Result<()> {
+    let state = SaveState::from_cpu(cpu)?;
+    write_file_atomically(file_name, |writer| state.write(writer))
+}
+
+fn load_state(cpu: &mut Cpu, file_name: &str) -> Result<()> {
+    let state_file = BufReader::new(File::open(file_name)?);
+    cpu.load_state(SaveState::read(state_file)?.to_cpu()?);
+
+    Ok(())
+}
+
+fn start_recording(file_name: &str) -> Option<AviRecorder> {
+    match AviRecorder::new(
+        file_name,
+        CYCLES_PER_SECOND as u32,
+        CYCLES_PER_FRAME as u32,
+        HOST_SAMPLE_RATE,
+    ) {
+        Ok(recorder) => {
+            log::info!("started recording to {file_name}");
+            Some(recorder)
+        }
+        Err(e) => {
+            log::error!("failed to start recording to {file_name}: {e}");
+            None
+        }
+    }
+}
+
+fn stop_recording(recorder: AviRecorder) {
+    match recorder.finish() {
+        Ok(()) => log::info!("finished recording"),
+        Err(e) => log::error!("failed to finish recording: {e}"),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Speed {
+    Full,
+    Half,
+    Quarter,
+}
+
+impl Speed {
+    fn next(self) -> Self {
+        match self {
+            Speed::Full => Speed::Half,
+            Speed::Half => Speed::Quarter,
+            Speed::Quarter => Speed::Full,
+        }
+    }
+
+    fn factor(self) -> f64 {
+        match self {
+            Speed::Full => 1.0,
+            Speed::Half => 0.5,
+            Speed::Quarter => 0.25,
+        }
+    }
+}
+
+// Runs until the start of the next vblank, so that exactly one new video frame is shown.
+fn emulate_to_vblank(
+    cpu: &mut Cpu,
+    source_sender: &mut SampleSourceSender,
+    recorder: &mut Option<AviRecorder>,
+) {
+    let mut previous_vcount = cpu.bus.lcd.read_vcount::<u16>(0);
+    emulate(cpu, source_sender, recorder, |cpu, _| {
+        let vcount = cpu.bus.lcd.read_vcount::<u16>(0);
+        let vblank_entered = previous_vcount != 160 && vcount == 160;
+        previous_vcount = vcount;
+        vblank_entered
+    });
+}
+
+// Runs the CPU until `should_stop` returns true, feeding generated audio to the output (and the
+// recorder, if active). `should_stop` is given the number of cycles elapsed so far.
+fn emulate(
+    cpu: &mut Cpu,
+    source_sender: &mut SampleSourceSender,
+    recorder: &mut Option<AviRecorder>,
+    mut should_stop: impl FnMut(&Cpu, u64) -> bool,
+) {
+    let cycle_start = cpu.bus.cycle_count();
+    let mut host_samples = 0;
+    // The APU's own output rate depends on SOUNDBIAS, so its samples are averaged together (or
+    // repeated) to make up each host sample.
+    let mut next_apu_sample_cycle = 0;
+    let mut apu_sample_sum = [0.0; 2];
+    let mut apu_sample_count = 0;
+    let mut sample = [0.0; 2];
+    loop {
+        let cycles_elapsed = cpu.bus.cycle_count() - cycle_start;
+
+        cpu.fetch_decode_execute();
+
+        while next_apu_sample_cycle < cycles_elapsed {
+            let apu_sample = cpu.sample_apu();
+            apu_sample_sum[0] += apu_sample[0];
+            apu_sample_sum[1] += apu_sample[1];
+            apu_sample_count += 1;
+            next_apu_sample_cycle += CYCLES_PER_SECOND / u64::from(cpu.apu_sample_rate());
+        }
+
+        while cycles_elapsed > (host_samples * CYCLES_PER_SECOND / u64::from(HOST_SAMPLE_RATE)) {
+            if apu_sample_count > 0 {
+                sample = apu_sample_sum.map(|sum| sum / apu_sample_count as f32);
+                apu_sample_sum = [0.0; 2];
+                apu_sample_count = 0;
+            }
+
+            source_sender.push(sample);
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.push_audio_sample(sample[0]);
+                recorder.push_audio_sample(sample[1]);
+            }
+            host_samples += 1;
+        }
+
+        if should_stop(cpu, cycles_elapsed) {
+            break;
+        }
+    }
+}
+
 fn main() -> Result<()> {
     env_logger::init();
 
     let (_stream, stream_handle) = OutputStream::try_default().unwrap();
     let sink = Sink::try_new(&stream_handle).unwrap();
 
-    let (mut source_sender, source) = sample_source(APU_SAMPLE_RATE);
+    let (source_sender, source) = sample_source(HOST_SAMPLE_RATE, AUDIO_RING_CAPACITY);
     sink.append(source);
 
     let args = Args::parse();
 
-    let save_file_name = format!("{}.sav", args.rom);
+    // Multiboot images run from EWRAM with the cartridge slot left empty.
+    let multiboot_image = Path::new(&args.rom)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("mb"))
+        .then(|| std::fs::read(&args.rom))
+        .transpose()
+        .map_err(|_| anyhow!("failed to open multiboot image \"{}\"", args.rom))?;
+    let mut cartridge = match &multiboot_image {
+        Some(_) => Cartridge::empty(),
+        None => {
+            let rom_file = File::open(&args.rom)
+                .map_err(|_| anyhow!("failed to open ROM file \"{}\"", args.rom))?;
+            Cartridge::new(rom_file, None)?
+        }
+    };
 
-    let rom_file =
-        File::open(&args.rom).map_err(|_| anyhow!("failed to open ROM file \"{}\"", args.rom))?;
+    let save_file_name = save_file_name(&args.rom, &cartridge);
 
     let save_file = File::open(&save_file_name).ok();
 
@@ -66,95 +443,209 @@ fn main() -> Result<()> {
     let save_data = save_file.map(serde_cbor::from_reader).transpose()?;
 
     match save_data {
-        Some(_) => log::info!("successfuly read save info from {save_file_name}"),
+        Some(save_data) => {
+            cartridge.set_backup(save_data)?;
+            log::info!("successfuly read save info from {save_file_name}");
+        }
         None => log::info!("failed to read save info from {save_file_name}"),
     };
 
+    if let Some(import_file_name) = &args.import_save {
+        let format = cartridge
+            .import_backup(&std::fs::read(import_file_name)?)
+            .map_err(|e| anyhow!("failed to import save from {import_file_name}: {e}"))?;
+        log::info!("imported {format} save from {import_file_name}");
+    }
+
+    if !args.cards.is_empty() {
+        if !cartridge.has_ereader() {
+            log::warn!("--card given, but the cartridge isn't an e-Reader");
+        }
+        cartridge.set_card_source(Some(Arc::new(Mutex::new(CardFiles::new(
+            args.cards.clone(),
+        )))));
+    }
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    {
+        let interrupted = Arc::clone(&interrupted);
+        ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst))?;
+    }
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
-        .with_title("Quantatic's GBA Emulator")
+        .with_title(format!(
+            "Quantatic's GBA Emulator - {}",
+            match &multiboot_image {
+                Some(image) => CartridgeHeader::parse(image).title,
+                None => cartridge.header().title,
+            }
+        ))
+        .with_fullscreen(args.fullscreen.then_some(Fullscreen::Borderless(None)))
         .build(&event_loop)?;
 
+    // Scaling and filtering are done on the CPU, so the pixel buffer always matches the surface
+    // size and is presented 1:1.
+    let mut window_size = window.inner_size();
     let mut pixels = {
-        let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        PixelsBuilder::new(
-            Lcd::LCD_WIDTH.try_into().unwrap(),
-            Lcd::LCD_HEIGHT.try_into().unwrap(),
-            surface_texture,
-        )
-        .texture_format(TextureFormat::Rgba8UnormSrgb)
-        .enable_vsync(true)
-        .build()?
+        PixelsBuilder::new(window_size.width, window_size.height, surface_texture)
+            .texture_format(TextureFormat::Rgba8UnormSrgb)
+            .enable_vsync(true)
+            .build()?
     };
 
-    let cartridge = Cartridge::new(rom_file, save_data)?;
-    let mut cpu = Cpu::new(cartridge);
+    let mut post_process_renderer =
+        PostProcessRenderer::new(&pixels, window_size.width, window_size.height);
 
-    let init = Instant::now();
-    let mut last_frame = Instant::now();
-    let mut i = 0;
-    let mut apu_samples: u64 = 0;
+    let mut frame_scaler = FrameScaler::default();
+    let mut scaling_mode = args.scaling;
+    let mut filter = args.filter;
+    let mut post_process = args.post_process;
 
-    event_loop.run(move |event, _, control_flow| {
-        match event {
-            Event::MainEventsCleared => {
-                let cycle_start = cpu.bus.cycle_count();
-                let mut apu_samples = 0;
-                loop {
-                    let cycles_elapsed = cpu.bus.cycle_count() - cycle_start;
+    let boot_mode = if args.skip_bios {
+        BootMode::SkipBios
+    } else {
+        BootMode::Bios
+    };
+    let mut cpu = match &multiboot_image {
+        Some(image) => Cpu::with_multiboot_image(cartridge, image)?,
+        None => Cpu::with_boot_mode(cartridge, boot_mode),
+    };
+    cpu.set_uncapped(args.uncapped);
+    cpu.set_config(CpuConfig {
+        error_policy: if args.ignore_unimplemented {
+            ErrorPolicy::Ignore
+        } else {
+            ErrorPolicy::LogAndContinue
+        },
+        skip_idle_loops: args.skip_idle_loops,
+        fast_ewram: args.fast_ewram,
+    });
+    if args.sync_to_audio {
+        cpu.set_timing_mode(TimingMode::HostAudioSync);
+    }
+    if args.coverage.is_some() {
+        cpu.bus.set_coverage(Some(CoverageRecorder::new()));
+    }
+    cpu.bus.lcd.set_color_correction(args.color_correction);
+    cpu.bus.lcd.set_rgba_output(true);
 
-                    cpu.fetch_decode_execute();
+    let link_transport = match (&args.link_listen, &args.link_connect) {
+        (Some(address), _) => Some(TcpLinkTransport::listen(address)?),
+        (_, Some(address)) => Some(TcpLinkTransport::connect(address)?),
+        (None, None) => None,
+    };
+    if let Some(link_transport) = link_transport {
+        cpu.set_link_transport(Some(Arc::new(Mutex::new(link_transport))));
+    }
 
-                    while cycles_elapsed
-                        > (apu_samples * CYCLES_PER_SECOND / u64::from(APU_SAMPLE_RATE))
-                    {
-                        let sample = cpu.sample_apu();
-                        source_sender.push(sample[0]);
-                        source_sender.push(sample[1]);
-                        apu_samples += 1;
-                    }
+    let netplay = match (&args.netplay_bind, &args.netplay_peer) {
+        (Some(bind_address), Some(peer_address)) => Some(UdpNetplay::new(
+            bind_address,
+            peer_address,
+            args.input_delay,
+        )?),
+        _ => None,
+    };
 
-                    if cycles_elapsed >= (CYCLES_PER_SECOND / 60) {
-                        break;
-                    }
+    if let Some(slot) = args.autoload_state {
+        let state_file_name = state_file_name(&args.rom, slot);
+        load_state(&mut cpu, &state_file_name)
+            .map_err(|e| anyhow!("failed to load state from {state_file_name}: {e}"))?;
+        log::info!("loaded state from {state_file_name}");
+    }
+
+    let (frame_sender, mut frame_receiver) = frame_channel();
+    let (mut emulation, notifications) = EmulationThread::spawn(
+        cpu,
+        args.clone(),
+        save_file_name,
+        source_sender,
+        frame_sender,
+        netplay,
+    )?;
+
+    // Key events are collected here and sent to the emulation thread as a whole.
+    let mut keys_state = KeysState::default();
+    let mut modifiers = ModifiersState::empty();
+    let mut osd = Osd::new(!args.no_osd);
+
+    event_loop.run(move |event, _, control_flow| {
+        match event {
+            Event::MainEventsCleared => {
+                // Exit through the event loop so that save data is written on the way out.
+                if interrupted.load(Ordering::SeqCst) {
+                    log::info!("interrupted, exiting");
+                    *control_flow = ControlFlow::Exit;
+                    return;
                 }
 
-                let draw_buffer = pixels.frame_mut();
-                let lcd_buffer = cpu.bus.lcd.get_buffer();
-                for (index, pixel) in lcd_buffer.iter().flatten().enumerate() {
-                    draw_buffer[(index * 4)..][0] = (pixel.red() << 3) | (pixel.red() >> 2);
-                    draw_buffer[(index * 4)..][1] = (pixel.green() << 3) | (pixel.green() >> 2);
-                    draw_buffer[(index * 4)..][2] = (pixel.blue() << 3) | (pixel.blue() >> 2);
-                    draw_buffer[(index * 4)..][3] = 255;
+                if emulation.is_finished() {
+                    *control_flow = ControlFlow::Exit;
+                    return;
                 }
-                pixels.render().expect("failed to render new frame");
 
-                if args.limit_framerate {
-                    while last_frame.elapsed() < Duration::from_secs(1) / FPS_TARGET {
-                        std::thread::yield_now();
+                for notification in notifications.try_iter() {
+                    match notification {
+                        Notification::Message(message) => osd.show(message),
+                        Notification::Fps(fps) => osd.update_fps(fps),
                     }
                 }
 
-                let time_elapsed = last_frame.elapsed();
-                let fps = 1.0 / time_elapsed.as_secs_f64();
-                window.set_title(format!("FPS: {}", fps).as_str());
-
-                last_frame = Instant::now();
-                match args.frames {
-                    Some(frames) if i >= frames => *control_flow = ControlFlow::Exit,
-                    _ => {}
-                };
+                let frame = frame_receiver.frame();
+                // Nothing to show until the first frame is emulated.
+                if frame.is_empty() {
+                    return;
+                }
 
-                i += 1;
+                frame_scaler.draw(
+                    &frame,
+                    pixels.frame_mut(),
+                    window_size.width as usize,
+                    window_size.height as usize,
+                    scaling_mode,
+                    filter,
+                );
+                drop(frame);
+                osd.draw(
+                    pixels.frame_mut(),
+                    window_size.width as usize,
+                    window_size.height as usize,
+                );
+                let viewport = Viewport::new(
+                    scaling_mode,
+                    window_size.width as usize,
+                    window_size.height as usize,
+                );
+                pixels
+                    .render_with(|encoder, render_target, context| {
+                        post_process_renderer.render(
+                            encoder,
+                            render_target,
+                            context,
+                            &viewport,
+                            post_process,
+                        );
+                        Ok(())
+                    })
+                    .expect("failed to render new frame");
             }
             Event::WindowEvent {
                 event: WindowEvent::Resized(new_size),
                 window_id,
             } if window_id == window.id() => {
-                pixels
-                    .resize_surface(new_size.width, new_size.height)
-                    .unwrap();
+                // Minimizing reports a zero-sized window, which can't back a surface.
+                if new_size.width > 0 && new_size.height > 0 {
+                    pixels
+                        .resize_surface(new_size.width, new_size.height)
+                        .unwrap();
+                    pixels
+                        .resize_buffer(new_size.width, new_size.height)
+                        .unwrap();
+                    post_process_renderer.resize(&pixels, new_size.width, new_size.height);
+                    window_size = new_size;
+                }
                 log::info!("resized to ({}, {})", new_size.width, new_size.height);
             }
             Event::WindowEvent {
@@ -176,40 +667,96 @@ fn main() -> Result<()> {
                     ElementState::Released => false,
                 };
 
+                let key = match keycode {
+                    VirtualKeyCode::Z => Some(Key::B),
+                    VirtualKeyCode::X => Some(Key::A),
+                    VirtualKeyCode::RShift | VirtualKeyCode::LShift => Some(Key::Select),
+                    VirtualKeyCode::Return => Some(Key::Start),
+                    VirtualKeyCode::Up => Some(Key::Up),
+                    VirtualKeyCode::Down => Some(Key::Down),
+                    VirtualKeyCode::Left => Some(Key::Left),
+                    VirtualKeyCode::Right => Some(Key::Right),
+                    VirtualKeyCode::Q => Some(Key::L),
+                    VirtualKeyCode::E => Some(Key::R),
+                    _ => None,
+                };
+                if let Some(key) = key {
+                    keys_state.set_pressed(key, pressed);
+                    emulation.send(Command::SetKeys(keys_state));
+                }
+
                 match keycode {
-                    VirtualKeyCode::Z => cpu.bus.keypad.set_pressed(Key::B, pressed),
-                    VirtualKeyCode::X => cpu.bus.keypad.set_pressed(Key::A, pressed),
-                    VirtualKeyCode::RShift | VirtualKeyCode::LShift => {
-                        cpu.bus.keypad.set_pressed(Key::Select, pressed)
+                    VirtualKeyCode::Space if pressed => emulation.send(Command::LogChecksum),
+                    VirtualKeyCode::P if pressed => emulation.send(Command::TogglePause),
+                    VirtualKeyCode::N if pressed => emulation.send(Command::AdvanceFrame),
+                    VirtualKeyCode::M if pressed => emulation.send(Command::CycleSpeed),
+                    VirtualKeyCode::F6 if pressed => {
+                        scaling_mode = scaling_mode.next();
+                        let message = format!("scaling mode: {scaling_mode:?}");
+                        log::info!("{message}");
+                        osd.show(message);
                     }
-                    VirtualKeyCode::Return => cpu.bus.keypad.set_pressed(Key::Start, pressed),
-                    VirtualKeyCode::Up => cpu.bus.keypad.set_pressed(Key::Up, pressed),
-                    VirtualKeyCode::Down => cpu.bus.keypad.set_pressed(Key::Down, pressed),
-                    VirtualKeyCode::Left => cpu.bus.keypad.set_pressed(Key::Left, pressed),
-                    VirtualKeyCode::Right => cpu.bus.keypad.set_pressed(Key::Right, pressed),
-                    VirtualKeyCode::Q => cpu.bus.keypad.set_pressed(Key::L, pressed),
-                    VirtualKeyCode::E => cpu.bus.keypad.set_pressed(Key::R, pressed),
-                    VirtualKeyCode::Space if pressed => {
-                        log::error!("current checksum: {:016X}", calculate_lcd_checksum(&cpu));
+                    VirtualKeyCode::F7 if pressed => {
+                        filter = filter.next();
+                        let message = format!("filter: {filter:?}");
+                        log::info!("{message}");
+                        osd.show(message);
                     }
+                    VirtualKeyCode::F8 if pressed => {
+                        post_process = post_process.next();
+                        let message = format!("post processing: {post_process:?}");
+                        log::info!("{message}");
+                        osd.show(message);
+                    }
+                    VirtualKeyCode::F11 if pressed => {
+                        let fullscreen = match window.fullscreen() {
+                            Some(_) => None,
+                            None => Some(Fullscreen::Borderless(None)),
+                        };
+                        window.set_fullscreen(fullscreen);
+                    }
+                    VirtualKeyCode::F9 if pressed => emulation.send(Command::ToggleRecording),
+                    VirtualKeyCode::F1
+                    | VirtualKeyCode::F2
+                    | VirtualKeyCode::F3
+                    | VirtualKeyCode::F4
+                        if pressed =>
+                    {
+                        let slot = match keycode {
+                            VirtualKeyCode::F1 => 1,
+                            VirtualKeyCode::F2 => 2,
+                            VirtualKeyCode::F3 => 3,
+                            VirtualKeyCode::F4 => 4,
+                            _ => unreachable!(),
+                        };
+
+                        // Shift is already Select, so loading takes Ctrl instead.
+                        if modifiers.ctrl() {
+                            emulation.send(Command::LoadState(slot));
+                        } else {
+                            emulation.send(Command::SaveState(slot));
+                        }
+                    }
+                    VirtualKeyCode::PageUp if pressed => {
+                        emulation.send(Command::RaiseSolarLevel);
+                    }
+                    VirtualKeyCode::PageDown if pressed => {
+                        emulation.send(Command::LowerSolarLevel);
+                    }
+                    VirtualKeyCode::F12 if pressed => emulation.send(Command::Screenshot),
                     _ => {}
                 }
             }
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(new_modifiers),
+                window_id,
+            } if window_id == window.id() => modifiers = new_modifiers,
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 window_id,
                 ..
             } if window_id == window.id() => *control_flow = ControlFlow::Exit,
-            Event::LoopDestroyed => {
-                log::info!("ran for {:?}", init.elapsed());
-
-                let save_file_name = format!("{}.sav", args.rom);
-                log::info!("writing save data to {save_file_name}");
-                let save_file = File::create(&save_file_name).expect("failed to create save file");
-                serde_cbor::to_writer(save_file, cpu.bus.cartridge.get_backup())
-                    .expect("failed to write save data to save file");
-                log::info!("finished writing save data to {save_file_name}");
-            }
+            Event::LoopDestroyed => emulation.exit(),
             _ => {}
         };
     });
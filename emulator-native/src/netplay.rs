@@ -0,0 +1,81 @@
+use std::{
+    io::ErrorKind,
+    net::{ToSocketAddrs, UdpSocket},
+};
+
+use anyhow::Result;
+use emulator_core::{Cpu, KeysState, Lockstep};
+
+// Largest packet we expect from the peer, comfortably above what `Lockstep` ever sends.
+const MAX_PACKET_SIZE: usize = 2048;
+
+// Lockstep netplay with a single peer over UDP. The socket never blocks, so while the peer's
+// input is late the frontend keeps drawing the last frame instead of freezing.
+pub struct UdpNetplay {
+    socket: UdpSocket,
+    session: Lockstep,
+    desync_reported: bool,
+}
+
+impl UdpNetplay {
+    pub fn new(
+        bind_address: impl ToSocketAddrs,
+        peer_address: impl ToSocketAddrs,
+        input_delay: u32,
+    ) -> Result<Self> {
+        let socket = UdpSocket::bind(bind_address)?;
+        socket.connect(peer_address)?;
+        socket.set_nonblocking(true)?;
+        log::info!(
+            "netplay on {} with {}, {input_delay} frames of input delay",
+            socket.local_addr()?,
+            socket.peer_addr()?
+        );
+
+        Ok(Self {
+            socket,
+            session: Lockstep::new(input_delay),
+            desync_reported: false,
+        })
+    }
+
+    // Exchanges inputs with the peer, then returns the keys to run the next frame with, or `None`
+    // if the peer's input for it hasn't arrived yet.
+    pub fn poll(&mut self, local_keys: KeysState, cpu: &Cpu) -> Option<KeysState> {
+        self.session.set_local_input(local_keys);
+
+        // Fails while nothing is listening on the other end yet, which resending every frame
+        // takes care of.
+        if let Err(e) = self.socket.send(&self.session.packet()) {
+            log::debug!("failed to send netplay packet: {e}");
+        }
+
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv(&mut buffer) {
+                Ok(len) => {
+                    if let Err(e) = self.session.receive(&buffer[..len]) {
+                        log::warn!("ignoring netplay packet: {e}");
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::debug!("failed to receive netplay packet: {e}");
+                    break;
+                }
+            }
+        }
+
+        self.session.begin_frame(cpu)
+    }
+
+    // The frame the games first diverged on, only returned the first time it's noticed.
+    pub fn poll_desync(&mut self) -> Option<u64> {
+        let frame = self
+            .session
+            .desync_frame()
+            .filter(|_| !self.desync_reported)?;
+        self.desync_reported = true;
+        Some(frame)
+    }
+}
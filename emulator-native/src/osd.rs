@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use font8x8::{UnicodeFonts, BASIC_FONTS};
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+const MAX_MESSAGES: usize = 4;
+const GLYPH_SIZE: usize = 8;
+const MARGIN: usize = 4; // in glyph pixels, before scaling
+                         // Text grows by one step for every this many pixels of window height.
+const WINDOW_HEIGHT_PER_SCALE: usize = 240;
+// How much each new FPS measurement moves the displayed value, so that it's readable.
+const FPS_SMOOTHING: f64 = 0.1;
+
+const TEXT_COLOR: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const SHADOW_COLOR: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+// Short-lived status messages and an FPS counter, drawn over the top of each frame.
+pub struct Osd {
+    enabled: bool,
+    messages: VecDeque<(String, Instant)>,
+    fps: Option<f64>,
+}
+
+impl Osd {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            messages: VecDeque::new(),
+            fps: None,
+        }
+    }
+
+    pub fn show(&mut self, message: impl Into<String>) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+
+        self.messages.push_back((message.into(), Instant::now()));
+    }
+
+    pub fn update_fps(&mut self, fps: f64) {
+        if !fps.is_finite() {
+            return;
+        }
+
+        self.fps = Some(match self.fps {
+            Some(previous) => previous + (fps - previous) * FPS_SMOOTHING,
+            None => fps,
+        });
+    }
+
+    // Draws into an RGBA8 frame of the given dimensions, scaled up along with the window.
+    pub fn draw(&mut self, frame: &mut [u8], frame_width: usize, frame_height: usize) {
+        self.messages
+            .retain(|(_, shown_at)| shown_at.elapsed() < MESSAGE_DURATION);
+
+        if !self.enabled {
+            return;
+        }
+
+        let scale = (frame_height / WINDOW_HEIGHT_PER_SCALE).max(1);
+        let line_height = (GLYPH_SIZE + 2) * scale;
+        let margin = MARGIN * scale;
+        let mut canvas = Canvas {
+            frame,
+            width: frame_width,
+            height: frame_height,
+            scale,
+        };
+
+        for (line, (message, _)) in self.messages.iter().enumerate() {
+            canvas.draw_text(message, margin, margin + line * line_height);
+        }
+
+        if let Some(fps) = self.fps {
+            let text = format!("{fps:.0} FPS");
+            let text_width = text.chars().count() * GLYPH_SIZE * scale;
+            canvas.draw_text(
+                &text,
+                frame_width.saturating_sub(text_width + margin),
+                margin,
+            );
+        }
+    }
+}
+
+struct Canvas<'a> {
+    frame: &'a mut [u8],
+    width: usize,
+    height: usize,
+    scale: usize,
+}
+
+impl Canvas<'_> {
+    fn draw_text(&mut self, text: &str, x: usize, y: usize) {
+        // The shadow keeps text readable on top of bright scenes.
+        for (offset, color) in [(self.scale, SHADOW_COLOR), (0, TEXT_COLOR)] {
+            for (index, character) in text.chars().enumerate() {
+                let glyph = BASIC_FONTS.get(character).unwrap_or_default();
+                let glyph_x = x + offset + index * GLYPH_SIZE * self.scale;
+                self.draw_glyph(glyph, glyph_x, y + offset, color);
+            }
+        }
+    }
+
+    fn draw_glyph(&mut self, glyph: [u8; 8], x: usize, y: usize, color: [u8; 4]) {
+        for (row, bits) in glyph.into_iter().enumerate() {
+            for column in 0..GLYPH_SIZE {
+                if (bits >> column) & 1 == 1 {
+                    self.fill(
+                        x + column * self.scale,
+                        y + row * self.scale,
+                        self.scale,
+                        color,
+                    );
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, size: usize, color: [u8; 4]) {
+        for pixel_y in y..(y + size).min(self.height) {
+            for pixel_x in x..(x + size).min(self.width) {
+                let offset = (pixel_y * self.width + pixel_x) * 4;
+                self.frame[offset..offset + 4].copy_from_slice(&color);
+            }
+        }
+    }
+}
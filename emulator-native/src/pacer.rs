@@ -0,0 +1,95 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Paces frames against the host clock. Each deadline follows on from the previous one, rather than
+// from when the last frame happened to finish, so time spent emulating and rendering doesn't add
+// up into drift. Most of each wait is slept through, only spinning for the last stretch, which
+// keeps precision without burning a whole core.
+#[derive(Debug, Default)]
+pub struct FramePacer {
+    deadline: Option<Instant>,
+}
+
+impl FramePacer {
+    // `thread::sleep` commonly overshoots by up to about this much.
+    const SPIN_THRESHOLD: Duration = Duration::from_millis(2);
+    // Falling behind by more than this, like while the host was suspended, starts over from now
+    // rather than running unpaced until caught up.
+    const MAX_LAG: Duration = Duration::from_millis(100);
+
+    // Waits until the end of the current interval, starting the next one lasting `duration`.
+    pub fn wait(&mut self, duration: Duration) {
+        let deadline = self.start_interval(Instant::now(), duration);
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            } else if remaining > Self::SPIN_THRESHOLD {
+                thread::sleep(remaining - Self::SPIN_THRESHOLD);
+            } else {
+                std::hint::spin_loop();
+            }
+        }
+    }
+
+    // Returns when the current interval ends, which is also when the next one, lasting
+    // `duration`, starts.
+    fn start_interval(&mut self, now: Instant, duration: Duration) -> Instant {
+        let deadline = self
+            .deadline
+            .filter(|&deadline| now.saturating_duration_since(deadline) < Self::MAX_LAG)
+            .unwrap_or(now);
+        self.deadline = Some(deadline + duration);
+
+        deadline
+    }
+
+    // Stops pacing, so that the next wait starts a fresh interval instead of catching up.
+    pub fn reset(&mut self) {
+        self.deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_micros(16_743);
+
+    #[test]
+    fn no_drift() {
+        let mut pacer = FramePacer::default();
+        let start = Instant::now();
+        assert_eq!(pacer.start_interval(start, FRAME), start);
+
+        // Waking up late doesn't push back the deadlines that follow.
+        for frame in 1..1000 {
+            let deadline = start + FRAME * frame;
+            let now = deadline + Duration::from_millis(1);
+            assert_eq!(pacer.start_interval(now, FRAME), deadline);
+        }
+    }
+
+    #[test]
+    fn catch_up() {
+        let mut pacer = FramePacer::default();
+        let start = Instant::now();
+        pacer.start_interval(start, FRAME);
+
+        // A few slow frames are made up for by not waiting on the next ones.
+        let now = start + FRAME * 3;
+        assert_eq!(pacer.start_interval(now, FRAME), start + FRAME);
+        assert_eq!(pacer.start_interval(now, FRAME), start + FRAME * 2);
+        assert_eq!(pacer.start_interval(now, FRAME), now);
+
+        // Falling too far behind starts over instead.
+        let now = now + FramePacer::MAX_LAG * 2;
+        assert_eq!(pacer.start_interval(now, FRAME), now);
+        assert_eq!(pacer.start_interval(now, FRAME), now + FRAME);
+
+        pacer.reset();
+        let now = now + FRAME / 2;
+        assert_eq!(pacer.start_interval(now, FRAME), now);
+    }
+}
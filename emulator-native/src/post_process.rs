@@ -0,0 +1,280 @@
+use clap::ValueEnum;
+use pixels::{
+    wgpu::{self, util::DeviceExt},
+    Pixels, PixelsContext,
+};
+
+use crate::display::Viewport;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PostProcess {
+    #[default]
+    None,
+    /// Approximate the colors of the original GBA LCD.
+    ColorCorrection,
+    /// Color correction plus a visible LCD cell grid and RGB subpixels.
+    LcdGrid,
+    /// Scanlines and vignetting.
+    Crt,
+}
+
+impl PostProcess {
+    pub fn next(self) -> Self {
+        match self {
+            PostProcess::None => PostProcess::ColorCorrection,
+            PostProcess::ColorCorrection => PostProcess::LcdGrid,
+            PostProcess::LcdGrid => PostProcess::Crt,
+            PostProcess::Crt => PostProcess::None,
+        }
+    }
+
+    // Must match the mode values in post_process.wgsl.
+    fn shader_mode(self) -> u32 {
+        match self {
+            PostProcess::None => 0,
+            PostProcess::ColorCorrection => 1,
+            PostProcess::LcdGrid => 2,
+            PostProcess::Crt => 3,
+        }
+    }
+}
+
+// viewport: vec4<f32>, mode: u32, padded out to 16 byte alignment.
+const UNIFORMS_SIZE: u64 = 32;
+
+// Renders the pixels buffer into an intermediate texture using the default scaling renderer, then
+// runs a full-screen pass over it with the post-processing shader.
+pub struct PostProcessRenderer {
+    texture_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl PostProcessRenderer {
+    pub fn new(pixels: &Pixels, width: u32, height: u32) -> Self {
+        let device = pixels.device();
+        let module =
+            device.create_shader_module(wgpu::include_wgsl!("../shaders/post_process.wgsl"));
+
+        let texture_view = create_texture_view(pixels, width, height);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post process sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        // A single triangle that covers the whole render target.
+        let vertex_data: [[f32; 2]; 3] = [[-1.0, -1.0], [3.0, -1.0], [-1.0, 3.0]];
+        let vertex_bytes = vertex_data
+            .iter()
+            .flatten()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect::<Vec<_>>();
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("post process vertex buffer"),
+            contents: &vertex_bytes,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: 0,
+                shader_location: 0,
+            }],
+        };
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("post process uniforms"),
+            size: UNIFORMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post process bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(UNIFORMS_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &texture_view,
+            &sampler,
+            &uniform_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post process pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("post process pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[vertex_buffer_layout],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: pixels.render_texture_format(),
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            texture_view,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            render_pipeline,
+            uniform_buffer,
+            vertex_buffer,
+        }
+    }
+
+    pub fn resize(&mut self, pixels: &Pixels, width: u32, height: u32) {
+        self.texture_view = create_texture_view(pixels, width, height);
+        self.bind_group = create_bind_group(
+            pixels.device(),
+            &self.bind_group_layout,
+            &self.texture_view,
+            &self.sampler,
+            &self.uniform_buffer,
+        );
+    }
+
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        render_target: &wgpu::TextureView,
+        context: &PixelsContext,
+        viewport: &Viewport,
+        post_process: PostProcess,
+    ) {
+        context.scaling_renderer.render(encoder, &self.texture_view);
+
+        let mut uniforms = Vec::with_capacity(UNIFORMS_SIZE as usize);
+        for value in [viewport.x, viewport.y, viewport.width, viewport.height] {
+            uniforms.extend_from_slice(&(value as f32).to_ne_bytes());
+        }
+        uniforms.extend_from_slice(&post_process.shader_mode().to_ne_bytes());
+        uniforms.resize(UNIFORMS_SIZE as usize, 0);
+        context
+            .queue
+            .write_buffer(&self.uniform_buffer, 0, &uniforms);
+
+        let (clip_x, clip_y, clip_width, clip_height) = context.scaling_renderer.clip_rect();
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("post process render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_scissor_rect(clip_x, clip_y, clip_width, clip_height);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_texture_view(pixels: &Pixels, width: u32, height: u32) -> wgpu::TextureView {
+    let texture = pixels.device().create_texture(&wgpu::TextureDescriptor {
+        label: Some("post process input texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: pixels.render_texture_format(),
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    texture_view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    uniform_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("post process bind group"),
+        layout: bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -1,34 +1,44 @@
-use std::sync::mpsc::{channel, Receiver, Sender};
-
+use emulator_core::AudioRingBuffer;
 use rodio::Source;
 
 pub struct SampleSource {
-    receiver: Receiver<f32>,
+    buffer: AudioRingBuffer,
     sample_rate: u32,
-    last_sample: f32,
+    last_sample: [f32; 2],
+    next_channel: usize,
 }
 
 pub struct SampleSourceSender {
-    sender: Sender<f32>,
+    buffer: AudioRingBuffer,
 }
 
-pub fn sample_source(sample_rate: u32) -> (SampleSourceSender, SampleSource) {
-    let (sender, receiver) = channel();
+// `capacity` is in (interleaved stereo) samples.
+pub fn sample_source(sample_rate: u32, capacity: usize) -> (SampleSourceSender, SampleSource) {
+    let buffer = AudioRingBuffer::new(capacity / 2);
 
-    let sample_source_sender = SampleSourceSender { sender };
+    let sample_source_sender = SampleSourceSender {
+        buffer: buffer.clone(),
+    };
 
     let sample_source = SampleSource {
-        receiver,
+        buffer,
         sample_rate,
-        last_sample: 0.0,
+        last_sample: [0.0; 2],
+        next_channel: 0,
     };
 
     (sample_source_sender, sample_source)
 }
 
 impl SampleSourceSender {
-    pub fn push(&mut self, sample: f32) {
-        self.sender.send(sample).unwrap();
+    // Pushes a left and right sample.
+    pub fn push(&mut self, sample: [f32; 2]) {
+        self.buffer.push(sample);
+    }
+
+    // Samples pushed which haven't been played yet, counting each channel separately.
+    pub fn buffered_samples(&self) -> usize {
+        self.buffer.len() * 2
     }
 }
 
@@ -54,10 +64,15 @@ impl Iterator for SampleSource {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Ok(sample) = self.receiver.try_recv() {
-            self.last_sample = sample;
+        if self.next_channel == 0 {
+            if let Some(sample) = self.buffer.pop() {
+                self.last_sample = sample;
+            }
         }
 
-        Some(self.last_sample)
+        let sample = self.last_sample[self.next_channel];
+        self.next_channel = (self.next_channel + 1) % 2;
+
+        Some(sample)
     }
 }